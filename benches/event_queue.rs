@@ -0,0 +1,72 @@
+// Benchmarks for queueing events in an `EventQueue`.
+//
+// Run with
+// ```
+// cargo bench --bench event_queue
+// ```
+#[macro_use]
+extern crate criterion;
+extern crate rsynth;
+
+use criterion::{BenchmarkId, Criterion};
+use rsynth::event::event_queue::{AlwaysInsertNewAfterOld, EventQueue};
+use rsynth::event::Timed;
+
+// The number of events per buffer, e.g. for a dense stream of control change events.
+const NUMBERS_OF_EVENTS: [u32; 3] = [16, 128, 1024];
+
+fn queue_events<I>(number_of_events: u32, times: I) -> EventQueue<u32>
+where
+    I: Iterator<Item = u32>,
+{
+    let mut queue = EventQueue::new(number_of_events as usize);
+    for (index, time) in times.enumerate() {
+        queue.queue_event(Timed::new(time, index as u32), AlwaysInsertNewAfterOld);
+    }
+    queue
+}
+
+fn in_chronological_order(c: &mut Criterion) {
+    let mut group = c.benchmark_group("queue_event in chronological order");
+    for &number_of_events in NUMBERS_OF_EVENTS.iter() {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(number_of_events),
+            &number_of_events,
+            |b, &n| b.iter(|| queue_events(n, 0..n)),
+        );
+    }
+    group.finish();
+}
+
+fn in_reverse_order(c: &mut Criterion) {
+    let mut group = c.benchmark_group("queue_event in reverse order");
+    for &number_of_events in NUMBERS_OF_EVENTS.iter() {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(number_of_events),
+            &number_of_events,
+            |b, &n| b.iter(|| queue_events(n, (0..n).rev())),
+        );
+    }
+    group.finish();
+}
+
+fn in_scrambled_order(c: &mut Criterion) {
+    let mut group = c.benchmark_group("queue_event in scrambled order");
+    for &number_of_events in NUMBERS_OF_EVENTS.iter() {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(number_of_events),
+            &number_of_events,
+            // 7919 is prime, so this visits all times in 0..n when n is a power of two.
+            |b, &n| b.iter(|| queue_events(n, (0..n).map(|i| (i * 7919) % n))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    in_chronological_order,
+    in_reverse_order,
+    in_scrambled_order
+);
+criterion_main!(benches);
@@ -0,0 +1,191 @@
+// An example that shows how the data of the voices can be stored as a "structure of arrays".
+// It contains a port of the noise generator in `test_synth.rs` and compares how long it takes
+// to render with the original and with the port.
+//
+// In `test_synth.rs`, each voice is a struct with its own position and amplitude
+// (an "array of structures"). Here, all positions are stored in one array and all amplitudes
+// in another array, so that the loop that runs for every sample iterates over contiguous memory.
+//
+// Compiling and running
+// =====================
+// You can compile and run this example with
+// ```
+// cargo run --release --example soa_synth
+// ```
+// Don't forget the `--release`: the comparison is meaningless for a debug build.
+#[macro_use]
+extern crate log;
+extern crate asprim;
+extern crate num_traits;
+extern crate rand;
+#[macro_use]
+extern crate rsynth;
+
+mod test_synth;
+use test_synth::NoisePlayer;
+
+use asprim::AsPrim;
+use midi_consts::channel_event::*;
+use num_traits::Float;
+use rand::{thread_rng, Rng};
+use rsynth::event::{ContextualEventHandler, RawMidiEvent, Timed};
+use rsynth::utilities::polyphony::{
+    simple_event_dispatching::{SimpleEventDispatcher, SimpleVoiceState},
+    RawMidiEventToneIdentifierDispatchClassifier, ToneIdentifier, VoiceAssigner, VoiceAssignment,
+};
+use rsynth::ContextualAudioRenderer;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// The same values as in `test_synth.rs`.
+static SAMPLE_SIZE: usize = 65536;
+static NUMBER_OF_VOICES: usize = 6;
+static AMPLIFY_MULTIPLIER: f32 = 1.0 / NUMBER_OF_VOICES as f32;
+
+structure_of_arrays! {
+    // The data of all the voices, one element per voice.
+    struct NoiseVoices {
+        // At which sample in the noise the voice is.
+        position: usize,
+        // The amplitude, this is 0.0 when the voice is idle.
+        amplitude: f32,
+        // This is used to know if the voice is currently playing and if so, what note.
+        state: SimpleVoiceState<ToneIdentifier>,
+    }
+}
+
+pub struct SoaNoisePlayer {
+    // Random data of the noise, shared by all voices.
+    white_noise: Vec<f32>,
+    voices: NoiseVoices,
+    // We only use the dispatcher to decide which voice should handle an event,
+    // so the voice states play the role of the voices.
+    dispatcher: SimpleEventDispatcher<
+        RawMidiEventToneIdentifierDispatchClassifier,
+        SimpleVoiceState<ToneIdentifier>,
+    >,
+}
+
+impl SoaNoisePlayer {
+    pub fn new() -> Self {
+        let mut rng = thread_rng();
+        let white_noise = rng
+            .gen_iter::<f32>()
+            .take(SAMPLE_SIZE)
+            .map(|r| 2.0 * r - 1.0)
+            .collect();
+        Self {
+            white_noise,
+            voices: NoiseVoices::new(NUMBER_OF_VOICES),
+            dispatcher: SimpleEventDispatcher::default(),
+        }
+    }
+
+    fn handle_voice_event(&mut self, index: usize, event: Timed<RawMidiEvent>) {
+        let data = event.event.data();
+        match data[0] & EVENT_TYPE_MASK {
+            NOTE_ON => {
+                self.voices.amplitude[index] = data[2] as f32 / 127.0 * AMPLIFY_MULTIPLIER;
+                self.voices.state[index] = SimpleVoiceState::Active(ToneIdentifier(data[1]));
+                // The voices share the noise, start at a different position for each voice
+                // so that they don't sound the same.
+                self.voices.position[index] = index * self.white_noise.len() / self.voices.len();
+            }
+            NOTE_OFF => {
+                self.voices.amplitude[index] = 0.0;
+                self.voices.state[index] = SimpleVoiceState::Idle;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Default for SoaNoisePlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S, Context> ContextualAudioRenderer<S, Context> for SoaNoisePlayer
+where
+    S: AsPrim + Float,
+{
+    fn render_buffer(
+        &mut self,
+        _inputs: &[&[S]],
+        outputs: &mut [&mut [S]],
+        _context: &mut Context,
+    ) {
+        let white_noise = &self.white_noise;
+        let voices = &mut self.voices;
+        for output in outputs.iter_mut() {
+            for sample in output.iter_mut() {
+                // This is the hot loop: it iterates over two contiguous arrays.
+                // Idle voices have amplitude 0.0, so they don't need to be skipped.
+                let mut sum = 0.0;
+                for (position, amplitude) in voices.position.iter_mut().zip(voices.amplitude.iter())
+                {
+                    sum += white_noise[*position] * amplitude;
+                    *position += 1;
+                    if *position == white_noise.len() {
+                        *position = 0;
+                    }
+                }
+                *sample = *sample + sum.as_();
+            }
+        }
+    }
+}
+
+impl<Context> ContextualEventHandler<Timed<RawMidiEvent>, Context> for SoaNoisePlayer {
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>, _context: &mut Context) {
+        match self.dispatcher.assign_event(event, &mut self.voices.state) {
+            VoiceAssignment::None => {}
+            VoiceAssignment::Some(index) => self.handle_voice_event(index, event),
+            VoiceAssignment::All => {
+                for index in 0..self.voices.len() {
+                    self.handle_voice_event(index, event);
+                }
+            }
+        }
+    }
+}
+
+const BUFFER_SIZE: usize = 256;
+const NUMBER_OF_BUFFERS: usize = 20_000;
+
+// Play a chord on all voices and measure how long it takes to render.
+fn measure<P>(player: &mut P) -> Duration
+where
+    P: ContextualAudioRenderer<f32, ()> + ContextualEventHandler<Timed<RawMidiEvent>, ()>,
+{
+    for note in 0..NUMBER_OF_VOICES as u8 {
+        player.handle_event(
+            Timed::new(0, RawMidiEvent::new(&[NOTE_ON, 60 + note, 100])),
+            &mut (),
+        );
+    }
+    let mut left = vec![0.0; BUFFER_SIZE];
+    let mut right = vec![0.0; BUFFER_SIZE];
+    let start = Instant::now();
+    for _ in 0..NUMBER_OF_BUFFERS {
+        for sample in left.iter_mut().chain(right.iter_mut()) {
+            *sample = 0.0;
+        }
+        player.render_buffer(&[], &mut [&mut left, &mut right], &mut ());
+        // Make sure that the compiler cannot optimize the rendering away.
+        black_box((&left, &right));
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let array_of_structures = measure(&mut NoisePlayer::new());
+    let structure_of_arrays = measure(&mut SoaNoisePlayer::new());
+    println!(
+        "Rendering {} buffers of {} frames with {} voices.",
+        NUMBER_OF_BUFFERS, BUFFER_SIZE, NUMBER_OF_VOICES
+    );
+    println!("Array of structures: {:?}", array_of_structures);
+    println!("Structure of arrays: {:?}", structure_of_arrays);
+}
@@ -13,7 +13,7 @@ use rsynth::utilities::polyphony::{
 };
 use rsynth::{
     AudioHandler, AudioHandlerMeta, CommonAudioPortMeta, CommonMidiPortMeta, CommonPluginMeta,
-    ContextualAudioRenderer, MidiHandlerMeta,
+    ContextualAudioRenderer, Latency, Lifecycle, MidiHandlerMeta,
 };
 use std::default::Default;
 
@@ -153,6 +153,15 @@ impl AudioHandler for NoisePlayer {
     }
 }
 
+impl Latency for NoisePlayer {
+    fn latency_in_frames(&self) -> usize {
+        0
+    }
+}
+
+// The noise player does not need to do anything when processing starts or stops.
+impl Lifecycle for NoisePlayer {}
+
 impl CommonAudioPortMeta for NoisePlayer {
     fn audio_output_name(&self, index: usize) -> String {
         trace!("audio_output_name(index = {})", index);
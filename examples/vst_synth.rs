@@ -55,18 +55,52 @@ use test_synth::*;
 use rsynth::backend::vst_backend::VstPluginMeta;
 
 #[cfg(feature = "backend-vst")]
-use vst::plugin::Category;
+use rsynth::meta::Category;
 
 #[cfg(feature = "backend-vst")]
-impl VstPluginMeta for NoisePlayer {
-    fn plugin_id(&self) -> i32 {
-        123
+use rsynth::parameter::{ParameterStore, Parameters};
+
+#[cfg(feature = "backend-vst")]
+use rsynth::{ContextualAudioRenderer, PluginIdentityMeta};
+
+#[cfg(feature = "backend-vst")]
+use vst::plugin::HostCallback;
+
+#[cfg(feature = "backend-vst")]
+use std::sync::Arc;
+
+#[cfg(feature = "backend-vst")]
+impl PluginIdentityMeta for NoisePlayer {
+    fn vendor(&self) -> &str {
+        "rsynth"
+    }
+    fn unique_id(&self) -> i32 {
+        i32::from_be_bytes(*b"RsNo")
+    }
+    fn version(&self) -> i32 {
+        1
     }
     fn category(&self) -> Category {
         Category::Synth
     }
 }
 
+#[cfg(feature = "backend-vst")]
+impl VstPluginMeta for NoisePlayer {
+    // The `NoisePlayer` can also render `f64` samples.
+    fn as_f64_renderer(&mut self) -> Option<&mut dyn ContextualAudioRenderer<f64, HostCallback>> {
+        Some(self)
+    }
+}
+
+#[cfg(feature = "backend-vst")]
+impl Parameters for NoisePlayer {
+    fn parameters(&self) -> Arc<ParameterStore> {
+        // This synth has no parameters.
+        Arc::new(ParameterStore::new(Vec::new()))
+    }
+}
+
 #[rustfmt::skip::macros(vst_init)]
 #[cfg(feature = "backend-vst")]
 vst_init!(
@@ -0,0 +1,389 @@
+//! Publish analysis data (peak, RMS, spectrum) from the audio thread to other threads.
+//!
+//! The audio thread owns an [`AnalysisPublisher`]: after rendering a buffer, it calls
+//! [`AnalysisPublisher::analyze`] to compute the peak and RMS level of each channel and to
+//! publish a new [`AnalysisSnapshot`]. A spectrum (e.g. the magnitudes of an FFT) can be added
+//! with [`AnalysisPublisher::set_spectrum`].
+//!
+//! Other threads, e.g. a graphical editor, an OSC server or a WebSocket server, share the
+//! corresponding [`AnalysisBus`] (in an `Arc`) and read the most recent snapshot whenever
+//! they need it.
+//!
+//! Real-time safety
+//! ----------------
+//! The snapshots are exchanged with a [triple buffer]: the audio thread never waits for the
+//! readers and never allocates memory when publishing. The readers may see fewer snapshots than
+//! have been published, but they always see a complete snapshot.
+//!
+//! Example
+//! -------
+//! ```
+//! use rsynth::analysis::analysis_bus;
+//! use std::thread;
+//!
+//! let (mut publisher, bus) = analysis_bus(2, 0);
+//!
+//! // In the audio thread, after rendering:
+//! let left = [0.5, -1.0, 0.5, 0.0];
+//! let right = [0.25; 4];
+//! publisher.analyze(&[&left, &right]);
+//!
+//! // In another thread:
+//! let reader = bus.clone();
+//! thread::spawn(move || {
+//!     let snapshot = reader.latest();
+//!     assert_eq!(snapshot.peak(), &[1.0, 0.25]);
+//! })
+//! .join()
+//! .unwrap();
+//! ```
+//!
+//! [`AnalysisPublisher`]: ./struct.AnalysisPublisher.html
+//! [`AnalysisPublisher::analyze`]: ./struct.AnalysisPublisher.html#method.analyze
+//! [`AnalysisPublisher::set_spectrum`]: ./struct.AnalysisPublisher.html#method.set_spectrum
+//! [`AnalysisSnapshot`]: ./struct.AnalysisSnapshot.html
+//! [`AnalysisBus`]: ./struct.AnalysisBus.html
+//! [triple buffer]: ./fn.triple_buffer.html
+use num_traits::Float;
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Set in `TripleBuffer::middle` when the middle buffer contains data the reader has not seen.
+const NEW_DATA: usize = 0b100;
+const INDEX_MASK: usize = 0b011;
+
+struct TripleBuffer<T> {
+    buffers: [UnsafeCell<T>; 3],
+    // The index of the buffer that is owned by neither the writer nor the reader,
+    // possibly combined with `NEW_DATA`.
+    middle: AtomicUsize,
+}
+
+// The writer and the reader each own a different buffer; ownership is exchanged atomically.
+unsafe impl<T: Send> Sync for TripleBuffer<T> {}
+
+/// The writing side of a triple buffer, see [`triple_buffer`].
+///
+/// [`triple_buffer`]: ./fn.triple_buffer.html
+pub struct TripleBufferWriter<T> {
+    shared: Arc<TripleBuffer<T>>,
+    index: usize,
+}
+
+/// The reading side of a triple buffer, see [`triple_buffer`].
+///
+/// [`triple_buffer`]: ./fn.triple_buffer.html
+pub struct TripleBufferReader<T> {
+    shared: Arc<TripleBuffer<T>>,
+    index: usize,
+}
+
+/// Create a triple buffer: a lock-free slot that can be written by one thread and read by
+/// another thread. Writing and reading never block and never allocate.
+///
+/// The writer modifies its own copy of the data with [`TripleBufferWriter::get_mut`] and then
+/// makes it available with [`TripleBufferWriter::publish`]. The reader gets the most recently
+/// published data with [`TripleBufferReader::read`].
+///
+/// All three copies are initialized with a clone of `initial`.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------
+/// This function allocates memory and cannot be used in a real-time context.
+///
+/// [`TripleBufferWriter::get_mut`]: ./struct.TripleBufferWriter.html#method.get_mut
+/// [`TripleBufferWriter::publish`]: ./struct.TripleBufferWriter.html#method.publish
+/// [`TripleBufferReader::read`]: ./struct.TripleBufferReader.html#method.read
+pub fn triple_buffer<T: Clone>(initial: T) -> (TripleBufferWriter<T>, TripleBufferReader<T>) {
+    let shared = Arc::new(TripleBuffer {
+        buffers: [
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial),
+        ],
+        middle: AtomicUsize::new(1),
+    });
+    (
+        TripleBufferWriter {
+            shared: Arc::clone(&shared),
+            index: 0,
+        },
+        TripleBufferReader { shared, index: 2 },
+    )
+}
+
+impl<T> TripleBufferWriter<T> {
+    /// Get the copy of the data that is owned by the writer.
+    ///
+    /// Note that this copy does not necessarily contain the most recently published data.
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.shared.buffers[self.index].get() }
+    }
+
+    /// Make the data that has been written with `get_mut` available to the reader.
+    pub fn publish(&mut self) {
+        let previous = self
+            .shared
+            .middle
+            .swap(self.index | NEW_DATA, Ordering::AcqRel);
+        self.index = previous & INDEX_MASK;
+    }
+}
+
+impl<T> TripleBufferReader<T> {
+    /// Fetch the most recently published data, if any.
+    /// Return `true` if new data has been published since the previous call.
+    pub fn update(&mut self) -> bool {
+        if self.shared.middle.load(Ordering::Relaxed) & NEW_DATA == 0 {
+            return false;
+        }
+        let previous = self.shared.middle.swap(self.index, Ordering::AcqRel);
+        self.index = previous & INDEX_MASK;
+        true
+    }
+
+    /// Get the data that has been fetched by the last call to `update`.
+    pub fn get(&self) -> &T {
+        unsafe { &*self.shared.buffers[self.index].get() }
+    }
+
+    /// Fetch and get the most recently published data.
+    pub fn read(&mut self) -> &T {
+        self.update();
+        self.get()
+    }
+}
+
+/// The analysis data of one buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalysisSnapshot {
+    sequence_number: u64,
+    peak: Vec<f32>,
+    rms: Vec<f32>,
+    spectrum: Vec<f32>,
+}
+
+impl AnalysisSnapshot {
+    fn new(number_of_channels: usize, number_of_spectrum_bins: usize) -> Self {
+        AnalysisSnapshot {
+            sequence_number: 0,
+            peak: vec![0.0; number_of_channels],
+            rms: vec![0.0; number_of_channels],
+            spectrum: vec![0.0; number_of_spectrum_bins],
+        }
+    }
+
+    /// The number of snapshots that have been published before this one.
+    /// This can be used by readers to detect that nothing has changed.
+    pub fn sequence_number(&self) -> u64 {
+        self.sequence_number
+    }
+
+    /// The peak level (the maximum absolute value) of each channel.
+    pub fn peak(&self) -> &[f32] {
+        &self.peak
+    }
+
+    /// The root mean square level of each channel.
+    pub fn rms(&self) -> &[f32] {
+        &self.rms
+    }
+
+    /// The spectrum, as set with [`AnalysisPublisher::set_spectrum`].
+    ///
+    /// [`AnalysisPublisher::set_spectrum`]: ./struct.AnalysisPublisher.html#method.set_spectrum
+    pub fn spectrum(&self) -> &[f32] {
+        &self.spectrum
+    }
+}
+
+/// Computes and publishes analysis data in the audio thread.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct AnalysisPublisher {
+    current: AnalysisSnapshot,
+    writer: TripleBufferWriter<AnalysisSnapshot>,
+}
+
+impl AnalysisPublisher {
+    /// Set the spectrum that is published with the next snapshot.
+    /// Only the first `number_of_spectrum_bins` elements are used; if `spectrum` is shorter,
+    /// the remaining bins are set to zero.
+    pub fn set_spectrum(&mut self, spectrum: &[f32]) {
+        for (index, bin) in self.current.spectrum.iter_mut().enumerate() {
+            *bin = spectrum.get(index).cloned().unwrap_or(0.0);
+        }
+    }
+
+    /// Compute the peak and RMS level of the given channels and publish a new snapshot.
+    ///
+    /// Channels beyond `number_of_channels` are ignored; if there are fewer channels,
+    /// the levels of the missing channels are set to zero.
+    pub fn analyze<S>(&mut self, channels: &[&[S]])
+    where
+        S: Float,
+    {
+        for (index, (peak, rms)) in self
+            .current
+            .peak
+            .iter_mut()
+            .zip(self.current.rms.iter_mut())
+            .enumerate()
+        {
+            let channel = channels.get(index).cloned().unwrap_or(&[]);
+            let mut maximum = 0.0_f32;
+            let mut sum_of_squares = 0.0_f32;
+            for sample in channel.iter() {
+                let value = sample.to_f32().unwrap_or(0.0);
+                maximum = maximum.max(value.abs());
+                sum_of_squares += value * value;
+            }
+            *peak = maximum;
+            *rms = if channel.is_empty() {
+                0.0
+            } else {
+                (sum_of_squares / channel.len() as f32).sqrt()
+            };
+        }
+        self.publish();
+    }
+
+    /// Publish the current data without analysing audio, e.g. after `set_spectrum`.
+    pub fn publish(&mut self) {
+        self.writer.get_mut().clone_from(&self.current);
+        self.writer.publish();
+        self.current.sequence_number += 1;
+    }
+}
+
+/// Gives other threads access to the most recent [`AnalysisSnapshot`].
+///
+/// Share it in an `Arc` between all the readers. The readers synchronize with each other,
+/// but never with the audio thread.
+///
+/// [`AnalysisSnapshot`]: ./struct.AnalysisSnapshot.html
+pub struct AnalysisBus {
+    reader: Mutex<TripleBufferReader<AnalysisSnapshot>>,
+}
+
+impl AnalysisBus {
+    /// Call `f` with the most recent snapshot.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method locks a mutex that is shared by all readers.
+    pub fn read<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&AnalysisSnapshot) -> T,
+    {
+        let mut reader = self
+            .reader
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(reader.read())
+    }
+
+    /// Get a copy of the most recent snapshot.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory and locks a mutex that is shared by all readers.
+    pub fn latest(&self) -> AnalysisSnapshot {
+        self.read(|snapshot| snapshot.clone())
+    }
+}
+
+/// Create an [`AnalysisPublisher`] for the audio thread and the [`AnalysisBus`] for the
+/// other threads.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------
+/// This function allocates memory and cannot be used in a real-time context.
+///
+/// [`AnalysisPublisher`]: ./struct.AnalysisPublisher.html
+/// [`AnalysisBus`]: ./struct.AnalysisBus.html
+pub fn analysis_bus(
+    number_of_channels: usize,
+    number_of_spectrum_bins: usize,
+) -> (AnalysisPublisher, Arc<AnalysisBus>) {
+    let initial = AnalysisSnapshot::new(number_of_channels, number_of_spectrum_bins);
+    let (writer, reader) = triple_buffer(initial.clone());
+    (
+        AnalysisPublisher {
+            current: initial,
+            writer,
+        },
+        Arc::new(AnalysisBus {
+            reader: Mutex::new(reader),
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn triple_buffer_reader_sees_the_most_recent_data() {
+        let (mut writer, mut reader) = triple_buffer(0);
+        assert!(!reader.update());
+        assert_eq!(*reader.get(), 0);
+        *writer.get_mut() = 1;
+        writer.publish();
+        *writer.get_mut() = 2;
+        writer.publish();
+        assert!(reader.update());
+        assert_eq!(*reader.get(), 2);
+        assert!(!reader.update());
+        assert_eq!(*reader.get(), 2);
+    }
+
+    #[test]
+    fn triple_buffer_works_across_threads() {
+        let (mut writer, mut reader) = triple_buffer([0_u32; 16]);
+        let writing_thread = thread::spawn(move || {
+            for value in 1..=10_000 {
+                *writer.get_mut() = [value; 16];
+                writer.publish();
+            }
+        });
+        let mut previous = 0;
+        loop {
+            let data = *reader.read();
+            // The reader never sees a partially written array.
+            assert!(data.iter().all(|v| *v == data[0]));
+            assert!(data[0] >= previous);
+            previous = data[0];
+            if previous == 10_000 {
+                break;
+            }
+        }
+        writing_thread.join().expect("Writer should not panic.");
+    }
+
+    #[test]
+    fn analyze_computes_peak_and_rms() {
+        let (mut publisher, bus) = analysis_bus(3, 0);
+        publisher.analyze(&[&[1.0_f32, -1.0, 1.0, -1.0][..], &[0.0, -0.5, 0.0, 0.0][..]]);
+        let snapshot = bus.latest();
+        assert_eq!(snapshot.sequence_number(), 0);
+        assert_eq!(snapshot.peak(), &[1.0, 0.5, 0.0]);
+        assert_eq!(snapshot.rms(), &[1.0, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn spectrum_is_published() {
+        let (mut publisher, bus) = analysis_bus(1, 3);
+        publisher.set_spectrum(&[1.0, 2.0]);
+        publisher.publish();
+        publisher.set_spectrum(&[3.0, 4.0, 5.0, 6.0]);
+        publisher.analyze(&[&[0.0_f32][..]]);
+        let snapshot = bus.latest();
+        assert_eq!(snapshot.sequence_number(), 1);
+        assert_eq!(snapshot.spectrum(), &[3.0, 4.0, 5.0]);
+    }
+}
@@ -0,0 +1,1450 @@
+//! Wrapper for the [Audio Unit] (version 2) backend, for macOS hosts such as Logic and GarageBand.
+//!
+//! Support is only enabled if you compile with the "backend-au" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! # Usage
+//! Build the plugin as a `cdylib` and export it with the [`au_init`] macro, which defines the
+//! factory function of the Audio Unit.
+//! Hosts find Audio Units by their bundle: a directory ending in `.component` with the
+//! following layout:
+//! ```text
+//! MyPlugin.component/
+//!     Contents/
+//!         Info.plist
+//!         MacOS/
+//!             MyPlugin (the dynamic library, renamed)
+//! ```
+//! The `Info.plist` file is generated from the meta-data of the plugin with [`info_plist`].
+//! Copy the bundle to `~/Library/Audio/Plug-Ins/Components` and check it with
+//! `auval -v <type> <subtype> <manufacturer>`, e.g. `auval -v aumu Mysy Demo`.
+//!
+//! # Buses
+//! The plugin has one input bus with [`max_number_of_audio_inputs`] channels (or no input bus
+//! if this is zero) and one output bus with [`max_number_of_audio_outputs`] channels.
+//! The channels are non-interleaved 32 bit floats; the number of channels cannot be changed
+//! by the host.
+//!
+//! Midi events that the host sends with `MusicDeviceMIDIEvent` are passed to the plugin
+//! as `Timed<RawMidiEvent>`, with the offset in the next buffer as time. System exclusive
+//! messages are passed as `Timed<SysExEvent>` with time `0`.
+//!
+//! The sample rate is set by the host with the stream format or the sample rate property;
+//! `set_sample_rate` is called when it changes and once when the plugin is created,
+//! with a sample rate of 44100 Hz.
+//!
+//! When the host initializes the plugin, `set_max_buffer_size` is called with the maximum
+//! number of frames per slice.
+//!
+//! # Limitations
+//! Parameters, presets and state (the "class info" property), midi output and more than one
+//! bus are not supported yet.
+//!
+//! [Audio Unit]: https://developer.apple.com/documentation/audiotoolbox/audio_unit_v2_c_api
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`au_init`]: ../../macro.au_init.html
+//! [`info_plist`]: ./fn.info_plist.html
+//! [`max_number_of_audio_inputs`]: ../../trait.AudioHandlerMeta.html#tymethod.max_number_of_audio_inputs
+//! [`max_number_of_audio_outputs`]: ../../trait.AudioHandlerMeta.html#tymethod.max_number_of_audio_outputs
+use self::sys::*;
+use crate::backend::HostInterface;
+use crate::event::{ContextualEventHandler, RawMidiEvent, SysExEvent, Timed};
+#[cfg(feature = "flush-denormals")]
+use crate::utilities::denormals::DenormalGuard;
+use crate::{AudioHandler, AudioHandlerMeta, CommonPluginMeta, ContextualAudioRenderer};
+use std::mem::size_of;
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+use vecstorage::VecStorage;
+
+/// The subset of the Audio Unit C API that is used by this backend.
+///
+/// These types and constants mirror the definitions in the headers `AudioComponent.h`,
+/// `AUComponent.h`, `AudioUnitProperties.h`, `MusicDevice.h` and `CoreAudioTypes.h` of the
+/// AudioToolbox and CoreAudio frameworks.
+pub mod sys {
+    use std::os::raw::c_void;
+
+    pub type OSStatus = i32;
+    /// A method of the Audio Unit, as returned by `lookup`. The actual signature depends on
+    /// the selector.
+    pub type AudioComponentMethod = unsafe extern "C" fn();
+
+    pub const NO_ERR: OSStatus = 0;
+    pub const PARAM_ERR: OSStatus = -50;
+    pub const ERR_INVALID_PROPERTY: OSStatus = -10879;
+    pub const ERR_INVALID_PARAMETER: OSStatus = -10878;
+    pub const ERR_INVALID_ELEMENT: OSStatus = -10877;
+    pub const ERR_TOO_MANY_FRAMES_TO_PROCESS: OSStatus = -10874;
+    pub const ERR_FORMAT_NOT_SUPPORTED: OSStatus = -10868;
+    pub const ERR_UNINITIALIZED: OSStatus = -10867;
+    pub const ERR_INVALID_SCOPE: OSStatus = -10866;
+    pub const ERR_PROPERTY_NOT_WRITABLE: OSStatus = -10865;
+    pub const ERR_INITIALIZED: OSStatus = -10849;
+
+    pub const INITIALIZE_SELECT: i16 = 0x0001;
+    pub const UNINITIALIZE_SELECT: i16 = 0x0002;
+    pub const GET_PROPERTY_INFO_SELECT: i16 = 0x0003;
+    pub const GET_PROPERTY_SELECT: i16 = 0x0004;
+    pub const SET_PROPERTY_SELECT: i16 = 0x0005;
+    pub const GET_PARAMETER_SELECT: i16 = 0x0006;
+    pub const SET_PARAMETER_SELECT: i16 = 0x0007;
+    pub const RESET_SELECT: i16 = 0x0009;
+    pub const ADD_PROPERTY_LISTENER_SELECT: i16 = 0x000A;
+    pub const REMOVE_PROPERTY_LISTENER_SELECT: i16 = 0x000B;
+    pub const ADD_RENDER_NOTIFY_SELECT: i16 = 0x000C;
+    pub const REMOVE_RENDER_NOTIFY_SELECT: i16 = 0x000D;
+    pub const RENDER_SELECT: i16 = 0x000E;
+    pub const REMOVE_PROPERTY_LISTENER_WITH_USER_DATA_SELECT: i16 = 0x0012;
+    pub const MIDI_EVENT_SELECT: i16 = 0x0101;
+    pub const SYS_EX_SELECT: i16 = 0x0102;
+
+    pub const SCOPE_GLOBAL: u32 = 0;
+    pub const SCOPE_INPUT: u32 = 1;
+    pub const SCOPE_OUTPUT: u32 = 2;
+
+    pub const PROPERTY_SAMPLE_RATE: u32 = 2;
+    pub const PROPERTY_STREAM_FORMAT: u32 = 8;
+    pub const PROPERTY_ELEMENT_COUNT: u32 = 11;
+    pub const PROPERTY_LATENCY: u32 = 12;
+    pub const PROPERTY_SUPPORTED_NUM_CHANNELS: u32 = 13;
+    pub const PROPERTY_MAXIMUM_FRAMES_PER_SLICE: u32 = 14;
+    pub const PROPERTY_TAIL_TIME: u32 = 20;
+    pub const PROPERTY_LAST_RENDER_ERROR: u32 = 22;
+    pub const PROPERTY_SET_RENDER_CALLBACK: u32 = 23;
+    pub const PROPERTY_IN_PLACE_PROCESSING: u32 = 29;
+
+    /// `kAudioFormatLinearPCM`: `'lpcm'`
+    pub const FORMAT_LINEAR_PCM: u32 = 0x6C70_636D;
+    pub const FORMAT_FLAG_IS_FLOAT: u32 = 1;
+    pub const FORMAT_FLAG_IS_PACKED: u32 = 1 << 3;
+    pub const FORMAT_FLAG_IS_NON_INTERLEAVED: u32 = 1 << 5;
+
+    /// `AudioComponentDescription`
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug)]
+    pub struct AudioComponentDescription {
+        pub component_type: u32,
+        pub component_sub_type: u32,
+        pub component_manufacturer: u32,
+        pub component_flags: u32,
+        pub component_flags_mask: u32,
+    }
+
+    /// `AudioComponentPlugInInterface`
+    #[repr(C)]
+    pub struct AudioComponentPlugInInterface {
+        pub open: unsafe extern "C" fn(this: *mut c_void, instance: *mut c_void) -> OSStatus,
+        pub close: unsafe extern "C" fn(this: *mut c_void) -> OSStatus,
+        pub lookup: unsafe extern "C" fn(selector: i16) -> Option<AudioComponentMethod>,
+        pub reserved: *mut c_void,
+    }
+
+    /// `AudioStreamBasicDescription`
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct AudioStreamBasicDescription {
+        pub sample_rate: f64,
+        pub format_id: u32,
+        pub format_flags: u32,
+        pub bytes_per_packet: u32,
+        pub frames_per_packet: u32,
+        pub bytes_per_frame: u32,
+        pub channels_per_frame: u32,
+        pub bits_per_channel: u32,
+        pub reserved: u32,
+    }
+
+    /// `AudioBuffer`
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug)]
+    pub struct AudioBuffer {
+        pub number_channels: u32,
+        pub data_byte_size: u32,
+        pub data: *mut c_void,
+    }
+
+    /// `AudioBufferList`: the header of the list, `number_buffers` buffers follow.
+    #[repr(C)]
+    pub struct AudioBufferList {
+        pub number_buffers: u32,
+        pub buffers: [AudioBuffer; 1],
+    }
+
+    /// `AudioTimeStamp`, only passed on by this backend.
+    #[repr(C)]
+    pub struct AudioTimeStamp {
+        _private: [u8; 0],
+    }
+
+    /// `AURenderCallback`
+    pub type AURenderCallback = unsafe extern "C" fn(
+        ref_con: *mut c_void,
+        action_flags: *mut u32,
+        time_stamp: *const AudioTimeStamp,
+        bus_number: u32,
+        number_frames: u32,
+        data: *mut AudioBufferList,
+    ) -> OSStatus;
+
+    /// `AURenderCallbackStruct`
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct AURenderCallbackStruct {
+        pub input_proc: Option<AURenderCallback>,
+        pub input_proc_ref_con: *mut c_void,
+    }
+
+    /// `AUChannelInfo`
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct AUChannelInfo {
+        pub in_channels: i16,
+        pub out_channels: i16,
+    }
+}
+
+/// The type of an Audio Unit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComponentType {
+    /// An effect (`aufx`): audio in, audio out.
+    Effect,
+    /// An effect that also handles midi (`aumf`).
+    MusicEffect,
+    /// An instrument (`aumu`): midi in, audio out.
+    Instrument,
+    /// A generator (`augn`): audio out, no midi.
+    Generator,
+}
+
+impl ComponentType {
+    /// The four-character code of the type.
+    pub fn code(self) -> [u8; 4] {
+        match self {
+            ComponentType::Effect => *b"aufx",
+            ComponentType::MusicEffect => *b"aumf",
+            ComponentType::Instrument => *b"aumu",
+            ComponentType::Generator => *b"augn",
+        }
+    }
+}
+
+/// A plugin that can be used with the Audio Unit backend should implement this trait in
+/// addition to the traits for the meta-data, see the [module level documentation].
+///
+/// [module level documentation]: ./index.html
+pub trait AuPluginMeta: CommonPluginMeta + AudioHandlerMeta {
+    fn component_type(&self) -> ComponentType;
+
+    /// A four-character code that identifies the plugin, e.g. `*b"Mysy"`.
+    fn component_subtype(&self) -> [u8; 4];
+
+    /// A four-character code that identifies the manufacturer, e.g. `*b"Demo"`.
+    /// Apple reserves the codes that consist of lower case letters only.
+    fn component_manufacturer(&self) -> [u8; 4];
+
+    /// The name of the manufacturer, as shown by the host.
+    fn manufacturer_name(&self) -> &str;
+
+    /// The version as `0xMMMMmmbb`: major version, minor version and bug fix version.
+    /// The default is 1.0.0.
+    fn version(&self) -> u32 {
+        0x0001_0000
+    }
+}
+
+/// Used internally by the `au_init` macro. Normally, plugins do not need to use this.
+pub trait AuCreate {
+    fn create() -> Self;
+}
+
+/// The context that is passed to the plugin when rendering audio and when handling events.
+pub struct AuHost {
+    _private: (),
+}
+
+impl HostInterface for AuHost {
+    fn output_initialized(&self) -> bool {
+        false
+    }
+}
+
+const DEFAULT_SAMPLE_RATE: f64 = 44100.0;
+// `kAUDefaultMaxFramesPerSlice`
+const DEFAULT_MAXIMUM_FRAMES_PER_SLICE: u32 = 1156;
+
+/// Used internally by the `au_init` macro. Normally, plugins do not need to use this.
+pub struct AuPluginWrapper<P> {
+    plugin: P,
+    host: AuHost,
+    sample_rate: f64,
+    maximum_frames_per_slice: u32,
+    initialized: bool,
+    input_callback: AURenderCallbackStruct,
+    input_buffers: Vec<Vec<f32>>,
+    output_buffers: Vec<Vec<f32>>,
+    // Storage for an `AudioBufferList` with one buffer per input channel.
+    input_buffer_list: Vec<u64>,
+    inputs: VecStorage<&'static [f32]>,
+    outputs: VecStorage<&'static [f32]>,
+}
+
+impl<P> AuPluginWrapper<P>
+where
+    P: AuPluginMeta + AudioHandler,
+{
+    pub fn new(mut plugin: P) -> Self {
+        plugin.set_sample_rate(DEFAULT_SAMPLE_RATE);
+        let number_of_inputs = plugin.max_number_of_audio_inputs();
+        let number_of_outputs = plugin.max_number_of_audio_outputs();
+        let list_size = size_of::<AudioBufferList>()
+            + number_of_inputs.saturating_sub(1) * size_of::<AudioBuffer>();
+        AuPluginWrapper {
+            plugin,
+            host: AuHost { _private: () },
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            maximum_frames_per_slice: DEFAULT_MAXIMUM_FRAMES_PER_SLICE,
+            initialized: false,
+            input_callback: AURenderCallbackStruct {
+                input_proc: None,
+                input_proc_ref_con: ptr::null_mut(),
+            },
+            input_buffers: vec![Vec::new(); number_of_inputs],
+            output_buffers: vec![Vec::new(); number_of_outputs],
+            input_buffer_list: vec![0; list_size.div_ceil(8)],
+            inputs: VecStorage::with_capacity(number_of_inputs),
+            outputs: VecStorage::with_capacity(number_of_outputs),
+        }
+    }
+
+    pub fn plugin(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Allocate the buffers for the current maximum number of frames per slice and pass it to
+    /// the plugin.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory.
+    pub fn initialize(&mut self) -> OSStatus {
+        let frames = self.maximum_frames_per_slice as usize;
+        for buffer in self
+            .input_buffers
+            .iter_mut()
+            .chain(self.output_buffers.iter_mut())
+        {
+            buffer.clear();
+            buffer.resize(frames, 0.0);
+        }
+        self.plugin.set_max_buffer_size(frames);
+        self.initialized = true;
+        NO_ERR
+    }
+
+    pub fn uninitialize(&mut self) -> OSStatus {
+        self.initialized = false;
+        NO_ERR
+    }
+
+    fn number_of_channels(&self, scope: u32) -> usize {
+        match scope {
+            SCOPE_INPUT => self.plugin.max_number_of_audio_inputs(),
+            SCOPE_OUTPUT => self.plugin.max_number_of_audio_outputs(),
+            _ => 0,
+        }
+    }
+
+    fn number_of_elements(&self, scope: u32) -> Result<u32, OSStatus> {
+        match scope {
+            SCOPE_GLOBAL => Ok(1),
+            SCOPE_INPUT | SCOPE_OUTPUT => Ok((self.number_of_channels(scope) > 0) as u32),
+            _ => Err(ERR_INVALID_SCOPE),
+        }
+    }
+
+    fn check_element(&self, scope: u32, element: u32) -> Result<(), OSStatus> {
+        if element < self.number_of_elements(scope)? {
+            Ok(())
+        } else {
+            Err(ERR_INVALID_ELEMENT)
+        }
+    }
+
+    fn stream_format(&self, scope: u32) -> AudioStreamBasicDescription {
+        AudioStreamBasicDescription {
+            sample_rate: self.sample_rate,
+            format_id: FORMAT_LINEAR_PCM,
+            format_flags: FORMAT_FLAG_IS_FLOAT
+                | FORMAT_FLAG_IS_PACKED
+                | FORMAT_FLAG_IS_NON_INTERLEAVED,
+            bytes_per_packet: size_of::<f32>() as u32,
+            frames_per_packet: 1,
+            bytes_per_frame: size_of::<f32>() as u32,
+            channels_per_frame: self.number_of_channels(scope) as u32,
+            bits_per_channel: 8 * size_of::<f32>() as u32,
+            reserved: 0,
+        }
+    }
+
+    /// Return the size of the property and whether it is writable.
+    pub fn property_info(
+        &self,
+        property: u32,
+        scope: u32,
+        element: u32,
+    ) -> Result<(usize, bool), OSStatus> {
+        let global = || {
+            if scope == SCOPE_GLOBAL {
+                Ok(())
+            } else {
+                Err(ERR_INVALID_SCOPE)
+            }
+        };
+        match property {
+            PROPERTY_SAMPLE_RATE => {
+                self.check_element(scope, element)?;
+                Ok((size_of::<f64>(), true))
+            }
+            PROPERTY_STREAM_FORMAT => {
+                if scope == SCOPE_GLOBAL {
+                    return Err(ERR_INVALID_SCOPE);
+                }
+                self.check_element(scope, element)?;
+                Ok((size_of::<AudioStreamBasicDescription>(), true))
+            }
+            PROPERTY_ELEMENT_COUNT => {
+                self.number_of_elements(scope)?;
+                Ok((size_of::<u32>(), false))
+            }
+            PROPERTY_LATENCY | PROPERTY_TAIL_TIME => {
+                global()?;
+                Ok((size_of::<f64>(), false))
+            }
+            PROPERTY_SUPPORTED_NUM_CHANNELS => {
+                global()?;
+                Ok((size_of::<AUChannelInfo>(), false))
+            }
+            PROPERTY_MAXIMUM_FRAMES_PER_SLICE => {
+                global()?;
+                Ok((size_of::<u32>(), true))
+            }
+            PROPERTY_LAST_RENDER_ERROR | PROPERTY_IN_PLACE_PROCESSING => {
+                global()?;
+                Ok((size_of::<u32>(), false))
+            }
+            PROPERTY_SET_RENDER_CALLBACK => {
+                if scope != SCOPE_INPUT {
+                    return Err(ERR_INVALID_SCOPE);
+                }
+                self.check_element(scope, element)?;
+                Ok((size_of::<AURenderCallbackStruct>(), true))
+            }
+            _ => Err(ERR_INVALID_PROPERTY),
+        }
+    }
+
+    /// Write the value of the property to `data`, which has room for `*data_size` bytes.
+    ///
+    /// # Safety
+    /// `data` must be valid for writing `*data_size` bytes.
+    pub unsafe fn get_property(
+        &self,
+        property: u32,
+        scope: u32,
+        element: u32,
+        data: *mut c_void,
+        data_size: *mut u32,
+    ) -> Result<(), OSStatus> {
+        let (size, _) = self.property_info(property, scope, element)?;
+        if data.is_null() || data_size.is_null() || (*data_size as usize) < size {
+            return Err(PARAM_ERR);
+        }
+        match property {
+            PROPERTY_SAMPLE_RATE => ptr::write_unaligned(data as *mut f64, self.sample_rate),
+            PROPERTY_STREAM_FORMAT => {
+                ptr::write_unaligned(data as *mut _, self.stream_format(scope))
+            }
+            PROPERTY_ELEMENT_COUNT => {
+                ptr::write_unaligned(data as *mut u32, self.number_of_elements(scope)?)
+            }
+            PROPERTY_LATENCY | PROPERTY_TAIL_TIME => ptr::write_unaligned(data as *mut f64, 0.0),
+            PROPERTY_SUPPORTED_NUM_CHANNELS => ptr::write_unaligned(
+                data as *mut AUChannelInfo,
+                AUChannelInfo {
+                    in_channels: self.plugin.max_number_of_audio_inputs() as i16,
+                    out_channels: self.plugin.max_number_of_audio_outputs() as i16,
+                },
+            ),
+            PROPERTY_MAXIMUM_FRAMES_PER_SLICE => {
+                ptr::write_unaligned(data as *mut u32, self.maximum_frames_per_slice)
+            }
+            PROPERTY_LAST_RENDER_ERROR | PROPERTY_IN_PLACE_PROCESSING => {
+                ptr::write_unaligned(data as *mut u32, 0)
+            }
+            PROPERTY_SET_RENDER_CALLBACK => {
+                ptr::write_unaligned(data as *mut AURenderCallbackStruct, self.input_callback)
+            }
+            _ => return Err(ERR_INVALID_PROPERTY),
+        }
+        *data_size = size as u32;
+        Ok(())
+    }
+
+    /// Set the property to the value at `data`.
+    ///
+    /// # Safety
+    /// `data` must be valid for reading `data_size` bytes.
+    pub unsafe fn set_property(
+        &mut self,
+        property: u32,
+        scope: u32,
+        element: u32,
+        data: *const c_void,
+        data_size: u32,
+    ) -> Result<(), OSStatus> {
+        let (size, writable) = self.property_info(property, scope, element)?;
+        if !writable {
+            return Err(ERR_PROPERTY_NOT_WRITABLE);
+        }
+        if data.is_null() || (data_size as usize) < size {
+            return Err(PARAM_ERR);
+        }
+        match property {
+            PROPERTY_SAMPLE_RATE => {
+                self.change_sample_rate(ptr::read_unaligned(data as *const f64))
+            }
+            PROPERTY_STREAM_FORMAT => {
+                let format = ptr::read_unaligned(data as *const AudioStreamBasicDescription);
+                let current = self.stream_format(scope);
+                let supported = AudioStreamBasicDescription {
+                    sample_rate: format.sample_rate,
+                    ..current
+                };
+                if format != supported {
+                    return Err(ERR_FORMAT_NOT_SUPPORTED);
+                }
+                self.change_sample_rate(format.sample_rate)
+            }
+            PROPERTY_MAXIMUM_FRAMES_PER_SLICE => {
+                let frames = ptr::read_unaligned(data as *const u32);
+                if frames != self.maximum_frames_per_slice {
+                    if self.initialized {
+                        return Err(ERR_INITIALIZED);
+                    }
+                    self.maximum_frames_per_slice = frames;
+                }
+                Ok(())
+            }
+            PROPERTY_SET_RENDER_CALLBACK => {
+                self.input_callback = ptr::read_unaligned(data as *const AURenderCallbackStruct);
+                Ok(())
+            }
+            _ => Err(ERR_INVALID_PROPERTY),
+        }
+    }
+
+    fn change_sample_rate(&mut self, sample_rate: f64) -> Result<(), OSStatus> {
+        if sample_rate <= 0.0 {
+            return Err(ERR_FORMAT_NOT_SUPPORTED);
+        }
+        if sample_rate != self.sample_rate {
+            if self.initialized {
+                return Err(ERR_INITIALIZED);
+            }
+            trace!("sample_rate: {}", sample_rate);
+            self.sample_rate = sample_rate;
+            self.plugin.set_sample_rate(sample_rate);
+        }
+        Ok(())
+    }
+}
+
+unsafe fn buffers<'a>(list: *mut AudioBufferList) -> &'a mut [AudioBuffer] {
+    slice::from_raw_parts_mut(
+        ptr::addr_of_mut!((*list).buffers) as *mut AudioBuffer,
+        (*list).number_buffers as usize,
+    )
+}
+
+impl<P> AuPluginWrapper<P>
+where
+    P: AuPluginMeta
+        + AudioHandler
+        + ContextualEventHandler<Timed<RawMidiEvent>, AuHost>
+        + ContextualAudioRenderer<f32, AuHost>,
+    for<'a> P: ContextualEventHandler<Timed<SysExEvent<'a>>, AuHost>,
+{
+    /// Pull the input from the host and render the output of one buffer.
+    ///
+    /// # Safety
+    /// `data` must point to a valid buffer list, as passed by the host.
+    pub unsafe fn render(
+        &mut self,
+        action_flags: *mut u32,
+        time_stamp: *const AudioTimeStamp,
+        bus: u32,
+        number_of_frames: u32,
+        data: *mut AudioBufferList,
+    ) -> OSStatus {
+        #[cfg(feature = "flush-denormals")]
+        let _denormal_guard = DenormalGuard::new();
+        if !self.initialized {
+            return ERR_UNINITIALIZED;
+        }
+        if self.check_element(SCOPE_OUTPUT, bus).is_err() {
+            return ERR_INVALID_ELEMENT;
+        }
+        if number_of_frames > self.maximum_frames_per_slice {
+            return ERR_TOO_MANY_FRAMES_TO_PROCESS;
+        }
+        if data.is_null() || (*data).number_buffers as usize != self.output_buffers.len() {
+            return PARAM_ERR;
+        }
+        let frames = number_of_frames as usize;
+        let byte_size = number_of_frames * size_of::<f32>() as u32;
+
+        let mut inputs = self.inputs.vec_guard();
+        if !self.input_buffers.is_empty() {
+            let list = self.input_buffer_list.as_mut_ptr() as *mut AudioBufferList;
+            (*list).number_buffers = self.input_buffers.len() as u32;
+            for (buffer, storage) in buffers(list).iter_mut().zip(self.input_buffers.iter_mut()) {
+                *buffer = AudioBuffer {
+                    number_channels: 1,
+                    data_byte_size: byte_size,
+                    data: storage.as_mut_ptr() as *mut c_void,
+                };
+            }
+            match self.input_callback.input_proc {
+                Some(input_proc) => {
+                    let status = input_proc(
+                        self.input_callback.input_proc_ref_con,
+                        action_flags,
+                        time_stamp,
+                        0,
+                        number_of_frames,
+                        list,
+                    );
+                    if status != NO_ERR {
+                        return status;
+                    }
+                }
+                None => {
+                    for storage in self.input_buffers.iter_mut() {
+                        storage[..frames]
+                            .iter_mut()
+                            .for_each(|sample| *sample = 0.0);
+                    }
+                }
+            }
+            // The host may have replaced our buffers by its own.
+            for (buffer, storage) in buffers(list).iter().zip(self.input_buffers.iter()) {
+                let input = if buffer.data.is_null() {
+                    storage.as_ptr()
+                } else {
+                    buffer.data as *const f32
+                };
+                inputs.push(slice::from_raw_parts(input, frames));
+            }
+        }
+
+        let mut outputs = self.outputs.vec_guard();
+        for (buffer, storage) in buffers(data).iter_mut().zip(self.output_buffers.iter_mut()) {
+            if buffer.data.is_null() {
+                buffer.data = storage.as_mut_ptr() as *mut c_void;
+            }
+            buffer.data_byte_size = byte_size;
+            outputs.push(slice::from_raw_parts_mut(buffer.data as *mut f32, frames));
+        }
+
+        self.plugin
+            .render_buffer(inputs.as_slice(), outputs.as_mut_slice(), &mut self.host);
+        NO_ERR
+    }
+
+    pub fn midi_event(&mut self, status: u32, data1: u32, data2: u32, offset: u32) -> OSStatus {
+        match RawMidiEvent::try_new(&[status as u8, data1 as u8, data2 as u8]) {
+            Some(event) => {
+                self.plugin
+                    .handle_event(Timed::new(offset, event), &mut self.host);
+                NO_ERR
+            }
+            None => {
+                warn!(
+                    "Ignoring invalid midi event: {:X} {:X} {:X}",
+                    status, data1, data2
+                );
+                PARAM_ERR
+            }
+        }
+    }
+
+    pub fn sys_ex(&mut self, data: &[u8]) -> OSStatus {
+        if data.first() != Some(&0xF0) {
+            warn!("Ignoring invalid system exclusive message.");
+            return PARAM_ERR;
+        }
+        self.plugin
+            .handle_event(Timed::new(0, SysExEvent::new(data)), &mut self.host);
+        NO_ERR
+    }
+}
+
+/// The instance that is passed to the methods of the Audio Unit.
+#[repr(C)]
+struct AuInstance<P> {
+    // Must be the first field: the host passes a pointer to the interface as `this`.
+    interface: AudioComponentPlugInInterface,
+    wrapper: AuPluginWrapper<P>,
+}
+
+unsafe fn wrapper<'a, P>(this: *mut c_void) -> &'a mut AuPluginWrapper<P> {
+    &mut (*(this as *mut AuInstance<P>)).wrapper
+}
+
+/// Used internally by the `au_init` macro. Normally, plugins do not need to use this.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// This function allocates memory.
+pub fn factory<P>() -> *mut AudioComponentPlugInInterface
+where
+    P: AuCreate
+        + AuPluginMeta
+        + AudioHandler
+        + ContextualEventHandler<Timed<RawMidiEvent>, AuHost>
+        + ContextualAudioRenderer<f32, AuHost>,
+    for<'a> P: ContextualEventHandler<Timed<SysExEvent<'a>>, AuHost>,
+{
+    let instance = Box::new(AuInstance {
+        interface: AudioComponentPlugInInterface {
+            open,
+            close: close::<P>,
+            lookup: lookup::<P>,
+            reserved: ptr::null_mut(),
+        },
+        wrapper: AuPluginWrapper::new(P::create()),
+    });
+    Box::into_raw(instance) as *mut AudioComponentPlugInInterface
+}
+
+unsafe extern "C" fn open(_this: *mut c_void, _instance: *mut c_void) -> OSStatus {
+    NO_ERR
+}
+
+unsafe extern "C" fn close<P>(this: *mut c_void) -> OSStatus {
+    drop(Box::from_raw(this as *mut AuInstance<P>));
+    NO_ERR
+}
+
+unsafe extern "C" fn lookup<P>(selector: i16) -> Option<AudioComponentMethod>
+where
+    P: AuPluginMeta
+        + AudioHandler
+        + ContextualEventHandler<Timed<RawMidiEvent>, AuHost>
+        + ContextualAudioRenderer<f32, AuHost>,
+    for<'a> P: ContextualEventHandler<Timed<SysExEvent<'a>>, AuHost>,
+{
+    let method = match selector {
+        INITIALIZE_SELECT => initialize::<P> as *const (),
+        UNINITIALIZE_SELECT => uninitialize::<P> as *const (),
+        GET_PROPERTY_INFO_SELECT => get_property_info::<P> as *const (),
+        GET_PROPERTY_SELECT => get_property::<P> as *const (),
+        SET_PROPERTY_SELECT => set_property::<P> as *const (),
+        GET_PARAMETER_SELECT => get_parameter as *const (),
+        SET_PARAMETER_SELECT => set_parameter as *const (),
+        RESET_SELECT => reset as *const (),
+        ADD_PROPERTY_LISTENER_SELECT
+        | REMOVE_PROPERTY_LISTENER_SELECT
+        | REMOVE_PROPERTY_LISTENER_WITH_USER_DATA_SELECT
+        | ADD_RENDER_NOTIFY_SELECT
+        | REMOVE_RENDER_NOTIFY_SELECT => ignore_listener as *const (),
+        RENDER_SELECT => render::<P> as *const (),
+        MIDI_EVENT_SELECT => midi_event::<P> as *const (),
+        SYS_EX_SELECT => sys_ex::<P> as *const (),
+        _ => return None,
+    };
+    Some(std::mem::transmute::<*const (), AudioComponentMethod>(
+        method,
+    ))
+}
+
+unsafe extern "C" fn initialize<P>(this: *mut c_void) -> OSStatus
+where
+    P: AuPluginMeta + AudioHandler,
+{
+    wrapper::<P>(this).initialize()
+}
+
+unsafe extern "C" fn uninitialize<P>(this: *mut c_void) -> OSStatus
+where
+    P: AuPluginMeta + AudioHandler,
+{
+    wrapper::<P>(this).uninitialize()
+}
+
+unsafe extern "C" fn get_property_info<P>(
+    this: *mut c_void,
+    property: u32,
+    scope: u32,
+    element: u32,
+    data_size: *mut u32,
+    writable: *mut u8,
+) -> OSStatus
+where
+    P: AuPluginMeta + AudioHandler,
+{
+    match wrapper::<P>(this).property_info(property, scope, element) {
+        Ok((size, is_writable)) => {
+            if !data_size.is_null() {
+                *data_size = size as u32;
+            }
+            if !writable.is_null() {
+                *writable = is_writable as u8;
+            }
+            NO_ERR
+        }
+        Err(status) => status,
+    }
+}
+
+unsafe extern "C" fn get_property<P>(
+    this: *mut c_void,
+    property: u32,
+    scope: u32,
+    element: u32,
+    data: *mut c_void,
+    data_size: *mut u32,
+) -> OSStatus
+where
+    P: AuPluginMeta + AudioHandler,
+{
+    match wrapper::<P>(this).get_property(property, scope, element, data, data_size) {
+        Ok(()) => NO_ERR,
+        Err(status) => status,
+    }
+}
+
+unsafe extern "C" fn set_property<P>(
+    this: *mut c_void,
+    property: u32,
+    scope: u32,
+    element: u32,
+    data: *const c_void,
+    data_size: u32,
+) -> OSStatus
+where
+    P: AuPluginMeta + AudioHandler,
+{
+    match wrapper::<P>(this).set_property(property, scope, element, data, data_size) {
+        Ok(()) => NO_ERR,
+        Err(status) => status,
+    }
+}
+
+unsafe extern "C" fn get_parameter(
+    _this: *mut c_void,
+    _parameter: u32,
+    _scope: u32,
+    _element: u32,
+    _value: *mut f32,
+) -> OSStatus {
+    ERR_INVALID_PARAMETER
+}
+
+unsafe extern "C" fn set_parameter(
+    _this: *mut c_void,
+    _parameter: u32,
+    _scope: u32,
+    _element: u32,
+    _value: f32,
+    _buffer_offset: u32,
+) -> OSStatus {
+    ERR_INVALID_PARAMETER
+}
+
+unsafe extern "C" fn reset(_this: *mut c_void, _scope: u32, _element: u32) -> OSStatus {
+    NO_ERR
+}
+
+// The properties never change by themselves, so listeners are never notified.
+unsafe extern "C" fn ignore_listener(_this: *mut c_void) -> OSStatus {
+    NO_ERR
+}
+
+unsafe extern "C" fn render<P>(
+    this: *mut c_void,
+    action_flags: *mut u32,
+    time_stamp: *const AudioTimeStamp,
+    bus: u32,
+    number_of_frames: u32,
+    data: *mut AudioBufferList,
+) -> OSStatus
+where
+    P: AuPluginMeta
+        + AudioHandler
+        + ContextualEventHandler<Timed<RawMidiEvent>, AuHost>
+        + ContextualAudioRenderer<f32, AuHost>,
+    for<'a> P: ContextualEventHandler<Timed<SysExEvent<'a>>, AuHost>,
+{
+    wrapper::<P>(this).render(action_flags, time_stamp, bus, number_of_frames, data)
+}
+
+unsafe extern "C" fn midi_event<P>(
+    this: *mut c_void,
+    status: u32,
+    data1: u32,
+    data2: u32,
+    offset: u32,
+) -> OSStatus
+where
+    P: AuPluginMeta
+        + AudioHandler
+        + ContextualEventHandler<Timed<RawMidiEvent>, AuHost>
+        + ContextualAudioRenderer<f32, AuHost>,
+    for<'a> P: ContextualEventHandler<Timed<SysExEvent<'a>>, AuHost>,
+{
+    wrapper::<P>(this).midi_event(status, data1, data2, offset)
+}
+
+unsafe extern "C" fn sys_ex<P>(this: *mut c_void, data: *const u8, length: u32) -> OSStatus
+where
+    P: AuPluginMeta
+        + AudioHandler
+        + ContextualEventHandler<Timed<RawMidiEvent>, AuHost>
+        + ContextualAudioRenderer<f32, AuHost>,
+    for<'a> P: ContextualEventHandler<Timed<SysExEvent<'a>>, AuHost>,
+{
+    if data.is_null() {
+        return PARAM_ERR;
+    }
+    wrapper::<P>(this).sys_ex(slice::from_raw_parts(data, length as usize))
+}
+
+// Escape a string for an xml text node.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn four_char_code(code: [u8; 4]) -> String {
+    escape(&String::from_utf8_lossy(&code))
+}
+
+/// Generate the contents of the `Info.plist` file of the bundle from the meta-data of the
+/// plugin.
+///
+/// `executable` is the file name of the dynamic library in `Contents/MacOS`,
+/// `bundle_identifier` is a reverse domain name, e.g. `com.example.my-plugin`, and
+/// `factory_function` is the name of the factory function that is defined with the
+/// [`au_init`] macro.
+///
+/// [`au_init`]: ../../macro.au_init.html
+pub fn info_plist<P>(
+    plugin: &P,
+    executable: &str,
+    bundle_identifier: &str,
+    factory_function: &str,
+) -> String
+where
+    P: AuPluginMeta,
+{
+    let version = plugin.version();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>CFBundleExecutable</key>
+    <string>{executable}</string>
+    <key>CFBundleIdentifier</key>
+    <string>{identifier}</string>
+    <key>CFBundleName</key>
+    <string>{name}</string>
+    <key>CFBundlePackageType</key>
+    <string>BNDL</string>
+    <key>CFBundleVersion</key>
+    <string>{major}.{minor}.{bug_fix}</string>
+    <key>AudioComponents</key>
+    <array>
+        <dict>
+            <key>factoryFunction</key>
+            <string>{factory_function}</string>
+            <key>manufacturer</key>
+            <string>{manufacturer}</string>
+            <key>name</key>
+            <string>{manufacturer_name}: {name}</string>
+            <key>subtype</key>
+            <string>{subtype}</string>
+            <key>type</key>
+            <string>{component_type}</string>
+            <key>version</key>
+            <integer>{version}</integer>
+        </dict>
+    </array>
+</dict>
+</plist>
+"#,
+        executable = escape(executable),
+        identifier = escape(bundle_identifier),
+        name = escape(plugin.name()),
+        major = version >> 16,
+        minor = (version >> 8) & 0xFF,
+        bug_fix = version & 0xFF,
+        factory_function = escape(factory_function),
+        manufacturer = four_char_code(plugin.component_manufacturer()),
+        manufacturer_name = escape(plugin.manufacturer_name()),
+        subtype = four_char_code(plugin.component_subtype()),
+        component_type = four_char_code(plugin.component_type().code()),
+        version = version,
+    )
+}
+
+/// Export the plugin as an Audio Unit.
+/// You call this with the name of the factory function (which must also be used in
+/// `Info.plist`, see [`info_plist`]) and the declaration of a function that creates your
+/// plugin. This function may also do some setup (e.g. initialize logging).
+///
+/// The plugin must implement the traits that are required by the [`au_backend`] module.
+///
+/// Example:
+/// ```
+/// # #[macro_use] extern crate rsynth;
+/// use rsynth::backend::au_backend::{AuHost, AuPluginMeta, ComponentType};
+/// use rsynth::event::{ContextualEventHandler, RawMidiEvent, SysExEvent, Timed};
+/// use rsynth::meta::{InOut, Meta, MetaData};
+/// use rsynth::{AudioHandler, ContextualAudioRenderer};
+///
+/// struct MyPlugin {
+///     meta: MetaData<&'static str, &'static str, &'static str>,
+/// }
+///
+/// impl Meta for MyPlugin {
+///     type MetaData = MetaData<&'static str, &'static str, &'static str>;
+///     fn meta(&self) -> &Self::MetaData {
+///         &self.meta
+///     }
+/// }
+///
+/// impl AuPluginMeta for MyPlugin {
+///     fn component_type(&self) -> ComponentType {
+///         ComponentType::Instrument
+///     }
+///     fn component_subtype(&self) -> [u8; 4] {
+///         *b"Mysy"
+///     }
+///     fn component_manufacturer(&self) -> [u8; 4] {
+///         *b"Demo"
+///     }
+///     fn manufacturer_name(&self) -> &str {
+///         "Demo Audio"
+///     }
+/// }
+///
+/// impl AudioHandler for MyPlugin {
+///     // Implementation omitted for brevity.
+/// #     fn set_sample_rate(&mut self, new_sample_rate: f64) {}
+/// }
+///
+/// impl ContextualAudioRenderer<f32, AuHost> for MyPlugin {
+///     // Implementation omitted for brevity.
+/// #    fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut[&mut[f32]], context: &mut AuHost) {}
+/// }
+///
+/// impl ContextualEventHandler<Timed<RawMidiEvent>, AuHost> for MyPlugin {
+///     // Implementation omitted for brevity.
+/// #    fn handle_event(&mut self, event: Timed<RawMidiEvent>, context: &mut AuHost) {}
+/// }
+///
+/// impl<'a> ContextualEventHandler<Timed<SysExEvent<'a>>, AuHost> for MyPlugin {
+///     // Implementation omitted for brevity.
+/// #    fn handle_event(&mut self, event: Timed<SysExEvent<'a>>, context: &mut AuHost) {}
+/// }
+///
+/// au_init!(
+///     MyPluginFactory,
+///     fn init() -> MyPlugin {
+///         MyPlugin {
+///             meta: MetaData {
+///                 general_meta: "my_plugin",
+///                 audio_port_meta: InOut {
+///                     inputs: vec![],
+///                     outputs: vec!["left", "right"],
+///                 },
+///                 midi_port_meta: InOut {
+///                     inputs: vec!["midi in"],
+///                     outputs: vec![],
+///                 },
+///             },
+///         }
+///     }
+/// );
+/// # fn main() {}
+/// ```
+///
+/// [`info_plist`]: ./backend/au_backend/fn.info_plist.html
+/// [`au_backend`]: ./backend/au_backend/index.html
+#[macro_export]
+macro_rules! au_init {
+    ($factory_name:ident, fn $function_name:ident() -> $return_type:ty
+        $body:block
+    ) => {
+        fn $function_name() -> $return_type
+        $body
+
+        impl $crate::backend::au_backend::AuCreate for $return_type {
+            fn create() -> Self {
+                $function_name()
+            }
+        }
+
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        pub extern "C" fn $factory_name(
+            _description: *const $crate::backend::au_backend::sys::AudioComponentDescription,
+        ) -> *mut $crate::backend::au_backend::sys::AudioComponentPlugInInterface {
+            $crate::backend::au_backend::factory::<$return_type>()
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::{InOut, Meta, MetaData};
+
+    struct TestPlugin {
+        meta: MetaData<&'static str, &'static str, &'static str>,
+        sample_rate: f64,
+        max_buffer_size: usize,
+        events: Vec<Timed<RawMidiEvent>>,
+        sysex_lengths: Vec<usize>,
+    }
+
+    impl Meta for TestPlugin {
+        type MetaData = MetaData<&'static str, &'static str, &'static str>;
+        fn meta(&self) -> &Self::MetaData {
+            &self.meta
+        }
+    }
+
+    impl AuPluginMeta for TestPlugin {
+        fn component_type(&self) -> ComponentType {
+            ComponentType::MusicEffect
+        }
+        fn component_subtype(&self) -> [u8; 4] {
+            *b"Test"
+        }
+        fn component_manufacturer(&self) -> [u8; 4] {
+            *b"Rsyn"
+        }
+        fn manufacturer_name(&self) -> &str {
+            "rsynth & co"
+        }
+        fn version(&self) -> u32 {
+            0x0002_0103
+        }
+    }
+
+    impl AuCreate for TestPlugin {
+        fn create() -> Self {
+            TestPlugin {
+                meta: MetaData {
+                    general_meta: "Test",
+                    audio_port_meta: InOut {
+                        inputs: vec!["in"],
+                        outputs: vec!["left", "right"],
+                    },
+                    midi_port_meta: InOut {
+                        inputs: vec!["midi in"],
+                        outputs: vec![],
+                    },
+                },
+                sample_rate: 0.0,
+                max_buffer_size: 0,
+                events: Vec::new(),
+                sysex_lengths: Vec::new(),
+            }
+        }
+    }
+
+    impl AudioHandler for TestPlugin {
+        fn set_sample_rate(&mut self, sample_rate: f64) {
+            self.sample_rate = sample_rate;
+        }
+
+        fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+            self.max_buffer_size = max_buffer_size;
+        }
+    }
+
+    impl ContextualAudioRenderer<f32, AuHost> for TestPlugin {
+        fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut [&mut [f32]], _: &mut AuHost) {
+            for output in outputs.iter_mut() {
+                for (output, input) in output.iter_mut().zip(inputs[0].iter()) {
+                    *output = 2.0 * input;
+                }
+            }
+        }
+    }
+
+    impl ContextualEventHandler<Timed<RawMidiEvent>, AuHost> for TestPlugin {
+        fn handle_event(&mut self, event: Timed<RawMidiEvent>, _: &mut AuHost) {
+            self.events.push(event);
+        }
+    }
+
+    impl<'a> ContextualEventHandler<Timed<SysExEvent<'a>>, AuHost> for TestPlugin {
+        fn handle_event(&mut self, event: Timed<SysExEvent<'a>>, _: &mut AuHost) {
+            self.sysex_lengths.push(event.event.data().len());
+        }
+    }
+
+    // Fills the input with 1, 2, 3, ...
+    unsafe extern "C" fn input(
+        _ref_con: *mut c_void,
+        _action_flags: *mut u32,
+        _time_stamp: *const AudioTimeStamp,
+        bus_number: u32,
+        number_frames: u32,
+        data: *mut AudioBufferList,
+    ) -> OSStatus {
+        assert_eq!(bus_number, 0);
+        for buffer in buffers(data) {
+            let samples =
+                slice::from_raw_parts_mut(buffer.data as *mut f32, number_frames as usize);
+            for (index, sample) in samples.iter_mut().enumerate() {
+                *sample = (index + 1) as f32;
+            }
+        }
+        NO_ERR
+    }
+
+    type Initialize = unsafe extern "C" fn(*mut c_void) -> OSStatus;
+    type GetPropertyInfo =
+        unsafe extern "C" fn(*mut c_void, u32, u32, u32, *mut u32, *mut u8) -> OSStatus;
+    type GetProperty =
+        unsafe extern "C" fn(*mut c_void, u32, u32, u32, *mut c_void, *mut u32) -> OSStatus;
+    type SetProperty =
+        unsafe extern "C" fn(*mut c_void, u32, u32, u32, *const c_void, u32) -> OSStatus;
+    type Render = unsafe extern "C" fn(
+        *mut c_void,
+        *mut u32,
+        *const AudioTimeStamp,
+        u32,
+        u32,
+        *mut AudioBufferList,
+    ) -> OSStatus;
+    type MidiEvent = unsafe extern "C" fn(*mut c_void, u32, u32, u32, u32) -> OSStatus;
+    type SysEx = unsafe extern "C" fn(*mut c_void, *const u8, u32) -> OSStatus;
+
+    unsafe fn method<F>(interface: &AudioComponentPlugInInterface, selector: i16) -> F {
+        let method = (interface.lookup)(selector).expect("the selector is supported");
+        std::mem::transmute_copy(&method)
+    }
+
+    #[test]
+    fn plugin_renders_through_the_c_interface() {
+        unsafe {
+            let this = factory::<TestPlugin>();
+            let interface = &*this;
+            let this = this as *mut c_void;
+            assert_eq!((interface.open)(this, ptr::null_mut()), NO_ERR);
+            assert!((interface.lookup)(0x7FFF).is_none());
+
+            let get_property_info: GetPropertyInfo = method(interface, GET_PROPERTY_INFO_SELECT);
+            let get_property: GetProperty = method(interface, GET_PROPERTY_SELECT);
+            let set_property: SetProperty = method(interface, SET_PROPERTY_SELECT);
+            let initialize: Initialize = method(interface, INITIALIZE_SELECT);
+            let render: Render = method(interface, RENDER_SELECT);
+            let midi_event: MidiEvent = method(interface, MIDI_EVENT_SELECT);
+            let sys_ex: SysEx = method(interface, SYS_EX_SELECT);
+
+            let mut size = 0_u32;
+            let mut writable = 0_u8;
+            assert_eq!(
+                get_property_info(
+                    this,
+                    PROPERTY_STREAM_FORMAT,
+                    SCOPE_OUTPUT,
+                    0,
+                    &mut size,
+                    &mut writable
+                ),
+                NO_ERR
+            );
+            assert_eq!(size as usize, size_of::<AudioStreamBasicDescription>());
+            assert_eq!(writable, 1);
+            assert_eq!(
+                get_property_info(
+                    this,
+                    PROPERTY_STREAM_FORMAT,
+                    SCOPE_OUTPUT,
+                    1,
+                    &mut size,
+                    &mut writable
+                ),
+                ERR_INVALID_ELEMENT
+            );
+            assert_eq!(
+                get_property_info(this, 0xFFFF, SCOPE_GLOBAL, 0, &mut size, &mut writable),
+                ERR_INVALID_PROPERTY
+            );
+
+            let mut format = std::mem::zeroed::<AudioStreamBasicDescription>();
+            let mut size = size_of::<AudioStreamBasicDescription>() as u32;
+            assert_eq!(
+                get_property(
+                    this,
+                    PROPERTY_STREAM_FORMAT,
+                    SCOPE_OUTPUT,
+                    0,
+                    &mut format as *mut _ as *mut c_void,
+                    &mut size
+                ),
+                NO_ERR
+            );
+            assert_eq!(format.channels_per_frame, 2);
+            assert_eq!(format.sample_rate, 44100.0);
+
+            // Change the sample rate with the stream format.
+            format.sample_rate = 48000.0;
+            let size = size_of::<AudioStreamBasicDescription>() as u32;
+            assert_eq!(
+                set_property(
+                    this,
+                    PROPERTY_STREAM_FORMAT,
+                    SCOPE_OUTPUT,
+                    0,
+                    &format as *const _ as *const c_void,
+                    size
+                ),
+                NO_ERR
+            );
+            format.channels_per_frame = 3;
+            assert_eq!(
+                set_property(
+                    this,
+                    PROPERTY_STREAM_FORMAT,
+                    SCOPE_OUTPUT,
+                    0,
+                    &format as *const _ as *const c_void,
+                    size
+                ),
+                ERR_FORMAT_NOT_SUPPORTED
+            );
+
+            let frames = 4_u32;
+            assert_eq!(
+                set_property(
+                    this,
+                    PROPERTY_MAXIMUM_FRAMES_PER_SLICE,
+                    SCOPE_GLOBAL,
+                    0,
+                    &frames as *const _ as *const c_void,
+                    4
+                ),
+                NO_ERR
+            );
+            let callback = AURenderCallbackStruct {
+                input_proc: Some(input),
+                input_proc_ref_con: ptr::null_mut(),
+            };
+            assert_eq!(
+                set_property(
+                    this,
+                    PROPERTY_SET_RENDER_CALLBACK,
+                    SCOPE_INPUT,
+                    0,
+                    &callback as *const _ as *const c_void,
+                    size_of::<AURenderCallbackStruct>() as u32
+                ),
+                NO_ERR
+            );
+
+            let mut list = [0_u64; 5];
+            let list_pointer = list.as_mut_ptr() as *mut AudioBufferList;
+            (*list_pointer).number_buffers = 2;
+            assert_eq!(
+                render(this, ptr::null_mut(), ptr::null(), 0, frames, list_pointer),
+                ERR_UNINITIALIZED
+            );
+            assert_eq!(initialize(this), NO_ERR);
+            assert_eq!(
+                render(
+                    this,
+                    ptr::null_mut(),
+                    ptr::null(),
+                    0,
+                    frames + 1,
+                    list_pointer
+                ),
+                ERR_TOO_MANY_FRAMES_TO_PROCESS
+            );
+
+            assert_eq!(midi_event(this, 0x90, 60, 100, 2), NO_ERR);
+            let sysex = [0xF0, 1, 2, 3, 0xF7];
+            assert_eq!(sys_ex(this, sysex.as_ptr(), sysex.len() as u32), NO_ERR);
+
+            // The first output buffer is ours, the second one is provided by the plugin.
+            let mut left = [0.0_f32; 4];
+            buffers(list_pointer)[0].data = left.as_mut_ptr() as *mut c_void;
+            assert_eq!(
+                render(this, ptr::null_mut(), ptr::null(), 0, frames, list_pointer),
+                NO_ERR
+            );
+            assert_eq!(left, [2.0, 4.0, 6.0, 8.0]);
+            let right = buffers(list_pointer)[1];
+            assert!(!right.data.is_null());
+            assert_eq!(right.data_byte_size, 16);
+            assert_eq!(slice::from_raw_parts(right.data as *const f32, 4), &left);
+
+            let plugin = wrapper::<TestPlugin>(this).plugin();
+            assert_eq!(plugin.sample_rate, 48000.0);
+            assert_eq!(plugin.max_buffer_size, 4);
+            assert_eq!(
+                plugin.events,
+                vec![Timed::new(2, RawMidiEvent::new(&[0x90, 60, 100]))]
+            );
+            assert_eq!(plugin.sysex_lengths, vec![5]);
+
+            // The sample rate cannot be changed while the plugin is initialized.
+            let sample_rate = 96000.0_f64;
+            assert_eq!(
+                set_property(
+                    this,
+                    PROPERTY_SAMPLE_RATE,
+                    SCOPE_GLOBAL,
+                    0,
+                    &sample_rate as *const _ as *const c_void,
+                    8
+                ),
+                ERR_INITIALIZED
+            );
+
+            assert_eq!((interface.close)(this), NO_ERR);
+        }
+    }
+
+    #[test]
+    fn info_plist_is_generated_from_the_meta_data() {
+        let plist = info_plist(
+            &TestPlugin::create(),
+            "Test",
+            "com.example.test",
+            "TestFactory",
+        );
+        assert!(plist.contains("<key>CFBundleExecutable</key>\n    <string>Test</string>"));
+        assert!(plist.contains("<string>2.1.3</string>"));
+        assert!(plist.contains(
+            "            <key>factoryFunction</key>\n            <string>TestFactory</string>\n\
+             \x20           <key>manufacturer</key>\n            <string>Rsyn</string>\n\
+             \x20           <key>name</key>\n            <string>rsynth &amp; co: Test</string>\n\
+             \x20           <key>subtype</key>\n            <string>Test</string>\n\
+             \x20           <key>type</key>\n            <string>aumf</string>\n\
+             \x20           <key>version</key>\n            <integer>131331</integer>\n"
+        ));
+    }
+}
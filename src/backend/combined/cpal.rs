@@ -0,0 +1,161 @@
+//! Play the output of the combined back-end on an audio device, using the [`cpal`] back-end.
+//!
+//! Support is only enabled if `rsynth` is compiled with both the "backend-combined" and the
+//! "backend-cpal" feature.
+//!
+//! This allows listening to the same setup that is used for offline rendering and testing:
+//!
+//! ```no_run
+//! use rsynth::backend::combined::cpal::CpalAudioWriter;
+//! use rsynth::backend::cpal::CpalSettings;
+//!
+//! let settings = CpalSettings {
+//!     sample_rate: Some(44100),
+//!     ..CpalSettings::default()
+//! };
+//! // Two channels, with a buffer of 4096 frames between the renderer and the device.
+//! let writer = CpalAudioWriter::open(2, &settings, 4096).unwrap();
+//! // Use `writer` as the audio output, e.g. with `rsynth::backend::combined::run_with_options`.
+//! // ...
+//! writer.drain();
+//! ```
+//!
+//! [`cpal`]: ../../cpal/index.html
+use super::AudioWriter;
+use crate::backend::cpal::{start, CpalError, CpalHost, CpalSettings, CpalStreams};
+use crate::{AudioHandler, AudioHandlerMeta, ContextualAudioRenderer};
+use ringbuf::{Consumer, Producer, RingBuffer};
+use std::thread;
+use std::time::Duration;
+
+// How long to wait when the buffer is full or not yet empty.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// An [`AudioWriter`] that plays the audio on an audio device.
+///
+/// The audio is passed to the device over a lock-free queue. When the queue is full,
+/// [`write_buffer`] waits until the device has played enough audio, so that rendering is
+/// paced by the audio device. When the renderer cannot keep up, the device plays silence.
+///
+/// The sample rate of the device may differ from the sample rate of the input of the renderer,
+/// e.g. when the device does not support it. Set [`CpalSettings::sample_rate`] and check
+/// [`sample_rate`] to avoid this.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// [`write_buffer`] may sleep.
+///
+/// [`AudioWriter`]: ../trait.AudioWriter.html
+/// [`write_buffer`]: ../trait.AudioWriter.html#tymethod.write_buffer
+/// [`CpalSettings::sample_rate`]: ../../cpal/struct.CpalSettings.html#structfield.sample_rate
+/// [`sample_rate`]: #method.sample_rate
+pub struct CpalAudioWriter {
+    producer: Producer<f32>,
+    streams: CpalStreams,
+    number_of_channels: usize,
+}
+
+impl CpalAudioWriter {
+    /// Open the output device with the given settings and start playing.
+    ///
+    /// At most `buffer_size_in_frames` frames are queued between the renderer and the device.
+    ///
+    /// # Panics
+    /// Panics if `number_of_channels` or `buffer_size_in_frames` is zero.
+    pub fn open(
+        number_of_channels: usize,
+        settings: &CpalSettings,
+        buffer_size_in_frames: usize,
+    ) -> Result<Self, CpalError> {
+        assert!(number_of_channels > 0);
+        assert!(buffer_size_in_frames > 0);
+        let (producer, consumer) =
+            RingBuffer::new(buffer_size_in_frames * number_of_channels).split();
+        let player = QueuePlayer {
+            consumer,
+            number_of_channels,
+        };
+        let streams = start(player, settings)?;
+        Ok(CpalAudioWriter {
+            producer,
+            streams,
+            number_of_channels,
+        })
+    }
+
+    /// The sample rate of the output device.
+    pub fn sample_rate(&self) -> u32 {
+        self.streams.sample_rate()
+    }
+
+    /// Wait until the audio that has been written has been passed to the device.
+    pub fn drain(&self) {
+        while !self.producer.is_empty() {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl AudioWriter<f32> for CpalAudioWriter {
+    type Err = std::convert::Infallible;
+
+    fn write_buffer(&mut self, buffer: &[&[f32]]) -> Result<(), Self::Err> {
+        assert_eq!(buffer.len(), self.number_of_channels);
+        let length = buffer[0].len();
+        for frame in 0..length {
+            for channel in buffer.iter() {
+                let mut sample = channel[frame];
+                while let Err(rejected) = self.producer.push(sample) {
+                    sample = rejected;
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn number_of_channels(&self) -> Option<usize> {
+        Some(self.number_of_channels)
+    }
+
+    fn frames_per_second(&self) -> Option<u64> {
+        Some(self.sample_rate() as u64)
+    }
+}
+
+// Plays the interleaved samples from the queue.
+struct QueuePlayer {
+    consumer: Consumer<f32>,
+    number_of_channels: usize,
+}
+
+impl AudioHandlerMeta for QueuePlayer {
+    fn max_number_of_audio_inputs(&self) -> usize {
+        0
+    }
+
+    fn max_number_of_audio_outputs(&self) -> usize {
+        self.number_of_channels
+    }
+}
+
+impl AudioHandler for QueuePlayer {
+    fn set_sample_rate(&mut self, _sample_rate: f64) {}
+}
+
+impl ContextualAudioRenderer<f32, CpalHost> for QueuePlayer {
+    fn render_buffer(&mut self, _inputs: &[&[f32]], outputs: &mut [&mut [f32]], _: &mut CpalHost) {
+        let length = outputs.first().map(|output| output.len()).unwrap_or(0);
+        for frame in 0..length {
+            // Only play complete frames, so that the channels stay aligned.
+            let frame_is_available = self.consumer.len() >= self.number_of_channels;
+            for output in outputs.iter_mut() {
+                output[frame] = if frame_is_available {
+                    self.consumer.pop().unwrap_or(0.0)
+                } else {
+                    0.0
+                };
+            }
+        }
+    }
+}
@@ -2,13 +2,40 @@ use super::{AudioReader, AudioWriter, MidiWriter};
 use crate::event::{DeltaEvent, RawMidiEvent};
 use std::marker::PhantomData;
 
+/// Dummy audio input and output.
+///
+/// As an audio input, `AudioDummy` has no channels. This can be used for plugins without audio
+/// inputs, such as synthesizers and midi effects. Use [`with_duration`] to specify how many
+/// frames are rendered; by default, no frames are rendered.
+///
+/// As an audio output, `AudioDummy` ignores the audio.
+///
+/// [`with_duration`]: #method.with_duration
 pub struct AudioDummy<S> {
+    frames_per_second: u64,
+    duration_in_frames: u64,
     _phantom: PhantomData<S>,
 }
 
 impl<S> AudioDummy<S> {
     pub fn new() -> Self {
         AudioDummy {
+            frames_per_second: 44100,
+            duration_in_frames: 0,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Create a new `AudioDummy` with the given sample rate, for rendering
+    /// `duration_in_frames` frames.
+    ///
+    /// # Panics
+    /// Panics if `frames_per_second` is zero.
+    pub fn with_duration(frames_per_second: u64, duration_in_frames: u64) -> Self {
+        assert!(frames_per_second > 0);
+        AudioDummy {
+            frames_per_second,
+            duration_in_frames,
             _phantom: PhantomData,
         }
     }
@@ -21,11 +48,16 @@ impl<S> AudioReader<S> for AudioDummy<S> {
     }
 
     fn frames_per_second(&self) -> u64 {
-        44100
+        self.frames_per_second
     }
 
     fn fill_buffer(&mut self, _output: &mut [&mut [S]]) -> Result<usize, Self::Err> {
-        Ok(0) // TODO: Have a look at this implementation again: is this logical?
+        // There are no channels, so no frames can be written to the buffer.
+        Ok(0)
+    }
+
+    fn duration_in_frames(&self) -> Option<u64> {
+        Some(self.duration_in_frames)
     }
 }
 
@@ -0,0 +1,534 @@
+//! Sources and sinks of events for the [`run_with_events`] function.
+//!
+//! An [`EventSource`] provides the events that are passed to the plugin and an [`EventSink`]
+//! receives the events that the plugin emits while rendering.
+//!
+//! Timing
+//! ======
+//! Audio is rendered buffer by buffer. The timing of every buffer is described by a
+//! [`BufferTiming`]: the position of the first frame of the buffer (both in frames and in
+//! microseconds since rendering started), the number of frames in the buffer and the sample rate.
+//!
+//! * Before a buffer is rendered, [`EventSource::read_events`] is called once. It must pass the
+//!   events that fall within the buffer to the handler, in chronological order.
+//!   The `time_in_frames` of these events is relative to the start of the buffer and is
+//!   smaller than the number of frames in the buffer.
+//! * While a buffer is rendered, [`EventSink::write_event`] is called for every event that the
+//!   plugin emits. The `time_in_frames` of these events is relative to the start of the buffer.
+//!
+//! The following adapters are available:
+//!
+//! * `Vec<Timed<E>>`: as a source, the `time_in_frames` of the events is the number of frames
+//!   since rendering started; the events must be sorted and are removed from the `Vec` when
+//!   they are read. As a sink, the events are appended with `time_in_frames` the number of frames
+//!   since rendering started.
+//! * `Receiver<Timed<E>>`: as a source, every event that has been received is passed in the
+//!   next buffer and `time_in_frames` is the offset within that buffer.
+//! * `Sender<Timed<E>>` and `SyncSender<Timed<E>>`: as a sink, the events are sent with
+//!   `time_in_frames` the number of frames since rendering started.
+//! * [`DeltaEventSource`]: an iterator over [`DeltaEvent`]s, such as [`TestMidiReader`] and
+//!   `RimdMidiReader` (which reads `.mid` files), as a source.
+//! * `MidlyMidiReader`: reads all tracks of a `.mid` file, as a source (and as an iterator
+//!   over [`DeltaEvent`]s).
+//! * [`MidiWriterSink`]: a [`MidiWriter`], such as [`TestMidiWriter`], `RimdMidiWriter` and
+//!   `MidlyMidiWriter` (which write `.mid` files), as a sink.
+//! * [`MergedEventSource`]: merges the events of two sources, e.g. of a `.mid` file and of a
+//!   `Receiver` that gets the events from a live midi input. Merged sources can be merged again.
+//!
+//! A `&mut` reference to a source or a sink is also a source or a sink, so that it can be
+//! inspected after rendering.
+//!
+//! [`run_with_events`]: ../fn.run_with_events.html
+//! [`EventSource`]: ./trait.EventSource.html
+//! [`EventSource::read_events`]: ./trait.EventSource.html#tymethod.read_events
+//! [`EventSink`]: ./trait.EventSink.html
+//! [`EventSink::write_event`]: ./trait.EventSink.html#tymethod.write_event
+//! [`BufferTiming`]: ./struct.BufferTiming.html
+//! [`DeltaEventSource`]: ./struct.DeltaEventSource.html
+//! [`MidiWriterSink`]: ./struct.MidiWriterSink.html
+//! [`MergedEventSource`]: ./struct.MergedEventSource.html
+//! [`DeltaEvent`]: ../../../event/struct.DeltaEvent.html
+//! [`TestMidiReader`]: ../struct.TestMidiReader.html
+//! [`TestMidiWriter`]: ../struct.TestMidiWriter.html
+//! [`MidiWriter`]: ../trait.MidiWriter.html
+use super::{MidiWriter, MICROSECONDS_PER_SECOND};
+use crate::event::{DeltaEvent, EventHandler, RawMidiEvent, Timed};
+use std::iter::Peekable;
+use std::sync::mpsc::{Receiver, Sender, SyncSender};
+
+/// The timing of one buffer. See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferTiming {
+    /// The number of frames since rendering started, at the start of the buffer.
+    pub start_in_frames: u64,
+    /// The number of microseconds since rendering started, at the start of the buffer.
+    pub start_in_microseconds: u64,
+    /// The number of frames in the buffer.
+    pub number_of_frames: usize,
+    /// The sample rate.
+    pub frames_per_second: u64,
+}
+
+impl BufferTiming {
+    /// The offset within the buffer of the given time in microseconds since rendering started,
+    /// or `None` if the time is after the buffer.
+    /// Times before the start of the buffer give offset `0`.
+    pub fn frame_offset(&self, time_in_microseconds: u64) -> Option<u32> {
+        let offset = time_in_microseconds.saturating_sub(self.start_in_microseconds)
+            * self.frames_per_second
+            / MICROSECONDS_PER_SECOND;
+        if offset < self.number_of_frames as u64 {
+            Some(offset as u32)
+        } else {
+            None
+        }
+    }
+
+    /// The time in microseconds since rendering started of the given offset within the buffer.
+    pub fn time_in_microseconds(&self, frame_offset: u32) -> u64 {
+        self.start_in_microseconds
+            + frame_offset as u64 * MICROSECONDS_PER_SECOND / self.frames_per_second
+    }
+
+    /// The number of frames since rendering started of the given offset within the buffer.
+    pub fn time_in_frames(&self, frame_offset: u32) -> u64 {
+        self.start_in_frames + frame_offset as u64
+    }
+}
+
+/// Provides the events for the plugin.
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub trait EventSource<E> {
+    /// Pass the events that fall within the buffer with the given timing to `handler`,
+    /// in chronological order and with `time_in_frames` relative to the start of the buffer.
+    fn read_events<H: EventHandler<Timed<E>>>(&mut self, timing: &BufferTiming, handler: &mut H);
+
+    /// Return `true` when no more events will be passed to the handler.
+    ///
+    /// This is used to decide when to stop rendering the tail of the plugin.
+    /// The default implementation returns `true`, e.g. for sources that cannot tell if more
+    /// events will come.
+    fn is_exhausted(&mut self) -> bool {
+        true
+    }
+}
+
+/// Receives the events that are emitted by the plugin.
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub trait EventSink<E> {
+    /// Receive an event that is emitted during the buffer with the given timing, with
+    /// `time_in_frames` relative to the start of the buffer.
+    fn write_event(&mut self, timing: &BufferTiming, event: Timed<E>);
+}
+
+impl<E, T> EventSource<E> for &mut T
+where
+    T: EventSource<E>,
+{
+    fn read_events<H: EventHandler<Timed<E>>>(&mut self, timing: &BufferTiming, handler: &mut H) {
+        (**self).read_events(timing, handler)
+    }
+
+    fn is_exhausted(&mut self) -> bool {
+        (**self).is_exhausted()
+    }
+}
+
+impl<E, T> EventSink<E> for &mut T
+where
+    T: EventSink<E>,
+{
+    fn write_event(&mut self, timing: &BufferTiming, event: Timed<E>) {
+        (**self).write_event(timing, event)
+    }
+}
+
+impl<E> EventSource<E> for Vec<Timed<E>> {
+    fn read_events<H: EventHandler<Timed<E>>>(&mut self, timing: &BufferTiming, handler: &mut H) {
+        let end = timing.start_in_frames + timing.number_of_frames as u64;
+        let number_of_events = self
+            .iter()
+            .take_while(|event| (event.time_in_frames as u64) < end)
+            .count();
+        for event in self.drain(..number_of_events) {
+            handler.handle_event(Timed {
+                time_in_frames: (event.time_in_frames as u64).saturating_sub(timing.start_in_frames)
+                    as u32,
+                event: event.event,
+            });
+        }
+    }
+
+    fn is_exhausted(&mut self) -> bool {
+        self.is_empty()
+    }
+}
+
+// Convert `time_in_frames` from relative to the start of the buffer to relative to the
+// start of rendering.
+fn since_start<E>(timing: &BufferTiming, event: Timed<E>) -> Timed<E> {
+    let time_in_frames = timing.time_in_frames(event.time_in_frames);
+    Timed {
+        time_in_frames: std::cmp::min(time_in_frames, u32::MAX as u64) as u32,
+        event: event.event,
+    }
+}
+
+impl<E> EventSink<E> for Vec<Timed<E>> {
+    fn write_event(&mut self, timing: &BufferTiming, event: Timed<E>) {
+        self.push(since_start(timing, event));
+    }
+}
+
+impl<E> EventSource<E> for Receiver<Timed<E>> {
+    fn read_events<H: EventHandler<Timed<E>>>(&mut self, timing: &BufferTiming, handler: &mut H) {
+        let last_frame = timing.number_of_frames.saturating_sub(1) as u32;
+        while let Ok(event) = self.try_recv() {
+            handler.handle_event(Timed {
+                time_in_frames: std::cmp::min(event.time_in_frames, last_frame),
+                event: event.event,
+            });
+        }
+    }
+}
+
+impl<E> EventSink<E> for Sender<Timed<E>> {
+    fn write_event(&mut self, timing: &BufferTiming, event: Timed<E>) {
+        // The receiver may have hung up, which is not an error for the renderer.
+        let _ = self.send(since_start(timing, event));
+    }
+}
+
+impl<E> EventSink<E> for SyncSender<Timed<E>> {
+    fn write_event(&mut self, timing: &BufferTiming, event: Timed<E>) {
+        // The receiver may have hung up, which is not an error for the renderer.
+        let _ = self.send(since_start(timing, event));
+    }
+}
+
+/// Use an iterator over [`DeltaEvent`]s, such as a `RimdMidiReader`, as an [`EventSource`].
+///
+/// [`DeltaEvent`]: ../../../event/struct.DeltaEvent.html
+/// [`EventSource`]: ./trait.EventSource.html
+pub struct DeltaEventSource<I>
+where
+    I: Iterator,
+{
+    events: Peekable<I>,
+    previous_time_in_microseconds: u64,
+}
+
+impl<I> DeltaEventSource<I>
+where
+    I: Iterator,
+{
+    pub fn new(events: I) -> Self {
+        DeltaEventSource {
+            events: events.peekable(),
+            previous_time_in_microseconds: 0,
+        }
+    }
+}
+
+impl<I, E> EventSource<E> for DeltaEventSource<I>
+where
+    I: Iterator<Item = DeltaEvent<E>>,
+{
+    fn read_events<H: EventHandler<Timed<E>>>(&mut self, timing: &BufferTiming, handler: &mut H) {
+        while let Some(event) = self.events.peek() {
+            let time_in_microseconds =
+                self.previous_time_in_microseconds + event.microseconds_since_previous_event;
+            let time_in_frames = match timing.frame_offset(time_in_microseconds) {
+                Some(offset) => offset,
+                None => break,
+            };
+            let event = self
+                .events
+                .next()
+                .expect("to see event that I just peeked at");
+            self.previous_time_in_microseconds = time_in_microseconds;
+            handler.handle_event(Timed {
+                time_in_frames,
+                event: event.event,
+            });
+        }
+    }
+
+    fn is_exhausted(&mut self) -> bool {
+        self.events.peek().is_none()
+    }
+}
+
+/// An [`EventSource`] that merges the events of two sources into one chronological stream.
+///
+/// Events with the same time are passed in the order of the sources: first the events of the
+/// first source, then the events of the second source. To merge more than two sources,
+/// merge the merged sources again:
+///
+/// ```
+/// use rsynth::backend::combined::events::MergedEventSource;
+/// use rsynth::event::Timed;
+///
+/// let first = vec![Timed::new(0, 'a')];
+/// let second = vec![Timed::new(5, 'b')];
+/// let third = vec![Timed::new(2, 'c')];
+/// let merged = MergedEventSource::new(MergedEventSource::new(first, second), third);
+/// ```
+///
+/// Note
+/// ----
+/// The events of the sources are buffered in two `Vec`s, which only allocate memory when
+/// more events occur in a buffer than before.
+///
+/// [`EventSource`]: ./trait.EventSource.html
+pub struct MergedEventSource<A, B, E> {
+    first: A,
+    second: B,
+    first_events: Vec<Timed<E>>,
+    second_events: Vec<Timed<E>>,
+}
+
+impl<A, B, E> MergedEventSource<A, B, E>
+where
+    A: EventSource<E>,
+    B: EventSource<E>,
+{
+    /// Create a new `MergedEventSource` that merges the events of `first` and `second`.
+    /// Events at the same time are ordered with the events of `first` before the events of
+    /// `second`.
+    pub fn new(first: A, second: B) -> Self {
+        MergedEventSource {
+            first,
+            second,
+            first_events: Vec::new(),
+            second_events: Vec::new(),
+        }
+    }
+
+    /// Return the two sources, e.g. to inspect them after rendering.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+// Collects the events of one of the merged sources.
+struct Collect<'a, E>(&'a mut Vec<Timed<E>>);
+
+impl<'a, E> EventHandler<Timed<E>> for Collect<'a, E> {
+    fn handle_event(&mut self, event: Timed<E>) {
+        self.0.push(event);
+    }
+}
+
+impl<A, B, E> EventSource<E> for MergedEventSource<A, B, E>
+where
+    A: EventSource<E>,
+    B: EventSource<E>,
+{
+    fn read_events<H: EventHandler<Timed<E>>>(&mut self, timing: &BufferTiming, handler: &mut H) {
+        self.first
+            .read_events(timing, &mut Collect(&mut self.first_events));
+        self.second
+            .read_events(timing, &mut Collect(&mut self.second_events));
+        let mut first_events = self.first_events.drain(..).peekable();
+        let mut second_events = self.second_events.drain(..).peekable();
+        loop {
+            let take_first = match (first_events.peek(), second_events.peek()) {
+                (Some(first), Some(second)) => first.time_in_frames <= second.time_in_frames,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            let event = if take_first {
+                first_events.next()
+            } else {
+                second_events.next()
+            };
+            handler.handle_event(event.expect("to see event that I just peeked at"));
+        }
+    }
+
+    fn is_exhausted(&mut self) -> bool {
+        self.first.is_exhausted() && self.second.is_exhausted()
+    }
+}
+
+/// Use a [`MidiWriter`], such as a `RimdMidiWriter`, as an [`EventSink`].
+///
+/// The plugin should emit the events in chronological order; an event that is emitted
+/// before an earlier event is written at the time of that earlier event.
+///
+/// [`MidiWriter`]: ../trait.MidiWriter.html
+/// [`EventSink`]: ./trait.EventSink.html
+pub struct MidiWriterSink<W>
+where
+    W: MidiWriter,
+{
+    inner: W,
+    previous_time_in_microseconds: u64,
+}
+
+impl<W> MidiWriterSink<W>
+where
+    W: MidiWriter,
+{
+    pub fn new(inner: W) -> Self {
+        MidiWriterSink {
+            inner,
+            previous_time_in_microseconds: 0,
+        }
+    }
+
+    pub fn inner(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W> EventSink<RawMidiEvent> for MidiWriterSink<W>
+where
+    W: MidiWriter,
+{
+    fn write_event(&mut self, timing: &BufferTiming, event: Timed<RawMidiEvent>) {
+        let time_in_microseconds = std::cmp::max(
+            timing.time_in_microseconds(event.time_in_frames),
+            self.previous_time_in_microseconds,
+        );
+        self.inner.write_event(DeltaEvent {
+            microseconds_since_previous_event: time_in_microseconds
+                - self.previous_time_in_microseconds,
+            event: event.event,
+        });
+        self.previous_time_in_microseconds = time_in_microseconds;
+    }
+}
+
+/// The context that is passed to the plugin by [`run_with_events`]: the events that the plugin
+/// emits with `handle_event` are written to the [`EventSink`].
+///
+/// [`run_with_events`]: ../fn.run_with_events.html
+/// [`EventSink`]: ./trait.EventSink.html
+pub struct EventSinkContext<K> {
+    sink: K,
+    timing: BufferTiming,
+}
+
+impl<K> EventSinkContext<K> {
+    pub(super) fn new(sink: K, timing: BufferTiming) -> Self {
+        EventSinkContext { sink, timing }
+    }
+
+    pub(super) fn set_timing(&mut self, timing: BufferTiming) {
+        self.timing = timing;
+    }
+
+    /// The timing of the buffer that is being rendered.
+    pub fn timing(&self) -> &BufferTiming {
+        &self.timing
+    }
+}
+
+impl<E, K> EventHandler<Timed<E>> for EventSinkContext<K>
+where
+    K: EventSink<E>,
+{
+    fn handle_event(&mut self, event: Timed<E>) {
+        self.sink.write_event(&self.timing, event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(start_in_frames: u64, number_of_frames: usize) -> BufferTiming {
+        BufferTiming {
+            start_in_frames,
+            start_in_microseconds: start_in_frames * 1000,
+            number_of_frames,
+            frames_per_second: 1000,
+        }
+    }
+
+    struct Collect(Vec<Timed<char>>);
+
+    impl EventHandler<Timed<char>> for Collect {
+        fn handle_event(&mut self, event: Timed<char>) {
+            self.0.push(event);
+        }
+    }
+
+    #[test]
+    fn vec_source_splits_events_over_buffers() {
+        let mut source = vec![Timed::new(1, 'a'), Timed::new(4, 'b'), Timed::new(5, 'c')];
+        let mut events = Collect(Vec::new());
+        source.read_events(&timing(0, 4), &mut events);
+        assert_eq!(events.0, vec![Timed::new(1, 'a')]);
+        events.0.clear();
+        source.read_events(&timing(4, 4), &mut events);
+        assert_eq!(events.0, vec![Timed::new(0, 'b'), Timed::new(1, 'c')]);
+        assert!(source.is_empty());
+    }
+
+    #[test]
+    fn delta_event_source_converts_microseconds_to_frames() {
+        let events = vec![
+            DeltaEvent {
+                microseconds_since_previous_event: 2000,
+                event: 'a',
+            },
+            DeltaEvent {
+                microseconds_since_previous_event: 3000,
+                event: 'b',
+            },
+        ];
+        let mut source = DeltaEventSource::new(events.into_iter());
+        let mut received = Collect(Vec::new());
+        source.read_events(&timing(0, 4), &mut received);
+        assert_eq!(received.0, vec![Timed::new(2, 'a')]);
+        received.0.clear();
+        source.read_events(&timing(4, 4), &mut received);
+        assert_eq!(received.0, vec![Timed::new(1, 'b')]);
+    }
+
+    #[test]
+    fn merged_event_source_merges_chronologically() {
+        let first = vec![Timed::new(1, 'a'), Timed::new(3, 'b'), Timed::new(6, 'c')];
+        let (sender, receiver) = std::sync::mpsc::channel();
+        sender.send(Timed::new(1, 'x')).expect("no error");
+        sender.send(Timed::new(2, 'y')).expect("no error");
+        let mut source = MergedEventSource::new(first, receiver);
+        let mut events = Collect(Vec::new());
+        source.read_events(&timing(0, 4), &mut events);
+        assert_eq!(
+            events.0,
+            vec![
+                Timed::new(1, 'a'),
+                Timed::new(1, 'x'),
+                Timed::new(2, 'y'),
+                Timed::new(3, 'b')
+            ]
+        );
+        events.0.clear();
+        source.read_events(&timing(4, 4), &mut events);
+        assert_eq!(events.0, vec![Timed::new(2, 'c')]);
+        assert!(source.is_exhausted());
+    }
+
+    #[test]
+    fn vec_sink_stores_the_time_since_the_start() {
+        let mut sink = Vec::new();
+        let mut context = EventSinkContext::new(&mut sink, timing(8, 4));
+        context.handle_event(Timed::new(3, 'a'));
+        assert_eq!(sink, vec![Timed::new(11, 'a')]);
+    }
+}
@@ -0,0 +1,299 @@
+//! Write audio to `.flac` files with [`flacenc`].
+//!
+//! Support is only enabled if `rsynth` is compiled with the "backend-file-flac" feature.
+//! FLAC is a lossless format, so this is an alternative for [`HoundAudioWriter`] that results
+//! in much smaller files, e.g. for long offline renders.
+//!
+//! ```no_run
+//! use rsynth::backend::combined::flac::FlacAudioWriter;
+//! use std::fs::File;
+//!
+//! let file = File::create("output.flac").unwrap();
+//! let mut writer = FlacAudioWriter::<_, f32>::new(file, 2, 44100, 24).unwrap();
+//! // Use `writer` as the audio output, e.g. with `rsynth::backend::combined::run`.
+//! // ...
+//! // `finish` writes the last block and the stream info.
+//! writer.finish().unwrap();
+//! ```
+//!
+//! [`flacenc`]: https://crates.io/crates/flacenc
+//! [`HoundAudioWriter`]: ../hound/struct.HoundAudioWriter.html
+use super::AudioWriter;
+use flacenc::bitsink::ByteSink;
+use flacenc::component::{BitRepr, StreamInfo};
+use flacenc::error::{Verified, Verify};
+use flacenc::source::{Fill, FrameBuf};
+use sample::conv::ToSample;
+use std::fmt::{Display, Formatter};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+
+// The header of the STREAMINFO metadata block: the "last block" flag, the block type (0) and
+// the length of the block (34 bytes).
+const STREAM_INFO_HEADER: [u8; 4] = [0x80, 0, 0, 34];
+
+/// The error type for [`FlacAudioWriter`].
+///
+/// [`FlacAudioWriter`]: ./struct.FlacAudioWriter.html
+#[derive(Debug)]
+pub enum FlacAudioError {
+    /// The number of bits per sample is not supported.
+    UnsupportedAudioFormat,
+    /// The audio could not be encoded.
+    Encoding(String),
+    /// The encoded audio could not be written.
+    Io(io::Error),
+}
+
+impl Display for FlacAudioError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            FlacAudioError::UnsupportedAudioFormat => write!(f, "unsupported audio format"),
+            FlacAudioError::Encoding(message) => write!(f, "error while encoding: {}", message),
+            FlacAudioError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FlacAudioError {}
+
+impl From<io::Error> for FlacAudioError {
+    fn from(e: io::Error) -> Self {
+        FlacAudioError::Io(e)
+    }
+}
+
+/// Write audio to a `.flac` file with [`flacenc`].
+///
+/// Samples are converted to integers with `bits_per_sample` bits, so that the full scale of
+/// the sample type `S` is the full scale of the file.
+///
+/// The audio is encoded and written block by block, so only one block is kept in memory.
+/// The stream info (e.g. the total number of frames) is only known at the end, so it is
+/// written when [`finish`] is called; for this reason, the writer must implement `Seek`.
+///
+/// Note
+/// ----
+/// The last (incomplete) block and the stream info are not written when the
+/// `FlacAudioWriter` is dropped without calling [`finish`].
+///
+/// [`flacenc`]: https://crates.io/crates/flacenc
+/// [`finish`]: #method.finish
+pub struct FlacAudioWriter<W, S>
+where
+    W: Write + Seek,
+{
+    writer: W,
+    number_of_channels: usize,
+    frames_per_second: u64,
+    config: Verified<flacenc::config::Encoder>,
+    stream_info: StreamInfo,
+    // The position of the stream info in `writer`.
+    stream_info_position: u64,
+    frame_buffer: FrameBuf,
+    shift: u32,
+    // The interleaved samples of the block that is not yet complete.
+    samples: Vec<i32>,
+    number_of_blocks: usize,
+    _phantom: PhantomData<S>,
+}
+
+impl<W, S> FlacAudioWriter<W, S>
+where
+    W: Write + Seek,
+{
+    /// Create a new `FlacAudioWriter` that writes to `writer`.
+    /// The header of the file is written immediately.
+    ///
+    /// `bits_per_sample` must be 8, 16 or 24, otherwise
+    /// `FlacAudioError::UnsupportedAudioFormat` is returned.
+    ///
+    /// # Panics
+    /// Panics if `number_of_channels` is not between 1 and 8 (the limits of the FLAC format).
+    pub fn new(
+        mut writer: W,
+        number_of_channels: usize,
+        frames_per_second: u64,
+        bits_per_sample: u32,
+    ) -> Result<Self, FlacAudioError> {
+        assert!(number_of_channels > 0 && number_of_channels <= 8);
+        match bits_per_sample {
+            8 | 16 | 24 => {}
+            _ => return Err(FlacAudioError::UnsupportedAudioFormat),
+        }
+        let config = flacenc::config::Encoder::default()
+            .into_verified()
+            .map_err(|(_, e)| encoding_error(e))?;
+        let block_size = config.block_size;
+        let mut stream_info = StreamInfo::new(
+            frames_per_second as usize,
+            number_of_channels,
+            bits_per_sample as usize,
+        )
+        .map_err(encoding_error)?;
+        stream_info
+            .set_block_sizes(block_size, block_size)
+            .map_err(encoding_error)?;
+        let frame_buffer =
+            FrameBuf::with_size(number_of_channels, block_size).map_err(encoding_error)?;
+
+        writer.write_all(b"fLaC")?;
+        writer.write_all(&STREAM_INFO_HEADER)?;
+        let stream_info_position = writer.stream_position()?;
+        write_bits(&mut writer, &stream_info)?;
+
+        Ok(Self {
+            writer,
+            number_of_channels,
+            frames_per_second,
+            config,
+            stream_info,
+            stream_info_position,
+            frame_buffer,
+            shift: 32 - bits_per_sample,
+            samples: Vec::with_capacity(block_size * number_of_channels),
+            number_of_blocks: 0,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// The number of frames that have been written so far.
+    pub fn number_of_frames(&self) -> usize {
+        self.number_of_blocks * self.config.block_size
+            + self.samples.len() / self.number_of_channels
+    }
+
+    // Encode the samples of one block and write the encoded frame.
+    fn write_block(&mut self) -> Result<(), FlacAudioError> {
+        let number_of_frames = self.samples.len() / self.number_of_channels;
+        if number_of_frames < self.frame_buffer.size() {
+            // Only the last block can be shorter.
+            self.frame_buffer = FrameBuf::with_size(self.number_of_channels, number_of_frames)
+                .map_err(encoding_error)?;
+        }
+        self.frame_buffer
+            .fill_interleaved(&self.samples)
+            .map_err(encoding_error)?;
+        let frame = flacenc::encode_fixed_size_frame(
+            &self.config,
+            &self.frame_buffer,
+            self.number_of_blocks,
+            &self.stream_info,
+        )
+        .map_err(encoding_error)?;
+        self.stream_info.update_frame_info(&frame);
+        write_bits(&mut self.writer, &frame)?;
+        self.samples.clear();
+        self.number_of_blocks += 1;
+        Ok(())
+    }
+
+    /// Write the last block and the stream info.
+    ///
+    /// Returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, FlacAudioError> {
+        if !self.samples.is_empty() {
+            self.write_block()?;
+        }
+        let end = self.writer.stream_position()?;
+        self.writer
+            .seek(SeekFrom::Start(self.stream_info_position))?;
+        write_bits(&mut self.writer, &self.stream_info)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn encoding_error<E: Display>(e: E) -> FlacAudioError {
+    FlacAudioError::Encoding(e.to_string())
+}
+
+fn write_bits<W: Write, T: BitRepr>(writer: &mut W, value: &T) -> Result<(), FlacAudioError> {
+    let mut sink = ByteSink::new();
+    value.write(&mut sink).map_err(encoding_error)?;
+    writer.write_all(sink.as_slice())?;
+    Ok(())
+}
+
+impl<W, S> AudioWriter<S> for FlacAudioWriter<W, S>
+where
+    W: Write + Seek,
+    S: ToSample<i32> + Copy,
+{
+    type Err = FlacAudioError;
+
+    fn write_buffer(&mut self, inputs: &[&[S]]) -> Result<(), Self::Err> {
+        assert_eq!(inputs.len(), self.number_of_channels);
+        let length = inputs[0].len();
+        for input in inputs.iter() {
+            assert_eq!(input.len(), length);
+        }
+
+        let block_length = self.config.block_size * self.number_of_channels;
+        for frame_index in 0..length {
+            for input in inputs.iter() {
+                let sample: i32 = input[frame_index].to_sample_();
+                self.samples.push(sample >> self.shift);
+            }
+            if self.samples.len() == block_length {
+                self.write_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn number_of_channels(&self) -> Option<usize> {
+        Some(self.number_of_channels)
+    }
+
+    fn frames_per_second(&self) -> Option<u64> {
+        Some(self.frames_per_second)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn samples_are_interleaved_and_scaled() {
+        let mut writer = FlacAudioWriter::<_, i32>::new(Cursor::new(Vec::new()), 2, 44100, 16)
+            .expect("no error");
+        writer
+            .write_buffer(&[&[1 << 30, 0], &[i32::MIN, 1 << 16]])
+            .expect("no error");
+        assert_eq!(writer.number_of_frames(), 2);
+        assert_eq!(writer.samples, vec![1 << 14, -(1 << 15), 0, 1]);
+    }
+
+    #[test]
+    fn blocks_are_written_as_they_are_complete() {
+        let mut writer = FlacAudioWriter::<_, i32>::new(Cursor::new(Vec::new()), 1, 44100, 16)
+            .expect("no error");
+        let header = writer.writer.get_ref().clone();
+        assert_eq!(&header[..8], b"fLaC\x80\x00\x00\x22");
+        assert_eq!(header.len(), 8 + 34);
+
+        let block_size = writer.config.block_size;
+        let input = vec![0; block_size + 1];
+        writer.write_buffer(&[&input]).expect("no error");
+        assert_eq!(writer.number_of_frames(), block_size + 1);
+        assert_eq!(writer.samples.len(), 1);
+        let length_after_first_block = writer.writer.get_ref().len();
+        assert!(length_after_first_block > header.len());
+
+        let data = writer.finish().expect("no error").into_inner();
+        assert!(data.len() > length_after_first_block);
+        // The stream info is updated at the end.
+        assert_eq!(&data[..8], &header[..8]);
+        assert_ne!(&data[8..42], &header[8..42]);
+    }
+
+    #[test]
+    fn unsupported_bits_per_sample_are_rejected() {
+        assert!(FlacAudioWriter::<_, i32>::new(Cursor::new(Vec::new()), 1, 44100, 32).is_err());
+    }
+}
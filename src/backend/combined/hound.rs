@@ -1,45 +1,65 @@
-use super::{AudioReader, AudioWriter};
-use hound::{WavReader, WavSamples, WavWriter};
+use super::{AudioMetadata, AudioReader, AudioWriter, LoopPoints};
+use hound::{WavReader, WavWriter};
 use sample::conv::{FromSample, ToSample};
-use std::io::{Read, Seek, Write};
-
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+
+/// Read audio from a `.wav` file with [`hound`].
+///
+/// Integer samples (8, 16, 24 or 32 bits) and 32-bit float samples are converted to the
+/// sample type `S`, so that the full scale of the file is the full scale of `S`.
+///
+/// By default, the reader has the same number of channels as the file. Use
+/// [`with_number_of_channels`] to read e.g. a mono file into a stereo renderer.
+///
+/// [`hound`]: https://crates.io/crates/hound
+/// [`with_number_of_channels`]: #method.with_number_of_channels
 pub struct HoundAudioReader<'wr, S>
 where
-    S: FromSample<f32> + FromSample<i32> + FromSample<i16>,
+    S: FromSample<f32> + FromSample<i32> + FromSample<i16> + Copy,
 {
     hound_sample_reader: Box<dyn HoundSampleReader<S> + 'wr>,
     number_of_channels: usize,
+    number_of_channels_in_file: usize,
     frames_per_second: u64,
+    duration_in_frames: u64,
+    metadata: AudioMetadata,
 }
 
+#[derive(Debug)]
 pub enum HoundAudioError {
     UnsupportedAudioFormat,
 }
 
+type SeekFunction<R> = fn(&mut WavReader<R>, u32) -> io::Result<()>;
+
 impl<'wr, S> HoundAudioReader<'wr, S>
 where
-    S: FromSample<f32> + FromSample<i32> + FromSample<i16>,
+    S: FromSample<f32> + FromSample<i32> + FromSample<i16> + Copy,
 {
     fn reader<R: Read>(
         r: &'wr mut WavReader<R>,
+        seek: Option<SeekFunction<R>>,
     ) -> Result<Box<dyn HoundSampleReader<S> + 'wr>, HoundAudioError> {
         let spec = r.spec();
         Ok(match spec.sample_format {
             hound::SampleFormat::Float => match spec.bits_per_sample {
-                32 => Box::new(F32SampleReader {
-                    samples: r.samples(),
-                }),
+                32 => Box::new(SampleReader::<R, f32>::new(r, seek)),
                 _ => {
                     return Err(HoundAudioError::UnsupportedAudioFormat);
                 }
             },
             hound::SampleFormat::Int => match spec.bits_per_sample {
-                24 | 32 => Box::new(I32SampleReader {
-                    samples: r.samples(),
-                }),
-                8 | 16 => Box::new(I16SampleReader {
-                    samples: r.samples(),
-                }),
+                24 | 32 => Box::new(
+                    SampleReader::<R, i32>::new(r, seek).with_shift(32 - spec.bits_per_sample),
+                ),
+                8 | 16 => Box::new(
+                    SampleReader::<R, i16>::new(r, seek).with_shift(16 - spec.bits_per_sample),
+                ),
                 _ => {
                     // Note: until 3.4.0, Hound only supports 8, 16, 24, 32 bits/sample.
                     // Something else (e.g. 12 bits) would result in an error at runtime,
@@ -50,22 +70,73 @@ where
         })
     }
 
-    pub fn new<R: Read>(reader: &'wr mut WavReader<R>) -> Result<Self, HoundAudioError> {
+    fn with_seek_function<R: Read>(
+        reader: &'wr mut WavReader<R>,
+        seek: Option<SeekFunction<R>>,
+    ) -> Result<Self, HoundAudioError> {
         let spec = reader.spec();
 
         let number_of_channels = spec.channels as usize;
-        let hound_sample_reader = Self::reader(reader)?;
+        let duration_in_frames = reader.duration() as u64;
+        let hound_sample_reader = Self::reader(reader, seek)?;
         Ok(Self {
             number_of_channels,
+            number_of_channels_in_file: number_of_channels,
             frames_per_second: spec.sample_rate as u64,
+            duration_in_frames,
+            metadata: AudioMetadata::default(),
             hound_sample_reader,
         })
     }
+
+    /// Create a new `HoundAudioReader`.
+    ///
+    /// A reader that is created with this method does not support seeking,
+    /// use [`new_seekable`] for that.
+    ///
+    /// [`new_seekable`]: #method.new_seekable
+    pub fn new<R: Read>(reader: &'wr mut WavReader<R>) -> Result<Self, HoundAudioError> {
+        Self::with_seek_function(reader, None)
+    }
+
+    /// Create a new `HoundAudioReader` that supports seeking.
+    pub fn new_seekable<R: Read + Seek>(
+        reader: &'wr mut WavReader<R>,
+    ) -> Result<Self, HoundAudioError> {
+        let seek: SeekFunction<R> = WavReader::seek;
+        Self::with_seek_function(reader, Some(seek))
+    }
+
+    /// Set the metadata that is returned by `metadata()`, e.g. the metadata that has
+    /// been read with [`read_metadata`].
+    ///
+    /// [`read_metadata`]: ./fn.read_metadata.html
+    pub fn with_metadata(mut self, metadata: AudioMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Read the audio into the given number of channels instead of the number of channels
+    /// of the file. The channels of the file that do not fit are dropped. A mono file is
+    /// copied to all channels; for other files, the extra channels are silent.
+    ///
+    /// # Panics
+    /// Panics if `number_of_channels` is zero.
+    pub fn with_number_of_channels(mut self, number_of_channels: usize) -> Self {
+        assert!(number_of_channels > 0);
+        self.number_of_channels = number_of_channels;
+        self
+    }
+
+    /// The number of channels of the file.
+    pub fn number_of_channels_in_file(&self) -> usize {
+        self.number_of_channels_in_file
+    }
 }
 
 impl<'wr, S> AudioReader<S> for HoundAudioReader<'wr, S>
 where
-    S: FromSample<f32> + FromSample<i32> + FromSample<i16>,
+    S: FromSample<f32> + FromSample<i32> + FromSample<i16> + Copy,
 {
     type Err = hound::Error;
 
@@ -84,74 +155,400 @@ where
         for output in outputs.iter() {
             assert_eq!(output.len(), length);
         }
+        let silence = S::from_sample_(0_i16);
         let mut frame_index = 0;
         while frame_index < length {
-            for output in outputs.iter_mut() {
+            for channel in 0..self.number_of_channels_in_file {
                 if let Some(sample) = self.hound_sample_reader.read_sample()? {
-                    output[frame_index] = sample;
+                    if let Some(output) = outputs.get_mut(channel) {
+                        output[frame_index] = sample;
+                    }
                 } else {
                     return Ok(frame_index);
                 }
             }
+            for channel in self.number_of_channels_in_file..outputs.len() {
+                outputs[channel][frame_index] = if self.number_of_channels_in_file == 1 {
+                    outputs[0][frame_index]
+                } else {
+                    silence
+                };
+            }
             frame_index += 1;
         }
         Ok(frame_index)
     }
-}
 
-trait HoundSampleReader<S> {
-    fn read_sample(&mut self) -> Result<Option<S>, hound::Error>;
+    fn duration_in_frames(&self) -> Option<u64> {
+        Some(self.duration_in_frames)
+    }
+
+    fn seek(&mut self, frame: u64) -> Result<bool, Self::Err> {
+        let frame = std::cmp::min(frame, self.duration_in_frames) as u32;
+        match self.hound_sample_reader.seek(frame) {
+            Some(result) => {
+                result?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn metadata(&self) -> AudioMetadata {
+        self.metadata.clone()
+    }
 }
 
-struct F32SampleReader<'wr, R: Read> {
-    samples: WavSamples<'wr, R, f32>,
+const STREAMING_CHUNK_SIZE_IN_FRAMES: usize = 4096;
+
+// A block of frames that has been read ahead, one `Vec` per channel.
+type StreamingChunk<S> = Result<Vec<Vec<S>>, hound::Error>;
+
+/// Read audio from a `.wav` file with [`hound`] on a background thread.
+///
+/// In contrast to [`HoundAudioReader`], which decodes the samples when [`fill_buffer`] is
+/// called, a `HoundStreamingAudioReader` owns the `WavReader` and decodes the file in
+/// blocks of 4096 frames on a separate thread, ahead of time. Only the blocks that have been
+/// read ahead are kept in memory, so that long files (e.g. an hour of audio) can be processed
+/// without loading them completely.
+///
+/// Samples are converted in the same way as by [`HoundAudioReader`].
+/// Seeking is not supported.
+///
+/// Note
+/// ----
+/// Blocks are allocated while reading, so this cannot be used in a real-time context.
+///
+/// [`hound`]: https://crates.io/crates/hound
+/// [`HoundAudioReader`]: ./struct.HoundAudioReader.html
+/// [`fill_buffer`]: ../trait.AudioReader.html#tymethod.fill_buffer
+pub struct HoundStreamingAudioReader<S> {
+    receiver: Receiver<StreamingChunk<S>>,
+    chunk: Vec<Vec<S>>,
+    position_in_chunk: usize,
+    number_of_channels: usize,
+    frames_per_second: u64,
+    duration_in_frames: u64,
 }
 
-impl<'wr, R: Read, S> HoundSampleReader<S> for F32SampleReader<'wr, R>
+impl<S> HoundStreamingAudioReader<S>
 where
-    S: FromSample<f32>,
+    S: FromSample<f32> + FromSample<i32> + FromSample<i16> + Copy + Send + 'static,
 {
-    fn read_sample(&mut self) -> Result<Option<S>, hound::Error> {
-        if let Some(n) = self.samples.next() {
-            Ok(Some(S::from_sample_(n?)))
-        } else {
-            Ok(None)
+    /// Open the `.wav` file at the given path.
+    ///
+    /// At most `read_ahead_in_frames` frames (rounded up to a multiple of 4096) are read
+    /// ahead of the frames that have been passed to [`fill_buffer`].
+    ///
+    /// [`fill_buffer`]: ../trait.AudioReader.html#tymethod.fill_buffer
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        read_ahead_in_frames: usize,
+    ) -> Result<Self, HoundStreamingAudioError> {
+        let wav_reader = WavReader::open(path).map_err(HoundStreamingAudioError::Hound)?;
+        Self::new(wav_reader, read_ahead_in_frames)
+    }
+
+    /// Create a new `HoundStreamingAudioReader` that reads from the given `WavReader`.
+    ///
+    /// See [`open`] for the meaning of `read_ahead_in_frames`.
+    ///
+    /// [`open`]: #method.open
+    pub fn new<R: Read + Send + 'static>(
+        mut wav_reader: WavReader<R>,
+        read_ahead_in_frames: usize,
+    ) -> Result<Self, HoundStreamingAudioError> {
+        // Check the audio format before starting to read.
+        if let Err(e) = HoundAudioReader::<S>::new(&mut wav_reader) {
+            return Err(HoundStreamingAudioError::Format(e));
         }
+        let spec = wav_reader.spec();
+        let number_of_channels = spec.channels as usize;
+        let frames_per_second = spec.sample_rate as u64;
+        let duration_in_frames = wav_reader.duration() as u64;
+        let number_of_chunks = std::cmp::max(
+            1,
+            read_ahead_in_frames.div_ceil(STREAMING_CHUNK_SIZE_IN_FRAMES),
+        );
+        let (sender, receiver) = sync_channel(number_of_chunks);
+        thread::spawn(move || Self::read_chunks(wav_reader, sender));
+        Ok(Self {
+            receiver,
+            chunk: Vec::new(),
+            position_in_chunk: 0,
+            number_of_channels,
+            frames_per_second,
+            duration_in_frames,
+        })
     }
-}
 
-struct I32SampleReader<'wr, R: Read> {
-    samples: WavSamples<'wr, R, i32>,
+    fn read_chunks<R: Read>(mut wav_reader: WavReader<R>, sender: SyncSender<StreamingChunk<S>>) {
+        let mut reader = match HoundAudioReader::<S>::new(&mut wav_reader) {
+            Ok(reader) => reader,
+            Err(_) => return,
+        };
+        let silence = S::from_sample_(0_i16);
+        loop {
+            let mut chunk =
+                vec![vec![silence; STREAMING_CHUNK_SIZE_IN_FRAMES]; reader.number_of_channels()];
+            let mut channels: Vec<&mut [S]> = chunk.iter_mut().map(|c| c.as_mut_slice()).collect();
+            let result = reader.fill_buffer(&mut channels);
+            let last = match result {
+                Ok(number_of_frames) => {
+                    for channel in chunk.iter_mut() {
+                        channel.truncate(number_of_frames);
+                    }
+                    number_of_frames < STREAMING_CHUNK_SIZE_IN_FRAMES
+                }
+                Err(_) => true,
+            };
+            // The receiver may have been dropped, which just means that we can stop reading.
+            if sender.send(result.map(|_| chunk)).is_err() || last {
+                return;
+            }
+        }
+    }
 }
 
-impl<'wr, R: Read, S> HoundSampleReader<S> for I32SampleReader<'wr, R>
+impl<S> AudioReader<S> for HoundStreamingAudioReader<S>
 where
-    S: FromSample<i32>,
+    S: Copy,
 {
-    fn read_sample(&mut self) -> Result<Option<S>, hound::Error> {
-        if let Some(n) = self.samples.next() {
-            Ok(Some(S::from_sample_(n?)))
-        } else {
-            Ok(None)
+    type Err = hound::Error;
+
+    fn number_of_channels(&self) -> usize {
+        self.number_of_channels
+    }
+
+    fn frames_per_second(&self) -> u64 {
+        self.frames_per_second
+    }
+
+    fn fill_buffer(&mut self, outputs: &mut [&mut [S]]) -> Result<usize, Self::Err> {
+        assert_eq!(outputs.len(), self.number_of_channels);
+        let length = outputs[0].len();
+        for output in outputs.iter() {
+            assert_eq!(output.len(), length);
+        }
+        let mut frame_index = 0;
+        while frame_index < length {
+            let remaining_in_chunk = self
+                .chunk
+                .first()
+                .map(|channel| channel.len() - self.position_in_chunk)
+                .unwrap_or(0);
+            if remaining_in_chunk == 0 {
+                match self.receiver.recv() {
+                    Ok(chunk) => {
+                        self.chunk = chunk?;
+                        self.position_in_chunk = 0;
+                        if self.chunk.first().map(Vec::is_empty).unwrap_or(true) {
+                            break;
+                        }
+                        continue;
+                    }
+                    // The file has been read completely.
+                    Err(_) => break,
+                }
+            }
+            let number_of_frames = std::cmp::min(remaining_in_chunk, length - frame_index);
+            for (output, channel) in outputs.iter_mut().zip(self.chunk.iter()) {
+                output[frame_index..frame_index + number_of_frames].copy_from_slice(
+                    &channel[self.position_in_chunk..self.position_in_chunk + number_of_frames],
+                );
+            }
+            self.position_in_chunk += number_of_frames;
+            frame_index += number_of_frames;
         }
+        Ok(frame_index)
+    }
+
+    fn duration_in_frames(&self) -> Option<u64> {
+        Some(self.duration_in_frames)
     }
 }
 
-struct I16SampleReader<'wr, R: Read> {
-    samples: WavSamples<'wr, R, i16>,
+/// The error type that is returned when creating a [`HoundStreamingAudioReader`].
+///
+/// [`HoundStreamingAudioReader`]: ./struct.HoundStreamingAudioReader.html
+#[derive(Debug)]
+pub enum HoundStreamingAudioError {
+    /// The file could not be opened or is not a valid `.wav` file.
+    Hound(hound::Error),
+    /// The audio format is not supported.
+    Format(HoundAudioError),
 }
 
-impl<'wr, R: Read, S> HoundSampleReader<S> for I16SampleReader<'wr, R>
+impl Display for HoundStreamingAudioError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            HoundStreamingAudioError::Hound(e) => write!(f, "error while reading: {}", e),
+            HoundStreamingAudioError::Format(HoundAudioError::UnsupportedAudioFormat) => {
+                write!(f, "unsupported audio format")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HoundStreamingAudioError {}
+
+trait HoundSampleReader<S> {
+    fn read_sample(&mut self) -> Result<Option<S>, hound::Error>;
+    // Return `None` when seeking is not supported.
+    fn seek(&mut self, frame: u32) -> Option<io::Result<()>>;
+}
+
+// Hound returns integer samples in the range of their number of bits, e.g. a 24-bit sample
+// in an `i32` is in the range `-2^23..2^23`. Shifting them to the left makes them full scale.
+trait Shift: Sized {
+    fn shift_left(self, bits: u16) -> Self;
+}
+
+impl Shift for f32 {
+    fn shift_left(self, _bits: u16) -> Self {
+        self
+    }
+}
+
+impl Shift for i16 {
+    fn shift_left(self, bits: u16) -> Self {
+        self << bits
+    }
+}
+
+impl Shift for i32 {
+    fn shift_left(self, bits: u16) -> Self {
+        self << bits
+    }
+}
+
+// `H` is the type of the samples that are read by Hound.
+struct SampleReader<'wr, R: Read, H> {
+    reader: &'wr mut WavReader<R>,
+    seek: Option<SeekFunction<R>>,
+    shift: u16,
+    _phantom: PhantomData<H>,
+}
+
+impl<'wr, R: Read, H> SampleReader<'wr, R, H> {
+    fn new(reader: &'wr mut WavReader<R>, seek: Option<SeekFunction<R>>) -> Self {
+        Self {
+            reader,
+            seek,
+            shift: 0,
+            _phantom: PhantomData,
+        }
+    }
+
+    fn with_shift(mut self, shift: u16) -> Self {
+        self.shift = shift;
+        self
+    }
+}
+
+impl<'wr, R: Read, H, S> HoundSampleReader<S> for SampleReader<'wr, R, H>
 where
-    S: FromSample<i16>,
+    H: hound::Sample + Shift,
+    S: FromSample<H>,
 {
     fn read_sample(&mut self) -> Result<Option<S>, hound::Error> {
-        if let Some(n) = self.samples.next() {
-            Ok(Some(S::from_sample_(n?)))
+        if let Some(n) = self.reader.samples::<H>().next() {
+            Ok(Some(S::from_sample_(n?.shift_left(self.shift))))
         } else {
             Ok(None)
         }
     }
+
+    fn seek(&mut self, frame: u32) -> Option<io::Result<()>> {
+        let reader = &mut self.reader;
+        self.seek.map(|seek| seek(reader, frame))
+    }
+}
+
+/// Read the metadata that is embedded in a `.wav` file: the loops in the `smpl` chunk
+/// and the time reference in the `bext` chunk of a Broadcast Wave Format (BWF) file.
+///
+/// Hound does not read these chunks, so the file has to be read separately, e.g.:
+/// ```no_run
+/// use rsynth::backend::combined::hound::{read_metadata, HoundAudioReader};
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// let metadata = read_metadata(BufReader::new(File::open("loop.wav").unwrap())).unwrap();
+/// let mut wav_reader = hound::WavReader::open("loop.wav").unwrap();
+/// let reader = HoundAudioReader::<i16>::new_seekable(&mut wav_reader)
+///     .unwrap()
+///     .with_metadata(metadata);
+/// ```
+pub fn read_metadata<R: Read + Seek>(mut reader: R) -> io::Result<AudioMetadata> {
+    let mut header = [0; 12];
+    reader.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a RIFF WAVE file",
+        ));
+    }
+    let mut metadata = AudioMetadata::default();
+    let mut chunk_header = [0; 8];
+    loop {
+        match reader.read_exact(&mut chunk_header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let size = read_u32(&chunk_header, 4) as u64;
+        // Chunks are padded to an even number of bytes.
+        let padding = size & 1;
+        match &chunk_header[0..4] {
+            b"smpl" | b"bext" => {
+                let mut data = Vec::new();
+                reader.by_ref().take(size).read_to_end(&mut data)?;
+                if &chunk_header[0..4] == b"smpl" {
+                    read_loops(&data, &mut metadata);
+                } else {
+                    read_time_reference(&data, &mut metadata);
+                }
+                reader.seek(SeekFrom::Current(padding as i64))?;
+            }
+            _ => {
+                reader.seek(SeekFrom::Current((size + padding) as i64))?;
+            }
+        }
+    }
+    Ok(metadata)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut buffer = [0; 4];
+    buffer.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(buffer)
+}
+
+fn read_loops(smpl: &[u8], metadata: &mut AudioMetadata) {
+    // The loops are preceded by 36 bytes, the number of loops is at byte 28.
+    if smpl.len() < 36 {
+        return;
+    }
+    let number_of_loops = read_u32(smpl, 28) as usize;
+    for loop_data in smpl[36..].chunks_exact(24).take(number_of_loops) {
+        // The end of the loop is stored as the last frame of the loop.
+        metadata.loops.push(LoopPoints {
+            start: read_u32(loop_data, 8) as u64,
+            end: read_u32(loop_data, 12) as u64 + 1,
+        });
+    }
+}
+
+fn read_time_reference(bext: &[u8], metadata: &mut AudioMetadata) {
+    // The time reference is stored after the description (256 bytes), the originator
+    // (32 bytes), the originator reference (32 bytes), the date (10 bytes) and the time (8 bytes).
+    if bext.len() < 346 {
+        return;
+    }
+    let low = read_u32(bext, 338) as u64;
+    let high = read_u32(bext, 342) as u64;
+    metadata.time_reference = Some((high << 32) | low);
 }
 
 pub struct HoundAudioWriter<'ww, S>
@@ -160,6 +557,7 @@ where
 {
     hound_sample_writer: Box<dyn HoundSampleWriter<S> + 'ww>,
     number_of_channels: usize,
+    frames_per_second: u64,
 }
 
 impl<'ww, S> HoundAudioWriter<'ww, S>
@@ -178,8 +576,14 @@ where
                 }
             },
             hound::SampleFormat::Int => match spec.bits_per_sample {
-                22 | 32 => Box::new(I32SampleWriter { writer }),
-                8 | 16 => Box::new(I16SampleWriter { writer }),
+                24 | 32 => Box::new(I32SampleWriter {
+                    writer,
+                    shift: 32 - spec.bits_per_sample,
+                }),
+                8 | 16 => Box::new(I16SampleWriter {
+                    writer,
+                    shift: 16 - spec.bits_per_sample,
+                }),
                 _ => {
                     // Note: until 3.4.0, Hound only supports 8, 16, 24, 32 bits/sample.
                     // Something else (e.g. 12 bits) would result in an error while writing
@@ -196,6 +600,7 @@ where
         Ok(Self {
             hound_sample_writer,
             number_of_channels: spec.channels as usize,
+            frames_per_second: spec.sample_rate as u64,
         })
     }
 }
@@ -224,6 +629,14 @@ where
 
         self.hound_sample_writer.flush()
     }
+
+    fn number_of_channels(&self) -> Option<usize> {
+        Some(self.number_of_channels)
+    }
+
+    fn frames_per_second(&self) -> Option<u64> {
+        Some(self.frames_per_second)
+    }
 }
 
 trait HoundSampleWriter<S> {
@@ -256,6 +669,8 @@ where
     W: Write + Seek,
 {
     writer: &'ww mut WavWriter<W>,
+    // The number of bits by which a full-scale sample is shifted to the right.
+    shift: u16,
 }
 
 impl<'ww, S, W> HoundSampleWriter<S> for I32SampleWriter<'ww, W>
@@ -264,7 +679,8 @@ where
     W: Write + Seek,
 {
     fn write_sample(&mut self, sample: S) -> Result<(), hound::Error> {
-        self.writer.write_sample::<i32>(sample.to_sample_())
+        let sample: i32 = sample.to_sample_();
+        self.writer.write_sample::<i32>(sample >> self.shift)
     }
 
     fn flush(&mut self) -> Result<(), hound::Error> {
@@ -277,6 +693,8 @@ where
     W: Write + Seek,
 {
     writer: &'ww mut WavWriter<W>,
+    // The number of bits by which a full-scale sample is shifted to the right.
+    shift: u16,
 }
 
 impl<'ww, S, W> HoundSampleWriter<S> for I16SampleWriter<'ww, W>
@@ -285,10 +703,182 @@ where
     W: Write + Seek,
 {
     fn write_sample(&mut self, sample: S) -> Result<(), hound::Error> {
-        self.writer.write_sample::<i16>(sample.to_sample_())
+        let sample: i16 = sample.to_sample_();
+        self.writer.write_sample::<i16>(sample >> self.shift)
     }
 
     fn flush(&mut self) -> Result<(), hound::Error> {
         self.writer.flush()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // A wav file with two channels and four frames, followed by a `smpl` chunk with one loop.
+    fn wav_file() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = WavWriter::new(Cursor::new(&mut bytes), spec).expect("no error");
+        for sample in &[1, 10, 2, 20, 3, 30, 4, 40] {
+            writer.write_sample::<i16>(*sample).expect("no error");
+        }
+        writer.finalize().expect("no error");
+        let mut smpl = vec![0; 36 + 24];
+        smpl[28..32].copy_from_slice(&1_u32.to_le_bytes());
+        smpl[36 + 8..36 + 12].copy_from_slice(&1_u32.to_le_bytes());
+        smpl[36 + 12..36 + 16].copy_from_slice(&2_u32.to_le_bytes());
+        bytes.extend_from_slice(b"smpl");
+        bytes.extend_from_slice(&(smpl.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&smpl);
+        let riff_size = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn read_metadata_reads_the_loops() {
+        let metadata = read_metadata(Cursor::new(wav_file())).expect("no error");
+        assert_eq!(metadata.loops, vec![LoopPoints { start: 1, end: 3 }]);
+        assert_eq!(metadata.time_reference, None);
+    }
+
+    #[test]
+    fn seekable_reader_can_seek() {
+        let mut wav_reader = WavReader::new(Cursor::new(wav_file())).expect("no error");
+        let mut reader = HoundAudioReader::<i16>::new_seekable(&mut wav_reader).expect("no error");
+        assert_eq!(reader.duration_in_frames(), Some(4));
+        let mut left = [0; 2];
+        let mut right = [0; 2];
+        assert!(reader.seek(2).expect("no error"));
+        assert_eq!(
+            reader
+                .fill_buffer(&mut [&mut left, &mut right])
+                .expect("no error"),
+            2
+        );
+        assert_eq!(left, [3, 4]);
+        assert_eq!(right, [30, 40]);
+        assert!(reader.seek(1).expect("no error"));
+        reader
+            .fill_buffer(&mut [&mut left, &mut right])
+            .expect("no error");
+        assert_eq!(left, [2, 3]);
+    }
+
+    #[test]
+    fn integer_samples_are_scaled_to_full_scale() {
+        let mut bytes = Vec::new();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 24,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = WavWriter::new(Cursor::new(&mut bytes), spec).expect("no error");
+        for sample in &[(1 << 22), -(1 << 23)] {
+            writer.write_sample::<i32>(*sample).expect("no error");
+        }
+        writer.finalize().expect("no error");
+        let mut wav_reader = WavReader::new(Cursor::new(bytes)).expect("no error");
+        let mut reader = HoundAudioReader::<i32>::new(&mut wav_reader).expect("no error");
+        let mut output = [0; 2];
+        reader.fill_buffer(&mut [&mut output]).expect("no error");
+        assert_eq!(output, [1 << 30, i32::MIN]);
+    }
+
+    #[test]
+    fn samples_are_written_in_24_bits() {
+        let mut bytes = Vec::new();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 24,
+            sample_format: hound::SampleFormat::Int,
+        };
+        {
+            let mut wav_writer = WavWriter::new(Cursor::new(&mut bytes), spec).expect("no error");
+            HoundAudioWriter::<i32>::new(&mut wav_writer)
+                .expect("no error")
+                .write_buffer(&[&[1 << 30, i32::MIN]])
+                .expect("no error");
+            wav_writer.finalize().expect("no error");
+        }
+        let mut wav_reader = WavReader::new(Cursor::new(bytes)).expect("no error");
+        let samples: Vec<i32> = wav_reader
+            .samples::<i32>()
+            .map(|sample| sample.expect("no error"))
+            .collect();
+        assert_eq!(samples, vec![1 << 22, -(1 << 23)]);
+    }
+
+    #[test]
+    fn channels_are_dropped_or_added() {
+        let mut wav_reader = WavReader::new(Cursor::new(wav_file())).expect("no error");
+        let mut reader = HoundAudioReader::<i16>::new(&mut wav_reader)
+            .expect("no error")
+            .with_number_of_channels(1);
+        assert_eq!(reader.number_of_channels(), 1);
+        assert_eq!(reader.number_of_channels_in_file(), 2);
+        let mut left = [0; 2];
+        reader.fill_buffer(&mut [&mut left]).expect("no error");
+        assert_eq!(left, [1, 2]);
+
+        let mut wav_reader = WavReader::new(Cursor::new(wav_file())).expect("no error");
+        let mut reader = HoundAudioReader::<i16>::new(&mut wav_reader)
+            .expect("no error")
+            .with_number_of_channels(3);
+        let mut outputs = [[0; 2]; 3];
+        let [first, second, third] = &mut outputs;
+        reader
+            .fill_buffer(&mut [first, second, third])
+            .expect("no error");
+        assert_eq!(outputs, [[1, 2], [10, 20], [0, 0]]);
+    }
+
+    #[test]
+    fn reader_without_seek_does_not_seek() {
+        let mut wav_reader = WavReader::new(Cursor::new(wav_file())).expect("no error");
+        let mut reader = HoundAudioReader::<i16>::new(&mut wav_reader).expect("no error");
+        assert!(!reader.seek(2).expect("no error"));
+    }
+
+    #[test]
+    fn streaming_reader_reads_all_frames() {
+        let wav_reader = WavReader::new(Cursor::new(wav_file())).expect("no error");
+        let mut reader = HoundStreamingAudioReader::<i16>::new(wav_reader, 1).expect("no error");
+        assert_eq!(reader.number_of_channels(), 2);
+        assert_eq!(reader.duration_in_frames(), Some(4));
+        let mut left = [0; 3];
+        let mut right = [0; 3];
+        assert_eq!(
+            reader
+                .fill_buffer(&mut [&mut left, &mut right])
+                .expect("no error"),
+            3
+        );
+        assert_eq!(left, [1, 2, 3]);
+        assert_eq!(right, [10, 20, 30]);
+        assert_eq!(
+            reader
+                .fill_buffer(&mut [&mut left, &mut right])
+                .expect("no error"),
+            1
+        );
+        assert_eq!(left[0], 4);
+        assert_eq!(right[0], 40);
+        assert_eq!(
+            reader
+                .fill_buffer(&mut [&mut left, &mut right])
+                .expect("no error"),
+            0
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use super::{AudioReader, AudioWriter};
 use crate::buffer::AudioChunk;
+use std::fmt::{Display, Formatter};
 
 /// An [`AudioReader`] that reads from a given [`AudioChunk`].
 /// The generic parameter type `S` represents the sample type.
@@ -60,6 +61,16 @@ where
         self.frame += frames_to_copy;
         Ok(frames_to_copy)
     }
+
+    fn duration_in_frames(&self) -> Option<u64> {
+        Some(self.buffer.channels().first().map_or(0, Vec::len) as u64)
+    }
+
+    fn seek(&mut self, frame: u64) -> Result<bool, Self::Err> {
+        let length = self.buffer.channels().first().map_or(0, Vec::len);
+        self.frame = std::cmp::min(frame, length as u64) as usize;
+        Ok(true)
+    }
 }
 
 #[cfg(test)]
@@ -90,6 +101,39 @@ mod AudioBufferReaderTests {
             assert_eq!(buffers[2], vec![15, 14].as_slice());
         }
     }
+
+    mod seek {
+        use super::super::super::AudioReader;
+        use super::super::AudioBufferReader;
+        use crate::buffer::AudioChunk;
+
+        #[test]
+        fn continues_reading_at_the_given_frame() {
+            let audio_buffer = audio_chunk![[1, 2, 3, 4, 5], [6, 7, 8, 9, 10]];
+            let mut reader = AudioBufferReader::new(&audio_buffer, 16);
+            assert_eq!(reader.duration_in_frames(), Some(5));
+            let mut output_buffer = AudioChunk::zero(2, 2);
+            let mut buffers = output_buffer.as_mut_slices();
+            assert_eq!(Ok(true), reader.seek(3));
+            assert_eq!(Ok(2), reader.fill_buffer(buffers.as_mut_slice()));
+            assert_eq!(buffers[0], vec![4, 5].as_slice());
+            assert_eq!(buffers[1], vec![9, 10].as_slice());
+            assert_eq!(Ok(true), reader.seek(1));
+            assert_eq!(Ok(2), reader.fill_buffer(buffers.as_mut_slice()));
+            assert_eq!(buffers[0], vec![2, 3].as_slice());
+            assert_eq!(buffers[1], vec![7, 8].as_slice());
+            assert_eq!(Ok(true), reader.seek(100));
+            assert_eq!(Ok(0), reader.fill_buffer(buffers.as_mut_slice()));
+        }
+
+        #[test]
+        fn works_without_channels() {
+            let audio_buffer = AudioChunk::<i32>::zero(0, 5);
+            let mut reader = AudioBufferReader::new(&audio_buffer, 16);
+            assert_eq!(reader.duration_in_frames(), Some(0));
+            assert_eq!(Ok(true), reader.seek(3));
+        }
+    }
 }
 
 /// An [`AudioWriter`] that appends to a given [`AudioChunk`].
@@ -121,4 +165,204 @@ where
         self.buffer.append_sliced_chunk(buffer);
         Ok(())
     }
+
+    fn number_of_channels(&self) -> Option<usize> {
+        Some(self.buffer.channels().len())
+    }
+}
+
+/// An [`AudioReader`] that reads from a slice with interleaved samples, e.g. the input
+/// buffer of an audio callback: first all the channels of the first frame, then all the
+/// channels of the second frame, and so on.
+/// The generic parameter type `S` represents the sample type.
+///
+/// [`AudioReader`]: ../trait.AudioReader.html
+pub struct InterleavedAudioReader<'b, S> {
+    buffer: &'b [S],
+    number_of_channels: usize,
+    frames_per_second: u64,
+    frame: usize,
+}
+
+impl<'b, S> InterleavedAudioReader<'b, S> {
+    /// Construct a new `InterleavedAudioReader` with the given interleaved samples,
+    /// number of channels and sample rate in frames per second.
+    ///
+    /// # Panics
+    /// Panics if `number_of_channels` is zero or if the length of `buffer` is not a multiple
+    /// of `number_of_channels`.
+    pub fn new(buffer: &'b [S], number_of_channels: usize, frames_per_second: u64) -> Self {
+        assert!(number_of_channels > 0);
+        assert_eq!(buffer.len() % number_of_channels, 0);
+        Self {
+            buffer,
+            number_of_channels,
+            frames_per_second,
+            frame: 0,
+        }
+    }
+}
+
+impl<'b, S> AudioReader<S> for InterleavedAudioReader<'b, S>
+where
+    S: Copy,
+{
+    type Err = std::convert::Infallible;
+
+    fn number_of_channels(&self) -> usize {
+        self.number_of_channels
+    }
+
+    fn frames_per_second(&self) -> u64 {
+        self.frames_per_second
+    }
+
+    fn fill_buffer(&mut self, output: &mut [&mut [S]]) -> Result<usize, Self::Err> {
+        assert_eq!(output.len(), self.number_of_channels);
+        let buffer_size = output[0].len();
+        let remainder = self.buffer.len() / self.number_of_channels - self.frame;
+        let frames_to_copy = std::cmp::min(buffer_size, remainder);
+        let start = self.frame * self.number_of_channels;
+        let frames = self.buffer[start..start + frames_to_copy * self.number_of_channels]
+            .chunks_exact(self.number_of_channels);
+        for (index, frame) in frames.enumerate() {
+            for (output_channel, sample) in output.iter_mut().zip(frame.iter()) {
+                output_channel[index] = *sample;
+            }
+        }
+        self.frame += frames_to_copy;
+        Ok(frames_to_copy)
+    }
+
+    fn duration_in_frames(&self) -> Option<u64> {
+        Some((self.buffer.len() / self.number_of_channels) as u64)
+    }
+
+    fn seek(&mut self, frame: u64) -> Result<bool, Self::Err> {
+        let length = self.buffer.len() / self.number_of_channels;
+        self.frame = std::cmp::min(frame, length as u64) as usize;
+        Ok(true)
+    }
+}
+
+/// The error that is returned by an [`InterleavedAudioWriter`] when the slice is full.
+///
+/// [`InterleavedAudioWriter`]: ./struct.InterleavedAudioWriter.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferFullError;
+
+impl Display for BufferFullError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "the buffer is full")
+    }
+}
+
+impl std::error::Error for BufferFullError {}
+
+/// An [`AudioWriter`] that writes to a slice with interleaved samples, e.g. the output
+/// buffer of an audio callback.
+/// The generic parameter type `S` represents the sample type.
+///
+/// In contrast to the [`AudioBufferWriter`], this does not allocate memory, so that it can be
+/// used in a real-time context.
+///
+/// [`AudioWriter`]: ../trait.AudioWriter.html
+/// [`AudioBufferWriter`]: ./struct.AudioBufferWriter.html
+pub struct InterleavedAudioWriter<'b, S> {
+    buffer: &'b mut [S],
+    number_of_channels: usize,
+    frame: usize,
+}
+
+impl<'b, S> InterleavedAudioWriter<'b, S> {
+    /// Construct a new `InterleavedAudioWriter` that writes to the given slice with the given
+    /// number of channels.
+    ///
+    /// # Panics
+    /// Panics if `number_of_channels` is zero or if the length of `buffer` is not a multiple
+    /// of `number_of_channels`.
+    pub fn new(buffer: &'b mut [S], number_of_channels: usize) -> Self {
+        assert!(number_of_channels > 0);
+        assert_eq!(buffer.len() % number_of_channels, 0);
+        Self {
+            buffer,
+            number_of_channels,
+            frame: 0,
+        }
+    }
+
+    /// The number of frames that have been written so far.
+    pub fn number_of_frames_written(&self) -> usize {
+        self.frame
+    }
+}
+
+impl<'b, S> AudioWriter<S> for InterleavedAudioWriter<'b, S>
+where
+    S: Copy,
+{
+    type Err = BufferFullError;
+
+    /// Write the frames to the slice.
+    /// When not all frames fit, the frames that fit are written and `BufferFullError`
+    /// is returned.
+    fn write_buffer(&mut self, buffer: &[&[S]]) -> Result<(), Self::Err> {
+        assert_eq!(buffer.len(), self.number_of_channels);
+        let length = buffer[0].len();
+        let remainder = self.buffer.len() / self.number_of_channels - self.frame;
+        let frames_to_copy = std::cmp::min(length, remainder);
+        let start = self.frame * self.number_of_channels;
+        let frames = self.buffer[start..start + frames_to_copy * self.number_of_channels]
+            .chunks_exact_mut(self.number_of_channels);
+        for (index, frame) in frames.enumerate() {
+            for (sample, input_channel) in frame.iter_mut().zip(buffer.iter()) {
+                *sample = input_channel[index];
+            }
+        }
+        self.frame += frames_to_copy;
+        if frames_to_copy < length {
+            Err(BufferFullError)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn number_of_channels(&self) -> Option<usize> {
+        Some(self.number_of_channels)
+    }
+}
+
+#[cfg(test)]
+mod interleaved_tests {
+    use super::super::{AudioReader, AudioWriter};
+    use super::{BufferFullError, InterleavedAudioReader, InterleavedAudioWriter};
+    use crate::buffer::AudioChunk;
+
+    #[test]
+    fn reader_deinterleaves() {
+        let samples = [1, 6, 2, 7, 3, 8];
+        let mut reader = InterleavedAudioReader::new(&samples, 2, 16);
+        assert_eq!(reader.duration_in_frames(), Some(3));
+        let mut output_buffer = AudioChunk::zero(2, 2);
+        let mut buffers = output_buffer.as_mut_slices();
+        assert_eq!(Ok(2), reader.fill_buffer(buffers.as_mut_slice()));
+        assert_eq!(buffers[0], vec![1, 2].as_slice());
+        assert_eq!(buffers[1], vec![6, 7].as_slice());
+        assert_eq!(Ok(1), reader.fill_buffer(buffers.as_mut_slice()));
+        assert_eq!(buffers[0], vec![3, 2].as_slice());
+        assert_eq!(buffers[1], vec![8, 7].as_slice());
+    }
+
+    #[test]
+    fn writer_interleaves_until_the_slice_is_full() {
+        let mut samples = [0; 6];
+        let mut writer = InterleavedAudioWriter::new(&mut samples, 2);
+        assert_eq!(Ok(()), writer.write_buffer(&[&[1, 2], &[6, 7]]));
+        assert_eq!(
+            Err(BufferFullError),
+            writer.write_buffer(&[&[3, 4], &[8, 9]])
+        );
+        assert_eq!(writer.number_of_frames_written(), 3);
+        assert_eq!(samples, [1, 6, 2, 7, 3, 8]);
+    }
 }
@@ -0,0 +1,430 @@
+//! Read and write Standard MIDI Files (`.mid`) with [`midly`].
+//!
+//! Support is only enabled if `rsynth` is compiled with the "backend-combined-midly" feature.
+//!
+//! In contrast to `RimdMidiReader`, which reads one track, a [`MidlyMidiReader`] merges all
+//! tracks of the file and applies the tempo changes of all tracks, so that a complete song
+//! can be rendered offline:
+//!
+//! ```no_run
+//! use rsynth::backend::combined::midly::MidlyMidiReader;
+//!
+//! let bytes = std::fs::read("song.mid").unwrap();
+//! let smf = midly::Smf::parse(&bytes).unwrap();
+//! let midi_reader = MidlyMidiReader::new(&smf).unwrap();
+//! // Use `midi_reader` as the source of events for `rsynth::backend::combined::run_with_events`
+//! // or as the midi input for `rsynth::backend::combined::run`.
+//! ```
+//!
+//! A [`MidlyMidiWriter`] records the events that a plugin emits, e.g. to capture the
+//! output of an arpeggiator:
+//!
+//! ```no_run
+//! use rsynth::backend::combined::midly::MidlyMidiWriter;
+//!
+//! // 480 ticks per beat, 120 beats per minute.
+//! let mut midi_writer = MidlyMidiWriter::new(480, 500_000);
+//! // Use `&mut midi_writer` as the midi output for `rsynth::backend::combined::run` or wrap it
+//! // in a `MidiWriterSink` for `rsynth::backend::combined::run_with_events`.
+//! // ...
+//! let file = std::fs::File::create("output.mid").unwrap();
+//! midi_writer.write(file).unwrap();
+//! ```
+//!
+//! [`midly`]: https://crates.io/crates/midly
+//! [`MidlyMidiReader`]: ./struct.MidlyMidiReader.html
+//! [`MidlyMidiWriter`]: ./struct.MidlyMidiWriter.html
+use super::events::{BufferTiming, EventSource};
+use super::{MidiWriter, MICROSECONDS_PER_SECOND};
+use crate::event::{DeltaEvent, EventHandler, RawMidiEvent, Timed};
+use midly::live::LiveEvent;
+use midly::num::{u15, u24, u28};
+use midly::{Format, Header, MetaMessage, Smf, Timing, TrackEvent, TrackEventKind};
+use std::io::{self, Write};
+
+const DEFAULT_MICROSECONDS_PER_BEAT: u64 = 500_000; // 120 beats per minute.
+
+// A tempo that is valid from a given time on.
+struct Tempo {
+    start_in_ticks: u64,
+    start_in_microseconds: u64,
+    microseconds_per_beat: u64,
+    ticks_per_beat: u64,
+}
+
+impl Tempo {
+    fn microseconds(&self, time_in_ticks: u64) -> u64 {
+        self.start_in_microseconds
+            + (time_in_ticks - self.start_in_ticks) * self.microseconds_per_beat
+                / self.ticks_per_beat
+    }
+}
+
+#[derive(Debug)]
+pub enum MidlyMidiError {
+    /// The time division of the file is zero ticks per beat or zero ticks per frame.
+    InvalidTimeDivision,
+}
+
+/// Read the midi events of a Standard MIDI File that has been parsed with [`midly`].
+///
+/// The events of all tracks are merged (for files with "parallel" tracks) or concatenated
+/// (for files with "sequential" tracks) and the time of every event is computed with the
+/// tempo changes of all tracks. System exclusive messages and meta messages are skipped.
+///
+/// `MidlyMidiReader` can be used in two ways:
+/// * as an [`EventSource`], which passes the events of every buffer with `time_in_frames`
+///   relative to the start of the buffer,
+/// * as an iterator over [`DeltaEvent`]s, e.g. as the midi input of the [`run`] function.
+///
+/// Note
+/// ----
+/// All events are converted when the `MidlyMidiReader` is created, so that no allocations
+/// are needed while reading.
+///
+/// [`midly`]: https://crates.io/crates/midly
+/// [`EventSource`]: ../events/trait.EventSource.html
+/// [`DeltaEvent`]: ../../../event/struct.DeltaEvent.html
+/// [`run`]: ../fn.run.html
+pub struct MidlyMidiReader {
+    events: Vec<(u64, RawMidiEvent)>,
+    position: usize,
+    previous_time_in_microseconds: u64,
+}
+
+impl MidlyMidiReader {
+    /// Create a new `MidlyMidiReader` that reads all tracks of `smf`.
+    pub fn new(smf: &Smf) -> Result<Self, MidlyMidiError> {
+        let mut tempo_changes = Vec::new();
+        let mut midi_events = Vec::new();
+        let mut track_start_in_ticks = 0;
+        for track in smf.tracks.iter() {
+            let mut time_in_ticks = track_start_in_ticks;
+            for event in track.iter() {
+                time_in_ticks += event.delta.as_int() as u64;
+                match event.kind {
+                    TrackEventKind::Midi { .. } => {
+                        let mut data = Vec::with_capacity(3);
+                        if let Some(live_event) = event.kind.as_live_event() {
+                            // Writing to a `Vec` does not fail.
+                            let _ = live_event.write_std(&mut data);
+                        }
+                        if let Some(raw_event) = RawMidiEvent::try_new(&data) {
+                            midi_events.push((time_in_ticks, raw_event));
+                        }
+                    }
+                    TrackEventKind::Meta(MetaMessage::Tempo(microseconds_per_beat)) => {
+                        tempo_changes.push((time_in_ticks, microseconds_per_beat.as_int() as u64));
+                    }
+                    _ => {}
+                }
+            }
+            if smf.header.format == Format::Sequential {
+                track_start_in_ticks = time_in_ticks;
+            }
+        }
+        // The sort is stable, so simultaneous events keep the order of the tracks.
+        tempo_changes.sort_by_key(|(time_in_ticks, _)| *time_in_ticks);
+        midi_events.sort_by_key(|(time_in_ticks, _)| *time_in_ticks);
+
+        let events = match smf.header.timing {
+            Timing::Metrical(ticks_per_beat) => {
+                let ticks_per_beat = ticks_per_beat.as_int() as u64;
+                if ticks_per_beat == 0 {
+                    return Err(MidlyMidiError::InvalidTimeDivision);
+                }
+                let mut tempo_changes = tempo_changes.into_iter().peekable();
+                let mut tempo = Tempo {
+                    start_in_ticks: 0,
+                    start_in_microseconds: 0,
+                    microseconds_per_beat: DEFAULT_MICROSECONDS_PER_BEAT,
+                    ticks_per_beat,
+                };
+                let mut events = Vec::with_capacity(midi_events.len());
+                for (time_in_ticks, event) in midi_events {
+                    while let Some((change_in_ticks, microseconds_per_beat)) =
+                        tempo_changes.next_if(|(change, _)| *change <= time_in_ticks)
+                    {
+                        tempo = Tempo {
+                            start_in_ticks: change_in_ticks,
+                            start_in_microseconds: tempo.microseconds(change_in_ticks),
+                            microseconds_per_beat,
+                            ticks_per_beat,
+                        };
+                    }
+                    events.push((tempo.microseconds(time_in_ticks), event));
+                }
+                events
+            }
+            Timing::Timecode(fps, ticks_per_frame) => {
+                let ticks_per_second = fps.as_f32() as f64 * ticks_per_frame as f64;
+                if ticks_per_second <= 0.0 {
+                    return Err(MidlyMidiError::InvalidTimeDivision);
+                }
+                midi_events
+                    .into_iter()
+                    .map(|(time_in_ticks, event)| {
+                        let time_in_seconds = time_in_ticks as f64 / ticks_per_second;
+                        (
+                            (time_in_seconds * MICROSECONDS_PER_SECOND as f64) as u64,
+                            event,
+                        )
+                    })
+                    .collect()
+            }
+        };
+        Ok(Self {
+            events,
+            position: 0,
+            previous_time_in_microseconds: 0,
+        })
+    }
+
+    /// The number of events that have not been read yet.
+    pub fn number_of_remaining_events(&self) -> usize {
+        self.events.len() - self.position
+    }
+
+    /// The time of the last event, in microseconds since the start of the file.
+    pub fn duration_in_microseconds(&self) -> u64 {
+        self.events.last().map(|(time, _)| *time).unwrap_or(0)
+    }
+}
+
+impl EventSource<RawMidiEvent> for MidlyMidiReader {
+    fn read_events<H: EventHandler<Timed<RawMidiEvent>>>(
+        &mut self,
+        timing: &BufferTiming,
+        handler: &mut H,
+    ) {
+        while let Some((time_in_microseconds, event)) = self.events.get(self.position) {
+            let time_in_frames = match timing.frame_offset(*time_in_microseconds) {
+                Some(offset) => offset,
+                None => break,
+            };
+            self.position += 1;
+            self.previous_time_in_microseconds = *time_in_microseconds;
+            handler.handle_event(Timed {
+                time_in_frames,
+                event: *event,
+            });
+        }
+    }
+
+    fn is_exhausted(&mut self) -> bool {
+        self.position == self.events.len()
+    }
+}
+
+impl Iterator for MidlyMidiReader {
+    type Item = DeltaEvent<RawMidiEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (time_in_microseconds, event) = *self.events.get(self.position)?;
+        self.position += 1;
+        let microseconds_since_previous_event =
+            time_in_microseconds - self.previous_time_in_microseconds;
+        self.previous_time_in_microseconds = time_in_microseconds;
+        Some(DeltaEvent {
+            microseconds_since_previous_event,
+            event,
+        })
+    }
+}
+
+/// Record midi events and write them to a Standard MIDI File with [`midly`].
+///
+/// The events are written to a single track, with the given resolution and tempo.
+/// Events that are not channel messages (e.g. real-time messages) are not written,
+/// because they cannot be stored in a Standard MIDI File.
+///
+/// [`midly`]: https://crates.io/crates/midly
+pub struct MidlyMidiWriter {
+    events: Vec<(u64, RawMidiEvent)>,
+    current_time_in_microseconds: u64,
+    ticks_per_beat: u16,
+    microseconds_per_beat: u32,
+}
+
+impl MidlyMidiWriter {
+    /// Create a new `MidlyMidiWriter`.
+    ///
+    /// # Panics
+    /// Panics if `ticks_per_beat` is zero or does not fit in 15 bits,
+    /// or if `microseconds_per_beat` does not fit in 24 bits.
+    pub fn new(ticks_per_beat: u16, microseconds_per_beat: u32) -> Self {
+        assert!(ticks_per_beat > 0 && ticks_per_beat < 1 << 15);
+        assert!(microseconds_per_beat < 1 << 24);
+        Self {
+            events: Vec::new(),
+            current_time_in_microseconds: 0,
+            ticks_per_beat,
+            microseconds_per_beat,
+        }
+    }
+
+    fn time_in_ticks(&self, time_in_microseconds: u64) -> u64 {
+        (time_in_microseconds * self.ticks_per_beat as u64 + self.microseconds_per_beat as u64 / 2)
+            / self.microseconds_per_beat as u64
+    }
+
+    /// The recorded events, as a Standard MIDI File with one track.
+    pub fn to_smf(&self) -> Smf<'static> {
+        let mut track = Vec::with_capacity(self.events.len() + 2);
+        track.push(TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(self.microseconds_per_beat))),
+        });
+        let mut previous_time_in_ticks = 0;
+        for (time_in_microseconds, event) in self.events.iter() {
+            let (channel, message) = match LiveEvent::parse(event.data()) {
+                Ok(LiveEvent::Midi { channel, message }) => (channel, message),
+                _ => continue,
+            };
+            let time_in_ticks = self.time_in_ticks(*time_in_microseconds);
+            track.push(TrackEvent {
+                delta: u28::new((time_in_ticks - previous_time_in_ticks) as u32),
+                kind: TrackEventKind::Midi { channel, message },
+            });
+            previous_time_in_ticks = time_in_ticks;
+        }
+        track.push(TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        });
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(self.ticks_per_beat)),
+        ));
+        smf.tracks.push(track);
+        smf
+    }
+
+    /// Write the recorded events as a Standard MIDI File.
+    ///
+    /// Note
+    /// ----
+    /// This method allocates and performs I/O, so it cannot be used in a real-time context.
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.to_smf().write_std(writer)
+    }
+}
+
+impl MidiWriter for MidlyMidiWriter {
+    fn write_event(&mut self, event: DeltaEvent<RawMidiEvent>) {
+        self.current_time_in_microseconds += event.microseconds_since_previous_event;
+        self.events
+            .push((self.current_time_in_microseconds, event.event));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::num::{u4, u7};
+    use midly::MidiMessage;
+
+    struct Collect(Vec<Timed<RawMidiEvent>>);
+
+    impl EventHandler<Timed<RawMidiEvent>> for Collect {
+        fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+            self.0.push(event);
+        }
+    }
+
+    fn note_on(delta: u32, key: u8) -> TrackEvent<'static> {
+        TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Midi {
+                channel: u4::new(0),
+                message: MidiMessage::NoteOn {
+                    key: u7::new(key),
+                    vel: u7::new(100),
+                },
+            },
+        }
+    }
+
+    fn tempo(delta: u32, microseconds_per_beat: u32) -> TrackEvent<'static> {
+        TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(microseconds_per_beat))),
+        }
+    }
+
+    #[test]
+    fn tracks_are_merged_with_the_tempo_map_of_all_tracks() {
+        let mut smf = Smf::new(Header::new(
+            Format::Parallel,
+            Timing::Metrical(u15::new(100)),
+        ));
+        // The tempo track: 120 bpm, then 60 bpm from the second beat on.
+        smf.tracks
+            .push(vec![tempo(0, 500_000), tempo(100, 1_000_000)]);
+        smf.tracks
+            .push(vec![note_on(50, 60), note_on(100, 62), note_on(100, 64)]);
+        let reader = MidlyMidiReader::new(&smf).expect("no error");
+        let times: Vec<u64> = reader
+            .map(|event| event.microseconds_since_previous_event)
+            .collect();
+        assert_eq!(times, vec![250_000, 250_000 + 500_000, 1_000_000]);
+    }
+
+    #[test]
+    fn events_are_read_per_buffer() {
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(1)),
+        ));
+        // One beat is half a second at the default tempo.
+        smf.tracks.push(vec![note_on(0, 60), note_on(1, 62)]);
+        let mut reader = MidlyMidiReader::new(&smf).expect("no error");
+        let mut events = Collect(Vec::new());
+        let mut timing = BufferTiming {
+            start_in_frames: 0,
+            start_in_microseconds: 0,
+            number_of_frames: 4,
+            frames_per_second: 10,
+        };
+        reader.read_events(&timing, &mut events);
+        assert_eq!(events.0.len(), 1);
+        assert_eq!(events.0[0].time_in_frames, 0);
+        assert_eq!(reader.number_of_remaining_events(), 1);
+        timing.start_in_frames = 4;
+        timing.start_in_microseconds = 400_000;
+        reader.read_events(&timing, &mut events);
+        assert_eq!(events.0.len(), 2);
+        assert_eq!(events.0[1].time_in_frames, 1);
+        assert_eq!(events.0[1].event.data(), &[0x90, 62, 100]);
+    }
+
+    #[test]
+    fn written_events_can_be_read_back() {
+        let mut writer = MidlyMidiWriter::new(480, 500_000);
+        writer.write_event(DeltaEvent {
+            microseconds_since_previous_event: 250_000,
+            event: RawMidiEvent::new(&[0x90, 60, 100]),
+        });
+        // Real-time messages are not written.
+        writer.write_event(DeltaEvent {
+            microseconds_since_previous_event: 0,
+            event: RawMidiEvent::new(&[0xF8]),
+        });
+        writer.write_event(DeltaEvent {
+            microseconds_since_previous_event: 500_000,
+            event: RawMidiEvent::new(&[0x80, 60, 0]),
+        });
+        let smf = writer.to_smf();
+        assert_eq!(smf.tracks.len(), 1);
+        assert_eq!(smf.tracks[0][1].delta.as_int(), 240);
+        assert_eq!(smf.tracks[0][2].delta.as_int(), 480);
+
+        let events: Vec<DeltaEvent<RawMidiEvent>> =
+            MidlyMidiReader::new(&smf).expect("no error").collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].microseconds_since_previous_event, 250_000);
+        assert_eq!(events[0].event.data(), &[0x90, 60, 100]);
+        assert_eq!(events[1].microseconds_since_previous_event, 500_000);
+        assert_eq!(events[1].event.data(), &[0x80, 60, 0]);
+    }
+}
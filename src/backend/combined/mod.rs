@@ -9,38 +9,93 @@
 //!
 //! Currently, the following inputs and outputs are available:
 //!
-//! * Dummy: [`AudioDummy`]: dummy audio input (generates silence) and output and [`MidiDummy`]: dummy midi input (generates no events) and output
-//! * Hound: [`HoundAudioReader`] and [`HoundAudioWriter`]: read and write `.wav` files (behind the "backend-combined-hound" feature)
+//! * Dummy: [`AudioDummy`]: dummy audio input (without channels, e.g. for synthesizers) and output and [`MidiDummy`]: dummy midi input (generates no events) and output
+//! * Hound: [`HoundAudioReader`] and [`HoundAudioWriter`]: read and write `.wav` files and [`HoundStreamingAudioReader`]: read long `.wav` files on a background thread (behind the "backend-combined-hound" feature)
+//! * Flac: [`FlacAudioWriter`]: write `.flac` files (behind the "backend-file-flac" feature)
+//! * Opus: [`OpusAudioWriter`]: write compressed `.opus` files, e.g. for previews (behind the "backend-file-opus" feature)
 //! * Rimd: [`RimdMidiReader`] and [`RimdMidiWriter`]: reand and write `.mid` files (behind the "backend-combined-rimd" feature)
-//! * Memory: [`AudioBufferReader`] and [`AudioBufferWriter`]: read and write audio from memory
+//! * Midly: [`MidlyMidiReader`] and [`MidlyMidiWriter`]: read all tracks of a `.mid` file, with tempo changes, and write `.mid` files (behind the "backend-combined-midly" feature)
+//! * Memory: [`AudioBufferReader`] and [`AudioBufferWriter`]: read and write audio from memory,
+//!   [`InterleavedAudioReader`] and [`InterleavedAudioWriter`]: read and write interleaved audio from memory, e.g. in an audio callback
 //! * Testing: [`TestAudioReader`] and [`TestAudioWriter`]: audio input and output, to be used in tests
+//! * Cpal: [`CpalAudioWriter`]: play the output on an audio device (behind the "backend-cpal" feature)
+//! * Stems: [`StemWriter`]: write groups of output channels with separate writers, e.g. to separate `.wav` files
+//!
+//! The [`run_with_events`] function is similar, but reads and writes events with an
+//! [`EventSource`] and an [`EventSink`], which have a documented timing contract and can be
+//! implemented for custom sources and destinations of events.
+//! [`run_with_events_until_silence`] continues rendering after the audio input has ended,
+//! until the output is silent, so that reverb tails and the release of notes are not truncated.
+//! [`run_with_options`] combines this with reporting the progress and cancelling rendering
+//! from another thread (see the [`progress`] module) and with rendering at the pace of a wall
+//! clock, e.g. to listen to the output with a [`CpalAudioWriter`].
+//!
+//! Besides reading and writing audio, readers and writers can optionally report their duration,
+//! support seeking and read or write embedded metadata such as loop points
+//! (see [`AudioReader`] and [`AudioWriter`]), so that e.g. rendering a loop can be implemented
+//! independently of the file format.
 //!
 //! [`AudioDummy`]: ./dummy/struct.AudioDummy.html
 //! [`MidiDummy`]: ./dummy/struct.MidiDummy.html
 //! [`HoundAudioReader`]: ./hound/struct.HoundAudioReader.html
 //! [`HoundAudioWriter`]: ./hound/struct.HoundAudioWriter.html
+//! [`HoundStreamingAudioReader`]: ./hound/struct.HoundStreamingAudioReader.html
+//! [`FlacAudioWriter`]: ./flac/struct.FlacAudioWriter.html
+//! [`OpusAudioWriter`]: ./opus/struct.OpusAudioWriter.html
 //! [`RimdMidiReader`]: ./rimd/struct.RimdMidiReader.html
 //! [`RimdMidiWriter`]: ./rimd/struct.RimdMidiWriter.html
+//! [`MidlyMidiReader`]: ./midly/struct.MidlyMidiReader.html
+//! [`MidlyMidiWriter`]: ./midly/struct.MidlyMidiWriter.html
 //! [`TestAudioReader`]: ./struct.TestAudioReader.html
 //! [`TestAudioWriter`]: ./struct.TestAudioWriter.html
 //! [`AudioBufferReader`]: ./memory/struct.AudioBufferReader.html
 //! [`AudioBufferWriter`]: ./memory/struct.AudioBufferWriter.html
+//! [`InterleavedAudioReader`]: ./memory/struct.InterleavedAudioReader.html
+//! [`InterleavedAudioWriter`]: ./memory/struct.InterleavedAudioWriter.html
+//! [`StemWriter`]: ./stems/struct.StemWriter.html
+//! [`CpalAudioWriter`]: ./cpal/struct.CpalAudioWriter.html
 //! [`run`]: ./fn.run.html
+//! [`run_with_events`]: ./fn.run_with_events.html
+//! [`run_with_events_until_silence`]: ./fn.run_with_events_until_silence.html
+//! [`run_with_options`]: ./fn.run_with_options.html
+//! [`progress`]: ./progress/index.html
+//! [`EventSource`]: ./events/trait.EventSource.html
+//! [`EventSink`]: ./events/trait.EventSink.html
+//! [`AudioReader`]: ./trait.AudioReader.html
+//! [`AudioWriter`]: ./trait.AudioWriter.html
 //! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
 
+use self::events::{
+    BufferTiming, DeltaEventSource, EventSink, EventSinkContext, EventSource, MidiWriterSink,
+};
+use self::progress::RenderProgress;
 use crate::buffer::{buffers_as_mut_slice, buffers_as_slice, AudioChunk};
 use crate::event::event_queue::{AlwaysInsertNewAfterOld, EventQueue};
 use crate::event::{DeltaEvent, EventHandler, RawMidiEvent, Timed};
-use crate::ContextualAudioRenderer;
+use crate::{AudioHandler, ContextualAudioRenderer};
+use asprim::AsPrim;
 use num_traits::Zero;
 use std::fmt::Debug;
+use std::thread;
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "backend-cpal")]
+pub mod cpal;
 pub mod dummy;
+pub mod events;
+#[cfg(feature = "backend-file-flac")]
+pub mod flac;
 #[cfg(feature = "backend-combined-hound")]
 pub mod hound;
 pub mod memory;
+#[cfg(feature = "backend-combined-midly")]
+pub mod midly;
+#[cfg(feature = "backend-file-opus")]
+pub mod opus;
+pub mod progress;
 #[cfg(feature = "backend-combined-rimd")]
 pub mod rimd; // TODO: choose better name for this module.
+pub mod stems;
 
 /// Define how audio is read.
 ///
@@ -50,15 +105,53 @@ pub trait AudioReader<S> {
     type Err;
 
     /// The number of audio channels that can be read.
+    ///
+    /// A reader without channels (e.g. the audio input of a synthesizer) has no buffers to fill,
+    /// so `fill_buffer` is not called by the back-end. Instead, the back-end renders the
+    /// number of frames given by [`duration_in_frames`], or no frames at all when this is `None`.
+    ///
+    /// [`duration_in_frames`]: ./trait.AudioReader.html#method.duration_in_frames
     fn number_of_channels(&self) -> usize;
 
     /// The sampling frequency in frames per second.
+    ///
+    /// The sampling frequency may change while reading, e.g. when reading several files
+    /// one after the other. Before the first call to `fill_buffer`, this returns the sampling
+    /// frequency of the first frames. After a call to `fill_buffer`, this returns the sampling
+    /// frequency of the frames that have been read by that call.
     fn frames_per_second(&self) -> u64;
 
     /// Fill the buffers. Return the number of frames that have been read and written
     /// to the buffer.
     /// If the return value is `<` the number of frames in the input, no more frames can be expected.
     fn fill_buffer(&mut self, output: &mut [&mut [S]]) -> Result<usize, Self::Err>;
+
+    /// The total number of frames that can be read, or `None` when this is not known
+    /// (e.g. for a stream).
+    ///
+    /// The default implementation returns `None`.
+    fn duration_in_frames(&self) -> Option<u64> {
+        None
+    }
+
+    /// Continue reading at the given frame.
+    /// Return `Ok(false)` when this reader does not support seeking; the position is then
+    /// not changed.
+    ///
+    /// Seeking beyond [`duration_in_frames`] moves to the end.
+    /// The default implementation does not support seeking.
+    ///
+    /// [`duration_in_frames`]: ./trait.AudioReader.html#method.duration_in_frames
+    fn seek(&mut self, _frame: u64) -> Result<bool, Self::Err> {
+        Ok(false)
+    }
+
+    /// The metadata that is embedded in the audio, such as loop points.
+    ///
+    /// The default implementation returns empty metadata.
+    fn metadata(&self) -> AudioMetadata {
+        AudioMetadata::default()
+    }
 }
 
 /// Define how audio is written.
@@ -69,6 +162,58 @@ pub trait AudioWriter<S> {
     type Err;
     // TODO: What if the writer gets an unexpected number of channels?
     fn write_buffer(&mut self, buffer: &[&[S]]) -> Result<(), Self::Err>;
+
+    /// The number of audio channels that are expected by `write_buffer`, or `None` when
+    /// any number of channels is accepted.
+    ///
+    /// The default implementation returns `None`.
+    fn number_of_channels(&self) -> Option<usize> {
+        None
+    }
+
+    /// The sampling frequency in frames per second of the output, or `None` when this
+    /// is not fixed by the writer.
+    ///
+    /// The default implementation returns `None`.
+    fn frames_per_second(&self) -> Option<u64> {
+        None
+    }
+
+    /// Embed the given metadata in the output.
+    /// Return `Ok(false)` when this writer cannot store metadata.
+    ///
+    /// The default implementation does not store metadata.
+    fn write_metadata(&mut self, _metadata: &AudioMetadata) -> Result<bool, Self::Err> {
+        Ok(false)
+    }
+}
+
+/// A loop that is embedded in an audio file, e.g. in the `smpl` chunk of a `.wav` file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoopPoints {
+    /// The first frame of the loop.
+    pub start: u64,
+    /// The first frame after the loop.
+    pub end: u64,
+}
+
+impl LoopPoints {
+    /// The number of frames of the loop.
+    pub fn length(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// Metadata that is embedded in audio, see [`AudioReader::metadata`].
+///
+/// [`AudioReader::metadata`]: ./trait.AudioReader.html#method.metadata
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AudioMetadata {
+    /// The loops, in the order in which they are stored.
+    pub loops: Vec<LoopPoints>,
+    /// The time of the first frame, in frames since midnight, as stored in the
+    /// "time reference" of a Broadcast Wave Format (BWF) file.
+    pub time_reference: Option<u64>,
 }
 
 pub const MICROSECONDS_PER_SECOND: u64 = 1_000_000;
@@ -77,12 +222,35 @@ pub trait MidiWriter {
     fn write_event(&mut self, event: DeltaEvent<RawMidiEvent>);
 }
 
+impl<W> MidiWriter for &mut W
+where
+    W: MidiWriter,
+{
+    fn write_event(&mut self, event: DeltaEvent<RawMidiEvent>) {
+        (**self).write_event(event)
+    }
+}
+
+/// Collects the events, e.g. to inspect them in a test.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// This may allocate memory when the capacity of the `Vec` is exceeded.
+impl MidiWriter for Vec<DeltaEvent<RawMidiEvent>> {
+    fn write_event(&mut self, event: DeltaEvent<RawMidiEvent>) {
+        self.push(event);
+    }
+}
+
 // TODO: find a better name for this.
 pub struct MidiWriterWrapper<W>
 where
     W: MidiWriter,
 {
     inner: W,
+    // The time at which the micro_seconds_per_frame was last changed.
+    offset_in_microseconds: u64,
+    // The number of frames since `offset_in_microseconds`.
     current_time_in_frames: u64,
     previous_time_in_microseconds: u64,
     micro_seconds_per_frame: f64,
@@ -96,6 +264,7 @@ where
     pub fn new(inner: W, micro_seconds_per_frame: f64) -> Self {
         MidiWriterWrapper {
             inner,
+            offset_in_microseconds: 0,
             previous_time_in_microseconds: 0,
             current_time_in_frames: 0,
             micro_seconds_per_frame,
@@ -107,8 +276,8 @@ where
         for event in self.event_queue.iter() {
             let current_time_in_frames =
                 self.current_time_in_frames + (event.time_in_frames as u64);
-            let current_time_in_microseconds =
-                (current_time_in_frames as f64 * self.micro_seconds_per_frame) as u64;
+            let current_time_in_microseconds = self.offset_in_microseconds
+                + (current_time_in_frames as f64 * self.micro_seconds_per_frame) as u64;
             let delta_event = DeltaEvent {
                 microseconds_since_previous_event: current_time_in_microseconds
                     - self.previous_time_in_microseconds,
@@ -120,6 +289,15 @@ where
         self.event_queue.clear();
         self.current_time_in_frames += number_of_frames;
     }
+
+    /// Change the duration of a frame, e.g. because the sample rate changes.
+    /// This only affects the frames after the frames that have already been stepped.
+    pub fn set_micro_seconds_per_frame(&mut self, micro_seconds_per_frame: f64) {
+        self.offset_in_microseconds +=
+            (self.current_time_in_frames as f64 * self.micro_seconds_per_frame) as u64;
+        self.current_time_in_frames = 0;
+        self.micro_seconds_per_frame = micro_seconds_per_frame;
+    }
 }
 
 impl<W> EventHandler<Timed<RawMidiEvent>> for MidiWriterWrapper<W>
@@ -140,114 +318,483 @@ pub enum CombinedError<AudioInErr, AudioOutErr> {
     AudioInError(AudioInErr),
     /// An error occurred when writing the audio.
     AudioOutError(AudioOutErr),
+    /// Rendering has been cancelled with [`RenderProgress::cancel`].
+    ///
+    /// [`RenderProgress::cancel`]: ./progress/struct.RenderProgress.html#method.cancel
+    Cancelled,
 }
 
+// The result of the `run` functions.
+type RunResult<S, AudioIn, AudioOut> =
+    Result<(), CombinedError<<AudioIn as AudioReader<S>>::Err, <AudioOut as AudioWriter<S>>::Err>>;
+
 /// Run an audio renderer with the given audio input, audio output, midi input and midi output.
 ///
+/// This is [`run_with_events`] with a [`DeltaEventSource`] as the event source and a
+/// [`MidiWriterSink`] as the event sink: all events are passed to the plugin with
+/// sample-accurate timing, also when several events fall within one buffer.
+///
 /// Parameters
 /// ==========
-/// * `buffer_size_in_frames`: the buffer size in frames.
+/// * `buffer_size_in_frames`: the buffer size in frames. It is passed to the plugin with
+///   `set_max_buffer_size` before the first buffer is rendered.
+///
+/// Sample rate
+/// ===========
+/// The sample rate of the audio input is passed to the plugin with `set_sample_rate`
+/// before the first buffer is rendered.
+/// When the sample rate of the audio input changes (see [`AudioReader::frames_per_second`]),
+/// `set_sample_rate` is called again before the buffer with the new sample rate is rendered.
+///
+/// Channels
+/// ========
+/// The plugin gets as many inputs as the audio input has channels and as many outputs as
+/// the audio output expects (see [`AudioWriter::number_of_channels`]); when the audio output
+/// accepts any number of channels, the plugin gets as many outputs as inputs.
+/// The audio input may have no channels, e.g. for a synthesizer. See
+/// [`AudioReader::number_of_channels`] for how many frames are rendered in that case.
 ///
 /// Panics
 /// ======
 /// Panics if `buffer_size_in_frames` is `0` or `> u32::max_value()`.
+///
+/// [`run_with_events`]: ./fn.run_with_events.html
+/// [`DeltaEventSource`]: ./events/struct.DeltaEventSource.html
+/// [`MidiWriterSink`]: ./events/struct.MidiWriterSink.html
+/// [`AudioReader::frames_per_second`]: ./trait.AudioReader.html#tymethod.frames_per_second
+/// [`AudioWriter::number_of_channels`]: ./trait.AudioWriter.html#method.number_of_channels
+/// [`AudioReader::number_of_channels`]: ./trait.AudioReader.html#tymethod.number_of_channels
 pub fn run<S, AudioIn, AudioOut, MidiIn, MidiOut, R>(
     plugin: &mut R,
     buffer_size_in_frames: usize,
-    mut audio_in: AudioIn,
-    mut audio_out: AudioOut,
+    audio_in: AudioIn,
+    audio_out: AudioOut,
     midi_in: MidiIn,
     midi_out: MidiOut,
-) -> Result<(), CombinedError<<AudioIn as AudioReader<S>>::Err, <AudioOut as AudioWriter<S>>::Err>>
+) -> RunResult<S, AudioIn, AudioOut>
 where
     AudioIn: AudioReader<S>,
     AudioOut: AudioWriter<S>,
     MidiIn: Iterator<Item = DeltaEvent<RawMidiEvent>>,
     MidiOut: MidiWriter,
     S: Zero,
-    R: ContextualAudioRenderer<S, MidiWriterWrapper<MidiOut>> + EventHandler<Timed<RawMidiEvent>>,
+    R: ContextualAudioRenderer<S, EventSinkContext<MidiWriterSink<MidiOut>>>
+        + EventHandler<Timed<RawMidiEvent>>
+        + AudioHandler,
 {
-    assert!(buffer_size_in_frames > 0);
-    assert!(buffer_size_in_frames < u32::max_value() as usize);
+    run_with_events(
+        plugin,
+        buffer_size_in_frames,
+        audio_in,
+        audio_out,
+        DeltaEventSource::new(midi_in),
+        MidiWriterSink::new(midi_out),
+    )
+}
+
+/// Run an audio renderer with the given audio input, audio output, event source and event sink.
+///
+/// This is similar to the [`run`] function, but the events are read from an [`EventSource`]
+/// and the events that the plugin emits while rendering are written to an [`EventSink`],
+/// see the [`events`] module for the timing of these events.
+///
+/// Parameters
+/// ==========
+/// * `buffer_size_in_frames`: the buffer size in frames. It is passed to the plugin with
+///   `set_max_buffer_size` before the first buffer is rendered.
+///
+/// Panics
+/// ======
+/// Panics if `buffer_size_in_frames` is `0` or `> u32::max_value()`.
+///
+/// [`run`]: ./fn.run.html
+/// [`EventSource`]: ./events/trait.EventSource.html
+/// [`EventSink`]: ./events/trait.EventSink.html
+/// [`events`]: ./events/index.html
+pub fn run_with_events<S, AudioIn, AudioOut, Source, Sink, E, R>(
+    plugin: &mut R,
+    buffer_size_in_frames: usize,
+    audio_in: AudioIn,
+    audio_out: AudioOut,
+    event_source: Source,
+    event_sink: Sink,
+) -> RunResult<S, AudioIn, AudioOut>
+where
+    AudioIn: AudioReader<S>,
+    AudioOut: AudioWriter<S>,
+    Source: EventSource<E>,
+    Sink: EventSink<E>,
+    S: Zero,
+    R: ContextualAudioRenderer<S, EventSinkContext<Sink>> + EventHandler<Timed<E>> + AudioHandler,
+{
+    run_with_events_and_tail(
+        plugin,
+        buffer_size_in_frames,
+        audio_in,
+        audio_out,
+        event_source,
+        event_sink,
+        Settings {
+            tail: None::<(RenderUntilSilence, fn(&S) -> bool)>,
+            progress: None,
+            real_time: false,
+        },
+    )
+}
 
-    let number_of_channels = audio_in.number_of_channels();
-    // TODO: Do not panic in this case.
-    assert!(number_of_channels > 0);
+/// When to stop rendering the tail of the plugin (e.g. reverb or the release of a note),
+/// see [`run_with_events_until_silence`].
+///
+/// [`run_with_events_until_silence`]: ./fn.run_with_events_until_silence.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderUntilSilence {
+    /// Samples with an absolute value below this threshold are considered to be silent.
+    pub threshold: f64,
+    /// Stop when all output channels have been silent for this number of consecutive frames.
+    pub number_of_silent_frames: usize,
+    /// Stop after rendering this number of frames after the end of the audio input, even if
+    /// the output is not silent yet, e.g. because the plugin oscillates.
+    pub max_tail_in_frames: u64,
+}
 
-    let frames_per_second = audio_in.frames_per_second();
-    assert!(frames_per_second > 0);
+impl Default for RenderUntilSilence {
+    /// A threshold of -80 dB (relative to full scale for floating point samples),
+    /// 4096 silent frames and a maximum tail of 10 seconds at 48000 frames per second.
+    fn default() -> Self {
+        RenderUntilSilence {
+            threshold: 0.0001,
+            number_of_silent_frames: 4096,
+            max_tail_in_frames: 480_000,
+        }
+    }
+}
 
-    let mut input_buffers = AudioChunk::zero(number_of_channels, buffer_size_in_frames).inner();
-    let mut output_buffers = AudioChunk::zero(number_of_channels, buffer_size_in_frames).inner();
+/// Run an audio renderer with the given audio input, audio output, event source and event sink
+/// and continue rendering after the audio input has ended, until the output is silent.
+///
+/// This is similar to the [`run_with_events`] function, but when the audio input has ended,
+/// the plugin keeps rendering buffers with silent input, so that e.g. reverb tails and the
+/// release of notes are not truncated. Rendering stops when the event source has no more
+/// events (see [`EventSource::is_exhausted`]) and the output has been below the threshold for
+/// the given number of frames, or when the maximum length of the tail has been rendered.
+/// The tail is rendered in complete buffers.
+///
+/// Panics
+/// ======
+/// Panics if `buffer_size_in_frames` is `0` or `> u32::max_value()`.
+///
+/// [`run_with_events`]: ./fn.run_with_events.html
+/// [`EventSource::is_exhausted`]: ./events/trait.EventSource.html#method.is_exhausted
+pub fn run_with_events_until_silence<S, AudioIn, AudioOut, Source, Sink, E, R>(
+    plugin: &mut R,
+    buffer_size_in_frames: usize,
+    audio_in: AudioIn,
+    audio_out: AudioOut,
+    event_source: Source,
+    event_sink: Sink,
+    until_silence: RenderUntilSilence,
+) -> RunResult<S, AudioIn, AudioOut>
+where
+    AudioIn: AudioReader<S>,
+    AudioOut: AudioWriter<S>,
+    Source: EventSource<E>,
+    Sink: EventSink<E>,
+    S: Zero + AsPrim,
+    R: ContextualAudioRenderer<S, EventSinkContext<Sink>> + EventHandler<Timed<E>> + AudioHandler,
+{
+    run_with_options(
+        plugin,
+        buffer_size_in_frames,
+        audio_in,
+        audio_out,
+        event_source,
+        event_sink,
+        &RenderOptions {
+            until_silence: Some(until_silence),
+            ..RenderOptions::default()
+        },
+    )
+}
 
-    let mut last_time_in_frames = 0;
-    let mut last_event_time_in_microseconds = 0;
+/// Options for the [`run_with_options`] function.
+///
+/// [`run_with_options`]: ./fn.run_with_options.html
+#[derive(Clone, Debug, Default)]
+pub struct RenderOptions {
+    /// Continue rendering after the audio input has ended, until the output is silent.
+    /// See [`run_with_events_until_silence`].
+    ///
+    /// [`run_with_events_until_silence`]: ./fn.run_with_events_until_silence.html
+    pub until_silence: Option<RenderUntilSilence>,
+    /// Report the progress of rendering to this handle and stop rendering when
+    /// it is cancelled. See the [`progress`] module.
+    ///
+    /// [`progress`]: ./progress/index.html
+    pub progress: Option<RenderProgress>,
+    /// Render at the pace of a wall clock: wait after every buffer until the time of the
+    /// rendered audio has passed, so that the output can be listened to, e.g. with a
+    /// [`CpalAudioWriter`]. When this is `false`, rendering is as fast as possible.
+    ///
+    /// [`CpalAudioWriter`]: ./cpal/struct.CpalAudioWriter.html
+    pub real_time: bool,
+}
 
-    let frames_per_second = audio_in.frames_per_second();
+/// Run an audio renderer with the given audio input, audio output, event source and event sink,
+/// with the given options.
+///
+/// This is similar to the [`run_with_events`] function. See [`RenderOptions`] for the options.
+///
+/// When the [`RenderProgress`] of the options is cancelled, rendering stops before the next
+/// buffer and `Err(CombinedError::Cancelled)` is returned.
+///
+/// Panics
+/// ======
+/// Panics if `buffer_size_in_frames` is `0` or `> u32::max_value()`.
+///
+/// [`run_with_events`]: ./fn.run_with_events.html
+/// [`RenderOptions`]: ./struct.RenderOptions.html
+/// [`RenderProgress`]: ./progress/struct.RenderProgress.html
+pub fn run_with_options<S, AudioIn, AudioOut, Source, Sink, E, R>(
+    plugin: &mut R,
+    buffer_size_in_frames: usize,
+    audio_in: AudioIn,
+    audio_out: AudioOut,
+    event_source: Source,
+    event_sink: Sink,
+    options: &RenderOptions,
+) -> RunResult<S, AudioIn, AudioOut>
+where
+    AudioIn: AudioReader<S>,
+    AudioOut: AudioWriter<S>,
+    Source: EventSource<E>,
+    Sink: EventSink<E>,
+    S: Zero + AsPrim,
+    R: ContextualAudioRenderer<S, EventSinkContext<Sink>> + EventHandler<Timed<E>> + AudioHandler,
+{
+    let tail = options.until_silence.map(|until_silence| {
+        let threshold = until_silence.threshold;
+        (until_silence, move |sample: &S| {
+            sample.as_::<f64>().abs() < threshold
+        })
+    });
+    run_with_events_and_tail(
+        plugin,
+        buffer_size_in_frames,
+        audio_in,
+        audio_out,
+        event_source,
+        event_sink,
+        Settings {
+            tail,
+            progress: options.progress.as_ref(),
+            real_time: options.real_time,
+        },
+    )
+}
+
+struct Settings<'a, F> {
+    // When to stop rendering after the audio input has ended and a function that tells if a
+    // sample is silent. When this is `None`, rendering stops when the audio input ends.
+    tail: Option<(RenderUntilSilence, F)>,
+    progress: Option<&'a RenderProgress>,
+    real_time: bool,
+}
+
+fn run_with_events_and_tail<S, AudioIn, AudioOut, Source, Sink, E, R, F>(
+    plugin: &mut R,
+    buffer_size_in_frames: usize,
+    mut audio_in: AudioIn,
+    mut audio_out: AudioOut,
+    mut event_source: Source,
+    event_sink: Sink,
+    settings: Settings<F>,
+) -> RunResult<S, AudioIn, AudioOut>
+where
+    AudioIn: AudioReader<S>,
+    AudioOut: AudioWriter<S>,
+    Source: EventSource<E>,
+    Sink: EventSink<E>,
+    S: Zero,
+    R: ContextualAudioRenderer<S, EventSinkContext<Sink>> + EventHandler<Timed<E>> + AudioHandler,
+    F: Fn(&S) -> bool,
+{
+    assert!(buffer_size_in_frames > 0);
+    assert!(buffer_size_in_frames < u32::max_value() as usize);
 
-    let mut writer = MidiWriterWrapper::new(
-        midi_out,
-        MICROSECONDS_PER_SECOND as f64 / frames_per_second as f64,
-    );
+    let number_of_input_channels = audio_in.number_of_channels();
+    let number_of_output_channels = audio_out
+        .number_of_channels()
+        .unwrap_or(number_of_input_channels);
+
+    let mut timing = BufferTiming {
+        start_in_frames: 0,
+        start_in_microseconds: 0,
+        number_of_frames: buffer_size_in_frames,
+        frames_per_second: audio_in.frames_per_second(),
+    };
+    assert!(timing.frames_per_second > 0);
+    plugin.set_sample_rate(timing.frames_per_second as f64);
+    plugin.set_max_buffer_size(buffer_size_in_frames);
+
+    let mut input_buffers =
+        AudioChunk::zero(number_of_input_channels, buffer_size_in_frames).inner();
+    let mut output_buffers =
+        AudioChunk::zero(number_of_output_channels, buffer_size_in_frames).inner();
+
+    // The time of the last sample rate change, both in frames and in microseconds.
+    let mut offset_in_frames = 0;
+    let mut offset_in_microseconds = 0;
+
+    let mut context = EventSinkContext::new(event_sink, timing);
+
+    let Settings {
+        tail,
+        progress,
+        real_time,
+    } = settings;
+    if let Some(progress) = progress {
+        progress.start(audio_in.duration_in_frames());
+    }
+    let start_instant = Instant::now();
 
-    let mut peekable_midi_reader = midi_in.peekable();
+    let mut input_has_ended = false;
+    let mut tail_in_frames = 0;
+    let mut silent_frames = 0;
 
     loop {
-        // Read audio.
-        let frames_read = match audio_in.fill_buffer(&mut buffers_as_mut_slice(
-            &mut input_buffers,
-            buffer_size_in_frames,
-        )) {
-            Ok(f) => f,
-            Err(e) => {
-                return Err(CombinedError::AudioInError(e));
+        if progress.map(RenderProgress::is_cancelled).unwrap_or(false) {
+            return Err(CombinedError::Cancelled);
+        }
+
+        let frames_read = if input_has_ended {
+            0
+        } else {
+            match read_audio(
+                &mut audio_in,
+                &mut input_buffers,
+                buffer_size_in_frames,
+                timing.start_in_frames,
+            ) {
+                Ok(f) => f,
+                Err(e) => {
+                    return Err(CombinedError::AudioInError(e));
+                }
             }
         };
         assert!(frames_read <= buffer_size_in_frames);
-        if frames_read == 0 {
+        let number_of_frames = if frames_read < buffer_size_in_frames && tail.is_some() {
+            if !input_has_ended {
+                // Render the rest of this buffer and the next buffers with silent input.
+                for channel in input_buffers.iter_mut() {
+                    for sample in channel[frames_read..].iter_mut() {
+                        *sample = S::zero();
+                    }
+                }
+                input_has_ended = true;
+            }
+            buffer_size_in_frames
+        } else {
+            frames_read
+        };
+        if number_of_frames == 0 {
             break;
         }
 
-        // Handle events
-        if let Some(event) = peekable_midi_reader.peek() {
-            let time_in_frames = (last_event_time_in_microseconds
-                + event.microseconds_since_previous_event)
-                * frames_per_second
-                / MICROSECONDS_PER_SECOND
-                - last_time_in_frames;
-            if time_in_frames < buffer_size_in_frames as u64 {
-                let event = peekable_midi_reader
-                    .next()
-                    .expect("to see event that I just peeked at");
-                plugin.handle_event(Timed {
-                    time_in_frames: time_in_frames as u32,
-                    event: event.event,
-                });
-                last_event_time_in_microseconds += event.microseconds_since_previous_event;
-            }
+        let frames_per_second = audio_in.frames_per_second();
+        if frames_per_second != timing.frames_per_second {
+            assert!(frames_per_second > 0);
+            offset_in_microseconds = timing.start_in_microseconds;
+            offset_in_frames = timing.start_in_frames;
+            timing.frames_per_second = frames_per_second;
+            plugin.set_sample_rate(frames_per_second as f64);
         }
+        timing.number_of_frames = number_of_frames;
+
+        event_source.read_events(&timing, plugin);
 
+        context.set_timing(timing);
         plugin.render_buffer(
-            &buffers_as_slice(&input_buffers, frames_read),
-            &mut buffers_as_mut_slice(&mut output_buffers, frames_read),
-            &mut writer,
+            &buffers_as_slice(&input_buffers, number_of_frames),
+            &mut buffers_as_mut_slice(&mut output_buffers, number_of_frames),
+            &mut context,
         );
 
-        if let Err(e) = audio_out.write_buffer(&buffers_as_slice(&output_buffers, frames_read)) {
+        if let Err(e) = audio_out.write_buffer(&buffers_as_slice(&output_buffers, number_of_frames))
+        {
             return Err(CombinedError::AudioOutError(e));
         }
+        if let Some(progress) = progress {
+            progress.add_frames(number_of_frames as u64);
+        }
+        if real_time {
+            let end_of_buffer =
+                Duration::from_micros(timing.time_in_microseconds(number_of_frames as u32));
+            if let Some(wait) = end_of_buffer.checked_sub(start_instant.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
 
-        writer.step_frames(frames_read as u64);
-
-        if frames_read < buffer_size_in_frames {
-            break;
+        match &tail {
+            Some((until_silence, is_silent)) => {
+                if input_has_ended {
+                    tail_in_frames += (number_of_frames - frames_read) as u64;
+                    for frame in frames_read..number_of_frames {
+                        if output_buffers
+                            .iter()
+                            .all(|channel| is_silent(&channel[frame]))
+                        {
+                            silent_frames += 1;
+                        } else {
+                            silent_frames = 0;
+                        }
+                    }
+                    let is_silent_now = silent_frames >= until_silence.number_of_silent_frames
+                        && event_source.is_exhausted();
+                    if is_silent_now || tail_in_frames >= until_silence.max_tail_in_frames {
+                        break;
+                    }
+                }
+            }
+            None => {
+                if frames_read < buffer_size_in_frames {
+                    break;
+                }
+            }
         }
 
-        last_time_in_frames += buffer_size_in_frames as u64;
+        timing.start_in_frames += number_of_frames as u64;
+        timing.start_in_microseconds = offset_in_microseconds
+            + (timing.start_in_frames - offset_in_frames) * MICROSECONDS_PER_SECOND
+                / timing.frames_per_second;
     }
     Ok(())
 }
 
+// Read the next buffer of audio and return the number of frames that have been read.
+// An audio input without channels has no buffers to fill, so the number of frames is
+// derived from its duration instead.
+fn read_audio<S, AudioIn>(
+    audio_in: &mut AudioIn,
+    input_buffers: &mut [Vec<S>],
+    buffer_size_in_frames: usize,
+    frames_read_so_far: u64,
+) -> Result<usize, AudioIn::Err>
+where
+    AudioIn: AudioReader<S>,
+{
+    if input_buffers.is_empty() {
+        let remaining_frames = audio_in
+            .duration_in_frames()
+            .unwrap_or(0)
+            .saturating_sub(frames_read_so_far);
+        return Ok(remaining_frames.min(buffer_size_in_frames as u64) as usize);
+    }
+    audio_in.fill_buffer(&mut buffers_as_mut_slice(
+        input_buffers,
+        buffer_size_in_frames,
+    ))
+}
+
 pub struct TestAudioReader<'b, S>
 where
     S: Copy,
@@ -301,6 +848,18 @@ where
         self.number_of_calls_to_fill_buffer += 1;
         self.inner.fill_buffer(output)
     }
+
+    fn duration_in_frames(&self) -> Option<u64> {
+        self.inner.duration_in_frames()
+    }
+
+    fn seek(&mut self, frame: u64) -> Result<bool, Self::Err> {
+        self.inner.seek(frame)
+    }
+
+    fn metadata(&self) -> AudioMetadata {
+        self.inner.metadata()
+    }
 }
 
 pub struct TestAudioWriter<'w, T, S>
@@ -340,6 +899,18 @@ where
         self.chunk_index += 1;
         Ok(())
     }
+
+    fn number_of_channels(&self) -> Option<usize> {
+        self.inner.number_of_channels()
+    }
+
+    fn frames_per_second(&self) -> Option<u64> {
+        self.inner.frames_per_second()
+    }
+
+    fn write_metadata(&mut self, metadata: &AudioMetadata) -> Result<bool, Self::Err> {
+        self.inner.write_metadata(metadata)
+    }
 }
 
 pub struct TestMidiReader {
@@ -406,17 +977,20 @@ impl MidiWriter for TestMidiWriter {
 mod tests {
     mod run {
         use super::super::{
-            dummy::MidiDummy,
+            dummy::{AudioDummy, MidiDummy},
             memory::{AudioBufferReader, AudioBufferWriter},
-            DeltaEvent, TestAudioReader, TestAudioWriter,
+            AudioReader, CombinedError, DeltaEvent, RenderOptions, RenderUntilSilence,
+            TestAudioReader, TestAudioWriter,
         };
+        use crate::backend::combined::events::{DeltaEventSource, MidiWriterSink};
+        use crate::backend::combined::progress::RenderProgress;
         use crate::backend::combined::{TestMidiReader, TestMidiWriter};
         use crate::buffer::AudioChunk;
-        use crate::event::{RawMidiEvent, Timed};
+        use crate::event::{EventHandler, RawMidiEvent, Timed};
         use crate::test_utilities::TestPlugin;
-        use crate::{AudioHandler, AudioHandlerMeta};
+        use crate::{AudioHandler, AudioHandlerMeta, ContextualAudioRenderer};
 
-        struct DummyMeta;
+        struct DummyMeta(f64);
 
         const EXPECTED_SAMPLE_RATE: f64 = 1234.0;
         impl AudioHandlerMeta for DummyMeta {
@@ -430,7 +1004,7 @@ mod tests {
 
         impl AudioHandler for DummyMeta {
             fn set_sample_rate(&mut self, sample_rate: f64) {
-                assert_eq!(sample_rate, EXPECTED_SAMPLE_RATE);
+                assert_eq!(sample_rate, self.0);
             }
         }
 
@@ -444,7 +1018,7 @@ mod tests {
 
             // So 1 frame  is 1/8000 seconds,
             //    8 frames is 1/1000 seconds = 1ms = 1000 microsecond.
-            let event = RawMidiEvent::new(&[1, 2, 3]);
+            let event = RawMidiEvent::new(&[0x90, 2, 3]);
             // Event is expected at frame 8:
             // 0 1 2 3 4 5 6 7 8        (in 1000 microseconds)
             // . . .|. . .|. . E|. . .|. . .|.
@@ -465,7 +1039,7 @@ mod tests {
                     vec![],
                 ],
                 vec![Vec::new(); 6],
-                DummyMeta,
+                DummyMeta(SAMPLE_RATE as f64),
             );
             let mut output_buffer = AudioChunk::new(NUMBER_OF_CHANNELS);
             super::super::run(
@@ -507,7 +1081,7 @@ mod tests {
                 output_data.clone().split(buffer_size),
                 vec![vec![], vec![], vec![], vec![]],
                 vec![Vec::new(); 4],
-                DummyMeta,
+                DummyMeta(EXPECTED_SAMPLE_RATE),
             );
             let mut output_buffer = AudioChunk::new(2);
             super::super::run(
@@ -539,7 +1113,7 @@ mod tests {
 
             // So 1 frame  is 1/8000 seconds,
             //    8 frames is 1/1000 seconds = 1ms = 1000 microsecond.
-            let event = RawMidiEvent::new(&[1, 2, 3]);
+            let event = RawMidiEvent::new(&[0x90, 2, 3]);
             let input_event = DeltaEvent {
                 microseconds_since_previous_event: 1000,
                 event,
@@ -560,7 +1134,7 @@ mod tests {
                     vec![],
                     vec![],
                 ],
-                DummyMeta,
+                DummyMeta(SAMPLE_RATE as f64),
             );
             let mut output_buffer = AudioChunk::new(NUMBER_OF_CHANNELS);
             super::super::run(
@@ -584,7 +1158,8 @@ mod tests {
                 ),
                 MidiDummy::new(),
                 TestMidiWriter::new(vec![input_event]),
-            );
+            )
+            .expect("Unexpected error.");
         }
 
         #[test]
@@ -598,12 +1173,12 @@ mod tests {
             // So 1 frame  is 1/8000 seconds,
             //    2 frames is 1/4000 seconds = 0.24 ms = 250 microsecond
             //    8 frames is 1/1000 seconds = 1ms = 1000 microsecond.
-            let event1 = RawMidiEvent::new(&[1, 2, 3]);
+            let event1 = RawMidiEvent::new(&[0x90, 2, 3]);
             let output_event1 = DeltaEvent {
                 microseconds_since_previous_event: 1000,
                 event: event1,
             };
-            let event2 = RawMidiEvent::new(&[4, 5, 6]);
+            let event2 = RawMidiEvent::new(&[0x80, 5, 6]);
             let output_event2 = DeltaEvent {
                 microseconds_since_previous_event: 250,
                 event: event2,
@@ -621,7 +1196,7 @@ mod tests {
                     vec![],
                     vec![],
                 ],
-                DummyMeta,
+                DummyMeta(SAMPLE_RATE as f64),
             );
             let mut output_buffer = AudioChunk::new(NUMBER_OF_CHANNELS);
             super::super::run(
@@ -657,12 +1232,12 @@ mod tests {
             let input_data = AudioChunk::<i16>::zero(1, 16);
             let output_data = AudioChunk::<i16>::zero(1, 16);
 
-            let event1 = RawMidiEvent::new(&[1, 2, 3]);
+            let event1 = RawMidiEvent::new(&[0x90, 2, 3]);
             let output_event1 = DeltaEvent {
                 microseconds_since_previous_event: 750,
                 event: event1,
             };
-            let event2 = RawMidiEvent::new(&[4, 5, 6]);
+            let event2 = RawMidiEvent::new(&[0x80, 5, 6]);
             let output_event2 = DeltaEvent {
                 microseconds_since_previous_event: 250,
                 event: event2,
@@ -680,7 +1255,7 @@ mod tests {
                     vec![],
                     vec![],
                 ],
-                DummyMeta,
+                DummyMeta(SAMPLE_RATE as f64),
             );
             let mut output_buffer = AudioChunk::new(NUMBER_OF_CHANNELS);
             super::super::run(
@@ -707,5 +1282,359 @@ mod tests {
             )
             .expect("Unexpected error.");
         }
+
+        // Reads silence and changes the sample rate after the given number of buffers.
+        struct SampleRateChangingReader {
+            sample_rates: Vec<u64>,
+            buffer_index: usize,
+        }
+
+        impl AudioReader<i16> for SampleRateChangingReader {
+            type Err = ();
+
+            fn number_of_channels(&self) -> usize {
+                1
+            }
+
+            fn frames_per_second(&self) -> u64 {
+                self.sample_rates[self.buffer_index.saturating_sub(1)]
+            }
+
+            fn fill_buffer(&mut self, output: &mut [&mut [i16]]) -> Result<usize, ()> {
+                if self.buffer_index == self.sample_rates.len() {
+                    return Ok(0);
+                }
+                self.buffer_index += 1;
+                Ok(output[0].len())
+            }
+        }
+
+        // Records the calls to `set_sample_rate` and `set_max_buffer_size` and the events,
+        // together with the index of the buffer in which they occur.
+        #[derive(Default)]
+        struct SampleRateRecorder {
+            buffer_index: usize,
+            sample_rates: Vec<(usize, f64)>,
+            max_buffer_sizes: Vec<(usize, usize)>,
+            events: Vec<(usize, Timed<RawMidiEvent>)>,
+        }
+
+        impl AudioHandler for SampleRateRecorder {
+            fn set_sample_rate(&mut self, sample_rate: f64) {
+                self.sample_rates.push((self.buffer_index, sample_rate));
+            }
+
+            fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+                self.max_buffer_sizes
+                    .push((self.buffer_index, max_buffer_size));
+            }
+        }
+
+        impl EventHandler<Timed<RawMidiEvent>> for SampleRateRecorder {
+            fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+                self.events.push((self.buffer_index, event));
+            }
+        }
+
+        impl<C> ContextualAudioRenderer<i16, C> for SampleRateRecorder
+        where
+            C: EventHandler<Timed<RawMidiEvent>>,
+        {
+            fn render_buffer(
+                &mut self,
+                _inputs: &[&[i16]],
+                _outputs: &mut [&mut [i16]],
+                context: &mut C,
+            ) {
+                if self.buffer_index == 4 {
+                    context.handle_event(Timed::new(2, RawMidiEvent::new(&[0x80, 5, 6])));
+                }
+                self.buffer_index += 1;
+            }
+        }
+
+        #[test]
+        fn propagates_sample_rate_changes() {
+            const BUFFER_SIZE: usize = 4;
+            // Buffer 0 and 1 are at 8000 frames per second: 4 frames is 500 microseconds.
+            // Buffer 2, 3 and 4 are at 16000 frames per second: 4 frames is 250 microseconds.
+            // So buffer 4 starts at 1500 microseconds.
+            let reader = SampleRateChangingReader {
+                sample_rates: vec![8000, 8000, 16000, 16000, 16000],
+                buffer_index: 0,
+            };
+            // 125 microseconds after the start of buffer 4 is 2 frames at 16000 frames per second.
+            let event = RawMidiEvent::new(&[0x90, 2, 3]);
+            let input_event = DeltaEvent {
+                microseconds_since_previous_event: 1625,
+                event,
+            };
+            let output_event = DeltaEvent {
+                microseconds_since_previous_event: 1625,
+                event: RawMidiEvent::new(&[0x80, 5, 6]),
+            };
+            let mut output_buffer = AudioChunk::new(1);
+            let mut plugin = SampleRateRecorder::default();
+            super::super::run(
+                &mut plugin,
+                BUFFER_SIZE,
+                reader,
+                AudioBufferWriter::new(&mut output_buffer),
+                TestMidiReader::new(vec![input_event]),
+                TestMidiWriter::new(vec![output_event]),
+            )
+            .expect("Unexpected error.");
+            assert_eq!(plugin.sample_rates, vec![(0, 8000.0), (2, 16000.0)]);
+            assert_eq!(plugin.max_buffer_sizes, vec![(0, BUFFER_SIZE)]);
+            assert_eq!(plugin.events, vec![(4, Timed::new(2, event))]);
+        }
+
+        #[test]
+        fn run_with_events_propagates_sample_rate_changes() {
+            const BUFFER_SIZE: usize = 4;
+            let reader = SampleRateChangingReader {
+                sample_rates: vec![8000, 8000, 16000, 16000, 16000],
+                buffer_index: 0,
+            };
+            let event = RawMidiEvent::new(&[0x90, 2, 3]);
+            let input_event = DeltaEvent {
+                microseconds_since_previous_event: 1625,
+                event,
+            };
+            let output_event = DeltaEvent {
+                microseconds_since_previous_event: 1625,
+                event: RawMidiEvent::new(&[0x80, 5, 6]),
+            };
+            let mut output_buffer = AudioChunk::new(1);
+            let mut plugin = SampleRateRecorder::default();
+            let mut sink = MidiWriterSink::new(TestMidiWriter::new(vec![output_event]));
+            super::super::run_with_events(
+                &mut plugin,
+                BUFFER_SIZE,
+                reader,
+                AudioBufferWriter::new(&mut output_buffer),
+                DeltaEventSource::new(TestMidiReader::new(vec![input_event])),
+                &mut sink,
+            )
+            .expect("Unexpected error.");
+            sink.inner().check_last();
+            assert_eq!(plugin.sample_rates, vec![(0, 8000.0), (2, 16000.0)]);
+            assert_eq!(plugin.events, vec![(4, Timed::new(2, event))]);
+        }
+
+        #[test]
+        fn run_with_events_reads_and_writes_vecs() {
+            const BUFFER_SIZE: usize = 4;
+            let reader = SampleRateChangingReader {
+                sample_rates: vec![8000; 3],
+                buffer_index: 0,
+            };
+            let event = RawMidiEvent::new(&[0x90, 2, 3]);
+            let mut output_buffer = AudioChunk::new(1);
+            let mut plugin = SampleRateRecorder::default();
+            let mut written_events = Vec::new();
+            super::super::run_with_events(
+                &mut plugin,
+                BUFFER_SIZE,
+                reader,
+                AudioBufferWriter::new(&mut output_buffer),
+                vec![Timed::new(1, event), Timed::new(9, event)],
+                &mut written_events,
+            )
+            .expect("Unexpected error.");
+            assert_eq!(
+                plugin.events,
+                vec![(0, Timed::new(1, event)), (2, Timed::new(1, event))]
+            );
+            // The plugin emits an event at frame 2 of buffer 4, but there are only 3 buffers.
+            assert!(written_events.is_empty());
+        }
+
+        // Outputs `level` and halves it after every frame.
+        struct Decay {
+            level: i16,
+        }
+
+        impl AudioHandler for Decay {
+            fn set_sample_rate(&mut self, _sample_rate: f64) {}
+        }
+
+        impl EventHandler<Timed<RawMidiEvent>> for Decay {
+            fn handle_event(&mut self, _event: Timed<RawMidiEvent>) {}
+        }
+
+        impl<C> ContextualAudioRenderer<i16, C> for Decay {
+            fn render_buffer(&mut self, _inputs: &[&[i16]], outputs: &mut [&mut [i16]], _: &mut C) {
+                for sample in outputs[0].iter_mut() {
+                    *sample = self.level;
+                    self.level /= 2;
+                }
+            }
+        }
+
+        #[test]
+        fn run_with_events_until_silence_renders_the_tail() {
+            let input_data = AudioChunk::<i16>::zero(1, 4);
+            let mut output_buffer = AudioChunk::new(1);
+            let mut plugin = Decay { level: 64 };
+            let mut written_events = Vec::<Timed<RawMidiEvent>>::new();
+            super::super::run_with_events_until_silence(
+                &mut plugin,
+                4,
+                AudioBufferReader::new(&input_data, 8000),
+                AudioBufferWriter::new(&mut output_buffer),
+                Vec::new(),
+                &mut written_events,
+                RenderUntilSilence {
+                    threshold: 1.0,
+                    number_of_silent_frames: 4,
+                    max_tail_in_frames: 100,
+                },
+            )
+            .expect("Unexpected error.");
+            assert_eq!(
+                output_buffer,
+                audio_chunk![[64, 32, 16, 8, 4, 2, 1, 0, 0, 0, 0, 0]]
+            );
+        }
+
+        #[test]
+        fn run_with_events_until_silence_stops_after_the_maximum_tail() {
+            let input_data = AudioChunk::<i16>::zero(1, 6);
+            let mut output_buffer = AudioChunk::new(1);
+            let mut plugin = Decay { level: i16::MAX };
+            let mut written_events = Vec::<Timed<RawMidiEvent>>::new();
+            super::super::run_with_events_until_silence(
+                &mut plugin,
+                4,
+                AudioBufferReader::new(&input_data, 8000),
+                AudioBufferWriter::new(&mut output_buffer),
+                Vec::new(),
+                &mut written_events,
+                RenderUntilSilence {
+                    threshold: 0.5,
+                    number_of_silent_frames: 100,
+                    max_tail_in_frames: 2,
+                },
+            )
+            .expect("Unexpected error.");
+            // The tail is rendered in complete buffers.
+            assert_eq!(output_buffer.channels()[0].len(), 8);
+        }
+
+        #[test]
+        fn run_with_options_reports_progress() {
+            let input_data = AudioChunk::<i16>::zero(1, 10);
+            let mut output_buffer = AudioChunk::new(1);
+            let mut plugin = Decay { level: 0 };
+            let mut written_events = Vec::<Timed<RawMidiEvent>>::new();
+            let progress = RenderProgress::new();
+            super::super::run_with_options(
+                &mut plugin,
+                4,
+                AudioBufferReader::new(&input_data, 8000),
+                AudioBufferWriter::new(&mut output_buffer),
+                Vec::new(),
+                &mut written_events,
+                &RenderOptions {
+                    progress: Some(progress.clone()),
+                    ..RenderOptions::default()
+                },
+            )
+            .expect("Unexpected error.");
+            assert_eq!(progress.frames_rendered(), 10);
+            assert_eq!(progress.total_frames(), Some(10));
+            assert_eq!(progress.fraction(), Some(1.0));
+        }
+
+        #[test]
+        fn run_with_options_stops_when_cancelled() {
+            let input_data = AudioChunk::<i16>::zero(1, 10);
+            let mut output_buffer = AudioChunk::new(1);
+            let mut plugin = Decay { level: 0 };
+            let mut written_events = Vec::<Timed<RawMidiEvent>>::new();
+            let progress = RenderProgress::new();
+            progress.clone().cancel();
+            let result = super::super::run_with_options(
+                &mut plugin,
+                4,
+                AudioBufferReader::new(&input_data, 8000),
+                AudioBufferWriter::new(&mut output_buffer),
+                Vec::new(),
+                &mut written_events,
+                &RenderOptions {
+                    progress: Some(progress.clone()),
+                    ..RenderOptions::default()
+                },
+            );
+            assert!(matches!(result, Err(CombinedError::Cancelled)));
+            assert_eq!(progress.frames_rendered(), 0);
+        }
+
+        #[test]
+        fn run_with_options_renders_in_real_time() {
+            // 40 frames at 8000 frames per second is 5 milliseconds.
+            let input_data = AudioChunk::<i16>::zero(1, 40);
+            let mut output_buffer = AudioChunk::new(1);
+            let mut plugin = Decay { level: 0 };
+            let mut written_events = Vec::<Timed<RawMidiEvent>>::new();
+            let start = std::time::Instant::now();
+            super::super::run_with_options(
+                &mut plugin,
+                8,
+                AudioBufferReader::new(&input_data, 8000),
+                AudioBufferWriter::new(&mut output_buffer),
+                Vec::new(),
+                &mut written_events,
+                &RenderOptions {
+                    real_time: true,
+                    ..RenderOptions::default()
+                },
+            )
+            .expect("Unexpected error.");
+            assert!(start.elapsed() >= std::time::Duration::from_millis(5));
+        }
+
+        #[test]
+        fn run_with_events_renders_without_audio_input() {
+            let mut output_buffer = AudioChunk::new(1);
+            let mut plugin = Decay { level: 64 };
+            let mut written_events = Vec::<Timed<RawMidiEvent>>::new();
+            super::super::run_with_events(
+                &mut plugin,
+                4,
+                AudioDummy::with_duration(8000, 6),
+                AudioBufferWriter::new(&mut output_buffer),
+                Vec::new(),
+                &mut written_events,
+            )
+            .expect("Unexpected error.");
+            assert_eq!(output_buffer.channels(), &vec![vec![64, 32, 16, 8, 4, 2]]);
+        }
+
+        #[test]
+        fn run_with_events_renders_without_audio_input_and_output() {
+            let event = RawMidiEvent::new(&[0x90, 2, 3]);
+            let mut plugin = SampleRateRecorder::default();
+            let mut written_events = Vec::new();
+            super::super::run_with_events(
+                &mut plugin,
+                4,
+                AudioDummy::with_duration(8000, 20),
+                AudioDummy::new(),
+                vec![Timed::new(1, event), Timed::new(9, event)],
+                &mut written_events,
+            )
+            .expect("Unexpected error.");
+            assert_eq!(plugin.sample_rates, vec![(0, 8000.0)]);
+            assert_eq!(
+                plugin.events,
+                vec![(0, Timed::new(1, event)), (2, Timed::new(1, event))]
+            );
+            assert_eq!(
+                written_events,
+                vec![Timed::new(18, RawMidiEvent::new(&[0x80, 5, 6]))]
+            );
+        }
     }
 }
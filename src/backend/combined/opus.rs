@@ -0,0 +1,392 @@
+//! Write audio to `.opus` files (Opus in an Ogg container) with [`opus`] and [`ogg`].
+//!
+//! Support is only enabled if `rsynth` is compiled with the "backend-file-opus" feature.
+//! Opus is a lossy format, so this is mainly useful for previews of long renders, where a
+//! `.wav` file would be too large.
+//!
+//! ```no_run
+//! use rsynth::backend::combined::opus::OpusAudioWriter;
+//! use std::fs::File;
+//!
+//! let file = File::create("preview.opus").unwrap();
+//! // Stereo at 48000 frames per second, encoded at 128 kbit/s.
+//! let mut writer = OpusAudioWriter::<_, f32>::new(file, 2, 48000, 128_000).unwrap();
+//! // Use `writer` as the audio output, e.g. with `rsynth::backend::combined::run`.
+//! // ...
+//! // The end of the stream is only written when `finish` is called.
+//! writer.finish().unwrap();
+//! ```
+//!
+//! [`opus`]: https://crates.io/crates/opus
+//! [`ogg`]: https://crates.io/crates/ogg
+use super::AudioWriter;
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use opus::{Application, Bitrate, Channels, Encoder};
+use sample::conv::ToSample;
+use std::fmt::{Display, Formatter};
+use std::io::{self, Write};
+use std::marker::PhantomData;
+
+// The serial number of the (only) logical stream in the file.
+const SERIAL: u32 = 0x7273_796e;
+// The granule position of an Ogg Opus stream is always in frames at 48000 Hz.
+const GRANULE_FRAMES_PER_SECOND: u64 = 48000;
+// The duration of an Opus frame is 20 milliseconds.
+const OPUS_FRAMES_PER_SECOND: u64 = 50;
+// The largest packet that is recommended by the Opus documentation.
+const MAX_PACKET_SIZE: usize = 4000;
+
+/// The error type for [`OpusAudioWriter`].
+///
+/// [`OpusAudioWriter`]: ./struct.OpusAudioWriter.html
+#[derive(Debug)]
+pub enum OpusAudioError {
+    /// The number of channels or the sample rate is not supported.
+    UnsupportedAudioFormat,
+    /// The audio could not be encoded, e.g. because the bitrate is out of range.
+    Encoding(opus::Error),
+    /// The encoded audio could not be written.
+    Io(io::Error),
+}
+
+impl Display for OpusAudioError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            OpusAudioError::UnsupportedAudioFormat => write!(f, "unsupported audio format"),
+            OpusAudioError::Encoding(e) => write!(f, "error while encoding: {}", e),
+            OpusAudioError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OpusAudioError {}
+
+impl From<io::Error> for OpusAudioError {
+    fn from(e: io::Error) -> Self {
+        OpusAudioError::Io(e)
+    }
+}
+
+impl From<opus::Error> for OpusAudioError {
+    fn from(e: opus::Error) -> Self {
+        OpusAudioError::Encoding(e)
+    }
+}
+
+/// Write audio to an Ogg Opus stream.
+///
+/// Opus supports one or two channels and a sample rate of 8000, 12000, 16000, 24000 or
+/// 48000 frames per second. Use e.g. a [`SampleRateAdapter`] to render at 48000 frames per
+/// second when the audio input has another sample rate.
+///
+/// Note
+/// ----
+/// The audio is encoded in frames of 20 milliseconds. The last frame, the lookahead of the
+/// encoder and the end of the stream are only written when [`finish`] is called; the stream
+/// is incomplete when the `OpusAudioWriter` is dropped without calling [`finish`].
+///
+/// [`SampleRateAdapter`]: ../../../utilities/resampling/struct.SampleRateAdapter.html
+/// [`finish`]: #method.finish
+pub struct OpusAudioWriter<W, S>
+where
+    W: Write,
+{
+    packet_writer: PacketWriter<W>,
+    encoder: Encoder,
+    number_of_channels: usize,
+    frames_per_second: u64,
+    // The number of input frames per Opus frame.
+    frame_size: usize,
+    // The number of frames at 48000 Hz per input frame.
+    granule_factor: u64,
+    // The lookahead of the encoder, in input frames.
+    lookahead: u64,
+    pre_skip: u64,
+    // Interleaved samples that have not been encoded yet.
+    samples: Vec<f32>,
+    number_of_frames: u64,
+    number_of_frames_encoded: u64,
+    packet: Vec<u8>,
+    // The last packet and its granule position. It is only written when the next packet is
+    // ready, so that the last packet of the stream can be marked as such.
+    last_packet: Option<(Box<[u8]>, u64)>,
+    _phantom: PhantomData<S>,
+}
+
+impl<W, S> OpusAudioWriter<W, S>
+where
+    W: Write,
+{
+    /// Create a new `OpusAudioWriter` that writes to `writer` with the given bitrate in bits
+    /// per second, e.g. `128_000` for 128 kbit/s.
+    ///
+    /// Returns `OpusAudioError::UnsupportedAudioFormat` when Opus does not support the number
+    /// of channels or the sample rate. The headers of the stream are written immediately.
+    pub fn new(
+        writer: W,
+        number_of_channels: usize,
+        frames_per_second: u64,
+        bitrate_in_bits_per_second: u32,
+    ) -> Result<Self, OpusAudioError> {
+        let channels = match number_of_channels {
+            1 => Channels::Mono,
+            2 => Channels::Stereo,
+            _ => return Err(OpusAudioError::UnsupportedAudioFormat),
+        };
+        match frames_per_second {
+            8000 | 12000 | 16000 | 24000 | 48000 => {}
+            _ => return Err(OpusAudioError::UnsupportedAudioFormat),
+        }
+        let mut encoder = Encoder::new(frames_per_second as u32, channels, Application::Audio)?;
+        encoder.set_bitrate(Bitrate::Bits(bitrate_in_bits_per_second as i32))?;
+        let granule_factor = GRANULE_FRAMES_PER_SECOND / frames_per_second;
+        let lookahead = encoder.get_lookahead()? as u64;
+        let pre_skip = lookahead * granule_factor;
+        let frame_size = (frames_per_second / OPUS_FRAMES_PER_SECOND) as usize;
+
+        let mut packet_writer = PacketWriter::new(writer);
+        packet_writer.write_packet(
+            opus_head(number_of_channels, pre_skip as u16, frames_per_second),
+            SERIAL,
+            PacketWriteEndInfo::EndPage,
+            0,
+        )?;
+        packet_writer.write_packet(opus_tags(), SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        Ok(Self {
+            packet_writer,
+            encoder,
+            number_of_channels,
+            frames_per_second,
+            frame_size,
+            granule_factor,
+            lookahead,
+            pre_skip,
+            samples: Vec::with_capacity(frame_size * number_of_channels),
+            number_of_frames: 0,
+            number_of_frames_encoded: 0,
+            packet: vec![0; MAX_PACKET_SIZE],
+            last_packet: None,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// The number of frames that have been written so far.
+    pub fn number_of_frames(&self) -> u64 {
+        self.number_of_frames
+    }
+
+    /// Encode the remaining audio and write the end of the stream.
+    ///
+    /// Returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, OpusAudioError> {
+        let frame_length = self.frame_size * self.number_of_channels;
+        // Complete the last frame with silence and encode silence until the encoder has output
+        // the lookahead, so that the decoder can decode the last frames. The silence is removed
+        // by the decoder, based on the granule position of the last page.
+        while self.number_of_frames_encoded < self.number_of_frames + self.lookahead
+            || self.last_packet.is_none()
+        {
+            self.samples.resize(frame_length, 0.0);
+            self.encode_frame()?;
+        }
+        if let Some((packet, _)) = self.last_packet.take() {
+            let granule_position = self.pre_skip + self.number_of_frames * self.granule_factor;
+            self.packet_writer.write_packet(
+                packet,
+                SERIAL,
+                PacketWriteEndInfo::EndStream,
+                granule_position,
+            )?;
+        }
+        let mut writer = self.packet_writer.into_inner();
+        writer.flush()?;
+        Ok(writer)
+    }
+
+    fn encode_frame(&mut self) -> Result<(), OpusAudioError> {
+        let frame_length = self.frame_size * self.number_of_channels;
+        let packet_length = self
+            .encoder
+            .encode_float(&self.samples[..frame_length], &mut self.packet)?;
+        self.samples.drain(..frame_length);
+        self.number_of_frames_encoded += self.frame_size as u64;
+        let packet = self.packet[..packet_length].to_vec().into_boxed_slice();
+        // The granule position counts the decoded frames, including the pre-skip.
+        // Only the granule position of the last page is corrected to end the stream after the
+        // last frame that has been written (see `finish`).
+        let granule_position = self.number_of_frames_encoded * self.granule_factor;
+        if let Some((previous_packet, previous_granule_position)) =
+            self.last_packet.replace((packet, granule_position))
+        {
+            self.packet_writer.write_packet(
+                previous_packet,
+                SERIAL,
+                PacketWriteEndInfo::NormalPacket,
+                previous_granule_position,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// The identification header, see RFC 7845, section 5.1.
+fn opus_head(number_of_channels: usize, pre_skip: u16, frames_per_second: u64) -> Box<[u8]> {
+    let mut header = Vec::with_capacity(19);
+    header.extend_from_slice(b"OpusHead");
+    header.push(1); // Version
+    header.push(number_of_channels as u8);
+    header.extend_from_slice(&pre_skip.to_le_bytes());
+    header.extend_from_slice(&(frames_per_second as u32).to_le_bytes());
+    header.extend_from_slice(&0_i16.to_le_bytes()); // Output gain
+    header.push(0); // Channel mapping family: mono or stereo
+    header.into_boxed_slice()
+}
+
+// The comment header, see RFC 7845, section 5.2.
+fn opus_tags() -> Box<[u8]> {
+    let vendor = b"rsynth";
+    let mut header = Vec::with_capacity(16 + vendor.len());
+    header.extend_from_slice(b"OpusTags");
+    header.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    header.extend_from_slice(vendor);
+    header.extend_from_slice(&0_u32.to_le_bytes()); // Number of comments
+    header.into_boxed_slice()
+}
+
+impl<W, S> AudioWriter<S> for OpusAudioWriter<W, S>
+where
+    W: Write,
+    S: ToSample<f32> + Copy,
+{
+    type Err = OpusAudioError;
+
+    fn write_buffer(&mut self, inputs: &[&[S]]) -> Result<(), Self::Err> {
+        assert_eq!(inputs.len(), self.number_of_channels);
+        let length = inputs[0].len();
+        for input in inputs.iter() {
+            assert_eq!(input.len(), length);
+        }
+
+        let frame_length = self.frame_size * self.number_of_channels;
+        for frame_index in 0..length {
+            for input in inputs.iter() {
+                self.samples.push(input[frame_index].to_sample_());
+            }
+            if self.samples.len() == frame_length {
+                self.encode_frame()?;
+            }
+        }
+        self.number_of_frames += length as u64;
+        Ok(())
+    }
+
+    fn number_of_channels(&self) -> Option<usize> {
+        Some(self.number_of_channels)
+    }
+
+    fn frames_per_second(&self) -> Option<u64> {
+        Some(self.frames_per_second)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ogg::reading::PacketReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn writes_headers_audio_packets_and_the_end_of_the_stream() {
+        let mut writer =
+            OpusAudioWriter::<_, f32>::new(Vec::new(), 2, 48000, 96_000).expect("no error");
+        // One and a half Opus frame.
+        let left = vec![0.25; 1440];
+        let right = vec![-0.25; 1440];
+        writer.write_buffer(&[&left, &right]).expect("no error");
+        assert_eq!(writer.number_of_frames(), 1440);
+        let data = writer.finish().expect("no error");
+
+        let mut reader = PacketReader::new(Cursor::new(data));
+        let head = reader.read_packet_expected().expect("no error");
+        assert_eq!(&head.data[..8], b"OpusHead");
+        assert_eq!(head.data[9], 2);
+        let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as u64;
+        assert_eq!(&head.data[12..16], &48000_u32.to_le_bytes());
+        let tags = reader.read_packet_expected().expect("no error");
+        assert_eq!(&tags.data[..8], b"OpusTags");
+
+        let first = reader.read_packet_expected().expect("no error");
+        assert!(!first.last_in_stream());
+        let last = reader.read_packet_expected().expect("no error");
+        assert!(last.last_in_stream());
+        // The padding of the last frame is not part of the stream.
+        assert_eq!(last.absgp_page(), pre_skip + 1440);
+        assert!(reader.read_packet().expect("no error").is_none());
+    }
+
+    #[test]
+    fn the_lookahead_is_flushed_when_the_input_fills_whole_opus_frames() {
+        let mut writer =
+            OpusAudioWriter::<_, f32>::new(Vec::new(), 1, 48000, 96_000).expect("no error");
+        // Two Opus frames.
+        let input = vec![0.25; 1920];
+        writer.write_buffer(&[&input]).expect("no error");
+        let lookahead = writer.lookahead;
+        assert!(lookahead > 0);
+        let data = writer.finish().expect("no error");
+
+        let mut reader = PacketReader::new(Cursor::new(data));
+        let head = reader.read_packet_expected().expect("no error");
+        let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as u64;
+        assert_eq!(pre_skip, lookahead);
+        reader.read_packet_expected().expect("no error");
+
+        let mut number_of_packets = 0;
+        let mut last_granule_position = 0;
+        while let Some(packet) = reader.read_packet().expect("no error") {
+            number_of_packets += 1;
+            last_granule_position = packet.absgp_page();
+            assert_eq!(packet.last_in_stream(), number_of_packets == 3);
+        }
+        // One extra packet with silence contains the end of the lookahead.
+        assert_eq!(number_of_packets, 3);
+        assert_eq!(last_granule_position, pre_skip + 1920);
+    }
+
+    #[test]
+    fn granule_positions_never_decrease() {
+        let mut writer =
+            OpusAudioWriter::<_, f32>::new(Vec::new(), 1, 48000, 96_000).expect("no error");
+        // Not a whole number of Opus frames.
+        let input = vec![0.25; 1900];
+        writer.write_buffer(&[&input]).expect("no error");
+        let data = writer.finish().expect("no error");
+
+        let mut reader = PacketReader::new(Cursor::new(data));
+        let head = reader.read_packet_expected().expect("no error");
+        let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as u64;
+        reader.read_packet_expected().expect("no error");
+
+        let mut granule_positions = Vec::new();
+        while let Some(packet) = reader.read_packet().expect("no error") {
+            granule_positions.push(packet.absgp_page());
+        }
+        assert!(granule_positions
+            .windows(2)
+            .all(|positions| positions[0] <= positions[1]));
+        assert_eq!(granule_positions.last(), Some(&(pre_skip + 1900)));
+    }
+
+    #[test]
+    fn unsupported_formats_are_rejected() {
+        assert!(matches!(
+            OpusAudioWriter::<_, f32>::new(Vec::new(), 2, 44100, 96_000),
+            Err(OpusAudioError::UnsupportedAudioFormat)
+        ));
+        assert!(matches!(
+            OpusAudioWriter::<_, f32>::new(Vec::new(), 3, 48000, 96_000),
+            Err(OpusAudioError::UnsupportedAudioFormat)
+        ));
+    }
+}
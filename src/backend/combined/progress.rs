@@ -0,0 +1,119 @@
+//! Report the progress of offline rendering and cancel it from another thread.
+//!
+//! Pass a [`RenderProgress`] to [`run_with_options`] in the [`RenderOptions`] and keep a clone
+//! of it, e.g. in the thread of a GUI:
+//!
+//! ```
+//! use rsynth::backend::combined::progress::RenderProgress;
+//!
+//! let progress = RenderProgress::new();
+//! let handle = progress.clone();
+//! // ... start rendering with `progress` on another thread ...
+//! if let Some(fraction) = handle.fraction() {
+//!     println!("{:.0}%", fraction * 100.0);
+//! }
+//! // When the user presses "cancel":
+//! handle.cancel();
+//! assert!(progress.is_cancelled());
+//! ```
+//!
+//! [`RenderProgress`]: ./struct.RenderProgress.html
+//! [`run_with_options`]: ../fn.run_with_options.html
+//! [`RenderOptions`]: ../struct.RenderOptions.html
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+// Used for `total_frames` when the total number of frames is not known.
+const UNKNOWN: u64 = u64::MAX;
+
+#[derive(Debug)]
+struct State {
+    frames_rendered: AtomicU64,
+    total_frames: AtomicU64,
+    cancelled: AtomicBool,
+}
+
+/// A handle to follow the progress of rendering and to cancel it.
+///
+/// Clones of a `RenderProgress` share their state, so that one clone can be passed to the
+/// renderer and another clone can be used from another thread.
+/// See the [module level documentation] for an example.
+///
+/// [module level documentation]: ./index.html
+#[derive(Clone, Debug)]
+pub struct RenderProgress {
+    state: Arc<State>,
+}
+
+impl RenderProgress {
+    /// Create a new `RenderProgress`, without frames rendered.
+    pub fn new() -> Self {
+        RenderProgress {
+            state: Arc::new(State {
+                frames_rendered: AtomicU64::new(0),
+                total_frames: AtomicU64::new(UNKNOWN),
+                cancelled: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// The number of frames that have been rendered and written so far.
+    pub fn frames_rendered(&self) -> u64 {
+        self.state.frames_rendered.load(Ordering::Relaxed)
+    }
+
+    /// The total number of frames that will be rendered, or `None` when this is not known,
+    /// e.g. when the audio input does not know its duration.
+    ///
+    /// When the tail of the plugin is rendered after the audio input has ended
+    /// (see [`RenderUntilSilence`]), more frames than this may be rendered.
+    ///
+    /// [`RenderUntilSilence`]: ../struct.RenderUntilSilence.html
+    pub fn total_frames(&self) -> Option<u64> {
+        match self.state.total_frames.load(Ordering::Relaxed) {
+            UNKNOWN => None,
+            total_frames => Some(total_frames),
+        }
+    }
+
+    /// The fraction of the frames that have been rendered, between `0.0` and `1.0`,
+    /// or `None` when the total number of frames is not known.
+    pub fn fraction(&self) -> Option<f64> {
+        let total_frames = self.total_frames()?;
+        if total_frames == 0 {
+            return Some(1.0);
+        }
+        Some((self.frames_rendered() as f64 / total_frames as f64).min(1.0))
+    }
+
+    /// Ask the renderer to stop. Rendering stops before the next buffer is rendered.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Return `true` when [`cancel`] has been called on this `RenderProgress` or on a clone.
+    ///
+    /// [`cancel`]: #method.cancel
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn start(&self, total_frames: Option<u64>) {
+        self.state.frames_rendered.store(0, Ordering::Relaxed);
+        self.state
+            .total_frames
+            .store(total_frames.unwrap_or(UNKNOWN), Ordering::Relaxed);
+    }
+
+    pub(super) fn add_frames(&self, number_of_frames: u64) {
+        self.state
+            .frames_rendered
+            .fetch_add(number_of_frames, Ordering::Relaxed);
+    }
+}
+
+impl Default for RenderProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -77,7 +77,7 @@ impl<'a> Iterator for RimdMidiReader<'a> {
                         unimplemented!("better error handling for this error case");
                     }
                     self.current_tempo_in_micro_seconds_per_beat =
-                        data[2] as f64 + 255.0 * (data[1] as f64 + (255.0 * data[0] as f64));
+                        ((data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32) as f64;
                 }
                 Event::Meta(_) => {}
             }
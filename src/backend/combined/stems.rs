@@ -0,0 +1,157 @@
+//! Write the audio outputs of a plugin to separate files ("stems").
+//!
+//! A [`StemWriter`] divides the channels of each buffer over a number of [`AudioWriter`]s, e.g.
+//! one stereo `.wav` file for every bus of a drum synth:
+//!
+//! ```no_run
+//! use rsynth::backend::combined::hound::HoundAudioWriter;
+//! use rsynth::backend::combined::stems::StemWriter;
+//!
+//! let spec = hound::WavSpec {
+//!     channels: 2,
+//!     sample_rate: 44100,
+//!     bits_per_sample: 24,
+//!     sample_format: hound::SampleFormat::Int,
+//! };
+//! let mut kick = hound::WavWriter::create("kick.wav", spec).unwrap();
+//! let mut snare = hound::WavWriter::create("snare.wav", spec).unwrap();
+//! // The first two outputs of the plugin are written to `kick.wav`,
+//! // the next two outputs to `snare.wav`.
+//! let stems: StemWriter<HoundAudioWriter<f32>> = StemWriter::new()
+//!     .with_stem(2, HoundAudioWriter::new(&mut kick).ok().unwrap())
+//!     .with_stem(2, HoundAudioWriter::new(&mut snare).ok().unwrap());
+//! ```
+//!
+//! [`StemWriter`]: ./struct.StemWriter.html
+//! [`AudioWriter`]: ../trait.AudioWriter.html
+use super::{AudioMetadata, AudioWriter};
+
+struct Stem<W> {
+    first_channel: usize,
+    number_of_channels: usize,
+    writer: W,
+}
+
+/// An [`AudioWriter`] that writes consecutive groups of channels to separate writers.
+///
+/// See the [module level documentation] for an example.
+///
+/// [`AudioWriter`]: ../trait.AudioWriter.html
+/// [module level documentation]: ./index.html
+pub struct StemWriter<W> {
+    stems: Vec<Stem<W>>,
+    number_of_channels: usize,
+}
+
+impl<W> StemWriter<W> {
+    /// Create a new `StemWriter` without stems.
+    pub fn new() -> Self {
+        StemWriter {
+            stems: Vec::new(),
+            number_of_channels: 0,
+        }
+    }
+
+    /// Write the next `number_of_channels` channels with `writer`.
+    ///
+    /// # Panics
+    /// Panics if `number_of_channels` is zero or if `writer` expects a different number
+    /// of channels.
+    pub fn with_stem<S>(mut self, number_of_channels: usize, writer: W) -> Self
+    where
+        W: AudioWriter<S>,
+    {
+        assert!(number_of_channels > 0);
+        if let Some(expected) = writer.number_of_channels() {
+            assert_eq!(
+                expected, number_of_channels,
+                "The writer expects a different number of channels."
+            );
+        }
+        self.stems.push(Stem {
+            first_channel: self.number_of_channels,
+            number_of_channels,
+            writer,
+        });
+        self.number_of_channels += number_of_channels;
+        self
+    }
+
+    /// The number of stems.
+    pub fn number_of_stems(&self) -> usize {
+        self.stems.len()
+    }
+
+    /// Return the writers of the stems, e.g. to finalize the files.
+    pub fn into_writers(self) -> Vec<W> {
+        self.stems.into_iter().map(|stem| stem.writer).collect()
+    }
+}
+
+impl<W> Default for StemWriter<W> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W, S> AudioWriter<S> for StemWriter<W>
+where
+    W: AudioWriter<S>,
+{
+    type Err = W::Err;
+
+    fn write_buffer(&mut self, buffer: &[&[S]]) -> Result<(), Self::Err> {
+        assert_eq!(buffer.len(), self.number_of_channels);
+        for stem in self.stems.iter_mut() {
+            let channels =
+                &buffer[stem.first_channel..stem.first_channel + stem.number_of_channels];
+            stem.writer.write_buffer(channels)?;
+        }
+        Ok(())
+    }
+
+    fn number_of_channels(&self) -> Option<usize> {
+        Some(self.number_of_channels)
+    }
+
+    fn frames_per_second(&self) -> Option<u64> {
+        self.stems
+            .iter()
+            .find_map(|stem| stem.writer.frames_per_second())
+    }
+
+    /// Embed the metadata in every stem. Returns `Ok(true)` if every stem has stored it.
+    fn write_metadata(&mut self, metadata: &AudioMetadata) -> Result<bool, Self::Err> {
+        let mut stored = true;
+        for stem in self.stems.iter_mut() {
+            stored &= stem.writer.write_metadata(metadata)?;
+        }
+        Ok(stored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::combined::memory::AudioBufferWriter;
+    use crate::buffer::AudioChunk;
+
+    #[test]
+    fn channels_are_divided_over_the_stems() {
+        let mut drums = AudioChunk::new(2);
+        let mut bass = AudioChunk::new(1);
+        {
+            let mut stems = StemWriter::new()
+                .with_stem(2, AudioBufferWriter::new(&mut drums))
+                .with_stem(1, AudioBufferWriter::new(&mut bass));
+            assert_eq!(stems.number_of_stems(), 2);
+            assert_eq!(AudioWriter::<i32>::number_of_channels(&stems), Some(3));
+            stems
+                .write_buffer(&[&[1, 2], &[3, 4], &[5, 6]])
+                .expect("no error");
+            stems.write_buffer(&[&[7], &[8], &[9]]).expect("no error");
+        }
+        assert_eq!(drums.channels(), &vec![vec![1, 2, 7], vec![3, 4, 8]]);
+        assert_eq!(bass.channels(), &vec![vec![5, 6, 9]]);
+    }
+}
@@ -0,0 +1,971 @@
+//! Standalone audio input and output, using the [`cpal`] crate: ALSA on Linux, CoreAudio on
+//! macOS and WASAPI or ASIO on Windows.
+//!
+//! Support is only enabled if you compile with the "backend-cpal" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! This allows running a plugin as a desktop application without JACK:
+//! ```no_run
+//! # use rsynth::{AudioHandler, AudioHandlerMeta, ContextualAudioRenderer};
+//! # use rsynth::backend::cpal::CpalHost;
+//! # struct MySynth;
+//! # impl AudioHandlerMeta for MySynth {
+//! #     fn max_number_of_audio_inputs(&self) -> usize { 0 }
+//! #     fn max_number_of_audio_outputs(&self) -> usize { 2 }
+//! # }
+//! # impl AudioHandler for MySynth {
+//! #     fn set_sample_rate(&mut self, sample_rate: f64) {}
+//! # }
+//! # impl ContextualAudioRenderer<f32, CpalHost> for MySynth {
+//! #     fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut [&mut [f32]], context: &mut CpalHost) {}
+//! # }
+//! use rsynth::backend::cpal::{output_device_names, run, CpalSettings};
+//!
+//! for name in output_device_names(None).expect("devices can be enumerated") {
+//!     println!("{}", name);
+//! }
+//! let settings = CpalSettings {
+//!     sample_rate: Some(48000),
+//!     ..CpalSettings::default()
+//! };
+//! run(MySynth, &settings).expect("the audio device can be opened");
+//! ```
+//!
+//! Stream configuration
+//! ====================
+//! The stream configuration is negotiated with the output device:
+//! * channels: a configuration with as many channels as the plugin has outputs is preferred,
+//!   then the configuration with the fewest extra channels. The extra channels of the device
+//!   are silent; outputs of the plugin without a device channel are not played.
+//! * sample rate: [`CpalSettings::sample_rate`] if the device supports it, otherwise the
+//!   default sample rate of the device, or else the closest sample rate that is supported.
+//!   `set_sample_rate` is called before the stream is started.
+//! * sample format: `f32` is preferred, `i16` and `u16` are converted.
+//!
+//! Channel mapping
+//! ---------------
+//! By default, output `i` of the plugin is sent to channel `i` of the output device.
+//! Audio interfaces often have many channels, so [`CpalSettings::output_channel_map`] can
+//! send the outputs to other channels, e.g. `vec![2, 3]` sends the plugin's stereo output to
+//! the third and fourth channel of the device. The channels that are not in the map are
+//! silent. Use [`max_output_channels`] to get the number of channels that the user can choose
+//! from.
+//!
+//! cpal uses interleaved buffers; these are deinterleaved into one slice per channel before
+//! calling `render_buffer`. Buffers that are longer than the maximum buffer size are
+//! rendered in parts. The maximum buffer size is passed to the plugin with
+//! `set_max_buffer_size` before the streams are started.
+//!
+//! Audio input
+//! ===========
+//! When the plugin has audio inputs, the input is read from an input device with the same
+//! sample rate. The input device has its own stream, so the input is passed over a lock-free
+//! queue, which adds some latency. When no input is available, the plugin gets silence.
+//!
+//! Input monitoring
+//! ----------------
+//! The audio input can be added to the outputs of the plugin, so that the player hears what
+//! they play, e.g. when the plugin is an effect for a guitar. Set
+//! [`CpalSettings::input_monitoring`] to start with monitoring switched on, and use the
+//! [`MonitorControl`] that is returned by [`CpalStreams::monitor`] to switch it on or off and to
+//! change the gain while the streams are running.
+//!
+//! The latency of the input and output devices is measured from the timestamps that cpal
+//! passes to the callbacks, including the latency of the queue between the input and the
+//! output stream, and is reported by [`MonitorControl::latency`].
+//!
+//! ASIO
+//! ====
+//! On Windows, the default host is WASAPI. For lower latencies, use an ASIO driver by
+//! compiling with the "backend-asio" feature and setting [`CpalSettings::host`] to
+//! `HostId::Asio`, e.g. with `CpalSettings::asio("Focusrite USB ASIO")`.
+//! Building with ASIO support requires the ASIO SDK, see the documentation of [`cpal`] for how
+//! to set it up. The names of the ASIO drivers are returned by
+//! `output_device_names(Some(HostId::Asio))`. With ASIO, the buffer size is usually set
+//! in the control panel of the driver, so leave [`CpalSettings::buffer_size`] at `None`.
+//!
+//! Midi
+//! ====
+//! cpal does not support midi. Use the [`midir`] module for hardware midi input and wrap your
+//! plugin in a [`LiveMidiInput`], which passes the received events to the plugin at the
+//! start of each buffer.
+//!
+//! [`cpal`]: https://crates.io/crates/cpal
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`CpalSettings::sample_rate`]: ./struct.CpalSettings.html#structfield.sample_rate
+//! [`CpalSettings::output_channel_map`]: ./struct.CpalSettings.html#structfield.output_channel_map
+//! [`CpalSettings::host`]: ./struct.CpalSettings.html#structfield.host
+//! [`CpalSettings::buffer_size`]: ./struct.CpalSettings.html#structfield.buffer_size
+//! [`CpalSettings::input_monitoring`]: ./struct.CpalSettings.html#structfield.input_monitoring
+//! [`CpalStreams::monitor`]: ./struct.CpalStreams.html#method.monitor
+//! [`MonitorControl`]: ../../utilities/input_monitor/struct.MonitorControl.html
+//! [`MonitorControl::latency`]: ../../utilities/input_monitor/struct.MonitorControl.html#method.latency
+//! [`max_output_channels`]: ./fn.max_output_channels.html
+//! [`midir`]: ../midir/index.html
+//! [`LiveMidiInput`]: ../midi_input_queue/struct.LiveMidiInput.html
+use crate::backend::HostInterface;
+#[cfg(feature = "flush-denormals")]
+use crate::utilities::denormals::DenormalGuard;
+use crate::utilities::input_monitor::{InputMonitor, MonitorControl};
+use crate::{AudioHandler, AudioHandlerMeta, ContextualAudioRenderer};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+pub use cpal::{available_hosts, HostId};
+use cpal::{
+    BufferSize, BuildStreamError, DefaultStreamConfigError, Device, DeviceNameError, DevicesError,
+    FromSample, Host, HostUnavailable, InputCallbackInfo, OutputCallbackInfo, PlayStreamError,
+    Sample, SampleFormat, SampleRate, SizedSample, Stream, StreamConfig, SupportedStreamConfig,
+    SupportedStreamConfigRange, SupportedStreamConfigsError,
+};
+use ringbuf::{Consumer, Producer, RingBuffer};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use vecstorage::VecStorage;
+
+/// The maximum number of frames that is rendered at once, when no buffer size is specified.
+pub const DEFAULT_MAXIMUM_BUFFER_SIZE: usize = 1024;
+
+/// The error type that represents the errors you can get when opening an audio device.
+#[derive(Debug)]
+pub enum CpalError {
+    /// The host (audio API) is not available.
+    HostUnavailable(HostUnavailable),
+    /// The devices could not be enumerated.
+    DevicesError(DevicesError),
+    /// The name of a device could not be retrieved.
+    DeviceNameError(DeviceNameError),
+    /// No device could be found with the given name.
+    DeviceNotFound(String),
+    /// There is no default output device.
+    NoDefaultDevice,
+    /// The supported configurations of the device could not be retrieved.
+    SupportedStreamConfigsError(SupportedStreamConfigsError),
+    /// The default configuration of the device could not be retrieved.
+    DefaultStreamConfigError(DefaultStreamConfigError),
+    /// The device does not support a sample format that can be used.
+    NoSupportedConfig,
+    /// The input device does not support the sample rate of the output device.
+    InputSampleRateNotSupported(u32),
+    /// The output channel map refers to a channel that the output device does not have.
+    OutputChannelNotAvailable(usize),
+    /// The output channel map contains the same channel more than once.
+    DuplicateOutputChannel(usize),
+    /// The stream could not be created.
+    BuildStreamError(BuildStreamError),
+    /// The stream could not be started.
+    PlayStreamError(PlayStreamError),
+}
+
+/// The settings for opening the audio devices.
+#[derive(Clone, Debug, Default)]
+pub struct CpalSettings {
+    /// The host (audio API) to use, e.g. `HostId::Asio`, see [`available_hosts`].
+    /// The default host of the platform is used when this is `None`.
+    ///
+    /// [`available_hosts`]: ./fn.available_hosts.html
+    pub host: Option<HostId>,
+    /// Use the first output device whose name contains this string.
+    /// The default output device is used when this is `None`.
+    pub output_device: Option<String>,
+    /// Use the first input device whose name contains this string.
+    /// The default input device is used when this is `None`.
+    /// The input device is only opened when the plugin has audio inputs.
+    pub input_device: Option<String>,
+    /// The preferred sample rate, see the [module level documentation].
+    ///
+    /// [module level documentation]: ./index.html
+    pub sample_rate: Option<u32>,
+    /// The number of frames per buffer. The default of the device is used when this is `None`.
+    pub buffer_size: Option<u32>,
+    /// For each output of the plugin, the index (starting from 0) of the channel of the output
+    /// device, see the [module level documentation].
+    /// Output `i` of the plugin is sent to channel `i` of the device when this is `None`.
+    ///
+    /// [module level documentation]: ./index.html
+    pub output_channel_map: Option<Vec<usize>>,
+    /// Add the audio input to the outputs with this linear gain, see the
+    /// [module level documentation].
+    /// Input monitoring is switched off when this is `None`; it can be switched on later
+    /// with [`CpalStreams::monitor`].
+    ///
+    /// [module level documentation]: ./index.html
+    /// [`CpalStreams::monitor`]: ./struct.CpalStreams.html#method.monitor
+    pub input_monitoring: Option<f64>,
+}
+
+#[cfg(all(windows, feature = "backend-asio"))]
+impl CpalSettings {
+    /// The settings for the ASIO driver whose name contains `driver_name`, which is used for
+    /// both audio input and audio output.
+    pub fn asio(driver_name: &str) -> Self {
+        CpalSettings {
+            host: Some(HostId::Asio),
+            output_device: Some(driver_name.to_string()),
+            input_device: Some(driver_name.to_string()),
+            ..CpalSettings::default()
+        }
+    }
+}
+
+fn host(host_id: Option<HostId>) -> Result<Host, CpalError> {
+    match host_id {
+        Some(host_id) => cpal::host_from_id(host_id).map_err(CpalError::HostUnavailable),
+        None => Ok(cpal::default_host()),
+    }
+}
+
+/// Get the names of the available audio output devices of the given host, or of the default
+/// host when `host_id` is `None`.
+pub fn output_device_names(host_id: Option<HostId>) -> Result<Vec<String>, CpalError> {
+    let devices = host(host_id)?
+        .output_devices()
+        .map_err(CpalError::DevicesError)?;
+    let mut result = Vec::new();
+    for device in devices {
+        result.push(device.name().map_err(CpalError::DeviceNameError)?);
+    }
+    Ok(result)
+}
+
+/// Get the names of the available audio input devices of the given host, or of the default
+/// host when `host_id` is `None`.
+pub fn input_device_names(host_id: Option<HostId>) -> Result<Vec<String>, CpalError> {
+    let devices = host(host_id)?
+        .input_devices()
+        .map_err(CpalError::DevicesError)?;
+    let mut result = Vec::new();
+    for device in devices {
+        result.push(device.name().map_err(CpalError::DeviceNameError)?);
+    }
+    Ok(result)
+}
+
+fn find_device<I>(devices: I, device_name: &str) -> Result<Device, CpalError>
+where
+    I: Iterator<Item = Device>,
+{
+    for device in devices {
+        if device
+            .name()
+            .map_err(CpalError::DeviceNameError)?
+            .contains(device_name)
+        {
+            return Ok(device);
+        }
+    }
+    Err(CpalError::DeviceNotFound(device_name.to_string()))
+}
+
+fn output_device(host: &Host, settings: &CpalSettings) -> Result<Device, CpalError> {
+    match &settings.output_device {
+        Some(name) => find_device(
+            host.output_devices().map_err(CpalError::DevicesError)?,
+            name,
+        ),
+        None => host
+            .default_output_device()
+            .ok_or(CpalError::NoDefaultDevice),
+    }
+}
+
+/// Get the maximum number of channels of the output device, e.g. to let the user choose the
+/// [`output_channel_map`].
+///
+/// [`output_channel_map`]: ./struct.CpalSettings.html#structfield.output_channel_map
+pub fn max_output_channels(settings: &CpalSettings) -> Result<u16, CpalError> {
+    let device = output_device(&host(settings.host)?, settings)?;
+    Ok(device
+        .supported_output_configs()
+        .map_err(CpalError::SupportedStreamConfigsError)?
+        .map(|range| range.channels())
+        .max()
+        .unwrap_or(0))
+}
+
+// For each output of the plugin, the channel of the output device.
+fn output_channel_map(
+    settings: &CpalSettings,
+    number_of_outputs: usize,
+) -> Result<Vec<usize>, CpalError> {
+    let map = match &settings.output_channel_map {
+        Some(map) => map.clone(),
+        None => return Ok((0..number_of_outputs).collect()),
+    };
+    for (index, channel) in map.iter().enumerate() {
+        if map[..index].contains(channel) {
+            return Err(CpalError::DuplicateOutputChannel(*channel));
+        }
+    }
+    Ok(map)
+}
+
+// The lower, the better.
+fn format_rank(sample_format: SampleFormat) -> Option<u8> {
+    match sample_format {
+        SampleFormat::F32 => Some(0),
+        SampleFormat::I16 => Some(1),
+        SampleFormat::U16 => Some(2),
+        _ => None,
+    }
+}
+
+// The lower, the better.
+fn channel_rank(available: usize, wanted: usize) -> (u8, usize) {
+    if available == wanted {
+        (0, 0)
+    } else if available > wanted {
+        (1, available - wanted)
+    } else {
+        (2, wanted - available)
+    }
+}
+
+fn choose_config<I>(
+    ranges: I,
+    number_of_channels: usize,
+    preferred_sample_rate: u32,
+) -> Option<SupportedStreamConfig>
+where
+    I: Iterator<Item = SupportedStreamConfigRange>,
+{
+    let clamp = |range: &SupportedStreamConfigRange| {
+        preferred_sample_rate
+            .max(range.min_sample_rate().0)
+            .min(range.max_sample_rate().0)
+    };
+    let range = ranges
+        .filter(|range| format_rank(range.sample_format()).is_some())
+        .min_by_key(|range| {
+            (
+                channel_rank(range.channels() as usize, number_of_channels),
+                (clamp(range) as i64 - preferred_sample_rate as i64).abs(),
+                format_rank(range.sample_format()),
+            )
+        })?;
+    let sample_rate = clamp(&range);
+    Some(range.with_sample_rate(SampleRate(sample_rate)))
+}
+
+/// The context that is passed to the plugin when rendering audio.
+pub struct CpalHost {
+    _private: (),
+}
+
+impl HostInterface for CpalHost {
+    fn output_initialized(&self) -> bool {
+        false
+    }
+}
+
+fn duration_in_frames(duration: Duration, sample_rate: u32) -> usize {
+    (duration.as_secs_f64() * sample_rate as f64).round() as usize
+}
+
+// Renders audio in the callback of the output stream.
+struct CpalProcessor<P> {
+    plugin: InputMonitor<P>,
+    monitor: MonitorControl,
+    host: CpalHost,
+    output_channels: usize,
+    output_channel_map: Vec<usize>,
+    input_channels: usize,
+    maximum_buffer_size: usize,
+    input: Option<Consumer<f32>>,
+    // The latency of the input device, measured by the input stream.
+    input_latency_in_frames: Arc<AtomicUsize>,
+    interleaved_input: Vec<f32>,
+    input_buffers: Vec<Vec<f32>>,
+    output_buffers: Vec<Vec<f32>>,
+    inputs: VecStorage<&'static [f32]>,
+    outputs: VecStorage<&'static [f32]>,
+}
+
+impl<P> CpalProcessor<P>
+where
+    P: AudioHandlerMeta + ContextualAudioRenderer<f32, CpalHost>,
+{
+    fn new(
+        plugin: InputMonitor<P>,
+        output_channels: usize,
+        output_channel_map: Vec<usize>,
+        input_channels: usize,
+        input: Option<Consumer<f32>>,
+        input_latency_in_frames: Arc<AtomicUsize>,
+        maximum_buffer_size: usize,
+    ) -> Self {
+        let number_of_inputs = plugin.inner().max_number_of_audio_inputs();
+        let number_of_outputs = plugin.inner().max_number_of_audio_outputs();
+        CpalProcessor {
+            monitor: plugin.control(),
+            plugin,
+            host: CpalHost { _private: () },
+            output_channels: output_channels.max(1),
+            output_channel_map,
+            input_channels,
+            maximum_buffer_size,
+            input,
+            input_latency_in_frames,
+            interleaved_input: vec![0.0; maximum_buffer_size * input_channels],
+            input_buffers: vec![vec![0.0; maximum_buffer_size]; number_of_inputs],
+            output_buffers: vec![vec![0.0; maximum_buffer_size]; number_of_outputs],
+            inputs: VecStorage::with_capacity(number_of_inputs),
+            outputs: VecStorage::with_capacity(number_of_outputs),
+        }
+    }
+
+    fn process<T>(&mut self, data: &mut [T])
+    where
+        T: Sample + FromSample<f32>,
+    {
+        #[cfg(feature = "flush-denormals")]
+        let _denormal_guard = DenormalGuard::new();
+        let silence = T::from_sample(0.0);
+        for part in data.chunks_mut(self.maximum_buffer_size * self.output_channels) {
+            let number_of_frames = part.len() / self.output_channels;
+            self.read_input(number_of_frames);
+            self.render(number_of_frames);
+            for (index, frame) in part.chunks_mut(self.output_channels).enumerate() {
+                for sample in frame.iter_mut() {
+                    *sample = silence;
+                }
+                for (buffer, channel) in self.output_buffers.iter().zip(&self.output_channel_map) {
+                    if let Some(sample) = frame.get_mut(*channel) {
+                        *sample = T::from_sample(buffer[index]);
+                    }
+                }
+            }
+        }
+    }
+
+    // Pass the latency of the devices to the input monitor. The input is delayed by the
+    // frames that are waiting in the queue.
+    fn report_latency(&mut self, output_latency_in_frames: usize) {
+        let input_latency_in_frames = match &self.input {
+            Some(consumer) => {
+                self.input_latency_in_frames.load(Ordering::Relaxed)
+                    + consumer.len() / self.input_channels.max(1)
+            }
+            None => 0,
+        };
+        self.monitor
+            .set_device_latency(input_latency_in_frames, output_latency_in_frames);
+    }
+
+    fn read_input(&mut self, number_of_frames: usize) {
+        if self.input_buffers.is_empty() {
+            return;
+        }
+        let interleaved = &mut self.interleaved_input[..number_of_frames * self.input_channels];
+        let number_of_samples_read = match &mut self.input {
+            Some(consumer) => {
+                // Only read whole frames, so that the channels stay aligned.
+                let available = consumer.len() / self.input_channels * self.input_channels;
+                let length = interleaved.len().min(available);
+                consumer.pop_slice(&mut interleaved[..length])
+            }
+            None => 0,
+        };
+        for sample in interleaved[number_of_samples_read..].iter_mut() {
+            *sample = 0.0;
+        }
+        for (channel, buffer) in self.input_buffers.iter_mut().enumerate() {
+            for (index, sample) in buffer[..number_of_frames].iter_mut().enumerate() {
+                *sample = if channel < self.input_channels {
+                    interleaved[index * self.input_channels + channel]
+                } else {
+                    0.0
+                };
+            }
+        }
+    }
+
+    fn render(&mut self, number_of_frames: usize) {
+        let mut inputs = self.inputs.vec_guard();
+        for buffer in self.input_buffers.iter() {
+            inputs.push(&buffer[..number_of_frames]);
+        }
+        let mut outputs = self.outputs.vec_guard();
+        for buffer in self.output_buffers.iter_mut() {
+            outputs.push(&mut buffer[..number_of_frames]);
+        }
+        self.plugin
+            .render_buffer(inputs.as_slice(), outputs.as_mut_slice(), &mut self.host);
+    }
+}
+
+/// The audio streams that are running. The streams are stopped when this is dropped.
+pub struct CpalStreams {
+    _output: Stream,
+    _input: Option<Stream>,
+    sample_rate: u32,
+    output_channels: u16,
+    monitor: MonitorControl,
+}
+
+impl CpalStreams {
+    /// The sample rate that has been negotiated with the output device.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The number of channels of the output device.
+    pub fn output_channels(&self) -> u16 {
+        self.output_channels
+    }
+
+    /// Switches input monitoring on and off, changes its gain and reports the latency of the
+    /// audio devices, see the [module level documentation].
+    ///
+    /// [module level documentation]: ./index.html
+    pub fn monitor(&self) -> &MonitorControl {
+        &self.monitor
+    }
+}
+
+fn build_output_stream<P, T>(
+    device: &Device,
+    config: &StreamConfig,
+    mut processor: CpalProcessor<P>,
+) -> Result<Stream, CpalError>
+where
+    P: AudioHandlerMeta + ContextualAudioRenderer<f32, CpalHost> + Send + 'static,
+    T: SizedSample + FromSample<f32>,
+{
+    let sample_rate = config.sample_rate.0;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], info: &OutputCallbackInfo| {
+                let timestamp = info.timestamp();
+                let output_latency = timestamp
+                    .playback
+                    .duration_since(&timestamp.callback)
+                    .map_or(0, |latency| duration_in_frames(latency, sample_rate));
+                processor.report_latency(output_latency);
+                processor.process(data)
+            },
+            |e| error!("Audio output error: {}", e),
+            None,
+        )
+        .map_err(CpalError::BuildStreamError)
+}
+
+// Push the input to the queue, one whole frame at a time.
+fn push_input<T>(producer: &mut Producer<f32>, data: &[T], channels: usize)
+where
+    T: Sample,
+    f32: FromSample<T>,
+{
+    for frame in data.chunks(channels) {
+        if producer.remaining() < frame.len() {
+            // The output stream is not keeping up; drop the rest of the input.
+            break;
+        }
+        for sample in frame {
+            let _ = producer.push(f32::from_sample(*sample));
+        }
+    }
+}
+
+fn build_input_stream<T>(
+    device: &Device,
+    config: &StreamConfig,
+    mut producer: Producer<f32>,
+    latency_in_frames: Arc<AtomicUsize>,
+) -> Result<Stream, CpalError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = (config.channels as usize).max(1);
+    let sample_rate = config.sample_rate.0;
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], info: &InputCallbackInfo| {
+                let timestamp = info.timestamp();
+                let latency = timestamp
+                    .callback
+                    .duration_since(&timestamp.capture)
+                    .map_or(0, |latency| duration_in_frames(latency, sample_rate));
+                latency_in_frames.store(latency, Ordering::Relaxed);
+                push_input(&mut producer, data, channels)
+            },
+            |e| error!("Audio input error: {}", e),
+            None,
+        )
+        .map_err(CpalError::BuildStreamError)
+}
+
+fn open_input(
+    host: &Host,
+    settings: &CpalSettings,
+    number_of_inputs: usize,
+    sample_rate: u32,
+    buffer_size: BufferSize,
+    maximum_buffer_size: usize,
+    latency_in_frames: Arc<AtomicUsize>,
+) -> Result<Option<(Stream, usize, Consumer<f32>)>, CpalError> {
+    let device = match &settings.input_device {
+        Some(name) => find_device(host.input_devices().map_err(CpalError::DevicesError)?, name)?,
+        None => match host.default_input_device() {
+            Some(device) => device,
+            None => {
+                warn!("No default audio input device, the audio input will be silent.");
+                return Ok(None);
+            }
+        },
+    };
+    let ranges = device
+        .supported_input_configs()
+        .map_err(CpalError::SupportedStreamConfigsError)?;
+    let supported =
+        choose_config(ranges, number_of_inputs, sample_rate).ok_or(CpalError::NoSupportedConfig)?;
+    if supported.sample_rate().0 != sample_rate {
+        return Err(CpalError::InputSampleRateNotSupported(sample_rate));
+    }
+    let mut config = supported.config();
+    config.buffer_size = buffer_size;
+    let channels = config.channels as usize;
+    let (producer, consumer) = RingBuffer::new(4 * maximum_buffer_size * channels).split();
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => {
+            build_input_stream::<f32>(&device, &config, producer, latency_in_frames)?
+        }
+        SampleFormat::I16 => {
+            build_input_stream::<i16>(&device, &config, producer, latency_in_frames)?
+        }
+        SampleFormat::U16 => {
+            build_input_stream::<u16>(&device, &config, producer, latency_in_frames)?
+        }
+        _ => return Err(CpalError::NoSupportedConfig),
+    };
+    Ok(Some((stream, channels, consumer)))
+}
+
+/// Open the audio devices and start rendering audio with the plugin.
+/// The streams keep running until the returned `CpalStreams` is dropped.
+///
+/// See the [module level documentation] for more information.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// This function allocates memory.
+///
+/// [module level documentation]: ./index.html
+pub fn start<P>(plugin: P, settings: &CpalSettings) -> Result<CpalStreams, CpalError>
+where
+    P: AudioHandler + AudioHandlerMeta + ContextualAudioRenderer<f32, CpalHost> + Send + 'static,
+{
+    let host = host(settings.host)?;
+    let device = output_device(&host, settings)?;
+    let preferred_sample_rate = match settings.sample_rate {
+        Some(sample_rate) => sample_rate,
+        None => {
+            device
+                .default_output_config()
+                .map_err(CpalError::DefaultStreamConfigError)?
+                .sample_rate()
+                .0
+        }
+    };
+    let channel_map = output_channel_map(settings, plugin.max_number_of_audio_outputs())?;
+    let wanted_channels = channel_map.iter().map(|channel| channel + 1).max();
+    let ranges = device
+        .supported_output_configs()
+        .map_err(CpalError::SupportedStreamConfigsError)?;
+    let supported = choose_config(ranges, wanted_channels.unwrap_or(0), preferred_sample_rate)
+        .ok_or(CpalError::NoSupportedConfig)?;
+    if settings.output_channel_map.is_some() {
+        if let Some(channel) = channel_map
+            .iter()
+            .find(|channel| **channel >= supported.channels() as usize)
+        {
+            return Err(CpalError::OutputChannelNotAvailable(*channel));
+        }
+    }
+    let mut config = supported.config();
+    let (buffer_size, maximum_buffer_size) = match settings.buffer_size {
+        Some(frames) => (BufferSize::Fixed(frames), frames as usize),
+        None => (BufferSize::Default, DEFAULT_MAXIMUM_BUFFER_SIZE),
+    };
+    config.buffer_size = buffer_size;
+    let sample_rate = config.sample_rate.0;
+    info!(
+        "Opening audio output with {} channels, {} Hz, {:?}",
+        config.channels,
+        sample_rate,
+        supported.sample_format()
+    );
+    let mut plugin = InputMonitor::new(plugin);
+    let monitor = plugin.control();
+    if let Some(gain) = settings.input_monitoring {
+        monitor.set_gain(gain);
+        monitor.set_enabled(true);
+    }
+    plugin.set_sample_rate(sample_rate as f64);
+    plugin.set_max_buffer_size(maximum_buffer_size);
+
+    let number_of_inputs = plugin.inner().max_number_of_audio_inputs();
+    let input_latency_in_frames = Arc::new(AtomicUsize::new(0));
+    let (input_stream, input_channels, consumer) = if number_of_inputs > 0 {
+        match open_input(
+            &host,
+            settings,
+            number_of_inputs,
+            sample_rate,
+            buffer_size,
+            maximum_buffer_size,
+            input_latency_in_frames.clone(),
+        )? {
+            Some((stream, channels, consumer)) => (Some(stream), channels, Some(consumer)),
+            None => (None, number_of_inputs, None),
+        }
+    } else {
+        (None, 0, None)
+    };
+
+    let processor = CpalProcessor::new(
+        plugin,
+        config.channels as usize,
+        channel_map,
+        input_channels,
+        consumer,
+        input_latency_in_frames,
+        maximum_buffer_size,
+    );
+    let output_stream = match supported.sample_format() {
+        SampleFormat::F32 => build_output_stream::<P, f32>(&device, &config, processor)?,
+        SampleFormat::I16 => build_output_stream::<P, i16>(&device, &config, processor)?,
+        SampleFormat::U16 => build_output_stream::<P, u16>(&device, &config, processor)?,
+        _ => return Err(CpalError::NoSupportedConfig),
+    };
+    if let Some(stream) = &input_stream {
+        stream.play().map_err(CpalError::PlayStreamError)?;
+    }
+    output_stream.play().map_err(CpalError::PlayStreamError)?;
+    Ok(CpalStreams {
+        _output: output_stream,
+        _input: input_stream,
+        sample_rate,
+        output_channels: config.channels,
+        monitor,
+    })
+}
+
+/// Run the plugin until the user presses a key on the computer keyboard.
+pub fn run<P>(plugin: P, settings: &CpalSettings) -> Result<(), CpalError>
+where
+    P: AudioHandler + AudioHandlerMeta + ContextualAudioRenderer<f32, CpalHost> + Send + 'static,
+{
+    let streams = start(plugin, settings)?;
+
+    println!("Press any key to quit");
+    let mut user_input = String::new();
+    io::stdin().read_line(&mut user_input).ok();
+
+    info!("Stopping audio...");
+    drop(streams);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpal::SupportedBufferSize;
+
+    struct TestPlugin;
+
+    impl AudioHandlerMeta for TestPlugin {
+        fn max_number_of_audio_inputs(&self) -> usize {
+            1
+        }
+
+        fn max_number_of_audio_outputs(&self) -> usize {
+            2
+        }
+    }
+
+    impl ContextualAudioRenderer<f32, CpalHost> for TestPlugin {
+        fn render_buffer(
+            &mut self,
+            inputs: &[&[f32]],
+            outputs: &mut [&mut [f32]],
+            _: &mut CpalHost,
+        ) {
+            for (index, input) in inputs[0].iter().enumerate() {
+                outputs[0][index] = 2.0 * input;
+                outputs[1][index] = -input;
+            }
+        }
+    }
+
+    fn test_processor(
+        output_channels: usize,
+        output_channel_map: Vec<usize>,
+        input_channels: usize,
+        input: Option<Consumer<f32>>,
+        maximum_buffer_size: usize,
+    ) -> CpalProcessor<TestPlugin> {
+        CpalProcessor::new(
+            InputMonitor::new(TestPlugin),
+            output_channels,
+            output_channel_map,
+            input_channels,
+            input,
+            Arc::new(AtomicUsize::new(0)),
+            maximum_buffer_size,
+        )
+    }
+
+    fn range(
+        channels: u16,
+        min: u32,
+        max: u32,
+        format: SampleFormat,
+    ) -> SupportedStreamConfigRange {
+        SupportedStreamConfigRange::new(
+            channels,
+            SampleRate(min),
+            SampleRate(max),
+            SupportedBufferSize::Unknown,
+            format,
+        )
+    }
+
+    #[test]
+    fn config_is_negotiated() {
+        let ranges = vec![
+            range(1, 44100, 48000, SampleFormat::F32),
+            range(2, 44100, 96000, SampleFormat::I16),
+            range(2, 44100, 44100, SampleFormat::F32),
+            range(4, 44100, 96000, SampleFormat::F32),
+            range(2, 8000, 192000, SampleFormat::I8),
+        ];
+        let choose = |channels, sample_rate| {
+            let config = choose_config(ranges.clone().into_iter(), channels, sample_rate)
+                .expect("a config is supported");
+            (
+                config.channels(),
+                config.sample_rate().0,
+                config.sample_format(),
+            )
+        };
+        assert_eq!(choose(2, 44100), (2, 44100, SampleFormat::F32));
+        assert_eq!(choose(2, 48000), (2, 48000, SampleFormat::I16));
+        assert_eq!(choose(2, 192000), (2, 96000, SampleFormat::I16));
+        assert_eq!(choose(1, 96000), (1, 48000, SampleFormat::F32));
+        assert_eq!(choose(3, 48000), (4, 48000, SampleFormat::F32));
+        assert_eq!(choose(8, 48000), (4, 48000, SampleFormat::F32));
+        assert!(choose_config(
+            vec![range(2, 8000, 192000, SampleFormat::I8)].into_iter(),
+            2,
+            48000
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn buffers_are_deinterleaved_and_interleaved() {
+        let (mut producer, consumer) = RingBuffer::new(64).split();
+        // Two input channels, the plugin only uses the first one.
+        let input = [1.0, 10.0, 2.0, 20.0, 3.0, 30.0, 4.0, 40.0];
+        assert_eq!(producer.push_slice(&input), input.len());
+        let mut processor = test_processor(3, vec![0, 1], 2, Some(consumer), 3);
+        // 5 frames are rendered in two parts; the input runs out after 4 frames.
+        let mut output = [9.0_f32; 15];
+        processor.process(&mut output);
+        assert_eq!(
+            output,
+            [2.0, -1.0, 0.0, 4.0, -2.0, 0.0, 6.0, -3.0, 0.0, 8.0, -4.0, 0.0, 0.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn samples_are_converted() {
+        let mut processor = test_processor(2, vec![0, 1], 1, None, 4);
+        let mut output = [1_i16; 4];
+        processor.process(&mut output);
+        assert_eq!(output, [0; 4]);
+
+        let (mut producer, consumer) = RingBuffer::new(4).split();
+        producer.push_slice(&[0.25, -0.25]);
+        let mut processor = test_processor(2, vec![0, 1], 1, Some(consumer), 4);
+        let mut output = [0_i16; 4];
+        processor.process(&mut output);
+        assert_eq!(output, [16384, -8192, -16384, 8192]);
+    }
+
+    #[test]
+    fn only_whole_frames_are_queued_when_the_input_overflows() {
+        // Room for two frames of two channels and one extra sample.
+        let (mut producer, consumer) = RingBuffer::new(5).split();
+        push_input(&mut producer, &[1.0_f32, 10.0, 2.0, 20.0, 3.0, 30.0], 2);
+        assert_eq!(producer.len(), 4);
+        let mut processor = test_processor(2, vec![0, 1], 2, Some(consumer), 4);
+        let mut output = [9.0_f32; 6];
+        processor.process(&mut output);
+        assert_eq!(output, [2.0, -1.0, 4.0, -2.0, 0.0, 0.0]);
+
+        // The channels stay aligned when the queue contains a partial frame.
+        let (mut producer, consumer) = RingBuffer::new(8).split();
+        producer.push_slice(&[1.0, 10.0, 2.0]);
+        let mut processor = test_processor(2, vec![0, 1], 2, Some(consumer), 4);
+        let mut output = [9.0_f32; 4];
+        processor.process(&mut output);
+        assert_eq!(output, [2.0, -1.0, 0.0, 0.0]);
+        producer.push_slice(&[20.0]);
+        processor.process(&mut output);
+        assert_eq!(output, [4.0, -2.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn outputs_are_sent_to_the_mapped_channels() {
+        let (mut producer, consumer) = RingBuffer::new(4).split();
+        producer.push_slice(&[1.0, 2.0]);
+        let mut processor = test_processor(4, vec![3, 1], 1, Some(consumer), 4);
+        let mut output = [9.0_f32; 8];
+        processor.process(&mut output);
+        assert_eq!(output, [0.0, -1.0, 0.0, 2.0, 0.0, -2.0, 0.0, 4.0]);
+    }
+
+    #[test]
+    fn the_input_is_monitored_and_the_latency_is_reported() {
+        let (mut producer, consumer) = RingBuffer::new(8).split();
+        producer.push_slice(&[1.0, 2.0, 3.0]);
+        let mut processor = test_processor(2, vec![0, 1], 1, Some(consumer), 2);
+        processor
+            .input_latency_in_frames
+            .store(10, Ordering::Relaxed);
+        let monitor = processor.monitor.clone();
+        monitor.set_enabled(true);
+        processor.report_latency(48);
+        assert_eq!(monitor.latency().dry_in_frames, 61);
+        // The gain is ramped up during the first buffer.
+        let mut output = [0.0_f32; 4];
+        processor.process(&mut output);
+        assert_eq!(output, [2.5, -0.5, 6.0, 0.0]);
+        assert_eq!(duration_in_frames(Duration::from_millis(2), 48000), 96);
+    }
+
+    #[test]
+    fn output_channel_map_is_validated() {
+        let settings = |map: Option<Vec<usize>>| CpalSettings {
+            output_channel_map: map,
+            ..CpalSettings::default()
+        };
+        assert_eq!(
+            output_channel_map(&settings(None), 2).expect("no map is valid"),
+            vec![0, 1]
+        );
+        assert_eq!(
+            output_channel_map(&settings(Some(vec![5, 2])), 2).expect("the map is valid"),
+            vec![5, 2]
+        );
+        match output_channel_map(&settings(Some(vec![2, 2])), 2) {
+            Err(CpalError::DuplicateOutputChannel(2)) => {}
+            other => panic!("duplicate channel should be rejected, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! A minimal back-end for embedded targets that sends the audio to an I2S DAC.
+//!
+//! Support is only enabled if you compile with the "backend-i2s" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//! This back-end does not need the `std` feature and does not allocate memory: the audio is
+//! rendered in fixed-size buffers of `FRAMES` frames that are part of the [`I2sOutput`].
+//!
+//! The plugin renders stereo audio: it gets no inputs and two outputs (left and right).
+//! The rendered audio is converted to interleaved frames (`[left, right]`) of `i16` or
+//! left-justified 24-bit samples in an `i32`, which is the memory layout that I2S
+//! peripherals expect.
+//!
+//! The hardware is abstracted by the [`I2sTransmit`] trait, which can be implemented with the
+//! hardware abstraction layer of the micro-controller (blocking writes), or you can call
+//! [`I2sOutput::fill`] to fill the half of a DMA buffer that has just been sent:
+//!
+//! ```
+//! use rsynth::backend::i2s::{I2sHost, I2sOutput, I2sTransmit};
+//! use rsynth::{AudioHandler, ContextualAudioRenderer};
+//!
+//! struct Square {
+//!     phase: u32,
+//! }
+//!
+//! impl AudioHandler for Square {
+//!     fn set_sample_rate(&mut self, sample_rate: f64) {}
+//! }
+//!
+//! impl ContextualAudioRenderer<f32, I2sHost> for Square {
+//!     fn render_buffer(&mut self, _: &[&[f32]], outputs: &mut [&mut [f32]], _: &mut I2sHost) {
+//!         for frame in 0..outputs[0].len() {
+//!             self.phase = (self.phase + 1) % 100;
+//!             let value = if self.phase < 50 { 0.25 } else { -0.25 };
+//!             for output in outputs.iter_mut() {
+//!                 output[frame] = value;
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! // The I2S peripheral, implemented with the HAL of the micro-controller.
+//! struct Dac;
+//!
+//! impl I2sTransmit<i16> for Dac {
+//!     type Error = ();
+//!     fn write(&mut self, frames: &[[i16; 2]]) -> Result<(), Self::Error> {
+//!         // Write the frames to the data register, blocking until there is room.
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut output: I2sOutput<_, 32> = I2sOutput::new(Square { phase: 0 }, 48000.0);
+//! let mut dac = Dac;
+//! # let mut buffers = 0;
+//! loop {
+//!     // Handle the midi input, e.g. from a UART, with `output.plugin_mut()`.
+//!     output.transmit(&mut dac).expect("the DAC does not fail");
+//! #   buffers += 1;
+//! #   if buffers == 4 { break; }
+//! }
+//! ```
+//!
+//! Events that are received between buffers (e.g. from a UART interrupt) can be collected in
+//! a [`FixedEventQueue`] and passed to the plugin before rendering the next buffer.
+//!
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`I2sOutput`]: ./struct.I2sOutput.html
+//! [`I2sTransmit`]: ./trait.I2sTransmit.html
+//! [`I2sOutput::fill`]: ./struct.I2sOutput.html#method.fill
+//! [`FixedEventQueue`]: ../../event/fixed_event_queue/struct.FixedEventQueue.html
+use crate::backend::HostInterface;
+use crate::{AudioHandler, ContextualAudioRenderer};
+
+/// A sample format that can be sent to an I2S DAC.
+pub trait I2sSample: Copy + Default {
+    /// Convert the sample, clipping values outside the range `-1.0..=1.0`.
+    fn from_f32(sample: f32) -> Self;
+}
+
+impl I2sSample for i16 {
+    fn from_f32(sample: f32) -> Self {
+        (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+    }
+}
+
+/// 24-bit samples, left-justified in 32 bits.
+impl I2sSample for i32 {
+    fn from_f32(sample: f32) -> Self {
+        const MAX_24_BIT: f32 = ((1 << 23) - 1) as f32;
+        ((sample.clamp(-1.0, 1.0) * MAX_24_BIT) as i32) << 8
+    }
+}
+
+/// The I2S peripheral, when writing in a blocking way.
+pub trait I2sTransmit<S> {
+    type Error;
+
+    /// Send the frames (`[left, right]`) to the DAC, blocking until they have all been
+    /// written to the peripheral.
+    fn write(&mut self, frames: &[[S; 2]]) -> Result<(), Self::Error>;
+}
+
+/// The context that is passed to the plugin when rendering audio.
+pub struct I2sHost {
+    _private: (),
+}
+
+impl HostInterface for I2sHost {
+    fn output_initialized(&self) -> bool {
+        false
+    }
+}
+
+/// Renders the audio of the plugin in buffers of `FRAMES` frames and converts it for an
+/// I2S DAC.
+///
+/// See the [module level documentation] for an example.
+///
+/// [module level documentation]: ./index.html
+pub struct I2sOutput<P, const FRAMES: usize> {
+    plugin: P,
+    host: I2sHost,
+    left: [f32; FRAMES],
+    right: [f32; FRAMES],
+}
+
+impl<P, const FRAMES: usize> I2sOutput<P, FRAMES>
+where
+    P: AudioHandler + ContextualAudioRenderer<f32, I2sHost>,
+{
+    /// Create a new `I2sOutput` and set the sample rate and the maximum buffer size (`FRAMES`)
+    /// of the plugin.
+    ///
+    /// # Panics
+    /// Panics if `FRAMES` is zero.
+    pub fn new(mut plugin: P, sample_rate: f64) -> Self {
+        assert!(FRAMES > 0, "The buffer size should be positive.");
+        plugin.set_sample_rate(sample_rate);
+        plugin.set_max_buffer_size(FRAMES);
+        I2sOutput {
+            plugin,
+            host: I2sHost { _private: () },
+            left: [0.0; FRAMES],
+            right: [0.0; FRAMES],
+        }
+    }
+
+    pub fn plugin(&self) -> &P {
+        &self.plugin
+    }
+
+    pub fn plugin_mut(&mut self) -> &mut P {
+        &mut self.plugin
+    }
+
+    pub fn into_plugin(self) -> P {
+        self.plugin
+    }
+
+    /// Render audio into `frames`, e.g. the half of a DMA buffer that has just been sent.
+    /// `frames` may have any length; longer buffers are rendered in parts of at most `FRAMES`
+    /// frames.
+    pub fn fill<S: I2sSample>(&mut self, frames: &mut [[S; 2]]) {
+        for chunk in frames.chunks_mut(FRAMES) {
+            let length = chunk.len();
+            self.render(length);
+            for ((frame, left), right) in chunk.iter_mut().zip(&self.left).zip(&self.right) {
+                *frame = [S::from_f32(*left), S::from_f32(*right)];
+            }
+        }
+    }
+
+    /// Render one buffer of `FRAMES` frames and write it to the transmitter.
+    pub fn transmit<S, T>(&mut self, transmitter: &mut T) -> Result<(), T::Error>
+    where
+        S: I2sSample,
+        T: I2sTransmit<S>,
+    {
+        let mut frames = [[S::default(); 2]; FRAMES];
+        self.fill(&mut frames);
+        transmitter.write(&frames)
+    }
+
+    fn render(&mut self, length: usize) {
+        let mut outputs = [&mut self.left[..length], &mut self.right[..length]];
+        self.plugin.render_buffer(&[], &mut outputs, &mut self.host);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Ramp {
+        value: f32,
+        sample_rate: f64,
+        max_buffer_size: usize,
+        buffer_sizes: Vec<usize>,
+    }
+
+    impl AudioHandler for Ramp {
+        fn set_sample_rate(&mut self, sample_rate: f64) {
+            self.sample_rate = sample_rate;
+        }
+
+        fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+            self.max_buffer_size = max_buffer_size;
+        }
+    }
+
+    impl ContextualAudioRenderer<f32, I2sHost> for Ramp {
+        fn render_buffer(&mut self, _: &[&[f32]], outputs: &mut [&mut [f32]], _: &mut I2sHost) {
+            self.buffer_sizes.push(outputs[0].len());
+            for frame in 0..outputs[0].len() {
+                outputs[0][frame] = self.value;
+                outputs[1][frame] = -self.value;
+                self.value += 0.5;
+            }
+        }
+    }
+
+    fn ramp() -> Ramp {
+        Ramp {
+            value: 0.0,
+            sample_rate: 0.0,
+            max_buffer_size: 0,
+            buffer_sizes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn samples_are_converted_and_clipped() {
+        assert_eq!(i16::from_f32(0.0), 0);
+        assert_eq!(i16::from_f32(1.0), i16::MAX);
+        assert_eq!(i16::from_f32(-2.0), -i16::MAX);
+        assert_eq!(i32::from_f32(1.0), 0x7fff_ff00);
+        assert_eq!(i32::from_f32(-1.0), -0x7fff_ff00);
+    }
+
+    #[test]
+    fn fill_renders_in_parts_of_at_most_frames() {
+        let mut output: I2sOutput<_, 2> = I2sOutput::new(ramp(), 48000.0);
+        assert_eq!(output.plugin().sample_rate, 48000.0);
+        assert_eq!(output.plugin().max_buffer_size, 2);
+        let mut frames = [[0_i16; 2]; 3];
+        output.fill(&mut frames);
+        assert_eq!(output.plugin().buffer_sizes, vec![2, 1]);
+        assert_eq!(
+            frames,
+            [[0, 0], [i16::MAX / 2, -i16::MAX / 2], [i16::MAX, -i16::MAX]]
+        );
+    }
+
+    struct Collector {
+        frames: Vec<[i32; 2]>,
+    }
+
+    impl I2sTransmit<i32> for Collector {
+        type Error = ();
+        fn write(&mut self, frames: &[[i32; 2]]) -> Result<(), ()> {
+            self.frames.extend_from_slice(frames);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn transmit_writes_one_buffer() {
+        let mut output: I2sOutput<_, 4> = I2sOutput::new(ramp(), 44100.0);
+        let mut collector = Collector { frames: Vec::new() };
+        output
+            .transmit(&mut collector)
+            .expect("collecting does not fail");
+        assert_eq!(collector.frames.len(), 4);
+        assert_eq!(collector.frames[0], [0, 0]);
+        assert_eq!(collector.frames[3], [0x7fff_ff00, -0x7fff_ff00]);
+    }
+}
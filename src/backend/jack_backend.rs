@@ -6,28 +6,545 @@
 //! `examples/test_synth.rs` contains the code that is shared for all backends and
 //! `examples/jack_synth.rs` contains the jack-specific code.
 //!
+//! System exclusive messages
+//! --------------------------
+//! System exclusive messages that are delivered in fragments are reassembled with a
+//! [`SysExReassembler`] before they are passed to the plugin. Messages longer than 4096 bytes
+//! are discarded.
+//!
+//! Midi output
+//! -----------
+//! A midi output port is registered for each midi output of the plugin, with the name given by
+//! [`CommonMidiPortMeta::midi_output_name`]. When rendering, the plugin can write events to
+//! these ports with [`JackHost::write_midi`] and [`JackHost::write_sysex`], or by passing
+//! `Indexed<Timed<RawMidiEvent>>` events to the `JackHost`, which implements `EventHandler`.
+//! Events must be written in chronological order per port.
+//!
+//! Metadata
+//! --------
+//! The ports are described with the JACK metadata API, so that session managers (e.g. Carla)
+//! can display them: the pretty name of a port is its name, followed by the speaker of the
+//! channel layout for audio ports (e.g. `"Main out (L)"`), and the order of the ports is their
+//! index. Audio ports get the signal type `AUDIO` and midi ports the event type `MIDI`.
+//!
+//! Connecting ports
+//! ----------------
+//! Use [`run_with_options`] or [`start`] with [`JackOptions`] to connect the ports of the
+//! plugin to other clients upon activation, e.g. the audio outputs to `system:playback_.*`.
+//!
+//! Starting and stopping
+//! ---------------------
+//! [`run`] and [`run_with_options`] run the plugin until the user presses a key. Applications
+//! with a GUI and tests can call [`start`] instead, which returns an [`ActiveJackClient`].
+//! Call [`ActiveJackClient::stop`], e.g. from another thread, to deactivate the client and get
+//! the plugin back.
+//! The plugin's [`Lifecycle::on_resume`] is called just before the client is activated and
+//! [`Lifecycle::on_suspend`] after the client has been deactivated.
+//!
+//! Transport
+//! ---------
+//! The [`JackHost`] implements [`TransportContext`], so the plugin can follow the JACK
+//! transport when rendering. The musical position is only known when a JACK client acts as
+//! timebase master; the position in frames and whether the transport is rolling are
+//! always available with [`JackHost::jack_transport`].
+//!
+//! Sample rate
+//! -----------
+//! The plugin's `set_sample_rate` is called before the client is activated. When the JACK
+//! server changes its sample rate, `set_sample_rate` is called again at the start of the
+//! next process cycle, before any events are passed and before `render_buffer` is called.
+//!
+//! Buffer size
+//! -----------
+//! The plugin's `set_max_buffer_size` is called with the buffer size of the JACK server before
+//! the client is activated. When the buffer size changes, JACK calls the buffer-size callback
+//! of the client, which calls `set_max_buffer_size` with the new buffer size. JACK does not
+//! process audio while it changes the buffer size, so the plugin can resize its internal
+//! buffers there. Should a process cycle nevertheless start while `set_max_buffer_size` is
+//! running, the outputs of that cycle are silent.
+//!
+//! Xruns
+//! -----
+//! Xruns (buffer under- or overruns, which cause audible dropouts) are logged as a warning.
+//! The plugin can check for xruns with [`JackHost::xruns_since_previous_cycle`], e.g. to
+//! display the dropouts or to reset its state.
+//!
+//! Freewheel mode
+//! --------------
+//! In freewheel mode, the JACK server does not wait for the audio interface, but runs the
+//! process cycles as fast as possible, e.g. to bounce a live setup to disk faster than real
+//! time. Set [`JackOptions::freewheel`] to enter freewheel mode upon activation or call
+//! [`set_freewheel`] to enter or leave it. Because any client can switch the server to
+//! freewheel mode, the plugin can check this with [`JackHost::is_freewheeling`].
+//!
+//! Latency
+//! -------
+//! The plugin reports its latency with the [`Latency`] trait. When JACK computes the latencies
+//! of the graph, the latency of the plugin is added to the latency of its input ports and the
+//! result is set on its output ports, so that other clients can compensate for it.
+//! When the latency of the plugin changes, JACK is asked to recompute the latencies of the
+//! graph, so that the other clients are notified. This cannot be done in the process cycle,
+//! so it is done by a separate thread that checks for changes every 100 milliseconds.
+//!
 //! [JACK]: http://www.jackaudio.org/
+//! [`SysExReassembler`]: ../../event/sysex/struct.SysExReassembler.html
+//! [`CommonMidiPortMeta::midi_output_name`]: ../../trait.CommonMidiPortMeta.html#method.midi_output_name
+//! [`JackHost::write_midi`]: ./struct.JackHost.html#method.write_midi
+//! [`JackHost`]: ./struct.JackHost.html
+//! [`run`]: ./fn.run.html
+//! [`run_with_options`]: ./fn.run_with_options.html
+//! [`start`]: ./fn.start.html
+//! [`ActiveJackClient`]: ./struct.ActiveJackClient.html
+//! [`ActiveJackClient::stop`]: ./struct.ActiveJackClient.html#method.stop
+//! [`JackOptions`]: ./struct.JackOptions.html
+//! [`TransportContext`]: ../../transport/trait.TransportContext.html
+//! [`JackHost::jack_transport`]: ./struct.JackHost.html#method.jack_transport
+//! [`JackHost::write_sysex`]: ./struct.JackHost.html#method.write_sysex
+//! [`Latency`]: ../../trait.Latency.html
+//! [`Lifecycle::on_resume`]: ../../trait.Lifecycle.html#method.on_resume
+//! [`Lifecycle::on_suspend`]: ../../trait.Lifecycle.html#method.on_suspend
+//! [`JackOptions::freewheel`]: ./struct.JackOptions.html#structfield.freewheel
+//! [`set_freewheel`]: ./fn.set_freewheel.html
+//! [`JackHost::is_freewheeling`]: ./struct.JackHost.html#method.is_freewheeling
+//! [`JackHost::xruns_since_previous_cycle`]: ./struct.JackHost.html#method.xruns_since_previous_cycle
 //! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+use crate::channel_layout::ChannelLayout;
+use crate::event::sysex::{SysExOverflowPolicy, SysExReassembler, START_OF_EXCLUSIVE};
 use crate::event::{EventHandler, Indexed};
+use crate::transport::{TimeSignature, TransportContext, TransportState};
+#[cfg(feature = "flush-denormals")]
+use crate::utilities::denormals::DenormalGuard;
 use crate::{
     backend::HostInterface,
     event::{ContextualEventHandler, RawMidiEvent, SysExEvent, Timed},
     AudioHandler, CommonAudioPortMeta, CommonMidiPortMeta, CommonPluginMeta,
-    ContextualAudioRenderer,
+    ContextualAudioRenderer, Latency, Lifecycle,
 };
 use core::cmp;
 use jack::{AudioIn, AudioOut, MidiIn, MidiOut, Port, ProcessScope, RawMidi};
-use jack::{Client, ClientOptions, Control, ProcessHandler};
+use jack::{
+    Client, ClientOptions, Control, Frames, LatencyType, NotificationHandler, PortFlags,
+    ProcessHandler,
+};
+use std::ffi::CString;
 use std::io;
+use std::os::raw::c_int;
+use std::ptr;
 use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 use vecstorage::VecStorage;
 
+/// The subset of the JACK C API that is not exposed by the `jack` crate.
+///
+/// These types mirror the definitions in the header `jack/types.h`.
+mod sys {
+    use std::os::raw::{c_char, c_int};
+
+    pub enum JackClient {}
+    pub enum JackPort {}
+
+    pub type JackUuid = u64;
+
+    pub const METADATA_PRETTY_NAME: &str = "http://jackaudio.org/metadata/pretty-name";
+    pub const METADATA_ORDER: &str = "http://jackaudio.org/metadata/order";
+    pub const METADATA_SIGNAL_TYPE: &str = "http://jackaudio.org/metadata/signal-type";
+    pub const METADATA_EVENT_TYPES: &str = "http://jackaudio.org/metadata/event-types";
+    pub const XSD_INTEGER: &str = "http://www.w3.org/2001/XMLSchema#integer";
+
+    pub const DEFAULT_AUDIO_TYPE: &str = "32 bit float mono audio";
+    pub const DEFAULT_MIDI_TYPE: &str = "8 bit raw midi";
+
+    pub type JackTransportState = c_int;
+    pub const TRANSPORT_ROLLING: JackTransportState = 1;
+    pub const TRANSPORT_LOOPING: JackTransportState = 2;
+
+    pub type JackPositionBits = c_int;
+    pub const POSITION_BBT: JackPositionBits = 0x10;
+
+    pub type JackLatencyCallbackMode = c_int;
+    pub const CAPTURE_LATENCY: JackLatencyCallbackMode = 0;
+    pub const PLAYBACK_LATENCY: JackLatencyCallbackMode = 1;
+
+    /// `jack_latency_range_t`
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct JackLatencyRange {
+        pub min: u32,
+        pub max: u32,
+    }
+
+    /// `jack_position_t`
+    #[repr(C, packed)]
+    #[derive(Clone, Copy, Default)]
+    pub struct JackPosition {
+        pub unique_1: u64,
+        pub usecs: u64,
+        pub frame_rate: u32,
+        pub frame: u32,
+        pub valid: JackPositionBits,
+        pub bar: i32,
+        pub beat: i32,
+        pub tick: i32,
+        pub bar_start_tick: f64,
+        pub beats_per_bar: f32,
+        pub beat_type: f32,
+        pub ticks_per_beat: f64,
+        pub beats_per_minute: f64,
+        pub frame_time: f64,
+        pub next_time: f64,
+        pub bbt_offset: u32,
+        pub audio_frames_per_video_frame: f32,
+        pub video_offset: u32,
+        pub tick_double: f64,
+        pub padding: [i32; 5],
+        pub unique_2: u64,
+    }
+
+    #[link(name = "jack")]
+    extern "C" {
+        pub fn jack_transport_query(
+            client: *const JackClient,
+            position: *mut JackPosition,
+        ) -> JackTransportState;
+
+        pub fn jack_set_freewheel(client: *mut JackClient, onoff: c_int) -> c_int;
+
+        pub fn jack_port_uuid(port: *const JackPort) -> JackUuid;
+
+        pub fn jack_set_property(
+            client: *mut JackClient,
+            subject: JackUuid,
+            key: *const c_char,
+            value: *const c_char,
+            type_: *const c_char,
+        ) -> c_int;
+
+        pub fn jack_port_get_latency_range(
+            port: *mut JackPort,
+            mode: JackLatencyCallbackMode,
+            range: *mut JackLatencyRange,
+        );
+
+        pub fn jack_port_set_latency_range(
+            port: *mut JackPort,
+            mode: JackLatencyCallbackMode,
+            range: *mut JackLatencyRange,
+        );
+
+        pub fn jack_recompute_total_latencies(client: *mut JackClient) -> c_int;
+    }
+}
+
+/// Options for running the plugin with the JACK backend.
+///
+/// The port name patterns are regular expressions that are matched against the full names
+/// of the ports of other clients (e.g. `system:playback_.*`), as with `jack_lsp` or
+/// `jack_get_ports`. Upon activation, the ports of the plugin are connected to the matching
+/// ports in the order in which JACK lists them: the first port of the plugin to the first
+/// matching port, the second to the second, and so on.
+/// Ports are not connected when the pattern is `None`.
+///
+/// # Example
+/// ```
+/// use rsynth::backend::jack_backend::JackOptions;
+///
+/// let options = JackOptions {
+///     connect_audio_outputs_to: Some("system:playback_.*".to_string()),
+///     connect_midi_inputs_from: Some("Keystation".to_string()),
+///     ..JackOptions::default()
+/// };
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct JackOptions {
+    /// The name of the JACK client, e.g. the client id given by a session manager.
+    /// When this is `None`, the name of the plugin is used.
+    pub client_name: Option<String>,
+    /// Connect the audio inputs from the audio outputs of other clients that match.
+    pub connect_audio_inputs_from: Option<String>,
+    /// Connect the audio outputs to the audio inputs of other clients that match.
+    pub connect_audio_outputs_to: Option<String>,
+    /// Connect the midi inputs from the midi outputs of other clients that match.
+    pub connect_midi_inputs_from: Option<String>,
+    /// Connect the midi outputs to the midi inputs of other clients that match.
+    pub connect_midi_outputs_to: Option<String>,
+    /// Switch the JACK server to freewheel mode upon activation and back to real-time
+    /// mode before deactivation.
+    pub freewheel: bool,
+}
+
+/// The error that is returned when the JACK server could not enter or leave freewheel mode.
+#[derive(Debug)]
+pub struct FreewheelError;
+
+/// Ask the JACK server to enter (`enabled == true`) or leave (`enabled == false`)
+/// freewheel mode.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// This method sends a request to the JACK server and should not be called from the
+/// process callback.
+pub fn set_freewheel(client: &Client, enabled: bool) -> Result<(), FreewheelError> {
+    let result =
+        unsafe { sys::jack_set_freewheel(client.raw() as *mut sys::JackClient, enabled as c_int) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(FreewheelError)
+    }
+}
+
+// Connect each of the `own_ports` with the matching port with the same index.
+fn auto_connect(
+    client: &Client,
+    own_ports: &[String],
+    pattern: &Option<String>,
+    port_type: &str,
+    own_ports_are_outputs: bool,
+) {
+    let pattern = match pattern {
+        Some(pattern) => pattern,
+        None => return,
+    };
+    let flags = if own_ports_are_outputs {
+        PortFlags::IS_INPUT
+    } else {
+        PortFlags::IS_OUTPUT
+    };
+    let other_ports = client.ports(Some(pattern), Some(port_type), flags);
+    if other_ports.len() < own_ports.len() {
+        warn!(
+            "Only {} of {} ports can be connected: no more ports match {}",
+            other_ports.len(),
+            own_ports.len(),
+            pattern
+        );
+    }
+    for (own_port, other_port) in own_ports.iter().zip(other_ports.iter()) {
+        let (source, destination) = if own_ports_are_outputs {
+            (own_port, other_port)
+        } else {
+            (other_port, own_port)
+        };
+        info!("Connecting {} to {}", source, destination);
+        if let Err(e) = client.connect_ports_by_name(source, destination) {
+            warn!("Failed to connect {} to {}: {:?}", source, destination, e);
+        }
+    }
+}
+
+// The full names of the ports of the plugin, so that they can be connected after the
+// ports have been moved to the process handler.
+struct PortNames {
+    audio_inputs: Vec<String>,
+    audio_outputs: Vec<String>,
+    midi_inputs: Vec<String>,
+    midi_outputs: Vec<String>,
+}
+
+impl PortNames {
+    fn auto_connect(&self, client: &Client, options: &JackOptions) {
+        let audio = sys::DEFAULT_AUDIO_TYPE;
+        let midi = sys::DEFAULT_MIDI_TYPE;
+        let o = options;
+        auto_connect(
+            client,
+            &self.audio_inputs,
+            &o.connect_audio_inputs_from,
+            audio,
+            false,
+        );
+        auto_connect(
+            client,
+            &self.audio_outputs,
+            &o.connect_audio_outputs_to,
+            audio,
+            true,
+        );
+        auto_connect(
+            client,
+            &self.midi_inputs,
+            &o.connect_midi_inputs_from,
+            midi,
+            false,
+        );
+        auto_connect(
+            client,
+            &self.midi_outputs,
+            &o.connect_midi_outputs_to,
+            midi,
+            true,
+        );
+    }
+}
+
+/// A position in bars, beats and ticks, as provided by the JACK timebase master.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct JackBarBeatTick {
+    /// The current bar, starting from 1.
+    pub bar: i32,
+    /// The current beat within the bar, starting from 1.
+    pub beat: i32,
+    /// The current tick within the beat, starting from 0.
+    pub tick: i32,
+    pub ticks_per_beat: f64,
+    /// The time signature, e.g. 6/8 has 6 beats per bar and beat type 8.
+    pub beats_per_bar: f32,
+    pub beat_type: f32,
+    /// The tempo, in beats of the beat type per minute.
+    pub beats_per_minute: f64,
+}
+
+/// The state of the JACK transport at the start of the current buffer.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct JackTransport {
+    /// `true` when the transport is rolling, `false` when it is stopped or starting.
+    pub is_rolling: bool,
+    /// The position of the transport in frames.
+    pub frame: u32,
+    /// The musical position, `None` when no JACK client acts as timebase master.
+    pub bar_beat_tick: Option<JackBarBeatTick>,
+}
+
+impl JackTransport {
+    fn from_raw(state: sys::JackTransportState, position: &sys::JackPosition) -> Self {
+        let position = *position;
+        let bar_beat_tick = if position.valid & sys::POSITION_BBT != 0 {
+            Some(JackBarBeatTick {
+                bar: position.bar,
+                beat: position.beat,
+                tick: position.tick,
+                ticks_per_beat: position.ticks_per_beat,
+                beats_per_bar: position.beats_per_bar,
+                beat_type: position.beat_type,
+                beats_per_minute: position.beats_per_minute,
+            })
+        } else {
+            None
+        };
+        JackTransport {
+            is_rolling: state == sys::TRANSPORT_ROLLING || state == sys::TRANSPORT_LOOPING,
+            frame: position.frame,
+            bar_beat_tick,
+        }
+    }
+
+    /// Convert to a [`TransportState`], with positions and tempo in quarter notes.
+    /// Returns `None` when the musical position is not known.
+    ///
+    /// [`TransportState`]: ../../transport/struct.TransportState.html
+    pub fn to_transport_state(&self) -> Option<TransportState> {
+        let bbt = self.bar_beat_tick?;
+        if bbt.beat_type <= 0.0 || bbt.ticks_per_beat <= 0.0 {
+            return None;
+        }
+        let quarter_notes_per_beat = 4.0 / bbt.beat_type as f64;
+        let beats = (bbt.bar - 1).max(0) as f64 * bbt.beats_per_bar as f64
+            + (bbt.beat - 1).max(0) as f64
+            + bbt.tick as f64 / bbt.ticks_per_beat;
+        Some(TransportState {
+            is_playing: self.is_rolling,
+            position_in_beats: beats * quarter_notes_per_beat,
+            tempo: bbt.beats_per_minute * quarter_notes_per_beat,
+            time_signature: TimeSignature::new(
+                bbt.beats_per_bar.round() as u32,
+                bbt.beat_type.round() as u32,
+            ),
+            loop_range: None,
+        })
+    }
+}
+
+/// The context that is passed to the plugin when rendering audio and handling events.
 pub struct JackHost<'c, 'mp, 'mw> {
-    // Note: the `_client` field is currently not used, but is is rather likely that it
-    // will be used in the future. Because it may introduce an extra complexity because of
-    // the lifetime, we keep it, so that we can keep track of this complexity.
-    _client: &'c Client,
+    client: &'c Client,
     midi_out_ports: &'mp mut [jack::MidiWriter<'mw>],
+    xruns_since_previous_cycle: usize,
+    is_freewheeling: bool,
+}
+
+/// The error type that represents the errors you can get when writing a midi event to a
+/// midi output port.
+#[derive(Debug)]
+pub enum MidiWriteError {
+    /// There is no midi output port with the given index.
+    PortIndexOutOfBounds(usize),
+    /// JACK could not write the event, e.g. because there is no more room in the buffer or
+    /// because the event is not later than the previous event written to the port.
+    WriteFailed(jack::Error),
+}
+
+impl<'c, 'mp, 'mw> JackHost<'c, 'mp, 'mw> {
+    /// Query the state of the JACK transport at the start of the current buffer.
+    ///
+    /// This method does not allocate memory and can be used in a real-time context.
+    pub fn jack_transport(&self) -> JackTransport {
+        let mut position = sys::JackPosition::default();
+        let state = unsafe {
+            sys::jack_transport_query(self.client.raw() as *const sys::JackClient, &mut position)
+        };
+        JackTransport::from_raw(state, &position)
+    }
+
+    /// The number of xruns that JACK has reported since the previous process cycle.
+    /// An xrun means that a buffer could not be processed in time and that the audio
+    /// has dropped out.
+    ///
+    /// This method does not allocate memory and can be used in a real-time context.
+    pub fn xruns_since_previous_cycle(&self) -> usize {
+        self.xruns_since_previous_cycle
+    }
+
+    /// Whether the JACK server is in freewheel mode, i.e. whether the plugin is rendering
+    /// faster than real time.
+    ///
+    /// This method does not allocate memory and can be used in a real-time context.
+    pub fn is_freewheeling(&self) -> bool {
+        self.is_freewheeling
+    }
+
+    /// The number of midi output ports.
+    pub fn number_of_midi_outputs(&self) -> usize {
+        self.midi_out_ports.len()
+    }
+
+    /// Write a midi event to the midi output port with the given index.
+    ///
+    /// The events that are written to one port must be in chronological order and their
+    /// `time_in_frames` must be smaller than the number of frames of the current buffer.
+    ///
+    /// This method does not allocate memory and can be used in a real-time context.
+    pub fn write_midi(
+        &mut self,
+        port_index: usize,
+        event: Timed<RawMidiEvent>,
+    ) -> Result<(), MidiWriteError> {
+        self.write(port_index, event.time_in_frames, event.event.bytes())
+    }
+
+    /// Write a system exclusive event to the midi output port with the given index.
+    /// See [`write_midi`] for more information.
+    ///
+    /// [`write_midi`]: #method.write_midi
+    pub fn write_sysex(
+        &mut self,
+        port_index: usize,
+        event: Timed<SysExEvent>,
+    ) -> Result<(), MidiWriteError> {
+        self.write(port_index, event.time_in_frames, event.event.data())
+    }
+
+    fn write(&mut self, port_index: usize, time: u32, bytes: &[u8]) -> Result<(), MidiWriteError> {
+        let midi_out_port = self
+            .midi_out_ports
+            .get_mut(port_index)
+            .ok_or(MidiWriteError::PortIndexOutOfBounds(port_index))?;
+        midi_out_port
+            .write(&RawMidi { time, bytes })
+            .map_err(MidiWriteError::WriteFailed)
+    }
 }
 
 impl<'c, 'mp, 'mw> HostInterface for JackHost<'c, 'mp, 'mw> {
@@ -36,21 +553,17 @@ impl<'c, 'mp, 'mw> HostInterface for JackHost<'c, 'mp, 'mw> {
     }
 }
 
+impl<'c, 'mp, 'mw> TransportContext for JackHost<'c, 'mp, 'mw> {
+    fn transport(&self) -> Option<TransportState> {
+        self.jack_transport().to_transport_state()
+    }
+}
+
 impl<'c, 'mp, 'mw> EventHandler<Indexed<Timed<RawMidiEvent>>> for JackHost<'c, 'mp, 'mw> {
     fn handle_event(&mut self, event: Indexed<Timed<RawMidiEvent>>) {
         let Indexed { index, event } = event;
-        if let Some(ref mut midi_out_port) = self.midi_out_ports.get_mut(index).as_mut() {
-            let raw_midi = RawMidi {
-                time: event.time_in_frames,
-                bytes: event.event.data(),
-            };
-            midi_out_port.write(&raw_midi); // TODO: error handling.
-        } else {
-            error!(
-                "midi port out of bounds: port index is {}, but only {} ports are available",
-                index,
-                self.midi_out_ports.len()
-            );
+        if let Err(e) = self.write_midi(index, event) {
+            error!("Failed to write midi event to port {}: {:?}", index, e);
         }
     }
 }
@@ -58,33 +571,96 @@ impl<'c, 'mp, 'mw> EventHandler<Indexed<Timed<RawMidiEvent>>> for JackHost<'c, '
 impl<'c, 'mp, 'mw, 'e> EventHandler<Indexed<Timed<SysExEvent<'e>>>> for JackHost<'c, 'mp, 'mw> {
     fn handle_event(&mut self, event: Indexed<Timed<SysExEvent>>) {
         let Indexed { index, event } = event;
-        if let Some(ref mut midi_out_port) = self.midi_out_ports.get_mut(index).as_mut() {
-            let raw_midi = RawMidi {
-                time: event.time_in_frames,
-                bytes: event.event.data(),
-            };
-            midi_out_port.write(&raw_midi); // TODO: error handling.
-        } else {
+        if let Err(e) = self.write_sysex(index, event) {
             error!(
-                "midi port out of bounds: port index is {}, but only {} ports are available",
-                index,
-                self.midi_out_ports.len()
+                "Failed to write system exclusive event to port {}: {:?}",
+                index, e
             );
         }
     }
 }
 
+// The human-readable name of an audio port, e.g. "Main out (L)".
+fn audio_port_pretty_name(name: &str, layout: &ChannelLayout, index: usize) -> String {
+    match layout.speaker(index) {
+        Some(speaker) => format!("{} ({})", name, speaker),
+        None => name.to_string(),
+    }
+}
+
+// Describe the port with the JACK metadata API, so that session managers can display it.
+fn set_port_metadata<PS>(
+    client: &Client,
+    port: &Port<PS>,
+    pretty_name: &str,
+    order: usize,
+    is_audio: bool,
+) {
+    let order = order.to_string();
+    let (type_key, type_value) = if is_audio {
+        (sys::METADATA_SIGNAL_TYPE, "AUDIO")
+    } else {
+        (sys::METADATA_EVENT_TYPES, "MIDI")
+    };
+    let properties = [
+        (sys::METADATA_PRETTY_NAME, pretty_name, None),
+        (sys::METADATA_ORDER, order.as_str(), Some(sys::XSD_INTEGER)),
+        (type_key, type_value, None),
+    ];
+    let subject = unsafe { sys::jack_port_uuid(port.raw() as *const sys::JackPort) };
+    for (key, value, value_type) in properties.iter() {
+        let value = match CString::new(*value) {
+            Ok(value) => value,
+            Err(_) => {
+                warn!(
+                    "Cannot set metadata {} of port {}: the value contains a nul byte.",
+                    key,
+                    port.name()
+                );
+                continue;
+            }
+        };
+        let key_c = CString::new(*key).expect("metadata keys do not contain nul bytes");
+        let value_type =
+            value_type.map(|t| CString::new(t).expect("metadata types do not contain nul bytes"));
+        let result = unsafe {
+            sys::jack_set_property(
+                client.raw() as *mut sys::JackClient,
+                subject,
+                key_c.as_ptr(),
+                value.as_ptr(),
+                value_type.as_ref().map_or(ptr::null(), |t| t.as_ptr()),
+            )
+        };
+        if result != 0 {
+            warn!("Failed to set metadata {} of port {}.", key, port.name());
+        }
+    }
+}
+
 fn audio_in_ports<P>(client: &Client, plugin: &P) -> Vec<Port<AudioIn>>
 where
     P: CommonAudioPortMeta,
 {
     let mut in_ports = Vec::with_capacity(plugin.max_number_of_audio_inputs());
+    let layout = plugin.audio_input_layout();
     for index in 0..plugin.max_number_of_audio_inputs() {
         let name = plugin.audio_input_name(index);
         info!("Registering audio input port with name {}", name);
         let port = client.register_port(&name, AudioIn::default());
         match port {
-            Ok(p) => {
+            Ok(mut p) => {
+                let pretty_name = audio_port_pretty_name(&name, &layout, index);
+                set_port_metadata(client, &p, &pretty_name, index, true);
+                if layout.speaker(index).is_some() {
+                    let alias = layout.port_name("in", index);
+                    if let Err(e) = p.set_alias(&alias) {
+                        warn!(
+                            "Failed to set alias {} of audio input port {}: {:?}",
+                            alias, name, e
+                        );
+                    }
+                }
                 in_ports.push(p);
             }
             Err(e) => {
@@ -102,12 +678,24 @@ where
     P: CommonAudioPortMeta,
 {
     let mut out_ports = Vec::with_capacity(plugin.max_number_of_audio_outputs());
+    let layout = plugin.audio_output_layout();
     for index in 0..plugin.max_number_of_audio_outputs() {
         let name = plugin.audio_output_name(index);
         info!("Registering audio output port with name {}", name);
         let port = client.register_port(&name, AudioOut::default());
         match port {
-            Ok(p) => {
+            Ok(mut p) => {
+                let pretty_name = audio_port_pretty_name(&name, &layout, index);
+                set_port_metadata(client, &p, &pretty_name, index, true);
+                if layout.speaker(index).is_some() {
+                    let alias = layout.port_name("out", index);
+                    if let Err(e) = p.set_alias(&alias) {
+                        warn!(
+                            "Failed to set alias {} of audio output port {}: {:?}",
+                            alias, name, e
+                        );
+                    }
+                }
                 out_ports.push(p);
             }
             Err(e) => {
@@ -131,6 +719,7 @@ where
         let port = client.register_port(&name, MidiIn::default());
         match port {
             Ok(p) => {
+                set_port_metadata(client, &p, &name, index, false);
                 in_ports.push(p);
             }
             Err(e) => {
@@ -153,6 +742,7 @@ where
         let port = client.register_port(&name, MidiOut::default());
         match port {
             Ok(p) => {
+                set_port_metadata(client, &p, &name, index, false);
                 out_ports.push(p);
             }
             Err(e) => {
@@ -175,77 +765,295 @@ struct MidiWriterWrapper {
 unsafe impl Send for MidiWriterWrapper {}
 unsafe impl Sync for MidiWriterWrapper {}
 
+// The maximum size of a system exclusive message, including the start and end bytes.
+const MAX_SYSEX_SIZE: usize = 4096;
+
+// The state that is shared between the notification handler and the process handler.
+struct SharedState {
+    // The number of xruns since the client has been activated.
+    xruns: AtomicUsize,
+    is_freewheeling: AtomicBool,
+    // The latency that has last been reported by the plugin.
+    latency: AtomicUsize,
+    // Set by the process handler when the latency has changed.
+    latency_changed: AtomicBool,
+}
+
+// How often the `LatencyWatcher` checks if the latency of the plugin has changed.
+const LATENCY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// The client is only used by the `LatencyWatcher`, which is stopped before the client is
+// deactivated.
+struct RawClient(*mut sys::JackClient);
+
+unsafe impl Send for RawClient {}
+
+// Asks JACK to recompute the latencies of the graph when the latency of the plugin has changed,
+// because `jack_recompute_total_latencies` cannot be called from the process thread.
+// The thread stops when the watcher is dropped.
+struct LatencyWatcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LatencyWatcher {
+    fn start(client: &Client, shared_state: Arc<SharedState>) -> Self {
+        let client = RawClient(client.raw() as *mut sys::JackClient);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                thread::sleep(LATENCY_POLL_INTERVAL);
+                if shared_state.latency_changed.swap(false, Ordering::AcqRel) {
+                    info!("The latency of the plugin has changed.");
+                    if unsafe { sys::jack_recompute_total_latencies(client.0) } != 0 {
+                        warn!("Failed to recompute the latencies.");
+                    }
+                }
+            }
+        });
+        LatencyWatcher {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for LatencyWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// The ports of the plugin, as needed to report the latency.
+struct RawPorts {
+    inputs: Vec<*mut sys::JackPort>,
+    outputs: Vec<*mut sys::JackPort>,
+}
+
+// The ports are only used by the notification handler, while the client is active, so they
+// are not unregistered in the meanwhile.
+unsafe impl Send for RawPorts {}
+unsafe impl Sync for RawPorts {}
+
+// The range that covers all `ranges`, delayed by `latency` frames.
+fn delayed_latency_range<I>(ranges: I, latency: u32) -> sys::JackLatencyRange
+where
+    I: Iterator<Item = sys::JackLatencyRange>,
+{
+    let mut result: Option<sys::JackLatencyRange> = None;
+    for range in ranges {
+        result = Some(match result {
+            None => range,
+            Some(r) => sys::JackLatencyRange {
+                min: cmp::min(r.min, range.min),
+                max: cmp::max(r.max, range.max),
+            },
+        });
+    }
+    let range = result.unwrap_or_default();
+    sys::JackLatencyRange {
+        min: range.min.saturating_add(latency),
+        max: range.max.saturating_add(latency),
+    }
+}
+
+struct JackNotificationHandler<P> {
+    shared_state: Arc<SharedState>,
+    ports: RawPorts,
+    // The plugin of the process handler.
+    plugin: Arc<Mutex<P>>,
+}
+
+impl<P> NotificationHandler for JackNotificationHandler<P>
+where
+    P: AudioHandler + Send,
+{
+    fn buffer_size(&mut self, _: &Client, size: Frames) -> Control {
+        match self.plugin.lock() {
+            Ok(mut plugin) => plugin.set_max_buffer_size(size as usize),
+            Err(_) => error!("Cannot pass the buffer size to the plugin: the plugin panicked."),
+        }
+        Control::Continue
+    }
+
+    fn freewheel(&mut self, _: &Client, is_freewheel_enabled: bool) {
+        info!("Freewheel mode enabled: {}", is_freewheel_enabled);
+        self.shared_state
+            .is_freewheeling
+            .store(is_freewheel_enabled, Ordering::Release);
+    }
+
+    fn latency(&mut self, _: &Client, mode: LatencyType) {
+        // For the capture latency, the latency flows from the inputs to the outputs,
+        // for the playback latency, it flows from the outputs to the inputs.
+        let (mode, from, to) = match mode {
+            LatencyType::Capture => (
+                sys::CAPTURE_LATENCY,
+                &self.ports.inputs,
+                &self.ports.outputs,
+            ),
+            LatencyType::Playback => (
+                sys::PLAYBACK_LATENCY,
+                &self.ports.outputs,
+                &self.ports.inputs,
+            ),
+        };
+        let latency = self.shared_state.latency.load(Ordering::Acquire) as u32;
+        let ranges = from.iter().map(|port| {
+            let mut range = sys::JackLatencyRange::default();
+            unsafe { sys::jack_port_get_latency_range(*port, mode, &mut range) };
+            range
+        });
+        let mut range = delayed_latency_range(ranges, latency);
+        for port in to.iter() {
+            unsafe { sys::jack_port_set_latency_range(*port, mode, &mut range) };
+        }
+    }
+
+    fn xrun(&mut self, _: &Client) -> Control {
+        warn!("JACK reported an xrun.");
+        self.shared_state.xruns.fetch_add(1, Ordering::AcqRel);
+        Control::Continue
+    }
+}
+
 struct JackProcessHandler<P> {
     audio_in_ports: Vec<Port<AudioIn>>,
     audio_out_ports: Vec<Port<AudioOut>>,
     midi_in_ports: Vec<Port<MidiIn>>,
+    // One for each midi input port.
+    sysex_reassemblers: Vec<SysExReassembler>,
     midi_out_ports: Vec<Port<MidiOut>>,
-    plugin: P,
+    // The process callback only uses `try_lock`; the lock is only taken elsewhere while JACK
+    // does not process audio.
+    plugin: Arc<Mutex<P>>,
     inputs: VecStorage<&'static [f32]>,
     outputs: VecStorage<&'static [f32]>,
     midi_writer: VecStorage<MidiWriterWrapper>,
+    // The sample rate that has last been passed to the plugin.
+    sample_rate: usize,
+    shared_state: Arc<SharedState>,
+    // The number of xruns that has last been seen by the plugin.
+    xruns: usize,
 }
 
 impl<P> JackProcessHandler<P>
 where
-    P: CommonAudioPortMeta + CommonMidiPortMeta + CommonPluginMeta + Send,
+    P: CommonAudioPortMeta + AudioHandler + CommonMidiPortMeta + CommonPluginMeta + Latency + Send,
     for<'c, 'mp, 'mw> P: ContextualAudioRenderer<f32, JackHost<'c, 'mp, 'mw>>
         + ContextualEventHandler<Indexed<Timed<RawMidiEvent>>, JackHost<'c, 'mp, 'mw>>,
     for<'c, 'mp, 'mw, 'a> P:
         ContextualEventHandler<Indexed<Timed<SysExEvent<'a>>>, JackHost<'c, 'mp, 'mw>>,
 {
-    fn new(client: &Client, plugin: P) -> Self {
+    fn new(client: &Client, plugin: P, shared_state: Arc<SharedState>) -> Self {
         trace!("JackProcessHandler::new()");
-        let audio_in_ports = audio_in_ports::<P>(&client, &plugin);
-        let audio_out_ports = audio_out_ports::<P>(&client, &plugin);
+        let audio_in_ports = audio_in_ports::<P>(client, &plugin);
+        let audio_out_ports = audio_out_ports::<P>(client, &plugin);
 
-        let midi_in_ports = midi_in_ports::<P>(&client, &plugin);
-        let midi_out_ports = midi_out_ports::<P>(&client, &plugin);
+        let midi_in_ports = midi_in_ports::<P>(client, &plugin);
+        let midi_out_ports = midi_out_ports::<P>(client, &plugin);
+        let sysex_reassemblers = midi_in_ports
+            .iter()
+            .map(|_| SysExReassembler::new(MAX_SYSEX_SIZE, SysExOverflowPolicy::Discard))
+            .collect();
 
         let inputs = VecStorage::with_capacity(plugin.max_number_of_audio_inputs());
         let outputs = VecStorage::with_capacity(plugin.max_number_of_audio_outputs());
 
         let midi_writer = VecStorage::with_capacity(plugin.max_number_of_midi_outputs());
 
+        let sample_rate = client.sample_rate();
+
         JackProcessHandler {
             audio_in_ports,
             audio_out_ports,
             midi_in_ports,
+            sysex_reassemblers,
             midi_out_ports,
-            plugin,
+            plugin: Arc::new(Mutex::new(plugin)),
             inputs,
             outputs,
             midi_writer,
+            sample_rate,
+            shared_state,
+            xruns: 0,
+        }
+    }
+
+    fn port_names(&self) -> PortNames {
+        fn names<S>(ports: &[Port<S>]) -> Vec<String> {
+            ports.iter().map(|port| port.name().to_string()).collect()
+        }
+        PortNames {
+            audio_inputs: names(&self.audio_in_ports),
+            audio_outputs: names(&self.audio_out_ports),
+            midi_inputs: names(&self.midi_in_ports),
+            midi_outputs: names(&self.midi_out_ports),
+        }
+    }
+
+    fn raw_ports(&self) -> RawPorts {
+        fn raw<S>(ports: &[Port<S>]) -> impl Iterator<Item = *mut sys::JackPort> + '_ {
+            ports.iter().map(|port| port.raw() as *mut sys::JackPort)
+        }
+        RawPorts {
+            inputs: raw(&self.audio_in_ports)
+                .chain(raw(&self.midi_in_ports))
+                .collect(),
+            outputs: raw(&self.audio_out_ports)
+                .chain(raw(&self.midi_out_ports))
+                .collect(),
         }
     }
 
     fn handle_events<'c, 'mp, 'mw>(
         midi_in_ports: &[Port<MidiIn>],
+        sysex_reassemblers: &mut [SysExReassembler],
         plugin: &mut P,
         process_scope: &ProcessScope,
         jack_host: &mut JackHost<'c, 'mp, 'mw>,
     ) {
         // No tracing here, because this is called in the `process` function,
         // and we do not want to trace that.
-        for (index, midi_in_port) in midi_in_ports.iter().enumerate() {
+        for ((index, midi_in_port), sysex_reassembler) in midi_in_ports
+            .iter()
+            .enumerate()
+            .zip(sysex_reassemblers.iter_mut())
+        {
             trace!("handle_events for input port {}", index);
             for input_event in midi_in_port.iter(process_scope) {
                 trace!("handle_events found event: {:?}", &input_event.bytes);
-                if input_event.bytes.len() <= 3 {
-                    if let Some(raw_event) = RawMidiEvent::try_new(&input_event.bytes) {
+                let first_byte = input_event.bytes.first().cloned();
+                // A fragment that continues a system exclusive message starts with a data byte.
+                let is_sysex = first_byte == Some(START_OF_EXCLUSIVE)
+                    || (sysex_reassembler.is_collecting()
+                        && matches!(first_byte, Some(0x00..=0x7F)));
+                if is_sysex {
+                    if let Some(sysex_event) = sysex_reassembler.push(input_event.bytes) {
                         let event = Indexed {
                             index,
                             event: Timed {
                                 time_in_frames: input_event.time,
-                                event: raw_event,
+                                event: sysex_event,
                             },
                         };
                         plugin.handle_event(event, jack_host);
-                    } else {
-                        warn!("Strange event of length {}", input_event.bytes.len());
                     }
+                } else if let Some(raw_event) = RawMidiEvent::try_new(input_event.bytes) {
+                    let event = Indexed {
+                        index,
+                        event: Timed {
+                            time_in_frames: input_event.time,
+                            event: raw_event,
+                        },
+                    };
+                    plugin.handle_event(event, jack_host);
                 } else {
-                    // TODO: SysEx event
+                    warn!("Strange event of length {}", input_event.bytes.len());
                 }
             }
         }
@@ -254,24 +1062,55 @@ where
 
 impl<P> ProcessHandler for JackProcessHandler<P>
 where
-    P: CommonAudioPortMeta + CommonMidiPortMeta + CommonPluginMeta + Send,
+    P: CommonAudioPortMeta + AudioHandler + CommonMidiPortMeta + CommonPluginMeta + Latency + Send,
     for<'c, 'mp, 'mw> P: ContextualAudioRenderer<f32, JackHost<'c, 'mp, 'mw>>
         + ContextualEventHandler<Indexed<Timed<RawMidiEvent>>, JackHost<'c, 'mp, 'mw>>,
     for<'c, 'mp, 'mw, 'a> P:
         ContextualEventHandler<Indexed<Timed<SysExEvent<'a>>>, JackHost<'c, 'mp, 'mw>>,
 {
     fn process(&mut self, client: &Client, process_scope: &ProcessScope) -> Control {
+        #[cfg(feature = "flush-denormals")]
+        let _denormal_guard = DenormalGuard::new();
+        let number_of_frames = process_scope.n_frames();
+        let mut plugin = match self.plugin.try_lock() {
+            Ok(plugin) => plugin,
+            Err(_) => {
+                for port in self.audio_out_ports.iter_mut() {
+                    for sample in port.as_mut_slice(process_scope).iter_mut() {
+                        *sample = 0.0;
+                    }
+                }
+                return Control::Continue;
+            }
+        };
+        let sample_rate = client.sample_rate();
+        if sample_rate != self.sample_rate {
+            plugin.set_sample_rate(sample_rate as f64);
+            self.sample_rate = sample_rate;
+        }
+        let latency = plugin.latency_in_frames();
+        if self.shared_state.latency.swap(latency, Ordering::AcqRel) != latency {
+            self.shared_state
+                .latency_changed
+                .store(true, Ordering::Release);
+        }
+        let xruns = self.shared_state.xruns.load(Ordering::Acquire);
+        let xruns_since_previous_cycle = xruns.wrapping_sub(self.xruns);
+        self.xruns = xruns;
         let mut midi_writer_guard = self.midi_writer.vec_guard();
         for midi_output in self.midi_out_ports.iter_mut() {
             midi_writer_guard.push(midi_output.writer(process_scope));
         }
         let mut jack_host: JackHost = JackHost {
-            _client: client,
+            client,
             midi_out_ports: midi_writer_guard.as_mut_slice(),
+            xruns_since_previous_cycle,
+            is_freewheeling: self.shared_state.is_freewheeling.load(Ordering::Acquire),
         };
         Self::handle_events(
             &self.midi_in_ports,
-            &mut self.plugin,
+            &mut self.sysex_reassemblers,
+            &mut *plugin,
             process_scope,
             &mut jack_host,
         );
@@ -282,7 +1121,6 @@ where
         }
 
         let mut outputs = self.outputs.vec_guard();
-        let number_of_frames = process_scope.n_frames();
         for i in 0..cmp::min(self.audio_out_ports.len(), outputs.capacity()) {
             // We use some unsafe here because otherwise, the compiler believes we are borrowing
             // `self.audio_out_ports` multiple times.
@@ -295,19 +1133,20 @@ where
             outputs.push(buffer);
         }
 
-        self.plugin
-            .render_buffer(inputs.as_slice(), outputs.as_mut_slice(), &mut jack_host);
+        plugin.render_buffer(inputs.as_slice(), outputs.as_mut_slice(), &mut jack_host);
         Control::Continue
     }
 }
 
 /// Run the plugin until the user presses a key on the computer keyboard.
-pub fn run<P>(mut plugin: P) -> Option<P>
+pub fn run<P>(plugin: P) -> Option<P>
 where
     P: CommonAudioPortMeta
         + AudioHandler
         + CommonMidiPortMeta
         + CommonPluginMeta
+        + Latency
+        + Lifecycle
         + Send
         + Sync
         + 'static,
@@ -316,16 +1155,31 @@ where
     for<'c, 'mp, 'mw, 'a> P:
         ContextualEventHandler<Indexed<Timed<SysExEvent<'a>>>, JackHost<'c, 'mp, 'mw>>,
 {
-    let (client, _status) = Client::new(plugin.name(), ClientOptions::NO_START_SERVER).unwrap();
-
-    let sample_rate = client.sample_rate();
-    plugin.set_sample_rate(sample_rate as f64);
+    run_with_options(plugin, &JackOptions::default())
+}
 
-    let jack_process_handler = JackProcessHandler::new(&client, plugin);
-    let active_client = match client.activate_async((), jack_process_handler) {
-        Ok(c) => c,
+/// Run the plugin with the given options until the user presses a key on the computer
+/// keyboard.
+pub fn run_with_options<P>(plugin: P, options: &JackOptions) -> Option<P>
+where
+    P: CommonAudioPortMeta
+        + AudioHandler
+        + CommonMidiPortMeta
+        + CommonPluginMeta
+        + Latency
+        + Lifecycle
+        + Send
+        + Sync
+        + 'static,
+    for<'c, 'mp, 'mw> P: ContextualAudioRenderer<f32, JackHost<'c, 'mp, 'mw>>
+        + ContextualEventHandler<Indexed<Timed<RawMidiEvent>>, JackHost<'c, 'mp, 'mw>>,
+    for<'c, 'mp, 'mw, 'a> P:
+        ContextualEventHandler<Indexed<Timed<SysExEvent<'a>>>, JackHost<'c, 'mp, 'mw>>,
+{
+    let active_client = match start(plugin, options) {
+        Ok(active_client) => active_client,
         Err(e) => {
-            error!("Failed to activate client: {:?}", e);
+            error!("Failed to start the JACK client: {:?}", e);
             return None;
         }
     };
@@ -334,16 +1188,225 @@ where
     let mut user_input = String::new();
     io::stdin().read_line(&mut user_input).ok();
 
-    info!("Deactivating client...");
-
-    match active_client.deactivate() {
-        Ok((_, _, plugin)) => {
-            info!("Client deactivated.");
-            Some(plugin.plugin)
-        }
+    match active_client.stop() {
+        Ok(plugin) => Some(plugin),
         Err(e) => {
             error!("Failed to deactivate client: {:?}", e);
             None
         }
     }
 }
+
+/// The error type that represents the errors you can get when starting or stopping the
+/// JACK client.
+#[derive(Debug)]
+pub enum JackError {
+    /// The client could not be opened, e.g. because the JACK server is not running.
+    ClientError(jack::Error),
+    /// The client could not be activated.
+    ActivationError(jack::Error),
+    /// The client could not be deactivated.
+    DeactivationError(jack::Error),
+}
+
+/// A JACK client that is running the plugin.
+///
+/// The client keeps running until [`stop`] is called, which can be done from another thread.
+///
+/// [`stop`]: #method.stop
+pub struct ActiveJackClient<P> {
+    // Declared before the client, so that it is stopped before the client is closed.
+    latency_watcher: LatencyWatcher,
+    async_client: jack::AsyncClient<JackNotificationHandler<P>, JackProcessHandler<P>>,
+    leave_freewheel_on_stop: bool,
+}
+
+impl<P> ActiveJackClient<P> {
+    /// The sample rate of the JACK server.
+    pub fn sample_rate(&self) -> usize {
+        self.async_client.as_client().sample_rate()
+    }
+
+    /// Ask the JACK server to enter or leave freewheel mode, see [`set_freewheel`].
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method sends a request to the JACK server.
+    ///
+    /// [`set_freewheel`]: ./fn.set_freewheel.html
+    pub fn set_freewheel(&self, enabled: bool) -> Result<(), FreewheelError> {
+        set_freewheel(self.async_client.as_client(), enabled)
+    }
+
+    /// Deactivate and close the client and return the plugin.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method waits for the JACK server to deactivate the client.
+    pub fn stop(self) -> Result<P, JackError>
+    where
+        P: Lifecycle,
+    {
+        if self.leave_freewheel_on_stop {
+            if let Err(e) = self.set_freewheel(false) {
+                error!("Failed to leave freewheel mode: {:?}", e);
+            }
+        }
+        drop(self.latency_watcher);
+        info!("Deactivating client...");
+        let (_, notification_handler, process_handler) = self
+            .async_client
+            .deactivate()
+            .map_err(JackError::DeactivationError)?;
+        info!("Client deactivated.");
+        // The notification handler holds the only other reference to the plugin.
+        drop(notification_handler);
+        let mut plugin = match Arc::try_unwrap(process_handler.plugin) {
+            Ok(plugin) => plugin.into_inner().unwrap_or_else(PoisonError::into_inner),
+            Err(_) => unreachable!("the plugin is only shared with the notification handler"),
+        };
+        plugin.on_suspend();
+        Ok(plugin)
+    }
+}
+
+/// Open a JACK client and start running the plugin with the given options.
+/// The client keeps running until [`ActiveJackClient::stop`] is called.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// This function allocates memory and communicates with the JACK server.
+///
+/// [`ActiveJackClient::stop`]: ./struct.ActiveJackClient.html#method.stop
+pub fn start<P>(mut plugin: P, options: &JackOptions) -> Result<ActiveJackClient<P>, JackError>
+where
+    P: CommonAudioPortMeta
+        + AudioHandler
+        + CommonMidiPortMeta
+        + CommonPluginMeta
+        + Latency
+        + Lifecycle
+        + Send
+        + Sync
+        + 'static,
+    for<'c, 'mp, 'mw> P: ContextualAudioRenderer<f32, JackHost<'c, 'mp, 'mw>>
+        + ContextualEventHandler<Indexed<Timed<RawMidiEvent>>, JackHost<'c, 'mp, 'mw>>,
+    for<'c, 'mp, 'mw, 'a> P:
+        ContextualEventHandler<Indexed<Timed<SysExEvent<'a>>>, JackHost<'c, 'mp, 'mw>>,
+{
+    let client_name = options
+        .client_name
+        .clone()
+        .unwrap_or_else(|| plugin.name().to_string());
+    let (client, _status) = Client::new(&client_name, ClientOptions::NO_START_SERVER)
+        .map_err(JackError::ClientError)?;
+
+    let sample_rate = client.sample_rate();
+    plugin.set_sample_rate(sample_rate as f64);
+    plugin.set_max_buffer_size(client.buffer_size() as usize);
+    plugin.on_resume();
+
+    let shared_state = Arc::new(SharedState {
+        xruns: AtomicUsize::new(0),
+        is_freewheeling: AtomicBool::new(false),
+        latency: AtomicUsize::new(plugin.latency_in_frames()),
+        latency_changed: AtomicBool::new(false),
+    });
+    let jack_process_handler = JackProcessHandler::new(&client, plugin, Arc::clone(&shared_state));
+    let notification_handler = JackNotificationHandler {
+        shared_state: Arc::clone(&shared_state),
+        ports: jack_process_handler.raw_ports(),
+        plugin: Arc::clone(&jack_process_handler.plugin),
+    };
+    let own_ports = jack_process_handler.port_names();
+    let async_client = client
+        .activate_async(notification_handler, jack_process_handler)
+        .map_err(JackError::ActivationError)?;
+    own_ports.auto_connect(async_client.as_client(), options);
+    if options.freewheel {
+        if let Err(e) = set_freewheel(async_client.as_client(), true) {
+            error!("Failed to enter freewheel mode: {:?}", e);
+        }
+    }
+
+    let latency_watcher = LatencyWatcher::start(async_client.as_client(), shared_state);
+
+    Ok(ActiveJackClient {
+        latency_watcher,
+        async_client,
+        leave_freewheel_on_stop: options.freewheel,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pretty_name_of_audio_port_contains_the_speaker() {
+        let stereo = ChannelLayout::for_number_of_channels(2);
+        assert_eq!(
+            audio_port_pretty_name("Main out", &stereo, 1),
+            "Main out (R)"
+        );
+        let discrete = ChannelLayout::for_number_of_channels(9);
+        assert_eq!(audio_port_pretty_name("Aux", &discrete, 8), "Aux");
+    }
+
+    #[test]
+    fn latency_is_added_to_the_range_of_all_ports() {
+        let ranges = vec![
+            sys::JackLatencyRange { min: 64, max: 128 },
+            sys::JackLatencyRange { min: 32, max: 96 },
+        ];
+        assert_eq!(
+            delayed_latency_range(ranges.into_iter(), 10),
+            sys::JackLatencyRange { min: 42, max: 138 }
+        );
+        assert_eq!(
+            delayed_latency_range(Vec::new().into_iter(), 10),
+            sys::JackLatencyRange { min: 10, max: 10 }
+        );
+    }
+
+    fn position_in_six_eight() -> sys::JackPosition {
+        sys::JackPosition {
+            frame: 44100,
+            valid: sys::POSITION_BBT,
+            bar: 3,
+            beat: 4,
+            tick: 960,
+            ticks_per_beat: 1920.0,
+            beats_per_bar: 6.0,
+            beat_type: 8.0,
+            beats_per_minute: 180.0,
+            ..sys::JackPosition::default()
+        }
+    }
+
+    #[test]
+    fn transport_is_converted_to_quarter_notes() {
+        let transport = JackTransport::from_raw(sys::TRANSPORT_ROLLING, &position_in_six_eight());
+        assert!(transport.is_rolling);
+        assert_eq!(transport.frame, 44100);
+        let state = transport.to_transport_state().expect("bbt is valid");
+        assert!(state.is_playing);
+        // Two bars of 6/8 and three and a half eighth notes.
+        assert_eq!(state.position_in_beats, (12.0 + 3.5) * 0.5);
+        assert_eq!(state.tempo, 90.0);
+        assert_eq!(state.time_signature, TimeSignature::new(6, 8));
+    }
+
+    #[test]
+    fn transport_without_bbt_has_no_musical_position() {
+        let position = sys::JackPosition {
+            frame: 512,
+            ..sys::JackPosition::default()
+        };
+        let transport = JackTransport::from_raw(0, &position);
+        assert!(!transport.is_rolling);
+        assert_eq!(transport.frame, 512);
+        assert_eq!(transport.bar_beat_tick, None);
+        assert_eq!(transport.to_transport_state(), None);
+    }
+}
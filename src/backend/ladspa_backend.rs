@@ -0,0 +1,556 @@
+//! Wrapper for the [LADSPA] backend, for simple effects in (older) Linux hosts.
+//!
+//! Support is only enabled if you compile with the "backend-ladspa" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! LADSPA plugins only have audio ports (and control ports, which are not supported by this
+//! backend); there is no midi support.
+//! Build the plugin as a `cdylib`, export it with the [`ladspa_init`] macro and copy the dynamic
+//! library to a directory in the `LADSPA_PATH`, e.g. `/usr/lib/ladspa` or `~/.ladspa`.
+//!
+//! # Ports
+//! The ports of the plugin are the audio inputs followed by the audio outputs, with the names
+//! given by the [`CommonAudioPortMeta`] trait.
+//!
+//! The plugin reports that it cannot process "in place", so hosts do not use the same buffer
+//! for an input and an output.
+//!
+//! # Sample rate
+//! The sample rate is passed by the host when the plugin is instantiated; `set_sample_rate`
+//! is called right after the plugin has been created.
+//!
+//! # Buffer size
+//! LADSPA hosts do not announce the maximum buffer size. `set_max_buffer_size` is called
+//! before a buffer is rendered that is longer than all previous buffers.
+//!
+//! [LADSPA]: https://www.ladspa.org/
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`ladspa_init`]: ../../macro.ladspa_init.html
+//! [`CommonAudioPortMeta`]: ../../trait.CommonAudioPortMeta.html
+use self::sys::*;
+use crate::backend::HostInterface;
+#[cfg(feature = "flush-denormals")]
+use crate::utilities::denormals::DenormalGuard;
+use crate::{AudioHandler, CommonAudioPortMeta, CommonPluginMeta, ContextualAudioRenderer};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_ulong};
+use std::ptr;
+use std::slice;
+use vecstorage::VecStorage;
+
+/// The subset of the LADSPA C API that is used by this backend.
+///
+/// These types mirror the definitions in the header `ladspa.h`.
+pub mod sys {
+    use std::os::raw::{c_char, c_int, c_ulong, c_void};
+
+    pub type LadspaData = f32;
+    pub type LadspaHandle = *mut c_void;
+
+    pub const PROPERTY_REALTIME: c_int = 0x1;
+    pub const PROPERTY_INPLACE_BROKEN: c_int = 0x2;
+    pub const PROPERTY_HARD_RT_CAPABLE: c_int = 0x4;
+
+    pub const PORT_INPUT: c_int = 0x1;
+    pub const PORT_OUTPUT: c_int = 0x2;
+    pub const PORT_CONTROL: c_int = 0x4;
+    pub const PORT_AUDIO: c_int = 0x8;
+
+    /// `LADSPA_PortRangeHint`
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct LadspaPortRangeHint {
+        pub hint_descriptor: c_int,
+        pub lower_bound: LadspaData,
+        pub upper_bound: LadspaData,
+    }
+
+    /// `LADSPA_Descriptor`
+    #[repr(C)]
+    pub struct LadspaDescriptor {
+        pub unique_id: c_ulong,
+        pub label: *const c_char,
+        pub properties: c_int,
+        pub name: *const c_char,
+        pub maker: *const c_char,
+        pub copyright: *const c_char,
+        pub port_count: c_ulong,
+        pub port_descriptors: *const c_int,
+        pub port_names: *const *const c_char,
+        pub port_range_hints: *const LadspaPortRangeHint,
+        pub implementation_data: *mut c_void,
+        pub instantiate: unsafe extern "C" fn(
+            descriptor: *const LadspaDescriptor,
+            sample_rate: c_ulong,
+        ) -> LadspaHandle,
+        pub connect_port:
+            unsafe extern "C" fn(instance: LadspaHandle, port: c_ulong, data: *mut LadspaData),
+        pub activate: Option<unsafe extern "C" fn(instance: LadspaHandle)>,
+        pub run: unsafe extern "C" fn(instance: LadspaHandle, sample_count: c_ulong),
+        pub run_adding: Option<unsafe extern "C" fn(instance: LadspaHandle, sample_count: c_ulong)>,
+        pub set_run_adding_gain:
+            Option<unsafe extern "C" fn(instance: LadspaHandle, gain: LadspaData)>,
+        pub deactivate: Option<unsafe extern "C" fn(instance: LadspaHandle)>,
+        pub cleanup: unsafe extern "C" fn(instance: LadspaHandle),
+    }
+}
+
+/// A plugin that can be used with the LADSPA backend should implement this trait in addition
+/// to the traits for the meta-data, see the [module level documentation].
+///
+/// [module level documentation]: ./index.html
+pub trait LadspaPluginMeta: CommonPluginMeta + CommonAudioPortMeta {
+    /// The author of the plugin. The default is an empty string.
+    fn maker(&self) -> &str {
+        ""
+    }
+
+    /// The copyright or license of the plugin. The default is `"None"`, as recommended by
+    /// `ladspa.h` for plugins without copyright.
+    fn copyright(&self) -> &str {
+        "None"
+    }
+}
+
+/// Used internally by the `ladspa_init` macro. Normally, plugins do not need to use this.
+pub trait LadspaCreate {
+    fn create() -> Self;
+}
+
+/// The context that is passed to the plugin when rendering audio.
+pub struct LadspaHost {
+    _private: (),
+}
+
+impl HostInterface for LadspaHost {
+    fn output_initialized(&self) -> bool {
+        false
+    }
+}
+
+// A C string with the given text, without the null characters that it may contain.
+fn c_string(text: &str) -> CString {
+    CString::new(text.replace('\0', "")).expect("null characters have been removed")
+}
+
+/// Used internally by the `ladspa_init` macro. Normally, plugins do not need to use this.
+///
+/// Owns the descriptor of the plugin and the data it points to.
+pub struct DescriptorStorage {
+    descriptor: LadspaDescriptor,
+    _strings: Vec<CString>,
+    _port_names: Vec<*const c_char>,
+    _port_descriptors: Vec<i32>,
+    _port_range_hints: Vec<LadspaPortRangeHint>,
+}
+
+// The descriptor is never changed after it has been created and all pointers point to data
+// that is owned by the `DescriptorStorage`.
+unsafe impl Send for DescriptorStorage {}
+unsafe impl Sync for DescriptorStorage {}
+
+impl DescriptorStorage {
+    /// Create the descriptor with the meta-data of a newly created plugin.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory.
+    pub fn new<P>(unique_id: c_ulong, label: &str) -> Self
+    where
+        P: LadspaCreate
+            + LadspaPluginMeta
+            + AudioHandler
+            + ContextualAudioRenderer<f32, LadspaHost>,
+    {
+        let plugin = P::create();
+        let mut port_names = Vec::new();
+        let mut port_descriptors = Vec::new();
+        for index in 0..plugin.max_number_of_audio_inputs() {
+            port_names.push(c_string(&plugin.audio_input_name(index)));
+            port_descriptors.push(PORT_INPUT | PORT_AUDIO);
+        }
+        for index in 0..plugin.max_number_of_audio_outputs() {
+            port_names.push(c_string(&plugin.audio_output_name(index)));
+            port_descriptors.push(PORT_OUTPUT | PORT_AUDIO);
+        }
+        let port_range_hints = vec![LadspaPortRangeHint::default(); port_descriptors.len()];
+        let port_name_pointers: Vec<*const c_char> =
+            port_names.iter().map(|name| name.as_ptr()).collect();
+
+        let mut strings = vec![
+            c_string(label),
+            c_string(plugin.name()),
+            c_string(plugin.maker()),
+            c_string(plugin.copyright()),
+        ];
+        let descriptor = LadspaDescriptor {
+            unique_id,
+            label: strings[0].as_ptr(),
+            properties: PROPERTY_HARD_RT_CAPABLE | PROPERTY_INPLACE_BROKEN,
+            name: strings[1].as_ptr(),
+            maker: strings[2].as_ptr(),
+            copyright: strings[3].as_ptr(),
+            port_count: port_descriptors.len() as c_ulong,
+            port_descriptors: port_descriptors.as_ptr(),
+            port_names: port_name_pointers.as_ptr(),
+            port_range_hints: port_range_hints.as_ptr(),
+            implementation_data: ptr::null_mut(),
+            instantiate: instantiate::<P>,
+            connect_port: connect_port::<P>,
+            activate: None,
+            run: run::<P>,
+            run_adding: None,
+            set_run_adding_gain: None,
+            deactivate: None,
+            cleanup: cleanup::<P>,
+        };
+        // Moving the `CString`s does not move the data they point to.
+        strings.extend(port_names);
+        DescriptorStorage {
+            descriptor,
+            _strings: strings,
+            _port_names: port_name_pointers,
+            _port_descriptors: port_descriptors,
+            _port_range_hints: port_range_hints,
+        }
+    }
+
+    pub fn descriptor(&self) -> *const LadspaDescriptor {
+        &self.descriptor
+    }
+}
+
+/// Used internally by the `ladspa_init` macro. Normally, plugins do not need to use this.
+pub struct LadspaPluginWrapper<P> {
+    plugin: P,
+    host: LadspaHost,
+    audio_inputs: Vec<*const f32>,
+    audio_outputs: Vec<*mut f32>,
+    inputs: VecStorage<&'static [f32]>,
+    outputs: VecStorage<&'static [f32]>,
+    // The maximum buffer size that has last been passed to the plugin.
+    max_buffer_size: usize,
+}
+
+impl<P> LadspaPluginWrapper<P>
+where
+    P: LadspaPluginMeta + AudioHandler + ContextualAudioRenderer<f32, LadspaHost>,
+{
+    pub fn new(mut plugin: P, sample_rate: f64) -> Self {
+        trace!("sample_rate: {}", sample_rate);
+        plugin.set_sample_rate(sample_rate);
+        let number_of_inputs = plugin.max_number_of_audio_inputs();
+        let number_of_outputs = plugin.max_number_of_audio_outputs();
+        LadspaPluginWrapper {
+            plugin,
+            host: LadspaHost { _private: () },
+            audio_inputs: vec![ptr::null(); number_of_inputs],
+            audio_outputs: vec![ptr::null_mut(); number_of_outputs],
+            inputs: VecStorage::with_capacity(number_of_inputs),
+            outputs: VecStorage::with_capacity(number_of_outputs),
+            max_buffer_size: 0,
+        }
+    }
+
+    pub fn plugin(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Connect the port with the given index to the buffer at `data`.
+    pub fn connect_port(&mut self, port: usize, data: *mut f32) {
+        if port < self.audio_inputs.len() {
+            self.audio_inputs[port] = data;
+        } else if let Some(output) = self.audio_outputs.get_mut(port - self.audio_inputs.len()) {
+            *output = data;
+        } else {
+            warn!("Ignoring connection to unknown port {}", port);
+        }
+    }
+
+    /// Render the audio of one buffer.
+    ///
+    /// # Safety
+    /// All ports must be connected to buffers that are valid for `sample_count` frames.
+    pub unsafe fn run(&mut self, sample_count: usize) {
+        #[cfg(feature = "flush-denormals")]
+        let _denormal_guard = DenormalGuard::new();
+        if sample_count > self.max_buffer_size {
+            self.plugin.set_max_buffer_size(sample_count);
+            self.max_buffer_size = sample_count;
+        }
+        let mut inputs = self.inputs.vec_guard();
+        for input in self.audio_inputs.iter().filter(|input| !input.is_null()) {
+            inputs.push(slice::from_raw_parts(*input, sample_count));
+        }
+        let mut outputs = self.outputs.vec_guard();
+        for output in self.audio_outputs.iter().filter(|output| !output.is_null()) {
+            outputs.push(slice::from_raw_parts_mut(*output, sample_count));
+        }
+        self.plugin
+            .render_buffer(inputs.as_slice(), outputs.as_mut_slice(), &mut self.host);
+    }
+}
+
+unsafe extern "C" fn instantiate<P>(
+    _descriptor: *const LadspaDescriptor,
+    sample_rate: c_ulong,
+) -> LadspaHandle
+where
+    P: LadspaCreate + LadspaPluginMeta + AudioHandler + ContextualAudioRenderer<f32, LadspaHost>,
+{
+    let wrapper = LadspaPluginWrapper::new(P::create(), sample_rate as f64);
+    Box::into_raw(Box::new(wrapper)) as LadspaHandle
+}
+
+unsafe extern "C" fn connect_port<P>(instance: LadspaHandle, port: c_ulong, data: *mut LadspaData)
+where
+    P: LadspaPluginMeta + AudioHandler + ContextualAudioRenderer<f32, LadspaHost>,
+{
+    (*(instance as *mut LadspaPluginWrapper<P>)).connect_port(port as usize, data);
+}
+
+unsafe extern "C" fn run<P>(instance: LadspaHandle, sample_count: c_ulong)
+where
+    P: LadspaPluginMeta + AudioHandler + ContextualAudioRenderer<f32, LadspaHost>,
+{
+    (*(instance as *mut LadspaPluginWrapper<P>)).run(sample_count as usize);
+}
+
+unsafe extern "C" fn cleanup<P>(instance: LadspaHandle) {
+    drop(Box::from_raw(instance as *mut LadspaPluginWrapper<P>));
+}
+
+/// Export the plugin as a LADSPA plugin with the given unique id and label.
+/// You call this with the unique id, the label (a short name without spaces) and the
+/// declaration of a function that creates your plugin.
+/// This function may also do some setup (e.g. initialize logging).
+///
+/// Unique ids can be requested from `ladspa@muse.demon.co.uk`; ids below 1000 are meant
+/// for testing.
+///
+/// The plugin must implement the traits that are required by the [`ladspa_backend`] module.
+///
+/// Example:
+/// ```
+/// # #[macro_use] extern crate rsynth;
+/// use rsynth::backend::ladspa_backend::{LadspaHost, LadspaPluginMeta};
+/// use rsynth::meta::{InOut, Meta, MetaData};
+/// use rsynth::{AudioHandler, ContextualAudioRenderer};
+///
+/// struct MyGain {
+///     meta: MetaData<&'static str, &'static str, &'static str>,
+/// }
+///
+/// impl Meta for MyGain {
+///     type MetaData = MetaData<&'static str, &'static str, &'static str>;
+///     fn meta(&self) -> &Self::MetaData {
+///         &self.meta
+///     }
+/// }
+///
+/// impl LadspaPluginMeta for MyGain {
+///     fn maker(&self) -> &str {
+///         "Jane Doe"
+///     }
+/// }
+///
+/// impl AudioHandler for MyGain {
+///     // Implementation omitted for brevity.
+/// #     fn set_sample_rate(&mut self, new_sample_rate: f64) {}
+/// }
+///
+/// impl ContextualAudioRenderer<f32, LadspaHost> for MyGain {
+///     // Implementation omitted for brevity.
+/// #    fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut[&mut[f32]], context: &mut LadspaHost) {}
+/// }
+///
+/// ladspa_init!(
+///     999,
+///     "my_gain",
+///     fn init() -> MyGain {
+///         MyGain {
+///             meta: MetaData {
+///                 general_meta: "My gain",
+///                 audio_port_meta: InOut {
+///                     inputs: vec!["in"],
+///                     outputs: vec!["out"],
+///                 },
+///                 midi_port_meta: InOut {
+///                     inputs: vec![],
+///                     outputs: vec![],
+///                 },
+///             },
+///         }
+///     }
+/// );
+/// # fn main() {}
+/// ```
+///
+/// [`ladspa_backend`]: ./backend/ladspa_backend/index.html
+#[macro_export]
+macro_rules! ladspa_init {
+    ($unique_id:expr, $label:expr, fn $function_name:ident() -> $return_type:ty
+        $body:block
+    ) => {
+        fn $function_name() -> $return_type
+        $body
+
+        impl $crate::backend::ladspa_backend::LadspaCreate for $return_type {
+            fn create() -> Self {
+                $function_name()
+            }
+        }
+
+        #[no_mangle]
+        pub extern "C" fn ladspa_descriptor(
+            index: ::std::os::raw::c_ulong,
+        ) -> *const $crate::backend::ladspa_backend::sys::LadspaDescriptor {
+            static DESCRIPTOR: ::std::sync::OnceLock<
+                $crate::backend::ladspa_backend::DescriptorStorage,
+            > = ::std::sync::OnceLock::new();
+            if index != 0 {
+                return ::std::ptr::null();
+            }
+            DESCRIPTOR
+                .get_or_init(|| {
+                    $crate::backend::ladspa_backend::DescriptorStorage::new::<$return_type>(
+                        $unique_id, $label,
+                    )
+                })
+                .descriptor()
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::{InOut, Meta, MetaData};
+    use std::ffi::CStr;
+
+    struct TestPlugin {
+        meta: MetaData<&'static str, &'static str, &'static str>,
+        sample_rate: f64,
+        max_buffer_sizes: Vec<usize>,
+    }
+
+    impl Meta for TestPlugin {
+        type MetaData = MetaData<&'static str, &'static str, &'static str>;
+        fn meta(&self) -> &Self::MetaData {
+            &self.meta
+        }
+    }
+
+    impl LadspaPluginMeta for TestPlugin {
+        fn maker(&self) -> &str {
+            "rsynth"
+        }
+    }
+
+    impl LadspaCreate for TestPlugin {
+        fn create() -> Self {
+            TestPlugin {
+                meta: MetaData {
+                    general_meta: "Test gain",
+                    audio_port_meta: InOut {
+                        inputs: vec!["left in", "right in"],
+                        outputs: vec!["left out", "right out"],
+                    },
+                    midi_port_meta: InOut {
+                        inputs: vec![],
+                        outputs: vec![],
+                    },
+                },
+                sample_rate: 0.0,
+                max_buffer_sizes: Vec::new(),
+            }
+        }
+    }
+
+    impl AudioHandler for TestPlugin {
+        fn set_sample_rate(&mut self, sample_rate: f64) {
+            self.sample_rate = sample_rate;
+        }
+
+        fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+            self.max_buffer_sizes.push(max_buffer_size);
+        }
+    }
+
+    impl ContextualAudioRenderer<f32, LadspaHost> for TestPlugin {
+        fn render_buffer(
+            &mut self,
+            inputs: &[&[f32]],
+            outputs: &mut [&mut [f32]],
+            _: &mut LadspaHost,
+        ) {
+            for (output, input) in outputs.iter_mut().zip(inputs.iter()) {
+                for (output, input) in output.iter_mut().zip(input.iter()) {
+                    *output = 0.5 * input;
+                }
+            }
+        }
+    }
+
+    unsafe fn text(pointer: *const c_char) -> &'static str {
+        CStr::from_ptr(pointer).to_str().expect("valid utf-8")
+    }
+
+    #[test]
+    fn descriptor_describes_the_audio_ports() {
+        let storage = DescriptorStorage::new::<TestPlugin>(999, "test_gain");
+        let descriptor = unsafe { &*storage.descriptor() };
+        assert_eq!(descriptor.unique_id, 999);
+        assert_eq!(
+            descriptor.properties,
+            PROPERTY_HARD_RT_CAPABLE | PROPERTY_INPLACE_BROKEN
+        );
+        unsafe {
+            assert_eq!(text(descriptor.label), "test_gain");
+            assert_eq!(text(descriptor.name), "Test gain");
+            assert_eq!(text(descriptor.maker), "rsynth");
+            assert_eq!(text(descriptor.copyright), "None");
+            assert_eq!(descriptor.port_count, 4);
+            let port_descriptors = slice::from_raw_parts(descriptor.port_descriptors, 4);
+            assert_eq!(
+                port_descriptors,
+                &[
+                    PORT_INPUT | PORT_AUDIO,
+                    PORT_INPUT | PORT_AUDIO,
+                    PORT_OUTPUT | PORT_AUDIO,
+                    PORT_OUTPUT | PORT_AUDIO
+                ]
+            );
+            let names: Vec<_> = slice::from_raw_parts(descriptor.port_names, 4)
+                .iter()
+                .map(|name| text(*name))
+                .collect();
+            assert_eq!(names, vec!["left in", "right in", "left out", "right out"]);
+        }
+    }
+
+    #[test]
+    fn plugin_runs_through_the_c_interface() {
+        let storage = DescriptorStorage::new::<TestPlugin>(999, "test_gain");
+        let descriptor = unsafe { &*storage.descriptor() };
+        let mut left_in = [1.0_f32, 2.0, 3.0];
+        let mut right_in = [4.0_f32, 5.0, 6.0];
+        let mut left_out = [0.0_f32; 3];
+        let mut right_out = [0.0_f32; 3];
+        unsafe {
+            let instance = (descriptor.instantiate)(descriptor, 48000);
+            (descriptor.connect_port)(instance, 0, left_in.as_mut_ptr());
+            (descriptor.connect_port)(instance, 1, right_in.as_mut_ptr());
+            (descriptor.connect_port)(instance, 2, left_out.as_mut_ptr());
+            (descriptor.connect_port)(instance, 3, right_out.as_mut_ptr());
+            (descriptor.run)(instance, 3);
+            (descriptor.run)(instance, 2);
+            let wrapper = &*(instance as *const LadspaPluginWrapper<TestPlugin>);
+            assert_eq!(wrapper.plugin().sample_rate, 48000.0);
+            assert_eq!(wrapper.plugin().max_buffer_sizes, vec![3]);
+            (descriptor.cleanup)(instance);
+        }
+        assert_eq!(left_out, [0.5, 1.0, 1.5]);
+        assert_eq!(right_out, [2.0, 2.5, 3.0]);
+    }
+}
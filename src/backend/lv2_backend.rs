@@ -0,0 +1,1100 @@
+//! Wrapper for the [LV2] backend.
+//!
+//! Support is only enabled if you compile with the "backend-lv2" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! # Usage
+//! Build the plugin as a `cdylib` and export it with the [`lv2_init`] macro.
+//! LV2 hosts (e.g. Ardour or Carla) find plugins by their "bundle": a directory ending in
+//! `.lv2` that contains the dynamic library and a description of the plugin in Turtle (`.ttl`)
+//! files. The `.ttl` files are generated from the meta-data of the plugin with
+//! [`write_bundle`], e.g. in a small binary or in a test:
+//!
+//! ```no_run
+//! # use rsynth::backend::lv2_backend::Lv2PluginMeta;
+//! # use rsynth::meta::{InOut, Meta, MetaData};
+//! # struct MyPlugin { meta: MetaData<&'static str, &'static str, &'static str> }
+//! # impl Meta for MyPlugin {
+//! #     type MetaData = MetaData<&'static str, &'static str, &'static str>;
+//! #     fn meta(&self) -> &Self::MetaData { &self.meta }
+//! # }
+//! # impl Lv2PluginMeta for MyPlugin {}
+//! # fn create_plugin() -> MyPlugin { unimplemented!() }
+//! use rsynth::backend::lv2_backend::write_bundle;
+//! use std::path::Path;
+//!
+//! write_bundle(
+//!     &create_plugin(),
+//!     "https://example.com/plugins/my_plugin",
+//!     "libmy_plugin.so",
+//!     Path::new("target/my_plugin.lv2"),
+//! )
+//! .expect("the bundle can be written");
+//! ```
+//!
+//! Copy the dynamic library into the bundle and the bundle into e.g. `~/.lv2`.
+//!
+//! # Ports
+//! The ports of the plugin are numbered as follows: first the audio inputs, then the audio
+//! outputs, then the midi inputs and finally the midi outputs, as given by the
+//! [`CommonAudioPortMeta`] and [`CommonMidiPortMeta`] traits.
+//! The midi ports are atom ports with `atom:Sequence` buffers; they require the host to
+//! support the `urid:map` feature.
+//!
+//! Midi events from all midi inputs are passed to the plugin before `render_buffer` is called,
+//! as `Timed<RawMidiEvent>` or, for system exclusive messages, as `Timed<SysExEvent>`.
+//! The plugin sends midi events to the midi outputs with the [`Lv2Host`] that is passed as
+//! the context; these events must be sent in chronological order, e.g. with an
+//! [`OutputEventQueue`].
+//!
+//! # Sample rate
+//! The sample rate is passed by the host when the plugin is instantiated; `set_sample_rate`
+//! is called right after the plugin has been created.
+//!
+//! # Buffer size
+//! When the host passes the maximum block length with the `options:options` feature,
+//! `set_max_buffer_size` is called with it right after the plugin has been created.
+//!
+//! [LV2]: https://lv2plug.in/
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`lv2_init`]: ../../macro.lv2_init.html
+//! [`write_bundle`]: ./fn.write_bundle.html
+//! [`CommonAudioPortMeta`]: ../../trait.CommonAudioPortMeta.html
+//! [`CommonMidiPortMeta`]: ../../trait.CommonMidiPortMeta.html
+//! [`Lv2Host`]: ./struct.Lv2Host.html
+//! [`OutputEventQueue`]: ../../event/output_event_queue/struct.OutputEventQueue.html
+use self::sys::{
+    Atom, AtomEvent, AtomSequence, Lv2Descriptor, Lv2Feature, Lv2Handle, Lv2OptionsOption,
+    Lv2UridMap, ATOM_INT_URI, ATOM_SEQUENCE_URI, MAX_BLOCK_LENGTH_URI, MIDI_EVENT_URI, OPTIONS_URI,
+    URID_MAP_URI,
+};
+use crate::backend::HostInterface;
+use crate::event::{
+    ContextualEventHandler, EventHandler, Indexed, RawMidiEvent, SysExEvent, Timed,
+};
+#[cfg(feature = "flush-denormals")]
+use crate::utilities::denormals::DenormalGuard;
+use crate::{
+    AudioHandler, CommonAudioPortMeta, CommonMidiPortMeta, CommonPluginMeta,
+    ContextualAudioRenderer,
+};
+use std::ffi::CStr;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::os::raw::{c_char, c_void};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use vecstorage::VecStorage;
+
+/// The subset of the LV2 C API that is used by this backend.
+///
+/// These types mirror the definitions in the headers `lv2/core/lv2.h`, `lv2/urid/urid.h`,
+/// `lv2/atom/atom.h` and `lv2/options/options.h` of the LV2 specification.
+#[allow(non_camel_case_types)]
+pub mod sys {
+    use std::os::raw::{c_char, c_void};
+
+    pub const URID_MAP_URI: &[u8] = b"http://lv2plug.in/ns/ext/urid#map\0";
+    pub const ATOM_SEQUENCE_URI: &[u8] = b"http://lv2plug.in/ns/ext/atom#Sequence\0";
+    pub const MIDI_EVENT_URI: &[u8] = b"http://lv2plug.in/ns/ext/midi#MidiEvent\0";
+    pub const OPTIONS_URI: &[u8] = b"http://lv2plug.in/ns/ext/options#options\0";
+    pub const MAX_BLOCK_LENGTH_URI: &[u8] = b"http://lv2plug.in/ns/ext/buf-size#maxBlockLength\0";
+    pub const ATOM_INT_URI: &[u8] = b"http://lv2plug.in/ns/ext/atom#Int\0";
+
+    pub type Lv2Handle = *mut c_void;
+    pub type Lv2Urid = u32;
+
+    /// `LV2_Descriptor`
+    #[repr(C)]
+    pub struct Lv2Descriptor {
+        pub uri: *const c_char,
+        pub instantiate: unsafe extern "C" fn(
+            descriptor: *const Lv2Descriptor,
+            sample_rate: f64,
+            bundle_path: *const c_char,
+            features: *const *const Lv2Feature,
+        ) -> Lv2Handle,
+        pub connect_port: unsafe extern "C" fn(instance: Lv2Handle, port: u32, data: *mut c_void),
+        pub activate: Option<unsafe extern "C" fn(instance: Lv2Handle)>,
+        pub run: unsafe extern "C" fn(instance: Lv2Handle, sample_count: u32),
+        pub deactivate: Option<unsafe extern "C" fn(instance: Lv2Handle)>,
+        pub cleanup: unsafe extern "C" fn(instance: Lv2Handle),
+        pub extension_data: unsafe extern "C" fn(uri: *const c_char) -> *const c_void,
+    }
+
+    // The descriptor is immutable and the URI is a static string.
+    unsafe impl Sync for Lv2Descriptor {}
+
+    /// `LV2_Feature`
+    #[repr(C)]
+    pub struct Lv2Feature {
+        pub uri: *const c_char,
+        pub data: *mut c_void,
+    }
+
+    /// `LV2_URID_Map`
+    #[repr(C)]
+    pub struct Lv2UridMap {
+        pub handle: *mut c_void,
+        pub map: unsafe extern "C" fn(handle: *mut c_void, uri: *const c_char) -> Lv2Urid,
+    }
+
+    /// `LV2_Options_Option`; an array of options is terminated by an option with a `key` of `0`
+    /// and a null `value`.
+    #[repr(C)]
+    pub struct Lv2OptionsOption {
+        pub context: u32,
+        pub subject: u32,
+        pub key: Lv2Urid,
+        pub size: u32,
+        pub type_: Lv2Urid,
+        pub value: *const c_void,
+    }
+
+    /// `LV2_Atom`
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug)]
+    pub struct Atom {
+        pub size: u32,
+        pub type_: Lv2Urid,
+    }
+
+    /// `LV2_Atom_Sequence`: the header of a sequence; the events follow the header.
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug)]
+    pub struct AtomSequence {
+        pub atom: Atom,
+        pub unit: Lv2Urid,
+        pub pad: u32,
+    }
+
+    /// `LV2_Atom_Event` with the time in frames: the header of an event; the data follows the
+    /// header.
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug)]
+    pub struct AtomEvent {
+        pub frames: i64,
+        pub body: Atom,
+    }
+}
+
+/// A plugin that can be used with the LV2 backend should implement this trait in addition to
+/// the traits for the meta-data, see the [module level documentation].
+///
+/// [module level documentation]: ./index.html
+pub trait Lv2PluginMeta: CommonPluginMeta + CommonAudioPortMeta + CommonMidiPortMeta {
+    /// The LV2 class of the plugin, e.g. `"InstrumentPlugin"` or `"DelayPlugin"`, see
+    /// the [LV2 core specification] for the available classes.
+    /// The default is `None`: just `lv2:Plugin`.
+    ///
+    /// [LV2 core specification]: https://lv2plug.in/ns/lv2core
+    fn plugin_class(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Used internally by the `lv2_init` macro. Normally, plugins do not need to use this.
+pub trait Lv2Create {
+    fn create() -> Self;
+}
+
+// The state of one midi output port during `run`.
+struct MidiOutput {
+    sequence: *mut AtomSequence,
+    // The number of bytes that the host has reserved after the header of the atom.
+    capacity: u32,
+}
+
+/// The context that is passed to the plugin when rendering audio and when handling events.
+///
+/// Send midi events to midi output `index` by passing an `Indexed<Timed<RawMidiEvent>>` or an
+/// `Indexed<Timed<SysExEvent>>` to `handle_event`. Events that do not fit in the buffer that
+/// the host has reserved are dropped.
+pub struct Lv2Host {
+    midi_outputs: Vec<MidiOutput>,
+    midi_event_urid: u32,
+    sequence_urid: u32,
+}
+
+impl Lv2Host {
+    fn write(&mut self, index: usize, time_in_frames: u32, data: &[u8]) {
+        let midi_event_urid = self.midi_event_urid;
+        let output = match self.midi_outputs.get_mut(index) {
+            Some(output) if !output.sequence.is_null() => output,
+            _ => {
+                error!(
+                    "midi port out of bounds: port index is {}, but only {} ports are connected",
+                    index,
+                    self.midi_outputs.len()
+                );
+                return;
+            }
+        };
+        unsafe {
+            let atom = &mut (*output.sequence).atom;
+            let offset = atom.size as usize;
+            let size_of_event = std::mem::size_of::<AtomEvent>() + data.len();
+            if offset + pad_size(size_of_event) > output.capacity as usize {
+                warn!("midi output buffer is full, dropping an event");
+                return;
+            }
+            // `atom.size` counts the bytes after the atom header.
+            let event = (output.sequence as *mut u8).add(std::mem::size_of::<Atom>() + offset)
+                as *mut AtomEvent;
+            ptr::write(
+                event,
+                AtomEvent {
+                    frames: time_in_frames as i64,
+                    body: Atom {
+                        size: data.len() as u32,
+                        type_: midi_event_urid,
+                    },
+                },
+            );
+            ptr::copy_nonoverlapping(data.as_ptr(), event.add(1) as *mut u8, data.len());
+            atom.size += pad_size(size_of_event) as u32;
+        }
+    }
+
+    fn begin_run(&mut self) {
+        let sequence_urid = self.sequence_urid;
+        for output in self.midi_outputs.iter_mut() {
+            if output.sequence.is_null() {
+                continue;
+            }
+            unsafe {
+                // The host passes the available space in the size of the atom.
+                output.capacity = (*output.sequence).atom.size;
+                let body_header_size =
+                    (std::mem::size_of::<AtomSequence>() - std::mem::size_of::<Atom>()) as u32;
+                *output.sequence = AtomSequence {
+                    atom: Atom {
+                        size: body_header_size,
+                        type_: sequence_urid,
+                    },
+                    unit: 0,
+                    pad: 0,
+                };
+            }
+        }
+    }
+}
+
+impl HostInterface for Lv2Host {
+    fn output_initialized(&self) -> bool {
+        false
+    }
+}
+
+impl EventHandler<Indexed<Timed<RawMidiEvent>>> for Lv2Host {
+    fn handle_event(&mut self, event: Indexed<Timed<RawMidiEvent>>) {
+        let Indexed { index, event } = event;
+        self.write(index, event.time_in_frames, event.event.bytes());
+    }
+}
+
+impl<'e> EventHandler<Indexed<Timed<SysExEvent<'e>>>> for Lv2Host {
+    fn handle_event(&mut self, event: Indexed<Timed<SysExEvent<'e>>>) {
+        let Indexed { index, event } = event;
+        self.write(index, event.time_in_frames, event.event.data());
+    }
+}
+
+// Atom events are padded to 64 bits.
+fn pad_size(size: usize) -> usize {
+    (size + 7) & !7
+}
+
+/// Used internally by the `lv2_init` macro. Normally, plugins do not need to use this.
+pub struct Lv2PluginWrapper<P> {
+    plugin: P,
+    host: Lv2Host,
+    audio_inputs: Vec<*const f32>,
+    audio_outputs: Vec<*mut f32>,
+    midi_inputs: Vec<*const AtomSequence>,
+    inputs: VecStorage<&'static [f32]>,
+    outputs: VecStorage<&'static [f32]>,
+}
+
+impl<P> Lv2PluginWrapper<P>
+where
+    P: Lv2PluginMeta
+        + AudioHandler
+        + ContextualEventHandler<Timed<RawMidiEvent>, Lv2Host>
+        + ContextualAudioRenderer<f32, Lv2Host>,
+    for<'a> P: ContextualEventHandler<Timed<SysExEvent<'a>>, Lv2Host>,
+{
+    /// Create the wrapper. Returns `None` if the plugin has midi ports and the host does not
+    /// support the `urid:map` feature.
+    ///
+    /// # Safety
+    /// `features` must be a null-terminated array of valid features, as passed by the host.
+    pub unsafe fn new(
+        mut plugin: P,
+        sample_rate: f64,
+        features: *const *const Lv2Feature,
+    ) -> Option<Self> {
+        let number_of_midi_ports =
+            plugin.max_number_of_midi_inputs() + plugin.max_number_of_midi_outputs();
+        let urid_map = find_feature(features, URID_MAP_URI).map(|data| data as *const Lv2UridMap);
+        let (midi_event_urid, sequence_urid) = match urid_map {
+            Some(map) => (
+                map_uri(map, MIDI_EVENT_URI),
+                map_uri(map, ATOM_SEQUENCE_URI),
+            ),
+            None if number_of_midi_ports == 0 => (0, 0),
+            None => {
+                error!("The host does not support the urid:map feature.");
+                return None;
+            }
+        };
+        trace!("sample_rate: {}", sample_rate);
+        plugin.set_sample_rate(sample_rate);
+        if let Some(map) = urid_map {
+            if let Some(max_block_length) = find_max_block_length(features, map) {
+                trace!("max_block_length: {}", max_block_length);
+                plugin.set_max_buffer_size(max_block_length);
+            }
+        }
+        let number_of_inputs = plugin.max_number_of_audio_inputs();
+        let number_of_outputs = plugin.max_number_of_audio_outputs();
+        Some(Lv2PluginWrapper {
+            host: Lv2Host {
+                midi_outputs: (0..plugin.max_number_of_midi_outputs())
+                    .map(|_| MidiOutput {
+                        sequence: ptr::null_mut(),
+                        capacity: 0,
+                    })
+                    .collect(),
+                midi_event_urid,
+                sequence_urid,
+            },
+            audio_inputs: vec![ptr::null(); number_of_inputs],
+            audio_outputs: vec![ptr::null_mut(); number_of_outputs],
+            midi_inputs: vec![ptr::null(); plugin.max_number_of_midi_inputs()],
+            inputs: VecStorage::with_capacity(number_of_inputs),
+            outputs: VecStorage::with_capacity(number_of_outputs),
+            plugin,
+        })
+    }
+
+    pub fn plugin(&self) -> &P {
+        &self.plugin
+    }
+
+    /// Connect the port with the given index to the buffer at `data`.
+    pub fn connect_port(&mut self, port: u32, data: *mut c_void) {
+        let mut index = port as usize;
+        if index < self.audio_inputs.len() {
+            self.audio_inputs[index] = data as *const f32;
+            return;
+        }
+        index -= self.audio_inputs.len();
+        if index < self.audio_outputs.len() {
+            self.audio_outputs[index] = data as *mut f32;
+            return;
+        }
+        index -= self.audio_outputs.len();
+        if index < self.midi_inputs.len() {
+            self.midi_inputs[index] = data as *const AtomSequence;
+            return;
+        }
+        index -= self.midi_inputs.len();
+        match self.host.midi_outputs.get_mut(index) {
+            Some(output) => output.sequence = data as *mut AtomSequence,
+            None => warn!("Ignoring connection to unknown port {}", port),
+        }
+    }
+
+    /// Handle the midi input and render the audio of one buffer.
+    ///
+    /// # Safety
+    /// All ports must be connected to buffers that are valid for `sample_count` frames.
+    pub unsafe fn run(&mut self, sample_count: u32) {
+        #[cfg(feature = "flush-denormals")]
+        let _denormal_guard = DenormalGuard::new();
+        self.host.begin_run();
+        for index in 0..self.midi_inputs.len() {
+            let sequence = self.midi_inputs[index];
+            if !sequence.is_null() {
+                self.handle_sequence(sequence);
+            }
+        }
+
+        let number_of_frames = sample_count as usize;
+        let mut inputs = self.inputs.vec_guard();
+        for input in self.audio_inputs.iter().filter(|input| !input.is_null()) {
+            inputs.push(slice::from_raw_parts(*input, number_of_frames));
+        }
+        let mut outputs = self.outputs.vec_guard();
+        for output in self.audio_outputs.iter().filter(|output| !output.is_null()) {
+            outputs.push(slice::from_raw_parts_mut(*output, number_of_frames));
+        }
+        self.plugin
+            .render_buffer(inputs.as_slice(), outputs.as_mut_slice(), &mut self.host);
+    }
+
+    unsafe fn handle_sequence(&mut self, sequence: *const AtomSequence) {
+        let start = sequence as *const u8;
+        // `atom.size` counts the bytes after the atom header.
+        let end = std::mem::size_of::<Atom>() + (*sequence).atom.size as usize;
+        let mut offset = std::mem::size_of::<AtomSequence>();
+        while offset + std::mem::size_of::<AtomEvent>() <= end {
+            let event = ptr::read(start.add(offset) as *const AtomEvent);
+            let data_start = offset + std::mem::size_of::<AtomEvent>();
+            let size = event.body.size as usize;
+            if data_start + size > end {
+                break;
+            }
+            if event.body.type_ == self.host.midi_event_urid && size > 0 {
+                let data = slice::from_raw_parts(start.add(data_start), size);
+                let time_in_frames = event.frames.max(0) as u32;
+                if data[0] == 0xF0 {
+                    let event = Timed::new(time_in_frames, SysExEvent::new(data));
+                    self.plugin.handle_event(event, &mut self.host);
+                } else if let Some(raw_event) = RawMidiEvent::try_new(data) {
+                    let event = Timed::new(time_in_frames, raw_event);
+                    self.plugin.handle_event(event, &mut self.host);
+                } else {
+                    warn!("Ignoring invalid midi event: {:?}", data);
+                }
+            }
+            offset = data_start + pad_size(size);
+        }
+    }
+}
+
+// The data of the feature with the given URI, if the host supports it.
+unsafe fn find_feature(features: *const *const Lv2Feature, uri: &[u8]) -> Option<*mut c_void> {
+    if features.is_null() {
+        return None;
+    }
+    let wanted = CStr::from_bytes_with_nul(uri).expect("the URI ends with a null byte");
+    let mut feature = features;
+    while !(*feature).is_null() {
+        if CStr::from_ptr((**feature).uri) == wanted {
+            return Some((**feature).data);
+        }
+        feature = feature.add(1);
+    }
+    None
+}
+
+unsafe fn map_uri(map: *const Lv2UridMap, uri: &[u8]) -> u32 {
+    ((*map).map)((*map).handle, uri.as_ptr() as *const c_char)
+}
+
+// The `bufsz:maxBlockLength` option, if the host passes it with the `options:options` feature.
+unsafe fn find_max_block_length(
+    features: *const *const Lv2Feature,
+    map: *const Lv2UridMap,
+) -> Option<usize> {
+    let mut option = find_feature(features, OPTIONS_URI)? as *const Lv2OptionsOption;
+    if option.is_null() {
+        return None;
+    }
+    let max_block_length_urid = map_uri(map, MAX_BLOCK_LENGTH_URI);
+    let int_urid = map_uri(map, ATOM_INT_URI);
+    while (*option).key != 0 || !(*option).value.is_null() {
+        if (*option).key == max_block_length_urid
+            && (*option).type_ == int_urid
+            && !(*option).value.is_null()
+        {
+            let max_block_length = *((*option).value as *const i32);
+            return if max_block_length > 0 {
+                Some(max_block_length as usize)
+            } else {
+                None
+            };
+        }
+        option = option.add(1);
+    }
+    None
+}
+
+/// Used internally by the `lv2_init` macro. Normally, plugins do not need to use this.
+pub const fn descriptor<P>(uri: &'static [u8]) -> Lv2Descriptor
+where
+    P: Lv2Create
+        + Lv2PluginMeta
+        + AudioHandler
+        + ContextualEventHandler<Timed<RawMidiEvent>, Lv2Host>
+        + ContextualAudioRenderer<f32, Lv2Host>,
+    for<'a> P: ContextualEventHandler<Timed<SysExEvent<'a>>, Lv2Host>,
+{
+    Lv2Descriptor {
+        uri: uri.as_ptr() as *const c_char,
+        instantiate: instantiate::<P>,
+        connect_port: connect_port::<P>,
+        activate: None,
+        run: run::<P>,
+        deactivate: None,
+        cleanup: cleanup::<P>,
+        extension_data,
+    }
+}
+
+unsafe extern "C" fn instantiate<P>(
+    _descriptor: *const Lv2Descriptor,
+    sample_rate: f64,
+    _bundle_path: *const c_char,
+    features: *const *const Lv2Feature,
+) -> Lv2Handle
+where
+    P: Lv2Create
+        + Lv2PluginMeta
+        + AudioHandler
+        + ContextualEventHandler<Timed<RawMidiEvent>, Lv2Host>
+        + ContextualAudioRenderer<f32, Lv2Host>,
+    for<'a> P: ContextualEventHandler<Timed<SysExEvent<'a>>, Lv2Host>,
+{
+    match Lv2PluginWrapper::new(P::create(), sample_rate, features) {
+        Some(wrapper) => Box::into_raw(Box::new(wrapper)) as Lv2Handle,
+        None => ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn connect_port<P>(instance: Lv2Handle, port: u32, data: *mut c_void)
+where
+    P: Lv2PluginMeta
+        + AudioHandler
+        + ContextualEventHandler<Timed<RawMidiEvent>, Lv2Host>
+        + ContextualAudioRenderer<f32, Lv2Host>,
+    for<'a> P: ContextualEventHandler<Timed<SysExEvent<'a>>, Lv2Host>,
+{
+    (*(instance as *mut Lv2PluginWrapper<P>)).connect_port(port, data);
+}
+
+unsafe extern "C" fn run<P>(instance: Lv2Handle, sample_count: u32)
+where
+    P: Lv2PluginMeta
+        + AudioHandler
+        + ContextualEventHandler<Timed<RawMidiEvent>, Lv2Host>
+        + ContextualAudioRenderer<f32, Lv2Host>,
+    for<'a> P: ContextualEventHandler<Timed<SysExEvent<'a>>, Lv2Host>,
+{
+    (*(instance as *mut Lv2PluginWrapper<P>)).run(sample_count);
+}
+
+unsafe extern "C" fn cleanup<P>(instance: Lv2Handle) {
+    drop(Box::from_raw(instance as *mut Lv2PluginWrapper<P>));
+}
+
+unsafe extern "C" fn extension_data(_uri: *const c_char) -> *const c_void {
+    ptr::null()
+}
+
+// Escape a string for a Turtle string literal.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Generate the contents of `manifest.ttl`, which tells the host where to find the plugin.
+///
+/// `binary` is the file name of the dynamic library, e.g. `libmy_plugin.so`.
+pub fn manifest_ttl(uri: &str, binary: &str) -> String {
+    format!(
+        "@prefix lv2: <http://lv2plug.in/ns/lv2core#> .\n\
+         @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .\n\
+         \n\
+         <{}>\n    a lv2:Plugin ;\n    lv2:binary <{}> ;\n    rdfs:seeAlso <plugin.ttl> .\n",
+        uri, binary
+    )
+}
+
+/// Generate the contents of `plugin.ttl`, which describes the ports of the plugin, from the
+/// meta-data of the plugin.
+pub fn plugin_ttl<P>(plugin: &P, uri: &str) -> String
+where
+    P: Lv2PluginMeta,
+{
+    let number_of_midi_ports =
+        plugin.max_number_of_midi_inputs() + plugin.max_number_of_midi_outputs();
+    let mut ttl = String::from(
+        "@prefix atom: <http://lv2plug.in/ns/ext/atom#> .\n\
+         @prefix doap: <http://usefulinc.com/ns/doap#> .\n\
+         @prefix lv2: <http://lv2plug.in/ns/lv2core#> .\n\
+         @prefix midi: <http://lv2plug.in/ns/ext/midi#> .\n\
+         @prefix urid: <http://lv2plug.in/ns/ext/urid#> .\n\n",
+    );
+    let _ = write!(ttl, "<{}>\n    a lv2:Plugin", uri);
+    if let Some(class) = plugin.plugin_class() {
+        let _ = write!(ttl, ", lv2:{}", class);
+    }
+    let _ = write!(
+        ttl,
+        " ;\n    doap:name \"{}\" ;\n    lv2:optionalFeature lv2:hardRTCapable ;\n",
+        escape(plugin.name())
+    );
+    if number_of_midi_ports > 0 {
+        ttl.push_str("    lv2:requiredFeature urid:map ;\n");
+    }
+
+    let mut ports = Vec::new();
+    for index in 0..plugin.max_number_of_audio_inputs() {
+        ports.push((
+            "lv2:AudioPort, lv2:InputPort",
+            format!("audio_in_{}", index),
+            plugin.audio_input_name(index),
+        ));
+    }
+    for index in 0..plugin.max_number_of_audio_outputs() {
+        ports.push((
+            "lv2:AudioPort, lv2:OutputPort",
+            format!("audio_out_{}", index),
+            plugin.audio_output_name(index),
+        ));
+    }
+    for index in 0..plugin.max_number_of_midi_inputs() {
+        ports.push((
+            "atom:AtomPort, lv2:InputPort",
+            format!("midi_in_{}", index),
+            plugin.midi_input_name(index),
+        ));
+    }
+    for index in 0..plugin.max_number_of_midi_outputs() {
+        ports.push((
+            "atom:AtomPort, lv2:OutputPort",
+            format!("midi_out_{}", index),
+            plugin.midi_output_name(index),
+        ));
+    }
+    for (index, (types, symbol, name)) in ports.iter().enumerate() {
+        ttl.push_str(if index == 0 {
+            "    lv2:port [\n"
+        } else {
+            "    ] , [\n"
+        });
+        let _ = writeln!(ttl, "        a {} ;", types);
+        if types.starts_with("atom:") {
+            ttl.push_str("        atom:bufferType atom:Sequence ;\n");
+            ttl.push_str("        atom:supports midi:MidiEvent ;\n");
+        }
+        let _ = writeln!(ttl, "        lv2:index {} ;", index);
+        let _ = writeln!(ttl, "        lv2:symbol \"{}\" ;", symbol);
+        let _ = writeln!(ttl, "        lv2:name \"{}\" ;", escape(name));
+    }
+    if ports.is_empty() {
+        // Remove the " ;\n" after the last statement.
+        ttl.truncate(ttl.len() - 3);
+        ttl.push_str(" .\n");
+    } else {
+        ttl.push_str("    ] .\n");
+    }
+    ttl
+}
+
+/// Write `manifest.ttl` and `plugin.ttl` to the bundle directory, which is created if needed.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// This function performs I/O.
+pub fn write_bundle<P>(plugin: &P, uri: &str, binary: &str, directory: &Path) -> io::Result<()>
+where
+    P: Lv2PluginMeta,
+{
+    fs::create_dir_all(directory)?;
+    fs::write(directory.join("manifest.ttl"), manifest_ttl(uri, binary))?;
+    fs::write(directory.join("plugin.ttl"), plugin_ttl(plugin, uri))
+}
+
+/// Export the plugin as an LV2 plugin with the given URI.
+/// You call this with the URI and the declaration of a function that creates your plugin.
+/// This function may also do some setup (e.g. initialize logging).
+///
+/// The plugin must implement the traits that are required by the [`lv2_backend`] module.
+///
+/// Example:
+/// ```
+/// # #[macro_use] extern crate rsynth;
+/// use rsynth::backend::lv2_backend::{Lv2Host, Lv2PluginMeta};
+/// use rsynth::event::{ContextualEventHandler, RawMidiEvent, SysExEvent, Timed};
+/// use rsynth::meta::{InOut, Meta, MetaData};
+/// use rsynth::{AudioHandler, ContextualAudioRenderer};
+///
+/// struct MyPlugin {
+///     meta: MetaData<&'static str, &'static str, &'static str>,
+/// }
+///
+/// impl Meta for MyPlugin {
+///     type MetaData = MetaData<&'static str, &'static str, &'static str>;
+///     fn meta(&self) -> &Self::MetaData {
+///         &self.meta
+///     }
+/// }
+///
+/// impl Lv2PluginMeta for MyPlugin {
+///     fn plugin_class(&self) -> Option<&str> {
+///         Some("InstrumentPlugin")
+///     }
+/// }
+///
+/// impl AudioHandler for MyPlugin {
+///     // Implementation omitted for brevity.
+/// #     fn set_sample_rate(&mut self, new_sample_rate: f64) {}
+/// }
+///
+/// impl ContextualAudioRenderer<f32, Lv2Host> for MyPlugin {
+///     // Implementation omitted for brevity.
+/// #    fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut[&mut[f32]], context: &mut Lv2Host) {}
+/// }
+///
+/// impl ContextualEventHandler<Timed<RawMidiEvent>, Lv2Host> for MyPlugin {
+///     // Implementation omitted for brevity.
+/// #    fn handle_event(&mut self, event: Timed<RawMidiEvent>, context: &mut Lv2Host) {}
+/// }
+///
+/// impl<'a> ContextualEventHandler<Timed<SysExEvent<'a>>, Lv2Host> for MyPlugin {
+///     // Implementation omitted for brevity.
+/// #    fn handle_event(&mut self, event: Timed<SysExEvent<'a>>, context: &mut Lv2Host) {}
+/// }
+///
+/// lv2_init!(
+///     "https://example.com/plugins/my_plugin",
+///     fn init() -> MyPlugin {
+///         MyPlugin {
+///             meta: MetaData {
+///                 general_meta: "my_plugin",
+///                 audio_port_meta: InOut {
+///                     inputs: vec![],
+///                     outputs: vec!["left", "right"],
+///                 },
+///                 midi_port_meta: InOut {
+///                     inputs: vec!["midi in"],
+///                     outputs: vec![],
+///                 },
+///             },
+///         }
+///     }
+/// );
+/// # fn main() {}
+/// ```
+///
+/// [`lv2_backend`]: ./backend/lv2_backend/index.html
+#[macro_export]
+macro_rules! lv2_init {
+    ($uri:expr, fn $function_name:ident() -> $return_type:ty
+        $body:block
+    ) => {
+        fn $function_name() -> $return_type
+        $body
+
+        impl $crate::backend::lv2_backend::Lv2Create for $return_type {
+            fn create() -> Self {
+                $function_name()
+            }
+        }
+
+        static RSYNTH_LV2_DESCRIPTOR: $crate::backend::lv2_backend::sys::Lv2Descriptor =
+            $crate::backend::lv2_backend::descriptor::<$return_type>(concat!($uri, "\0").as_bytes());
+
+        #[no_mangle]
+        pub extern "C" fn lv2_descriptor(
+            index: u32,
+        ) -> *const $crate::backend::lv2_backend::sys::Lv2Descriptor {
+            if index == 0 {
+                &RSYNTH_LV2_DESCRIPTOR
+            } else {
+                ::std::ptr::null()
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::{InOut, Meta, MetaData};
+    use std::collections::HashMap;
+    use std::ffi::CString;
+
+    struct TestPlugin {
+        meta: MetaData<&'static str, &'static str, &'static str>,
+        sample_rate: f64,
+        max_buffer_size: Option<usize>,
+        events: Vec<Timed<RawMidiEvent>>,
+        sysex_lengths: Vec<usize>,
+    }
+
+    impl Meta for TestPlugin {
+        type MetaData = MetaData<&'static str, &'static str, &'static str>;
+        fn meta(&self) -> &Self::MetaData {
+            &self.meta
+        }
+    }
+
+    impl Lv2PluginMeta for TestPlugin {
+        fn plugin_class(&self) -> Option<&str> {
+            Some("InstrumentPlugin")
+        }
+    }
+
+    impl Lv2Create for TestPlugin {
+        fn create() -> Self {
+            TestPlugin {
+                meta: MetaData {
+                    general_meta: "Test \"synth\"",
+                    audio_port_meta: InOut {
+                        inputs: vec!["in"],
+                        outputs: vec!["left", "right"],
+                    },
+                    midi_port_meta: InOut {
+                        inputs: vec!["midi in"],
+                        outputs: vec!["midi out"],
+                    },
+                },
+                sample_rate: 0.0,
+                max_buffer_size: None,
+                events: Vec::new(),
+                sysex_lengths: Vec::new(),
+            }
+        }
+    }
+
+    impl AudioHandler for TestPlugin {
+        fn set_sample_rate(&mut self, sample_rate: f64) {
+            self.sample_rate = sample_rate;
+        }
+
+        fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+            self.max_buffer_size = Some(max_buffer_size);
+        }
+    }
+
+    impl ContextualAudioRenderer<f32, Lv2Host> for TestPlugin {
+        fn render_buffer(
+            &mut self,
+            inputs: &[&[f32]],
+            outputs: &mut [&mut [f32]],
+            host: &mut Lv2Host,
+        ) {
+            for output in outputs.iter_mut() {
+                for (output, input) in output.iter_mut().zip(inputs[0].iter()) {
+                    *output = 2.0 * input;
+                }
+            }
+            for event in self.events.iter() {
+                host.handle_event(Indexed::new(0, *event));
+            }
+        }
+    }
+
+    impl ContextualEventHandler<Timed<RawMidiEvent>, Lv2Host> for TestPlugin {
+        fn handle_event(&mut self, event: Timed<RawMidiEvent>, _: &mut Lv2Host) {
+            self.events.push(event);
+        }
+    }
+
+    impl<'a> ContextualEventHandler<Timed<SysExEvent<'a>>, Lv2Host> for TestPlugin {
+        fn handle_event(&mut self, event: Timed<SysExEvent<'a>>, _: &mut Lv2Host) {
+            self.sysex_lengths.push(event.event.data().len());
+        }
+    }
+
+    static TEST_DESCRIPTOR: Lv2Descriptor = descriptor::<TestPlugin>(b"urn:rsynth:test\0");
+
+    unsafe extern "C" fn map(handle: *mut c_void, uri: *const c_char) -> u32 {
+        let map = &mut *(handle as *mut HashMap<CString, u32>);
+        let next = map.len() as u32 + 1;
+        *map.entry(CStr::from_ptr(uri).to_owned()).or_insert(next)
+    }
+
+    // An atom sequence buffer, aligned to 64 bits.
+    fn sequence(events: &[(i64, u32, &[u8])], capacity_in_words: usize) -> Vec<u64> {
+        let mut buffer = vec![0_u64; capacity_in_words];
+        let mut bytes = Vec::new();
+        for (frames, type_, data) in events {
+            bytes.extend_from_slice(&frames.to_ne_bytes());
+            bytes.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+            bytes.extend_from_slice(&type_.to_ne_bytes());
+            bytes.extend_from_slice(data);
+            while bytes.len() % 8 != 0 {
+                bytes.push(0);
+            }
+        }
+        let header = AtomSequence {
+            atom: Atom {
+                size: 8 + bytes.len() as u32,
+                type_: 0,
+            },
+            unit: 0,
+            pad: 0,
+        };
+        unsafe {
+            ptr::write(buffer.as_mut_ptr() as *mut AtomSequence, header);
+            ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                (buffer.as_mut_ptr() as *mut u8).add(16),
+                bytes.len(),
+            );
+        }
+        buffer
+    }
+
+    #[test]
+    fn plugin_runs_through_the_c_interface() {
+        let mut uris: HashMap<CString, u32> = HashMap::new();
+        let mut urid_map = Lv2UridMap {
+            handle: &mut uris as *mut _ as *mut c_void,
+            map,
+        };
+        let feature = Lv2Feature {
+            uri: URID_MAP_URI.as_ptr() as *const c_char,
+            data: &mut urid_map as *mut _ as *mut c_void,
+        };
+        let features = [&feature as *const Lv2Feature, ptr::null()];
+        let descriptor = &TEST_DESCRIPTOR;
+        unsafe {
+            assert_eq!(
+                CStr::from_ptr(descriptor.uri).to_str(),
+                Ok("urn:rsynth:test")
+            );
+            let instance =
+                (descriptor.instantiate)(descriptor, 48000.0, ptr::null(), features.as_ptr());
+            assert!(!instance.is_null());
+            let midi_event_urid =
+                uris[&CString::new("http://lv2plug.in/ns/ext/midi#MidiEvent").unwrap()];
+
+            let mut input = [1.0_f32, 2.0, 3.0, 4.0];
+            let mut left = [0.0_f32; 4];
+            let mut right = [0.0_f32; 4];
+            let sysex: &[u8] = &[0xF0, 1, 2, 0xF7];
+            let mut midi_in = sequence(
+                &[
+                    (1, midi_event_urid, &[0x90, 60, 100]),
+                    (2, midi_event_urid + 100, &[1, 2, 3]),
+                    (3, midi_event_urid, sysex),
+                ],
+                16,
+            );
+            let mut midi_out = vec![0_u64; 16];
+            // The host passes the capacity in the size of the atom.
+            (*(midi_out.as_mut_ptr() as *mut AtomSequence)).atom.size = 120;
+            let buffers: [*mut c_void; 5] = [
+                input.as_mut_ptr() as *mut c_void,
+                left.as_mut_ptr() as *mut c_void,
+                right.as_mut_ptr() as *mut c_void,
+                midi_in.as_mut_ptr() as *mut c_void,
+                midi_out.as_mut_ptr() as *mut c_void,
+            ];
+            for (port, buffer) in buffers.iter().enumerate() {
+                (descriptor.connect_port)(instance, port as u32, *buffer);
+            }
+            (descriptor.run)(instance, 4);
+
+            assert_eq!(left, [2.0, 4.0, 6.0, 8.0]);
+            assert_eq!(right, left);
+            let wrapper = &*(instance as *const Lv2PluginWrapper<TestPlugin>);
+            assert_eq!(wrapper.plugin().sample_rate, 48000.0);
+            assert_eq!(wrapper.plugin().max_buffer_size, None);
+            assert_eq!(
+                wrapper.plugin().events,
+                vec![Timed::new(1, RawMidiEvent::new(&[0x90, 60, 100]))]
+            );
+            assert_eq!(wrapper.plugin().sysex_lengths, vec![4]);
+
+            // The event that was sent by the plugin.
+            let output = *(midi_out.as_ptr() as *const AtomSequence);
+            assert_eq!(output.atom.size, 8 + 24);
+            let event = *((midi_out.as_ptr() as *const u8).add(16) as *const AtomEvent);
+            assert_eq!(event.frames, 1);
+            assert_eq!(event.body.size, 3);
+            assert_eq!(event.body.type_, midi_event_urid);
+            let data = slice::from_raw_parts((midi_out.as_ptr() as *const u8).add(32), 3);
+            assert_eq!(data, &[0x90, 60, 100]);
+
+            (descriptor.cleanup)(instance);
+        }
+    }
+
+    #[test]
+    fn max_block_length_is_passed_to_the_plugin() {
+        let mut uris: HashMap<CString, u32> = HashMap::new();
+        let mut urid_map = Lv2UridMap {
+            handle: &mut uris as *mut _ as *mut c_void,
+            map,
+        };
+        let max_block_length: i32 = 512;
+        let options = unsafe {
+            [
+                Lv2OptionsOption {
+                    context: 0,
+                    subject: 0,
+                    key: map_uri(&urid_map, MAX_BLOCK_LENGTH_URI),
+                    size: 4,
+                    type_: map_uri(&urid_map, ATOM_INT_URI),
+                    value: &max_block_length as *const i32 as *const c_void,
+                },
+                Lv2OptionsOption {
+                    context: 0,
+                    subject: 0,
+                    key: 0,
+                    size: 0,
+                    type_: 0,
+                    value: ptr::null(),
+                },
+            ]
+        };
+        let urid_map_feature = Lv2Feature {
+            uri: URID_MAP_URI.as_ptr() as *const c_char,
+            data: &mut urid_map as *mut _ as *mut c_void,
+        };
+        let options_feature = Lv2Feature {
+            uri: OPTIONS_URI.as_ptr() as *const c_char,
+            data: options.as_ptr() as *mut c_void,
+        };
+        let features = [
+            &urid_map_feature as *const Lv2Feature,
+            &options_feature as *const Lv2Feature,
+            ptr::null(),
+        ];
+        let descriptor = &TEST_DESCRIPTOR;
+        unsafe {
+            let instance =
+                (descriptor.instantiate)(descriptor, 48000.0, ptr::null(), features.as_ptr());
+            assert!(!instance.is_null());
+            let wrapper = &*(instance as *const Lv2PluginWrapper<TestPlugin>);
+            assert_eq!(wrapper.plugin().max_buffer_size, Some(512));
+            (descriptor.cleanup)(instance);
+        }
+    }
+
+    #[test]
+    fn instantiation_fails_without_urid_map() {
+        let features = [ptr::null::<Lv2Feature>()];
+        let descriptor = &TEST_DESCRIPTOR;
+        let instance = unsafe {
+            (descriptor.instantiate)(descriptor, 44100.0, ptr::null(), features.as_ptr())
+        };
+        assert!(instance.is_null());
+    }
+
+    #[test]
+    fn ttl_is_generated_from_the_meta_data() {
+        assert_eq!(
+            manifest_ttl("urn:rsynth:test", "libtest.so"),
+            "@prefix lv2: <http://lv2plug.in/ns/lv2core#> .\n\
+             @prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .\n\
+             \n\
+             <urn:rsynth:test>\n    a lv2:Plugin ;\n    lv2:binary <libtest.so> ;\n    \
+             rdfs:seeAlso <plugin.ttl> .\n"
+        );
+        let ttl = plugin_ttl(&TestPlugin::create(), "urn:rsynth:test");
+        assert!(ttl.contains("<urn:rsynth:test>\n    a lv2:Plugin, lv2:InstrumentPlugin ;\n"));
+        assert!(ttl.contains("doap:name \"Test \\\"synth\\\"\" ;"));
+        assert!(ttl.contains("lv2:requiredFeature urid:map ;"));
+        assert!(ttl.contains(
+            "        a lv2:AudioPort, lv2:OutputPort ;\n        lv2:index 2 ;\n        \
+             lv2:symbol \"audio_out_1\" ;\n        lv2:name \"right\" ;\n"
+        ));
+        assert!(ttl.contains(
+            "        a atom:AtomPort, lv2:OutputPort ;\n        atom:bufferType atom:Sequence ;\n        \
+             atom:supports midi:MidiEvent ;\n        lv2:index 4 ;\n"
+        ));
+        assert_eq!(ttl.matches("lv2:port [").count(), 1);
+        assert_eq!(ttl.matches("] , [").count(), 4);
+        assert!(ttl.ends_with("    ] .\n"));
+    }
+}
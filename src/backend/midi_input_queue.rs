@@ -0,0 +1,362 @@
+//! A lock-free queue to pass midi events from a midi input thread to the audio thread.
+//!
+//! This is used by midi inputs that receive events on a separate thread, such as
+//! [`MidirInput`].
+//! Each event is timestamped when it is received.
+//! The [`MidiInputReceiver`] converts the timestamp to an offset relative to the audio buffer
+//! by assuming that the audio buffer starts exactly one buffer length before
+//! [`MidiInputReceiver::handle_events`] is called.
+//! This adds the latency of one buffer, but avoids jitter.
+//!
+//! Standalone applications
+//! =======================
+//! Wrap the plugin in a [`LiveMidiInput`] to handle the events at the start of each buffer
+//! automatically, e.g. when using the [`cpal`] backend, which has no midi support:
+//!
+//! ```
+//! use rsynth::backend::midi_input_queue::{LiveMidiInput, MidiInputReceiver};
+//! use rsynth::event::{EventHandler, RawMidiEvent, Timed};
+//! use rsynth::{AudioHandler, AudioRenderer};
+//! # use rsynth::backend::midi_input_queue::midi_input_queue;
+//!
+//! struct MySynth;
+//!
+//! impl EventHandler<Timed<RawMidiEvent>> for MySynth {
+//!     fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+//!         // Start or stop a voice.
+//!     }
+//! }
+//!
+//! impl AudioRenderer<f32> for MySynth {
+//!     fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+//!         // Render the voices.
+//!     }
+//! }
+//!
+//! impl AudioHandler for MySynth {
+//!     fn set_sample_rate(&mut self, sample_rate: f64) {}
+//! }
+//!
+//! // E.g. with `MidirInput::open`.
+//! # fn open_midi_input() -> MidiInputReceiver { midi_input_queue(16).1 }
+//! let receiver = open_midi_input();
+//! let mut synth = LiveMidiInput::new(MySynth, receiver);
+//!
+//! // This is done by the audio backend:
+//! synth.set_sample_rate(44100.0);
+//! let mut left = [0.0; 256];
+//! let mut right = [0.0; 256];
+//! synth.render_buffer(&[], &mut [&mut left, &mut right]);
+//! ```
+//!
+//! [`MidirInput`]: ../midir/struct.MidirInput.html
+//! [`MidiInputReceiver`]: ./struct.MidiInputReceiver.html
+//! [`MidiInputReceiver::handle_events`]: ./struct.MidiInputReceiver.html#method.handle_events
+//! [`LiveMidiInput`]: ./struct.LiveMidiInput.html
+//! [`cpal`]: ../cpal/index.html
+use crate::buffer::number_of_frames;
+use crate::event::{ContextualEventHandler, EventHandler, RawMidiEvent, Timed};
+use crate::meta::Meta;
+use crate::{AudioHandler, AudioRenderer, ContextualAudioRenderer, Latency, Lifecycle};
+use ringbuf::{Consumer, Producer, RingBuffer};
+use std::time::{Duration, Instant};
+
+struct ReceivedEvent {
+    received_at: Instant,
+    event: RawMidiEvent,
+}
+
+/// Create a new queue that can contain at most `capacity` events.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// This method allocates memory.
+pub fn midi_input_queue(capacity: usize) -> (MidiInputSender, MidiInputReceiver) {
+    let (producer, consumer) = RingBuffer::new(capacity).split();
+    (MidiInputSender { producer }, MidiInputReceiver { consumer })
+}
+
+/// The sending side of the queue, to be used in the midi input thread.
+pub struct MidiInputSender {
+    producer: Producer<ReceivedEvent>,
+}
+
+impl MidiInputSender {
+    /// Send an event that has been received at the given instant.
+    ///
+    /// When the queue is full, the event is dropped and a warning is logged.
+    pub fn send(&mut self, received_at: Instant, event: RawMidiEvent) {
+        if self
+            .producer
+            .push(ReceivedEvent { received_at, event })
+            .is_err()
+        {
+            warn!("Midi input queue is full, dropping event {:?}", event);
+        }
+    }
+}
+
+/// The receiving side of the queue, to be used in the audio thread.
+pub struct MidiInputReceiver {
+    consumer: Consumer<ReceivedEvent>,
+}
+
+impl MidiInputReceiver {
+    /// Pass all events that have been received so far to the event handler.
+    /// This method should be called at the start of each audio buffer, before rendering.
+    ///
+    /// # Parameters
+    /// * `number_of_frames`: the number of frames in the audio buffer that is about to be rendered.
+    /// * `sample_rate`: the sample rate in frames per second.
+    ///
+    /// This method does not allocate memory and can be used in a real-time context.
+    pub fn handle_events<H>(&mut self, number_of_frames: usize, sample_rate: f64, handler: &mut H)
+    where
+        H: EventHandler<Timed<RawMidiEvent>>,
+    {
+        self.pop_events(number_of_frames, sample_rate, |event| {
+            handler.handle_event(event)
+        });
+    }
+
+    /// Pass all events that have been received so far to the event handler, together with
+    /// the context. See [`handle_events`] for more information.
+    ///
+    /// [`handle_events`]: #method.handle_events
+    pub fn handle_events_with_context<H, C>(
+        &mut self,
+        number_of_frames: usize,
+        sample_rate: f64,
+        handler: &mut H,
+        context: &mut C,
+    ) where
+        H: ContextualEventHandler<Timed<RawMidiEvent>, C>,
+    {
+        self.pop_events(number_of_frames, sample_rate, |event| {
+            handler.handle_event(event, context)
+        });
+    }
+
+    fn pop_events<F>(&mut self, number_of_frames: usize, sample_rate: f64, mut handle_event: F)
+    where
+        F: FnMut(Timed<RawMidiEvent>),
+    {
+        if number_of_frames == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let buffer_duration = Duration::from_secs_f64(number_of_frames as f64 / sample_rate);
+        let buffer_start = now.checked_sub(buffer_duration).unwrap_or(now);
+        let last_frame = (number_of_frames - 1) as u32;
+        while let Some(ReceivedEvent { received_at, event }) = self.consumer.pop() {
+            let time_in_frames = if received_at <= buffer_start {
+                0
+            } else {
+                let offset = (received_at - buffer_start).as_secs_f64() * sample_rate;
+                if offset >= last_frame as f64 {
+                    last_frame
+                } else {
+                    offset as u32
+                }
+            };
+            handle_event(Timed {
+                time_in_frames,
+                event,
+            });
+        }
+    }
+}
+
+/// Wraps a plugin and passes the events of a [`MidiInputReceiver`] to the plugin at the start
+/// of each audio buffer, before rendering.
+///
+/// See the [module level documentation] for an example.
+///
+/// [`MidiInputReceiver`]: ./struct.MidiInputReceiver.html
+/// [module level documentation]: ./index.html
+pub struct LiveMidiInput<R> {
+    inner: R,
+    receiver: MidiInputReceiver,
+    sample_rate: f64,
+}
+
+impl<R> LiveMidiInput<R> {
+    /// Wrap `inner`.
+    pub fn new(inner: R, receiver: MidiInputReceiver) -> Self {
+        LiveMidiInput {
+            inner,
+            receiver,
+            sample_rate: 44100.0,
+        }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> Latency for LiveMidiInput<R>
+where
+    R: Latency,
+{
+    fn latency_in_frames(&self) -> usize {
+        self.inner.latency_in_frames()
+    }
+}
+
+impl<R> Lifecycle for LiveMidiInput<R>
+where
+    R: Lifecycle,
+{
+    fn on_resume(&mut self) {
+        self.inner.on_resume();
+    }
+
+    fn on_suspend(&mut self) {
+        self.inner.on_suspend();
+    }
+}
+
+impl<R, S> AudioRenderer<S> for LiveMidiInput<R>
+where
+    R: AudioRenderer<S> + EventHandler<Timed<RawMidiEvent>>,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        self.receiver.handle_events(
+            number_of_frames(inputs, outputs),
+            self.sample_rate,
+            &mut self.inner,
+        );
+        self.inner.render_buffer(inputs, outputs);
+    }
+}
+
+impl<R, S, Context> ContextualAudioRenderer<S, Context> for LiveMidiInput<R>
+where
+    R: ContextualAudioRenderer<S, Context> + ContextualEventHandler<Timed<RawMidiEvent>, Context>,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], context: &mut Context) {
+        self.receiver.handle_events_with_context(
+            number_of_frames(inputs, outputs),
+            self.sample_rate,
+            &mut self.inner,
+            context,
+        );
+        self.inner.render_buffer(inputs, outputs, context);
+    }
+}
+
+impl<R, E> EventHandler<E> for LiveMidiInput<R>
+where
+    R: EventHandler<E>,
+{
+    fn handle_event(&mut self, event: E) {
+        self.inner.handle_event(event);
+    }
+
+    fn handle_events(&mut self, events: &[E])
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events);
+    }
+}
+
+impl<R, E, Context> ContextualEventHandler<E, Context> for LiveMidiInput<R>
+where
+    R: ContextualEventHandler<E, Context>,
+{
+    fn handle_event(&mut self, event: E, context: &mut Context) {
+        self.inner.handle_event(event, context);
+    }
+
+    fn handle_events(&mut self, events: &[E], context: &mut Context)
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events, context);
+    }
+}
+
+impl<R> AudioHandler for LiveMidiInput<R>
+where
+    R: AudioHandler,
+{
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        self.inner.set_sample_rate(sample_rate);
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.inner.set_max_buffer_size(max_buffer_size);
+    }
+}
+
+impl<R> Meta for LiveMidiInput<R>
+where
+    R: Meta,
+{
+    type MetaData = R::MetaData;
+
+    fn meta(&self) -> &Self::MetaData {
+        self.inner.meta()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<(Timed<RawMidiEvent>, usize)>,
+        rendered: usize,
+    }
+
+    impl ContextualEventHandler<Timed<RawMidiEvent>, usize> for Recorder {
+        fn handle_event(&mut self, event: Timed<RawMidiEvent>, context: &mut usize) {
+            self.events.push((event, *context));
+        }
+    }
+
+    impl ContextualAudioRenderer<f32, usize> for Recorder {
+        fn render_buffer(&mut self, _: &[&[f32]], _: &mut [&mut [f32]], _: &mut usize) {
+            self.rendered = self.events.len();
+        }
+    }
+
+    impl AudioHandler for Recorder {
+        fn set_sample_rate(&mut self, _: f64) {}
+    }
+
+    #[test]
+    fn events_are_handled_before_rendering() {
+        let (mut sender, receiver) = midi_input_queue(4);
+        let mut input = LiveMidiInput::new(Recorder::default(), receiver);
+        input.set_sample_rate(1000.0);
+        let note_on = RawMidiEvent::new(&[0x90, 60, 100]);
+        let note_off = RawMidiEvent::new(&[0x80, 60, 0]);
+        let now = Instant::now();
+        sender.send(now - Duration::from_secs(1), note_on);
+        sender.send(now + Duration::from_secs(1), note_off);
+
+        let mut output = [0.0_f32; 8];
+        let mut context = 7_usize;
+        input.render_buffer(&[], &mut [&mut output], &mut context);
+        // The first event was received before the buffer, the second one is too late for
+        // this buffer: it gets the time of the last frame.
+        assert_eq!(
+            input.inner().events,
+            vec![(Timed::new(0, note_on), 7), (Timed::new(7, note_off), 7)]
+        );
+        assert_eq!(input.inner().rendered, 2);
+    }
+}
@@ -0,0 +1,263 @@
+//! Hardware midi input and output, using the [`midir`] crate.
+//!
+//! Support is only enabled if you compile with the "midi-io" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! This is intended to be used in standalone applications that use an audio backend without
+//! midi support.
+//!
+//! Midi input
+//! ==========
+//! A [`MidirInput`] receives midi events from a hardware port on a separate thread
+//! (this is handled by `midir`) and sends them over a lock-free queue to the audio thread.
+//! In the audio thread, call [`MidiInputReceiver::handle_events`] at the start of each
+//! audio buffer to pass the events to the plugin as `Timed<RawMidiEvent>`, or wrap the
+//! plugin in a [`LiveMidiInput`] to do this automatically.
+//! See the [`midi_input_queue`] module for more information about the timing of the events.
+//!
+//! The events are timestamped with the timestamps of `midir`, rather than with the time at
+//! which the midi input thread handles them, so that events that are handled in a burst
+//! keep their relative timing.
+//!
+//! Midi output
+//! ===========
+//! A [`MidirOutput`] sends midi events to a hardware port.
+//!
+//! [`midir`]: https://crates.io/crates/midir
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`MidirInput`]: ./struct.MidirInput.html
+//! [`MidiInputReceiver::handle_events`]: ../midi_input_queue/struct.MidiInputReceiver.html#method.handle_events
+//! [`midi_input_queue`]: ../midi_input_queue/index.html
+//! [`LiveMidiInput`]: ../midi_input_queue/struct.LiveMidiInput.html
+//! [`MidirOutput`]: ./struct.MidirOutput.html
+use super::midi_input_queue::{midi_input_queue, MidiInputReceiver, MidiInputSender};
+use crate::event::{EventHandler, RawMidiEvent, Timed};
+use midir::{
+    ConnectErrorKind, InitError, MidiInput, MidiInputConnection, MidiInputPort, MidiOutput,
+    MidiOutputConnection, MidiOutputPort, PortInfoError, SendError,
+};
+use std::time::{Duration, Instant};
+
+/// The error type that represents the errors you can get when opening a midi port.
+#[derive(Debug)]
+pub enum MidirError {
+    /// Midi support could not be initialized.
+    InitError(InitError),
+    /// No port could be found with the given name.
+    PortNotFound(String),
+    /// The information about a port could not be retrieved.
+    PortInfoError(PortInfoError),
+    /// An error occurred when connecting to the port.
+    ConnectError(ConnectErrorKind),
+}
+
+/// Get the names of the available midi input ports.
+pub fn input_port_names(client_name: &str) -> Result<Vec<String>, MidirError> {
+    let midi_input = MidiInput::new(client_name).map_err(MidirError::InitError)?;
+    let mut result = Vec::new();
+    for port in midi_input.ports().iter() {
+        result.push(
+            midi_input
+                .port_name(port)
+                .map_err(MidirError::PortInfoError)?,
+        );
+    }
+    Ok(result)
+}
+
+/// Get the names of the available midi output ports.
+pub fn output_port_names(client_name: &str) -> Result<Vec<String>, MidirError> {
+    let midi_output = MidiOutput::new(client_name).map_err(MidirError::InitError)?;
+    let mut result = Vec::new();
+    for port in midi_output.ports().iter() {
+        result.push(
+            midi_output
+                .port_name(port)
+                .map_err(MidirError::PortInfoError)?,
+        );
+    }
+    Ok(result)
+}
+
+fn find_input_port(midi_input: &MidiInput, port_name: &str) -> Result<MidiInputPort, MidirError> {
+    for port in midi_input.ports() {
+        if midi_input
+            .port_name(&port)
+            .map_err(MidirError::PortInfoError)?
+            .contains(port_name)
+        {
+            return Ok(port);
+        }
+    }
+    Err(MidirError::PortNotFound(port_name.to_string()))
+}
+
+fn find_output_port(
+    midi_output: &MidiOutput,
+    port_name: &str,
+) -> Result<MidiOutputPort, MidirError> {
+    for port in midi_output.ports() {
+        if midi_output
+            .port_name(&port)
+            .map_err(MidirError::PortInfoError)?
+            .contains(port_name)
+        {
+            return Ok(port);
+        }
+    }
+    Err(MidirError::PortNotFound(port_name.to_string()))
+}
+
+/// A connection to a midi input port.
+///
+/// The connection is closed when the `MidirInput` is dropped.
+/// See the [module level documentation] for more information.
+///
+/// System exclusive events are not supported
+/// =========================================
+/// Only events that fit in a [`RawMidiEvent`] (at most three bytes) are passed to the audio
+/// thread. System exclusive events are dropped and a warning is logged.
+///
+/// [module level documentation]: ./index.html
+/// [`RawMidiEvent`]: ../../event/struct.RawMidiEvent.html
+pub struct MidirInput {
+    _connection: MidiInputConnection<MidirReceiveState>,
+}
+
+// Converts the timestamps of `midir` (in microseconds, relative to an unspecified origin)
+// to `Instant`s.
+struct TimestampClock {
+    origin: Option<Instant>,
+}
+
+impl TimestampClock {
+    fn new() -> Self {
+        TimestampClock { origin: None }
+    }
+
+    // `now` is the time at which the event with the given timestamp is handled, which is
+    // always some time after the event has been received. The origin is estimated from the
+    // event that has been handled with the smallest delay so far.
+    fn received_at(&mut self, timestamp: u64, now: Instant) -> Instant {
+        let timestamp = Duration::from_micros(timestamp);
+        let estimate = now.checked_sub(timestamp).unwrap_or(now);
+        let origin = match self.origin {
+            Some(origin) if origin <= estimate => origin,
+            _ => estimate,
+        };
+        self.origin = Some(origin);
+        (origin + timestamp).min(now)
+    }
+}
+
+struct MidirReceiveState {
+    sender: MidiInputSender,
+    clock: TimestampClock,
+}
+
+impl MidirInput {
+    /// Connect to the first midi input port whose name contains `port_name`.
+    ///
+    /// At most `queue_capacity` events can be waiting to be handled by the audio thread,
+    /// events that are received when the queue is full are dropped.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory.
+    pub fn open(
+        client_name: &str,
+        port_name: &str,
+        queue_capacity: usize,
+    ) -> Result<(Self, MidiInputReceiver), MidirError> {
+        let midi_input = MidiInput::new(client_name).map_err(MidirError::InitError)?;
+        let port = find_input_port(&midi_input, port_name)?;
+        let (sender, receiver) = midi_input_queue(queue_capacity);
+        info!("Connecting to midi input port {}", port_name);
+        let state = MidirReceiveState {
+            sender,
+            clock: TimestampClock::new(),
+        };
+        let connection = midi_input
+            .connect(&port, client_name, Self::receive, state)
+            .map_err(|e| MidirError::ConnectError(e.kind()))?;
+        Ok((
+            Self {
+                _connection: connection,
+            },
+            receiver,
+        ))
+    }
+
+    fn receive(timestamp: u64, data: &[u8], state: &mut MidirReceiveState) {
+        let received_at = state.clock.received_at(timestamp, Instant::now());
+        if let Some(event) = RawMidiEvent::try_new(data) {
+            state.sender.send(received_at, event);
+        } else {
+            // The midi input queue only has room for `RawMidiEvent`s.
+            warn!("Ignoring midi event of length {}", data.len());
+        }
+    }
+}
+
+/// A connection to a midi output port.
+///
+/// The connection is closed when the `MidirOutput` is dropped.
+///
+/// Note about using in a real-time context
+/// =======================================
+/// Events are sent immediately, which involves a system call.
+/// The `time_in_frames` of `Timed` events is ignored.
+pub struct MidirOutput {
+    connection: MidiOutputConnection,
+}
+
+impl MidirOutput {
+    /// Connect to the first midi output port whose name contains `port_name`.
+    pub fn open(client_name: &str, port_name: &str) -> Result<Self, MidirError> {
+        let midi_output = MidiOutput::new(client_name).map_err(MidirError::InitError)?;
+        let port = find_output_port(&midi_output, port_name)?;
+        info!("Connecting to midi output port {}", port_name);
+        let connection = midi_output
+            .connect(&port, client_name)
+            .map_err(|e| MidirError::ConnectError(e.kind()))?;
+        Ok(Self { connection })
+    }
+
+    /// Send the event to the midi output port.
+    pub fn send(&mut self, event: &RawMidiEvent) -> Result<(), SendError> {
+        self.connection.send(event.bytes())
+    }
+}
+
+impl EventHandler<RawMidiEvent> for MidirOutput {
+    fn handle_event(&mut self, event: RawMidiEvent) {
+        if let Err(e) = self.send(&event) {
+            error!("Failed to send midi event {:?}: {:?}", event, e);
+        }
+    }
+}
+
+impl EventHandler<Timed<RawMidiEvent>> for MidirOutput {
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+        self.handle_event(event.event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_keep_their_relative_timing() {
+        let mut clock = TimestampClock::new();
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        // Handled 5 ms after being received.
+        assert_eq!(clock.received_at(1_000, start + ms(6)), start + ms(6));
+        // Handled without delay: this gives a better estimate of the origin.
+        assert_eq!(clock.received_at(3_000, start + ms(4)), start + ms(4));
+        // Two events that are handled in a burst.
+        assert_eq!(clock.received_at(5_000, start + ms(9)), start + ms(6));
+        assert_eq!(clock.received_at(6_000, start + ms(9)), start + ms(7));
+    }
+}
@@ -3,11 +3,24 @@
 //! Pre-defined backends
 //! ====================
 //! `rsynth` currently supports the following back-ends:
+//! * [`au`] Audio Units for macOS (behind the `backend-au` feature)
 //! * [`combined`] combine different back-ends for audio input, audio output, midi input and
 //!     midi output, mostly for offline rendering and testing (behind various features)
+//! * [`cpal`] standalone audio on ALSA, CoreAudio and WASAPI (behind the `backend-cpal`
+//!     feature) and ASIO (behind the `backend-asio` feature)
+//! * [`i2s`] I2S DACs on embedded targets, without the `std` feature (behind the
+//!     `backend-i2s` feature)
 //! * [`jack`] (behind the `backend-jack` feature)
+//! * [`ladspa`] audio-only effects (behind the `backend-ladspa` feature)
+//! * [`lv2`] (behind the `backend-lv2` feature)
 //! * [`vst`] (behind the backend-vst)
 //!
+//! Additionally, [`midir`] provides hardware midi input and output for standalone
+//! applications (behind the `midi-io` feature) and [`rtp_midi`] provides network midi
+//! input and output (behind the `rtp-midi` feature).
+//! [`standalone`] combines [`cpal`] and [`midir`] to run a plugin as a standalone
+//! application (behind the `standalone` feature).
+//!
 //! These backends are currently in the `rsynth` crate, but we may eventually move them to
 //! separate crates.
 //!
@@ -23,13 +36,39 @@
 //! When you publish a backend crate, let us know by opening an issue or pull request
 //! so that we can link to it in the documentation of rsynth.
 //!
+//! [`au`]: ./au_backend/index.html
+//! [`i2s`]: ./i2s/index.html
 //! [`jack`]: ./jack_backend/index.html
+//! [`ladspa`]: ./ladspa_backend/index.html
+//! [`lv2`]: ./lv2_backend/index.html
 //! [`vst`]: ./bvst_backend/index.html
 //! [`combined`]: ./combined/index.html
+//! [`cpal`]: ./cpal/index.html
+//! [`midir`]: ./midir/index.html
+//! [`rtp_midi`]: ./rtp_midi/index.html
+//! [`standalone`]: ./standalone/index.html
+#[cfg(feature = "backend-au")]
+pub mod au_backend;
 #[cfg(feature = "backend-combined")]
 pub mod combined;
+#[cfg(feature = "backend-cpal")]
+pub mod cpal;
+#[cfg(feature = "backend-i2s")]
+pub mod i2s;
 #[cfg(feature = "backend-jack")]
 pub mod jack_backend;
+#[cfg(feature = "backend-ladspa")]
+pub mod ladspa_backend;
+#[cfg(feature = "backend-lv2")]
+pub mod lv2_backend;
+#[cfg(any(feature = "midi-io", feature = "rtp-midi"))]
+pub mod midi_input_queue;
+#[cfg(feature = "midi-io")]
+pub mod midir;
+#[cfg(feature = "rtp-midi")]
+pub mod rtp_midi;
+#[cfg(feature = "standalone")]
+pub mod standalone;
 #[cfg(feature = "backend-vst")]
 pub mod vst_backend;
 
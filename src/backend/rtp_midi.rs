@@ -0,0 +1,734 @@
+//! Network midi input and output, using RTP-MIDI (also known as "AppleMIDI").
+//!
+//! Support is only enabled if you compile with the "rtp-midi" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! This allows other machines (e.g. an iPad or a Mac with "Audio MIDI Setup") to drive a
+//! standalone application over the network.
+//!
+//! Sessions
+//! ========
+//! An [`RtpMidiSession`] listens on two consecutive UDP ports: the control port and the data
+//! port (which is the control port plus one).
+//! It accepts every invitation and answers clock synchronisation requests,
+//! but it does not invite other participants itself.
+//! Note that the session is not announced with Bonjour (mDNS), so you need to add the
+//! address and port of the session manually on the other machine.
+//!
+//! Midi input
+//! ==========
+//! The midi events that are received are sent over a lock-free queue to the audio thread.
+//! In the audio thread, call [`MidiInputReceiver::handle_events`] at the start of each
+//! audio buffer to pass the events to the plugin as `Timed<RawMidiEvent>`.
+//! See the [`midi_input_queue`] module for more information about the timing of the events.
+//!
+//! System exclusive messages are currently ignored, and so is the recovery journal.
+//!
+//! Midi output
+//! ===========
+//! An [`RtpMidiOutput`] sends midi events to all participants of the session.
+//!
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`RtpMidiSession`]: ./struct.RtpMidiSession.html
+//! [`RtpMidiOutput`]: ./struct.RtpMidiOutput.html
+//! [`MidiInputReceiver::handle_events`]: ../midi_input_queue/struct.MidiInputReceiver.html#method.handle_events
+//! [`midi_input_queue`]: ../midi_input_queue/index.html
+use super::midi_input_queue::{midi_input_queue, MidiInputReceiver, MidiInputSender};
+use crate::event::{EventHandler, RawMidiEvent, Timed};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const PROTOCOL_VERSION: u32 = 2;
+const RTP_VERSION: u8 = 0x80;
+const RTP_MIDI_PAYLOAD_TYPE: u8 = 0x61;
+/// The session clock runs at 10 kHz, so timestamps and delta times are in units of 100 µs.
+const MICROSECONDS_PER_TICK: u64 = 100;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const MAX_PACKET_SIZE: usize = 1500;
+
+/// The packets of the AppleMIDI session protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SessionPacket {
+    Invitation {
+        token: u32,
+        ssrc: u32,
+        name: String,
+    },
+    Accept {
+        token: u32,
+        ssrc: u32,
+        name: String,
+    },
+    Reject {
+        token: u32,
+        ssrc: u32,
+    },
+    End {
+        token: u32,
+        ssrc: u32,
+    },
+    ClockSync {
+        ssrc: u32,
+        count: u8,
+        timestamps: [u64; 3],
+    },
+    ReceiverFeedback {
+        ssrc: u32,
+        sequence_number: u16,
+    },
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(((read_u32(data, offset)? as u64) << 32) | read_u32(data, offset + 4)? as u64)
+}
+
+impl SessionPacket {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.get(0..2)? != [0xFF, 0xFF] {
+            return None;
+        }
+        let command = [*data.get(2)?, *data.get(3)?];
+        match &command {
+            b"IN" | b"OK" | b"NO" | b"BY" => {
+                if read_u32(data, 4)? != PROTOCOL_VERSION {
+                    warn!("Ignoring session packet with unsupported protocol version");
+                    return None;
+                }
+                let token = read_u32(data, 8)?;
+                let ssrc = read_u32(data, 12)?;
+                let name = data
+                    .get(16..)
+                    .and_then(|name| name.split(|byte| *byte == 0).next())
+                    .map(|name| String::from_utf8_lossy(name).into_owned())
+                    .unwrap_or_default();
+                Some(match &command {
+                    b"IN" => SessionPacket::Invitation { token, ssrc, name },
+                    b"OK" => SessionPacket::Accept { token, ssrc, name },
+                    b"NO" => SessionPacket::Reject { token, ssrc },
+                    _ => SessionPacket::End { token, ssrc },
+                })
+            }
+            b"CK" => Some(SessionPacket::ClockSync {
+                ssrc: read_u32(data, 4)?,
+                count: *data.get(8)?,
+                timestamps: [
+                    read_u64(data, 12)?,
+                    read_u64(data, 20)?,
+                    read_u64(data, 28)?,
+                ],
+            }),
+            b"RS" => Some(SessionPacket::ReceiverFeedback {
+                ssrc: read_u32(data, 4)?,
+                sequence_number: (read_u32(data, 8)? >> 16) as u16,
+            }),
+            _ => None,
+        }
+    }
+
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.clear();
+        buffer.extend_from_slice(&[0xFF, 0xFF]);
+        match self {
+            SessionPacket::Invitation { token, ssrc, name }
+            | SessionPacket::Accept { token, ssrc, name } => {
+                buffer.extend_from_slice(match self {
+                    SessionPacket::Invitation { .. } => b"IN",
+                    _ => b"OK",
+                });
+                buffer.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+                buffer.extend_from_slice(&token.to_be_bytes());
+                buffer.extend_from_slice(&ssrc.to_be_bytes());
+                buffer.extend_from_slice(name.as_bytes());
+                buffer.push(0);
+            }
+            SessionPacket::Reject { token, ssrc } | SessionPacket::End { token, ssrc } => {
+                buffer.extend_from_slice(match self {
+                    SessionPacket::Reject { .. } => b"NO",
+                    _ => b"BY",
+                });
+                buffer.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+                buffer.extend_from_slice(&token.to_be_bytes());
+                buffer.extend_from_slice(&ssrc.to_be_bytes());
+            }
+            SessionPacket::ClockSync {
+                ssrc,
+                count,
+                timestamps,
+            } => {
+                buffer.extend_from_slice(b"CK");
+                buffer.extend_from_slice(&ssrc.to_be_bytes());
+                buffer.extend_from_slice(&[*count, 0, 0, 0]);
+                for timestamp in timestamps.iter() {
+                    buffer.extend_from_slice(&timestamp.to_be_bytes());
+                }
+            }
+            SessionPacket::ReceiverFeedback {
+                ssrc,
+                sequence_number,
+            } => {
+                buffer.extend_from_slice(b"RS");
+                buffer.extend_from_slice(&ssrc.to_be_bytes());
+                buffer.extend_from_slice(&((*sequence_number as u32) << 16).to_be_bytes());
+            }
+        }
+    }
+}
+
+/// Read a delta time from the start of `data`.
+/// Return the delta time (in ticks) and the number of bytes that have been read.
+fn read_delta_time(data: &[u8]) -> Option<(u32, usize)> {
+    let mut value = 0;
+    for (index, byte) in data.iter().take(4).enumerate() {
+        value = (value << 7) | (byte & 0x7F) as u32;
+        if byte & 0x80 == 0 {
+            return Some((value, index + 1));
+        }
+    }
+    None
+}
+
+/// Parse the midi command section of an RTP-MIDI packet.
+///
+/// `running_status` is kept between packets, because the first command of a packet may
+/// use the running status of the previous packet.
+/// `handler` is called with the delta time (in ticks) relative to the start of the packet
+/// and the event.
+fn parse_midi_command_section<F>(
+    payload: &[u8],
+    running_status: &mut Option<u8>,
+    mut handler: F,
+) -> Option<()>
+where
+    F: FnMut(u32, RawMidiEvent),
+{
+    let flags = *payload.first()?;
+    let (length, start) = if flags & 0x80 != 0 {
+        (
+            (((flags & 0x0F) as usize) << 8) | *payload.get(1)? as usize,
+            2,
+        )
+    } else {
+        ((flags & 0x0F) as usize, 1)
+    };
+    let first_has_delta_time = flags & 0x20 != 0;
+    let list = payload.get(start..start + length)?;
+
+    let mut position = 0;
+    let mut time = 0_u32;
+    let mut first = true;
+    while position < list.len() {
+        if !first || first_has_delta_time {
+            let (delta_time, size) = read_delta_time(&list[position..])?;
+            time = time.wrapping_add(delta_time);
+            position += size;
+        }
+        first = false;
+
+        let status = if *list.get(position)? & 0x80 != 0 {
+            position += 1;
+            list[position - 1]
+        } else {
+            (*running_status)?
+        };
+        let number_of_data_bytes = match status {
+            0x80..=0xEF => {
+                *running_status = Some(status);
+                if status & 0xE0 == 0xC0 {
+                    1
+                } else {
+                    2
+                }
+            }
+            0xF0 => {
+                // TODO: SysEx event
+                *running_status = None;
+                warn!("Ignoring system exclusive message");
+                let end = list[position..]
+                    .iter()
+                    .position(|byte| *byte == 0xF7 || *byte == 0xF0 || *byte == 0xF4)?;
+                position += end + 1;
+                continue;
+            }
+            0xF1 | 0xF3 => {
+                *running_status = None;
+                1
+            }
+            0xF2 => {
+                *running_status = None;
+                2
+            }
+            0xF6 => {
+                *running_status = None;
+                0
+            }
+            0xF8..=0xFF => 0,
+            _ => return None,
+        };
+        let data = list.get(position..position + number_of_data_bytes)?;
+        position += number_of_data_bytes;
+        let mut bytes = [status, 0, 0];
+        bytes[1..=number_of_data_bytes].copy_from_slice(data);
+        handler(
+            time,
+            RawMidiEvent::try_new(&bytes[..=number_of_data_bytes])?,
+        );
+    }
+    Some(())
+}
+
+/// Parse an RTP-MIDI packet.
+fn parse_rtp_midi_packet<F>(
+    packet: &[u8],
+    running_status: &mut Option<u8>,
+    handler: F,
+) -> Option<()>
+where
+    F: FnMut(u32, RawMidiEvent),
+{
+    if packet.len() < 12 || packet[0] & 0xC0 != RTP_VERSION {
+        return None;
+    }
+    let number_of_contributing_sources = (packet[0] & 0x0F) as usize;
+    let payload = packet.get(12 + 4 * number_of_contributing_sources..)?;
+    parse_midi_command_section(payload, running_status, handler)
+}
+
+/// Encode a single midi event as an RTP-MIDI packet (without recovery journal).
+fn encode_rtp_midi_packet(
+    sequence_number: u16,
+    timestamp: u32,
+    ssrc: u32,
+    event: &RawMidiEvent,
+    buffer: &mut Vec<u8>,
+) {
+    buffer.clear();
+    buffer.push(RTP_VERSION);
+    buffer.push(RTP_MIDI_PAYLOAD_TYPE);
+    buffer.extend_from_slice(&sequence_number.to_be_bytes());
+    buffer.extend_from_slice(&timestamp.to_be_bytes());
+    buffer.extend_from_slice(&ssrc.to_be_bytes());
+    let bytes = event.bytes();
+    buffer.push(bytes.len() as u8);
+    buffer.extend_from_slice(bytes);
+}
+
+struct Participant {
+    ssrc: u32,
+    name: String,
+    control_address: SocketAddr,
+    data_address: Option<SocketAddr>,
+}
+
+struct Session {
+    name: String,
+    ssrc: u32,
+    start: Instant,
+    participants: Mutex<Vec<Participant>>,
+    control_socket: UdpSocket,
+    data_socket: UdpSocket,
+}
+
+impl Session {
+    fn now_in_ticks(&self) -> u64 {
+        (self.start.elapsed().as_micros() / MICROSECONDS_PER_TICK as u128) as u64
+    }
+
+    fn handle_session_packet(
+        &self,
+        packet: SessionPacket,
+        from: SocketAddr,
+        socket: &UdpSocket,
+        is_data_port: bool,
+        buffer: &mut Vec<u8>,
+    ) {
+        let reply = match packet {
+            SessionPacket::Invitation { token, ssrc, name } => {
+                let mut participants = self.participants.lock().expect("Lock is poisoned.");
+                if is_data_port {
+                    if let Some(participant) = participants.iter_mut().find(|p| p.ssrc == ssrc) {
+                        participant.data_address = Some(from);
+                        info!("Participant {} has joined the rtp-midi session", name);
+                    } else {
+                        warn!("Ignoring invitation on the data port from {}", from);
+                        return;
+                    }
+                } else {
+                    participants.retain(|p| p.ssrc != ssrc);
+                    participants.push(Participant {
+                        ssrc,
+                        name,
+                        control_address: from,
+                        data_address: None,
+                    });
+                }
+                Some(SessionPacket::Accept {
+                    token,
+                    ssrc: self.ssrc,
+                    name: self.name.clone(),
+                })
+            }
+            SessionPacket::End { ssrc, .. } => {
+                let mut participants = self.participants.lock().expect("Lock is poisoned.");
+                for participant in participants.iter().filter(|p| p.ssrc == ssrc) {
+                    info!(
+                        "Participant {} has left the rtp-midi session",
+                        participant.name
+                    );
+                }
+                participants.retain(|p| p.ssrc != ssrc);
+                None
+            }
+            SessionPacket::ClockSync {
+                count: 0,
+                timestamps,
+                ..
+            } => Some(SessionPacket::ClockSync {
+                ssrc: self.ssrc,
+                count: 1,
+                timestamps: [timestamps[0], self.now_in_ticks(), 0],
+            }),
+            packet => {
+                trace!("Ignoring session packet {:?}", packet);
+                None
+            }
+        };
+        if let Some(reply) = reply {
+            reply.encode(buffer);
+            if let Err(e) = socket.send_to(buffer, from) {
+                error!("Failed to send session packet to {}: {:?}", from, e);
+            }
+        }
+    }
+
+    fn serve(&self, socket: &UdpSocket, mut sender: Option<MidiInputSender>, stop: &AtomicBool) {
+        let mut receive_buffer = [0; MAX_PACKET_SIZE];
+        let mut send_buffer = Vec::with_capacity(MAX_PACKET_SIZE);
+        let mut running_status = None;
+        while !stop.load(Ordering::Relaxed) {
+            let (size, from) = match socket.recv_from(&mut receive_buffer) {
+                Ok(result) => result,
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    continue;
+                }
+                Err(e) => {
+                    error!("Failed to receive rtp-midi packet: {:?}", e);
+                    continue;
+                }
+            };
+            let received_at = Instant::now();
+            let packet = &receive_buffer[..size];
+            if let Some(session_packet) = SessionPacket::parse(packet) {
+                self.handle_session_packet(
+                    session_packet,
+                    from,
+                    socket,
+                    sender.is_some(),
+                    &mut send_buffer,
+                );
+            } else if let Some(sender) = sender.as_mut() {
+                let result = parse_rtp_midi_packet(packet, &mut running_status, |ticks, event| {
+                    let delay = Duration::from_micros(ticks as u64 * MICROSECONDS_PER_TICK);
+                    sender.send(received_at + delay, event);
+                });
+                if result.is_none() {
+                    warn!("Ignoring malformed rtp-midi packet from {}", from);
+                }
+            }
+        }
+    }
+}
+
+/// An RTP-MIDI session that accepts invitations from other participants.
+///
+/// The session is ended when the `RtpMidiSession` is dropped.
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct RtpMidiSession {
+    session: Arc<Session>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl RtpMidiSession {
+    /// Start a session with the given name, listening on the given control port.
+    /// The data port is the control port plus one.
+    /// The default port for RTP-MIDI is 5004.
+    ///
+    /// At most `queue_capacity` events can be waiting to be handled by the audio thread,
+    /// events that are received when the queue is full are dropped.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory and spawns threads.
+    pub fn start(
+        name: &str,
+        control_port: u16,
+        queue_capacity: usize,
+    ) -> io::Result<(Self, MidiInputReceiver)> {
+        let control_socket = UdpSocket::bind(("0.0.0.0", control_port))?;
+        let data_socket = UdpSocket::bind(("0.0.0.0", control_port.wrapping_add(1)))?;
+        control_socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        data_socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0);
+        let session = Arc::new(Session {
+            name: name.to_string(),
+            ssrc: seed ^ std::process::id().rotate_left(16),
+            start: Instant::now(),
+            participants: Mutex::new(Vec::new()),
+            control_socket: control_socket.try_clone()?,
+            data_socket: data_socket.try_clone()?,
+        });
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = midi_input_queue(queue_capacity);
+
+        let mut threads = Vec::with_capacity(2);
+        for (socket, sender) in [(control_socket, None), (data_socket, Some(sender))] {
+            let session = session.clone();
+            let stop = stop.clone();
+            threads.push(std::thread::spawn(move || {
+                session.serve(&socket, sender, &stop)
+            }));
+        }
+        info!("Started rtp-midi session {} on port {}", name, control_port);
+        Ok((
+            Self {
+                session,
+                stop,
+                threads,
+            },
+            receiver,
+        ))
+    }
+
+    /// Get the names of the participants that have joined the session.
+    pub fn participant_names(&self) -> Vec<String> {
+        self.session
+            .participants
+            .lock()
+            .expect("Lock is poisoned.")
+            .iter()
+            .map(|participant| participant.name.clone())
+            .collect()
+    }
+
+    /// Create an output that sends midi events to all participants of the session.
+    pub fn output(&self) -> RtpMidiOutput {
+        RtpMidiOutput {
+            session: self.session.clone(),
+            sequence_number: 0,
+            buffer: Vec::with_capacity(MAX_PACKET_SIZE),
+        }
+    }
+}
+
+impl Drop for RtpMidiSession {
+    fn drop(&mut self) {
+        let mut buffer = Vec::new();
+        for participant in self
+            .session
+            .participants
+            .lock()
+            .expect("Lock is poisoned.")
+            .drain(..)
+        {
+            SessionPacket::End {
+                token: 0,
+                ssrc: self.session.ssrc,
+            }
+            .encode(&mut buffer);
+            if let Err(e) = self
+                .session
+                .control_socket
+                .send_to(&buffer, participant.control_address)
+            {
+                warn!(
+                    "Failed to end the session with {}: {:?}",
+                    participant.name, e
+                );
+            }
+        }
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            if thread.join().is_err() {
+                error!("Rtp-midi thread has panicked.");
+            }
+        }
+    }
+}
+
+/// Sends midi events to all participants of an [`RtpMidiSession`].
+///
+/// Note about using in a real-time context
+/// =======================================
+/// Events are sent immediately, which involves a system call and locking a mutex.
+/// The `time_in_frames` of `Timed` events is ignored.
+///
+/// [`RtpMidiSession`]: ./struct.RtpMidiSession.html
+pub struct RtpMidiOutput {
+    session: Arc<Session>,
+    sequence_number: u16,
+    buffer: Vec<u8>,
+}
+
+impl RtpMidiOutput {
+    /// Send the event to all participants of the session.
+    pub fn send(&mut self, event: &RawMidiEvent) -> io::Result<()> {
+        encode_rtp_midi_packet(
+            self.sequence_number,
+            self.session.now_in_ticks() as u32,
+            self.session.ssrc,
+            event,
+            &mut self.buffer,
+        );
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        let participants = self.session.participants.lock().expect("Lock is poisoned.");
+        for address in participants.iter().filter_map(|p| p.data_address) {
+            self.session.data_socket.send_to(&self.buffer, address)?;
+        }
+        Ok(())
+    }
+}
+
+impl EventHandler<RawMidiEvent> for RtpMidiOutput {
+    fn handle_event(&mut self, event: RawMidiEvent) {
+        if let Err(e) = self.send(&event) {
+            error!("Failed to send midi event {:?}: {:?}", event, e);
+        }
+    }
+}
+
+impl EventHandler<Timed<RawMidiEvent>> for RtpMidiOutput {
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+        self.handle_event(event.event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod session_packet {
+        use super::super::SessionPacket;
+
+        #[test]
+        fn invitation_round_trips() {
+            let packet = SessionPacket::Invitation {
+                token: 0x01020304,
+                ssrc: 0x0A0B0C0D,
+                name: "iPad".to_string(),
+            };
+            let mut buffer = Vec::new();
+            packet.encode(&mut buffer);
+            assert_eq!(&buffer[0..4], &[0xFF, 0xFF, b'I', b'N']);
+            assert_eq!(SessionPacket::parse(&buffer), Some(packet));
+        }
+
+        #[test]
+        fn clock_sync_round_trips() {
+            let packet = SessionPacket::ClockSync {
+                ssrc: 5,
+                count: 1,
+                timestamps: [1, 2, 3],
+            };
+            let mut buffer = Vec::new();
+            packet.encode(&mut buffer);
+            assert_eq!(buffer.len(), 36);
+            assert_eq!(SessionPacket::parse(&buffer), Some(packet));
+        }
+
+        #[test]
+        fn truncated_packet_is_not_parsed() {
+            assert_eq!(
+                SessionPacket::parse(&[0xFF, 0xFF, b'I', b'N', 0, 0, 0, 2]),
+                None
+            );
+        }
+    }
+
+    mod midi_command_section {
+        use super::super::{encode_rtp_midi_packet, parse_rtp_midi_packet};
+        use crate::event::RawMidiEvent;
+
+        fn parse(payload: &[u8], running_status: &mut Option<u8>) -> Vec<(u32, RawMidiEvent)> {
+            let mut packet = vec![0x80, 0x61, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+            packet.extend_from_slice(payload);
+            let mut result = Vec::new();
+            parse_rtp_midi_packet(&packet, running_status, |time, event| {
+                result.push((time, event))
+            })
+            .expect("Packet should be valid.");
+            result
+        }
+
+        #[test]
+        fn parses_commands_with_delta_times_and_running_status() {
+            let payload = [
+                0x0A, // Short header, no journal, no delta time for the first command.
+                0x90, 0x40, 0x7F, // Note on.
+                0x05, // Delta time.
+                0x41, 0x7F, // Note on with running status.
+                0x81, 0x00, // Delta time of two bytes (128).
+                0xC0, 0x03, // Program change.
+            ];
+            let mut running_status = None;
+            assert_eq!(
+                parse(&payload, &mut running_status),
+                vec![
+                    (0, RawMidiEvent::new(&[0x90, 0x40, 0x7F])),
+                    (5, RawMidiEvent::new(&[0x90, 0x41, 0x7F])),
+                    (133, RawMidiEvent::new(&[0xC0, 0x03])),
+                ]
+            );
+            assert_eq!(running_status, Some(0xC0));
+        }
+
+        #[test]
+        fn uses_running_status_of_previous_packet() {
+            let mut running_status = Some(0x80);
+            assert_eq!(
+                parse(&[0x12, 0x40, 0x00], &mut running_status),
+                vec![(0, RawMidiEvent::new(&[0x80, 0x40, 0x00]))]
+            );
+        }
+
+        #[test]
+        fn system_real_time_does_not_cancel_running_status() {
+            let payload = [0x08, 0x90, 0x40, 0x7F, 0x00, 0xF8, 0x00, 0x41, 0x7F];
+            let mut running_status = None;
+            assert_eq!(
+                parse(&payload, &mut running_status),
+                vec![
+                    (0, RawMidiEvent::new(&[0x90, 0x40, 0x7F])),
+                    (0, RawMidiEvent::new(&[0xF8])),
+                    (0, RawMidiEvent::new(&[0x90, 0x41, 0x7F])),
+                ]
+            );
+            assert_eq!(running_status, Some(0x90));
+        }
+
+        #[test]
+        fn encoded_packet_can_be_parsed() {
+            let event = RawMidiEvent::new(&[0xB0, 0x07, 0x64]);
+            let mut buffer = Vec::new();
+            encode_rtp_midi_packet(1, 2, 3, &event, &mut buffer);
+            let mut result = Vec::new();
+            parse_rtp_midi_packet(&buffer, &mut None, |time, event| result.push((time, event)))
+                .expect("Packet should be valid.");
+            assert_eq!(result, vec![(0, event)]);
+        }
+    }
+}
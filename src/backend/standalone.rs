@@ -0,0 +1,495 @@
+//! Run a plugin as a standalone application, with audio from the [`cpal`] backend and
+//! midi input from the [`midir`] backend.
+//!
+//! Support is only enabled if you compile with the "standalone" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! The devices, the midi input port, the sample rate and the buffer size are set in a
+//! [`StandaloneConfig`], which can also be parsed from the command line arguments.
+//! The plugin runs until a [`ShutdownSignal`] is requested:
+//!
+//! ```no_run
+//! # use rsynth::backend::cpal::CpalHost;
+//! # use rsynth::event::{ContextualEventHandler, RawMidiEvent, Timed};
+//! # use rsynth::meta::{InOut, Meta, MetaData};
+//! # use rsynth::{AudioHandler, ContextualAudioRenderer};
+//! # struct MySynth { meta: MetaData<&'static str, &'static str, &'static str> }
+//! # impl Meta for MySynth {
+//! #     type MetaData = MetaData<&'static str, &'static str, &'static str>;
+//! #     fn meta(&self) -> &Self::MetaData { &self.meta }
+//! # }
+//! # impl AudioHandler for MySynth {
+//! #     fn set_sample_rate(&mut self, sample_rate: f64) {}
+//! # }
+//! # impl ContextualAudioRenderer<f32, CpalHost> for MySynth {
+//! #     fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut [&mut [f32]], context: &mut CpalHost) {}
+//! # }
+//! # impl ContextualEventHandler<Timed<RawMidiEvent>, CpalHost> for MySynth {
+//! #     fn handle_event(&mut self, event: Timed<RawMidiEvent>, context: &mut CpalHost) {}
+//! # }
+//! # fn create_synth() -> MySynth { MySynth { meta: MetaData {
+//! #     general_meta: "My synth",
+//! #     audio_port_meta: InOut { inputs: vec![], outputs: vec!["left", "right"] },
+//! #     midi_port_meta: InOut { inputs: vec!["midi in"], outputs: vec![] },
+//! # } } }
+//! use rsynth::backend::standalone::{run, ShutdownSignal, StandaloneConfig};
+//! use std::process;
+//!
+//! let config = match StandaloneConfig::from_arguments("my-synth", std::env::args().skip(1)) {
+//!     Ok(config) => config,
+//!     Err(e) => {
+//!         eprintln!("{:?}\n\n{}", e, StandaloneConfig::USAGE);
+//!         process::exit(1);
+//!     }
+//! };
+//! let shutdown = ShutdownSignal::on_enter();
+//! run(create_synth(), &config, &shutdown).expect("the devices can be opened");
+//! ```
+//!
+//! Midi input
+//! ==========
+//! The plugin is wrapped in a [`LiveMidiInput`], so it receives the events of the midi input
+//! port at the start of each audio buffer. When no midi input port is configured, the plugin
+//! does not receive any events.
+//!
+//! Input monitoring
+//! ================
+//! With the `--monitor-input <GAIN>` option, the audio input is added to the outputs of the
+//! plugin, see the [`cpal`] module. Use [`Standalone::monitor`] to switch monitoring on and off
+//! and to display the latency, e.g. in the user interface of the application.
+//!
+//! [`cpal`]: ../cpal/index.html
+//! [`Standalone::monitor`]: ./struct.Standalone.html#method.monitor
+//! [`midir`]: ../midir/index.html
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`StandaloneConfig`]: ./struct.StandaloneConfig.html
+//! [`ShutdownSignal`]: ./struct.ShutdownSignal.html
+//! [`LiveMidiInput`]: ../midi_input_queue/struct.LiveMidiInput.html
+use super::cpal::{
+    self as cpal_backend, available_hosts, CpalError, CpalHost, CpalSettings, CpalStreams, HostId,
+};
+use super::midi_input_queue::{midi_input_queue, LiveMidiInput};
+use super::midir::{MidirError, MidirInput};
+use crate::event::{ContextualEventHandler, RawMidiEvent, Timed};
+use crate::meta::{AudioPort, Meta, Port};
+use crate::utilities::input_monitor::MonitorControl;
+use crate::{AudioHandler, ContextualAudioRenderer};
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// The default number of midi events that can be waiting to be handled by the audio thread.
+pub const DEFAULT_MIDI_QUEUE_CAPACITY: usize = 1024;
+
+/// The error type that represents the errors you can get when starting a standalone
+/// application.
+#[derive(Debug)]
+pub enum StandaloneError {
+    /// The audio devices could not be opened.
+    AudioError(CpalError),
+    /// The midi input port could not be opened.
+    MidiError(MidirError),
+    /// A command line argument could not be parsed.
+    InvalidArgument(String),
+}
+
+/// The configuration of a standalone application.
+#[derive(Clone, Debug)]
+pub struct StandaloneConfig {
+    /// The name of the application, used as the client name of the midi connection.
+    pub client_name: String,
+    /// The host (audio API), `None` for the default host of the platform.
+    pub host: Option<HostId>,
+    /// The name of the audio output device, `None` for the default device.
+    pub output_device: Option<String>,
+    /// The name of the audio input device, `None` for the default device.
+    /// Only used when the plugin has audio inputs.
+    pub input_device: Option<String>,
+    /// (Part of) the name of the midi input port, `None` for no midi input.
+    pub midi_input_port: Option<String>,
+    /// The preferred sample rate, `None` for the default sample rate of the output device.
+    pub sample_rate: Option<u32>,
+    /// The buffer size in frames, `None` for the default buffer size of the output device.
+    pub buffer_size: Option<u32>,
+    /// For each output of the plugin, the channel of the output device, see
+    /// [`CpalSettings::output_channel_map`].
+    ///
+    /// [`CpalSettings::output_channel_map`]: ../cpal/struct.CpalSettings.html#structfield.output_channel_map
+    pub output_channel_map: Option<Vec<usize>>,
+    /// The linear gain for monitoring the audio input, `None` for no monitoring, see
+    /// [`CpalSettings::input_monitoring`].
+    ///
+    /// [`CpalSettings::input_monitoring`]: ../cpal/struct.CpalSettings.html#structfield.input_monitoring
+    pub input_monitoring: Option<f64>,
+    /// The number of midi events that can be waiting to be handled by the audio thread.
+    pub midi_queue_capacity: usize,
+}
+
+impl Default for StandaloneConfig {
+    fn default() -> Self {
+        StandaloneConfig {
+            client_name: "rsynth".to_string(),
+            host: None,
+            output_device: None,
+            input_device: None,
+            midi_input_port: None,
+            sample_rate: None,
+            buffer_size: None,
+            output_channel_map: None,
+            input_monitoring: None,
+            midi_queue_capacity: DEFAULT_MIDI_QUEUE_CAPACITY,
+        }
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(
+    option: &str,
+    value: Option<String>,
+) -> Result<T, StandaloneError> {
+    let value = value.ok_or_else(|| {
+        StandaloneError::InvalidArgument(format!("Missing value for `{}`.", option))
+    })?;
+    value.parse().map_err(|_| {
+        StandaloneError::InvalidArgument(format!("Invalid value for `{}`: `{}`.", option, value))
+    })
+}
+
+fn parse_host(value: Option<String>) -> Result<HostId, StandaloneError> {
+    let value = parse_value::<String>("--audio-api", value)?;
+    available_hosts()
+        .into_iter()
+        .find(|host_id| host_id.name().eq_ignore_ascii_case(&value))
+        .ok_or_else(|| {
+            let names: Vec<_> = available_hosts()
+                .iter()
+                .map(|host_id| host_id.name())
+                .collect();
+            StandaloneError::InvalidArgument(format!(
+                "Unknown audio API: `{}`, expected one of: {}.",
+                value,
+                names.join(", ")
+            ))
+        })
+}
+
+fn parse_channel_map(value: Option<String>) -> Result<Vec<usize>, StandaloneError> {
+    let value = parse_value::<String>("--output-channels", value)?;
+    value
+        .split(',')
+        .map(|channel| {
+            channel.trim().parse().map_err(|_| {
+                StandaloneError::InvalidArgument(format!(
+                    "Invalid value for `--output-channels`: `{}`, expected e.g. `0,1`.",
+                    value
+                ))
+            })
+        })
+        .collect()
+}
+
+impl StandaloneConfig {
+    /// The description of the command line options that are understood by
+    /// [`from_arguments`](#method.from_arguments), to be included in the help of the application.
+    pub const USAGE: &'static str = "\
+Options:
+  --audio-api <NAME>        The audio API, e.g. `ASIO` [default: the default of the platform].
+  --output-device <NAME>    The audio output device [default: the default device].
+  --input-device <NAME>     The audio input device [default: the default device].
+  --midi-input <NAME>       (Part of) the name of the midi input port [default: no midi input].
+  --sample-rate <HZ>        The preferred sample rate [default: the default of the device].
+  --buffer-size <FRAMES>    The buffer size [default: the default of the device].
+  --output-channels <LIST>  The device channel for each output, e.g. `2,3` [default: 0,1,...].
+  --monitor-input <GAIN>    Add the audio input to the outputs with this gain [default: off].
+  --midi-queue <NUMBER>     The maximum number of pending midi events [default: 1024].";
+
+    /// Parse the configuration from the command line arguments (without the name of the
+    /// executable), see [`USAGE`](#associatedconstant.USAGE) for the options.
+    /// Options that are not given keep their default value.
+    pub fn from_arguments<I>(client_name: &str, mut arguments: I) -> Result<Self, StandaloneError>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut config = StandaloneConfig {
+            client_name: client_name.to_string(),
+            ..StandaloneConfig::default()
+        };
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "--audio-api" => config.host = Some(parse_host(arguments.next())?),
+                "--output-device" => {
+                    config.output_device = Some(parse_value(&argument, arguments.next())?)
+                }
+                "--input-device" => {
+                    config.input_device = Some(parse_value(&argument, arguments.next())?)
+                }
+                "--midi-input" => {
+                    config.midi_input_port = Some(parse_value(&argument, arguments.next())?)
+                }
+                "--sample-rate" => {
+                    config.sample_rate = Some(parse_value(&argument, arguments.next())?)
+                }
+                "--buffer-size" => {
+                    config.buffer_size = Some(parse_value(&argument, arguments.next())?)
+                }
+                "--output-channels" => {
+                    config.output_channel_map = Some(parse_channel_map(arguments.next())?)
+                }
+                "--monitor-input" => {
+                    config.input_monitoring = Some(parse_value(&argument, arguments.next())?)
+                }
+                "--midi-queue" => {
+                    config.midi_queue_capacity = parse_value(&argument, arguments.next())?
+                }
+                _ => {
+                    return Err(StandaloneError::InvalidArgument(format!(
+                        "Unknown argument: `{}`.",
+                        argument
+                    )))
+                }
+            }
+        }
+        if config.sample_rate == Some(0) {
+            return Err(StandaloneError::InvalidArgument(
+                "The sample rate cannot be 0.".to_string(),
+            ));
+        }
+        if config.buffer_size == Some(0) {
+            return Err(StandaloneError::InvalidArgument(
+                "The buffer size cannot be 0.".to_string(),
+            ));
+        }
+        if matches!(config.input_monitoring, Some(gain) if gain < 0.0) {
+            return Err(StandaloneError::InvalidArgument(
+                "The monitoring gain cannot be negative.".to_string(),
+            ));
+        }
+        if config.midi_queue_capacity == 0 {
+            return Err(StandaloneError::InvalidArgument(
+                "The midi queue capacity cannot be 0.".to_string(),
+            ));
+        }
+        Ok(config)
+    }
+
+    /// The settings for the [`cpal`](../cpal/index.html) backend.
+    pub fn cpal_settings(&self) -> CpalSettings {
+        CpalSettings {
+            host: self.host,
+            output_device: self.output_device.clone(),
+            input_device: self.input_device.clone(),
+            sample_rate: self.sample_rate,
+            buffer_size: self.buffer_size,
+            output_channel_map: self.output_channel_map.clone(),
+            input_monitoring: self.input_monitoring,
+        }
+    }
+}
+
+/// A signal to stop a standalone application, which can be cloned and requested from any
+/// thread.
+#[derive(Clone, Default)]
+pub struct ShutdownSignal {
+    state: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl ShutdownSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a signal that is requested when the user presses enter on the computer keyboard
+    /// (or when the standard input is closed).
+    pub fn on_enter() -> Self {
+        let signal = Self::new();
+        let requester = signal.clone();
+        println!("Press enter to quit");
+        thread::spawn(move || {
+            let mut user_input = String::new();
+            io::stdin().read_line(&mut user_input).ok();
+            requester.request();
+        });
+        signal
+    }
+
+    /// Request the shutdown.
+    pub fn request(&self) {
+        let (requested, condvar) = &*self.state;
+        *requested.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        condvar.notify_all();
+    }
+
+    /// Whether the shutdown has been requested.
+    pub fn is_requested(&self) -> bool {
+        *self.state.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Block the current thread until the shutdown is requested.
+    pub fn wait(&self) {
+        let (requested, condvar) = &*self.state;
+        let mut guard = requested.lock().unwrap_or_else(|e| e.into_inner());
+        while !*guard {
+            guard = condvar.wait(guard).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+/// The running audio streams and midi connection.
+/// Everything is stopped when this is dropped.
+pub struct Standalone {
+    streams: CpalStreams,
+    _midi_input: Option<MidirInput>,
+}
+
+impl Standalone {
+    /// The sample rate that has been negotiated with the output device.
+    pub fn sample_rate(&self) -> u32 {
+        self.streams.sample_rate()
+    }
+
+    /// Switches input monitoring on and off, changes its gain and reports the latency.
+    pub fn monitor(&self) -> &MonitorControl {
+        self.streams.monitor()
+    }
+}
+
+/// Open the midi input port and the audio devices and start rendering audio with the plugin.
+/// Everything keeps running until the returned `Standalone` is dropped.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// This function allocates memory.
+pub fn start<P>(plugin: P, config: &StandaloneConfig) -> Result<Standalone, StandaloneError>
+where
+    P: Meta
+        + AudioHandler
+        + ContextualAudioRenderer<f32, CpalHost>
+        + ContextualEventHandler<Timed<RawMidiEvent>, CpalHost>
+        + Send
+        + 'static,
+    P::MetaData: Port<AudioPort>,
+{
+    let (midi_input, receiver) = match &config.midi_input_port {
+        Some(port_name) => {
+            let (midi_input, receiver) =
+                MidirInput::open(&config.client_name, port_name, config.midi_queue_capacity)
+                    .map_err(StandaloneError::MidiError)?;
+            (Some(midi_input), receiver)
+        }
+        None => {
+            info!("No midi input port configured.");
+            // The sender is dropped, so nothing is ever received.
+            let (_, receiver) = midi_input_queue(1);
+            (None, receiver)
+        }
+    };
+    let streams = cpal_backend::start(
+        LiveMidiInput::new(plugin, receiver),
+        &config.cpal_settings(),
+    )
+    .map_err(StandaloneError::AudioError)?;
+    Ok(Standalone {
+        streams,
+        _midi_input: midi_input,
+    })
+}
+
+/// Run the plugin until the shutdown is requested.
+///
+/// See the [module level documentation] for an example.
+///
+/// [module level documentation]: ./index.html
+pub fn run<P>(
+    plugin: P,
+    config: &StandaloneConfig,
+    shutdown: &ShutdownSignal,
+) -> Result<(), StandaloneError>
+where
+    P: Meta
+        + AudioHandler
+        + ContextualAudioRenderer<f32, CpalHost>
+        + ContextualEventHandler<Timed<RawMidiEvent>, CpalHost>
+        + Send
+        + 'static,
+    P::MetaData: Port<AudioPort>,
+{
+    let standalone = start(plugin, config)?;
+    shutdown.wait();
+    info!("Stopping audio and midi...");
+    drop(standalone);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments(arguments: &[&str]) -> impl Iterator<Item = String> {
+        arguments
+            .iter()
+            .map(|argument| argument.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn arguments_are_parsed_into_the_config() {
+        let config = StandaloneConfig::from_arguments(
+            "test",
+            arguments(&[
+                "--output-device",
+                "Speakers",
+                "--midi-input",
+                "Keyboard",
+                "--sample-rate",
+                "48000",
+                "--buffer-size",
+                "128",
+                "--output-channels",
+                "2, 3",
+                "--monitor-input",
+                "0.5",
+            ]),
+        )
+        .expect("valid arguments");
+        assert_eq!(config.client_name, "test");
+        assert_eq!(config.output_device.as_deref(), Some("Speakers"));
+        assert_eq!(config.input_device, None);
+        assert_eq!(config.midi_input_port.as_deref(), Some("Keyboard"));
+        assert_eq!(config.sample_rate, Some(48000));
+        assert_eq!(config.buffer_size, Some(128));
+        assert_eq!(config.output_channel_map, Some(vec![2, 3]));
+        assert_eq!(config.input_monitoring, Some(0.5));
+        assert_eq!(config.cpal_settings().input_monitoring, Some(0.5));
+        assert_eq!(config.host, None);
+        assert_eq!(config.midi_queue_capacity, DEFAULT_MIDI_QUEUE_CAPACITY);
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        for invalid in [
+            &["--sample-rate"][..],
+            &["--sample-rate", "fast"],
+            &["--buffer-size", "0"],
+            &["--output-channels", "left,right"],
+            &["--audio-api", "no such api"],
+            &["--monitor-input", "-1"],
+            &["--unknown"],
+        ] {
+            match StandaloneConfig::from_arguments("test", arguments(invalid)) {
+                Err(StandaloneError::InvalidArgument(_)) => {}
+                other => panic!("{:?} should be rejected, got {:?}", invalid, other),
+            }
+        }
+    }
+
+    #[test]
+    fn shutdown_signal_wakes_up_the_waiting_thread() {
+        let signal = ShutdownSignal::new();
+        assert!(!signal.is_requested());
+        let requester = signal.clone();
+        let handle = thread::spawn(move || requester.request());
+        signal.wait();
+        assert!(signal.is_requested());
+        handle.join().expect("requesting does not panic");
+    }
+}
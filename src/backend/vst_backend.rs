@@ -10,28 +10,189 @@
 //! # Usage
 //! See also the documentation of the [`vst_init`] macro.
 //!
+//! # Resume and suspend
+//! The plugin's [`Lifecycle`] methods are called when the host resumes and suspends the
+//! plugin, e.g. when the plugin is switched on and off.
+//!
+//! # Speaker arrangements
+//! The host gets the speaker arrangement of every audio channel from the channel layouts of
+//! the meta-data, see the [`channel_layout`] module, so surround plugins are not assumed to
+//! be stereo. A plugin that supports several channel layouts returns its
+//! [`ChannelLayoutNegotiation`] from [`VstPluginMeta::as_channel_layout_negotiation`].
+//!
+//! Note: the `vst` crate does not (yet) pass the `effSetSpeakerArrangement` and
+//! `effGetSpeakerArrangement` opcodes to the plugin. Until it does, the host cannot propose
+//! other layouts and `VstPluginWrapper::set_speaker_arrangement` is not called.
+//!
+//! # Sample formats
+//! The plugin renders `f32` samples with its `ContextualAudioRenderer<f32, _>` implementation.
+//! A plugin that can also render `f64` samples returns its `ContextualAudioRenderer<f64, _>`
+//! implementation from [`VstPluginMeta::as_f64_renderer`]. In that case, the plugin tells the
+//! host that it supports double precision, so that hosts that process in double precision
+//! (e.g. for mastering) call the `f64` implementation.
+//!
+//! # Parameters
+//! The plugin exposes its parameters to the host with the [`Parameters`] trait, so that the
+//! host can display and automate them. The host gets and sets the normalized values and
+//! displays the plain values with the label (unit) of the parameter. A plugin without
+//! parameters returns an empty [`ParameterStore`].
+//!
+//! # Transport
+//! The `HostCallback` that is passed to the plugin as context implements [`TransportContext`],
+//! so that e.g. arpeggiators and tempo-synced LFOs can follow the tempo, the position in
+//! beats, the time signature and the loop of the host.
+//! Other information, such as the position in samples, can be queried from the host
+//! with `vst::host::Host::get_time_info`.
+//!
+//! # Midi output
+//! A plugin that generates midi (e.g. an arpeggiator or a chorder) passes its
+//! `Timed<RawMidiEvent>`s to a [`VstMidiOutput`] while rendering and calls
+//! [`VstMidiOutput::flush`] at the end of `render_buffer`, which sends the events to the host.
+//! The plugin tells the host that it sends midi events, so that the host can route them.
+//!
+//! # Editor
+//! The plugin can provide a graphical user interface by returning an [`Editor`] from
+//! [`VstPluginMeta::editor`]. The host opens the editor inside a window that it provides.
+//!
+//! # Tail and bypass
+//! A plugin with a tail (e.g. a reverb) returns its [`TailTime`] from
+//! [`VstPluginMeta::as_tail_time`], so that the host keeps rendering it after the input has
+//! become silent.
+//!
+//! Note: the `vst` crate does not (yet) pass the `effSetBypass` opcode to the plugin.
+//! For this reason, the plugin answers "no" when the host asks whether it can bypass, so
+//! that the host bypasses the plugin itself.
+//!
+//! # Latency
+//! A plugin with latency (e.g. a look-ahead limiter) returns its [`Latency`] from
+//! [`VstPluginMeta::as_latency`]; the latency is reported to the host as the initial delay.
+//! When the latency changes, the host is notified with `audioMasterIOChanged` after the
+//! buffer in which it has changed, or when the plugin is resumed.
+//!
+//! # State
+//! The host stores the state of the plugin (the "chunk") in its session and restores it when
+//! the session is loaded. The state is saved and restored with the [`PluginState`]
+//! implementation that the plugin returns from [`VstPluginMeta::state`].
+//! Because the host saves and restores the state from its GUI thread, the plugin shares this
+//! state with the audio thread, e.g. in an `Arc<Mutex<_>>`.
+//! A plugin without such a state only exposes the values of its parameters to the host.
+//!
 //! [`vst_init`]: ../../macro.vst_init.html
+//! [`Parameters`]: ../../parameter/trait.Parameters.html
+//! [`ParameterStore`]: ../../parameter/struct.ParameterStore.html
+//! [`PluginState`]: ../../trait.PluginState.html
+//! [`TransportContext`]: ../../transport/trait.TransportContext.html
+//! [`VstMidiOutput`]: ./struct.VstMidiOutput.html
+//! [`Editor`]: ../../trait.Editor.html
+//! [`Lifecycle`]: ../../trait.Lifecycle.html
+//! [`channel_layout`]: ../../channel_layout/index.html
+//! [`ChannelLayoutNegotiation`]: ../../channel_layout/trait.ChannelLayoutNegotiation.html
+//! [`VstPluginMeta::as_channel_layout_negotiation`]: ./trait.VstPluginMeta.html#method.as_channel_layout_negotiation
+//! [`VstPluginMeta::editor`]: ./trait.VstPluginMeta.html#method.editor
+//! [`VstPluginMeta::state`]: ./trait.VstPluginMeta.html#method.state
+//! [`VstPluginMeta::as_f64_renderer`]: ./trait.VstPluginMeta.html#method.as_f64_renderer
+//! [`TailTime`]: ../../trait.TailTime.html
+//! [`VstPluginMeta::as_tail_time`]: ./trait.VstPluginMeta.html#method.as_tail_time
+//! [`Latency`]: ../../trait.Latency.html
+//! [`VstPluginMeta::as_latency`]: ./trait.VstPluginMeta.html#method.as_latency
+//! [`VstMidiOutput::flush`]: ./struct.VstMidiOutput.html#method.flush
 //! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
 use crate::backend::HostInterface;
-use crate::event::{ContextualEventHandler, RawMidiEvent, SysExEvent, Timed};
+use crate::buffer::initialize_to_zero;
+use crate::channel_layout::{ChannelLayout, ChannelLayoutNegotiation};
+use crate::event::output_event_queue::OutputEventQueue;
+use crate::event::{ContextualEventHandler, EventHandler, RawMidiEvent, SysExEvent, Timed};
+use crate::meta::Category;
+use crate::parameter::{ParameterStore, Parameters};
+use crate::transport::{LoopRange, TimeSignature, TransportContext, TransportState};
+#[cfg(feature = "flush-denormals")]
+use crate::utilities::denormals::DenormalGuard;
+use crate::utilities::rt_log::Level;
 use crate::{
     AudioHandler, AudioHandlerMeta, CommonAudioPortMeta, CommonPluginMeta, ContextualAudioRenderer,
+    Editor, Latency, Lifecycle, PluginIdentityMeta, PluginState, TailTime,
 };
 use core::cmp;
+use core::ffi::c_void;
+use std::sync::{Arc, Mutex, PoisonError};
 use vecstorage::VecStorage;
-use vst::api::Events;
-use vst::buffer::AudioBuffer;
-use vst::channels::ChannelInfo;
+use vst::api::{Events, Supported, TimeInfo, TimeInfoFlags};
+use vst::buffer::{AudioBuffer, SendEventBuffer};
+use vst::channels::{
+    ChannelInfo, CinemaConfig, MusicConfig, SpeakerArrangementType, StereoChannel, StereoConfig,
+    SurroundConfig,
+};
 use vst::event::MidiEvent as VstMidiEvent;
 use vst::event::{Event as VstEvent, SysExEvent as VstSysExEvent};
-use vst::plugin::Category;
-use vst::plugin::{HostCallback, Info};
+use vst::host::{Host, OpCode};
+use vst::plugin::{CanDo, Category as VstCategory};
+use vst::plugin::{HostCallback, Info, PluginParameters};
 
 /// A VST plugin should implement this trait in addition to some other traits.
+///
+/// The name, vendor, unique id, version and category that are reported to the host are defined
+/// with the [`PluginIdentityMeta`] trait. All methods of this trait have a default
+/// implementation.
+///
+/// [`PluginIdentityMeta`]: ../../trait.PluginIdentityMeta.html
 // TODO: document which other traits.
-pub trait VstPluginMeta: CommonPluginMeta + AudioHandlerMeta {
-    fn plugin_id(&self) -> i32;
-    fn category(&self) -> Category;
+pub trait VstPluginMeta: CommonPluginMeta + PluginIdentityMeta + AudioHandlerMeta {
+    /// The graphical user interface of the plugin, or `None` if the plugin has no editor.
+    ///
+    /// This method is called once, when the plugin is loaded by the host.
+    /// The default implementation returns `None`.
+    fn editor(&self) -> Option<Box<dyn Editor>> {
+        None
+    }
+
+    /// The state that the host stores in its session, or `None` if the host only stores the
+    /// values of the parameters.
+    ///
+    /// This method is called once, when the plugin is loaded by the host.
+    /// The state is saved and restored from the GUI thread of the host, so the plugin should
+    /// not block on the `Mutex` in the audio thread.
+    /// The default implementation returns `None`.
+    fn state(&self) -> Option<Arc<Mutex<dyn PluginState + Send>>> {
+        None
+    }
+
+    /// The tail of the plugin, or `None` if the plugin does not know its tail.
+    /// A plugin that implements [`TailTime`] typically returns `Some(self)`.
+    /// The default implementation returns `None`.
+    ///
+    /// [`TailTime`]: ../../trait.TailTime.html
+    fn as_tail_time(&self) -> Option<&dyn TailTime> {
+        None
+    }
+
+    /// The latency of the plugin, or `None` if the plugin has no latency.
+    /// A plugin that implements [`Latency`] typically returns `Some(self)`.
+    /// The default implementation returns `None`.
+    ///
+    /// [`Latency`]: ../../trait.Latency.html
+    fn as_latency(&self) -> Option<&dyn Latency> {
+        None
+    }
+
+    /// The renderer for `f64` samples, or `None` if the plugin only renders `f32` samples.
+    /// A plugin that implements `ContextualAudioRenderer<f64, HostCallback>` typically returns
+    /// `Some(self)`. The default implementation returns `None`.
+    ///
+    /// This method is called once when the plugin is loaded, to tell the host whether the
+    /// plugin supports double precision, and for every buffer in double precision.
+    fn as_f64_renderer(&mut self) -> Option<&mut dyn ContextualAudioRenderer<f64, HostCallback>> {
+        None
+    }
+
+    /// The channel layout negotiation of the plugin, or `None` if the plugin only supports
+    /// the channel layouts of its meta-data.
+    /// A plugin that implements [`ChannelLayoutNegotiation`] typically returns `Some(self)`.
+    /// The default implementation returns `None`.
+    ///
+    /// [`ChannelLayoutNegotiation`]: ../../channel_layout/trait.ChannelLayoutNegotiation.html
+    fn as_channel_layout_negotiation(&mut self) -> Option<&mut dyn ChannelLayoutNegotiation> {
+        None
+    }
 }
 
 /// A struct used internally by the `vst_init` macro. Normally, plugin's do not need to use this.
@@ -42,6 +203,10 @@ pub struct VstPluginWrapper<P> {
     outputs_f32: VecStorage<&'static [f32]>,
     inputs_f64: VecStorage<&'static [f64]>,
     outputs_f64: VecStorage<&'static [f64]>,
+    // The initial delay that has last been reported to the host.
+    initial_delay: i32,
+    state: Option<Arc<Mutex<dyn PluginState + Send>>>,
+    f64_precision: bool,
 }
 
 impl<P> VstPluginWrapper<P>
@@ -49,9 +214,10 @@ where
     P: CommonAudioPortMeta
         + VstPluginMeta
         + AudioHandler
+        + Lifecycle
+        + Parameters
         + ContextualEventHandler<Timed<RawMidiEvent>, HostCallback>
-        + ContextualAudioRenderer<f32, HostCallback>
-        + ContextualAudioRenderer<f64, HostCallback>,
+        + ContextualAudioRenderer<f32, HostCallback>,
     for<'a> P: ContextualEventHandler<Timed<SysExEvent<'a>>, HostCallback>,
 {
     pub fn get_info(&self) -> Info {
@@ -60,18 +226,27 @@ where
             name: self.plugin.name().to_string(),
             inputs: self.plugin.max_number_of_audio_inputs() as i32,
             outputs: self.plugin.max_number_of_audio_outputs() as i32,
-            unique_id: self.plugin.plugin_id(),
-            category: self.plugin.category(),
+            vendor: self.plugin.vendor().to_string(),
+            unique_id: self.plugin.unique_id(),
+            version: self.plugin.version(),
+            category: vst_category(self.plugin.category()),
+            parameters: self.plugin.parameters().len() as i32,
+            initial_delay: initial_delay(self.plugin.as_latency()),
+            preset_chunks: self.state.is_some(),
+            f64_precision: self.f64_precision,
             ..Info::default()
         }
     }
 
-    pub fn new(plugin: P, host: HostCallback) -> Self {
+    pub fn new(mut plugin: P, host: HostCallback) -> Self {
         Self {
+            f64_precision: plugin.as_f64_renderer().is_some(),
             inputs_f32: VecStorage::with_capacity(plugin.max_number_of_audio_inputs()),
             outputs_f32: VecStorage::with_capacity(plugin.max_number_of_audio_outputs()),
             inputs_f64: VecStorage::with_capacity(plugin.max_number_of_audio_inputs()),
             outputs_f64: VecStorage::with_capacity(plugin.max_number_of_audio_outputs()),
+            initial_delay: initial_delay(plugin.as_latency()),
+            state: plugin.state(),
             plugin,
             host,
         }
@@ -81,7 +256,38 @@ where
         &self.host
     }
 
+    pub fn can_do(&self, can_do: CanDo) -> Supported {
+        trace!("can_do");
+        match can_do {
+            CanDo::SendEvents | CanDo::SendMidiEvent => Supported::Yes,
+            // The `vst` crate does not pass `effSetBypass` to the plugin.
+            CanDo::Bypass => Supported::No,
+            _ => Supported::Maybe,
+        }
+    }
+
+    pub fn get_tail_size(&self) -> isize {
+        trace!("get_tail_size");
+        tail_size(self.plugin.as_tail_time())
+    }
+
+    pub fn get_editor(&mut self) -> Option<Box<dyn vst::editor::Editor>> {
+        trace!("get_editor");
+        self.plugin
+            .editor()
+            .map(|editor| Box::new(VstEditor { editor }) as Box<dyn vst::editor::Editor>)
+    }
+
+    pub fn get_parameter_object(&mut self) -> Arc<dyn PluginParameters> {
+        Arc::new(VstParameters {
+            store: self.plugin.parameters(),
+            state: self.state.clone(),
+        })
+    }
+
     pub fn process<'b>(&mut self, buffer: &mut AudioBuffer<'b, f32>) {
+        #[cfg(feature = "flush-denormals")]
+        let _denormal_guard = DenormalGuard::new();
         let (input_buffers, mut output_buffers) = buffer.split();
 
         let mut inputs = self.inputs_f32.vec_guard();
@@ -98,9 +304,14 @@ where
 
         self.plugin
             .render_buffer(inputs.as_slice(), outputs.as_mut_slice(), &mut self.host);
+        drop(inputs);
+        drop(outputs);
+        self.report_latency_change();
     }
 
     pub fn process_f64<'b>(&mut self, buffer: &mut AudioBuffer<'b, f64>) {
+        #[cfg(feature = "flush-denormals")]
+        let _denormal_guard = DenormalGuard::new();
         let (input_buffers, mut output_buffers) = buffer.split();
 
         let mut inputs = self.inputs_f64.vec_guard();
@@ -115,27 +326,106 @@ where
             outputs.push(output_buffers.get_mut(i));
         }
 
-        self.plugin
-            .render_buffer(inputs.as_slice(), outputs.as_mut_slice(), &mut self.host);
+        match self.plugin.as_f64_renderer() {
+            Some(renderer) => {
+                renderer.render_buffer(inputs.as_slice(), outputs.as_mut_slice(), &mut self.host)
+            }
+            // The host only calls this when the plugin supports double precision.
+            None => initialize_to_zero(outputs.as_mut_slice()),
+        }
+        drop(inputs);
+        drop(outputs);
+        self.report_latency_change();
+    }
+
+    // Notify the host when the latency of the plugin differs from the reported initial delay.
+    fn report_latency_change(&mut self) {
+        let initial_delay = initial_delay(self.plugin.as_latency());
+        if initial_delay == self.initial_delay {
+            return;
+        }
+        self.initial_delay = initial_delay;
+        if let Some(callback) = self.host.raw_callback() {
+            let effect = self.host.raw_effect();
+            if !effect.is_null() {
+                unsafe {
+                    (*effect).initial_delay = initial_delay;
+                }
+                callback(
+                    effect,
+                    OpCode::IOChanged as i32,
+                    0,
+                    0,
+                    core::ptr::null_mut(),
+                    0.0,
+                );
+            }
+        }
     }
 
     pub fn get_input_info(&self, input_index: i32) -> ChannelInfo {
         trace!("get_input_info({})", input_index);
+        let index = input_index as usize;
+        let layout = self.plugin.audio_input_layout();
         ChannelInfo::new(
-            self.plugin.audio_input_name(input_index as usize),
-            None,
+            self.plugin.audio_input_name(index),
+            layout.speaker(index).map(|speaker| speaker.to_string()),
             true,
-            None,
+            speaker_arrangement(layout, index),
         )
     }
 
     pub fn get_output_info(&self, output_index: i32) -> ChannelInfo {
         trace!("get_output_info({})", output_index);
+        let index = output_index as usize;
+        let layout = self.plugin.audio_output_layout();
         ChannelInfo::new(
-            self.plugin.audio_output_name(output_index as usize),
-            None,
+            self.plugin.audio_output_name(index),
+            layout.speaker(index).map(|speaker| speaker.to_string()),
             true,
-            None,
+            speaker_arrangement(layout, index),
+        )
+    }
+
+    /// Switch to the speaker arrangements that are proposed by the host, if the plugin
+    /// supports them. Returns `false` if the plugin keeps its current channel layouts.
+    pub fn set_speaker_arrangement(
+        &mut self,
+        input: SpeakerArrangementType,
+        number_of_inputs: usize,
+        output: SpeakerArrangementType,
+        number_of_outputs: usize,
+    ) -> bool {
+        trace!("set_speaker_arrangement");
+        if number_of_inputs > self.plugin.max_number_of_audio_inputs()
+            || number_of_outputs > self.plugin.max_number_of_audio_outputs()
+        {
+            return false;
+        }
+        let inputs = channel_layout(&input, number_of_inputs);
+        let outputs = channel_layout(&output, number_of_outputs);
+        match self.plugin.as_channel_layout_negotiation() {
+            Some(negotiation) => negotiation.set_channel_layouts(inputs, outputs),
+            None => false,
+        }
+    }
+
+    /// The speaker arrangements (type and number of channels) of the inputs and the outputs.
+    pub fn get_speaker_arrangement(
+        &self,
+    ) -> (
+        (Option<SpeakerArrangementType>, usize),
+        (Option<SpeakerArrangementType>, usize),
+    ) {
+        trace!("get_speaker_arrangement");
+        let inputs = self.plugin.audio_input_layout();
+        let outputs = self.plugin.audio_output_layout();
+        (
+            (speaker_arrangement(inputs, 0), inputs.number_of_channels()),
+            (
+                speaker_arrangement(outputs, 0),
+                outputs.number_of_channels(),
+            ),
         )
     }
 
@@ -157,11 +447,15 @@ where
                 VstEvent::Midi(VstMidiEvent {
                     data, delta_frames, ..
                 }) => {
-                    let event = Timed {
-                        time_in_frames: delta_frames as u32,
-                        event: RawMidiEvent::new(&data),
-                    };
-                    self.plugin.handle_event(event, &mut self.host);
+                    if let Some(raw_event) = RawMidiEvent::try_new(&data) {
+                        let event = Timed {
+                            time_in_frames: delta_frames as u32,
+                            event: raw_event,
+                        };
+                        self.plugin.handle_event(event, &mut self.host);
+                    } else {
+                        warn!("Ignoring invalid midi event: {:?}", data);
+                    }
                 }
                 _ => (),
             }
@@ -172,6 +466,320 @@ where
         trace!("sample_rate: {}", sample_rate);
         self.plugin.set_sample_rate(sample_rate);
     }
+
+    pub fn set_block_size(&mut self, block_size: i64) {
+        trace!("block_size: {}", block_size);
+        if block_size > 0 {
+            self.plugin.set_max_buffer_size(block_size as usize);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        trace!("resume");
+        self.plugin.on_resume();
+        self.report_latency_change();
+    }
+
+    pub fn suspend(&mut self) {
+        trace!("suspend");
+        self.plugin.on_suspend();
+    }
+}
+
+// Gives the host access to the parameters of the plugin.
+// The host may ask for parameters that do not exist, so all indices are checked.
+struct VstParameters {
+    store: Arc<ParameterStore>,
+    state: Option<Arc<Mutex<dyn PluginState + Send>>>,
+}
+
+impl VstParameters {
+    fn index(&self, index: i32) -> Option<usize> {
+        if index >= 0 && (index as usize) < self.store.len() {
+            Some(index as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl PluginParameters for VstParameters {
+    fn get_parameter_label(&self, index: i32) -> String {
+        self.index(index)
+            .map(|index| self.store.info(index).label.clone())
+            .unwrap_or_default()
+    }
+
+    fn get_parameter_text(&self, index: i32) -> String {
+        self.index(index)
+            .map(|index| format!("{:.2}", self.store.get(index)))
+            .unwrap_or_default()
+    }
+
+    fn get_parameter_name(&self, index: i32) -> String {
+        self.index(index)
+            .map(|index| self.store.info(index).name.clone())
+            .unwrap_or_default()
+    }
+
+    fn get_parameter(&self, index: i32) -> f32 {
+        self.index(index)
+            .map(|index| self.store.get_normalized(index))
+            .unwrap_or(0.0)
+    }
+
+    fn set_parameter(&self, index: i32, value: f32) {
+        if let Some(index) = self.index(index) {
+            self.store.set_normalized(index, value);
+        }
+    }
+
+    fn string_to_parameter(&self, index: i32, text: String) -> bool {
+        match (self.index(index), text.trim().parse::<f32>()) {
+            (Some(index), Ok(value)) => {
+                self.store.set(index, value);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn get_preset_data(&self) -> Vec<u8> {
+        match &self.state {
+            Some(state) => state
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .save_state(),
+            None => Vec::new(),
+        }
+    }
+
+    fn get_bank_data(&self) -> Vec<u8> {
+        self.get_preset_data()
+    }
+
+    fn load_preset_data(&self, data: &[u8]) {
+        if let Some(state) = &self.state {
+            let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            if state.load_state(data).is_err() {
+                warn!("Ignoring invalid state of {} bytes.", data.len());
+            }
+        }
+    }
+
+    fn load_bank_data(&self, data: &[u8]) {
+        self.load_preset_data(data);
+    }
+}
+
+fn vst_category(category: Category) -> VstCategory {
+    match category {
+        Category::Synth => VstCategory::Synth,
+        Category::Effect => VstCategory::Effect,
+        Category::Analysis => VstCategory::Analysis,
+        Category::Mastering => VstCategory::Mastering,
+        Category::Spatializer => VstCategory::Spacializer,
+        Category::RoomFx => VstCategory::RoomFx,
+        Category::SurroundFx => VstCategory::SurroundFx,
+        Category::Restoration => VstCategory::Restoration,
+        Category::Generator => VstCategory::Generator,
+        Category::Unknown => VstCategory::Unknown,
+    }
+}
+
+// In VST, a tail size of 0 means that the tail is unknown and 1 means that there is no tail.
+fn tail_size(tail_time: Option<&dyn TailTime>) -> isize {
+    match tail_time.map(|tail_time| tail_time.tail_time_in_frames()) {
+        None => 0,
+        Some(0) => 1,
+        Some(frames) => frames as isize,
+    }
+}
+
+// The initial delay that is reported to the host.
+fn initial_delay(latency: Option<&dyn Latency>) -> i32 {
+    let frames = latency.map_or(0, |latency| latency.latency_in_frames());
+    cmp::min(frames, i32::MAX as usize) as i32
+}
+
+// Exposes the `Editor` of the plugin to the host.
+struct VstEditor {
+    editor: Box<dyn Editor>,
+}
+
+impl vst::editor::Editor for VstEditor {
+    fn size(&self) -> (i32, i32) {
+        let (width, height) = self.editor.size();
+        (width as i32, height as i32)
+    }
+
+    fn position(&self) -> (i32, i32) {
+        (0, 0)
+    }
+
+    fn idle(&mut self) {
+        self.editor.idle();
+    }
+
+    fn close(&mut self) {
+        self.editor.close();
+    }
+
+    fn open(&mut self, parent: *mut c_void) -> bool {
+        trace!("open editor");
+        self.editor.open(parent)
+    }
+
+    fn is_open(&mut self) -> bool {
+        self.editor.is_open()
+    }
+}
+
+// The VST speaker arrangement of the channel with the given index.
+fn speaker_arrangement(layout: ChannelLayout, index: usize) -> Option<SpeakerArrangementType> {
+    if index >= layout.number_of_channels() {
+        return None;
+    }
+    let surround = |config| Some(SpeakerArrangementType::Surround(config));
+    match layout {
+        ChannelLayout::Mono => Some(SpeakerArrangementType::Mono),
+        ChannelLayout::Stereo => {
+            let channel = if index == 0 {
+                StereoChannel::Left
+            } else {
+                StereoChannel::Right
+            };
+            Some(SpeakerArrangementType::Stereo(StereoConfig::L_R, channel))
+        }
+        ChannelLayout::Lcr => surround(SurroundConfig::Cinema(CinemaConfig::S3_0)),
+        ChannelLayout::Quad => surround(SurroundConfig::Music(MusicConfig::S4_0)),
+        ChannelLayout::Surround50 => surround(SurroundConfig::Cinema(CinemaConfig::S5_0)),
+        ChannelLayout::Surround51 => surround(SurroundConfig::Cinema(CinemaConfig::S5_1)),
+        ChannelLayout::Surround71 => surround(SurroundConfig::Music(MusicConfig::S7_1)),
+        ChannelLayout::Ambisonic { .. } => Some(SpeakerArrangementType::Custom),
+        ChannelLayout::Discrete(_) => None,
+    }
+}
+
+// The inverse of `speaker_arrangement`: the channel layout of a bus with the given speaker
+// arrangement.
+fn channel_layout(
+    arrangement: &SpeakerArrangementType,
+    number_of_channels: usize,
+) -> ChannelLayout {
+    let layout = match arrangement {
+        SpeakerArrangementType::Mono => ChannelLayout::Mono,
+        SpeakerArrangementType::Stereo(..) => ChannelLayout::Stereo,
+        SpeakerArrangementType::Surround(SurroundConfig::Cinema(CinemaConfig::S3_0)) => {
+            ChannelLayout::Lcr
+        }
+        SpeakerArrangementType::Surround(SurroundConfig::Music(MusicConfig::S4_0)) => {
+            ChannelLayout::Quad
+        }
+        SpeakerArrangementType::Surround(SurroundConfig::Cinema(CinemaConfig::S5_0)) => {
+            ChannelLayout::Surround50
+        }
+        SpeakerArrangementType::Surround(SurroundConfig::Cinema(CinemaConfig::S5_1)) => {
+            ChannelLayout::Surround51
+        }
+        SpeakerArrangementType::Surround(SurroundConfig::Music(MusicConfig::S7_1)) => {
+            ChannelLayout::Surround71
+        }
+        _ => ChannelLayout::Discrete(number_of_channels),
+    };
+    if layout.number_of_channels() == number_of_channels {
+        layout
+    } else {
+        ChannelLayout::Discrete(number_of_channels)
+    }
+}
+
+/// Sends the midi events that are generated by the plugin to the host.
+///
+/// Pass the `Timed<RawMidiEvent>`s to its `EventHandler` implementation while rendering and
+/// call [`flush`] at the end of `render_buffer`. Events that are scheduled after the end of the
+/// buffer can be collected in an `OutputEventQueue` and sent with [`send`]; see the
+/// documentation of the [`output_event_queue`] module for more information.
+///
+/// [`flush`]: ./struct.VstMidiOutput.html#method.flush
+/// [`send`]: ./struct.VstMidiOutput.html#method.send
+/// [`output_event_queue`]: ../../event/output_event_queue/index.html
+pub struct VstMidiOutput {
+    send_buffer: SendEventBuffer,
+    events: Vec<VstMidiEvent>,
+    number_of_dropped_events: usize,
+}
+
+impl VstMidiOutput {
+    /// Create a new `VstMidiOutput` that can send `capacity` events per buffer.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(capacity: usize) -> Self {
+        VstMidiOutput {
+            send_buffer: SendEventBuffer::new(capacity),
+            events: Vec::with_capacity(capacity),
+            number_of_dropped_events: 0,
+        }
+    }
+
+    /// The number of events that are waiting to be sent to the host.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Send the events that have been passed to this `VstMidiOutput` to the host.
+    ///
+    /// When more events were passed than the capacity allows, the remaining events were dropped
+    /// and a warning is logged.
+    pub fn flush(&mut self, host: &mut HostCallback) {
+        if self.number_of_dropped_events > 0 {
+            crate::rt_log!(
+                Level::Warn,
+                "Too many midi output events, dropping {} events.",
+                self.number_of_dropped_events
+            );
+            self.number_of_dropped_events = 0;
+        }
+        self.send_buffer.send_events(self.events.drain(..), host);
+    }
+
+    /// Send the events of `queue` that fall within the current buffer of `number_of_frames`
+    /// frames to the host.
+    ///
+    /// When there are more events than the capacity allows, the remaining events are dropped.
+    pub fn send(
+        &mut self,
+        queue: &mut OutputEventQueue<RawMidiEvent>,
+        number_of_frames: usize,
+        host: &mut HostCallback,
+    ) {
+        queue.drain(number_of_frames, |event| self.handle_event(event));
+        self.flush(host);
+    }
+}
+
+impl EventHandler<Timed<RawMidiEvent>> for VstMidiOutput {
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+        if self.events.len() < self.events.capacity() {
+            self.events.push(VstMidiEvent {
+                data: *event.event.data(),
+                delta_frames: event.time_in_frames as i32,
+                live: true,
+                note_length: None,
+                note_offset: None,
+                detune: 0,
+                note_off_velocity: 0,
+            });
+        } else {
+            self.number_of_dropped_events += 1;
+        }
+    }
 }
 
 impl HostInterface for HostCallback {
@@ -182,6 +790,49 @@ impl HostInterface for HostCallback {
     }
 }
 
+impl TransportContext for HostCallback {
+    fn transport(&self) -> Option<TransportState> {
+        let mask = TimeInfoFlags::PPQ_POS_VALID
+            | TimeInfoFlags::TEMPO_VALID
+            | TimeInfoFlags::TIME_SIG_VALID
+            | TimeInfoFlags::CYCLE_POS_VALID;
+        self.get_time_info(mask.bits())
+            .and_then(|time_info| transport_state(&time_info))
+    }
+}
+
+// The state of the transport, if the host provides the position in beats and the tempo.
+fn transport_state(time_info: &TimeInfo) -> Option<TransportState> {
+    let flags = TimeInfoFlags::from_bits_truncate(time_info.flags);
+    if !flags.contains(TimeInfoFlags::PPQ_POS_VALID | TimeInfoFlags::TEMPO_VALID) {
+        return None;
+    }
+    let time_signature = if flags.contains(TimeInfoFlags::TIME_SIG_VALID) {
+        TimeSignature::new(
+            time_info.time_sig_numerator as u32,
+            time_info.time_sig_denominator as u32,
+        )
+    } else {
+        TimeSignature::default()
+    };
+    let loop_range =
+        if flags.contains(TimeInfoFlags::CYCLE_POS_VALID | TimeInfoFlags::TRANSPORT_CYCLE_ACTIVE) {
+            Some(LoopRange::new(
+                time_info.cycle_start_pos,
+                time_info.cycle_end_pos,
+            ))
+        } else {
+            None
+        };
+    Some(TransportState {
+        is_playing: flags.contains(TimeInfoFlags::TRANSPORT_PLAYING),
+        position_in_beats: time_info.ppq_pos,
+        tempo: time_info.tempo,
+        time_signature,
+        loop_range,
+    })
+}
+
 /// A wrapper around the `plugin_main!` macro from the `vst` crate.
 /// You call this with one parameter, which is the function declaration of a function
 /// that creates your plugin.
@@ -194,12 +845,13 @@ impl HostInterface for HostCallback {
 /// # extern crate asprim;
 /// # #[macro_use] extern crate vst;
 /// struct MyPlugin {
-///   meta: MetaData<&'static str, &'static str, &'static str>
+///   meta: MetaData<PluginIdentity<&'static str>, &'static str, &'static str>,
+///   parameters: Arc<ParameterStore>,
 ///   // Define other fields here
 /// }
 ///
 /// use rsynth::{
-///     meta::{Meta, MetaData, Port, MidiPort, AudioPort, InOut},
+///     meta::{Category, Meta, MetaData, Port, MidiPort, AudioPort, InOut, PluginIdentity},
 ///     event::{
 ///         ContextualEventHandler,
 ///         Timed,
@@ -210,21 +862,28 @@ impl HostInterface for HostCallback {
 ///         HostInterface,
 ///         vst_backend::VstPluginMeta
 ///     },
+///     parameter::{ParameterInfo, ParameterStore, Parameters},
 ///     ContextualAudioRenderer,
-///     AudioHandler
+///     AudioHandler,
+///     Lifecycle
 /// };
+/// use std::sync::Arc;
 ///
 /// impl Meta for MyPlugin {
-///    type MetaData = MetaData<&'static str, &'static str, &'static str>;
+///    type MetaData = MetaData<PluginIdentity<&'static str>, &'static str, &'static str>;
 ///     fn meta(&self) -> &Self::MetaData {
 ///         &self.meta
 ///     }
 /// }
 ///
-/// use vst::plugin::Category;
-/// impl VstPluginMeta for MyPlugin {
-///     fn plugin_id(&self) -> i32 { 123 }
-///     fn category(&self) -> Category { Category::Synth }
+/// impl VstPluginMeta for MyPlugin {}
+///
+/// impl Lifecycle for MyPlugin {}
+///
+/// impl Parameters for MyPlugin {
+///     fn parameters(&self) -> Arc<ParameterStore> {
+///         Arc::clone(&self.parameters)
+///     }
 /// }
 ///
 /// use asprim::AsPrim;
@@ -268,7 +927,13 @@ impl HostInterface for HostCallback {
 ///    fn init() -> MyPlugin {
 ///        MyPlugin {
 ///             meta: MetaData {
-///                 general_meta: "my_plugin",
+///                 general_meta: PluginIdentity {
+///                     name: "my_plugin",
+///                     vendor: "me",
+///                     unique_id: i32::from_be_bytes(*b"MyPl"),
+///                     version: 1,
+///                     category: Category::Synth,
+///                 },
 ///                 audio_port_meta: InOut {
 ///                     inputs: vec!["audio in 1", "audio in 2"],
 ///                     outputs: vec!["audio out 1", "audio out 2"],
@@ -277,7 +942,10 @@ impl HostInterface for HostCallback {
 ///                     inputs: vec!["midi in 1"],
 ///                     outputs: vec![],
 ///                 },
-///             }
+///             },
+///             parameters: Arc::new(ParameterStore::new(vec![
+///                 ParameterInfo::new("Volume", "", 0.0, 1.0, 0.8),
+///             ])),
 ///        }
 ///    }
 /// );
@@ -350,6 +1018,18 @@ macro_rules! vst_init {
                 }
             }
 
+            fn set_block_size(&mut self, size: i64) {
+                self.wrapper.set_block_size(size);
+            }
+
+            fn resume(&mut self) {
+                self.wrapper.resume();
+            }
+
+            fn suspend(&mut self) {
+                self.wrapper.suspend();
+            }
+
             #[inline]
             fn process<'b>(&mut self, buffer: &mut vst::buffer::AudioBuffer<'b, f32>) {
                 self.wrapper.process(buffer);
@@ -360,6 +1040,22 @@ macro_rules! vst_init {
                 self.wrapper.process_f64(buffer);
             }
 
+            fn get_tail_size(&self) -> isize {
+                self.wrapper.get_tail_size()
+            }
+
+            fn get_editor(&mut self) -> Option<Box<dyn vst::editor::Editor>> {
+                self.wrapper.get_editor()
+            }
+
+            fn can_do(&self, can_do: vst::plugin::CanDo) -> vst::api::Supported {
+                self.wrapper.can_do(can_do)
+            }
+
+            fn get_parameter_object(&mut self) -> std::sync::Arc<dyn vst::plugin::PluginParameters> {
+                self.wrapper.get_parameter_object()
+            }
+
             fn get_input_info(&self, input_index: i32) -> vst::channels::ChannelInfo {
                 self.wrapper.get_input_info(input_index)
             }
@@ -377,3 +1073,269 @@ macro_rules! vst_init {
         plugin_main!(VstWrapperWrapper);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameter::ParameterInfo;
+
+    fn parameters() -> VstParameters {
+        VstParameters {
+            store: Arc::new(ParameterStore::new(vec![ParameterInfo::new(
+                "Cutoff", "Hz", 20.0, 20020.0, 1020.0,
+            )])),
+            state: None,
+        }
+    }
+
+    #[test]
+    fn parameters_are_exposed_to_the_host() {
+        let parameters = parameters();
+        assert_eq!(parameters.get_parameter_name(0), "Cutoff");
+        assert_eq!(parameters.get_parameter_label(0), "Hz");
+        assert_eq!(parameters.get_parameter(0), 0.05);
+        parameters.set_parameter(0, 0.5);
+        assert_eq!(parameters.get_parameter_text(0), "10020.00");
+        assert!(parameters.string_to_parameter(0, " 20 ".to_string()));
+        assert_eq!(parameters.get_parameter(0), 0.0);
+    }
+
+    struct Patch {
+        name: String,
+    }
+
+    impl PluginState for Patch {
+        fn save_state(&self) -> Vec<u8> {
+            self.name.as_bytes().to_vec()
+        }
+
+        fn load_state(&mut self, state: &[u8]) -> Result<(), crate::InvalidState> {
+            self.name = String::from_utf8(state.to_vec()).map_err(|_| crate::InvalidState)?;
+            Ok(())
+        }
+    }
+
+    fn parameters_with_patch(name: &str) -> VstParameters {
+        VstParameters {
+            state: Some(Arc::new(Mutex::new(Patch {
+                name: name.to_string(),
+            }))),
+            ..parameters()
+        }
+    }
+
+    #[test]
+    fn state_is_saved_and_restored() {
+        let parameters = parameters_with_patch("Bass");
+        let state = parameters.get_preset_data();
+        let restored = parameters_with_patch("Init");
+        restored.load_bank_data(&state);
+        assert_eq!(restored.get_bank_data(), b"Bass");
+        restored.load_preset_data(b"\xff");
+        assert_eq!(restored.get_preset_data(), b"Bass");
+    }
+
+    #[test]
+    fn only_the_parameters_are_exposed_without_state() {
+        let parameters = parameters();
+        assert!(parameters.get_preset_data().is_empty());
+        parameters.load_preset_data(b"Bass");
+        assert_eq!(parameters.get_parameter(0), 0.05);
+    }
+
+    fn time_info(flags: TimeInfoFlags) -> TimeInfo {
+        TimeInfo {
+            sample_pos: 44100.0,
+            sample_rate: 44100.0,
+            nanoseconds: 0.0,
+            ppq_pos: 2.0,
+            tempo: 120.0,
+            bar_start_pos: 0.0,
+            cycle_start_pos: 4.0,
+            cycle_end_pos: 8.0,
+            time_sig_numerator: 6,
+            time_sig_denominator: 8,
+            smpte_offset: 0,
+            smpte_frame_rate: 0,
+            samples_to_next_clock: 0,
+            flags: flags.bits(),
+        }
+    }
+
+    #[test]
+    fn time_info_is_converted_to_transport_state() {
+        let flags = TimeInfoFlags::TRANSPORT_PLAYING
+            | TimeInfoFlags::TRANSPORT_CYCLE_ACTIVE
+            | TimeInfoFlags::PPQ_POS_VALID
+            | TimeInfoFlags::TEMPO_VALID
+            | TimeInfoFlags::TIME_SIG_VALID
+            | TimeInfoFlags::CYCLE_POS_VALID;
+        assert_eq!(
+            transport_state(&time_info(flags)),
+            Some(TransportState {
+                is_playing: true,
+                position_in_beats: 2.0,
+                tempo: 120.0,
+                time_signature: TimeSignature::new(6, 8),
+                loop_range: Some(LoopRange::new(4.0, 8.0)),
+            })
+        );
+
+        let flags = TimeInfoFlags::PPQ_POS_VALID | TimeInfoFlags::TEMPO_VALID;
+        let state = transport_state(&time_info(flags)).expect("position and tempo are valid");
+        assert!(!state.is_playing);
+        assert_eq!(state.time_signature, TimeSignature::default());
+        assert_eq!(state.loop_range, None);
+
+        assert_eq!(
+            transport_state(&time_info(TimeInfoFlags::TEMPO_VALID)),
+            None
+        );
+    }
+
+    #[test]
+    fn midi_output_events_beyond_the_capacity_are_dropped() {
+        let mut midi_output = VstMidiOutput::new(2);
+        for time in 0..3 {
+            midi_output.handle_event(Timed::new(time, RawMidiEvent::new(&[0x90, 60, 100])));
+        }
+        assert_eq!(midi_output.len(), 2);
+        assert_eq!(midi_output.number_of_dropped_events, 1);
+        assert_eq!(midi_output.events[1].delta_frames, 1);
+        assert_eq!(midi_output.events[1].data, [0x90, 60, 100]);
+    }
+
+    #[derive(Default)]
+    struct TestEditor {
+        parent: Option<usize>,
+        idle_calls: usize,
+    }
+
+    impl Editor for TestEditor {
+        fn size(&self) -> (u32, u32) {
+            (400, 300)
+        }
+
+        fn open(&mut self, parent: *mut c_void) -> bool {
+            self.parent = Some(parent as usize);
+            true
+        }
+
+        fn close(&mut self) {
+            self.parent = None;
+        }
+
+        fn idle(&mut self) {
+            self.idle_calls += 1;
+        }
+
+        fn is_open(&self) -> bool {
+            self.parent.is_some()
+        }
+    }
+
+    #[test]
+    fn editor_is_exposed_to_the_host() {
+        use vst::editor::Editor as _;
+        let mut editor = VstEditor {
+            editor: Box::new(TestEditor::default()),
+        };
+        assert_eq!(editor.size(), (400, 300));
+        assert!(!editor.is_open());
+        assert!(editor.open(42 as *mut c_void));
+        editor.idle();
+        assert!(editor.is_open());
+        editor.close();
+        assert!(!editor.is_open());
+    }
+
+    struct Reverb {
+        tail_time_in_frames: usize,
+    }
+
+    impl TailTime for Reverb {
+        fn tail_time_in_frames(&self) -> usize {
+            self.tail_time_in_frames
+        }
+    }
+
+    #[test]
+    fn tail_time_is_converted_to_tail_size() {
+        assert_eq!(tail_size(None), 0);
+        let reverb = Reverb {
+            tail_time_in_frames: 0,
+        };
+        assert_eq!(tail_size(Some(&reverb)), 1);
+        let reverb = Reverb {
+            tail_time_in_frames: 88200,
+        };
+        assert_eq!(tail_size(Some(&reverb)), 88200);
+    }
+
+    struct Limiter {
+        latency_in_frames: usize,
+    }
+
+    impl Latency for Limiter {
+        fn latency_in_frames(&self) -> usize {
+            self.latency_in_frames
+        }
+    }
+
+    #[test]
+    fn latency_is_converted_to_initial_delay() {
+        assert_eq!(initial_delay(None), 0);
+        let limiter = Limiter {
+            latency_in_frames: 64,
+        };
+        assert_eq!(initial_delay(Some(&limiter)), 64);
+        let limiter = Limiter {
+            latency_in_frames: usize::MAX,
+        };
+        assert_eq!(initial_delay(Some(&limiter)), i32::MAX);
+    }
+
+    #[test]
+    fn speaker_arrangements_are_converted_to_channel_layouts() {
+        let surround51 =
+            SpeakerArrangementType::Surround(SurroundConfig::Cinema(CinemaConfig::S5_1));
+        assert_eq!(channel_layout(&surround51, 6), ChannelLayout::Surround51);
+        assert_eq!(
+            channel_layout(
+                &SpeakerArrangementType::Stereo(StereoConfig::L_R, StereoChannel::Left),
+                2
+            ),
+            ChannelLayout::Stereo
+        );
+        assert_eq!(
+            channel_layout(&SpeakerArrangementType::Custom, 4),
+            ChannelLayout::Discrete(4)
+        );
+        // The number of channels does not match the arrangement.
+        assert_eq!(channel_layout(&surround51, 2), ChannelLayout::Discrete(2));
+        for layout in [
+            ChannelLayout::Mono,
+            ChannelLayout::Stereo,
+            ChannelLayout::Lcr,
+            ChannelLayout::Quad,
+            ChannelLayout::Surround50,
+            ChannelLayout::Surround51,
+            ChannelLayout::Surround71,
+        ] {
+            let arrangement = speaker_arrangement(layout, 0).expect("not discrete");
+            assert_eq!(
+                channel_layout(&arrangement, layout.number_of_channels()),
+                layout
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_parameters_are_ignored() {
+        let parameters = parameters();
+        assert_eq!(parameters.get_parameter_name(1), "");
+        assert_eq!(parameters.get_parameter(-1), 0.0);
+        parameters.set_parameter(1, 0.5);
+        assert!(!parameters.string_to_parameter(1, "0.5".to_string()));
+    }
+}
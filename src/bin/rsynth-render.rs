@@ -0,0 +1,673 @@
+// A command line tool to render audio offline, built on the `combined` back-end.
+//
+// Compiling
+// =========
+// You can compile this tool with
+// ```
+// cargo build --release --features cli
+// ```
+// If you also want to render with VST plugins that are loaded at runtime, compile with
+// ```
+// cargo build --release --features cli,vst-hosting
+// ```
+//
+// Running
+// =======
+// Run `rsynth-render --help` to see all options. Some examples:
+// ```
+// rsynth-render --input dry.wav --plugin gain --parameter 0=0.5 --output quiet.wav
+// rsynth-render --midi song.mid --plugin sine --output song.wav
+// rsynth-render --midi song.mid --plugin ./libmy_plugin.so --parameter 3=0.25 --output song.wav
+// ```
+//
+// Plugins
+// =======
+// Built-in plugins are selected by name, other plugins are loaded from the given path
+// (if compiled with the "vst-hosting" feature).
+// Parameter overrides have the form `<index>=<value>`.
+// For built-in plugins, the value is the "plain" value of the parameter,
+// for VST plugins, the value is normalized, i.e. in the range `0.0..=1.0`.
+#[macro_use]
+extern crate log;
+extern crate hound;
+extern crate midi_consts;
+extern crate rimd;
+extern crate rsynth;
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use midi_consts::channel_event::{EVENT_TYPE_MASK, NOTE_OFF, NOTE_ON};
+use rimd::SMF;
+use rsynth::backend::combined::dummy::MidiDummy;
+use rsynth::backend::combined::hound::{HoundAudioError, HoundAudioReader, HoundAudioWriter};
+use rsynth::backend::combined::rimd::RimdMidiReader;
+use rsynth::backend::combined::{
+    run, AudioReader, AudioWriter, CombinedError, MICROSECONDS_PER_SECOND,
+};
+use rsynth::event::{EventHandler, RawMidiEvent, Timed};
+#[cfg(feature = "vst-hosting")]
+use rsynth::hosting::HostedVstPlugin;
+use rsynth::parameter::{ParameterInfo, ParameterStore};
+use rsynth::{AudioHandler, AudioRenderer, ContextualAudioRenderer};
+use std::cmp;
+use std::env;
+use std::f32::consts::PI;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "\
+Render audio offline with an rsynth plugin.
+
+Usage: rsynth-render [OPTIONS] --output <FILE>
+
+Options:
+  --input <FILE>               The `.wav` file to use as audio input.
+  --midi <FILE>                The `.mid` file to use as midi input.
+  --track <INDEX>              The track of the `.mid` file to use [default: 0].
+  --plugin <NAME|PATH>         The name of a built-in plugin or the path to a VST plugin [default: gain].
+  --parameter <INDEX>=<VALUE>  Override the value of a parameter. Can be given more than once.
+  --output <FILE>              The `.wav` file to write the audio output to.
+  --buffer-size <FRAMES>       The number of frames that are rendered at once [default: 256].
+  --sample-rate <HZ>           The sample rate when there is no audio input [default: 44100].
+  --channels <NUMBER>          The number of channels when there is no audio input [default: 2].
+  --tail <SECONDS>             The time to render after the last midi event when there is no
+                               audio input [default: 1].
+  --list-plugins               List the built-in plugins and exit.
+  --help                       Print this help and exit.";
+
+const DEFAULT_PLUGIN: &str = "gain";
+const DEFAULT_BUFFER_SIZE: usize = 256;
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+const DEFAULT_NUMBER_OF_CHANNELS: u16 = 2;
+const DEFAULT_TAIL_IN_SECONDS: f64 = 1.0;
+#[cfg(feature = "vst-hosting")]
+const MAX_NUMBER_OF_EVENTS: usize = 1024;
+
+/// The built-in plugins: name and description.
+const BUILTIN_PLUGINS: [(&str, &str); 2] = [
+    (
+        "gain",
+        "Multiplies the audio input by the \"Gain\" parameter.",
+    ),
+    (
+        "sine",
+        "A monophonic sine wave synthesizer with a \"Volume\" parameter.",
+    ),
+];
+
+struct Options {
+    input: Option<String>,
+    midi: Option<String>,
+    track: usize,
+    plugin: String,
+    parameters: Vec<(usize, f32)>,
+    output: String,
+    buffer_size: usize,
+    sample_rate: u32,
+    number_of_channels: u16,
+    tail_in_seconds: f64,
+}
+
+enum Command {
+    Render(Options),
+    ListPlugins,
+    Help,
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("Missing value for `{}`.", option))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for `{}`: `{}`.", option, value))
+}
+
+fn parse_parameter(value: Option<String>) -> Result<(usize, f32), String> {
+    let value = value.ok_or_else(|| "Missing value for `--parameter`.".to_string())?;
+    let invalid = || {
+        format!(
+            "Invalid value for `--parameter`: `{}`, expected `<index>=<value>`.",
+            value
+        )
+    };
+    let mut parts = value.splitn(2, '=');
+    let index = parts
+        .next()
+        .and_then(|index| index.trim().parse().ok())
+        .ok_or_else(invalid)?;
+    let parameter_value = parts
+        .next()
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(invalid)?;
+    Ok((index, parameter_value))
+}
+
+fn parse_arguments<I>(mut arguments: I) -> Result<Command, String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut input = None;
+    let mut midi = None;
+    let mut track = 0;
+    let mut plugin = DEFAULT_PLUGIN.to_string();
+    let mut parameters = Vec::new();
+    let mut output = None;
+    let mut buffer_size = DEFAULT_BUFFER_SIZE;
+    let mut sample_rate = DEFAULT_SAMPLE_RATE;
+    let mut number_of_channels = DEFAULT_NUMBER_OF_CHANNELS;
+    let mut tail_in_seconds = DEFAULT_TAIL_IN_SECONDS;
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--input" => input = Some(parse_value(&argument, arguments.next())?),
+            "--midi" => midi = Some(parse_value(&argument, arguments.next())?),
+            "--track" => track = parse_value(&argument, arguments.next())?,
+            "--plugin" => plugin = parse_value(&argument, arguments.next())?,
+            "--parameter" => parameters.push(parse_parameter(arguments.next())?),
+            "--output" => output = Some(parse_value(&argument, arguments.next())?),
+            "--buffer-size" => buffer_size = parse_value(&argument, arguments.next())?,
+            "--sample-rate" => sample_rate = parse_value(&argument, arguments.next())?,
+            "--channels" => number_of_channels = parse_value(&argument, arguments.next())?,
+            "--tail" => tail_in_seconds = parse_value(&argument, arguments.next())?,
+            "--list-plugins" => return Ok(Command::ListPlugins),
+            "--help" | "-h" => return Ok(Command::Help),
+            _ => return Err(format!("Unknown argument: `{}`.", argument)),
+        }
+    }
+    let output = output.ok_or_else(|| "Missing `--output`.".to_string())?;
+    if buffer_size == 0 || buffer_size >= u32::MAX as usize {
+        return Err(format!("Invalid buffer size: {}.", buffer_size));
+    }
+    if sample_rate == 0 {
+        return Err("The sample rate cannot be 0.".to_string());
+    }
+    if number_of_channels == 0 {
+        return Err("The number of channels cannot be 0.".to_string());
+    }
+    if tail_in_seconds.is_nan() || tail_in_seconds < 0.0 {
+        return Err(format!("Invalid tail: {}.", tail_in_seconds));
+    }
+    Ok(Command::Render(Options {
+        input,
+        midi,
+        track,
+        plugin,
+        parameters,
+        output,
+        buffer_size,
+        sample_rate,
+        number_of_channels,
+        tail_in_seconds,
+    }))
+}
+
+/// Multiplies the audio input by the "Gain" parameter.
+struct Gain {
+    parameters: ParameterStore,
+}
+
+impl Gain {
+    fn new() -> Self {
+        Self {
+            parameters: ParameterStore::new(vec![ParameterInfo::new("Gain", "", 0.0, 4.0, 1.0)]),
+        }
+    }
+}
+
+impl AudioRenderer<f32> for Gain {
+    fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let gain = self.parameters.get(0);
+        for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+            for (input_sample, output_sample) in input.iter().zip(output.iter_mut()) {
+                *output_sample = input_sample * gain;
+            }
+        }
+    }
+}
+
+impl EventHandler<Timed<RawMidiEvent>> for Gain {
+    fn handle_event(&mut self, _event: Timed<RawMidiEvent>) {}
+}
+
+/// A monophonic sine wave synthesizer.
+struct Sine {
+    parameters: ParameterStore,
+    sample_rate: f32,
+    phase: f32,
+    frequency: f32,
+    amplitude: f32,
+    note: Option<u8>,
+    events: Vec<Timed<RawMidiEvent>>,
+}
+
+impl Sine {
+    fn new() -> Self {
+        Self {
+            parameters: ParameterStore::new(vec![ParameterInfo::new("Volume", "", 0.0, 1.0, 0.5)]),
+            sample_rate: DEFAULT_SAMPLE_RATE as f32,
+            phase: 0.0,
+            frequency: 0.0,
+            amplitude: 0.0,
+            note: None,
+            events: Vec::new(),
+        }
+    }
+
+    fn apply(&mut self, event: RawMidiEvent) {
+        let [status, note, velocity] = *event.data();
+        match status & EVENT_TYPE_MASK {
+            NOTE_ON if velocity > 0 => {
+                self.note = Some(note);
+                self.frequency = 440.0 * 2.0_f32.powf((note as f32 - 69.0) / 12.0);
+                self.amplitude = velocity as f32 / 127.0;
+            }
+            NOTE_ON | NOTE_OFF if self.note == Some(note) => {
+                self.note = None;
+                self.amplitude = 0.0;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl AudioHandler for Sine {
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate as f32;
+    }
+}
+
+impl AudioRenderer<f32> for Sine {
+    fn render_buffer(&mut self, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let volume = self.parameters.get(0);
+        let number_of_frames = outputs.first().map(|output| output.len()).unwrap_or(0);
+        let mut events = std::mem::take(&mut self.events);
+        events.sort_by_key(|event| event.time_in_frames);
+        let mut pending_events = events.iter().peekable();
+        for frame in 0..number_of_frames {
+            while let Some(event) =
+                pending_events.next_if(|event| event.time_in_frames as usize <= frame)
+            {
+                self.apply(event.event);
+            }
+            let sample = (2.0 * PI * self.phase).sin() * self.amplitude * volume;
+            self.phase = (self.phase + self.frequency / self.sample_rate).fract();
+            for output in outputs.iter_mut() {
+                output[frame] = sample;
+            }
+        }
+        for event in pending_events {
+            self.apply(event.event);
+        }
+        events.clear();
+        self.events = events;
+    }
+}
+
+impl EventHandler<Timed<RawMidiEvent>> for Sine {
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+        self.events.push(event);
+    }
+}
+
+/// The plugin that is selected on the command line.
+enum Plugin {
+    Gain(Gain),
+    Sine(Sine),
+    #[cfg(feature = "vst-hosting")]
+    Vst(Box<HostedVstPlugin>),
+}
+
+impl Plugin {
+    fn load(name_or_path: &str, buffer_size: usize) -> Result<Self, String> {
+        match name_or_path {
+            "gain" => Ok(Plugin::Gain(Gain::new())),
+            "sine" => Ok(Plugin::Sine(Sine::new())),
+            _ => Self::load_vst(name_or_path, buffer_size),
+        }
+    }
+
+    #[cfg(feature = "vst-hosting")]
+    fn load_vst(path: &str, buffer_size: usize) -> Result<Self, String> {
+        HostedVstPlugin::load(Path::new(path), buffer_size, MAX_NUMBER_OF_EVENTS)
+            .map(|plugin| Plugin::Vst(Box::new(plugin)))
+            .map_err(|e| format!("Failed to load VST plugin `{}`: {:?}", path, e))
+    }
+
+    #[cfg(not(feature = "vst-hosting"))]
+    fn load_vst(name: &str, _buffer_size: usize) -> Result<Self, String> {
+        Err(format!(
+            "Unknown plugin: `{}`. Loading VST plugins requires the \"vst-hosting\" feature.",
+            name
+        ))
+    }
+
+    fn max_number_of_audio_outputs(&self) -> Option<usize> {
+        match self {
+            Plugin::Gain(_) | Plugin::Sine(_) => None,
+            #[cfg(feature = "vst-hosting")]
+            Plugin::Vst(plugin) => Some(rsynth::AudioHandlerMeta::max_number_of_audio_outputs(
+                &**plugin,
+            )),
+        }
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) -> Result<(), String> {
+        let number_of_parameters = match self {
+            Plugin::Gain(Gain { parameters }) => parameters.len(),
+            Plugin::Sine(Sine { parameters, .. }) => parameters.len(),
+            #[cfg(feature = "vst-hosting")]
+            Plugin::Vst(plugin) => plugin.number_of_parameters(),
+        };
+        if index >= number_of_parameters {
+            return Err(format!(
+                "Parameter index {} is out of bounds: the plugin has {} parameters.",
+                index, number_of_parameters
+            ));
+        }
+        match self {
+            Plugin::Gain(Gain { parameters }) => parameters.set(index, value),
+            Plugin::Sine(Sine { parameters, .. }) => parameters.set(index, value),
+            #[cfg(feature = "vst-hosting")]
+            Plugin::Vst(plugin) => plugin.set_parameter(index, value),
+        }
+        Ok(())
+    }
+}
+
+impl AudioHandler for Plugin {
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        match self {
+            Plugin::Gain(_) => {}
+            Plugin::Sine(plugin) => plugin.set_sample_rate(sample_rate),
+            #[cfg(feature = "vst-hosting")]
+            Plugin::Vst(plugin) => plugin.set_sample_rate(sample_rate),
+        }
+    }
+}
+
+impl<Context> ContextualAudioRenderer<f32, Context> for Plugin {
+    fn render_buffer(
+        &mut self,
+        inputs: &[&[f32]],
+        outputs: &mut [&mut [f32]],
+        _context: &mut Context,
+    ) {
+        match self {
+            Plugin::Gain(plugin) => plugin.render_buffer(inputs, outputs),
+            Plugin::Sine(plugin) => plugin.render_buffer(inputs, outputs),
+            #[cfg(feature = "vst-hosting")]
+            Plugin::Vst(plugin) => AudioRenderer::render_buffer(&mut **plugin, inputs, outputs),
+        }
+    }
+}
+
+impl EventHandler<Timed<RawMidiEvent>> for Plugin {
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+        match self {
+            Plugin::Gain(plugin) => plugin.handle_event(event),
+            Plugin::Sine(plugin) => plugin.handle_event(event),
+            #[cfg(feature = "vst-hosting")]
+            Plugin::Vst(plugin) => EventHandler::handle_event(&mut **plugin, event),
+        }
+    }
+}
+
+/// Generates silence of a given length, used when there is no audio input.
+struct Silence {
+    number_of_channels: usize,
+    frames_per_second: u64,
+    remaining_frames: u64,
+}
+
+impl AudioReader<f32> for Silence {
+    type Err = hound::Error;
+
+    fn number_of_channels(&self) -> usize {
+        self.number_of_channels
+    }
+
+    fn frames_per_second(&self) -> u64 {
+        self.frames_per_second
+    }
+
+    fn fill_buffer(&mut self, output: &mut [&mut [f32]]) -> Result<usize, Self::Err> {
+        let buffer_size = output.first().map(|channel| channel.len()).unwrap_or(0);
+        let number_of_frames = cmp::min(buffer_size as u64, self.remaining_frames) as usize;
+        for channel in output.iter_mut() {
+            for sample in channel[..number_of_frames].iter_mut() {
+                *sample = 0.0;
+            }
+        }
+        self.remaining_frames -= number_of_frames as u64;
+        Ok(number_of_frames)
+    }
+}
+
+enum Input<'wr> {
+    Wav(HoundAudioReader<'wr, f32>),
+    Silence(Silence),
+}
+
+impl<'wr> AudioReader<f32> for Input<'wr> {
+    type Err = hound::Error;
+
+    fn number_of_channels(&self) -> usize {
+        match self {
+            Input::Wav(reader) => reader.number_of_channels(),
+            Input::Silence(reader) => reader.number_of_channels(),
+        }
+    }
+
+    fn frames_per_second(&self) -> u64 {
+        match self {
+            Input::Wav(reader) => reader.frames_per_second(),
+            Input::Silence(reader) => reader.frames_per_second(),
+        }
+    }
+
+    fn fill_buffer(&mut self, output: &mut [&mut [f32]]) -> Result<usize, Self::Err> {
+        match self {
+            Input::Wav(reader) => reader.fill_buffer(output),
+            Input::Silence(reader) => reader.fill_buffer(output),
+        }
+    }
+}
+
+/// Prints the progress to `stderr` while writing.
+struct ProgressWriter<W> {
+    inner: W,
+    frames_written: u64,
+    total_frames: u64,
+    last_percentage: Option<u64>,
+}
+
+impl<W> ProgressWriter<W> {
+    fn new(inner: W, total_frames: u64) -> Self {
+        Self {
+            inner,
+            frames_written: 0,
+            total_frames,
+            last_percentage: None,
+        }
+    }
+}
+
+impl<W> AudioWriter<f32> for ProgressWriter<W>
+where
+    W: AudioWriter<f32>,
+{
+    type Err = W::Err;
+
+    fn write_buffer(&mut self, buffer: &[&[f32]]) -> Result<(), Self::Err> {
+        self.inner.write_buffer(buffer)?;
+        self.frames_written += buffer.first().map(|channel| channel.len()).unwrap_or(0) as u64;
+        let percentage = cmp::min(
+            100,
+            self.frames_written * 100 / cmp::max(self.total_frames, 1),
+        );
+        if self.last_percentage != Some(percentage) {
+            self.last_percentage = Some(percentage);
+            let mut stderr = std::io::stderr();
+            let _ = write!(stderr, "\rRendering: {:3}%", percentage);
+            let _ = stderr.flush();
+        }
+        Ok(())
+    }
+}
+
+fn hound_audio_error_message(error: HoundAudioError) -> &'static str {
+    match error {
+        HoundAudioError::UnsupportedAudioFormat => "unsupported audio format",
+    }
+}
+
+fn midi_duration_in_microseconds(smf: &SMF, track: usize) -> u64 {
+    RimdMidiReader::new(smf, track)
+        .map(|event| event.microseconds_since_previous_event)
+        .sum()
+}
+
+fn render(options: Options) -> Result<(), String> {
+    let mut plugin = Plugin::load(&options.plugin, options.buffer_size)?;
+    for &(index, value) in options.parameters.iter() {
+        plugin.set_parameter(index, value)?;
+    }
+
+    let smf = match &options.midi {
+        Some(path) => {
+            let smf = SMF::from_file(Path::new(path))
+                .map_err(|e| format!("Failed to read `{}`: {}", path, e))?;
+            if options.track >= smf.tracks.len() {
+                return Err(format!(
+                    "Track index {} is out of bounds: `{}` has {} tracks.",
+                    options.track,
+                    path,
+                    smf.tracks.len()
+                ));
+            }
+            Some(smf)
+        }
+        None => None,
+    };
+
+    let mut wav_reader = match &options.input {
+        Some(path) => {
+            Some(WavReader::open(path).map_err(|e| format!("Failed to open `{}`: {}", path, e))?)
+        }
+        None => None,
+    };
+    let (input, total_frames) = match &mut wav_reader {
+        Some(reader) => {
+            let total_frames = reader.duration() as u64;
+            let input = HoundAudioReader::new(reader).map_err(|e| {
+                format!(
+                    "Failed to read `{}`: {}",
+                    options.input.as_deref().unwrap_or_default(),
+                    hound_audio_error_message(e)
+                )
+            })?;
+            (Input::Wav(input), total_frames)
+        }
+        None => {
+            let duration_in_microseconds = smf
+                .as_ref()
+                .map(|smf| midi_duration_in_microseconds(smf, options.track))
+                .unwrap_or(0);
+            let duration_in_seconds = duration_in_microseconds as f64
+                / MICROSECONDS_PER_SECOND as f64
+                + options.tail_in_seconds;
+            let total_frames = (duration_in_seconds * options.sample_rate as f64).ceil() as u64;
+            let number_of_channels = plugin
+                .max_number_of_audio_outputs()
+                .filter(|&outputs| outputs > 0)
+                .unwrap_or(options.number_of_channels as usize);
+            let silence = Silence {
+                number_of_channels,
+                frames_per_second: options.sample_rate as u64,
+                remaining_frames: total_frames,
+            };
+            (Input::Silence(silence), total_frames)
+        }
+    };
+    if input.number_of_channels() == 0 || input.number_of_channels() > u16::MAX as usize {
+        return Err(format!(
+            "Unsupported number of channels: {}.",
+            input.number_of_channels()
+        ));
+    }
+
+    plugin.set_sample_rate(input.frames_per_second() as f64);
+
+    let spec = WavSpec {
+        channels: input.number_of_channels() as u16,
+        sample_rate: input.frames_per_second() as u32,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut wav_writer = WavWriter::create(&options.output, spec)
+        .map_err(|e| format!("Failed to create `{}`: {}", options.output, e))?;
+    let output = HoundAudioWriter::new(&mut wav_writer).map_err(|e| {
+        format!(
+            "Failed to write `{}`: {}",
+            options.output,
+            hound_audio_error_message(e)
+        )
+    })?;
+
+    info!(
+        "Rendering {} frames with plugin `{}` to `{}`",
+        total_frames, options.plugin, options.output
+    );
+    let midi_in = smf
+        .as_ref()
+        .map(|smf| RimdMidiReader::new(smf, options.track))
+        .into_iter()
+        .flatten();
+    let result = run(
+        &mut plugin,
+        options.buffer_size,
+        input,
+        ProgressWriter::new(output, total_frames),
+        midi_in,
+        MidiDummy::new(),
+    );
+    eprintln!();
+    match result {
+        Ok(()) => {}
+        Err(CombinedError::AudioInError(e)) => {
+            return Err(format!(
+                "Failed to read `{}`: {}",
+                options.input.as_deref().unwrap_or_default(),
+                e
+            ));
+        }
+        Err(CombinedError::AudioOutError(e)) => {
+            return Err(format!("Failed to write `{}`: {}", options.output, e));
+        }
+        Err(CombinedError::Cancelled) => {
+            return Err("Rendering has been cancelled.".to_string());
+        }
+    }
+    wav_writer
+        .finalize()
+        .map_err(|e| format!("Failed to write `{}`: {}", options.output, e))
+}
+
+fn main() {
+    let result = match parse_arguments(env::args().skip(1)) {
+        Ok(Command::Render(options)) => render(options),
+        Ok(Command::ListPlugins) => {
+            for (name, description) in BUILTIN_PLUGINS.iter() {
+                println!("{:8} {}", name, description);
+            }
+            Ok(())
+        }
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Err(message) => Err(format!("{}\n\n{}", message, USAGE)),
+    };
+    if let Err(message) = result {
+        eprintln!("rsynth-render: {}", message);
+        process::exit(1);
+    }
+}
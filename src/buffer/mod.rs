@@ -31,8 +31,13 @@
 //!                                            ↑
 //!                                            └ buffer
 //! ```
+use crate::ContextualAudioRenderer;
 use num_traits::Zero;
 use std::mem;
+use std::ops::Range;
+use vecstorage::VecStorage;
+
+pub mod simd;
 
 // Alternative name: "packet"?
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -282,6 +287,16 @@ pub fn buffers_as_mut_slice<'a, S>(
     buffers.iter_mut().map(|b| &mut b[0..slice_len]).collect()
 }
 
+/// The number of frames of a buffer with the given inputs and outputs, also when there are
+/// no inputs or no outputs.
+pub fn number_of_frames<S>(inputs: &[&[S]], outputs: &[&mut [S]]) -> usize {
+    inputs
+        .first()
+        .map(|input| input.len())
+        .or_else(|| outputs.first().map(|output| output.len()))
+        .unwrap_or(0)
+}
+
 /// Initialize a slice of buffers to zero.
 // TODO: what we really want is silence (equilibrium).
 pub fn initialize_to_zero<S: num_traits::Zero>(buffers: &mut [&mut [S]]) {
@@ -291,3 +306,162 @@ pub fn initialize_to_zero<S: num_traits::Zero>(buffers: &mut [&mut [S]]) {
         }
     }
 }
+
+/// Create sub-slices of the input and output buffers for a range of frames, e.g. in order to
+/// render the frames between two events separately.
+///
+/// A `BufferRange` keeps the memory that is needed for the slices of the channels,
+/// so that no memory is allocated when creating the sub-slices.
+///
+/// Example
+/// -------
+/// ```
+/// use rsynth::buffer::BufferRange;
+///
+/// let mut buffer_range = BufferRange::new(1, 2);
+/// let input = [1.0, 2.0, 3.0, 4.0];
+/// let mut left = [0.0; 4];
+/// let mut right = [0.0; 4];
+/// buffer_range.with_range(
+///     &[&input],
+///     &mut [&mut left, &mut right],
+///     1..3,
+///     |inputs, outputs| {
+///         for output in outputs.iter_mut() {
+///             output.copy_from_slice(inputs[0]);
+///         }
+///     },
+/// );
+/// assert_eq!(left, [0.0, 2.0, 3.0, 0.0]);
+/// assert_eq!(right, [0.0, 2.0, 3.0, 0.0]);
+/// ```
+pub struct BufferRange<S>
+where
+    S: 'static,
+{
+    inputs: VecStorage<&'static [S]>,
+    outputs: VecStorage<&'static mut [S]>,
+}
+
+impl<S> BufferRange<S>
+where
+    S: 'static,
+{
+    /// Create a new `BufferRange` that does not need to allocate memory as long as the number
+    /// of input channels is at most `max_number_of_inputs` and the number of output channels
+    /// is at most `max_number_of_outputs`.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory and cannot be used in a real-time context.
+    pub fn new(max_number_of_inputs: usize, max_number_of_outputs: usize) -> Self {
+        Self {
+            inputs: VecStorage::with_capacity(max_number_of_inputs),
+            outputs: VecStorage::with_capacity(max_number_of_outputs),
+        }
+    }
+
+    /// Call `function` with the frames in `range` of all `inputs` and `outputs`.
+    ///
+    /// Note about using in a real-time context
+    /// ---------------------------------------
+    /// This method will allocate memory if there are more channels than specified when
+    /// creating the `BufferRange` and cannot be used in a real-time context in that case.
+    ///
+    /// # Panics
+    /// Panics if `range.start > range.end` or if `range.end` is larger than the length of any
+    /// of the channels.
+    pub fn with_range<F, T>(
+        &mut self,
+        inputs: &[&[S]],
+        outputs: &mut [&mut [S]],
+        range: Range<usize>,
+        function: F,
+    ) -> T
+    where
+        F: FnOnce(&[&[S]], &mut [&mut [S]]) -> T,
+    {
+        let mut input_guard = self.inputs.vec_guard();
+        for input in inputs.iter() {
+            input_guard.push(&input[range.clone()]);
+        }
+        let mut output_guard = self.outputs.vec_guard();
+        for output in outputs.iter_mut() {
+            output_guard.push(&mut output[range.clone()]);
+        }
+        function(&input_guard, &mut output_guard)
+    }
+
+    /// Let `renderer` render the frames in `range` of all `inputs` and `outputs`.
+    ///
+    /// See [`with_range`] for more information.
+    ///
+    /// [`with_range`]: #method.with_range
+    pub fn render<R, C>(
+        &mut self,
+        inputs: &[&[S]],
+        outputs: &mut [&mut [S]],
+        range: Range<usize>,
+        renderer: &mut R,
+        context: &mut C,
+    ) where
+        R: ContextualAudioRenderer<S, C>,
+    {
+        self.with_range(inputs, outputs, range, |inputs, outputs| {
+            renderer.render_buffer(inputs, outputs, context)
+        })
+    }
+}
+
+#[test]
+fn buffer_range_works_for_inputs() {
+    let mut buffer_range = BufferRange::new(2, 0);
+    let channel1 = [11, 12, 13, 14];
+    let channel2 = [21, 22, 23, 24];
+    let chunk: &[&[_]] = &[&channel1, &channel2];
+    let mut ranges = Vec::new();
+    for range in [0..0, 0..1, 0..2, 1..2] {
+        buffer_range.with_range(chunk, &mut [], range, |inputs, outputs| {
+            assert!(outputs.is_empty());
+            ranges.push(
+                inputs
+                    .iter()
+                    .map(|input| input.to_vec())
+                    .collect::<Vec<_>>(),
+            );
+        });
+    }
+    assert_eq!(
+        ranges,
+        vec![
+            vec![vec![], vec![]],
+            vec![vec![11], vec![21]],
+            vec![vec![11, 12], vec![21, 22]],
+            vec![vec![12], vec![22]],
+        ]
+    );
+}
+
+#[test]
+fn buffer_range_works_for_outputs() {
+    let mut buffer_range = BufferRange::new(0, 2);
+    let mut channel1 = [11, 12, 13, 14];
+    let mut channel2 = [21, 22, 23, 24];
+    let chunk: &mut [&mut [_]] = &mut [&mut channel1, &mut channel2];
+    for range in [0..0, 0..1, 0..2, 1..2] {
+        let expected_len = range.len();
+        buffer_range.with_range(&[], chunk, range, |inputs, outputs| {
+            assert!(inputs.is_empty());
+            assert_eq!(outputs.len(), 2);
+            for output in outputs.iter_mut() {
+                assert_eq!(output.len(), expected_len);
+                for sample in output.iter_mut() {
+                    *sample += 100;
+                }
+            }
+        });
+    }
+    // Frames 0 and 1 are both in two of the ranges.
+    assert_eq!(channel1, [211, 212, 13, 14]);
+    assert_eq!(channel2, [221, 222, 23, 24]);
+}
@@ -0,0 +1,337 @@
+//! Sample types that contain the samples of several voices, so that these voices can be
+//! processed in lockstep.
+//!
+//! A voice that is generic over the sample type `S` (e.g. with `S: SimdSample`) can be used
+//! with `S = F32x4` to render four voices at once: lane `i` of each sample belongs to voice `i`.
+//! The arithmetic on [`F32x4`] and [`F32x8`] is written lane by lane on aligned arrays,
+//! so that the compiler can translate it to SIMD instructions.
+//!
+//! Because these types implement `num_traits::Zero`, the buffer utilities (e.g.
+//! [`AudioChunk::zero`] and [`initialize_to_zero`]) can be used with them as well.
+//!
+//! Packing and unpacking
+//! =====================
+//! The back-ends work with buffers of scalar samples (e.g. `f32`).
+//! Use [`pack`] to combine the buffers of several voices into one buffer with one voice per lane,
+//! [`unpack`] to split such a buffer again into one buffer per lane and [`mix_lanes`] to mix
+//! all lanes into one buffer of scalar samples.
+//!
+//! Example
+//! -------
+//! ```
+//! use rsynth::buffer::simd::{mix_lanes, F32x4, SimdSample};
+//!
+//! // Four sawtooth voices, rendered in lockstep.
+//! let phase_increment = F32x4([0.01, 0.02, 0.03, 0.04]);
+//! let mut phase = F32x4::splat(0.0);
+//! let mut rendered = [F32x4::splat(0.0); 64];
+//! for sample in rendered.iter_mut() {
+//!     *sample = phase * F32x4::splat(2.0) - F32x4::splat(1.0);
+//!     phase = phase + phase_increment;
+//! }
+//!
+//! let mut output = [0.0_f32; 64];
+//! mix_lanes(&rendered, &mut output);
+//! ```
+//!
+//! [`F32x4`]: ./struct.F32x4.html
+//! [`F32x8`]: ./struct.F32x8.html
+//! [`AudioChunk::zero`]: ../struct.AudioChunk.html#method.zero
+//! [`initialize_to_zero`]: ../fn.initialize_to_zero.html
+//! [`pack`]: ./fn.pack.html
+//! [`unpack`]: ./fn.unpack.html
+//! [`mix_lanes`]: ./fn.mix_lanes.html
+use num_traits::Zero;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// A sample type that consists of a number of "lanes" of scalar samples.
+///
+/// Scalar sample types (`f32` and `f64`) are considered to have one lane.
+pub trait SimdSample:
+    Copy
+    + Zero
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    /// The type of the samples in one lane.
+    type Scalar: Copy + Zero + Add<Output = Self::Scalar>;
+
+    /// The number of lanes.
+    const LANES: usize;
+
+    /// Create a sample with the given value in all lanes.
+    fn splat(value: Self::Scalar) -> Self;
+
+    /// Get the value of the given lane.
+    ///
+    /// Panics
+    /// ------
+    /// Panics if `lane >= Self::LANES`.
+    fn extract(self, lane: usize) -> Self::Scalar;
+
+    /// Set the value of the given lane.
+    ///
+    /// Panics
+    /// ------
+    /// Panics if `lane >= Self::LANES`.
+    fn replace(&mut self, lane: usize, value: Self::Scalar);
+
+    /// The sum of all lanes.
+    fn sum_lanes(self) -> Self::Scalar {
+        let mut sum = Self::Scalar::zero();
+        for lane in 0..Self::LANES {
+            sum = sum + self.extract(lane);
+        }
+        sum
+    }
+}
+
+macro_rules! impl_scalar_simd_sample {
+    ($scalar:ty) => {
+        impl SimdSample for $scalar {
+            type Scalar = $scalar;
+            const LANES: usize = 1;
+
+            fn splat(value: $scalar) -> Self {
+                value
+            }
+
+            fn extract(self, lane: usize) -> $scalar {
+                assert_eq!(lane, 0);
+                self
+            }
+
+            fn replace(&mut self, lane: usize, value: $scalar) {
+                assert_eq!(lane, 0);
+                *self = value;
+            }
+
+            fn sum_lanes(self) -> $scalar {
+                self
+            }
+        }
+    };
+}
+
+impl_scalar_simd_sample!(f32);
+impl_scalar_simd_sample!(f64);
+
+macro_rules! impl_binary_operator {
+    ($name:ident, $trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident, $operator:tt) => {
+        impl $trait for $name {
+            type Output = Self;
+
+            #[inline]
+            fn $method(mut self, other: Self) -> Self {
+                self.$assign_method(other);
+                self
+            }
+        }
+
+        impl $assign_trait for $name {
+            #[inline]
+            fn $assign_method(&mut self, other: Self) {
+                for (lane, other_lane) in self.0.iter_mut().zip(other.0.iter()) {
+                    *lane = *lane $operator *other_lane;
+                }
+            }
+        }
+    };
+}
+
+macro_rules! simd_type {
+    ($(#[$attribute:meta])* $name:ident, $lanes:expr) => {
+        $(#[$attribute])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq)]
+        pub struct $name(pub [f32; $lanes]);
+
+        impl SimdSample for $name {
+            type Scalar = f32;
+            const LANES: usize = $lanes;
+
+            #[inline]
+            fn splat(value: f32) -> Self {
+                $name([value; $lanes])
+            }
+
+            #[inline]
+            fn extract(self, lane: usize) -> f32 {
+                self.0[lane]
+            }
+
+            #[inline]
+            fn replace(&mut self, lane: usize, value: f32) {
+                self.0[lane] = value;
+            }
+
+            #[inline]
+            fn sum_lanes(self) -> f32 {
+                self.0.iter().sum()
+            }
+        }
+
+        impl Zero for $name {
+            #[inline]
+            fn zero() -> Self {
+                Self::splat(0.0)
+            }
+
+            fn is_zero(&self) -> bool {
+                self.0.iter().all(|lane| *lane == 0.0)
+            }
+        }
+
+        impl From<f32> for $name {
+            #[inline]
+            fn from(value: f32) -> Self {
+                Self::splat(value)
+            }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+
+            #[inline]
+            fn neg(mut self) -> Self {
+                for lane in self.0.iter_mut() {
+                    *lane = -*lane;
+                }
+                self
+            }
+        }
+
+        impl_binary_operator!($name, Add, add, AddAssign, add_assign, +);
+        impl_binary_operator!($name, Sub, sub, SubAssign, sub_assign, -);
+        impl_binary_operator!($name, Mul, mul, MulAssign, mul_assign, *);
+        impl_binary_operator!($name, Div, div, DivAssign, div_assign, /);
+    };
+}
+
+simd_type!(
+    /// Four `f32` samples that are processed in lockstep.
+    #[repr(C, align(16))]
+    F32x4,
+    4
+);
+
+simd_type!(
+    /// Eight `f32` samples that are processed in lockstep.
+    #[repr(C, align(32))]
+    F32x8,
+    8
+);
+
+/// Pack the buffers in `inputs` into `output`: `inputs[i]` is written to lane `i`.
+/// Lanes for which there is no input are set to zero.
+///
+/// Panics
+/// ------
+/// Panics if there are more inputs than lanes or if the length of an input differs from
+/// the length of the output.
+pub fn pack<V>(inputs: &[&[V::Scalar]], output: &mut [V])
+where
+    V: SimdSample,
+{
+    assert!(inputs.len() <= V::LANES);
+    for input in inputs.iter() {
+        assert_eq!(input.len(), output.len());
+    }
+    for (index, sample) in output.iter_mut().enumerate() {
+        let mut packed = V::zero();
+        for (lane, input) in inputs.iter().enumerate() {
+            packed.replace(lane, input[index]);
+        }
+        *sample = packed;
+    }
+}
+
+/// Unpack `input` into the buffers in `outputs`: lane `i` is written to `outputs[i]`.
+/// Lanes for which there is no output are ignored.
+///
+/// Panics
+/// ------
+/// Panics if there are more outputs than lanes or if the length of an output differs from
+/// the length of the input.
+pub fn unpack<V>(input: &[V], outputs: &mut [&mut [V::Scalar]])
+where
+    V: SimdSample,
+{
+    assert!(outputs.len() <= V::LANES);
+    for output in outputs.iter() {
+        assert_eq!(output.len(), input.len());
+    }
+    for (lane, output) in outputs.iter_mut().enumerate() {
+        for (output_sample, input_sample) in output.iter_mut().zip(input.iter()) {
+            *output_sample = input_sample.extract(lane);
+        }
+    }
+}
+
+/// Mix all lanes of `input` and add the result to `output`.
+///
+/// Panics
+/// ------
+/// Panics if the length of `output` differs from the length of `input`.
+pub fn mix_lanes<V>(input: &[V], output: &mut [V::Scalar])
+where
+    V: SimdSample,
+{
+    assert_eq!(input.len(), output.len());
+    for (output_sample, input_sample) in output.iter_mut().zip(input.iter()) {
+        *output_sample = *output_sample + input_sample.sum_lanes();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{initialize_to_zero, AudioChunk};
+
+    #[test]
+    fn arithmetic_is_applied_per_lane() {
+        let a = F32x4([1.0, 2.0, 3.0, 4.0]);
+        let b = F32x4::splat(2.0);
+        assert_eq!(a + b, F32x4([3.0, 4.0, 5.0, 6.0]));
+        assert_eq!(a - b, F32x4([-1.0, 0.0, 1.0, 2.0]));
+        assert_eq!(a * b, F32x4([2.0, 4.0, 6.0, 8.0]));
+        assert_eq!(a / b, F32x4([0.5, 1.0, 1.5, 2.0]));
+        assert_eq!(-a, F32x4([-1.0, -2.0, -3.0, -4.0]));
+        assert_eq!(a.sum_lanes(), 10.0);
+    }
+
+    #[test]
+    fn pack_and_unpack_round_trip() {
+        let voice0 = [1.0, 2.0, 3.0];
+        let voice1 = [4.0, 5.0, 6.0];
+        let mut packed = [F32x8::splat(7.0); 3];
+        pack(&[&voice0, &voice1], &mut packed);
+        assert_eq!(packed[1].0, [2.0, 5.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+
+        let mut output0 = [0.0; 3];
+        let mut output1 = [0.0; 3];
+        unpack(&packed, &mut [&mut output0, &mut output1]);
+        assert_eq!(output0, voice0);
+        assert_eq!(output1, voice1);
+    }
+
+    #[test]
+    fn mix_lanes_adds_all_lanes_to_the_output() {
+        let input = [F32x4([1.0, 2.0, 3.0, 4.0]), F32x4::splat(0.5)];
+        let mut output = [1.0, 1.0];
+        mix_lanes(&input, &mut output);
+        assert_eq!(output, [11.0, 3.0]);
+    }
+
+    #[test]
+    fn buffer_utilities_work_with_simd_samples() {
+        let mut chunk = AudioChunk::<F32x4>::zero(2, 3);
+        assert!(chunk.channels()[1][2].is_zero());
+        let mut buffers = chunk.as_mut_slices();
+        buffers[0][1] = F32x4::splat(1.0);
+        initialize_to_zero(&mut buffers);
+        assert!(buffers[0][1].is_zero());
+    }
+}
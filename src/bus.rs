@@ -0,0 +1,447 @@
+//! Render audio with the inputs and outputs grouped into named buses.
+//!
+//! Back-ends pass the audio inputs and outputs as flat slices of channels. When a plugin has
+//! more than one group of channels (e.g. a stereo main input and a stereo sidechain input),
+//! the plugin has to know which channels belong to which group.
+//! With this module, the plugin looks up its buses by name instead.
+//!
+//! The buses are defined by the meta-data of the audio ports: every port has the name of the
+//! bus that it belongs to (see the [`BusName`] trait and the [`BusPort`] struct) and
+//! consecutive ports with the same bus name form one bus.
+//! Common bus names are [`MAIN`], [`SIDECHAIN`] and `"aux1"`, `"aux2"`, ...
+//!
+//! Implement the [`BusAudioRenderer`] trait (or the [`ContextualBusAudioRenderer`] trait) and
+//! wrap the plugin in a [`BusRenderer`], which implements the `AudioRenderer` and
+//! `ContextualAudioRenderer` traits, so that it can be used with every back-end.
+//!
+//! Example
+//! -------
+//! ```
+//! use rsynth::bus::{BusAudioRenderer, BusRenderer, InputBuses, OutputBuses, MAIN, SIDECHAIN};
+//! use rsynth::meta::{BusPort, InOut, Meta, MetaData};
+//! use rsynth::AudioRenderer;
+//!
+//! // Ducks the main input when the sidechain is loud.
+//! struct Ducker {
+//!     meta: MetaData<&'static str, BusPort<&'static str>, &'static str>,
+//! }
+//!
+//! impl Meta for Ducker {
+//!     type MetaData = MetaData<&'static str, BusPort<&'static str>, &'static str>;
+//!     fn meta(&self) -> &Self::MetaData {
+//!         &self.meta
+//!     }
+//! }
+//!
+//! impl BusAudioRenderer<f32> for Ducker {
+//!     fn render_buses(&mut self, inputs: &InputBuses<f32>, outputs: &mut OutputBuses<f32>) {
+//!         let main = inputs.get(MAIN).unwrap_or(&[]);
+//!         let sidechain = inputs.get(SIDECHAIN).unwrap_or(&[]);
+//!         if let Some(output) = outputs.get_mut(MAIN) {
+//!             for (output_channel, input_channel) in output.iter_mut().zip(main.iter()) {
+//!                 for (frame, sample) in output_channel.iter_mut().enumerate() {
+//!                     let level = sidechain.iter().map(|c| c[frame].abs()).fold(0.0, f32::max);
+//!                     *sample = input_channel[frame] * (1.0 - level.min(1.0));
+//!                 }
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! let mut ducker = BusRenderer::new(Ducker {
+//!     meta: MetaData {
+//!         general_meta: "ducker",
+//!         audio_port_meta: InOut {
+//!             inputs: vec![BusPort::new("in", MAIN), BusPort::new("sidechain in", SIDECHAIN)],
+//!             outputs: vec![BusPort::new("out", MAIN)],
+//!         },
+//!         midi_port_meta: InOut {
+//!             inputs: vec![],
+//!             outputs: vec![],
+//!         },
+//!     },
+//! });
+//! let input = [1.0, 1.0];
+//! let sidechain = [0.0, 0.5];
+//! let mut output = [0.0; 2];
+//! ducker.render_buffer(&[&input[..], &sidechain[..]], &mut [&mut output[..]]);
+//! assert_eq!(output, [1.0, 0.5]);
+//! ```
+//!
+//! [`BusName`]: ../meta/trait.BusName.html
+//! [`BusPort`]: ../meta/struct.BusPort.html
+//! [`MAIN`]: ./constant.MAIN.html
+//! [`SIDECHAIN`]: ./constant.SIDECHAIN.html
+//! [`BusAudioRenderer`]: ./trait.BusAudioRenderer.html
+//! [`ContextualBusAudioRenderer`]: ./trait.ContextualBusAudioRenderer.html
+//! [`BusRenderer`]: ./struct.BusRenderer.html
+use crate::event::{ContextualEventHandler, EventHandler};
+use crate::meta::{AudioPort, BusName, Meta, Port};
+use crate::{AudioHandler, AudioRenderer, ContextualAudioRenderer, Latency, Lifecycle};
+use std::ops::Range;
+
+/// The name of the main bus.
+pub const MAIN: &str = "main";
+/// The name of the sidechain bus.
+pub const SIDECHAIN: &str = "sidechain";
+
+/// The names of the buses and the indices of their channels.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusLayout {
+    inputs: Vec<(String, Range<usize>)>,
+    outputs: Vec<(String, Range<usize>)>,
+}
+
+fn group_by_bus<P: BusName>(ports: &[P]) -> Vec<(String, Range<usize>)> {
+    let mut buses: Vec<(String, Range<usize>)> = Vec::new();
+    for (index, port) in ports.iter().enumerate() {
+        match buses.last_mut() {
+            Some((name, range)) if name == port.bus_name() => range.end = index + 1,
+            _ => buses.push((port.bus_name().to_string(), index..index + 1)),
+        }
+    }
+    buses
+}
+
+fn find<'a>(buses: &'a [(String, Range<usize>)], name: &str) -> Option<&'a Range<usize>> {
+    buses
+        .iter()
+        .find(|(bus_name, _)| bus_name == name)
+        .map(|(_, range)| range)
+}
+
+// Limit the range to the number of channels that the back-end actually passes.
+fn clamp(range: &Range<usize>, number_of_channels: usize) -> Range<usize> {
+    let end = std::cmp::min(range.end, number_of_channels);
+    std::cmp::min(range.start, end)..end
+}
+
+impl BusLayout {
+    /// Determine the buses from the meta-data of the audio ports.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn from_meta<M>(meta: &M) -> Self
+    where
+        M: Port<AudioPort>,
+        M::PortData: BusName,
+    {
+        BusLayout {
+            inputs: group_by_bus(meta.in_ports()),
+            outputs: group_by_bus(meta.out_ports()),
+        }
+    }
+
+    /// The indices of the input channels of the bus with the given name.
+    pub fn input_channels(&self, name: &str) -> Option<Range<usize>> {
+        find(&self.inputs, name).cloned()
+    }
+
+    /// The indices of the output channels of the bus with the given name.
+    pub fn output_channels(&self, name: &str) -> Option<Range<usize>> {
+        find(&self.outputs, name).cloned()
+    }
+
+    /// The names of the input buses, in the order of their channels.
+    pub fn input_buses(&self) -> impl Iterator<Item = &str> {
+        self.inputs.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// The names of the output buses, in the order of their channels.
+    pub fn output_buses(&self) -> impl Iterator<Item = &str> {
+        self.outputs.iter().map(|(name, _)| name.as_str())
+    }
+}
+
+/// The audio inputs, grouped by bus.
+pub struct InputBuses<'a, 'b, S> {
+    layout: &'a BusLayout,
+    channels: &'a [&'b [S]],
+}
+
+impl<'a, 'b, S> InputBuses<'a, 'b, S> {
+    pub fn new(layout: &'a BusLayout, channels: &'a [&'b [S]]) -> Self {
+        InputBuses { layout, channels }
+    }
+
+    /// The channels of the bus with the given name, or `None` if there is no such bus.
+    /// When the back-end passes fewer channels than defined in the meta-data, the bus
+    /// may have fewer channels (or none).
+    pub fn get(&self, name: &str) -> Option<&'a [&'b [S]]> {
+        find(&self.layout.inputs, name)
+            .map(|range| &self.channels[clamp(range, self.channels.len())])
+    }
+
+    /// All channels, as passed by the back-end.
+    pub fn all(&self) -> &'a [&'b [S]] {
+        self.channels
+    }
+}
+
+/// The audio outputs, grouped by bus.
+pub struct OutputBuses<'a, 'b, 'c, S> {
+    layout: &'a BusLayout,
+    channels: &'b mut [&'c mut [S]],
+}
+
+impl<'a, 'b, 'c, S> OutputBuses<'a, 'b, 'c, S> {
+    pub fn new(layout: &'a BusLayout, channels: &'b mut [&'c mut [S]]) -> Self {
+        OutputBuses { layout, channels }
+    }
+
+    /// The channels of the bus with the given name, or `None` if there is no such bus.
+    /// When the back-end passes fewer channels than defined in the meta-data, the bus
+    /// may have fewer channels (or none).
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut [&'c mut [S]]> {
+        let number_of_channels = self.channels.len();
+        let range = find(&self.layout.outputs, name)?;
+        Some(&mut self.channels[clamp(range, number_of_channels)])
+    }
+
+    /// All channels, as passed by the back-end.
+    pub fn all_mut(&mut self) -> &mut [&'c mut [S]] {
+        &mut *self.channels
+    }
+}
+
+/// Defines how audio is rendered, with the inputs and outputs grouped into buses.
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub trait BusAudioRenderer<S> {
+    /// Render one buffer; see [`AudioRenderer::render_buffer`] for more information.
+    ///
+    /// [`AudioRenderer::render_buffer`]: ../trait.AudioRenderer.html#tymethod.render_buffer
+    fn render_buses(&mut self, inputs: &InputBuses<S>, outputs: &mut OutputBuses<S>);
+}
+
+/// Defines how audio is rendered, with the inputs and outputs grouped into buses,
+/// similar to the [`BusAudioRenderer`] trait.
+/// The extra parameter `context` can be used by the back-end to provide extra information.
+///
+/// [`BusAudioRenderer`]: ./trait.BusAudioRenderer.html
+pub trait ContextualBusAudioRenderer<S, Context> {
+    /// Render one buffer; see [`AudioRenderer::render_buffer`] for more information.
+    ///
+    /// [`AudioRenderer::render_buffer`]: ../trait.AudioRenderer.html#tymethod.render_buffer
+    fn render_buses(
+        &mut self,
+        inputs: &InputBuses<S>,
+        outputs: &mut OutputBuses<S>,
+        context: &mut Context,
+    );
+}
+
+/// Wraps a [`BusAudioRenderer`] or a [`ContextualBusAudioRenderer`] so that it can be
+/// used as an `AudioRenderer` or a `ContextualAudioRenderer`.
+///
+/// The events, the sample rate, the latency and the meta-data are forwarded to the wrapped
+/// renderer.
+/// See the [module level documentation] for more information.
+///
+/// [`BusAudioRenderer`]: ./trait.BusAudioRenderer.html
+/// [`ContextualBusAudioRenderer`]: ./trait.ContextualBusAudioRenderer.html
+/// [module level documentation]: ./index.html
+pub struct BusRenderer<R> {
+    inner: R,
+    layout: BusLayout,
+}
+
+impl<R> BusRenderer<R> {
+    /// Wrap the renderer; the buses are determined from its meta-data.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(inner: R) -> Self
+    where
+        R: Meta,
+        R::MetaData: Port<AudioPort>,
+        <R::MetaData as Port<AudioPort>>::PortData: BusName,
+    {
+        let layout = BusLayout::from_meta(inner.meta());
+        BusRenderer { inner, layout }
+    }
+
+    pub fn layout(&self) -> &BusLayout {
+        &self.layout
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R, S> AudioRenderer<S> for BusRenderer<R>
+where
+    R: BusAudioRenderer<S>,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        self.inner.render_buses(
+            &InputBuses::new(&self.layout, inputs),
+            &mut OutputBuses::new(&self.layout, outputs),
+        );
+    }
+}
+
+impl<R, S, Context> ContextualAudioRenderer<S, Context> for BusRenderer<R>
+where
+    R: ContextualBusAudioRenderer<S, Context>,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], context: &mut Context) {
+        self.inner.render_buses(
+            &InputBuses::new(&self.layout, inputs),
+            &mut OutputBuses::new(&self.layout, outputs),
+            context,
+        );
+    }
+}
+
+impl<R, E> EventHandler<E> for BusRenderer<R>
+where
+    R: EventHandler<E>,
+{
+    fn handle_event(&mut self, event: E) {
+        self.inner.handle_event(event);
+    }
+
+    fn handle_events(&mut self, events: &[E])
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events);
+    }
+}
+
+impl<R, E, Context> ContextualEventHandler<E, Context> for BusRenderer<R>
+where
+    R: ContextualEventHandler<E, Context>,
+{
+    fn handle_event(&mut self, event: E, context: &mut Context) {
+        self.inner.handle_event(event, context);
+    }
+
+    fn handle_events(&mut self, events: &[E], context: &mut Context)
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events, context);
+    }
+}
+
+impl<R> AudioHandler for BusRenderer<R>
+where
+    R: AudioHandler,
+{
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.inner.set_sample_rate(sample_rate);
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.inner.set_max_buffer_size(max_buffer_size);
+    }
+}
+
+impl<R> Latency for BusRenderer<R>
+where
+    R: Latency,
+{
+    fn latency_in_frames(&self) -> usize {
+        self.inner.latency_in_frames()
+    }
+}
+
+impl<R> Lifecycle for BusRenderer<R>
+where
+    R: Lifecycle,
+{
+    fn on_resume(&mut self) {
+        self.inner.on_resume();
+    }
+
+    fn on_suspend(&mut self) {
+        self.inner.on_suspend();
+    }
+}
+
+impl<R> Meta for BusRenderer<R>
+where
+    R: Meta,
+{
+    type MetaData = R::MetaData;
+
+    fn meta(&self) -> &Self::MetaData {
+        self.inner.meta()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::{BusPort, InOut, MetaData};
+
+    fn meta_data(inputs: &[&'static str]) -> MetaData<&'static str, BusPort<&'static str>, ()> {
+        MetaData {
+            general_meta: "test",
+            audio_port_meta: InOut {
+                inputs: inputs
+                    .iter()
+                    .map(|bus| BusPort::new("port", *bus))
+                    .collect(),
+                outputs: vec![BusPort::new("out", MAIN)],
+            },
+            midi_port_meta: InOut {
+                inputs: vec![],
+                outputs: vec![],
+            },
+        }
+    }
+
+    #[test]
+    fn consecutive_ports_with_the_same_bus_form_one_bus() {
+        let layout = BusLayout::from_meta(&meta_data(&[MAIN, MAIN, SIDECHAIN, "aux1", "aux1"]));
+        assert_eq!(layout.input_channels(MAIN), Some(0..2));
+        assert_eq!(layout.input_channels(SIDECHAIN), Some(2..3));
+        assert_eq!(layout.input_channels("aux1"), Some(3..5));
+        assert_eq!(layout.input_channels("aux2"), None);
+        assert_eq!(
+            layout.input_buses().collect::<Vec<_>>(),
+            vec![MAIN, SIDECHAIN, "aux1"]
+        );
+        assert_eq!(layout.output_channels(MAIN), Some(0..1));
+    }
+
+    #[test]
+    fn buses_are_limited_to_the_channels_of_the_back_end() {
+        let layout = BusLayout::from_meta(&meta_data(&[MAIN, MAIN, SIDECHAIN, SIDECHAIN]));
+        let first = [1.0];
+        let second = [2.0];
+        let third = [3.0];
+        let channels = [&first[..], &second[..], &third[..]];
+        let inputs = InputBuses::new(&layout, &channels);
+        assert_eq!(inputs.get(MAIN), Some(&channels[0..2]));
+        assert_eq!(inputs.get(SIDECHAIN), Some(&channels[2..3]));
+        let inputs = InputBuses::new(&layout, &channels[..1]);
+        assert_eq!(inputs.get(SIDECHAIN).map(|bus| bus.len()), Some(0));
+    }
+
+    #[test]
+    fn output_buses_can_be_written() {
+        let layout = BusLayout::from_meta(&meta_data(&[MAIN]));
+        let mut output = [0.0_f32; 2];
+        let mut channels = [&mut output[..]];
+        let mut outputs = OutputBuses::new(&layout, &mut channels);
+        outputs.get_mut(MAIN).expect("main bus")[0][1] = 1.0;
+        assert!(outputs.get_mut(SIDECHAIN).is_none());
+        assert_eq!(output, [0.0, 1.0]);
+    }
+}
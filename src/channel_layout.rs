@@ -0,0 +1,394 @@
+//! Describe the channel layout (mono, stereo, surround, ambisonics, ...) of the audio ports.
+//!
+//! A [`ChannelLayout`] defines the number of channels and the [`Speaker`] that every channel
+//! is meant for. Back-ends use the channel layout to inform the host: the VST back-end
+//! reports the corresponding speaker arrangement and the JACK back-end gives every port an
+//! alias with the short name of the speaker (e.g. `"out LFE"`).
+//!
+//! The channel layout of the audio ports is defined by the meta-data, see the
+//! `in_channel_layout` and `out_channel_layout` methods of the [`Port`] trait and the
+//! [`WithChannelLayout`] struct. When the meta-data does not define a channel layout, it is
+//! derived from the number of ports with [`ChannelLayout::for_number_of_channels`].
+//!
+//! A plugin that supports several channel layouts (e.g. a surround reverb) implements
+//! [`ChannelLayoutNegotiation`], so that the host can propose the layouts of its tracks.
+//!
+//! Use the [`DownMixer`] to convert audio from one channel layout to another, e.g. from 5.1
+//! surround to stereo.
+//!
+//! Example
+//! -------
+//! ```
+//! use rsynth::channel_layout::{ChannelLayout, Speaker};
+//!
+//! let layout = ChannelLayout::Surround51;
+//! assert_eq!(layout.number_of_channels(), 6);
+//! assert_eq!(layout.speaker(3), Some(Speaker::Lfe));
+//! assert_eq!(layout.port_name("out", 3), "out LFE");
+//! assert_eq!(ChannelLayout::Ambisonic { order: 1 }.number_of_channels(), 4);
+//! ```
+//!
+//! [`ChannelLayout`]: ./enum.ChannelLayout.html
+//! [`ChannelLayout::for_number_of_channels`]: ./enum.ChannelLayout.html#method.for_number_of_channels
+//! [`Speaker`]: ./enum.Speaker.html
+//! [`Port`]: ../meta/trait.Port.html
+//! [`WithChannelLayout`]: ../meta/struct.WithChannelLayout.html
+//! [`DownMixer`]: ../utilities/channel_routing/struct.DownMixer.html
+//! [`ChannelLayoutNegotiation`]: ./trait.ChannelLayoutNegotiation.html
+use std::f64::consts::FRAC_1_SQRT_2;
+use std::fmt::{self, Display, Formatter};
+
+/// The speaker that a channel is meant for.
+///
+/// The `Display` implementation gives the short name of the speaker, e.g. `"L"` or `"LFE"`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Speaker {
+    Left,
+    Right,
+    Center,
+    /// Low frequency effects.
+    Lfe,
+    /// Left surround, behind the listener.
+    LeftSurround,
+    /// Right surround, behind the listener.
+    RightSurround,
+    /// Left side, next to the listener.
+    LeftSide,
+    /// Right side, next to the listener.
+    RightSide,
+    /// An ambisonic component, with its index in the "ambisonic channel number" (ACN) order.
+    Ambisonic(u16),
+}
+
+impl Display for Speaker {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Speaker::Left => write!(f, "L"),
+            Speaker::Right => write!(f, "R"),
+            Speaker::Center => write!(f, "C"),
+            Speaker::Lfe => write!(f, "LFE"),
+            Speaker::LeftSurround => write!(f, "Ls"),
+            Speaker::RightSurround => write!(f, "Rs"),
+            Speaker::LeftSide => write!(f, "Sl"),
+            Speaker::RightSide => write!(f, "Sr"),
+            Speaker::Ambisonic(index) => write!(f, "ACN{}", index),
+        }
+    }
+}
+
+const MONO: [Speaker; 1] = [Speaker::Center];
+const STEREO: [Speaker; 2] = [Speaker::Left, Speaker::Right];
+const LCR: [Speaker; 3] = [Speaker::Left, Speaker::Right, Speaker::Center];
+const QUAD: [Speaker; 4] = [
+    Speaker::Left,
+    Speaker::Right,
+    Speaker::LeftSurround,
+    Speaker::RightSurround,
+];
+const SURROUND_50: [Speaker; 5] = [
+    Speaker::Left,
+    Speaker::Right,
+    Speaker::Center,
+    Speaker::LeftSurround,
+    Speaker::RightSurround,
+];
+const SURROUND_51: [Speaker; 6] = [
+    Speaker::Left,
+    Speaker::Right,
+    Speaker::Center,
+    Speaker::Lfe,
+    Speaker::LeftSurround,
+    Speaker::RightSurround,
+];
+const SURROUND_71: [Speaker; 8] = [
+    Speaker::Left,
+    Speaker::Right,
+    Speaker::Center,
+    Speaker::Lfe,
+    Speaker::LeftSurround,
+    Speaker::RightSurround,
+    Speaker::LeftSide,
+    Speaker::RightSide,
+];
+
+/// The layout of a group of audio channels.
+///
+/// The channels of the surround layouts are in the order that is also used by WAV files and
+/// by VST: left, right, center, LFE, surround and side channels.
+/// The channels of the [`Ambisonic`] layout are in ACN order.
+///
+/// [`Ambisonic`]: #variant.Ambisonic
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChannelLayout {
+    /// One channel, for the center speaker.
+    Mono,
+    /// Left and right.
+    Stereo,
+    /// Left, right and center.
+    Lcr,
+    /// Left, right, left surround and right surround.
+    Quad,
+    /// Left, right, center, left surround and right surround.
+    Surround50,
+    /// Left, right, center, LFE, left surround and right surround.
+    Surround51,
+    /// Left, right, center, LFE, left surround, right surround, left side and right side.
+    Surround71,
+    /// Full-sphere ambisonics of the given order, with `(order + 1)²` channels.
+    Ambisonic { order: u8 },
+    /// The given number of channels that are not meant for a particular speaker.
+    Discrete(usize),
+}
+
+impl ChannelLayout {
+    /// The most common layout with the given number of channels: mono, stereo, 5.1 or 7.1
+    /// surround. Other numbers of channels are ambiguous (e.g. four channels can be
+    /// quadraphonic or first order ambisonics) and give a [`Discrete`] layout.
+    ///
+    /// [`Discrete`]: #variant.Discrete
+    pub fn for_number_of_channels(number_of_channels: usize) -> Self {
+        match number_of_channels {
+            1 => ChannelLayout::Mono,
+            2 => ChannelLayout::Stereo,
+            6 => ChannelLayout::Surround51,
+            8 => ChannelLayout::Surround71,
+            n => ChannelLayout::Discrete(n),
+        }
+    }
+
+    pub fn number_of_channels(&self) -> usize {
+        match self {
+            ChannelLayout::Ambisonic { order } => (*order as usize + 1) * (*order as usize + 1),
+            ChannelLayout::Discrete(number_of_channels) => *number_of_channels,
+            _ => self.fixed_speakers().len(),
+        }
+    }
+
+    /// The speaker of the channel with the given index, or `None` if the index is out of range
+    /// or when the channel is not meant for a particular speaker.
+    pub fn speaker(&self, index: usize) -> Option<Speaker> {
+        match self {
+            ChannelLayout::Ambisonic { .. } if index < self.number_of_channels() => {
+                Some(Speaker::Ambisonic(index as u16))
+            }
+            _ => self.fixed_speakers().get(index).copied(),
+        }
+    }
+
+    /// The index of the channel for the given speaker, if the layout has such a channel.
+    pub fn index_of(&self, speaker: Speaker) -> Option<usize> {
+        (0..self.number_of_channels()).find(|index| self.speaker(*index) == Some(speaker))
+    }
+
+    /// The name of the port for the channel with the given index: the `prefix`, followed by the
+    /// short name of the speaker (e.g. `"out L"`), or by the index when the channel is not
+    /// meant for a particular speaker (e.g. `"out 3"`).
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn port_name(&self, prefix: &str, index: usize) -> String {
+        match self.speaker(index) {
+            Some(speaker) => format!("{} {}", prefix, speaker),
+            None => format!("{} {}", prefix, index),
+        }
+    }
+
+    /// The gains to convert audio in this layout to the `target` layout: the returned matrix
+    /// has one row for every channel of `target` and the row has the gain of every channel of
+    /// this layout.
+    ///
+    /// Speakers that are in both layouts are copied unchanged. The other speakers are mixed
+    /// into the nearest speakers of the target layout, with the coefficients of ITU-R BS.775
+    /// (-3 dB for center and surround channels). The LFE channel is dropped when the target
+    /// layout has no LFE channel.
+    /// An ambisonic layout is converted to an ambisonic layout of a lower order by dropping
+    /// the components of the higher orders. In all other cases where one of the layouts has
+    /// no speakers (e.g. discrete channels), channel `i` is copied to channel `i`.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn down_mix_matrix(&self, target: ChannelLayout) -> Vec<Vec<f64>> {
+        let mut matrix = vec![vec![0.0; self.number_of_channels()]; target.number_of_channels()];
+        let has_speakers = |layout: &ChannelLayout| !layout.fixed_speakers().is_empty();
+        if has_speakers(self) && has_speakers(&target) {
+            for input in 0..self.number_of_channels() {
+                if let Some(speaker) = self.speaker(input) {
+                    mix_into(&mut matrix, input, speaker, 1.0, target, 0);
+                }
+            }
+        } else {
+            for (index, row) in matrix.iter_mut().enumerate() {
+                if let Some(gain) = row.get_mut(index) {
+                    *gain = 1.0;
+                }
+            }
+        }
+        matrix
+    }
+
+    // The speakers of the layouts that have a fixed number of channels.
+    fn fixed_speakers(&self) -> &'static [Speaker] {
+        match self {
+            ChannelLayout::Mono => &MONO,
+            ChannelLayout::Stereo => &STEREO,
+            ChannelLayout::Lcr => &LCR,
+            ChannelLayout::Quad => &QUAD,
+            ChannelLayout::Surround50 => &SURROUND_50,
+            ChannelLayout::Surround51 => &SURROUND_51,
+            ChannelLayout::Surround71 => &SURROUND_71,
+            ChannelLayout::Ambisonic { .. } | ChannelLayout::Discrete(_) => &[],
+        }
+    }
+}
+
+impl Display for ChannelLayout {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ChannelLayout::Mono => write!(f, "mono"),
+            ChannelLayout::Stereo => write!(f, "stereo"),
+            ChannelLayout::Lcr => write!(f, "LCR"),
+            ChannelLayout::Quad => write!(f, "quad"),
+            ChannelLayout::Surround50 => write!(f, "5.0"),
+            ChannelLayout::Surround51 => write!(f, "5.1"),
+            ChannelLayout::Surround71 => write!(f, "7.1"),
+            ChannelLayout::Ambisonic { order } => write!(f, "ambisonic order {}", order),
+            ChannelLayout::Discrete(number_of_channels) => {
+                write!(f, "{} discrete channels", number_of_channels)
+            }
+        }
+    }
+}
+
+// The speakers that a speaker is mixed into when the target layout does not have it.
+fn fallback(speaker: Speaker) -> &'static [(Speaker, f64)] {
+    match speaker {
+        Speaker::Left | Speaker::Right => &[(Speaker::Center, FRAC_1_SQRT_2)],
+        Speaker::Center => &[
+            (Speaker::Left, FRAC_1_SQRT_2),
+            (Speaker::Right, FRAC_1_SQRT_2),
+        ],
+        Speaker::LeftSide => &[(Speaker::LeftSurround, 1.0)],
+        Speaker::RightSide => &[(Speaker::RightSurround, 1.0)],
+        Speaker::LeftSurround => &[(Speaker::Left, FRAC_1_SQRT_2)],
+        Speaker::RightSurround => &[(Speaker::Right, FRAC_1_SQRT_2)],
+        Speaker::Lfe | Speaker::Ambisonic(_) => &[],
+    }
+}
+
+fn mix_into(
+    matrix: &mut [Vec<f64>],
+    input: usize,
+    speaker: Speaker,
+    gain: f64,
+    target: ChannelLayout,
+    depth: usize,
+) {
+    if let Some(output) = target.index_of(speaker) {
+        matrix[output][input] += gain;
+    } else if depth < 4 {
+        for (nearest, nearest_gain) in fallback(speaker) {
+            mix_into(
+                matrix,
+                input,
+                *nearest,
+                gain * nearest_gain,
+                target,
+                depth + 1,
+            );
+        }
+    }
+}
+
+/// A renderer that can switch to the channel layouts that are proposed by the host.
+///
+/// After a successful negotiation, the `audio_input_layout` and `audio_output_layout` methods
+/// of the [`CommonAudioPortMeta`] trait should return the new layouts.
+/// Back-ends only propose layouts that do not have more channels than the maximum number of
+/// audio inputs and outputs of the renderer.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// This method is not called from the audio thread, so it may allocate memory.
+///
+/// [`CommonAudioPortMeta`]: ../trait.CommonAudioPortMeta.html
+pub trait ChannelLayoutNegotiation {
+    /// Switch to the given channel layouts of the inputs and the outputs.
+    /// Return `false` and keep the current layouts if the renderer does not support them.
+    fn set_channel_layouts(&mut self, inputs: ChannelLayout, outputs: ChannelLayout) -> bool;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_gain(matrix: &[Vec<f64>], output: usize, input: usize, expected: f64) {
+        assert!(
+            (matrix[output][input] - expected).abs() < 1e-12,
+            "gain from {} to {} is {}, expected {}",
+            input,
+            output,
+            matrix[output][input],
+            expected
+        );
+    }
+
+    #[test]
+    fn speakers_and_port_names() {
+        let layout = ChannelLayout::Surround71;
+        assert_eq!(layout.number_of_channels(), 8);
+        assert_eq!(layout.speaker(6), Some(Speaker::LeftSide));
+        assert_eq!(layout.speaker(8), None);
+        assert_eq!(layout.index_of(Speaker::RightSurround), Some(5));
+        assert_eq!(layout.port_name("in", 4), "in Ls");
+        let ambisonic = ChannelLayout::Ambisonic { order: 2 };
+        assert_eq!(ambisonic.number_of_channels(), 9);
+        assert_eq!(ambisonic.port_name("in", 8), "in ACN8");
+        assert_eq!(ChannelLayout::Discrete(3).port_name("in", 2), "in 2");
+        assert_eq!(
+            ChannelLayout::for_number_of_channels(2),
+            ChannelLayout::Stereo
+        );
+        assert_eq!(
+            ChannelLayout::for_number_of_channels(4),
+            ChannelLayout::Discrete(4)
+        );
+    }
+
+    #[test]
+    fn surround_51_to_stereo_follows_itu() {
+        let matrix = ChannelLayout::Surround51.down_mix_matrix(ChannelLayout::Stereo);
+        assert_eq!(matrix.len(), 2);
+        // Left output: L + 0.707 C + 0.707 Ls.
+        assert_gain(&matrix, 0, 0, 1.0);
+        assert_gain(&matrix, 0, 1, 0.0);
+        assert_gain(&matrix, 0, 2, FRAC_1_SQRT_2);
+        assert_gain(&matrix, 0, 3, 0.0);
+        assert_gain(&matrix, 0, 4, FRAC_1_SQRT_2);
+        assert_gain(&matrix, 0, 5, 0.0);
+        // Right output: R + 0.707 C + 0.707 Rs.
+        assert_gain(&matrix, 1, 1, 1.0);
+        assert_gain(&matrix, 1, 2, FRAC_1_SQRT_2);
+        assert_gain(&matrix, 1, 5, FRAC_1_SQRT_2);
+    }
+
+    #[test]
+    fn other_down_mixes() {
+        let matrix = ChannelLayout::Stereo.down_mix_matrix(ChannelLayout::Mono);
+        assert_gain(&matrix, 0, 0, FRAC_1_SQRT_2);
+        assert_gain(&matrix, 0, 1, FRAC_1_SQRT_2);
+
+        let matrix = ChannelLayout::Surround71.down_mix_matrix(ChannelLayout::Surround51);
+        assert_gain(&matrix, 3, 3, 1.0);
+        assert_gain(&matrix, 4, 4, 1.0);
+        assert_gain(&matrix, 4, 6, 1.0);
+        assert_gain(&matrix, 5, 7, 1.0);
+
+        let matrix = ChannelLayout::Ambisonic { order: 2 }
+            .down_mix_matrix(ChannelLayout::Ambisonic { order: 1 });
+        assert_eq!(matrix.len(), 4);
+        assert_gain(&matrix, 3, 3, 1.0);
+        assert_eq!(matrix[3].iter().sum::<f64>(), 1.0);
+    }
+}
@@ -0,0 +1,253 @@
+//! A generic graphical editor for the parameters of a plugin.
+//!
+//! Support is only enabled if you compile with the "editor" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! The [`GenericEditor`] shows a slider for each parameter in a [`ParameterStore`].
+//! The sliders are generated from the [`ParameterInfo`]s, so no custom GUI code is needed.
+//! The editor reads and writes the values of the parameters via the `ParameterStore`,
+//! which is lock-free, so the audio thread is never blocked by the editor.
+//! The changes are recorded in an [`UndoHistory`], so that the user can undo and redo them
+//! with the buttons at the top of the editor.
+//!
+//! The editor uses [`egui`] with [`baseview`].
+//! It can be opened in its own window (e.g. for a standalone application) or inside a window
+//! that is provided by a plugin host. `GenericEditor` implements the [`Editor`] trait, so a
+//! plugin can return it from e.g. [`VstPluginMeta::editor`].
+//!
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`GenericEditor`]: ./struct.GenericEditor.html
+//! [`Editor`]: ../trait.Editor.html
+//! [`VstPluginMeta::editor`]: ../backend/vst_backend/trait.VstPluginMeta.html#method.editor
+//! [`ParameterStore`]: ../parameter/struct.ParameterStore.html
+//! [`ParameterInfo`]: ../parameter/struct.ParameterInfo.html
+//! [`UndoHistory`]: ../parameter/history/struct.UndoHistory.html
+//! [`egui`]: https://crates.io/crates/egui
+//! [`baseview`]: https://github.com/RustAudio/baseview
+use crate::parameter::history::{UndoHistory, DEFAULT_CAPACITY};
+use crate::parameter::ParameterStore;
+use crate::Editor;
+use core::ffi::c_void;
+use egui_baseview::baseview::dpi::{LogicalSize, Size};
+use egui_baseview::baseview::WindowHandle;
+use egui_baseview::{EguiWindow, EguiWindowSettings, ExtraOutputCommands};
+use raw_window_handle::{HandleError, HasWindowHandle, RawWindowHandle};
+use std::sync::{Arc, Mutex};
+
+const WIDTH: f64 = 400.0;
+const ROW_HEIGHT: f64 = 24.0;
+const MARGIN: f64 = 16.0;
+
+/// A graphical editor that shows a slider for each parameter.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct GenericEditor {
+    title: String,
+    state: EditorState,
+    // The window that has been opened with `Editor::open`.
+    window: Option<WindowHandle>,
+}
+
+// The state that is passed to `update`.
+#[derive(Clone)]
+struct EditorState {
+    store: Arc<ParameterStore>,
+    history: Arc<Mutex<UndoHistory>>,
+}
+
+impl GenericEditor {
+    /// Create a new `GenericEditor` for the parameters in the given store.
+    pub fn new(title: &str, store: Arc<ParameterStore>) -> Self {
+        Self {
+            title: title.to_string(),
+            state: EditorState {
+                store,
+                history: Arc::new(Mutex::new(UndoHistory::new(DEFAULT_CAPACITY))),
+            },
+            window: None,
+        }
+    }
+
+    /// Record the changes in the given undo history, e.g. to share the history with a
+    /// remote control.
+    pub fn with_history(mut self, history: Arc<Mutex<UndoHistory>>) -> Self {
+        self.state.history = history;
+        self
+    }
+
+    /// The logical size (width, height) of the window of the editor.
+    pub fn size(&self) -> (f64, f64) {
+        (
+            WIDTH,
+            2.0 * MARGIN + ROW_HEIGHT * (self.state.store.len().max(1) + 1) as f64,
+        )
+    }
+
+    fn settings(&self) -> EguiWindowSettings {
+        let (width, height) = self.size();
+        EguiWindowSettings {
+            title: self.title.clone(),
+            size: Size::Logical(LogicalSize { width, height }),
+            ..EguiWindowSettings::default()
+        }
+    }
+
+    /// Open the editor in its own window and block until the window is closed.
+    pub fn open_blocking(self) {
+        EguiWindow::open_blocking(
+            self.settings(),
+            self.state,
+            |_: &egui::Context, _: &mut ExtraOutputCommands, _: &mut EditorState| {},
+            |_: &egui::FullOutput, _: &egui::ViewportOutput, _: &mut EditorState| {},
+            Self::update,
+        );
+    }
+
+    /// Open the editor inside the given parent window, e.g. a window provided by a plugin host.
+    /// Use the returned handle to close the editor.
+    pub fn open_parented<P>(self, parent: &P) -> WindowHandle
+    where
+        P: HasWindowHandle,
+    {
+        Self::open_window(parent, self.settings(), self.state)
+    }
+
+    fn open_window<P>(parent: &P, settings: EguiWindowSettings, state: EditorState) -> WindowHandle
+    where
+        P: HasWindowHandle,
+    {
+        EguiWindow::open_parented(
+            parent,
+            settings,
+            state,
+            |_: &egui::Context, _: &mut ExtraOutputCommands, _: &mut EditorState| {},
+            |_: &egui::FullOutput, _: &egui::ViewportOutput, _: &mut EditorState| {},
+            Self::update,
+        )
+    }
+
+    fn update(ui: &mut egui::Ui, _: &mut ExtraOutputCommands, state: &mut EditorState) {
+        let store = &state.store;
+        // The history remains consistent when a thread panics while it is locked.
+        let mut history = state
+            .history
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(history.can_undo(), egui::Button::new("Undo"))
+                .clicked()
+            {
+                history.undo(store);
+            }
+            if ui
+                .add_enabled(history.can_redo(), egui::Button::new("Redo"))
+                .clicked()
+            {
+                history.redo(store);
+            }
+        });
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("parameters").num_columns(3).show(ui, |ui| {
+                for (index, info) in store.infos().iter().enumerate() {
+                    ui.label(&info.name);
+                    let mut value = store.get(index);
+                    let response = ui
+                        .add(egui::Slider::new(&mut value, info.min..=info.max).show_value(false));
+                    if response.drag_started() {
+                        history.begin_gesture(index);
+                    }
+                    if response.changed() {
+                        history.set(store, index, value);
+                    }
+                    if response.drag_stopped() {
+                        history.end_gesture();
+                    }
+                    ui.label(format!("{:.2} {}", store.get(index), info.label));
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
+
+impl Editor for GenericEditor {
+    fn size(&self) -> (u32, u32) {
+        let (width, height) = GenericEditor::size(self);
+        (width.ceil() as u32, height.ceil() as u32)
+    }
+
+    fn open(&mut self, parent: *mut c_void) -> bool {
+        if self.is_open() {
+            return true;
+        }
+        let parent = match ParentWindow::new(parent) {
+            Some(parent) => parent,
+            None => return false,
+        };
+        self.window = Some(Self::open_window(
+            &parent,
+            self.settings(),
+            self.state.clone(),
+        ));
+        true
+    }
+
+    fn close(&mut self) {
+        if let Some(mut window) = self.window.take() {
+            window.close();
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.window.as_ref().is_some_and(WindowHandle::is_open)
+    }
+}
+
+// The parent window that is passed by the host to `Editor::open`.
+struct ParentWindow(RawWindowHandle);
+
+impl ParentWindow {
+    // `parent` is an `HWND` on Windows, an `NSView` on macOS and an X11 window id on Linux.
+    fn new(parent: *mut c_void) -> Option<Self> {
+        #[cfg(target_os = "windows")]
+        let handle = RawWindowHandle::Win32(raw_window_handle::Win32WindowHandle::new(
+            core::num::NonZeroIsize::new(parent as isize)?,
+        ));
+        #[cfg(target_os = "macos")]
+        let handle = RawWindowHandle::AppKit(raw_window_handle::AppKitWindowHandle::new(
+            core::ptr::NonNull::new(parent)?,
+        ));
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        let handle = {
+            if parent.is_null() {
+                return None;
+            }
+            RawWindowHandle::Xlib(raw_window_handle::XlibWindowHandle::new(
+                parent as core::ffi::c_ulong,
+            ))
+        };
+        Some(ParentWindow(handle))
+    }
+}
+
+impl HasWindowHandle for ParentWindow {
+    fn window_handle(&self) -> Result<raw_window_handle::WindowHandle<'_>, HandleError> {
+        // The host keeps the parent window alive while the editor is open.
+        Ok(unsafe { raw_window_handle::WindowHandle::borrow_raw(self.0) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_parent_window_cannot_be_null() {
+        assert!(ParentWindow::new(core::ptr::null_mut()).is_none());
+        let parent = ParentWindow::new(42 as *mut c_void).expect("the parent is not null");
+        assert!(parent.window_handle().is_ok());
+    }
+}
@@ -1,23 +1,24 @@
 use super::{Envelope, EnvelopeIteratorItem};
-use crate::event::event_queue::{AlwaysRemoveOld, EventQueue};
+use crate::event::collision::AlwaysRemoveOld;
+use crate::event::fixed_event_queue::FixedEventQueue;
 use crate::event::Timed;
 
-pub struct StairCaseEnvelopeIterator<'a, T>
+pub struct StairCaseEnvelopeIterator<'a, T, const N: usize>
 where
     T: Copy,
 {
-    envelope: &'a StairCaseEnvelope<T>,
+    envelope: &'a StairCaseEnvelope<T, N>,
     index: usize,
     // Time to live
     ttl: usize,
     current_value: T,
 }
 
-impl<'a, T> StairCaseEnvelopeIterator<'a, T>
+impl<'a, T, const N: usize> StairCaseEnvelopeIterator<'a, T, N>
 where
     T: Copy + 'a,
 {
-    fn new(envelope: &'a StairCaseEnvelope<T>) -> Self {
+    fn new(envelope: &'a StairCaseEnvelope<T, N>) -> Self {
         Self {
             envelope,
             index: 0,
@@ -31,7 +32,7 @@ where
     }
 }
 
-impl<'a, T> Iterator for StairCaseEnvelopeIterator<'a, T>
+impl<'a, T, const N: usize> Iterator for StairCaseEnvelopeIterator<'a, T, N>
 where
     T: Copy + 'a,
 {
@@ -66,24 +67,17 @@ where
 
 #[test]
 fn staircase_envelope_iterator_next_called_with_empty_staircase_initial_value_returned() {
-    let se = StairCaseEnvelope {
-        initial_value: 4,
-        event_queue: EventQueue::from_vec(vec![]),
-    };
+    let se: StairCaseEnvelope<_, 4> = StairCaseEnvelope::new(4);
     let mut iterator = se.iter();
     assert_eq!(iterator.next().map(|x| x.item), Some(4));
 }
 
 #[test]
 fn staircase_envelope_iterator_next_called_with_nonempty_staircase_initial_value_returned() {
-    let se = StairCaseEnvelope {
-        initial_value: 1,
-        event_queue: EventQueue::from_vec(vec![
-            Timed::new(2, 4),
-            Timed::new(3, 9),
-            Timed::new(5, 25),
-        ]),
-    };
+    let mut se: StairCaseEnvelope<_, 4> = StairCaseEnvelope::new(1);
+    se.insert_event(Timed::new(2, 4));
+    se.insert_event(Timed::new(3, 9));
+    se.insert_event(Timed::new(5, 25));
     let iterator = se.iter();
     assert_eq!(
         iterator.take(7).map(|x| x.item).collect::<Vec<_>>(),
@@ -91,19 +85,40 @@ fn staircase_envelope_iterator_next_called_with_nonempty_staircase_initial_value
     );
 }
 
-pub struct StairCaseEnvelope<T>
+/// An envelope that jumps to a new value at the time of each event.
+///
+/// At most `N` events are remembered; when more events are inserted, the first event is
+/// dropped. The envelope does not allocate memory, so it can be used without the `std`
+/// feature.
+pub struct StairCaseEnvelope<T, const N: usize>
 where
     T: Copy,
 {
     initial_value: T,
-    event_queue: EventQueue<T>,
+    event_queue: FixedEventQueue<T, N>,
 }
 
-impl<'a, T> Envelope<'a, T> for StairCaseEnvelope<T>
+impl<T, const N: usize> StairCaseEnvelope<T, N>
+where
+    T: Copy,
+{
+    /// Create a new envelope with the given value and without events.
+    ///
+    /// # Panics
+    /// Panics if `N == 0`.
+    pub fn new(initial_value: T) -> Self {
+        Self {
+            initial_value,
+            event_queue: FixedEventQueue::new(),
+        }
+    }
+}
+
+impl<'a, T, const N: usize> Envelope<'a, T> for StairCaseEnvelope<T, N>
 where
     T: Copy + 'a,
 {
-    type Iter = StairCaseEnvelopeIterator<'a, T>;
+    type Iter = StairCaseEnvelopeIterator<'a, T, N>;
     type EventType = Timed<T>;
 
     fn iter(&'a self) -> Self::Iter {
@@ -118,7 +133,6 @@ where
         if let Some(ref event) = self.event_queue.get_last_before(number_of_frames_to_forget) {
             self.initial_value = event.event;
         }
-        self.event_queue.forget_before(number_of_frames_to_forget);
-        self.event_queue.shift_time(number_of_frames_to_forget);
+        self.event_queue.advance(number_of_frames_to_forget);
     }
 }
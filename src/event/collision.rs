@@ -0,0 +1,50 @@
+//! Decide what happens when an event is queued at the same time as an event that is already
+//! in the queue.
+//!
+//! These types are used by both the [`EventQueue`] and the [`FixedEventQueue`].
+//!
+//! [`EventQueue`]: ../event_queue/struct.EventQueue.html
+//! [`FixedEventQueue`]: ../fixed_event_queue/struct.FixedEventQueue.html
+
+pub enum EventCollisionHandling {
+    InsertNewBeforeOld,
+    InsertNewAfterOld,
+    IgnoreNew,
+    RemoveOld,
+}
+
+pub trait HandleEventCollision<T> {
+    fn decide_on_collision(&self, old_event: &T, new_event: &T) -> EventCollisionHandling;
+}
+
+pub struct AlwaysInsertNewBeforeOld;
+impl<T> HandleEventCollision<T> for AlwaysInsertNewBeforeOld {
+    #[inline(always)]
+    fn decide_on_collision(&self, _old_event: &T, _new_event: &T) -> EventCollisionHandling {
+        EventCollisionHandling::InsertNewBeforeOld
+    }
+}
+
+pub struct AlwaysInsertNewAfterOld;
+impl<T> HandleEventCollision<T> for AlwaysInsertNewAfterOld {
+    #[inline(always)]
+    fn decide_on_collision(&self, _old_event: &T, _new_event: &T) -> EventCollisionHandling {
+        EventCollisionHandling::InsertNewAfterOld
+    }
+}
+
+pub struct AlwaysIgnoreNew;
+impl<T> HandleEventCollision<T> for AlwaysIgnoreNew {
+    #[inline(always)]
+    fn decide_on_collision(&self, _old_event: &T, _new_event: &T) -> EventCollisionHandling {
+        EventCollisionHandling::IgnoreNew
+    }
+}
+
+pub struct AlwaysRemoveOld;
+impl<T> HandleEventCollision<T> for AlwaysRemoveOld {
+    #[inline(always)]
+    fn decide_on_collision(&self, _old_event: &T, _new_event: &T) -> EventCollisionHandling {
+        EventCollisionHandling::RemoveOld
+    }
+}
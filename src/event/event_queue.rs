@@ -1,58 +1,21 @@
+pub use super::collision::{
+    AlwaysIgnoreNew, AlwaysInsertNewAfterOld, AlwaysInsertNewBeforeOld, AlwaysRemoveOld,
+    EventCollisionHandling, HandleEventCollision,
+};
 use super::Timed;
+use crate::buffer::BufferRange;
 use crate::event::EventHandler;
 #[cfg(test)]
 use crate::test_utilities::{DummyEventHandler, TestPlugin};
+use crate::utilities::rt_log::Level;
 use crate::ContextualAudioRenderer;
-use std::cmp::Ordering;
 use std::collections::VecDeque;
 use std::ops::{Deref, Index, IndexMut};
-use vecstorage::{VecGuard, VecStorage};
 
 pub struct EventQueue<T> {
     queue: VecDeque<Timed<T>>,
-}
-
-pub enum EventCollisionHandling {
-    InsertNewBeforeOld,
-    InsertNewAfterOld,
-    IgnoreNew,
-    RemoveOld,
-}
-
-pub trait HandleEventCollision<T> {
-    fn decide_on_collision(&self, old_event: &T, new_event: &T) -> EventCollisionHandling;
-}
-
-pub struct AlwaysInsertNewBeforeOld;
-impl<T> HandleEventCollision<T> for AlwaysInsertNewBeforeOld {
-    #[inline(always)]
-    fn decide_on_collision(&self, _old_event: &T, _new_event: &T) -> EventCollisionHandling {
-        EventCollisionHandling::InsertNewBeforeOld
-    }
-}
-
-pub struct AlwaysInsertNewAfterOld;
-impl<T> HandleEventCollision<T> for AlwaysInsertNewAfterOld {
-    #[inline(always)]
-    fn decide_on_collision(&self, _old_event: &T, _new_event: &T) -> EventCollisionHandling {
-        EventCollisionHandling::InsertNewAfterOld
-    }
-}
-
-pub struct AlwaysIgnoreNew;
-impl<T> HandleEventCollision<T> for AlwaysIgnoreNew {
-    #[inline(always)]
-    fn decide_on_collision(&self, _old_event: &T, _new_event: &T) -> EventCollisionHandling {
-        EventCollisionHandling::IgnoreNew
-    }
-}
-
-pub struct AlwaysRemoveOld;
-impl<T> HandleEventCollision<T> for AlwaysRemoveOld {
-    #[inline(always)]
-    fn decide_on_collision(&self, _old_event: &T, _new_event: &T) -> EventCollisionHandling {
-        EventCollisionHandling::RemoveOld
-    }
+    // Used by `split` to pass events with the same time to the event handler at once.
+    batch: Vec<T>,
 }
 
 impl<T> Index<usize> for EventQueue<T> {
@@ -73,6 +36,7 @@ impl<T> EventQueue<T> {
     #[cfg(test)]
     pub fn from_vec(events: Vec<Timed<T>>) -> Self {
         Self {
+            batch: Vec::with_capacity(events.len()),
             queue: events.into(),
         }
     }
@@ -83,12 +47,20 @@ impl<T> EventQueue<T> {
         assert!(capacity > 0);
         Self {
             queue: VecDeque::with_capacity(capacity),
+            batch: Vec::with_capacity(capacity),
         }
     }
 
     /// Queue a new event.
     /// When the buffer is full, an element may be removed from the queue to make some room.
     /// This element is returned.
+    ///
+    /// Queueing an event takes constant time when the new event comes after all the queued
+    /// events. Otherwise, the position of the new event is found in O(log n) time, where n is
+    /// the number of events in the queue, but inserting it takes O(n) time, because the later
+    /// events are moved to make room.
+    /// When other events are queued at the same time, the collision decider is consulted for
+    /// each of them.
     pub fn queue_event<H>(&mut self, new_event: Timed<T>, collision_decider: H) -> Option<Timed<T>>
     where
         H: HandleEventCollision<T>,
@@ -97,7 +69,7 @@ impl<T> EventQueue<T> {
         let result;
         if self.queue.len() >= self.queue.capacity() {
             // Note: self.queue.capacity() > 0, so self.queue is not empty.
-            // TODO: Log an error.
+            crate::rt_log!(Level::Warn, "Event queue is full, dropping an event.");
             // We remove the first event to come, in this way,
             // we are sure we are not skipping the "last" event,
             // because we assume that the state of the first event
@@ -115,33 +87,40 @@ impl<T> EventQueue<T> {
         // If we are at this point, we can assume that we can insert at least one more event.
         debug_assert!(self.queue.len() < self.queue.capacity());
 
-        let mut insert_index = 0;
-        for read_event in self.queue.iter_mut() {
-            match read_event.time_in_frames.cmp(&new_event.time_in_frames) {
-                Ordering::Less => {
-                    insert_index += 1;
-                }
-                Ordering::Equal => {
-                    match collision_decider.decide_on_collision(&read_event.event, &new_event.event)
-                    {
-                        EventCollisionHandling::IgnoreNew => {
-                            return Some(new_event);
-                        }
-                        EventCollisionHandling::InsertNewBeforeOld => {
-                            break;
-                        }
-                        EventCollisionHandling::InsertNewAfterOld => {
-                            insert_index += 1;
-                        }
-                        EventCollisionHandling::RemoveOld => {
-                            std::mem::swap(&mut read_event.event, &mut new_event.event);
-                            return Some(new_event);
-                        }
-                    }
+        // Fast path for the common case where events are queued in chronological order.
+        let comes_last = match self.queue.back() {
+            Some(last) => last.time_in_frames < new_event.time_in_frames,
+            None => true,
+        };
+        if comes_last {
+            self.queue.push_back(new_event);
+            return result;
+        }
+
+        // The queue is sorted, so we can find the first event that does not come before
+        // the new event with a binary search. Only the events at the same time as the new event
+        // need to be inspected one by one.
+        let mut insert_index = self
+            .queue
+            .partition_point(|event| event.time_in_frames < new_event.time_in_frames);
+        for read_event in self.queue.range_mut(insert_index..) {
+            if read_event.time_in_frames > new_event.time_in_frames {
+                break;
+            }
+            match collision_decider.decide_on_collision(&read_event.event, &new_event.event) {
+                EventCollisionHandling::IgnoreNew => {
+                    return Some(new_event);
                 }
-                Ordering::Greater => {
+                EventCollisionHandling::InsertNewBeforeOld => {
                     break;
                 }
+                EventCollisionHandling::InsertNewAfterOld => {
+                    insert_index += 1;
+                }
+                EventCollisionHandling::RemoveOld => {
+                    std::mem::swap(&mut read_event.event, &mut new_event.event);
+                    return Some(new_event);
+                }
             }
         }
         self.queue.insert(insert_index, new_event);
@@ -155,10 +134,7 @@ impl<T> EventQueue<T> {
     /// If `T` implements drop, the elements that are removed are dropped.
     /// This may cause memory de-allocation, which you want to avoid in
     /// the real-time part of your library.
-    pub fn forget_before(&mut self, threshold: u32)
-    where
-        T: Copy,
-    {
+    pub fn forget_before(&mut self, threshold: u32) {
         self.queue.retain(|x| x.time_in_frames >= threshold);
     }
 
@@ -172,15 +148,61 @@ impl<T> EventQueue<T> {
         self.queue.clear()
     }
 
-    /// Shift time forward by `new_zero_time` frames.
+    /// Shift time forward by `new_zero_time` frames: `new_zero_time` is subtracted from the
+    /// time of each event.
     ///
-    /// # Panics
-    /// Panics in debug mode when at least one event has a `time_in_frames`
-    /// that is < `new_zero_time`.  
-    pub fn shift_time(&mut self, new_zero_time: u32) {
+    /// Events with a `time_in_frames` that is < `new_zero_time` get time `0`.
+    /// The number of events for which this happened is returned.
+    /// If you want to remove these events instead, use [`advance`].
+    ///
+    /// [`advance`]: #method.advance
+    pub fn shift_time(&mut self, new_zero_time: u32) -> usize {
+        let mut number_of_clamped_events = 0;
+        for event in self.queue.iter_mut() {
+            if event.time_in_frames < new_zero_time {
+                number_of_clamped_events += 1;
+            }
+            event.time_in_frames = event.time_in_frames.saturating_sub(new_zero_time);
+        }
+        number_of_clamped_events
+    }
+
+    /// Shift time backwards by `frames` frames: `frames` is added to the time of each event.
+    ///
+    /// Events for which this would overflow get time `u32::MAX`.
+    /// The number of events for which this happened is returned.
+    pub fn shift_time_backwards(&mut self, frames: u32) -> usize {
+        let mut number_of_clamped_events = 0;
         for event in self.queue.iter_mut() {
-            event.time_in_frames -= new_zero_time;
+            match event.time_in_frames.checked_add(frames) {
+                Some(time) => event.time_in_frames = time,
+                None => {
+                    event.time_in_frames = u32::MAX;
+                    number_of_clamped_events += 1;
+                }
+            }
         }
+        number_of_clamped_events
+    }
+
+    /// Advance time by `frames` frames, e.g. after rendering a buffer of `frames` frames:
+    /// the events before `frames` are removed and `frames` is subtracted from the time
+    /// of the remaining events, so that they can be carried to the next buffer.
+    ///
+    /// The number of removed events is returned.
+    ///
+    /// # Note about usage in real-time context
+    /// If `T` implements drop, the elements that are removed are dropped.
+    /// This may cause memory de-allocation, which you want to avoid in
+    /// the real-time part of your library.
+    pub fn advance(&mut self, frames: u32) -> usize {
+        let number_of_events = self.queue.len();
+        self.forget_before(frames);
+        let number_of_removed_events = number_of_events - self.queue.len();
+        for event in self.queue.iter_mut() {
+            event.time_in_frames -= frames;
+        }
+        number_of_removed_events
     }
 
     pub fn get_last_before(&self, time: u32) -> Option<&Timed<T>> {
@@ -195,82 +217,67 @@ impl<T> EventQueue<T> {
         self.queue.get(0)
     }
 
-    fn render<'storage, 's, 'chunk, S, R, C>(
-        start: usize,
-        stop: usize,
-        input_storage: &'storage mut VecStorage<&'static [S]>,
-        output_storage: &'storage mut VecStorage<&'static mut [S]>,
-        inputs: &[&[S]],
-        outputs: &mut [&mut [S]],
-        renderer: &mut R,
-        context: &mut C,
-    ) where
-        S: 'static,
-        R: ContextualAudioRenderer<S, C>,
-    {
-        let input_guard = mid(input_storage, inputs, start, stop);
-        let mut output_guard = mid_mut(output_storage, outputs, start, stop);
-        renderer.render_buffer(&input_guard, &mut output_guard, context);
-    }
-
-    pub fn split<'storage, 's, 'chunk, S, R, C>(
+    /// Render the audio and handle the events in the queue that fall within the buffer,
+    /// in chronological order.
+    ///
+    /// The buffer is split at the times of the events and `buffer_range` is used to render
+    /// each part.
+    ///
+    /// `number_of_frames` is the length of the buffer. It must be passed explicitly, so that
+    /// plugins without audio inputs and outputs (e.g. midi effects) can be supported as well.
+    ///
+    /// # Panics
+    /// Panics in debug mode when the length of a channel differs from `number_of_frames`.
+    pub fn split<S, R, C>(
         &mut self,
-        input_storage: &'storage mut VecStorage<&'static [S]>,
-        output_storage: &'storage mut VecStorage<&'static mut [S]>,
+        buffer_range: &mut BufferRange<S>,
+        number_of_frames: usize,
         inputs: &[&[S]],
-        outputs: &'s mut [&'s mut [S]],
+        outputs: &mut [&mut [S]],
         renderer: &mut R,
         context: &mut C,
     ) where
         S: 'static,
         R: ContextualAudioRenderer<S, C> + EventHandler<T>,
-        T: std::fmt::Debug,
+        T: std::fmt::Debug + Clone,
     {
-        let buffer_length = if inputs.len() > 0 {
-            inputs[0].len()
-        } else if outputs.len() > 0 {
-            outputs[0].len()
-        } else {
-            todo!();
-        };
+        debug_assert!(inputs.iter().all(|input| input.len() == number_of_frames));
+        debug_assert!(outputs
+            .iter()
+            .all(|output| output.len() == number_of_frames));
+        let buffer_length = number_of_frames;
         let mut last_event_time = 0;
-        loop {
-            if let Some(ref first) = self.queue.get(0) {
-                if first.time_in_frames as usize >= buffer_length {
+        while let Some(event_time) = self
+            .queue
+            .front()
+            .map(|event| event.time_in_frames)
+            .filter(|time| (*time as usize) < buffer_length)
+        {
+            if event_time != last_event_time {
+                buffer_range.render(
+                    inputs,
+                    outputs,
+                    last_event_time as usize..event_time as usize,
+                    renderer,
+                    context,
+                );
+                last_event_time = event_time;
+            }
+            while let Some(event) = self.queue.front() {
+                if event.time_in_frames != event_time {
                     break;
                 }
-            } else {
-                break;
-            };
-            let Timed {
-                time_in_frames: event_time,
-                event,
-            } = self.queue.pop_front().expect("event queue is not empty");
-            if event_time == last_event_time {
-                renderer.handle_event(event);
-                continue;
+                let event = self.queue.pop_front().expect("event queue is not empty");
+                self.batch.push(event.event);
             }
-            Self::render(
-                last_event_time as usize,
-                event_time as usize,
-                input_storage,
-                output_storage,
-                inputs,
-                outputs,
-                renderer,
-                context,
-            );
-            renderer.handle_event(event);
-            last_event_time = event_time;
+            renderer.handle_events(&self.batch);
+            self.batch.clear();
         }
         if (last_event_time as usize) < buffer_length {
-            Self::render(
-                last_event_time as usize,
-                buffer_length,
-                input_storage,
-                output_storage,
+            buffer_range.render(
                 inputs,
                 outputs,
+                last_event_time as usize..buffer_length,
                 renderer,
                 context,
             );
@@ -318,12 +325,11 @@ fn split_works() {
         },
     ];
     let mut queue = EventQueue::from_vec(events);
-    let mut input_storage = VecStorage::with_capacity(2);
-    let mut output_storage = VecStorage::with_capacity(2);
+    let mut buffer_range = BufferRange::new(2, 2);
     let mut result_event_handler = DummyEventHandler;
     queue.split(
-        &mut input_storage,
-        &mut output_storage,
+        &mut buffer_range,
+        4,
         &input.as_slices(),
         &mut output.as_mut_slices(),
         &mut test_plugin,
@@ -344,12 +350,11 @@ fn split_works_with_empty_event_queue() {
     let mut output = audio_chunk![[0, 0, 0, 0], [0, 0, 0, 0]];
     let events: Vec<()> = vec![];
     let mut queue = EventQueue::new(1);
-    let mut input_storage = VecStorage::with_capacity(2);
-    let mut output_storage = VecStorage::with_capacity(2);
+    let mut buffer_range = BufferRange::new(2, 2);
     let mut result_event_handler = DummyEventHandler;
     queue.split(
-        &mut input_storage,
-        &mut output_storage,
+        &mut buffer_range,
+        4,
         &input.as_slices(),
         &mut output.as_mut_slices(),
         &mut test_plugin,
@@ -357,122 +362,95 @@ fn split_works_with_empty_event_queue() {
     )
 }
 
-impl<T> Deref for EventQueue<T> {
-    type Target = VecDeque<Timed<T>>;
-
-    fn deref(&self) -> &Self::Target {
-        &self.queue
+#[test]
+fn split_passes_events_with_the_same_time_at_once() {
+    struct BatchRecorder {
+        batches: Vec<Vec<u32>>,
     }
-}
-
-// TODO: Move to a better place in the module hierarchy.
-pub fn mid<'storage, 'chunk, 's, S>(
-    storage: &'storage mut VecStorage<&'static [S]>,
-    chunk: &'chunk [&'s [S]],
-    start: usize,
-    end: usize,
-) -> VecGuard<'storage, &'static [S], &'chunk [S]> {
-    let mut remaining_chunk = chunk;
-    let mut guard = storage.vec_guard();
-    let mut len = remaining_chunk.len();
-    while len > 0 {
-        let (first_channel, other_channels) = remaining_chunk.split_at(1);
-        let channel = &(first_channel[0]);
-        let (first, _) = channel.split_at(end);
-        let (_, middle) = first.split_at(start);
-        guard.push(middle);
-        remaining_chunk = other_channels;
-        len = remaining_chunk.len();
+    impl ContextualAudioRenderer<f32, ()> for BatchRecorder {
+        fn render_buffer(&mut self, _: &[&[f32]], _: &mut [&mut [f32]], _: &mut ()) {}
     }
-    guard
-}
+    impl EventHandler<u32> for BatchRecorder {
+        fn handle_event(&mut self, event: u32) {
+            self.batches.push(vec![event]);
+        }
 
-// TODO: Move to a better place in the module hierarchy.
-///
-/// ## Panics
-/// Panics if `start` > `end` or if `end` > the length of any item in `chunk`.
-pub fn mid_mut<'storage, 'chunk, 's, S>(
-    storage: &'storage mut VecStorage<&'static mut [S]>,
-    chunk: &'chunk mut [&'s mut [S]],
-    start: usize,
-    end: usize,
-) -> VecGuard<'storage, &'static mut [S], &'chunk mut [S]> {
-    let mut remaining_chunk = chunk;
-    let mut guard = storage.vec_guard();
-    let mut len = remaining_chunk.len();
-    while len > 0 {
-        let (first_channel, other_channels) = remaining_chunk.split_at_mut(1);
-        let channel = &mut (first_channel[0]);
-        let (first, _) = channel.split_at_mut(end);
-        let (_, middle) = first.split_at_mut(start);
-        guard.push(middle);
-        remaining_chunk = other_channels;
-        len = remaining_chunk.len();
+        fn handle_events(&mut self, events: &[u32]) {
+            self.batches.push(events.to_vec());
+        }
     }
-    guard
+
+    let mut renderer = BatchRecorder { batches: vec![] };
+    let input = audio_chunk![[0.0, 0.0, 0.0, 0.0]];
+    let mut output = audio_chunk![[0.0, 0.0, 0.0, 0.0]];
+    let events = vec![
+        Timed::new(0, 1),
+        Timed::new(0, 2),
+        Timed::new(1, 3),
+        Timed::new(3, 4),
+        Timed::new(3, 5),
+        Timed::new(3, 6),
+        Timed::new(4, 7),
+    ];
+    let mut queue = EventQueue::from_vec(events);
+    let mut buffer_range = BufferRange::new(1, 1);
+    queue.split(
+        &mut buffer_range,
+        4,
+        &input.as_slices(),
+        &mut output.as_mut_slices(),
+        &mut renderer,
+        &mut (),
+    );
+    assert_eq!(renderer.batches, vec![vec![1, 2], vec![3], vec![4, 5, 6]]);
+    assert_eq!(queue.len(), 1);
 }
 
 #[test]
-fn mid_mut_works() {
-    let mut storage = VecStorage::with_capacity(2);
-    let mut channel1 = [11, 12, 13, 14];
-    let mut channel2 = [21, 22, 23, 24];
-    let chunk: &mut [&mut [_]] = &mut [&mut channel1, &mut channel2];
-    {
-        let guard = mid_mut(&mut storage, chunk, 0, 0);
-        assert_eq!(guard.len(), 2);
-        assert!(guard[0].is_empty());
-        assert!(guard[1].is_empty());
+fn split_works_without_inputs_and_outputs() {
+    struct MidiEffect {
+        number_of_renders: usize,
+        events: Vec<u32>,
     }
-    {
-        let guard = mid_mut(&mut storage, chunk, 0, 1);
-        assert_eq!(guard.len(), 2);
-        assert_eq!(guard[0], &mut [11]);
-        assert_eq!(guard[1], &mut [21]);
-    }
-    {
-        let guard = mid_mut(&mut storage, chunk, 0, 2);
-        assert_eq!(guard.len(), 2);
-        assert_eq!(guard[0], &mut [11, 12]);
-        assert_eq!(guard[1], &mut [21, 22]);
+    impl ContextualAudioRenderer<f32, ()> for MidiEffect {
+        fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut [&mut [f32]], _: &mut ()) {
+            assert!(inputs.is_empty());
+            assert!(outputs.is_empty());
+            self.number_of_renders += 1;
+        }
     }
-    {
-        let guard = mid_mut(&mut storage, chunk, 1, 2);
-        assert_eq!(guard.len(), 2);
-        assert_eq!(guard[0], &mut [12]);
-        assert_eq!(guard[1], &mut [22]);
+    impl EventHandler<u32> for MidiEffect {
+        fn handle_event(&mut self, event: u32) {
+            self.events.push(event);
+        }
     }
+
+    let mut midi_effect = MidiEffect {
+        number_of_renders: 0,
+        events: vec![],
+    };
+    let events = vec![Timed::new(1, 1), Timed::new(3, 2), Timed::new(8, 3)];
+    let mut queue = EventQueue::from_vec(events);
+    let mut buffer_range = BufferRange::new(0, 0);
+    queue.split(
+        &mut buffer_range,
+        8,
+        &[],
+        &mut [],
+        &mut midi_effect,
+        &mut (),
+    );
+    assert_eq!(midi_effect.events, vec![1, 2]);
+    // Frames 0..1, 1..3 and 3..8.
+    assert_eq!(midi_effect.number_of_renders, 3);
+    assert_eq!(queue.len(), 1);
 }
 
-#[test]
-fn mid_works() {
-    let mut storage = VecStorage::with_capacity(2);
-    let channel1 = [11, 12, 13, 14];
-    let channel2 = [21, 22, 23, 24];
-    let chunk: &[&[_]] = &[&channel1, &channel2];
-    {
-        let guard = mid(&mut storage, chunk, 0, 0);
-        assert_eq!(guard.len(), 2);
-        assert!(guard[0].is_empty());
-        assert!(guard[1].is_empty());
-    }
-    {
-        let guard = mid(&mut storage, chunk, 0, 1);
-        assert_eq!(guard.len(), 2);
-        assert_eq!(guard[0], &[11]);
-        assert_eq!(guard[1], &[21]);
-    }
-    {
-        let guard = mid(&mut storage, chunk, 0, 2);
-        assert_eq!(guard.len(), 2);
-        assert_eq!(guard[0], &[11, 12]);
-        assert_eq!(guard[1], &[21, 22]);
-    }
-    {
-        let guard = mid(&mut storage, chunk, 1, 2);
-        assert_eq!(guard.len(), 2);
-        assert_eq!(guard[0], &[12]);
-        assert_eq!(guard[1], &[22]);
+impl<T> Deref for EventQueue<T> {
+    type Target = VecDeque<Timed<T>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.queue
     }
 }
 
@@ -622,6 +600,28 @@ fn eventqueue_queue_event_with_always_insert_new_before_old() {
     assert_eq!(queue.queue, expected_buffer);
 }
 
+#[test]
+fn eventqueue_queue_event_keeps_events_sorted_when_queued_out_of_order() {
+    let mut queue = EventQueue::new(64);
+    // Visit the times 0..64 in a scrambled order, with doubles.
+    for index in 0..64 {
+        let time = (index * 37) % 32;
+        assert_eq!(
+            queue.queue_event(Timed::new(time, index), AlwaysInsertNewAfterOld),
+            None
+        );
+    }
+
+    assert_eq!(queue.len(), 64);
+    for (previous, next) in queue.iter().zip(queue.iter().skip(1)) {
+        assert!(previous.time_in_frames <= next.time_in_frames);
+        if previous.time_in_frames == next.time_in_frames {
+            // Inserted after the old event.
+            assert!(previous.event < next.event);
+        }
+    }
+}
+
 #[test]
 fn eventqueue_forget_before() {
     let mut queue = EventQueue::from_vec({
@@ -636,6 +636,52 @@ fn eventqueue_forget_before() {
     assert_eq!(queue.queue, vec![Timed::new(7, 49), Timed::new(8, 64),]);
 }
 
+#[test]
+fn eventqueue_forget_before_does_not_require_copy() {
+    let mut queue = EventQueue::from_vec(vec![
+        Timed::new(4, String::from("a")),
+        Timed::new(6, String::from("b")),
+    ]);
+    queue.forget_before(5);
+    assert_eq!(queue.queue, vec![Timed::new(6, String::from("b"))]);
+}
+
+#[test]
+fn eventqueue_shift_time_clamps_events_in_the_past() {
+    let mut queue = EventQueue::from_vec(vec![
+        Timed::new(4, 16),
+        Timed::new(6, 36),
+        Timed::new(7, 49),
+    ]);
+    assert_eq!(queue.shift_time(6), 1);
+    assert_eq!(
+        queue.queue,
+        vec![Timed::new(0, 16), Timed::new(0, 36), Timed::new(1, 49)]
+    );
+}
+
+#[test]
+fn eventqueue_shift_time_backwards_clamps_on_overflow() {
+    let mut queue = EventQueue::from_vec(vec![Timed::new(4, 16), Timed::new(u32::MAX - 1, 36)]);
+    assert_eq!(queue.shift_time_backwards(2), 1);
+    assert_eq!(
+        queue.queue,
+        vec![Timed::new(6, 16), Timed::new(u32::MAX, 36)]
+    );
+}
+
+#[test]
+fn eventqueue_advance_removes_past_events_and_shifts_the_others() {
+    let mut queue = EventQueue::from_vec(vec![
+        Timed::new(4, 16),
+        Timed::new(6, 36),
+        Timed::new(7, 49),
+        Timed::new(8, 64),
+    ]);
+    assert_eq!(queue.advance(7), 2);
+    assert_eq!(queue.queue, vec![Timed::new(0, 49), Timed::new(1, 64)]);
+}
+
 #[test]
 fn eventqueue_forget_everything() {
     let mut queue = EventQueue::from_vec({
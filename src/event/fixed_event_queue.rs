@@ -0,0 +1,328 @@
+//! An event queue with a capacity that is fixed at compile time.
+//!
+//! The [`FixedEventQueue`] keeps its events in an array, so it never allocates memory and it
+//! can be used without the `std` feature, e.g. on embedded targets.
+//! It queues events in the same way as the [`EventQueue`]; because events are moved when an
+//! event is inserted or removed, it is meant for small capacities, e.g. the events of one
+//! voice or one envelope.
+//!
+//! ```
+//! use rsynth::event::collision::AlwaysInsertNewAfterOld;
+//! use rsynth::event::fixed_event_queue::FixedEventQueue;
+//! use rsynth::event::Timed;
+//!
+//! let mut queue: FixedEventQueue<u8, 4> = FixedEventQueue::new();
+//! queue.queue_event(Timed::new(10, 2), AlwaysInsertNewAfterOld);
+//! queue.queue_event(Timed::new(5, 1), AlwaysInsertNewAfterOld);
+//! assert_eq!(queue.pop_front(), Some(Timed::new(5, 1)));
+//! queue.advance(8);
+//! assert_eq!(queue.first(), Some(&Timed::new(2, 2)));
+//! ```
+//!
+//! [`FixedEventQueue`]: ./struct.FixedEventQueue.html
+//! [`EventQueue`]: ../event_queue/struct.EventQueue.html
+use super::collision::{EventCollisionHandling, HandleEventCollision};
+use super::Timed;
+use core::ops::{Index, IndexMut};
+
+/// A queue of at most `N` timed events, sorted by time.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+#[derive(Clone, Copy)]
+pub struct FixedEventQueue<T, const N: usize>
+where
+    T: Copy,
+{
+    // The events at `0..length` are `Some`, the others are `None`.
+    events: [Option<Timed<T>>; N],
+    length: usize,
+}
+
+impl<T, const N: usize> FixedEventQueue<T, N>
+where
+    T: Copy,
+{
+    /// Create a new, empty queue.
+    ///
+    /// # Panics
+    /// Panics if `N == 0`.
+    pub fn new() -> Self {
+        assert!(N > 0);
+        Self {
+            events: [None; N],
+            length: 0,
+        }
+    }
+
+    /// The maximum number of events in the queue: `N`.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// The number of events in the queue.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Queue a new event.
+    /// When the queue is full, an element may be removed from the queue to make some room.
+    /// This element is returned.
+    ///
+    /// This behaves as [`EventQueue::queue_event`]: when the queue is full, the first event
+    /// is removed if the new event comes after it, otherwise the new event is returned.
+    ///
+    /// [`EventQueue::queue_event`]: ../event_queue/struct.EventQueue.html#method.queue_event
+    pub fn queue_event<H>(&mut self, new_event: Timed<T>, collision_decider: H) -> Option<Timed<T>>
+    where
+        H: HandleEventCollision<T>,
+    {
+        let mut new_event = new_event;
+        let mut result = None;
+        if self.length == N {
+            if new_event.time_in_frames > self[0].time_in_frames {
+                result = self.pop_front();
+            } else {
+                return Some(new_event);
+            }
+        }
+
+        let mut insert_index = self.events[..self.length]
+            .iter()
+            .position(|event| event_time(event) >= new_event.time_in_frames)
+            .unwrap_or(self.length);
+        while insert_index < self.length {
+            let read_event = self.events[insert_index]
+                .as_mut()
+                .expect("events before `length` are `Some`");
+            if read_event.time_in_frames > new_event.time_in_frames {
+                break;
+            }
+            match collision_decider.decide_on_collision(&read_event.event, &new_event.event) {
+                EventCollisionHandling::IgnoreNew => {
+                    return Some(new_event);
+                }
+                EventCollisionHandling::InsertNewBeforeOld => {
+                    break;
+                }
+                EventCollisionHandling::InsertNewAfterOld => {
+                    insert_index += 1;
+                }
+                EventCollisionHandling::RemoveOld => {
+                    core::mem::swap(&mut read_event.event, &mut new_event.event);
+                    return Some(new_event);
+                }
+            }
+        }
+        self.events
+            .copy_within(insert_index..self.length, insert_index + 1);
+        self.events[insert_index] = Some(new_event);
+        self.length += 1;
+
+        result
+    }
+
+    /// Remove the first event from the queue and return it.
+    pub fn pop_front(&mut self) -> Option<Timed<T>> {
+        if self.length == 0 {
+            return None;
+        }
+        let first = self.events[0];
+        self.events.copy_within(1..self.length, 0);
+        self.length -= 1;
+        self.events[self.length] = None;
+        first
+    }
+
+    /// Remove all events before, but not on, this threshold.
+    pub fn forget_before(&mut self, threshold: u32) {
+        let number_of_events_to_forget = self.events[..self.length]
+            .iter()
+            .position(|event| event_time(event) >= threshold)
+            .unwrap_or(self.length);
+        self.events
+            .copy_within(number_of_events_to_forget..self.length, 0);
+        for event in self.events[self.length - number_of_events_to_forget..self.length].iter_mut() {
+            *event = None;
+        }
+        self.length -= number_of_events_to_forget;
+    }
+
+    /// Remove all events from the queue.
+    pub fn clear(&mut self) {
+        self.events = [None; N];
+        self.length = 0;
+    }
+
+    /// Shift time forward by `new_zero_time` frames: `new_zero_time` is subtracted from the
+    /// time of each event.
+    ///
+    /// Events with a `time_in_frames` that is < `new_zero_time` get time `0`.
+    /// The number of events for which this happened is returned.
+    /// If you want to remove these events instead, use [`advance`].
+    ///
+    /// [`advance`]: #method.advance
+    pub fn shift_time(&mut self, new_zero_time: u32) -> usize {
+        let mut number_of_clamped_events = 0;
+        for event in self.iter_mut() {
+            if event.time_in_frames < new_zero_time {
+                number_of_clamped_events += 1;
+            }
+            event.time_in_frames = event.time_in_frames.saturating_sub(new_zero_time);
+        }
+        number_of_clamped_events
+    }
+
+    /// Advance time by `frames` frames, e.g. after rendering a buffer of `frames` frames:
+    /// the events before `frames` are removed and `frames` is subtracted from the time
+    /// of the remaining events.
+    ///
+    /// The number of removed events is returned.
+    pub fn advance(&mut self, frames: u32) -> usize {
+        let number_of_events = self.length;
+        self.forget_before(frames);
+        for event in self.iter_mut() {
+            event.time_in_frames -= frames;
+        }
+        number_of_events - self.length
+    }
+
+    pub fn get_last_before(&self, time: u32) -> Option<&Timed<T>> {
+        self.iter().rev().find(|event| event.time_in_frames < time)
+    }
+
+    pub fn first(&self) -> Option<&Timed<T>> {
+        self.events[0].as_ref()
+    }
+
+    /// Iterate over the events in chronological order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Timed<T>> {
+        self.events[..self.length].iter().flatten()
+    }
+
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut Timed<T>> {
+        self.events[..self.length].iter_mut().flatten()
+    }
+}
+
+fn event_time<T>(event: &Option<Timed<T>>) -> u32 {
+    event
+        .as_ref()
+        .map(|event| event.time_in_frames)
+        .unwrap_or(u32::MAX)
+}
+
+impl<T, const N: usize> Default for FixedEventQueue<T, N>
+where
+    T: Copy,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Index<usize> for FixedEventQueue<T, N>
+where
+    T: Copy,
+{
+    type Output = Timed<T>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        assert!(index < self.length, "index out of bounds");
+        self.events[index]
+            .as_ref()
+            .expect("events before `length` are `Some`")
+    }
+}
+
+impl<T, const N: usize> IndexMut<usize> for FixedEventQueue<T, N>
+where
+    T: Copy,
+{
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        assert!(index < self.length, "index out of bounds");
+        self.events[index]
+            .as_mut()
+            .expect("events before `length` are `Some`")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::collision::{AlwaysIgnoreNew, AlwaysInsertNewAfterOld, AlwaysRemoveOld};
+
+    fn times<const N: usize>(queue: &FixedEventQueue<u32, N>) -> Vec<(u32, u32)> {
+        queue
+            .iter()
+            .map(|event| (event.time_in_frames, event.event))
+            .collect()
+    }
+
+    #[test]
+    fn events_are_sorted_and_collisions_are_handled() {
+        let mut queue: FixedEventQueue<u32, 8> = FixedEventQueue::new();
+        assert_eq!(
+            queue.queue_event(Timed::new(4, 1), AlwaysInsertNewAfterOld),
+            None
+        );
+        assert_eq!(
+            queue.queue_event(Timed::new(2, 2), AlwaysInsertNewAfterOld),
+            None
+        );
+        assert_eq!(
+            queue.queue_event(Timed::new(4, 3), AlwaysInsertNewAfterOld),
+            None
+        );
+        assert_eq!(times(&queue), vec![(2, 2), (4, 1), (4, 3)]);
+        assert_eq!(
+            queue.queue_event(Timed::new(4, 5), AlwaysIgnoreNew),
+            Some(Timed::new(4, 5))
+        );
+        assert_eq!(
+            queue.queue_event(Timed::new(2, 6), AlwaysRemoveOld),
+            Some(Timed::new(2, 2))
+        );
+        assert_eq!(times(&queue), vec![(2, 6), (4, 1), (4, 3)]);
+        assert_eq!(queue.get_last_before(4), Some(&Timed::new(2, 6)));
+        assert_eq!(queue.get_last_before(2), None);
+    }
+
+    #[test]
+    fn full_queue_drops_the_first_event() {
+        let mut queue: FixedEventQueue<u32, 2> = FixedEventQueue::new();
+        queue.queue_event(Timed::new(1, 1), AlwaysInsertNewAfterOld);
+        queue.queue_event(Timed::new(3, 3), AlwaysInsertNewAfterOld);
+        // The new event comes first: it is dropped.
+        assert_eq!(
+            queue.queue_event(Timed::new(0, 0), AlwaysInsertNewAfterOld),
+            Some(Timed::new(0, 0))
+        );
+        assert_eq!(
+            queue.queue_event(Timed::new(2, 2), AlwaysInsertNewAfterOld),
+            Some(Timed::new(1, 1))
+        );
+        assert_eq!(times(&queue), vec![(2, 2), (3, 3)]);
+    }
+
+    #[test]
+    fn advance_removes_past_events_and_shifts_time() {
+        let mut queue: FixedEventQueue<u32, 4> = FixedEventQueue::new();
+        for time in 0..4 {
+            queue.queue_event(Timed::new(time * 2, time), AlwaysInsertNewAfterOld);
+        }
+        assert_eq!(queue.advance(3), 2);
+        assert_eq!(times(&queue), vec![(1, 2), (3, 3)]);
+        assert_eq!(queue.shift_time(2), 1);
+        assert_eq!(times(&queue), vec![(0, 2), (1, 3)]);
+        assert_eq!(queue.pop_front(), Some(Timed::new(0, 2)));
+        queue.clear();
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop_front(), None);
+    }
+}
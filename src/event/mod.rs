@@ -11,20 +11,73 @@
 //!
 //! If possible, implement the `Copy` trait for the event,
 //! so that the event can be dispatched to different voices in a polyphonic context.
-use std::convert::{AsMut, AsRef};
-use std::fmt::{Debug, Error, Formatter};
+//!
+//! `no_std`
+//! ========
+//!
+//! The event types, the [`program`] tracker and the [`FixedEventQueue`] can be used without
+//! the `std` feature. The [`EventQueue`], the [`OutputEventQueue`], the [`BeatScheduler`] and
+//! the [`SysExReassembler`] allocate memory and require the `std` feature.
+//!
+//! [`program`]: ./program/index.html
+//! [`FixedEventQueue`]: ./fixed_event_queue/struct.FixedEventQueue.html
+//! [`EventQueue`]: ./event_queue/struct.EventQueue.html
+//! [`OutputEventQueue`]: ./output_event_queue/struct.OutputEventQueue.html
+//! [`BeatScheduler`]: ./scheduler/struct.BeatScheduler.html
+//! [`SysExReassembler`]: ./sysex/struct.SysExReassembler.html
+use core::convert::{AsMut, AsRef};
+use core::fmt::{Debug, Error, Formatter};
 
+pub mod collision;
+#[cfg(feature = "std")]
 pub mod event_queue;
+pub mod fixed_event_queue;
+#[cfg(feature = "std")]
+pub mod output_event_queue;
+pub mod program;
+#[cfg(feature = "std")]
+pub mod scheduler;
+#[cfg(feature = "std")]
+pub mod sysex;
 
 /// The trait that plugins should implement in order to handle the given type of events.
 ///
 /// The type parameter `E` corresponds to the type of the event.
 pub trait EventHandler<E> {
     fn handle_event(&mut self, event: E);
+
+    /// Handle a number of events that occur at the same time, in the given order.
+    ///
+    /// The default implementation calls `handle_event` for each event.
+    /// You can override this in order to amortize the per-event overhead, e.g. by handling
+    /// the note-off events before the note-on events.
+    fn handle_events(&mut self, events: &[E])
+    where
+        E: Clone,
+    {
+        for event in events {
+            self.handle_event(event.clone());
+        }
+    }
 }
 
 pub trait ContextualEventHandler<E, Context> {
     fn handle_event(&mut self, event: E, context: &mut Context);
+
+    /// Handle a number of events that occur at the same time, in the given order.
+    ///
+    /// The default implementation calls `handle_event` for each event.
+    /// See also [`EventHandler::handle_events`].
+    ///
+    /// [`EventHandler::handle_events`]: ./trait.EventHandler.html#method.handle_events
+    fn handle_events(&mut self, events: &[E], context: &mut Context)
+    where
+        E: Clone,
+    {
+        for event in events {
+            self.handle_event(event.clone(), context);
+        }
+    }
 }
 
 /// A System Exclusive ("SysEx") event.
@@ -76,40 +129,118 @@ impl Debug for RawMidiEvent {
     }
 }
 
+/// Return the length of a midi message (including the status byte) with the given status byte,
+/// or `None` if `status` is not the status byte of a message that fits in a `RawMidiEvent`,
+/// i.e. when it is a data byte (`< 0x80`) or the start or the end of a system exclusive message.
+///
+/// Undefined system common messages and undefined real-time messages have length 1.
+pub fn canonical_length_for_status(status: u8) -> Option<usize> {
+    match status {
+        0x00..=0x7F => None,
+        0x80..=0xBF | 0xE0..=0xEF => Some(3),
+        0xC0..=0xDF => Some(2),
+        0xF0 | 0xF7 => None,
+        0xF1 | 0xF3 => Some(2),
+        0xF2 => Some(3),
+        0xF4..=0xF6 | 0xF8..=0xFF => Some(1),
+    }
+}
+
+// `len` is always at least 1, so `is_empty` would not make sense.
+#[allow(clippy::len_without_is_empty)]
 impl RawMidiEvent {
     /// Create a new `RawMidiEvent` with the given raw data.
     ///
+    /// See [`try_new`] for the data that is accepted.
+    ///
     /// Panics
     /// ------
-    /// Panics when `data` does not have length 1, 2 or 3.
+    /// Panics when `data` is not a valid midi message.
+    ///
+    /// [`try_new`]: #method.try_new
     #[inline]
     pub fn new(data: &[u8]) -> Self {
-        Self::try_new(data).expect("Raw midi event is expected to have length 1, 2 or 3.")
+        Self::try_new(data)
+            .expect("Raw midi event is expected to be a valid midi message of length 1, 2 or 3.")
     }
 
     /// Try to create a new `RawMidiEvent` with the given raw data.
-    /// Return None when `data` does not have length 1, 2 or 3.
+    ///
+    /// Return `None` when
+    /// * `data` is empty or has more than 3 bytes,
+    /// * `data` does not start with a status byte (e.g. with a data byte because of running status),
+    ///   or starts with the status byte of a system exclusive message,
+    /// * `data` is shorter than the length of the message, as given by
+    ///   [`canonical_length_for_status`],
+    /// * one of the data bytes of the message is not a data byte (`>= 0x80`),
+    ///   e.g. when a real-time message is followed by other bytes.
+    ///
+    /// When `data` is longer than the message (some back-ends, e.g. VST, always use three bytes),
+    /// the bytes after the message are ignored, so that [`len`] reflects the length
+    /// of the message.
+    ///
+    /// [`canonical_length_for_status`]: ./fn.canonical_length_for_status.html
+    /// [`len`]: #method.len
     pub fn try_new(data: &[u8]) -> Option<Self> {
-        match data.len() {
-            1 => Some(Self {
-                data: [data[0], 0, 0],
-                length: data.len(),
-            }),
-            2 => Some(Self {
-                data: [data[0], data[1], 0],
-                length: data.len(),
-            }),
-            3 => Some(Self {
-                data: [data[0], data[1], data[2]],
-                length: data.len(),
-            }),
-            _ => None,
+        if data.len() > 3 {
+            return None;
         }
+        let status = *data.first()?;
+        let length = canonical_length_for_status(status)?;
+        if data.len() < length {
+            return None;
+        }
+        let mut padded = [0; 3];
+        padded[..length].copy_from_slice(&data[..length]);
+        if padded[1..length].iter().any(|byte| *byte >= 0x80) {
+            return None;
+        }
+        // Bytes after a real-time message must be ignorable padding.
+        if length == 1 && status >= 0xF8 && data[1..].iter().any(|byte| *byte != 0) {
+            return None;
+        }
+        Some(Self {
+            data: padded,
+            length,
+        })
     }
+
     /// Get the raw data from a `RawMidiEvent`.
     pub fn data(&self) -> &[u8; 3] {
         &self.data
     }
+
+    /// Get the raw data from a `RawMidiEvent`, without padding.
+    /// The length of the returned slice is 1, 2 or 3.
+    pub fn bytes(&self) -> &[u8] {
+        &self.data[..self.length]
+    }
+
+    /// The length of the message in bytes, including the status byte: 1, 2 or 3.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    /// The status byte.
+    pub fn status(&self) -> u8 {
+        self.data[0]
+    }
+
+    /// The midi channel (`0..=15`) for channel messages (e.g. note on or control change),
+    /// `None` for system messages.
+    pub fn channel(&self) -> Option<u8> {
+        if self.data[0] < 0xF0 {
+            Some(self.data[0] & 0x0F)
+        } else {
+            None
+        }
+    }
+
+    /// The data bytes, without the status byte and without padding.
+    /// The length of the returned slice is 0, 1 or 2.
+    pub fn data_bytes(&self) -> &[u8] {
+        &self.data[1..self.length]
+    }
 }
 
 impl AsRef<Self> for RawMidiEvent {
@@ -124,6 +255,21 @@ impl AsMut<Self> for RawMidiEvent {
     }
 }
 
+/// A change of the value of a parameter.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ParameterChange {
+    /// The index of the parameter.
+    pub index: usize,
+    /// The new value of the parameter.
+    pub value: f32,
+}
+
+impl ParameterChange {
+    pub fn new(index: usize, value: f32) -> Self {
+        Self { index, value }
+    }
+}
+
 /// `Timed<E>` adds timing to an event.
 #[derive(PartialEq, Eq, Debug)]
 pub struct Timed<E> {
@@ -221,3 +367,59 @@ pub struct DeltaEvent<E> {
     pub microseconds_since_previous_event: u64,
     pub event: E,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical_length_for_status, RawMidiEvent};
+
+    #[test]
+    fn raw_midi_event_try_new_accepts_valid_messages() {
+        let note_on = RawMidiEvent::try_new(&[0x93, 60, 100]).expect("valid note on");
+        assert_eq!(note_on.len(), 3);
+        assert_eq!(note_on.status(), 0x93);
+        assert_eq!(note_on.channel(), Some(3));
+        assert_eq!(note_on.data_bytes(), &[60, 100]);
+
+        let clock = RawMidiEvent::try_new(&[0xF8]).expect("valid timing clock");
+        assert_eq!(clock.len(), 1);
+        assert_eq!(clock.channel(), None);
+        assert!(clock.data_bytes().is_empty());
+    }
+
+    #[test]
+    fn raw_midi_event_try_new_ignores_padding() {
+        let program_change = RawMidiEvent::try_new(&[0xC0, 5, 0]).expect("valid program change");
+        assert_eq!(program_change.len(), 2);
+        assert_eq!(program_change.bytes(), &[0xC0, 5]);
+
+        let clock = RawMidiEvent::try_new(&[0xF8, 0, 0]).expect("valid timing clock");
+        assert_eq!(clock.bytes(), &[0xF8]);
+    }
+
+    #[test]
+    fn raw_midi_event_try_new_rejects_invalid_messages() {
+        // Empty or too long.
+        assert_eq!(RawMidiEvent::try_new(&[]), None);
+        assert_eq!(RawMidiEvent::try_new(&[0x90, 60, 100, 0]), None);
+        // Data byte first.
+        assert_eq!(RawMidiEvent::try_new(&[60, 100]), None);
+        // System exclusive.
+        assert_eq!(RawMidiEvent::try_new(&[0xF0, 1, 0xF7]), None);
+        // Too short.
+        assert_eq!(RawMidiEvent::try_new(&[0x90, 60]), None);
+        // Status byte where a data byte is expected.
+        assert_eq!(RawMidiEvent::try_new(&[0x90, 0xF8, 100]), None);
+        // Real-time message followed by other bytes.
+        assert_eq!(RawMidiEvent::try_new(&[0xF8, 0x90, 60]), None);
+    }
+
+    #[test]
+    fn canonical_length_for_status_works() {
+        assert_eq!(canonical_length_for_status(0x40), None);
+        assert_eq!(canonical_length_for_status(0x85), Some(3));
+        assert_eq!(canonical_length_for_status(0xD2), Some(2));
+        assert_eq!(canonical_length_for_status(0xF0), None);
+        assert_eq!(canonical_length_for_status(0xF2), Some(3));
+        assert_eq!(canonical_length_for_status(0xFE), Some(1));
+    }
+}
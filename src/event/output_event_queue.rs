@@ -0,0 +1,168 @@
+//! Collect the events that the plugin generates, so that they can be sent out sample-accurately.
+//!
+//! A plugin that generates events (e.g. an arpeggiator or a sequencer) pushes them into an
+//! [`OutputEventQueue`] while rendering. At the end of `render_buffer`, the events that fall
+//! within the buffer are drained, in chronological order, and written to the output of the
+//! back-end with their offset within the buffer. Events that are scheduled after the end
+//! of the buffer remain in the queue and are drained in one of the next buffers.
+//!
+//! How the events are written depends on the back-end:
+//! * Jack: pass the events to the `JackHost` context, together with the index of the
+//!   midi output port:
+//!   `queue.drain(number_of_frames, |event| context.handle_event(Indexed::new(0, event)))`
+//! * VST: use a [`VstMidiOutput`], which sends the events to the host:
+//!   `midi_output.send(&mut queue, number_of_frames, context)`
+//! * combined: pass the events to the `EventSinkContext` context, which writes them to an
+//!   `EventSink` (e.g. a `.mid` file with a `MidiWriterSink`):
+//!   `queue.drain(number_of_frames, |event| context.handle_event(event))`
+//!
+//! ```
+//! use rsynth::event::output_event_queue::OutputEventQueue;
+//! use rsynth::event::{RawMidiEvent, Timed};
+//!
+//! let mut queue = OutputEventQueue::new(64);
+//! // While rendering a buffer of 128 frames:
+//! queue.push(Timed::new(100, RawMidiEvent::new(&[0x80, 60, 0]))).expect("not full");
+//! queue.push(Timed::new(10, RawMidiEvent::new(&[0x90, 60, 100]))).expect("not full");
+//! queue.push(Timed::new(130, RawMidiEvent::new(&[0x90, 62, 100]))).expect("not full");
+//!
+//! let mut times = Vec::new();
+//! queue.drain(128, |event| times.push(event.time_in_frames));
+//! assert_eq!(times, vec![10, 100]);
+//! // The last event is at frame 2 of the next buffer.
+//! assert_eq!(queue.len(), 1);
+//! ```
+//!
+//! [`OutputEventQueue`]: ./struct.OutputEventQueue.html
+//! [`VstMidiOutput`]: ../../backend/vst_backend/struct.VstMidiOutput.html
+use super::{EventHandler, Timed};
+use crate::utilities::rt_log::Level;
+
+/// A queue for the events that are generated by the plugin.
+/// See the [module level documentation] for more information.
+///
+/// Real-time safety
+/// ----------------
+/// Pushing and draining events do not allocate memory.
+///
+/// [module level documentation]: ./index.html
+pub struct OutputEventQueue<E> {
+    // Sorted by time; events with the same time are in the order they were pushed.
+    events: Vec<Timed<E>>,
+}
+
+impl<E> OutputEventQueue<E> {
+    /// Create a new queue that can hold `capacity` events.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(capacity: usize) -> Self {
+        OutputEventQueue {
+            events: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Push an event; the time is relative to the start of the current buffer.
+    /// Return the event as an error when the queue is full.
+    pub fn push(&mut self, event: Timed<E>) -> Result<(), Timed<E>> {
+        if self.events.len() == self.events.capacity() {
+            return Err(event);
+        }
+        let index = self
+            .events
+            .partition_point(|e| e.time_in_frames <= event.time_in_frames);
+        self.events.insert(index, event);
+        Ok(())
+    }
+
+    /// Pass the events that fall within a buffer of `number_of_frames` frames to `handler`,
+    /// in chronological order.
+    /// The time of the remaining events is made relative to the start of the next buffer.
+    pub fn drain<F>(&mut self, number_of_frames: usize, mut handler: F)
+    where
+        F: FnMut(Timed<E>),
+    {
+        let number_of_frames = number_of_frames.min(u32::MAX as usize) as u32;
+        let end = self
+            .events
+            .partition_point(|e| e.time_in_frames < number_of_frames);
+        for event in self.events.drain(..end) {
+            handler(event);
+        }
+        for event in self.events.iter_mut() {
+            event.time_in_frames -= number_of_frames;
+        }
+    }
+
+    /// Remove all events.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// The number of events in the queue.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Return `true` when the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+impl<E> EventHandler<Timed<E>> for OutputEventQueue<E> {
+    fn handle_event(&mut self, event: Timed<E>) {
+        if self.push(event).is_err() {
+            crate::rt_log!(
+                Level::Warn,
+                "Output event queue is full, dropping an event."
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(queue: &mut OutputEventQueue<u8>, number_of_frames: usize) -> Vec<(u32, u8)> {
+        let mut events = Vec::new();
+        queue.drain(number_of_frames, |e| {
+            events.push((e.time_in_frames, e.event))
+        });
+        events
+    }
+
+    #[test]
+    fn events_are_drained_in_chronological_order() {
+        let mut queue = OutputEventQueue::new(8);
+        for (time, event) in [(5, 1), (2, 2), (5, 3), (0, 4)].iter() {
+            queue.push(Timed::new(*time, *event)).expect("not full");
+        }
+        assert_eq!(drain(&mut queue, 10), vec![(0, 4), (2, 2), (5, 1), (5, 3)]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn later_events_are_kept_for_the_next_buffer() {
+        let mut queue = OutputEventQueue::new(8);
+        queue.push(Timed::new(3, 1)).expect("not full");
+        queue.push(Timed::new(12, 2)).expect("not full");
+        queue.push(Timed::new(25, 3)).expect("not full");
+        assert_eq!(drain(&mut queue, 10), vec![(3, 1)]);
+        assert_eq!(drain(&mut queue, 10), vec![(2, 2)]);
+        assert_eq!(drain(&mut queue, 10), vec![(5, 3)]);
+    }
+
+    #[test]
+    fn full_queue_returns_the_event() {
+        let mut queue = OutputEventQueue::new(1);
+        assert!(queue.push(Timed::new(0, 1)).is_ok());
+        match queue.push(Timed::new(0, 2)) {
+            Err(event) => assert_eq!(event.event, 2),
+            Ok(()) => panic!("Expected an error."),
+        }
+        assert_eq!(queue.len(), 1);
+    }
+}
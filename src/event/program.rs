@@ -0,0 +1,349 @@
+//! Combine bank select and program change messages into one event.
+//!
+//! Selecting a program on a MIDI device takes up to three messages on the same channel:
+//! bank select MSB (controller 0), bank select LSB (controller 32) and program change.
+//! A [`ProgramTracker`] remembers the bank select messages of every channel and turns every
+//! program change into a [`ProgramSelected`] event with the complete bank number.
+//! Wrap the plugin in a [`ProgramSelector`] to deliver these events to the plugin.
+//!
+//! The tracker follows the MIDI specification:
+//! * The bank select messages only take effect at the next program change of the same
+//!   channel; a bank select without program change does not generate an event.
+//! * The bank select MSB and LSB may be received in either order.
+//! * The bank is remembered: a program change without bank select selects the program in the
+//!   bank that was selected last. When only the MSB is sent, the LSB is unchanged and vice versa.
+//! * "Reset all controllers" does not reset the bank; a system reset resets all channels to
+//!   bank 0.
+//!
+//! Example
+//! -------
+//! ```
+//! use rsynth::event::program::{ProgramSelected, ProgramTracker};
+//! use rsynth::event::RawMidiEvent;
+//!
+//! let mut tracker = ProgramTracker::new();
+//! // Bank select LSB before MSB on channel 2.
+//! assert_eq!(tracker.track(&RawMidiEvent::new(&[0xB1, 32, 3])), None);
+//! assert_eq!(tracker.track(&RawMidiEvent::new(&[0xB1, 0, 1])), None);
+//! let selected = tracker.track(&RawMidiEvent::new(&[0xC1, 5, 0]));
+//! assert_eq!(selected, Some(ProgramSelected { channel: 1, bank: 131, program: 5 }));
+//! ```
+//!
+//! [`ProgramTracker`]: ./struct.ProgramTracker.html
+//! [`ProgramSelected`]: ./struct.ProgramSelected.html
+//! [`ProgramSelector`]: ./struct.ProgramSelector.html
+use super::{ContextualEventHandler, EventHandler, RawMidiEvent, Timed};
+#[cfg(feature = "std")]
+use crate::meta::Meta;
+use crate::{AudioHandler, AudioRenderer, ContextualAudioRenderer};
+use midi_consts::channel_event::control_change::{BANK_SELECT_LSB, BANK_SELECT_MSB};
+use midi_consts::channel_event::{
+    CONTROL_CHANGE, EVENT_TYPE_MASK, MIDI_CHANNEL_MASK, PROGRAM_CHANGE,
+};
+
+/// The status byte of a system reset.
+pub const SYSTEM_RESET: u8 = 0xFF;
+
+/// A program has been selected on a channel.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ProgramSelected {
+    /// The MIDI channel, `0..16`.
+    pub channel: u8,
+    /// The 14-bit bank number: the bank select MSB times 128 plus the bank select LSB.
+    pub bank: u16,
+    /// The program number, `0..128`.
+    pub program: u8,
+}
+
+impl ProgramSelected {
+    /// The bank select MSB (controller 0), which is the bank number of e.g. SoundFonts.
+    pub fn bank_msb(&self) -> u8 {
+        (self.bank >> 7) as u8
+    }
+
+    /// The bank select LSB (controller 32).
+    pub fn bank_lsb(&self) -> u8 {
+        (self.bank & 0x7F) as u8
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+struct ChannelState {
+    bank_msb: u8,
+    bank_lsb: u8,
+    selected: Option<ProgramSelected>,
+}
+
+/// Tracks the bank select and program change messages of all channels.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+#[derive(Clone, Debug, Default)]
+pub struct ProgramTracker {
+    channels: [ChannelState; 16],
+}
+
+impl ProgramTracker {
+    /// Create a new tracker; all channels start in bank 0, without a selected program.
+    pub fn new() -> Self {
+        ProgramTracker::default()
+    }
+
+    /// Track the event and return the selected program if the event is a program change.
+    pub fn track(&mut self, event: &RawMidiEvent) -> Option<ProgramSelected> {
+        let data = event.data();
+        if data[0] == SYSTEM_RESET {
+            self.reset();
+            return None;
+        }
+        let channel = data[0] & MIDI_CHANNEL_MASK;
+        let state = &mut self.channels[channel as usize];
+        match data[0] & EVENT_TYPE_MASK {
+            CONTROL_CHANGE => {
+                match data[1] {
+                    BANK_SELECT_MSB => state.bank_msb = data[2] & 0x7F,
+                    BANK_SELECT_LSB => state.bank_lsb = data[2] & 0x7F,
+                    _ => {}
+                }
+                None
+            }
+            PROGRAM_CHANGE => {
+                let selected = ProgramSelected {
+                    channel,
+                    bank: (state.bank_msb as u16) << 7 | state.bank_lsb as u16,
+                    program: data[1] & 0x7F,
+                };
+                state.selected = Some(selected);
+                Some(selected)
+            }
+            _ => None,
+        }
+    }
+
+    /// The program that was selected last on the given channel (`0..16`), or `None` if no
+    /// program has been selected on this channel.
+    ///
+    /// The bank is the bank in which the program was selected: bank select messages that
+    /// have been received after the program change are not taken into account.
+    pub fn selected(&self, channel: u8) -> Option<ProgramSelected> {
+        self.channels[(channel & MIDI_CHANNEL_MASK) as usize].selected
+    }
+
+    /// Forget the banks and programs of all channels.
+    pub fn reset(&mut self) {
+        *self = ProgramTracker::default();
+    }
+}
+
+/// Wraps a plugin and delivers a [`ProgramSelected`] event to the plugin after every
+/// program change.
+///
+/// All midi events are forwarded to the wrapped plugin unchanged, including the bank select
+/// and program change messages. The `ProgramSelected` event has the same time as the
+/// program change. The audio, the sample rate and the meta-data are forwarded as well.
+///
+/// See the [module level documentation] for more information.
+///
+/// [`ProgramSelected`]: ./struct.ProgramSelected.html
+/// [module level documentation]: ./index.html
+pub struct ProgramSelector<P> {
+    inner: P,
+    tracker: ProgramTracker,
+}
+
+impl<P> ProgramSelector<P> {
+    pub fn new(inner: P) -> Self {
+        ProgramSelector {
+            inner,
+            tracker: ProgramTracker::new(),
+        }
+    }
+
+    /// The tracker, e.g. to query the program that is selected on a channel.
+    pub fn tracker(&self) -> &ProgramTracker {
+        &self.tracker
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+}
+
+impl<P> EventHandler<Timed<RawMidiEvent>> for ProgramSelector<P>
+where
+    P: EventHandler<Timed<RawMidiEvent>> + EventHandler<Timed<ProgramSelected>>,
+{
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+        let selected = self.tracker.track(&event.event);
+        self.inner.handle_event(event);
+        if let Some(selected) = selected {
+            self.inner
+                .handle_event(Timed::new(event.time_in_frames, selected));
+        }
+    }
+}
+
+impl<P, Context> ContextualEventHandler<Timed<RawMidiEvent>, Context> for ProgramSelector<P>
+where
+    P: ContextualEventHandler<Timed<RawMidiEvent>, Context>
+        + ContextualEventHandler<Timed<ProgramSelected>, Context>,
+{
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>, context: &mut Context) {
+        let selected = self.tracker.track(&event.event);
+        self.inner.handle_event(event, context);
+        if let Some(selected) = selected {
+            self.inner
+                .handle_event(Timed::new(event.time_in_frames, selected), context);
+        }
+    }
+}
+
+impl<P, S> AudioRenderer<S> for ProgramSelector<P>
+where
+    P: AudioRenderer<S>,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        self.inner.render_buffer(inputs, outputs);
+    }
+}
+
+impl<P, S, Context> ContextualAudioRenderer<S, Context> for ProgramSelector<P>
+where
+    P: ContextualAudioRenderer<S, Context>,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], context: &mut Context) {
+        self.inner.render_buffer(inputs, outputs, context);
+    }
+}
+
+impl<P> AudioHandler for ProgramSelector<P>
+where
+    P: AudioHandler,
+{
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.inner.set_sample_rate(sample_rate);
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.inner.set_max_buffer_size(max_buffer_size);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<P> Meta for ProgramSelector<P>
+where
+    P: Meta,
+{
+    type MetaData = P::MetaData;
+
+    fn meta(&self) -> &Self::MetaData {
+        self.inner.meta()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(data: &[u8]) -> RawMidiEvent {
+        RawMidiEvent::new(data)
+    }
+
+    #[test]
+    fn bank_takes_effect_at_the_next_program_change() {
+        let mut tracker = ProgramTracker::new();
+        assert_eq!(
+            tracker.track(&event(&[0xC0, 7, 0])),
+            Some(ProgramSelected {
+                channel: 0,
+                bank: 0,
+                program: 7
+            })
+        );
+        assert_eq!(tracker.track(&event(&[0xB0, 0, 2])), None);
+        // The bank select does not change the program that is currently selected.
+        assert_eq!(tracker.selected(0).map(|selected| selected.bank), Some(0));
+        let selected = tracker.track(&event(&[0xC0, 8, 0])).unwrap();
+        assert_eq!(selected.bank, 256);
+        assert_eq!(selected.bank_msb(), 2);
+        assert_eq!(selected.bank_lsb(), 0);
+
+        // The bank is remembered; only the LSB changes.
+        tracker.track(&event(&[0xB0, 32, 5]));
+        let selected = tracker.track(&event(&[0xC0, 9, 0])).unwrap();
+        assert_eq!((selected.bank_msb(), selected.bank_lsb()), (2, 5));
+        let selected = tracker.track(&event(&[0xC0, 10, 0])).unwrap();
+        assert_eq!((selected.bank_msb(), selected.bank_lsb()), (2, 5));
+    }
+
+    #[test]
+    fn channels_are_independent_and_resets_are_handled() {
+        let mut tracker = ProgramTracker::new();
+        tracker.track(&event(&[0xB3, 0, 1]));
+        assert_eq!(tracker.track(&event(&[0xC2, 1, 0])).unwrap().bank, 0);
+        assert_eq!(tracker.track(&event(&[0xC3, 1, 0])).unwrap().bank, 128);
+        assert_eq!(tracker.selected(0), None);
+
+        // Reset all controllers does not reset the bank.
+        tracker.track(&event(&[0xB3, 121, 0]));
+        assert_eq!(tracker.track(&event(&[0xC3, 2, 0])).unwrap().bank, 128);
+
+        tracker.track(&event(&[SYSTEM_RESET]));
+        assert_eq!(tracker.selected(3), None);
+        assert_eq!(tracker.track(&event(&[0xC3, 2, 0])).unwrap().bank, 0);
+    }
+
+    #[derive(Default)]
+    struct Collector {
+        raw: Vec<(u32, u8)>,
+        selected: Vec<(u32, ProgramSelected)>,
+    }
+
+    impl EventHandler<Timed<RawMidiEvent>> for Collector {
+        fn handle_event(&mut self, timed: Timed<RawMidiEvent>) {
+            self.raw.push((timed.time_in_frames, timed.event.data()[0]));
+        }
+    }
+
+    impl EventHandler<Timed<ProgramSelected>> for Collector {
+        fn handle_event(&mut self, timed: Timed<ProgramSelected>) {
+            self.selected.push((timed.time_in_frames, timed.event));
+        }
+    }
+
+    #[test]
+    fn selector_forwards_all_events_and_adds_program_selected() {
+        let mut selector = ProgramSelector::new(Collector::default());
+        selector.handle_event(Timed::new(3, event(&[0xB0, 0, 1])));
+        selector.handle_event(Timed::new(4, event(&[0xC0, 2, 0])));
+        selector.handle_event(Timed::new(5, event(&[0x90, 60, 100])));
+        assert_eq!(selector.inner().raw, vec![(3, 0xB0), (4, 0xC0), (5, 0x90)]);
+        assert_eq!(
+            selector.inner().selected,
+            vec![(
+                4,
+                ProgramSelected {
+                    channel: 0,
+                    bank: 128,
+                    program: 2
+                }
+            )]
+        );
+        assert_eq!(
+            selector
+                .tracker()
+                .selected(0)
+                .map(|selected| selected.program),
+            Some(2)
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! Schedule events in musical time.
+//!
+//! The [`BeatScheduler`] stores events at positions in beats (quarter notes). For every audio
+//! buffer, it converts the events that fall within the buffer to [`Timed`] events with an
+//! offset in frames, taking into account the tempo and the loop range of the transport.
+//! The events are not removed, so that they are played again when the transport loops or is
+//! moved back.
+//!
+//! ```
+//! use rsynth::event::scheduler::BeatScheduler;
+//! use rsynth::event::RawMidiEvent;
+//! use rsynth::transport::{MusicalPosition, TimeSignature, TransportState};
+//!
+//! let mut scheduler = BeatScheduler::with_capacity(128);
+//! let position = MusicalPosition::new(3, 2.5).in_beats(TimeSignature::default());
+//! scheduler
+//!     .schedule(position, RawMidiEvent::new(&[0x90, 60, 100]))
+//!     .expect("the scheduler is not full");
+//!
+//! // In the audio thread, for every buffer:
+//! let transport = TransportState {
+//!     is_playing: true,
+//!     position_in_beats: 9.0,
+//!     tempo: 120.0,
+//!     ..TransportState::default()
+//! };
+//! let mut events = Vec::new();
+//! scheduler.process_transport(&transport, 44100.0, 44100, |event| events.push(event));
+//! // Half a beat at 120 beats per minute is a quarter of a second.
+//! assert_eq!(events[0].time_in_frames, 11025);
+//! ```
+//!
+//! [`BeatScheduler`]: ./struct.BeatScheduler.html
+//! [`Timed`]: ../struct.Timed.html
+use super::Timed;
+use crate::transport::{ConstantTempo, LoopRange, TempoMapping, TransportState};
+
+/// Stores events at positions in beats and converts them to [`Timed`] events.
+/// See the [module level documentation] for more information.
+///
+/// Real-time safety
+/// ----------------
+/// Scheduling events and processing buffers do not allocate memory, as long as the number
+/// of scheduled events does not exceed the capacity.
+///
+/// [`Timed`]: ../struct.Timed.html
+/// [module level documentation]: ./index.html
+pub struct BeatScheduler<E> {
+    // Sorted by position; events at the same position are in the order they were scheduled.
+    events: Vec<(f64, E)>,
+}
+
+impl<E: Copy> BeatScheduler<E> {
+    /// Create a new scheduler that can hold `capacity` events.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn with_capacity(capacity: usize) -> Self {
+        BeatScheduler {
+            events: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Schedule an event at the given position in beats.
+    /// Return the event as an error when the scheduler is full.
+    pub fn schedule(&mut self, position_in_beats: f64, event: E) -> Result<(), E> {
+        if self.events.len() == self.events.capacity() {
+            return Err(event);
+        }
+        let index = self
+            .events
+            .partition_point(|(position, _)| *position <= position_in_beats);
+        self.events.insert(index, (position_in_beats, event));
+        Ok(())
+    }
+
+    /// Remove all events for which `predicate` returns `false`.
+    pub fn retain<F: FnMut(f64, &E) -> bool>(&mut self, mut predicate: F) {
+        self.events
+            .retain(|(position, event)| predicate(*position, event));
+    }
+
+    /// Remove all events.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// The number of scheduled events.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Return `true` when no events are scheduled.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Pass the events that fall within the current buffer to `handler`, in chronological order.
+    ///
+    /// This uses the position, the tempo and the loop range of the transport.
+    /// Nothing happens when the transport is not playing.
+    pub fn process_transport<F>(
+        &self,
+        transport: &TransportState,
+        sample_rate: f64,
+        number_of_frames: usize,
+        handler: F,
+    ) where
+        F: FnMut(Timed<E>),
+    {
+        if transport.is_playing {
+            self.process(
+                transport.position_in_beats,
+                transport.loop_range,
+                &ConstantTempo::new(transport.tempo),
+                sample_rate,
+                number_of_frames,
+                handler,
+            );
+        }
+    }
+
+    /// Pass the events that fall within the current buffer to `handler`, in chronological order.
+    ///
+    /// The buffer starts at `position_in_beats` and `tempo` is used to convert between beats
+    /// and frames, so that tempo changes within the buffer are taken into account.
+    /// When the end of `loop_range` is reached within the buffer, playback continues at the
+    /// start of the loop range. A loop range that is shorter than one frame takes one frame.
+    /// Return the position in beats at the end of the buffer, which is the position at the
+    /// start of the next buffer when the transport is driven by the plugin itself.
+    pub fn process<T, F>(
+        &self,
+        position_in_beats: f64,
+        loop_range: Option<LoopRange>,
+        tempo: &T,
+        sample_rate: f64,
+        number_of_frames: usize,
+        mut handler: F,
+    ) -> f64
+    where
+        T: TempoMapping,
+        F: FnMut(Timed<E>),
+    {
+        let loop_range = loop_range.filter(|range| range.end > range.start);
+        let mut position = position_in_beats;
+        let mut first_frame = 0;
+        loop {
+            let start_seconds = tempo.seconds_at_beat(position);
+            let remaining_frames = number_of_frames - first_frame;
+            let end_seconds = start_seconds + remaining_frames as f64 / sample_rate;
+            let mut end = tempo.beat_at_seconds(end_seconds);
+            let mut loops = false;
+            if let Some(range) = loop_range {
+                if position < range.end && end >= range.end {
+                    end = range.end;
+                    loops = true;
+                }
+            }
+            let start_index = self.events.partition_point(|(p, _)| *p < position);
+            let end_index = self.events.partition_point(|(p, _)| *p < end);
+            for (event_position, event) in &self.events[start_index..end_index] {
+                let offset = (tempo.seconds_at_beat(*event_position) - start_seconds) * sample_rate;
+                let frame = (first_frame + offset.max(0.0) as usize).min(number_of_frames - 1);
+                handler(Timed::new(frame as u32, *event));
+            }
+            if !loops {
+                return end;
+            }
+            let range = loop_range.expect("`loops` is only set when there is a loop range");
+            let frames_until_loop_end =
+                ((tempo.seconds_at_beat(range.end) - start_seconds) * sample_rate).round() as usize;
+            // Advance by at least one frame, also when the loop range is shorter than one frame.
+            first_frame += frames_until_loop_end.max(1);
+            position = range.start;
+            if first_frame >= number_of_frames {
+                return position;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::TempoMap;
+
+    fn collect<T: TempoMapping>(
+        scheduler: &BeatScheduler<u8>,
+        position: f64,
+        loop_range: Option<LoopRange>,
+        tempo: &T,
+        number_of_frames: usize,
+    ) -> (Vec<(u32, u8)>, f64) {
+        let mut events = Vec::new();
+        // One beat per second at 60 BPM and a sample rate of 10 Hz: 10 frames per beat.
+        let end = scheduler.process(position, loop_range, tempo, 10.0, number_of_frames, |e| {
+            events.push((e.time_in_frames, e.event))
+        });
+        (events, end)
+    }
+
+    fn scheduler() -> BeatScheduler<u8> {
+        let mut scheduler = BeatScheduler::with_capacity(8);
+        for (position, event) in [(0.0, 1), (1.5, 2), (3.0, 3), (1.5, 4)].iter() {
+            scheduler.schedule(*position, *event).expect("not full");
+        }
+        scheduler
+    }
+
+    #[test]
+    fn events_within_the_buffer_are_converted_to_frames() {
+        let scheduler = scheduler();
+        let tempo = ConstantTempo::new(60.0);
+        let (events, end) = collect(&scheduler, 0.0, None, &tempo, 20);
+        assert_eq!(events, vec![(0, 1), (15, 2), (15, 4)]);
+        assert_eq!(end, 2.0);
+        let (events, _) = collect(&scheduler, 2.0, None, &tempo, 20);
+        assert_eq!(events, vec![(10, 3)]);
+    }
+
+    #[test]
+    fn looping_wraps_around_within_the_buffer() {
+        let scheduler = scheduler();
+        let tempo = ConstantTempo::new(60.0);
+        let loop_range = Some(LoopRange::new(1.0, 2.0));
+        let (events, end) = collect(&scheduler, 1.0, loop_range, &tempo, 25);
+        assert_eq!(events, vec![(5, 2), (5, 4), (15, 2), (15, 4)]);
+        assert_eq!(end, 1.5);
+    }
+
+    #[test]
+    fn loop_range_shorter_than_one_frame_advances_one_frame_per_loop() {
+        let mut scheduler = BeatScheduler::with_capacity(1);
+        scheduler.schedule(1.0, 1).expect("not full");
+        let tempo = ConstantTempo::new(60.0);
+        let loop_range = Some(LoopRange::new(1.0, 1.001));
+        let (events, end) = collect(&scheduler, 1.0, loop_range, &tempo, 4);
+        assert_eq!(events, vec![(0, 1), (1, 1), (2, 1), (3, 1)]);
+        assert_eq!(end, 1.0);
+    }
+
+    #[test]
+    fn tempo_changes_within_the_buffer_are_taken_into_account() {
+        let scheduler = scheduler();
+        let mut tempo = TempoMap::new(60.0);
+        // From beat 1 on, two beats per second.
+        tempo.set_tempo(1.0, 120.0);
+        let (events, end) = collect(&scheduler, 0.0, None, &tempo, 20);
+        // The event at beat 3 is at the start of the next buffer.
+        assert_eq!(events, vec![(0, 1), (12, 2), (12, 4)]);
+        assert_eq!(end, 3.0);
+    }
+
+    #[test]
+    fn stopped_transport_produces_no_events() {
+        let scheduler = scheduler();
+        let mut count = 0;
+        scheduler.process_transport(&TransportState::default(), 10.0, 100, |_| count += 1);
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn full_scheduler_returns_the_event() {
+        let mut scheduler = BeatScheduler::with_capacity(1);
+        assert_eq!(scheduler.schedule(0.0, 1), Ok(()));
+        assert_eq!(scheduler.schedule(0.0, 2), Err(2));
+        assert_eq!(scheduler.len(), 1);
+    }
+}
@@ -0,0 +1,247 @@
+//! Reassemble system exclusive messages that are split over several fragments.
+//!
+//! Some back-ends and hardware interfaces deliver long system exclusive ("SysEx") messages
+//! in fragments, possibly spread over several process callbacks.
+//! A [`SysExReassembler`] collects the fragments and returns one complete [`SysExEvent`]
+//! when the end of the message has been received.
+//!
+//! [`SysExReassembler`]: ./struct.SysExReassembler.html
+//! [`SysExEvent`]: ../struct.SysExEvent.html
+use super::SysExEvent;
+
+/// The status byte that starts a system exclusive message.
+pub const START_OF_EXCLUSIVE: u8 = 0xF0;
+/// The status byte that ends a system exclusive message.
+pub const END_OF_EXCLUSIVE: u8 = 0xF7;
+
+/// What a [`SysExReassembler`] does with messages that are longer than its maximum size.
+///
+/// [`SysExReassembler`]: ./struct.SysExReassembler.html
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SysExOverflowPolicy {
+    /// Discard the message.
+    Discard,
+    /// Return the first bytes of the message, followed by [`END_OF_EXCLUSIVE`], so that the
+    /// message has the maximum size.
+    ///
+    /// [`END_OF_EXCLUSIVE`]: ./constant.END_OF_EXCLUSIVE.html
+    Truncate,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum State {
+    Idle,
+    Collecting,
+    Overflowed,
+    Complete,
+}
+
+/// Collect the fragments of a system exclusive message.
+///
+/// Call [`push`] for each fragment. The first fragment must start with [`START_OF_EXCLUSIVE`].
+/// When the fragment that contains [`END_OF_EXCLUSIVE`] is pushed, the complete message
+/// (including the start and end bytes) is returned.
+///
+/// * Real-time messages (status bytes `0xF8` and higher) may occur in the middle of a system
+///   exclusive message; they are skipped.
+/// * Any other status byte ends the message prematurely, the incomplete message is discarded.
+/// * Bytes that are not part of a system exclusive message are ignored, so you should only push
+///   data that is not handled otherwise.
+///
+/// Real-time safety
+/// ----------------
+/// Pushing fragments does not allocate memory: all memory is allocated in [`new`].
+///
+/// Example
+/// -------
+/// ```
+/// use rsynth::event::sysex::{SysExOverflowPolicy, SysExReassembler};
+///
+/// let mut reassembler = SysExReassembler::new(1024, SysExOverflowPolicy::Discard);
+/// assert!(reassembler.push(&[0xF0, 0x7E, 0x00]).is_none());
+/// let event = reassembler.push(&[0x06, 0x01, 0xF7]).expect("The message is complete.");
+/// assert_eq!(event.data(), &[0xF0, 0x7E, 0x00, 0x06, 0x01, 0xF7]);
+/// ```
+///
+/// [`push`]: #method.push
+/// [`new`]: #method.new
+/// [`START_OF_EXCLUSIVE`]: ./constant.START_OF_EXCLUSIVE.html
+/// [`END_OF_EXCLUSIVE`]: ./constant.END_OF_EXCLUSIVE.html
+pub struct SysExReassembler {
+    buffer: Vec<u8>,
+    max_size: usize,
+    overflow_policy: SysExOverflowPolicy,
+    state: State,
+}
+
+impl SysExReassembler {
+    /// Create a new `SysExReassembler` for messages of at most `max_size` bytes,
+    /// including the start and end bytes.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory and cannot be used in a real-time context.
+    ///
+    /// # Panics
+    /// Panics if `max_size < 2`.
+    pub fn new(max_size: usize, overflow_policy: SysExOverflowPolicy) -> Self {
+        assert!(max_size >= 2);
+        Self {
+            buffer: Vec::with_capacity(max_size),
+            max_size,
+            overflow_policy,
+            state: State::Idle,
+        }
+    }
+
+    /// The maximum size of a message, including the start and end bytes.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Return `true` if the start of a message has been received, but not the end.
+    pub fn is_collecting(&self) -> bool {
+        match self.state {
+            State::Collecting | State::Overflowed => true,
+            State::Idle | State::Complete => false,
+        }
+    }
+
+    /// Forget the message that is currently being collected, if any.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.state = State::Idle;
+    }
+
+    /// Push the next fragment.
+    ///
+    /// Return the complete message when `fragment` contains the end of the message.
+    /// Bytes after the end of the message are ignored.
+    pub fn push(&mut self, fragment: &[u8]) -> Option<SysExEvent<'_>> {
+        if self.state == State::Complete {
+            self.reset();
+        }
+        for byte in fragment.iter().cloned() {
+            match byte {
+                START_OF_EXCLUSIVE => {
+                    if self.is_collecting() {
+                        warn!("Discarding incomplete system exclusive message.");
+                    }
+                    self.buffer.clear();
+                    self.buffer.push(START_OF_EXCLUSIVE);
+                    self.state = State::Collecting;
+                }
+                0xF8..=0xFF => {
+                    // Real-time messages may be interleaved with system exclusive messages.
+                }
+                END_OF_EXCLUSIVE => match self.state {
+                    State::Idle | State::Complete => {}
+                    State::Collecting => {
+                        self.buffer.push(END_OF_EXCLUSIVE);
+                        self.state = State::Complete;
+                        break;
+                    }
+                    State::Overflowed => match self.overflow_policy {
+                        SysExOverflowPolicy::Discard => {
+                            self.reset();
+                        }
+                        SysExOverflowPolicy::Truncate => {
+                            self.buffer.push(END_OF_EXCLUSIVE);
+                            self.state = State::Complete;
+                            break;
+                        }
+                    },
+                },
+                0x80..=0xFF => {
+                    if self.is_collecting() {
+                        warn!("Discarding incomplete system exclusive message.");
+                        self.reset();
+                    }
+                }
+                _ => match self.state {
+                    State::Idle | State::Complete | State::Overflowed => {}
+                    State::Collecting => {
+                        // Keep room for the end byte.
+                        if self.buffer.len() + 1 < self.max_size {
+                            self.buffer.push(byte);
+                        } else {
+                            warn!(
+                                "System exclusive message longer than {} bytes.",
+                                self.max_size
+                            );
+                            self.state = State::Overflowed;
+                        }
+                    }
+                },
+            }
+        }
+        if self.state == State::Complete {
+            Some(SysExEvent::new(&self.buffer))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_in_one_fragment() {
+        let mut reassembler = SysExReassembler::new(16, SysExOverflowPolicy::Discard);
+        let event = reassembler
+            .push(&[0xF0, 1, 2, 0xF7])
+            .map(|e| e.data().to_vec());
+        assert_eq!(event, Some(vec![0xF0, 1, 2, 0xF7]));
+        assert!(!reassembler.is_collecting());
+    }
+
+    #[test]
+    fn message_in_several_fragments_with_real_time_messages() {
+        let mut reassembler = SysExReassembler::new(16, SysExOverflowPolicy::Discard);
+        assert!(reassembler.push(&[0xF0, 1]).is_none());
+        assert!(reassembler.is_collecting());
+        assert!(reassembler.push(&[2, 0xF8, 3]).is_none());
+        let event = reassembler.push(&[4, 0xF7]).map(|e| e.data().to_vec());
+        assert_eq!(event, Some(vec![0xF0, 1, 2, 3, 4, 0xF7]));
+
+        // The next message starts from scratch.
+        let event = reassembler
+            .push(&[0xF0, 5, 0xF7])
+            .map(|e| e.data().to_vec());
+        assert_eq!(event, Some(vec![0xF0, 5, 0xF7]));
+    }
+
+    #[test]
+    fn fragments_without_start_are_ignored() {
+        let mut reassembler = SysExReassembler::new(16, SysExOverflowPolicy::Discard);
+        assert!(reassembler.push(&[1, 2, 0xF7]).is_none());
+        assert!(!reassembler.is_collecting());
+    }
+
+    #[test]
+    fn other_status_byte_discards_incomplete_message() {
+        let mut reassembler = SysExReassembler::new(16, SysExOverflowPolicy::Discard);
+        assert!(reassembler.push(&[0xF0, 1, 2]).is_none());
+        assert!(reassembler.push(&[0x90, 60, 100]).is_none());
+        assert!(!reassembler.is_collecting());
+        assert!(reassembler.push(&[3, 0xF7]).is_none());
+    }
+
+    #[test]
+    fn overflow_with_discard() {
+        let mut reassembler = SysExReassembler::new(4, SysExOverflowPolicy::Discard);
+        assert!(reassembler.push(&[0xF0, 1, 2, 3]).is_none());
+        assert!(reassembler.push(&[4, 0xF7]).is_none());
+        assert!(!reassembler.is_collecting());
+    }
+
+    #[test]
+    fn overflow_with_truncate() {
+        let mut reassembler = SysExReassembler::new(4, SysExOverflowPolicy::Truncate);
+        assert!(reassembler.push(&[0xF0, 1, 2, 3]).is_none());
+        let event = reassembler.push(&[4, 0xF7]).map(|e| e.data().to_vec());
+        assert_eq!(event, Some(vec![0xF0, 1, 2, 0xF7]));
+    }
+}
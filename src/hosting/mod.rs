@@ -0,0 +1,263 @@
+//! Host external VST 2.4 plugins.
+//!
+//! Support is only enabled if you compile with the "vst-hosting" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! A [`HostedVstPlugin`] loads a VST plugin from a dynamic library and implements
+//! the renderer and event handler traits of `rsynth`, so that it can for instance be used
+//! with the [`combined`] backend for offline rendering and testing.
+//!
+//! Channel mapping
+//! ===============
+//! Audio input `i` is passed to the input `i` of the plugin. If the plugin has more inputs,
+//! silence is passed to the remaining inputs.
+//! The output `i` of the plugin is written to audio output `i`. If the plugin has fewer
+//! outputs, the remaining audio outputs are filled with silence.
+//!
+//! Only `f32` samples are supported.
+//!
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`HostedVstPlugin`]: ./struct.HostedVstPlugin.html
+//! [`combined`]: ../backend/combined/index.html
+use crate::event::{ContextualEventHandler, EventHandler, RawMidiEvent, Timed};
+use crate::{
+    AudioHandler, AudioHandlerMeta, AudioRenderer, CommonPluginMeta, ContextualAudioRenderer,
+};
+use std::cmp;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use vecstorage::VecStorage;
+use vst::buffer::SendEventBuffer;
+use vst::event::MidiEvent as VstMidiEvent;
+use vst::host::{Host, HostBuffer, PluginInstance, PluginLoader};
+use vst::plugin::{Plugin, PluginParameters};
+
+pub use vst::host::PluginLoadError;
+
+struct RsynthHost;
+
+impl Host for RsynthHost {}
+
+/// A VST plugin that is loaded from a dynamic library.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct HostedVstPlugin {
+    instance: PluginInstance,
+    parameters: Arc<dyn PluginParameters>,
+    // Fields are dropped in declaration order, so the instance and the parameters are dropped
+    // before the loader.
+    _loader: PluginLoader<RsynthHost>,
+    name: String,
+    number_of_parameters: usize,
+    number_of_inputs: usize,
+    number_of_outputs: usize,
+    max_block_size: usize,
+    host_buffer: HostBuffer<f32>,
+    silence: Vec<f32>,
+    scratch: Vec<Vec<f32>>,
+    inputs: VecStorage<&'static [f32]>,
+    outputs: VecStorage<&'static [f32]>,
+    events: Vec<Timed<RawMidiEvent>>,
+    event_buffer: SendEventBuffer,
+}
+
+impl HostedVstPlugin {
+    /// Load the VST plugin from the dynamic library at the given path.
+    ///
+    /// # Parameters
+    /// * `max_block_size`: the maximum number of frames that are passed to the plugin at once.
+    ///   Larger buffers are split.
+    /// * `max_number_of_events`: the maximum number of events that can be handled per buffer.
+    ///   Events above this number are ignored.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory and loads a dynamic library.
+    pub fn load(
+        path: &Path,
+        max_block_size: usize,
+        max_number_of_events: usize,
+    ) -> Result<Self, PluginLoadError> {
+        assert!(max_block_size > 0);
+        let mut loader = PluginLoader::load(path, Arc::new(Mutex::new(RsynthHost)))?;
+        let mut instance = loader.instance()?;
+        let info = instance.get_info();
+        info!("Loaded VST plugin {} from {}", info.name, path.display());
+        let number_of_inputs = cmp::max(info.inputs, 0) as usize;
+        let number_of_outputs = cmp::max(info.outputs, 0) as usize;
+        let number_of_parameters = cmp::max(info.parameters, 0) as usize;
+        instance.init();
+        let parameters = instance.get_parameter_object();
+        instance.set_block_size(max_block_size as i64);
+        instance.resume();
+        Ok(Self {
+            instance,
+            parameters,
+            _loader: loader,
+            name: info.name,
+            number_of_parameters,
+            number_of_inputs,
+            number_of_outputs,
+            max_block_size,
+            host_buffer: HostBuffer::new(number_of_inputs, number_of_outputs),
+            silence: vec![0.0; max_block_size],
+            scratch: vec![vec![0.0; max_block_size]; number_of_outputs],
+            inputs: VecStorage::with_capacity(number_of_inputs),
+            outputs: VecStorage::with_capacity(number_of_outputs),
+            events: Vec::with_capacity(max_number_of_events),
+            event_buffer: SendEventBuffer::new(max_number_of_events),
+        })
+    }
+
+    /// The number of parameters of the plugin.
+    pub fn number_of_parameters(&self) -> usize {
+        self.number_of_parameters
+    }
+
+    /// Get the name of the parameter with the given index.
+    pub fn parameter_name(&self, index: usize) -> String {
+        self.parameters.get_parameter_name(index as i32)
+    }
+
+    /// Get the value of the parameter with the given index.
+    /// VST parameters are normalized: the value is in the range `0.0..=1.0`.
+    pub fn get_parameter(&self, index: usize) -> f32 {
+        self.parameters.get_parameter(index as i32)
+    }
+
+    /// Set the value of the parameter with the given index.
+    /// VST parameters are normalized: the value should be in the range `0.0..=1.0`.
+    pub fn set_parameter(&self, index: usize, value: f32) {
+        self.parameters.set_parameter(index as i32, value);
+    }
+
+    fn render_chunk(
+        &mut self,
+        inputs: &[&[f32]],
+        outputs: &mut [&mut [f32]],
+        start: usize,
+        end: usize,
+    ) {
+        let length = end - start;
+        let events = self
+            .events
+            .iter()
+            .filter(|event| (start..end).contains(&(event.time_in_frames as usize)))
+            .map(|event| VstMidiEvent {
+                data: *event.event.data(),
+                delta_frames: (event.time_in_frames as usize - start) as i32,
+                live: true,
+                note_length: None,
+                note_offset: None,
+                detune: 0,
+                note_off_velocity: 0,
+            });
+        self.event_buffer.store_events(events);
+        self.instance.process_events(self.event_buffer.events());
+
+        let mut plugin_inputs = self.inputs.vec_guard();
+        for index in 0..self.number_of_inputs {
+            plugin_inputs.push(match inputs.get(index) {
+                Some(input) => &input[start..end],
+                None => &self.silence[..length],
+            });
+        }
+        let mut plugin_outputs = self.outputs.vec_guard();
+        let mut audio_outputs = outputs.iter_mut();
+        for scratch in self.scratch.iter_mut() {
+            plugin_outputs.push(match audio_outputs.next() {
+                Some(output) => &mut output[start..end],
+                None => &mut scratch[..length],
+            });
+        }
+        for output in audio_outputs {
+            for sample in output[start..end].iter_mut() {
+                *sample = 0.0;
+            }
+        }
+
+        let mut buffer = self
+            .host_buffer
+            .bind(plugin_inputs.as_slice(), plugin_outputs.as_mut_slice());
+        self.instance.process(&mut buffer);
+    }
+}
+
+impl CommonPluginMeta for HostedVstPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl AudioHandlerMeta for HostedVstPlugin {
+    fn max_number_of_audio_inputs(&self) -> usize {
+        self.number_of_inputs
+    }
+
+    fn max_number_of_audio_outputs(&self) -> usize {
+        self.number_of_outputs
+    }
+}
+
+impl AudioHandler for HostedVstPlugin {
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.instance.suspend();
+        self.instance.set_sample_rate(sample_rate as f32);
+        self.instance.resume();
+    }
+}
+
+impl AudioRenderer<f32> for HostedVstPlugin {
+    fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        let number_of_frames = match (outputs.first(), inputs.first()) {
+            (Some(output), _) => output.len(),
+            (None, Some(input)) => input.len(),
+            (None, None) => return,
+        };
+        let mut start = 0;
+        while start < number_of_frames {
+            let end = cmp::min(start + self.max_block_size, number_of_frames);
+            self.render_chunk(inputs, outputs, start, end);
+            start = end;
+        }
+        self.events.clear();
+    }
+}
+
+impl<Context> ContextualAudioRenderer<f32, Context> for HostedVstPlugin {
+    fn render_buffer(
+        &mut self,
+        inputs: &[&[f32]],
+        outputs: &mut [&mut [f32]],
+        _context: &mut Context,
+    ) {
+        AudioRenderer::render_buffer(self, inputs, outputs);
+    }
+}
+
+impl EventHandler<Timed<RawMidiEvent>> for HostedVstPlugin {
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+        if self.events.len() < self.events.capacity() {
+            self.events.push(event);
+        } else {
+            warn!(
+                "Too many events for the hosted VST plugin, ignoring {:?}",
+                event
+            );
+        }
+    }
+}
+
+impl<Context> ContextualEventHandler<Timed<RawMidiEvent>, Context> for HostedVstPlugin {
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>, _context: &mut Context) {
+        EventHandler::handle_event(self, event);
+    }
+}
+
+impl Drop for HostedVstPlugin {
+    fn drop(&mut self) {
+        self.instance.suspend();
+    }
+}
@@ -0,0 +1,564 @@
+//! Reload the plugin from a dynamic library while it is running, during development.
+//!
+//! Support is only enabled if you compile with the "hotreload" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! Instead of restarting the application after every change of the DSP code, build the plugin
+//! as a dynamic library that exports its constructor with the [`hotreload_entry_point!`] macro,
+//! and run it in a [`HotReloadHost`]. The host is a renderer that can be used with any
+//! back-end. [`HotReloadHost::watch`] starts a thread that checks the library for changes;
+//! after every rebuild, it loads the new library, hands over the state of the running plugin
+//! with the [`PluginState`] trait, and swaps the instance.
+//!
+//! Real-time safety
+//! ----------------
+//! The audio thread never waits for the reload: the new instance is created and initialized
+//! in the watching thread, and the old instance and library are dropped there as well.
+//! While the instances are being swapped, the audio thread renders silence and ignores
+//! events, which typically lasts less than one buffer.
+//!
+//! Limitations
+//! -----------
+//! The plugin and the host exchange Rust trait objects, so both must be compiled with the same
+//! compiler and the same version of `rsynth`. This is intended for development only; do not
+//! use it in a release.
+//!
+//! Example
+//! -------
+//! In the plugin crate, with `crate-type = ["cdylib"]`:
+//! ```
+//! # #[macro_use]
+//! # extern crate rsynth;
+//! use rsynth::event::{EventHandler, RawMidiEvent, Timed};
+//! use rsynth::parameter::{ParameterInfo, ParameterStore};
+//! use rsynth::{AudioHandler, AudioRenderer, InvalidState, PluginState};
+//!
+//! struct Gain {
+//!     parameters: ParameterStore,
+//! }
+//!
+//! impl AudioRenderer<f32> for Gain {
+//!     fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+//!         let gain = self.parameters.get(0);
+//!         for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+//!             for (input_sample, output_sample) in input.iter().zip(output.iter_mut()) {
+//!                 *output_sample = gain * input_sample;
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! impl EventHandler<Timed<RawMidiEvent>> for Gain {
+//!     fn handle_event(&mut self, _event: Timed<RawMidiEvent>) {}
+//! }
+//!
+//! impl AudioHandler for Gain {
+//!     fn set_sample_rate(&mut self, _sample_rate: f64) {}
+//! }
+//!
+//! // The state is handed over to the new instance after a rebuild.
+//! impl PluginState for Gain {
+//!     fn save_state(&self) -> Vec<u8> {
+//!         self.parameters.save_state()
+//!     }
+//!
+//!     fn load_state(&mut self, state: &[u8]) -> Result<(), InvalidState> {
+//!         self.parameters.load_state(state)
+//!     }
+//! }
+//!
+//! hotreload_entry_point!(Gain {
+//!     parameters: ParameterStore::new(vec![ParameterInfo::new("Gain", "", 0.0, 1.0, 0.5)]),
+//! });
+//! # fn main() {}
+//! ```
+//! In the application that runs the plugin:
+//! ```no_run
+//! use rsynth::hotreload::HotReloadHost;
+//! use std::time::Duration;
+//!
+//! let host = HotReloadHost::load("target/debug/libmy_synth.so").expect("the plugin can be loaded");
+//! let _watcher = host.watch(Duration::from_millis(500));
+//! // Run `host` with a back-end; the plugin is reloaded after every `cargo build`.
+//! ```
+//!
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`hotreload_entry_point!`]: ../macro.hotreload_entry_point.html
+//! [`HotReloadHost`]: ./struct.HotReloadHost.html
+//! [`HotReloadHost::watch`]: ./struct.HotReloadHost.html#method.watch
+//! [`PluginState`]: ../trait.PluginState.html
+use crate::event::{ContextualEventHandler, EventHandler, RawMidiEvent, Timed};
+use crate::{AudioHandler, AudioRenderer, ContextualAudioRenderer, PluginState};
+use libloading::{Library, Symbol};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+/// The name of the function that is exported by [`hotreload_entry_point!`].
+///
+/// [`hotreload_entry_point!`]: ../macro.hotreload_entry_point.html
+pub const ENTRY_POINT: &[u8] = b"rsynth_hotreload_create";
+
+/// A plugin that can be reloaded by a [`HotReloadHost`].
+///
+/// This trait is implemented automatically for every type that implements its super traits.
+///
+/// [`HotReloadHost`]: ./struct.HotReloadHost.html
+pub trait HotReloadPlugin:
+    AudioRenderer<f32> + EventHandler<Timed<RawMidiEvent>> + AudioHandler + PluginState + Send
+{
+}
+
+impl<T> HotReloadPlugin for T where
+    T: AudioRenderer<f32> + EventHandler<Timed<RawMidiEvent>> + AudioHandler + PluginState + Send
+{
+}
+
+/// The signature of the function that is exported by [`hotreload_entry_point!`].
+///
+/// [`hotreload_entry_point!`]: ../macro.hotreload_entry_point.html
+pub type CreateFunction = fn() -> Box<dyn HotReloadPlugin>;
+
+/// Export the constructor of the plugin from a dynamic library, so that the library can be
+/// loaded by a [`HotReloadHost`].
+///
+/// The argument is an expression that creates the plugin.
+///
+/// [`HotReloadHost`]: ./hotreload/struct.HotReloadHost.html
+#[macro_export]
+macro_rules! hotreload_entry_point {
+    ($constructor:expr) => {
+        #[no_mangle]
+        pub fn rsynth_hotreload_create() -> Box<dyn $crate::hotreload::HotReloadPlugin> {
+            Box::new($constructor)
+        }
+    };
+}
+
+/// The error type that represents the errors you can get when (re)loading a plugin.
+#[derive(Debug)]
+pub enum HotReloadError {
+    /// The library could not be copied.
+    Io(io::Error),
+    /// The library could not be loaded or does not export the [`ENTRY_POINT`].
+    ///
+    /// [`ENTRY_POINT`]: ./constant.ENTRY_POINT.html
+    Library(libloading::Error),
+}
+
+impl Display for HotReloadError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            HotReloadError::Io(e) => write!(f, "I/O error: {}", e),
+            HotReloadError::Library(e) => write!(f, "error while loading the library: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HotReloadError {}
+
+struct LoadedPlugin {
+    // Fields are dropped in declaration order, so the plugin is dropped before the library.
+    plugin: Box<dyn HotReloadPlugin>,
+    _library: Library,
+}
+
+// The settings that have been passed to the host, to be applied to every new instance.
+#[derive(Default)]
+struct Settings {
+    sample_rate: Option<f64>,
+    max_buffer_size: Option<usize>,
+}
+
+struct Shared {
+    path: PathBuf,
+    // The audio thread only uses `try_lock` on this mutex.
+    current: Mutex<LoadedPlugin>,
+    // Kept separately, so that reading them for a new instance does not interrupt the audio.
+    settings: Mutex<Settings>,
+    number_of_reloads: AtomicUsize,
+}
+
+/// Runs a plugin that is loaded from a dynamic library and reloads it when the library changes.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct HotReloadHost {
+    shared: Arc<Shared>,
+}
+
+impl HotReloadHost {
+    /// Load the plugin from the dynamic library at the given path.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory and loads a dynamic library.
+    pub fn load<P: Into<PathBuf>>(path: P) -> Result<Self, HotReloadError> {
+        let path = path.into();
+        let loaded = load_library(&path)?;
+        Ok(HotReloadHost {
+            shared: Arc::new(Shared {
+                path,
+                current: Mutex::new(loaded),
+                settings: Mutex::new(Settings::default()),
+                number_of_reloads: AtomicUsize::new(0),
+            }),
+        })
+    }
+
+    /// Reload the plugin now and hand over the state of the running instance.
+    ///
+    /// If the library cannot be loaded, the running instance is kept.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory and loads a dynamic library.
+    pub fn reload(&self) -> Result<(), HotReloadError> {
+        self.shared.reload()
+    }
+
+    /// The number of times the plugin has been reloaded successfully.
+    pub fn number_of_reloads(&self) -> usize {
+        self.shared.number_of_reloads.load(Ordering::Relaxed)
+    }
+
+    /// Start a thread that checks the library for changes every `poll_interval` and reloads
+    /// the plugin when it has changed. The thread stops when the returned [`Watcher`] is dropped.
+    ///
+    /// A change is only picked up when the library has not changed during the last poll
+    /// interval, so that a library that is still being written is not loaded.
+    ///
+    /// [`Watcher`]: ./struct.Watcher.html
+    pub fn watch(&self, poll_interval: Duration) -> Watcher {
+        let shared = self.shared.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            let mut loaded_version = modification_time(&shared.path);
+            let mut previous_version = loaded_version;
+            while !thread_stop.load(Ordering::Relaxed) {
+                thread::sleep(poll_interval);
+                let version = modification_time(&shared.path);
+                if version != loaded_version && version == previous_version {
+                    loaded_version = version;
+                    match shared.reload() {
+                        Ok(()) => info!("Reloaded `{}`.", shared.path.display()),
+                        Err(error) => {
+                            error!("Failed to reload `{}`: {:?}", shared.path.display(), error)
+                        }
+                    }
+                }
+                previous_version = version;
+            }
+        });
+        Watcher {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    fn render(&mut self, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        match self.shared.current.try_lock() {
+            Ok(mut current) => current.plugin.render_buffer(inputs, outputs),
+            Err(_) => {
+                for output in outputs.iter_mut() {
+                    for sample in output.iter_mut() {
+                        *sample = 0.0;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Shared {
+    fn reload(&self) -> Result<(), HotReloadError> {
+        let mut loaded = load_library(&self.path)?;
+        // Holding this lock until the swap prevents that the settings change in between.
+        let settings = self.settings.lock().unwrap();
+        if let Some(sample_rate) = settings.sample_rate {
+            loaded.plugin.set_sample_rate(sample_rate);
+        }
+        if let Some(max_buffer_size) = settings.max_buffer_size {
+            loaded.plugin.set_max_buffer_size(max_buffer_size);
+        }
+        let old = {
+            let mut current = self.current.lock().unwrap();
+            let state = current.plugin.save_state();
+            if loaded.plugin.load_state(&state).is_err() {
+                warn!("The new instance cannot read the state of the old instance.");
+            }
+            std::mem::replace(&mut *current, loaded)
+        };
+        drop(settings);
+        // The old instance and its library are dropped here, outside the lock.
+        drop(old);
+        self.number_of_reloads.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Stops watching the library when dropped.
+pub struct Watcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl AudioRenderer<f32> for HotReloadHost {
+    fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        self.render(inputs, outputs);
+    }
+}
+
+impl<Context> ContextualAudioRenderer<f32, Context> for HotReloadHost {
+    fn render_buffer(
+        &mut self,
+        inputs: &[&[f32]],
+        outputs: &mut [&mut [f32]],
+        _context: &mut Context,
+    ) {
+        self.render(inputs, outputs);
+    }
+}
+
+impl EventHandler<Timed<RawMidiEvent>> for HotReloadHost {
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+        if let Ok(mut current) = self.shared.current.try_lock() {
+            current.plugin.handle_event(event);
+        }
+    }
+}
+
+impl<Context> ContextualEventHandler<Timed<RawMidiEvent>, Context> for HotReloadHost {
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>, _context: &mut Context) {
+        EventHandler::handle_event(self, event);
+    }
+}
+
+impl AudioHandler for HotReloadHost {
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        let mut settings = self.shared.settings.lock().unwrap();
+        settings.sample_rate = Some(sample_rate);
+        self.shared
+            .current
+            .lock()
+            .unwrap()
+            .plugin
+            .set_sample_rate(sample_rate);
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        let mut settings = self.shared.settings.lock().unwrap();
+        settings.max_buffer_size = Some(max_buffer_size);
+        self.shared
+            .current
+            .lock()
+            .unwrap()
+            .plugin
+            .set_max_buffer_size(max_buffer_size);
+    }
+}
+
+fn load_library(path: &Path) -> Result<LoadedPlugin, HotReloadError> {
+    // The dynamic loader returns the library that is already loaded when the same path is
+    // loaded again, so every version is loaded from a copy with a unique name.
+    static NUMBER_OF_COPIES: AtomicUsize = AtomicUsize::new(0);
+    let copy_number = NUMBER_OF_COPIES.fetch_add(1, Ordering::Relaxed);
+    let mut file_name = format!("rsynth-hotreload-{}-{}", process::id(), copy_number);
+    if let Some(extension) = path.extension() {
+        file_name.push('.');
+        file_name.push_str(&extension.to_string_lossy());
+    }
+    let copy = std::env::temp_dir().join(file_name);
+    fs::copy(path, &copy).map_err(HotReloadError::Io)?;
+    let result = create_plugin(&copy);
+    // The copy can be removed while it is loaded, except on Windows.
+    let _ = fs::remove_file(&copy);
+    result
+}
+
+fn create_plugin(path: &Path) -> Result<LoadedPlugin, HotReloadError> {
+    unsafe {
+        let library = Library::new(path).map_err(HotReloadError::Library)?;
+        let plugin = {
+            let create: Symbol<CreateFunction> =
+                library.get(ENTRY_POINT).map_err(HotReloadError::Library)?;
+            create()
+        };
+        Ok(LoadedPlugin {
+            plugin,
+            _library: library,
+        })
+    }
+}
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Recorder {
+        sample_rate: Option<f64>,
+        max_buffer_size: Option<usize>,
+        events: Vec<Timed<RawMidiEvent>>,
+    }
+
+    impl AudioRenderer<f32> for Recorder {
+        fn render_buffer(&mut self, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+            for output in outputs.iter_mut() {
+                for sample in output.iter_mut() {
+                    *sample = self.events.len() as f32;
+                }
+            }
+        }
+    }
+
+    impl EventHandler<Timed<RawMidiEvent>> for Recorder {
+        fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+            self.events.push(event);
+        }
+    }
+
+    impl AudioHandler for Recorder {
+        fn set_sample_rate(&mut self, sample_rate: f64) {
+            self.sample_rate = Some(sample_rate);
+        }
+
+        fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+            self.max_buffer_size = Some(max_buffer_size);
+        }
+    }
+
+    impl PluginState for Recorder {
+        fn save_state(&self) -> Vec<u8> {
+            Vec::new()
+        }
+
+        fn load_state(&mut self, _state: &[u8]) -> Result<(), crate::InvalidState> {
+            Ok(())
+        }
+    }
+
+    // A host that runs a `Recorder` without loading a library from disk.
+    #[cfg(unix)]
+    fn host() -> HotReloadHost {
+        HotReloadHost {
+            shared: Arc::new(Shared {
+                path: PathBuf::new(),
+                current: Mutex::new(LoadedPlugin {
+                    plugin: Box::new(Recorder {
+                        sample_rate: None,
+                        max_buffer_size: None,
+                        events: Vec::new(),
+                    }),
+                    _library: libloading::os::unix::Library::this().into(),
+                }),
+                settings: Mutex::new(Settings::default()),
+                number_of_reloads: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn forwards_events_and_audio_to_the_plugin() {
+        let mut host = host();
+        host.set_sample_rate(44100.0);
+        host.set_max_buffer_size(256);
+        EventHandler::handle_event(
+            &mut host,
+            Timed::new(0, RawMidiEvent::new(&[0x90, 60, 100])),
+        );
+        let mut output = vec![0.0; 4];
+        AudioRenderer::render_buffer(&mut host, &[], &mut [&mut output]);
+        assert_eq!(output, vec![1.0; 4]);
+        let settings = host.shared.settings.lock().unwrap();
+        assert_eq!(settings.sample_rate, Some(44100.0));
+        assert_eq!(settings.max_buffer_size, Some(256));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn renders_silence_and_ignores_events_while_the_plugin_is_swapped() {
+        let mut host = host();
+        let shared = host.shared.clone();
+        let _current = shared.current.lock().unwrap();
+        EventHandler::handle_event(
+            &mut host,
+            Timed::new(0, RawMidiEvent::new(&[0x90, 60, 100])),
+        );
+        let mut output = vec![1.0; 4];
+        AudioRenderer::render_buffer(&mut host, &[], &mut [&mut output]);
+        assert_eq!(output, vec![0.0; 4]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_the_running_instance_when_reloading_fails() {
+        let mut host = host();
+        let missing = std::env::temp_dir().join("rsynth-hotreload-test-missing.so");
+        let _ = fs::remove_file(&missing);
+        Arc::get_mut(&mut host.shared).unwrap().path = missing;
+        assert!(matches!(host.reload(), Err(HotReloadError::Io(_))));
+        assert_eq!(host.number_of_reloads(), 0);
+        EventHandler::handle_event(
+            &mut host,
+            Timed::new(0, RawMidiEvent::new(&[0x90, 60, 100])),
+        );
+        let mut output = vec![0.0; 4];
+        AudioRenderer::render_buffer(&mut host, &[], &mut [&mut output]);
+        assert_eq!(output, vec![1.0; 4]);
+    }
+
+    #[test]
+    fn loading_a_missing_library_is_an_io_error() {
+        let missing = std::env::temp_dir().join("rsynth-hotreload-test-missing.so");
+        let _ = fs::remove_file(&missing);
+        match HotReloadHost::load(missing) {
+            Err(error @ HotReloadError::Io(_)) => {
+                assert!(error.to_string().starts_with("I/O error: "))
+            }
+            _ => panic!("expected an I/O error"),
+        }
+    }
+
+    #[test]
+    fn loading_a_file_that_is_not_a_library_is_a_library_error() {
+        let path = std::env::temp_dir().join(format!(
+            "rsynth-hotreload-test-{}-not-a-library.so",
+            process::id()
+        ));
+        fs::write(&path, b"not a library").unwrap();
+        let result = HotReloadHost::load(path.clone());
+        let _ = fs::remove_file(&path);
+        match result {
+            Err(error @ HotReloadError::Library(_)) => {
+                assert!(error
+                    .to_string()
+                    .starts_with("error while loading the library: "))
+            }
+            _ => panic!("expected a library error"),
+        }
+    }
+}
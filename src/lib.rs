@@ -6,9 +6,16 @@
 //! ## Back-ends
 //! `rsynth` currently supports the following back-ends:
 //!
+//! * [`au`] Audio Units for macOS (behind the `backend-au` feature)
 //! * [`combined`] combine different back-ends for audio input, audio output, midi input and
 //!     midi output, mostly for offline rendering and testing (behind various features)
+//! * [`cpal`] standalone audio on ALSA, CoreAudio and WASAPI (behind the `backend-cpal`
+//!     feature) and ASIO (behind the `backend-asio` feature)
+//! * [`i2s`] I2S DACs on embedded targets, without the `std` feature (behind the
+//!     `backend-i2s` feature)
 //! * [`jack`] (behind the `backend-jack` feature)
+//! * [`ladspa`] audio-only effects (behind the `backend-ladspa` feature)
+//! * [`lv2`] (behind the `backend-lv2` feature)
 //! * [`vst`] (behind the backend-vst)
 //!
 //! See the documentation of each back-end for more information.
@@ -29,6 +36,11 @@
 //! the back-end. Because the trait is generic, the application or plugin can have a generic implementation
 //! as well that can be used by different back-ends.
 //!
+//! Plugins with more than one group of inputs or outputs (e.g. a sidechain input) can
+//! look up these groups by name with the [`bus`] module.
+//! Channel counts beyond stereo (surround, ambisonics) are described with the
+//! [`channel_layout`] module.
+//!
 //! ## Meta-data
 //! There are a number of traits that an application or plugin needs to implement in order to define meta-data.
 //! Every plugin should implement these, but it can be tedious, so you can implement these
@@ -42,9 +54,11 @@
 //! * [`MidiHandlerMeta`]
 //!     * Number of midi ports
 //! * [`CommonAudioPortMeta`]
-//!     * Names of the audio in and out ports
+//!     * Names and channel layouts of the audio in and out ports
 //! * [`CommonPluginMeta`]
 //!     * Name of the plugin or application
+//! * [`PluginIdentityMeta`]
+//!     * Vendor, unique id, version and category of the plugin
 //!
 //! Additionally, back-ends can require extra trait bounds related to meta-data.
 //!
@@ -68,22 +82,119 @@
 //! * [`Timed<T>`]: a timed event
 //! * [`Indexed<T>`]:
 //!
+//! Events that are generated by the plugin can be collected in an [`OutputEventQueue`],
+//! so that the back-end can send them out with the correct offset within the buffer.
+//!
 //! ## Utilities
 //! Utilities are are types that you can include to perform several common tasks for the
 //! plugin or application:
 //!
+//! * channel routing: converting between mono and stereo and rearranging channels
+//! * compensation delay: delaying the outputs of a renderer to align parallel chains with
+//!   different latencies
+//! * cpu watchdog: reducing the CPU load (e.g. by dropping the quietest voices) when rendering
+//!   nearly exceeds the real-time budget
+//! * debug renderer: detecting NaN, infinite, denormal and over-range samples in the output
+//! * denormals: avoiding CPU spikes caused by denormal numbers (the JACK and VST back-ends do
+//!   this automatically when compiled with the `flush-denormals` feature)
+//! * disk streaming: playing samples that are too large for memory by streaming them from disk
+//! * input monitor: mixing the audio input into the output, e.g. to audition an effect plugin
+//!   in a standalone application
+//! * pitch detector: detecting the pitch of a monophonic signal, e.g. for a tuner
 //! * polyphony: managing of different voices
+//! * random: a seedable pseudo-random number generator that can be used in a real-time context
+//! * recorder: recording audio from the audio thread to a `.wav` file (behind the `recorder`
+//!   feature)
+//! * rt_log: logging from the real-time thread with the `rt_log!` macro
+//! * scale quantizer: mapping incoming notes to the notes of a scale or a chord
+//! * soa: storing per-voice data as a "structure of arrays", so that rendering loops
+//!   iterate over contiguous memory
+//! * spectral: processing audio in the frequency domain with a short-time Fourier transform,
+//!   e.g. for a spectral freeze or a spectral gate
+//! * spectrum analyzer: computing the spectrum of the audio in a background thread, e.g. for
+//!   display in a GUI
+//! * thread pool: rendering voices in parallel (behind the `thread-pool` feature)
+//! * timing filter: quantizing incoming notes to a grid or humanizing their timing and velocity
+//! * trash can: dropping values that the audio thread no longer uses in a background thread
+//!   (behind the `trash-can` feature)
+//! * trivial renderers: renderers that output silence, pass their input through or ignore it,
+//!   e.g. as placeholders or in tests
+//! * wavetable: a band-limited wavetable oscillator and loading of `.wav` and `.wt` wavetables
+//! * worker: offloading jobs such as loading samples from the audio thread to a background
+//!   thread (behind the `worker` feature)
+//!
+//! ## `no_std`
+//! The `std` feature is enabled by default. Without it, `rsynth` is `no_std` and does not
+//! use an allocator, so that the voice and event logic can run on embedded targets (e.g. an
+//! STM32-based Daisy or an RP2040). Only the following is available then:
+//!
+//! * the [`AudioRenderer`], [`ContextualAudioRenderer`], [`AudioHandler`] and [`Latency`] traits
+//! * the event types and traits in the [`event`] module, including the [`FixedEventQueue`]
+//! * the [`envelope`] module
+//! * the [`polyphony`] module
+//! * the [`HostInterface`] trait and the [`i2s`] back-end (behind the `backend-i2s` feature),
+//!   which sends the audio to an I2S DAC
+//!
+//! All other modules and all other back-ends require the `std` feature.
+//!
+//! ## Musical time
+//! The [`transport`] module describes the state of the transport and converts between beats and
+//! seconds. Events can be scheduled at positions in beats with the [`BeatScheduler`].
+//!
+//! ## Hosting
+//! External VST plugins can be loaded with the [`hosting`] module (behind the `vst-hosting`
+//! feature), e.g. to include them in offline renders and tests.
+//! During development, the [`hotreload`] module (behind the `hotreload` feature) reloads the
+//! plugin from a dynamic library after every rebuild, without restarting the application.
+//!
+//! ## Parameters
+//! The values of parameters can be shared lock-free between the audio thread and other
+//! threads with the [`parameter`] module, which can also generate random patches.
+//! The [`editor`] module provides a generic graphical editor for them (behind the `editor`
+//! feature).
+//!
+//! ## Analysis
+//! Peak and RMS levels and spectra can be published from the audio thread to e.g. a graphical
+//! editor or a remote control with the [`analysis`] module.
+//!
+//! ## Remote control
+//! Applications can be remote-controlled with OSC messages, see the [`osc`] module
+//! (behind the `osc` feature), or from a web browser, see the [`websocket`] module
+//! (behind the `websocket` feature).
+//!
+//! ## Session management
+//! Standalone applications can take part in sessions of the New Session Manager on Linux,
+//! which saves and restores their state, see the [`nsm`] module (behind the `nsm` feature).
 //!
 //! [`Plugin`]: ./trait.Plugin.html
+//! [`au`]: ./backend/au_backend/index.html
+//! [`i2s`]: ./backend/i2s/index.html
 //! [`jack`]: ./backend/jack_backend/index.html
+//! [`ladspa`]: ./backend/ladspa_backend/index.html
+//! [`lv2`]: ./backend/lv2_backend/index.html
 //! [`vst`]: ./backend/vst_backend/index.html
 //! [`combined`]: ./backend/combined/index.html
+//! [`cpal`]: ./backend/cpal/index.html
+//! [`bus`]: ./bus/index.html
+//! [`channel_layout`]: ./channel_layout/index.html
+//! [`osc`]: ./osc/index.html
+//! [`nsm`]: ./nsm/index.html
+//! [`hosting`]: ./hosting/index.html
+//! [`hotreload`]: ./hotreload/index.html
+//! [`websocket`]: ./websocket/index.html
+//! [`parameter`]: ./parameter/index.html
+//! [`editor`]: ./editor/index.html
+//! [`analysis`]: ./analysis/index.html
+//! [`transport`]: ./transport/index.html
+//! [`BeatScheduler`]: ./event/scheduler/struct.BeatScheduler.html
+//! [`OutputEventQueue`]: ./event/output_event_queue/struct.OutputEventQueue.html
 //! [`EventHandler`]: ./event/trait.EventHandler.html
 //! [`RawMidiEvent`]: ./event/struct.RawMidiEvent.html
 //! [`SysExEvent`]: ./event/struct.SysExEvent.html
 //! [`Timed<T>`]: ./event/struct.Timed.html
 //! [`Indexed<T>`]: ./event/struct.Indexed.html
 //! [`CommonPluginMeta`]: ./trait.CommonPluginMeta.html
+//! [`PluginIdentityMeta`]: ./trait.PluginIdentityMeta.html
 //! [`AudioHandlerMeta`]: ./trait.AudioHandlerMeta.html
 //! [`MidiHandlerMeta`]: ./trait.MidiHandlerMeta.html
 //! [`CommonAudioPortMeta`]: ./trait.CommonAudioPortMeta.html
@@ -91,35 +202,116 @@
 //! [`AudioRenderer`]: ./trait.AudioRenderer.html
 //! [`ContextualEventHandler`]: ./event/trait.ContextualEventHandler.html
 //! [`EventHandler`]: ./event/trait.EventHandler.html
+//! [`AudioHandler`]: ./trait.AudioHandler.html
+//! [`Latency`]: ./trait.Latency.html
+//! [`event`]: ./event/index.html
+//! [`FixedEventQueue`]: ./event/fixed_event_queue/struct.FixedEventQueue.html
+//! [`envelope`]: ./envelope/index.html
+//! [`polyphony`]: ./utilities/polyphony/index.html
+//! [`HostInterface`]: ./backend/trait.HostInterface.html
+#![cfg_attr(not(feature = "std"), no_std)]
 
-#[macro_use]
-extern crate log;
+#[cfg(feature = "std")]
 extern crate asprim;
+#[cfg_attr(feature = "std", macro_use)]
+extern crate log;
+#[cfg(feature = "std")]
 extern crate num_traits;
+#[cfg(feature = "std")]
 extern crate vecstorage;
 
+#[cfg(feature = "dasp")]
+extern crate dasp;
+#[cfg(feature = "editor")]
+extern crate egui;
+#[cfg(feature = "editor")]
+extern crate egui_baseview;
+#[cfg(feature = "backend-file-flac")]
+extern crate flacenc;
 #[cfg(feature = "backend-file-hound")]
 extern crate hound;
 #[cfg(feature = "backend-jack")]
 extern crate jack;
-#[cfg(feature = "backend-file-hound")]
+#[cfg(all(feature = "thread-pool", unix))]
+extern crate libc;
+#[cfg(feature = "midi-io")]
+extern crate midir;
+#[cfg(feature = "backend-combined-midly")]
+extern crate midly;
+#[cfg(feature = "backend-file-opus")]
+extern crate ogg;
+#[cfg(feature = "backend-file-opus")]
+extern crate opus;
+#[cfg(feature = "editor")]
+extern crate raw_window_handle;
+#[cfg(any(
+    feature = "osc",
+    feature = "midi-io",
+    feature = "rtp-midi",
+    feature = "trash-can",
+    feature = "worker"
+))]
+extern crate ringbuf;
+#[cfg(feature = "osc")]
+extern crate rosc;
+#[cfg(any(
+    feature = "backend-file-hound",
+    feature = "backend-file-flac",
+    feature = "backend-file-opus"
+))]
 extern crate sample;
-#[cfg(feature = "backend-vst")]
+#[cfg(feature = "websocket")]
+extern crate serde;
+#[cfg(feature = "websocket")]
+extern crate serde_json;
+#[cfg(feature = "websocket")]
+extern crate tungstenite;
+#[cfg(any(feature = "backend-vst", feature = "vst-hosting"))]
 extern crate vst;
 
 #[macro_use]
 extern crate doc_comment;
 
-use crate::meta::{AudioPort, General, Meta, MidiPort, Name, Port};
+#[cfg(feature = "std")]
+use crate::channel_layout::ChannelLayout;
+#[cfg(feature = "std")]
+use crate::meta::{AudioPort, Category, General, Identity, Meta, MidiPort, Name, Port};
 
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "std")]
 #[macro_use]
 pub mod buffer;
 pub mod backend;
+#[cfg(feature = "std")]
+pub mod bus;
+#[cfg(feature = "std")]
+pub mod channel_layout;
+#[cfg(feature = "editor")]
+pub mod editor;
 pub mod envelope;
 pub mod event;
+#[cfg(feature = "vst-hosting")]
+pub mod hosting;
+#[cfg(feature = "hotreload")]
+pub mod hotreload;
+#[cfg(feature = "std")]
 pub mod meta;
+#[cfg(feature = "std")]
+pub mod meter;
+#[cfg(feature = "nsm")]
+pub mod nsm;
+#[cfg(feature = "osc")]
+pub mod osc;
+#[cfg(feature = "std")]
+pub mod parameter;
+#[cfg(feature = "std")]
 pub mod test_utilities;
+#[cfg(feature = "std")]
+pub mod transport;
 pub mod utilities;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
 doctest!("../README.md");
 
@@ -138,6 +330,7 @@ doctest!("../README.md");
 /// [`Meta`]: ./meta/trait.Meta.html
 /// [`render_buffer`]: ./trait.AudioHandlerMeta.html#tymethod.render_buffer
 /// [`AudioRenderer`]: ./trait.AudioHandlerMeta.html
+#[cfg(feature = "std")]
 pub trait AudioHandlerMeta {
     /// The maximum number of audio inputs supported.
     /// This method should return the same value every time it is called.
@@ -149,6 +342,21 @@ pub trait AudioHandlerMeta {
 }
 
 /// Define how sample-rate changes are handled.
+///
+/// Sample-rate changes
+/// -------------------
+/// The sample rate may change while the plugin is running, e.g. when the user changes the
+/// settings of the audio interface or when the host switches to offline rendering.
+/// All backends follow the same protocol:
+/// * `set_sample_rate` is called before the first event is handled and before `render_buffer`
+///   is called for the first time,
+/// * when the sample rate changes, `set_sample_rate` is called again,
+/// * `set_sample_rate` is never called during a call to `render_buffer` or while handling an
+///   event, so the events and the buffer of one process cycle always have the same sample rate.
+///
+/// Everything that depends on the sample rate (filter coefficients, envelope rates,
+/// delay line lengths, ...) should be recomputed in `set_sample_rate`.
+/// Note that `set_sample_rate` may be called from the real-time thread.
 pub trait AudioHandler {
     /// Called when the sample-rate changes.
     /// The backend should ensure that this function is called before
@@ -160,12 +368,26 @@ pub trait AudioHandler {
     // TODO: Looking at the WikiPedia list https://en.wikipedia.org/wiki/Sample_rate, it seems that
     // TODO: there are no fractional sample rates. Maybe change the data type into u32?
     fn set_sample_rate(&mut self, sample_rate: f64);
+
+    /// Called when the maximum number of frames per buffer changes.
+    /// Backends that know the maximum buffer size call this before `render_buffer` is called
+    /// for the first time and again whenever the maximum buffer size changes, but never
+    /// during a call to `render_buffer` or while handling an event.
+    /// Renderers can use it to resize their internal scratch buffers, so that they
+    /// do not need to allocate memory in `render_buffer`.
+    ///
+    /// The default implementation does nothing.
+    ///
+    /// # Parameters
+    /// `max_buffer_size`: the maximum number of frames that is passed to `render_buffer`.
+    fn set_max_buffer_size(&mut self, _max_buffer_size: usize) {}
 }
 
 /// Define the maximum number of midi inputs and the maximum number of midi outputs.
 /// This trait can be more conveniently implemented by implementing the [`Meta`] trait.
 ///
 /// [`Meta`]: ./meta/trait.Meta.html
+#[cfg(feature = "std")]
 pub trait MidiHandlerMeta {
     /// The maximum number of midi inputs supported.
     /// This method should return the same value for subsequent calls.
@@ -176,6 +398,113 @@ pub trait MidiHandlerMeta {
     fn max_number_of_midi_outputs(&self) -> usize;
 }
 
+/// Define the latency of a renderer.
+///
+/// The latency is the number of frames by which the output lags behind the input,
+/// e.g. because the renderer uses look-ahead or processes the audio in blocks.
+/// Wrappers add their own latency to the latency of the renderer they wrap, so that the
+/// latency of nested renderers is computed automatically,
+/// see e.g. the [`CompensationDelay`].
+///
+/// [`CompensationDelay`]: ./utilities/compensation_delay/struct.CompensationDelay.html
+pub trait Latency {
+    /// The latency in frames.
+    fn latency_in_frames(&self) -> usize;
+}
+
+/// Define the tail of a renderer, e.g. a reverb or a delay.
+///
+/// The tail is the number of frames during which the output may still be non-silent after the
+/// input has become silent. Hosts use it to decide how long they keep rendering a plugin
+/// after the transport has stopped or when rendering a region offline.
+pub trait TailTime {
+    /// The tail in frames; `0` if the output becomes silent together with the input.
+    fn tail_time_in_frames(&self) -> usize;
+}
+
+/// Get notified when the back-end starts and stops processing audio.
+///
+/// When processing restarts, the audio of the previous buffers is unrelated to the new input,
+/// so this is the place to e.g. release all voices and clear delay lines.
+/// Both methods have an empty default implementation, so a renderer that does not need
+/// them can implement this trait with `impl Lifecycle for MyRenderer {}`.
+/// Wrappers forward these calls to the renderer they wrap.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// These methods are not called from the audio thread, so they may allocate memory.
+pub trait Lifecycle {
+    /// Called before the back-end starts processing audio, e.g. when the VST host resumes
+    /// the plugin or when the JACK client is activated.
+    fn on_resume(&mut self) {}
+
+    /// Called after the back-end has stopped processing audio, e.g. when the VST host
+    /// suspends the plugin or when the JACK client has been deactivated.
+    fn on_suspend(&mut self) {}
+}
+
+/// Save and restore the state of a plugin, e.g. its patch.
+///
+/// The state is serialized to bytes, so that it can be stored by the host or handed over to
+/// a new instance of the plugin, see e.g. the [`hotreload`] module.
+/// A plugin whose state consists of its parameters can delegate to its [`ParameterStore`],
+/// which implements this trait.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// Both methods are called outside the audio thread and may allocate memory.
+///
+/// [`hotreload`]: ./hotreload/index.html
+/// [`ParameterStore`]: ./parameter/struct.ParameterStore.html
+#[cfg(feature = "std")]
+pub trait PluginState {
+    /// Serialize the state.
+    fn save_state(&self) -> Vec<u8>;
+
+    /// Restore a state that has been serialized with `save_state`, possibly by another
+    /// version of the plugin.
+    fn load_state(&mut self, state: &[u8]) -> Result<(), InvalidState>;
+}
+
+/// The error that is returned by [`PluginState::load_state`] when the state cannot be read.
+///
+/// [`PluginState::load_state`]: ./trait.PluginState.html#tymethod.load_state
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InvalidState;
+
+/// A graphical user interface of a plugin that is embedded in a window of the host.
+///
+/// The back-end calls `open` with the raw handle of the parent window when the host shows the
+/// editor, `idle` regularly while the editor is open, and `close` when the host hides it.
+/// This way, the GUI can be written with any toolkit that can open a window inside a parent
+/// window (e.g. egui or iced with baseview, or platform-specific code).
+/// See e.g. the [`vst`] back-end.
+///
+/// All methods are called from the GUI thread of the host, not from the audio thread.
+///
+/// [`vst`]: ./backend/vst_backend/index.html
+pub trait Editor {
+    /// The size (width, height) of the editor in pixels.
+    fn size(&self) -> (u32, u32);
+
+    /// Open the editor inside the given parent window.
+    ///
+    /// `parent` is the raw handle of the parent window: an `HWND` on Windows, an `NSView`
+    /// on macOS and an X11 window id on Linux.
+    /// Return `true` if the editor has been opened.
+    fn open(&mut self, parent: *mut core::ffi::c_void) -> bool;
+
+    /// Close the editor.
+    fn close(&mut self);
+
+    /// Called regularly while the editor is open, e.g. to repaint the GUI.
+    fn idle(&mut self) {}
+
+    /// Whether the editor is currently open.
+    fn is_open(&self) -> bool;
+}
+
 /// Defines how audio is rendered.
 ///
 /// The type parameter `S` refers to the data type of a sample.
@@ -211,15 +540,43 @@ pub trait ContextualAudioRenderer<S, Context> {
 /// This trait can be more conveniently implemented by implementing the [`Meta`] trait.
 ///
 /// [`Meta`]: ./meta/trait.Meta.html
+#[cfg(feature = "std")]
 pub trait CommonPluginMeta {
     /// The name of the plugin or application.
     fn name(&self) -> &str;
 }
 
+/// Provides the meta-data that plugin hosts use to identify the plugin, e.g. to restore it
+/// when a session is loaded.
+/// This trait can be more conveniently implemented by implementing the [`Meta`] trait with
+/// [`PluginIdentity`] as general meta-data.
+///
+/// [`Meta`]: ./meta/trait.Meta.html
+/// [`PluginIdentity`]: ./meta/struct.PluginIdentity.html
+#[cfg(feature = "std")]
+pub trait PluginIdentityMeta: CommonPluginMeta {
+    /// The name of the vendor of the plugin.
+    fn vendor(&self) -> &str;
+
+    /// An id that is unique for each plugin.
+    ///
+    /// # Note
+    /// Hosts identify plugins by this id, so two different plugins should never use the
+    /// same id.
+    fn unique_id(&self) -> i32;
+
+    /// The version of the plugin.
+    fn version(&self) -> i32;
+
+    /// The category of the plugin.
+    fn category(&self) -> Category;
+}
+
 /// Provides some meta-data of the audio-ports used by the plugin or application to the host.
 /// This trait can be more conveniently implemented by implementing the [`Meta`] trait.
 ///
 /// [`Meta`]: ./meta/trait.Meta.html
+#[cfg(feature = "std")]
 pub trait CommonAudioPortMeta: AudioHandlerMeta {
     /// The name of the audio input with the given index.
     /// You can assume that `index` is strictly smaller than [`Self::max_number_of_audio_inputs()`].
@@ -242,12 +599,25 @@ pub trait CommonAudioPortMeta: AudioHandlerMeta {
     fn audio_output_name(&self, index: usize) -> String {
         format!("audio out {}", index)
     }
+
+    /// The channel layout of the audio inputs.
+    /// The default implementation derives the channel layout from the number of audio inputs.
+    fn audio_input_layout(&self) -> ChannelLayout {
+        ChannelLayout::for_number_of_channels(self.max_number_of_audio_inputs())
+    }
+
+    /// The channel layout of the audio outputs.
+    /// The default implementation derives the channel layout from the number of audio outputs.
+    fn audio_output_layout(&self) -> ChannelLayout {
+        ChannelLayout::for_number_of_channels(self.max_number_of_audio_outputs())
+    }
 }
 
 /// Provides some meta-data of the midi-ports used by the plugin or application to the host.
 /// This trait can be more conveniently implemented by implementing the [`Meta`] trait.
 ///
 /// [`Meta`]: ./meta/trait.Meta.html
+#[cfg(feature = "std")]
 pub trait CommonMidiPortMeta: MidiHandlerMeta {
     /// The name of the midi input with the given index.
     /// You can assume that `index` is strictly smaller than [`Self::max_number_of_midi_inputs()`].
@@ -272,6 +642,7 @@ pub trait CommonMidiPortMeta: MidiHandlerMeta {
     }
 }
 
+#[cfg(feature = "std")]
 impl<T> CommonPluginMeta for T
 where
     T: Meta,
@@ -283,6 +654,31 @@ where
     }
 }
 
+#[cfg(feature = "std")]
+impl<T> PluginIdentityMeta for T
+where
+    T: Meta,
+    T::MetaData: General,
+    <<T as Meta>::MetaData as General>::GeneralData: Name + Identity,
+{
+    fn vendor(&self) -> &str {
+        self.meta().general().vendor()
+    }
+
+    fn unique_id(&self) -> i32 {
+        self.meta().general().unique_id()
+    }
+
+    fn version(&self) -> i32 {
+        self.meta().general().version()
+    }
+
+    fn category(&self) -> Category {
+        self.meta().general().category()
+    }
+}
+
+#[cfg(feature = "std")]
 impl<T> AudioHandlerMeta for T
 where
     T: Meta,
@@ -297,6 +693,7 @@ where
     }
 }
 
+#[cfg(feature = "std")]
 impl<T> CommonAudioPortMeta for T
 where
     T: Meta,
@@ -310,8 +707,21 @@ where
     fn audio_output_name(&self, index: usize) -> String {
         self.meta().out_ports()[index].name().to_string()
     }
+
+    fn audio_input_layout(&self) -> ChannelLayout {
+        self.meta().in_channel_layout().unwrap_or_else(|| {
+            ChannelLayout::for_number_of_channels(self.max_number_of_audio_inputs())
+        })
+    }
+
+    fn audio_output_layout(&self) -> ChannelLayout {
+        self.meta().out_channel_layout().unwrap_or_else(|| {
+            ChannelLayout::for_number_of_channels(self.max_number_of_audio_outputs())
+        })
+    }
 }
 
+#[cfg(feature = "std")]
 impl<T> MidiHandlerMeta for T
 where
     T: Meta,
@@ -326,6 +736,7 @@ where
     }
 }
 
+#[cfg(feature = "std")]
 impl<T> CommonMidiPortMeta for T
 where
     T: Meta,
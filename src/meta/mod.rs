@@ -42,6 +42,7 @@
 //!     }
 //! }
 //! ```
+use crate::channel_layout::ChannelLayout;
 
 /// Define the meta-data for an application or plug-in.
 ///
@@ -91,6 +92,157 @@ impl Name for &'static str {
     }
 }
 
+/// The category of a plugin, which hosts use to sort plugins in their menus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    /// An instrument that generates sound from midi input.
+    Synth,
+    /// An audio effect.
+    Effect,
+    /// A plugin that analyses the audio, e.g. a meter or a spectrum analyser.
+    Analysis,
+    /// A dynamics processor, e.g. a compressor or a limiter.
+    Mastering,
+    /// A plugin that positions the sound, e.g. a panner.
+    Spatializer,
+    /// A reverb or a delay.
+    RoomFx,
+    /// An effect for surround sound.
+    SurroundFx,
+    /// A plugin that restores audio, e.g. a denoiser.
+    Restoration,
+    /// A plugin that generates sound without midi input.
+    Generator,
+    /// Any other plugin.
+    Unknown,
+}
+
+/// Implement this trait to indicate that the general meta-data contains the information that
+/// plugin hosts use to identify the plugin.
+pub trait Identity {
+    /// The name of the vendor of the plugin.
+    fn vendor(&self) -> &str;
+    /// An id that is unique for each plugin, e.g. four ascii characters as an `i32`.
+    fn unique_id(&self) -> i32;
+    /// The version of the plugin.
+    fn version(&self) -> i32;
+    /// The category of the plugin.
+    fn category(&self) -> Category;
+}
+
+/// General meta-data with the name and the identity of a plugin.
+///
+/// Example
+/// -------
+/// ```
+/// use rsynth::meta::{Category, Identity, Name, PluginIdentity};
+/// let identity = PluginIdentity {
+///     name: "Noise generator",
+///     vendor: "rsynth",
+///     unique_id: i32::from_be_bytes(*b"RsNg"),
+///     version: 1,
+///     category: Category::Synth,
+/// };
+/// assert_eq!(identity.name(), "Noise generator");
+/// assert_eq!(identity.vendor(), "rsynth");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PluginIdentity<T> {
+    /// The name of the plugin.
+    pub name: T,
+    /// The name of the vendor.
+    pub vendor: T,
+    /// An id that is unique for each plugin.
+    pub unique_id: i32,
+    /// The version of the plugin.
+    pub version: i32,
+    /// The category of the plugin.
+    pub category: Category,
+}
+
+impl<T> Name for PluginIdentity<T>
+where
+    T: Name,
+{
+    fn name(&self) -> &str {
+        self.name.name()
+    }
+}
+
+impl<T> Identity for PluginIdentity<T>
+where
+    T: Name,
+{
+    fn vendor(&self) -> &str {
+        self.vendor.name()
+    }
+
+    fn unique_id(&self) -> i32 {
+        self.unique_id
+    }
+
+    fn version(&self) -> i32 {
+        self.version
+    }
+
+    fn category(&self) -> Category {
+        self.category
+    }
+}
+
+/// Implement this trait to indicate that the meta-data of a port contains the name of the
+/// bus that the port belongs to, e.g. `"main"` or `"sidechain"`.
+///
+/// Consecutive ports with the same bus name form one bus, see the [`bus`] module.
+///
+/// [`bus`]: ../bus/index.html
+pub trait BusName {
+    /// Get the name of the bus.
+    fn bus_name(&self) -> &str;
+}
+
+/// Meta-data of a port that belongs to a named bus.
+///
+/// Example
+/// -------
+/// ```
+/// use rsynth::meta::{BusName, BusPort, Name};
+/// let port = BusPort::new("sidechain left", "sidechain");
+/// assert_eq!(port.name(), "sidechain left");
+/// assert_eq!(port.bus_name(), "sidechain");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusPort<T> {
+    /// The name of the port.
+    pub name: T,
+    /// The name of the bus.
+    pub bus: T,
+}
+
+impl<T> BusPort<T> {
+    pub fn new(name: T, bus: T) -> Self {
+        BusPort { name, bus }
+    }
+}
+
+impl<T> Name for BusPort<T>
+where
+    T: Name,
+{
+    fn name(&self) -> &str {
+        self.name.name()
+    }
+}
+
+impl<T> BusName for BusPort<T>
+where
+    T: Name,
+{
+    fn bus_name(&self) -> &str {
+        self.bus.name()
+    }
+}
+
 /// Define meta-data for input ports and output ports.
 ///
 /// The type parameter `T` is a dummy type parameter so that meta-data for different types of
@@ -141,6 +293,18 @@ pub trait Port<T> {
     type PortData;
     fn in_ports(&self) -> &[Self::PortData];
     fn out_ports(&self) -> &[Self::PortData];
+
+    /// The channel layout of the input ports, or `None` to derive it from the number of ports.
+    /// This is only used for audio ports.
+    fn in_channel_layout(&self) -> Option<ChannelLayout> {
+        None
+    }
+
+    /// The channel layout of the output ports, or `None` to derive it from the number of ports.
+    /// This is only used for audio ports.
+    fn out_channel_layout(&self) -> Option<ChannelLayout> {
+        None
+    }
 }
 
 /// A "marker" struct to be used as a type parameter for the [`Port`] trait, indicating
@@ -210,3 +374,89 @@ impl<G, AP, MP> Port<MidiPort> for MetaData<G, AP, MP> {
         self.midi_port_meta.outputs.as_ref()
     }
 }
+
+/// Meta-data with an explicit channel layout for the audio inputs and outputs.
+///
+/// Wrap the meta-data (e.g. a [`MetaData`]) in a `WithChannelLayout` when the channel layout
+/// cannot be derived from the number of audio ports, e.g. for quadraphonic or ambisonic
+/// plugins.
+///
+/// Example
+/// -------
+/// ```
+/// use rsynth::channel_layout::ChannelLayout;
+/// use rsynth::meta::{InOut, MetaData, WithChannelLayout};
+/// let meta: WithChannelLayout<MetaData<&str, &str, &str>> = WithChannelLayout {
+///     meta: MetaData {
+///         general_meta: "ambisonic rotator",
+///         audio_port_meta: InOut {
+///             inputs: vec!["W in", "Y in", "Z in", "X in"],
+///             outputs: vec!["W out", "Y out", "Z out", "X out"],
+///         },
+///         midi_port_meta: InOut {
+///             inputs: vec![],
+///             outputs: vec![],
+///         },
+///     },
+///     inputs: ChannelLayout::Ambisonic { order: 1 },
+///     outputs: ChannelLayout::Ambisonic { order: 1 },
+/// };
+/// ```
+///
+/// [`MetaData`]: ./struct.MetaData.html
+pub struct WithChannelLayout<M> {
+    /// The wrapped meta-data.
+    pub meta: M,
+    /// The channel layout of the audio inputs.
+    pub inputs: ChannelLayout,
+    /// The channel layout of the audio outputs.
+    pub outputs: ChannelLayout,
+}
+
+impl<M> General for WithChannelLayout<M>
+where
+    M: General,
+{
+    type GeneralData = M::GeneralData;
+    fn general(&self) -> &Self::GeneralData {
+        self.meta.general()
+    }
+}
+
+impl<M> Port<AudioPort> for WithChannelLayout<M>
+where
+    M: Port<AudioPort>,
+{
+    type PortData = M::PortData;
+
+    fn in_ports(&self) -> &[Self::PortData] {
+        self.meta.in_ports()
+    }
+
+    fn out_ports(&self) -> &[Self::PortData] {
+        self.meta.out_ports()
+    }
+
+    fn in_channel_layout(&self) -> Option<ChannelLayout> {
+        Some(self.inputs)
+    }
+
+    fn out_channel_layout(&self) -> Option<ChannelLayout> {
+        Some(self.outputs)
+    }
+}
+
+impl<M> Port<MidiPort> for WithChannelLayout<M>
+where
+    M: Port<MidiPort>,
+{
+    type PortData = M::PortData;
+
+    fn in_ports(&self) -> &[Self::PortData] {
+        self.meta.in_ports()
+    }
+
+    fn out_ports(&self) -> &[Self::PortData] {
+        self.meta.out_ports()
+    }
+}
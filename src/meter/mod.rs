@@ -0,0 +1,75 @@
+//! Meters that are written by the audio thread and read by other threads.
+//!
+//! A [`Meters`] contains a number of named values (e.g. the peak level of an output),
+//! which can be shared (e.g. in an `Arc`) between the audio thread and other threads,
+//! for instance a graphical editor or a remote control.
+//! Reading and writing the values is lock-free.
+//!
+//! [`Meters`]: ./struct.Meters.html
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// A number of named values that are written by the audio thread.
+///
+/// See the [module level documentation] for more information.
+///
+/// All methods except `new` are lock-free and do not allocate memory,
+/// so they can be used in a real-time context.
+///
+/// Panics
+/// ------
+/// The methods that take an index panic when the index is out of bounds.
+///
+/// [module level documentation]: ./index.html
+pub struct Meters {
+    names: Vec<String>,
+    values: Vec<AtomicU32>,
+}
+
+impl Meters {
+    /// Create new `Meters` with the given names. All values are initially `0.0`.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory.
+    pub fn new(names: Vec<String>) -> Self {
+        let values = names
+            .iter()
+            .map(|_| AtomicU32::new(0.0_f32.to_bits()))
+            .collect();
+        Self { names, values }
+    }
+
+    /// The number of meters.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Return `true` if there are no meters.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Get the names of all meters.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Get the value of the meter with the given index.
+    pub fn get(&self, index: usize) -> f32 {
+        f32::from_bits(self.values[index].load(Ordering::Relaxed))
+    }
+
+    /// Set the value of the meter with the given index.
+    pub fn set(&self, index: usize, value: f32) {
+        self.values[index].store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn meters_start_at_zero_and_can_be_set() {
+    let meters = Meters::new(vec!["Left".to_string(), "Right".to_string()]);
+    assert_eq!(meters.get(1), 0.0);
+    meters.set(1, 0.25);
+    assert_eq!(meters.get(0), 0.0);
+    assert_eq!(meters.get(1), 0.25);
+}
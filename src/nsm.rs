@@ -0,0 +1,415 @@
+//! Take part in sessions of the [New Session Manager] (NSM), which saves and restores the
+//! state of all the audio applications of a session on Linux.
+//!
+//! Support is only enabled if you compile with the "nsm" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! When the session manager starts the application, it sets the environment variable
+//! `NSM_URL`. The application then announces itself with [`NsmClient::announce`], which waits
+//! until the session manager tells the application which session to open.
+//! The state of the application is stored in a file in the session, see
+//! [`NsmSession::state_path`]. It is read with [`NsmClient::open`] and written whenever the
+//! session manager asks for it, which is handled by [`NsmClient::handle_messages`].
+//! The state is saved and restored with the [`PluginState`] trait, e.g. of the
+//! [`ParameterStore`] of the plugin, which can be shared with the audio thread.
+//!
+//! The session manager stops the application with the `SIGTERM` signal.
+//! When using the JACK backend, the client id of the session should be used as the name of
+//! the JACK client, see [`JackOptions::client_name`].
+//!
+//! # Example
+//! ```no_run
+//! use rsynth::nsm::{NsmClient, NsmError};
+//! use rsynth::PluginState;
+//! use std::thread;
+//! use std::time::Duration;
+//!
+//! fn take_part_in_session<S: PluginState>(state: &mut S) -> Result<(), NsmError> {
+//!     let client = NsmClient::announce("My synth", "my-synth")?;
+//!     client.open(state)?;
+//!     // Start the JACK client with `client.session().client_id` as the name.
+//!     loop {
+//!         client.handle_messages(state)?;
+//!         thread::sleep(Duration::from_millis(100));
+//!     }
+//! }
+//! ```
+//!
+//! [New Session Manager]: https://new-session-manager.jackaudio.org/
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`NsmClient::announce`]: ./struct.NsmClient.html#method.announce
+//! [`NsmSession::state_path`]: ./struct.NsmSession.html#method.state_path
+//! [`NsmClient::open`]: ./struct.NsmClient.html#method.open
+//! [`NsmClient::handle_messages`]: ./struct.NsmClient.html#method.handle_messages
+//! [`PluginState`]: ../trait.PluginState.html
+//! [`ParameterStore`]: ../parameter/struct.ParameterStore.html
+//! [`JackOptions::client_name`]: ../backend/jack_backend/struct.JackOptions.html#structfield.client_name
+use crate::PluginState;
+use rosc::{OscMessage, OscPacket, OscType};
+use std::env;
+use std::fs;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant};
+
+// The maximum size of an OSC packet that we can receive.
+const MAXIMUM_PACKET_SIZE: usize = 1536;
+
+// How long we wait for the session manager to open a session after announcing.
+const OPEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How long the socket blocks when waiting for a packet during the announcement.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// The version of the NSM API that is implemented.
+const API_VERSION_MAJOR: i32 = 1;
+const API_VERSION_MINOR: i32 = 2;
+
+// Error codes of the NSM API.
+const ERR_GENERAL: i32 = -1;
+const ERR_BAD_PROJECT: i32 = -9;
+
+/// The error type that represents the errors you can get when taking part in a session.
+#[derive(Debug)]
+pub enum NsmError {
+    /// The environment variable `NSM_URL` is not set: the application has not been started by
+    /// a session manager.
+    NoSessionManager,
+    /// The `NSM_URL` is not of the form `osc.udp://host:port/`.
+    InvalidUrl(String),
+    /// The session manager refused the announcement, with the given message.
+    AnnounceRefused(String),
+    /// The session manager did not open a session in time after the announcement.
+    Timeout,
+    /// The state in the session could not be loaded.
+    InvalidState,
+    /// An error occurred when encoding a message.
+    EncodeError(rosc::OscError),
+    /// An error occurred when communicating with the session manager or when reading or
+    /// writing the state.
+    IoError(io::Error),
+}
+
+impl From<io::Error> for NsmError {
+    fn from(e: io::Error) -> Self {
+        NsmError::IoError(e)
+    }
+}
+
+/// The session that the application takes part in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NsmSession {
+    /// The path, without extension, of the files of the application in the session.
+    pub path: String,
+    /// The name of the session, to display to the user.
+    pub display_name: String,
+    /// The unique id of the application in the session, e.g. to use as the name of
+    /// the JACK client.
+    pub client_id: String,
+}
+
+impl NsmSession {
+    /// The file in which the state of the application is stored.
+    pub fn state_path(&self) -> PathBuf {
+        PathBuf::from(format!("{}.state", self.path))
+    }
+}
+
+// A request of the session manager.
+#[derive(Debug, PartialEq)]
+enum Request {
+    Open(NsmSession),
+    Save,
+    // The reply to the announcement, with the name of the session manager.
+    AnnounceAccepted(String),
+    AnnounceRefused(String),
+    Other,
+}
+
+fn string_argument(message: &OscMessage, index: usize) -> Option<String> {
+    match message.args.get(index) {
+        Some(OscType::String(value)) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+fn parse_request(message: &OscMessage) -> Request {
+    let is_reply_to_announce =
+        string_argument(message, 0).as_deref() == Some("/nsm/server/announce");
+    match message.addr.as_str() {
+        "/nsm/client/open" => match (
+            string_argument(message, 0),
+            string_argument(message, 1),
+            string_argument(message, 2),
+        ) {
+            (Some(path), Some(display_name), Some(client_id)) => Request::Open(NsmSession {
+                path,
+                display_name,
+                client_id,
+            }),
+            _ => Request::Other,
+        },
+        "/nsm/client/save" => Request::Save,
+        "/reply" if is_reply_to_announce => {
+            Request::AnnounceAccepted(string_argument(message, 2).unwrap_or_default())
+        }
+        "/error" if is_reply_to_announce => {
+            Request::AnnounceRefused(string_argument(message, 2).unwrap_or_default())
+        }
+        _ => Request::Other,
+    }
+}
+
+// Convert an url of the form `osc.udp://host:port/` to `host:port`.
+fn parse_url(url: &str) -> Result<&str, NsmError> {
+    url.strip_prefix("osc.udp://")
+        .map(|address| address.trim_end_matches('/'))
+        .filter(|address| !address.is_empty())
+        .ok_or_else(|| NsmError::InvalidUrl(url.to_string()))
+}
+
+/// A connection to the session manager.
+///
+/// See the [module level documentation] for more information.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// All methods allocate memory and perform system calls.
+///
+/// [module level documentation]: ./index.html
+pub struct NsmClient {
+    socket: UdpSocket,
+    server: SocketAddr,
+    session: NsmSession,
+}
+
+impl NsmClient {
+    /// Announce the application to the session manager from the environment variable
+    /// `NSM_URL` and wait until the session manager opens a session.
+    /// Then call [`open`] to load the state and to tell the session manager that the session
+    /// has been opened.
+    ///
+    /// # Parameters
+    /// * `application_name`: the name of the application, to display to the user.
+    /// * `executable_name`: the name of the executable, which the session manager uses to
+    ///   start the application.
+    ///
+    /// [`open`]: #method.open
+    pub fn announce(application_name: &str, executable_name: &str) -> Result<Self, NsmError> {
+        let url = env::var("NSM_URL").map_err(|_| NsmError::NoSessionManager)?;
+        let server = parse_url(&url)?
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| NsmError::InvalidUrl(url.clone()))?;
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        send(
+            &socket,
+            server,
+            "/nsm/server/announce",
+            vec![
+                OscType::String(application_name.to_string()),
+                OscType::String(":".to_string()),
+                OscType::String(executable_name.to_string()),
+                OscType::Int(API_VERSION_MAJOR),
+                OscType::Int(API_VERSION_MINOR),
+                OscType::Int(process::id() as i32),
+            ],
+        )?;
+
+        let deadline = Instant::now() + OPEN_TIMEOUT;
+        while Instant::now() < deadline {
+            let message = match receive(&socket)? {
+                Some(message) => message,
+                None => continue,
+            };
+            match parse_request(&message) {
+                Request::AnnounceAccepted(session_manager) => {
+                    info!("Announced to session manager {}.", session_manager);
+                }
+                Request::AnnounceRefused(reason) => return Err(NsmError::AnnounceRefused(reason)),
+                Request::Open(session) => {
+                    info!("Opening session {}.", session.display_name);
+                    socket.set_nonblocking(true)?;
+                    return Ok(NsmClient {
+                        socket,
+                        server,
+                        session,
+                    });
+                }
+                _ => {
+                    debug!("Ignoring NSM message with address {}", message.addr);
+                }
+            }
+        }
+        Err(NsmError::Timeout)
+    }
+
+    /// The session that has been opened.
+    pub fn session(&self) -> &NsmSession {
+        &self.session
+    }
+
+    /// Load the state from the session, if it has been saved before, and tell the session
+    /// manager whether the session could be opened.
+    pub fn open<S: PluginState>(&self, state: &mut S) -> Result<(), NsmError> {
+        let result = match fs::read(self.session.state_path()) {
+            Ok(bytes) => state.load_state(&bytes).map_err(|_| NsmError::InvalidState),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(NsmError::IoError(e)),
+        };
+        match result {
+            Ok(()) => self.reply("/nsm/client/open", "Session opened.")?,
+            Err(NsmError::InvalidState) => {
+                self.error("/nsm/client/open", ERR_BAD_PROJECT, "Invalid state.")?
+            }
+            Err(_) => self.error("/nsm/client/open", ERR_GENERAL, "Cannot read the state.")?,
+        }
+        result
+    }
+
+    /// Handle the messages that the session manager has sent since the previous call,
+    /// e.g. by saving the state. This method does not block.
+    pub fn handle_messages<S: PluginState>(&self, state: &S) -> Result<(), NsmError> {
+        while let Some(message) = receive(&self.socket)? {
+            match parse_request(&message) {
+                Request::Save => match fs::write(self.session.state_path(), state.save_state()) {
+                    Ok(()) => self.reply("/nsm/client/save", "Saved.")?,
+                    Err(e) => {
+                        error!("Failed to save the state: {:?}", e);
+                        self.error("/nsm/client/save", ERR_GENERAL, "Cannot write the state.")?;
+                    }
+                },
+                _ => {
+                    debug!("Ignoring NSM message with address {}", message.addr);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn reply(&self, request: &str, message: &str) -> Result<(), NsmError> {
+        send(
+            &self.socket,
+            self.server,
+            "/reply",
+            vec![
+                OscType::String(request.to_string()),
+                OscType::String(message.to_string()),
+            ],
+        )
+    }
+
+    fn error(&self, request: &str, code: i32, message: &str) -> Result<(), NsmError> {
+        send(
+            &self.socket,
+            self.server,
+            "/error",
+            vec![
+                OscType::String(request.to_string()),
+                OscType::Int(code),
+                OscType::String(message.to_string()),
+            ],
+        )
+    }
+}
+
+fn send(
+    socket: &UdpSocket,
+    server: SocketAddr,
+    address: &str,
+    args: Vec<OscType>,
+) -> Result<(), NsmError> {
+    let packet = rosc::encoder::encode(&OscPacket::Message(OscMessage {
+        addr: address.to_string(),
+        args,
+    }))
+    .map_err(NsmError::EncodeError)?;
+    socket.send_to(&packet, server)?;
+    Ok(())
+}
+
+// Receive the next message, if any.
+fn receive(socket: &UdpSocket) -> Result<Option<OscMessage>, NsmError> {
+    let mut buffer = [0; MAXIMUM_PACKET_SIZE];
+    let size = match socket.recv_from(&mut buffer) {
+        Ok((size, _)) => size,
+        Err(ref e)
+            if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+        {
+            return Ok(None);
+        }
+        Err(e) => return Err(NsmError::IoError(e)),
+    };
+    match rosc::decoder::decode(&buffer[..size]) {
+        Ok(OscPacket::Message(message)) => Ok(Some(message)),
+        Ok(OscPacket::Bundle(_)) => {
+            debug!("Ignoring OSC bundle from the session manager.");
+            receive(socket)
+        }
+        Err(e) => {
+            warn!("Failed to decode OSC packet: {:?}", e);
+            receive(socket)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(addr: &str, args: &[&str]) -> OscMessage {
+        OscMessage {
+            addr: addr.to_string(),
+            args: args
+                .iter()
+                .map(|arg| OscType::String(arg.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn url_is_converted_to_address() {
+        assert_eq!(
+            parse_url("osc.udp://localhost:17000/").unwrap(),
+            "localhost:17000"
+        );
+        assert!(parse_url("osc.tcp://localhost:17000/").is_err());
+        assert!(parse_url("osc.udp:///").is_err());
+    }
+
+    #[test]
+    fn requests_are_parsed() {
+        let open = message(
+            "/nsm/client/open",
+            &["/home/user/session/synth.nABCD", "My session", "nABCD"],
+        );
+        let session = NsmSession {
+            path: "/home/user/session/synth.nABCD".to_string(),
+            display_name: "My session".to_string(),
+            client_id: "nABCD".to_string(),
+        };
+        assert_eq!(parse_request(&open), Request::Open(session.clone()));
+        assert_eq!(
+            session.state_path(),
+            PathBuf::from("/home/user/session/synth.nABCD.state")
+        );
+        assert_eq!(
+            parse_request(&message("/nsm/client/save", &[])),
+            Request::Save
+        );
+        assert_eq!(
+            parse_request(&message(
+                "/reply",
+                &["/nsm/server/announce", "Howdy", "New Session Manager", ":"]
+            )),
+            Request::AnnounceAccepted("New Session Manager".to_string())
+        );
+        assert_eq!(
+            parse_request(&message("/nsm/client/open", &["incomplete"])),
+            Request::Other
+        );
+    }
+}
@@ -0,0 +1,388 @@
+//! Receive and send [OSC] (Open Sound Control) messages.
+//!
+//! Support is only enabled if you compile with the "osc" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! This can be used to remote-control an application, e.g. a headless application
+//! that uses the Jack backend.
+//!
+//! Receiving messages
+//! ==================
+//! Incoming messages are received by an [`OscServer`] on a separate thread.
+//! The [`OscServer`] converts each message into an event with an [`OscEventMapper`]
+//! and sends the event over a lock-free queue to the audio thread.
+//! In the audio thread, you call [`OscReceiver::handle_events`] (e.g. at the start of
+//! `render_buffer`) to pass the events to an [`EventHandler`].
+//!
+//! You can define your own [`OscEventMapper`] (a closure is also an [`OscEventMapper`]),
+//! or you can use the [`ParameterChangeMapper`], which converts messages like
+//! `/parameter/3 0.5` into a [`ParameterChange`] event.
+//!
+//! Sending messages
+//! ================
+//! Messages (e.g. meter values or state) can be broadcast with an [`OscSender`].
+//! Sending messages involves system calls, so you should not use the [`OscSender`]
+//! in the real-time thread.
+//!
+//! [OSC]: http://opensoundcontrol.org/
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`OscServer`]: ./struct.OscServer.html
+//! [`OscEventMapper`]: ./trait.OscEventMapper.html
+//! [`OscReceiver::handle_events`]: ./struct.OscReceiver.html#method.handle_events
+//! [`EventHandler`]: ../event/trait.EventHandler.html
+//! [`ParameterChangeMapper`]: ./struct.ParameterChangeMapper.html
+//! [`ParameterChange`]: ../event/struct.ParameterChange.html
+//! [`OscSender`]: ./struct.OscSender.html
+use crate::event::{EventHandler, ParameterChange};
+use ringbuf::{Consumer, Producer, RingBuffer};
+use rosc::{OscMessage, OscPacket, OscType};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// The maximum size of an OSC packet that we can receive.
+const MAXIMUM_PACKET_SIZE: usize = 1536;
+
+// How long the server thread blocks when waiting for a packet before it checks
+// if it needs to stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Define how an OSC message is converted into an event.
+///
+/// The type parameter `E` corresponds to the type of the event.
+///
+/// # Remark
+/// A closure of type `FnMut(&OscMessage) -> Option<E>` implements this trait.
+pub trait OscEventMapper<E> {
+    /// Convert the OSC message into an event.
+    /// Return `None` if the message should be ignored.
+    fn map_message(&mut self, message: &OscMessage) -> Option<E>;
+}
+
+impl<E, F> OscEventMapper<E> for F
+where
+    F: FnMut(&OscMessage) -> Option<E>,
+{
+    fn map_message(&mut self, message: &OscMessage) -> Option<E> {
+        self(message)
+    }
+}
+
+/// An [`OscEventMapper`] that converts messages with an address of the form
+/// `prefix/index` and one numeric argument to a [`ParameterChange`] event.
+///
+/// # Example
+/// With the prefix `/parameter`, the message `/parameter/3 0.5` is converted to the event
+/// `ParameterChange { index: 3, value: 0.5 }`.
+///
+/// [`OscEventMapper`]: ./trait.OscEventMapper.html
+/// [`ParameterChange`]: ../event/struct.ParameterChange.html
+pub struct ParameterChangeMapper {
+    prefix: String,
+}
+
+impl ParameterChangeMapper {
+    /// Create a new `ParameterChangeMapper` for OSC addresses starting with `prefix`.
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl Default for ParameterChangeMapper {
+    fn default() -> Self {
+        Self::new("/parameter")
+    }
+}
+
+impl OscEventMapper<ParameterChange> for ParameterChangeMapper {
+    fn map_message(&mut self, message: &OscMessage) -> Option<ParameterChange> {
+        let index = message
+            .addr
+            .strip_prefix(self.prefix.as_str())?
+            .strip_prefix('/')?
+            .parse()
+            .ok()?;
+        let value = match message.args.as_slice() {
+            [OscType::Float(value)] => *value,
+            [OscType::Double(value)] => *value as f32,
+            [OscType::Int(value)] => *value as f32,
+            _ => {
+                return None;
+            }
+        };
+        Some(ParameterChange { index, value })
+    }
+}
+
+/// Receives OSC messages on a separate thread.
+///
+/// The thread is stopped when the `OscServer` is dropped.
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct OscServer {
+    local_address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl OscServer {
+    /// Start listening for OSC messages on the given UDP address.
+    ///
+    /// Each message is converted to an event by the `mapper`.
+    /// The events can be retrieved in the audio thread with the returned [`OscReceiver`].
+    /// At most `queue_capacity` events can be waiting to be retrieved,
+    /// events that are received when the queue is full are dropped.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory and spawns a thread.
+    ///
+    /// [`OscReceiver`]: ./struct.OscReceiver.html
+    pub fn start<A, M, E>(
+        address: A,
+        mapper: M,
+        queue_capacity: usize,
+    ) -> io::Result<(Self, OscReceiver<E>)>
+    where
+        A: ToSocketAddrs,
+        M: OscEventMapper<E> + Send + 'static,
+        E: Send + 'static,
+    {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let local_address = socket.local_addr()?;
+        let (producer, consumer) = RingBuffer::new(queue_capacity).split();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        info!("Listening for OSC messages on {}", local_address);
+        let thread = thread::Builder::new()
+            .name("rsynth osc server".to_string())
+            .spawn(move || serve(socket, mapper, producer, &thread_stop))?;
+        Ok((
+            Self {
+                local_address,
+                stop,
+                thread: Some(thread),
+            },
+            OscReceiver { consumer },
+        ))
+    }
+
+    /// The address the server is listening on.
+    pub fn local_address(&self) -> SocketAddr {
+        self.local_address
+    }
+}
+
+impl Drop for OscServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("The OSC server thread panicked.");
+            }
+        }
+    }
+}
+
+fn serve<M, E>(socket: UdpSocket, mut mapper: M, mut producer: Producer<E>, stop: &AtomicBool)
+where
+    M: OscEventMapper<E>,
+{
+    let mut buffer = [0; MAXIMUM_PACKET_SIZE];
+    while !stop.load(Ordering::Relaxed) {
+        let size = match socket.recv_from(&mut buffer) {
+            Ok((size, _)) => size,
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue;
+            }
+            Err(e) => {
+                error!("Failed to receive OSC packet: {:?}", e);
+                continue;
+            }
+        };
+        match rosc::decoder::decode(&buffer[..size]) {
+            Ok(packet) => handle_packet(packet, &mut mapper, &mut producer),
+            Err(e) => {
+                warn!("Failed to decode OSC packet: {:?}", e);
+            }
+        }
+    }
+}
+
+fn handle_packet<M, E>(packet: OscPacket, mapper: &mut M, producer: &mut Producer<E>)
+where
+    M: OscEventMapper<E>,
+{
+    match packet {
+        OscPacket::Message(message) => {
+            if let Some(event) = mapper.map_message(&message) {
+                if producer.push(event).is_err() {
+                    warn!(
+                        "OSC event queue is full, dropping event for message with address {}",
+                        message.addr
+                    );
+                }
+            } else {
+                debug!("Ignoring OSC message with address {}", message.addr);
+            }
+        }
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                handle_packet(packet, mapper, producer);
+            }
+        }
+    }
+}
+
+/// Retrieves the events that are received by an [`OscServer`].
+///
+/// [`OscServer`]: ./struct.OscServer.html
+pub struct OscReceiver<E> {
+    consumer: Consumer<E>,
+}
+
+impl<E> OscReceiver<E> {
+    /// Get the next event, if any.
+    ///
+    /// This method does not allocate memory and can be used in a real-time context.
+    pub fn receive(&mut self) -> Option<E> {
+        self.consumer.pop()
+    }
+
+    /// Pass all events that have been received so far to the event handler.
+    ///
+    /// This method does not allocate memory and can be used in a real-time context.
+    pub fn handle_events<H>(&mut self, handler: &mut H)
+    where
+        H: EventHandler<E>,
+    {
+        while let Some(event) = self.consumer.pop() {
+            handler.handle_event(event);
+        }
+    }
+}
+
+/// The error type that represents the errors you can get when sending OSC messages.
+#[derive(Debug)]
+pub enum OscSendError {
+    /// An error occurred when encoding the message.
+    EncodeError(rosc::OscError),
+    /// An error occurred when sending the message.
+    IoError(io::Error),
+}
+
+/// Sends OSC messages to a number of targets.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// Sending messages allocates memory and performs system calls,
+/// so an `OscSender` should not be used in the real-time thread.
+pub struct OscSender {
+    socket: UdpSocket,
+    targets: Vec<SocketAddr>,
+}
+
+impl OscSender {
+    /// Create a new `OscSender` without targets.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind("0.0.0.0:0")?,
+            targets: Vec::new(),
+        })
+    }
+
+    /// Add a target to which all subsequent messages will be sent.
+    pub fn add_target<A: ToSocketAddrs>(&mut self, address: A) -> io::Result<()> {
+        self.targets.extend(address.to_socket_addrs()?);
+        Ok(())
+    }
+
+    /// Send the message to all targets.
+    pub fn send(&self, message: OscMessage) -> Result<(), OscSendError> {
+        let packet = rosc::encoder::encode(&OscPacket::Message(message))
+            .map_err(OscSendError::EncodeError)?;
+        for target in self.targets.iter() {
+            self.socket
+                .send_to(&packet, target)
+                .map_err(OscSendError::IoError)?;
+        }
+        Ok(())
+    }
+
+    /// Send a message with the given address and one float argument to all targets.
+    /// This can be used to broadcast the value of a meter or a parameter.
+    pub fn send_float(&self, address: &str, value: f32) -> Result<(), OscSendError> {
+        self.send(OscMessage {
+            addr: address.to_string(),
+            args: vec![OscType::Float(value)],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod parameter_change_mapper {
+        use super::super::{OscEventMapper, ParameterChangeMapper};
+        use crate::event::ParameterChange;
+        use rosc::{OscMessage, OscType};
+
+        fn message(addr: &str, args: Vec<OscType>) -> OscMessage {
+            OscMessage {
+                addr: addr.to_string(),
+                args,
+            }
+        }
+
+        #[test]
+        fn maps_message_with_float_argument() {
+            let mut mapper = ParameterChangeMapper::default();
+            assert_eq!(
+                mapper.map_message(&message("/parameter/3", vec![OscType::Float(0.5)])),
+                Some(ParameterChange::new(3, 0.5))
+            );
+        }
+
+        #[test]
+        fn maps_message_with_custom_prefix() {
+            let mut mapper = ParameterChangeMapper::new("/synth/param/");
+            assert_eq!(
+                mapper.map_message(&message("/synth/param/12", vec![OscType::Int(1)])),
+                Some(ParameterChange::new(12, 1.0))
+            );
+        }
+
+        #[test]
+        fn ignores_other_messages() {
+            let mut mapper = ParameterChangeMapper::default();
+            assert_eq!(
+                mapper.map_message(&message("/meter/3", vec![OscType::Float(0.5)])),
+                None
+            );
+            assert_eq!(
+                mapper.map_message(&message("/parameter3", vec![OscType::Float(0.5)])),
+                None
+            );
+            assert_eq!(
+                mapper.map_message(&message("/parameter/abc", vec![OscType::Float(0.5)])),
+                None
+            );
+            assert_eq!(mapper.map_message(&message("/parameter/3", vec![])), None);
+            assert_eq!(
+                mapper.map_message(&message(
+                    "/parameter/3",
+                    vec![OscType::String("loud".to_string())]
+                )),
+                None
+            );
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! A/B comparison of the complete state of a plugin.
+//!
+//! [`AbSlots`] keep a snapshot of the state of the plugin for the slot that is not active, so
+//! that the user can compare two settings. The snapshot is the complete state (e.g. the patch,
+//! including the values of the parameters), not only the values of the parameters.
+//!
+//! The `AbSlots` are used in the audio thread, next to the state that is active:
+//! [`AbSlots::toggle`] swaps the active state with the snapshot and
+//! [`AbSlots::copy_to_other_slot`] replaces the snapshot with a copy of the active state.
+//! Copying the state allocates memory, so the copy is made outside the audio thread (e.g. with
+//! `Clone` or with the [`PluginState`] trait) and passed to the audio thread. The snapshot that
+//! is replaced is thrown in a [`TrashCan`], so that it is not dropped in the audio thread.
+//!
+//! This module is only available when compiled with the `trash-can` feature.
+//!
+//! ```
+//! use rsynth::parameter::ab::{AbSlot, AbSlots};
+//! use rsynth::utilities::trash_can::trash_can;
+//! # use rsynth::{InvalidState, PluginState};
+//!
+//! #[derive(Clone)]
+//! struct Patch {
+//!     wavetable: Vec<f32>,
+//! }
+//! # impl PluginState for Patch {
+//! #     fn save_state(&self) -> Vec<u8> { Vec::new() }
+//! #     fn load_state(&mut self, _: &[u8]) -> Result<(), InvalidState> { Ok(()) }
+//! # }
+//!
+//! let (trash_can, _collector) = trash_can(4).expect("the thread can be started");
+//! let mut patch = Patch { wavetable: vec![0.0; 2048] };
+//! let mut slots = AbSlots::new(patch.clone(), trash_can);
+//! // Prepared outside the audio thread and then passed to the audio thread:
+//! let mut tweaked = Patch { wavetable: vec![1.0; 2048] };
+//!
+//! // In the audio thread:
+//! std::mem::swap(&mut patch, &mut tweaked);
+//! slots.toggle(&mut patch);
+//! assert_eq!(slots.active_slot(), AbSlot::B);
+//! assert_eq!(patch.wavetable[0], 0.0);
+//! slots.toggle(&mut patch);
+//! assert_eq!(patch.wavetable[0], 1.0);
+//! ```
+//!
+//! Real-time safety
+//! ----------------
+//! [`AbSlots::toggle`], [`AbSlots::copy_to_other_slot`] and [`AbSlots::active_slot`] do not
+//! allocate memory, do not lock and do not drop a snapshot.
+//!
+//! [`AbSlots`]: ./struct.AbSlots.html
+//! [`AbSlots::toggle`]: ./struct.AbSlots.html#method.toggle
+//! [`AbSlots::copy_to_other_slot`]: ./struct.AbSlots.html#method.copy_to_other_slot
+//! [`AbSlots::active_slot`]: ./struct.AbSlots.html#method.active_slot
+//! [`PluginState`]: ../../trait.PluginState.html
+//! [`TrashCan`]: ../../utilities/trash_can/struct.TrashCan.html
+use crate::utilities::trash_can::TrashCan;
+use crate::{InvalidState, PluginState};
+
+/// One of the two slots for A/B comparison.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AbSlot {
+    A,
+    B,
+}
+
+/// The snapshot of the state of the slot that is not active.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct AbSlots<S> {
+    other: S,
+    active: AbSlot,
+    trash_can: TrashCan<S>,
+}
+
+impl<S> AbSlots<S>
+where
+    S: PluginState,
+{
+    /// Create the slots with slot A active and `other` (typically a copy of the current state)
+    /// in slot B. The snapshots that are replaced are thrown in `trash_can`.
+    pub fn new(other: S, trash_can: TrashCan<S>) -> Self {
+        Self {
+            other,
+            active: AbSlot::A,
+            trash_can,
+        }
+    }
+
+    /// The slot that is active, i.e. whose state is used by the plugin.
+    pub fn active_slot(&self) -> AbSlot {
+        self.active
+    }
+
+    /// The snapshot of the state of the slot that is not active.
+    pub fn other_slot(&self) -> &S {
+        &self.other
+    }
+
+    /// Replace the snapshot in the other slot by `snapshot`, which is a copy of the active state
+    /// that has been made outside the audio thread.
+    /// The previous snapshot is thrown in the trash can. When the trash can is full, the other
+    /// slot is not changed and `snapshot` is returned as an error.
+    pub fn copy_to_other_slot(&mut self, snapshot: S) -> Result<(), S> {
+        self.trash_can.replace(&mut self.other, snapshot)
+    }
+
+    /// Switch to the other slot: `active`, the state that is used by the plugin, is swapped
+    /// with the snapshot, so that the state of the previously active slot is kept.
+    pub fn toggle(&mut self, active: &mut S) {
+        std::mem::swap(active, &mut self.other);
+        self.active = match self.active {
+            AbSlot::A => AbSlot::B,
+            AbSlot::B => AbSlot::A,
+        };
+    }
+
+    /// Serialize the snapshot in the other slot, e.g. to save both slots with the project.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory.
+    pub fn save_other_slot(&self) -> Vec<u8> {
+        self.other.save_state()
+    }
+
+    /// Restore the snapshot in the other slot from a state that has been serialized with
+    /// [`save_other_slot`].
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// Loading the state may allocate memory.
+    ///
+    /// [`save_other_slot`]: #method.save_other_slot
+    pub fn load_other_slot(&mut self, state: &[u8]) -> Result<(), InvalidState> {
+        self.other.load_state(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::trash_can::trash_can;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Patch {
+        name: String,
+        cutoff: f32,
+    }
+
+    impl Patch {
+        fn new(name: &str, cutoff: f32) -> Self {
+            Patch {
+                name: name.to_string(),
+                cutoff,
+            }
+        }
+    }
+
+    impl PluginState for Patch {
+        fn save_state(&self) -> Vec<u8> {
+            format!("{}={}", self.name, self.cutoff).into_bytes()
+        }
+
+        fn load_state(&mut self, state: &[u8]) -> Result<(), InvalidState> {
+            let text = std::str::from_utf8(state).map_err(|_| InvalidState)?;
+            let mut parts = text.splitn(2, '=');
+            let name = parts.next().ok_or(InvalidState)?;
+            let cutoff = parts
+                .next()
+                .and_then(|cutoff| cutoff.parse().ok())
+                .ok_or(InvalidState)?;
+            *self = Patch::new(name, cutoff);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn toggling_swaps_the_complete_state() {
+        let (trash_can, _collector) = trash_can(4).expect("no error");
+        let mut active = Patch::new("Init", 1000.0);
+        let mut slots = AbSlots::new(active.clone(), trash_can);
+        active = Patch::new("Bright", 5000.0);
+        slots.toggle(&mut active);
+        assert_eq!(slots.active_slot(), AbSlot::B);
+        assert_eq!(active, Patch::new("Init", 1000.0));
+        assert_eq!(slots.other_slot(), &Patch::new("Bright", 5000.0));
+        slots.toggle(&mut active);
+        assert_eq!(slots.active_slot(), AbSlot::A);
+        assert_eq!(active, Patch::new("Bright", 5000.0));
+    }
+
+    #[test]
+    fn the_replaced_snapshot_is_thrown_away() {
+        let (trash_can, collector) = trash_can(1).expect("no error");
+        // Stop the collector, so that the trash can is not emptied.
+        drop(collector);
+        let mut slots = AbSlots::new(Patch::new("Init", 1000.0), trash_can);
+        assert_eq!(slots.copy_to_other_slot(Patch::new("Dark", 200.0)), Ok(()));
+        assert_eq!(slots.other_slot(), &Patch::new("Dark", 200.0));
+        assert_eq!(
+            slots.copy_to_other_slot(Patch::new("Bright", 5000.0)),
+            Err(Patch::new("Bright", 5000.0))
+        );
+        assert_eq!(slots.other_slot(), &Patch::new("Dark", 200.0));
+    }
+
+    #[test]
+    fn the_other_slot_can_be_saved_and_loaded() {
+        let (trash_can, _collector) = trash_can(1).expect("no error");
+        let mut slots = AbSlots::new(Patch::new("Init", 1000.0), trash_can);
+        let saved = slots.save_other_slot();
+        slots.load_other_slot(b"Dark=200").expect("a valid state");
+        assert_eq!(slots.other_slot(), &Patch::new("Dark", 200.0));
+        slots.load_other_slot(&saved).expect("a valid state");
+        assert_eq!(slots.other_slot(), &Patch::new("Init", 1000.0));
+        assert_eq!(slots.load_other_slot(b"Dark"), Err(InvalidState));
+    }
+}
@@ -0,0 +1,310 @@
+//! Undo and redo changes of parameters.
+//!
+//! The [`UndoHistory`] changes the parameters in a [`ParameterStore`] on behalf of a graphical
+//! editor or a remote control and records every change, so that it can be undone and redone.
+//! Changes that are made directly on the `ParameterStore` (e.g. by the host) are not recorded.
+//!
+//! While the user drags a slider, many small changes are made. To undo the whole drag at once,
+//! call [`begin_gesture`] when the drag starts and [`end_gesture`] when it stops: all changes
+//! of the parameter in between are coalesced into one step.
+//! Changes of several parameters at once (e.g. loading a preset or generating a random patch)
+//! are recorded as one step with [`change_all`].
+//!
+//! The `UndoHistory` is meant for the non-real-time threads; share it between e.g. the editor
+//! and a remote control with an `Arc<Mutex<UndoHistory>>`.
+//!
+//! ```
+//! use rsynth::parameter::{ParameterInfo, ParameterStore};
+//! use rsynth::parameter::history::UndoHistory;
+//!
+//! let store = ParameterStore::new(vec![ParameterInfo::new("Volume", "", 0.0, 1.0, 1.0)]);
+//! let mut history = UndoHistory::new(100);
+//! history.begin_gesture(0);
+//! for step in 1..=5 {
+//!     history.set(&store, 0, 1.0 - 0.1 * step as f32);
+//! }
+//! history.end_gesture();
+//! assert!(history.undo(&store));
+//! assert_eq!(store.get(0), 1.0);
+//! assert!(history.redo(&store));
+//! assert!((store.get(0) - 0.5).abs() < 1e-6);
+//! ```
+//!
+//! [`UndoHistory`]: ./struct.UndoHistory.html
+//! [`ParameterStore`]: ../struct.ParameterStore.html
+//! [`begin_gesture`]: ./struct.UndoHistory.html#method.begin_gesture
+//! [`end_gesture`]: ./struct.UndoHistory.html#method.end_gesture
+//! [`change_all`]: ./struct.UndoHistory.html#method.change_all
+use super::ParameterStore;
+use std::collections::VecDeque;
+
+/// The number of steps that the editor and the remote control remember by default.
+pub const DEFAULT_CAPACITY: usize = 100;
+
+// One undoable step, with normalized values.
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Parameter {
+        index: usize,
+        before: f32,
+        after: f32,
+    },
+    All {
+        before: Vec<f32>,
+        after: Vec<f32>,
+    },
+}
+
+/// Records changes of parameters so that they can be undone and redone.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+#[derive(Debug)]
+pub struct UndoHistory {
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+    capacity: usize,
+    // The parameter of the current gesture and whether the gesture already has a step.
+    gesture: Option<(usize, bool)>,
+}
+
+impl UndoHistory {
+    /// Create an empty history that remembers at most `capacity` steps.
+    pub fn new(capacity: usize) -> Self {
+        UndoHistory {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            capacity,
+            gesture: None,
+        }
+    }
+
+    /// Set the plain value of the parameter with the given index and record the change.
+    pub fn set(&mut self, store: &ParameterStore, index: usize, value: f32) {
+        let normalized = store.info(index).normalize(value);
+        self.set_normalized(store, index, normalized);
+    }
+
+    /// Set the normalized value of the parameter with the given index and record the change.
+    pub fn set_normalized(&mut self, store: &ParameterStore, index: usize, value: f32) {
+        let before = store.get_normalized(index);
+        store.set_normalized(index, value);
+        let after = store.get_normalized(index);
+        if let Some((gesture_index, has_step)) = self.gesture {
+            if gesture_index == index && has_step {
+                if let Some(Step::Parameter {
+                    index: last_index,
+                    after: last,
+                    ..
+                }) = self.undo.back_mut()
+                {
+                    if *last_index == index {
+                        *last = after;
+                        return;
+                    }
+                }
+            }
+        }
+        if before == after {
+            return;
+        }
+        if let Some((gesture_index, has_step)) = self.gesture.as_mut() {
+            if *gesture_index == index {
+                *has_step = true;
+            }
+        }
+        self.push(Step::Parameter {
+            index,
+            before,
+            after,
+        });
+    }
+
+    /// Start a gesture (e.g. dragging a slider) on the parameter with the given index:
+    /// all changes of this parameter until [`end_gesture`] are undone in one step.
+    ///
+    /// [`end_gesture`]: #method.end_gesture
+    pub fn begin_gesture(&mut self, index: usize) {
+        self.gesture = Some((index, false));
+    }
+
+    /// End the current gesture.
+    pub fn end_gesture(&mut self) {
+        self.gesture = None;
+    }
+
+    /// Change any number of parameters with `change` and record all changes as one step,
+    /// e.g. to load a preset or to generate a random patch.
+    ///
+    /// ```
+    /// use rsynth::parameter::{ParameterInfo, ParameterStore};
+    /// use rsynth::parameter::history::UndoHistory;
+    /// use rsynth::utilities::random::Random;
+    ///
+    /// let store = ParameterStore::new(vec![ParameterInfo::new("Volume", "", 0.0, 1.0, 1.0)]);
+    /// let mut history = UndoHistory::new(100);
+    /// history.change_all(&store, |store| store.randomize(&mut Random::new(1)));
+    /// history.undo(&store);
+    /// assert_eq!(store.get(0), 1.0);
+    /// ```
+    pub fn change_all<F>(&mut self, store: &ParameterStore, change: F)
+    where
+        F: FnOnce(&ParameterStore),
+    {
+        let before = normalized_values(store);
+        change(store);
+        let after = normalized_values(store);
+        if before != after {
+            self.gesture = None;
+            self.push(Step::All { before, after });
+        }
+    }
+
+    /// Undo the last step. Returns `false` if there is nothing to undo.
+    pub fn undo(&mut self, store: &ParameterStore) -> bool {
+        self.gesture = None;
+        match self.undo.pop_back() {
+            Some(step) => {
+                match &step {
+                    Step::Parameter { index, before, .. } => store.set_normalized(*index, *before),
+                    Step::All { before, .. } => set_normalized_values(store, before),
+                }
+                self.redo.push(step);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Redo the last step that has been undone. Returns `false` if there is nothing to redo.
+    pub fn redo(&mut self, store: &ParameterStore) -> bool {
+        self.gesture = None;
+        match self.redo.pop() {
+            Some(step) => {
+                match &step {
+                    Step::Parameter { index, after, .. } => store.set_normalized(*index, *after),
+                    Step::All { after, .. } => set_normalized_values(store, after),
+                }
+                self.undo.push_back(step);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forget all steps, e.g. after the plugin state has been replaced by the host.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.gesture = None;
+    }
+
+    fn push(&mut self, step: Step) {
+        self.redo.clear();
+        if self.capacity == 0 {
+            return;
+        }
+        if self.undo.len() == self.capacity {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(step);
+    }
+}
+
+fn normalized_values(store: &ParameterStore) -> Vec<f32> {
+    (0..store.len())
+        .map(|index| store.get_normalized(index))
+        .collect()
+}
+
+fn set_normalized_values(store: &ParameterStore, values: &[f32]) {
+    for (index, value) in values.iter().enumerate().take(store.len()) {
+        store.set_normalized(index, *value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameter::ParameterInfo;
+
+    fn store() -> ParameterStore {
+        ParameterStore::new(vec![
+            ParameterInfo::new("Cutoff", "Hz", 0.0, 1000.0, 500.0),
+            ParameterInfo::new("Volume", "", 0.0, 1.0, 1.0),
+        ])
+    }
+
+    #[test]
+    fn undo_and_redo_single_changes() {
+        let store = store();
+        let mut history = UndoHistory::new(10);
+        assert!(!history.undo(&store));
+        history.set(&store, 0, 250.0);
+        history.set(&store, 1, 0.5);
+        // Setting the same value again is not a step.
+        history.set(&store, 1, 0.5);
+        assert!(history.undo(&store));
+        assert_eq!(store.get(1), 1.0);
+        assert!(history.undo(&store));
+        assert_eq!(store.get(0), 500.0);
+        assert!(!history.can_undo());
+        assert!(history.redo(&store));
+        assert_eq!(store.get(0), 250.0);
+        // A new change discards the steps that can be redone.
+        history.set(&store, 0, 750.0);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn gestures_are_coalesced() {
+        let store = store();
+        let mut history = UndoHistory::new(10);
+        history.begin_gesture(0);
+        history.set(&store, 0, 600.0);
+        history.set(&store, 0, 700.0);
+        history.end_gesture();
+        history.set(&store, 0, 800.0);
+        assert!(history.undo(&store));
+        assert_eq!(store.get(0), 700.0);
+        assert!(history.undo(&store));
+        assert_eq!(store.get(0), 500.0);
+        assert!(!history.can_undo());
+
+        // A change of another parameter during the gesture interrupts the coalescing.
+        history.begin_gesture(0);
+        history.set(&store, 0, 600.0);
+        history.set(&store, 1, 0.5);
+        history.set(&store, 0, 700.0);
+        history.end_gesture();
+        assert!(history.undo(&store));
+        assert_eq!(store.get(0), 600.0);
+        assert!(history.undo(&store));
+        assert_eq!(store.get(1), 1.0);
+        assert!(history.undo(&store));
+        assert_eq!(store.get(0), 500.0);
+    }
+
+    #[test]
+    fn capacity_limits_the_number_of_steps() {
+        let store = store();
+        let mut history = UndoHistory::new(2);
+        history.set(&store, 0, 100.0);
+        history.set(&store, 0, 200.0);
+        history.change_all(&store, |store| store.reset());
+        assert!(history.undo(&store));
+        assert_eq!(store.get(0), 200.0);
+        assert!(history.undo(&store));
+        assert_eq!(store.get(0), 100.0);
+        assert!(!history.undo(&store));
+    }
+}
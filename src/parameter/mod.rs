@@ -0,0 +1,393 @@
+//! Parameters that can be changed by the host, a graphical editor or a remote control.
+//!
+//! The parameters are declared with a list of [`ParameterInfo`]s and their values are kept
+//! in a [`ParameterStore`].
+//! The `ParameterStore` can be shared (e.g. in an `Arc`) between the audio thread and other
+//! threads: reading and writing the values is lock-free.
+//!
+//! Normalized values
+//! =================
+//! Internally, values are stored "normalized", i.e. in the range `0.0..=1.0`.
+//! This is what plugin hosts and graphical editors typically work with.
+//! The plugin itself typically works with the "plain" values in the range
+//! `info.min..=info.max`.
+//!
+//! Random patches
+//! ==============
+//! [`ParameterStore::randomize`] sets the parameters to random values and
+//! [`ParameterStore::mutate`] moves them by a random amount, e.g. for a "random patch" button
+//! or to fuzz-test the stability of the DSP code. Both take a seedable [`Random`] generator, so
+//! that the result can be reproduced.
+//! Parameters that are locked with [`ParameterStore::set_locked`] are left unchanged and
+//! [`ParameterStore::set_random_range`] limits the random values of a parameter, e.g. to avoid
+//! extreme settings.
+//!
+//! ```
+//! use rsynth::parameter::{ParameterInfo, ParameterStore};
+//! use rsynth::utilities::random::Random;
+//!
+//! let store = ParameterStore::new(vec![
+//!     ParameterInfo::new("Cutoff", "Hz", 20.0, 20020.0, 1020.0),
+//!     ParameterInfo::new("Volume", "", 0.0, 1.0, 0.8),
+//! ]);
+//! store.set_random_range(0, 200.0, 5000.0);
+//! store.set_locked(1, true);
+//! store.randomize(&mut Random::new(1234));
+//! assert!(200.0 <= store.get(0) && store.get(0) <= 5000.0);
+//! assert_eq!(store.get(1), 0.8);
+//! ```
+//!
+//! A/B comparison
+//! ==============
+//! The [`ab`] module keeps a snapshot of the complete state of the plugin, so that the user
+//! can compare two settings (only when compiled with the `trash-can` feature).
+//!
+//! Plugin hosts
+//! ============
+//! Plugins implement the [`Parameters`] trait to expose their parameters to the host, e.g.
+//! with the VST backend, so that the host can automate them.
+//!
+//! Presets
+//! =======
+//! The [`preset`] module saves and loads the values of the parameters as presets, with tags
+//! and a search index for preset browsers.
+//!
+//! Undo
+//! ====
+//! The [`history`] module records changes of parameters by an editor or a remote control, so
+//! that they can be undone and redone.
+//!
+//! [`ab`]: ./ab/index.html
+//! [`preset`]: ./preset/index.html
+//! [`history`]: ./history/index.html
+//! [`ParameterInfo`]: ./struct.ParameterInfo.html
+//! [`ParameterStore`]: ./struct.ParameterStore.html
+//! [`Parameters`]: ./trait.Parameters.html
+//! [`ParameterStore::randomize`]: ./struct.ParameterStore.html#method.randomize
+//! [`ParameterStore::mutate`]: ./struct.ParameterStore.html#method.mutate
+//! [`ParameterStore::set_locked`]: ./struct.ParameterStore.html#method.set_locked
+//! [`ParameterStore::set_random_range`]: ./struct.ParameterStore.html#method.set_random_range
+//! [`Random`]: ../utilities/random/struct.Random.html
+use crate::utilities::random::Random;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "trash-can")]
+pub mod ab;
+pub mod history;
+pub mod preset;
+
+/// The declaration of a parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterInfo {
+    /// The name of the parameter, e.g. "Cutoff".
+    pub name: String,
+    /// The unit of the parameter, e.g. "Hz". Can be empty.
+    pub label: String,
+    /// The minimum plain value.
+    pub min: f32,
+    /// The maximum plain value.
+    pub max: f32,
+    /// The default plain value.
+    pub default: f32,
+}
+
+impl ParameterInfo {
+    pub fn new(name: &str, label: &str, min: f32, max: f32, default: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            label: label.to_string(),
+            min,
+            max,
+            default,
+        }
+    }
+
+    /// Convert a plain value to a normalized value, clamping to `0.0..=1.0`.
+    pub fn normalize(&self, plain_value: f32) -> f32 {
+        if self.max == self.min {
+            return 0.0;
+        }
+        clamp((plain_value - self.min) / (self.max - self.min))
+    }
+
+    /// Convert a normalized value to a plain value.
+    pub fn denormalize(&self, normalized_value: f32) -> f32 {
+        self.min + clamp(normalized_value) * (self.max - self.min)
+    }
+}
+
+fn clamp(normalized_value: f32) -> f32 {
+    if normalized_value.is_nan() {
+        0.0
+    } else {
+        normalized_value.clamp(0.0, 1.0)
+    }
+}
+
+/// Stores the values of the parameters.
+///
+/// See the [module level documentation] for more information.
+///
+/// All methods except `new` are lock-free and do not allocate memory,
+/// so they can be used in a real-time context.
+///
+/// Panics
+/// ------
+/// The methods that take an index panic when the index is out of bounds.
+///
+/// [module level documentation]: ./index.html
+pub struct ParameterStore {
+    infos: Vec<ParameterInfo>,
+    values: Vec<AtomicU32>,
+    locks: Vec<AtomicBool>,
+    // The normalized lower and upper bound of the random values of every parameter.
+    random_ranges: Vec<(AtomicU32, AtomicU32)>,
+}
+
+impl ParameterStore {
+    /// Create a new `ParameterStore` with the given parameters, set to their default values.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory.
+    pub fn new(infos: Vec<ParameterInfo>) -> Self {
+        let values: Vec<AtomicU32> = infos
+            .iter()
+            .map(|info| AtomicU32::new(info.normalize(info.default).to_bits()))
+            .collect();
+        let locks = infos.iter().map(|_| AtomicBool::new(false)).collect();
+        let random_ranges = infos
+            .iter()
+            .map(|_| {
+                (
+                    AtomicU32::new(0.0_f32.to_bits()),
+                    AtomicU32::new(1.0_f32.to_bits()),
+                )
+            })
+            .collect();
+        Self {
+            infos,
+            values,
+            locks,
+            random_ranges,
+        }
+    }
+
+    /// The number of parameters.
+    pub fn len(&self) -> usize {
+        self.infos.len()
+    }
+
+    /// Return `true` if there are no parameters.
+    pub fn is_empty(&self) -> bool {
+        self.infos.is_empty()
+    }
+
+    /// Get the declarations of all parameters.
+    pub fn infos(&self) -> &[ParameterInfo] {
+        &self.infos
+    }
+
+    /// Get the declaration of the parameter with the given index.
+    pub fn info(&self, index: usize) -> &ParameterInfo {
+        &self.infos[index]
+    }
+
+    /// The index of the parameter with the given name.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.infos.iter().position(|info| info.name == name)
+    }
+
+    /// Get the normalized value of the parameter with the given index.
+    pub fn get_normalized(&self, index: usize) -> f32 {
+        f32::from_bits(self.values[index].load(Ordering::Relaxed))
+    }
+
+    /// Set the normalized value of the parameter with the given index.
+    /// The value is clamped to `0.0..=1.0`.
+    pub fn set_normalized(&self, index: usize, value: f32) {
+        self.values[index].store(clamp(value).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Get the plain value of the parameter with the given index.
+    pub fn get(&self, index: usize) -> f32 {
+        self.infos[index].denormalize(self.get_normalized(index))
+    }
+
+    /// Set the plain value of the parameter with the given index.
+    /// The value is clamped to the range of the parameter.
+    pub fn set(&self, index: usize, value: f32) {
+        self.set_normalized(index, self.infos[index].normalize(value));
+    }
+
+    /// Reset all parameters to their default values.
+    pub fn reset(&self) {
+        for (index, info) in self.infos.iter().enumerate() {
+            self.set(index, info.default);
+        }
+    }
+
+    /// Return `true` if the parameter with the given index is locked, i.e. if it is not
+    /// changed by [`randomize`] and [`mutate`].
+    ///
+    /// [`randomize`]: #method.randomize
+    /// [`mutate`]: #method.mutate
+    pub fn is_locked(&self, index: usize) -> bool {
+        self.locks[index].load(Ordering::Relaxed)
+    }
+
+    /// Lock or unlock the parameter with the given index.
+    /// Locking does not prevent changing the parameter with [`set`] or [`set_normalized`].
+    ///
+    /// [`set`]: #method.set
+    /// [`set_normalized`]: #method.set_normalized
+    pub fn set_locked(&self, index: usize, locked: bool) {
+        self.locks[index].store(locked, Ordering::Relaxed);
+    }
+
+    /// The range of plain values that [`randomize`] and [`mutate`] use for the parameter
+    /// with the given index. By default, this is the full range of the parameter.
+    ///
+    /// [`randomize`]: #method.randomize
+    /// [`mutate`]: #method.mutate
+    pub fn random_range(&self, index: usize) -> (f32, f32) {
+        let (low, high) = self.normalized_random_range(index);
+        let info = &self.infos[index];
+        (info.denormalize(low), info.denormalize(high))
+    }
+
+    /// Limit the values that [`randomize`] and [`mutate`] use for the parameter with the given
+    /// index to the plain values between `min` and `max`, clamped to the range of the parameter.
+    ///
+    /// [`randomize`]: #method.randomize
+    /// [`mutate`]: #method.mutate
+    pub fn set_random_range(&self, index: usize, min: f32, max: f32) {
+        let info = &self.infos[index];
+        let (min, max) = (info.normalize(min), info.normalize(max));
+        let (low, high) = if min <= max { (min, max) } else { (max, min) };
+        let range = &self.random_ranges[index];
+        range.0.store(low.to_bits(), Ordering::Relaxed);
+        range.1.store(high.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Set every parameter that is not locked to a random value within its random range.
+    pub fn randomize(&self, random: &mut Random) {
+        for index in 0..self.len() {
+            if self.is_locked(index) {
+                continue;
+            }
+            let (low, high) = self.normalized_random_range(index);
+            let value = low + (random.next_unipolar() as f32) * (high - low);
+            self.set_normalized(index, value);
+        }
+    }
+
+    /// Move every parameter that is not locked by a random amount of at most `amount`
+    /// (as a normalized value, so `0.1` is 10% of the range of the parameter).
+    /// The new value is kept within the random range of the parameter.
+    pub fn mutate(&self, random: &mut Random, amount: f32) {
+        for index in 0..self.len() {
+            if self.is_locked(index) {
+                continue;
+            }
+            let (low, high) = self.normalized_random_range(index);
+            let value = self.get_normalized(index) + (random.next_bipolar() as f32) * amount;
+            self.set_normalized(index, value.clamp(low, high));
+        }
+    }
+
+    fn normalized_random_range(&self, index: usize) -> (f32, f32) {
+        let range = &self.random_ranges[index];
+        (
+            f32::from_bits(range.0.load(Ordering::Relaxed)),
+            f32::from_bits(range.1.load(Ordering::Relaxed)),
+        )
+    }
+}
+
+/// Implemented by plugins whose parameters can be changed by the host, e.g. to automate them.
+///
+/// The plugin shares its [`ParameterStore`] with the backend and reads the values of the
+/// parameters from it when rendering.
+///
+/// [`ParameterStore`]: ./struct.ParameterStore.html
+pub trait Parameters {
+    /// The parameters of the plugin.
+    fn parameters(&self) -> Arc<ParameterStore>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParameterInfo, ParameterStore};
+    use crate::utilities::random::Random;
+
+    fn store() -> ParameterStore {
+        ParameterStore::new(vec![
+            ParameterInfo::new("Cutoff", "Hz", 20.0, 20020.0, 1020.0),
+            ParameterInfo::new("Volume", "", 0.0, 1.0, 1.0),
+        ])
+    }
+
+    #[test]
+    fn parameters_start_at_their_default_value() {
+        let store = store();
+        assert_eq!(store.get(0), 1020.0);
+        assert_eq!(store.get_normalized(0), 0.05);
+        assert_eq!(store.get(1), 1.0);
+    }
+
+    #[test]
+    fn values_are_clamped() {
+        let store = store();
+        store.set(0, 0.0);
+        assert_eq!(store.get(0), 20.0);
+        store.set_normalized(1, 2.0);
+        assert_eq!(store.get_normalized(1), 1.0);
+        store.set_normalized(1, f32::NAN);
+        assert_eq!(store.get_normalized(1), 0.0);
+    }
+
+    #[test]
+    fn reset_restores_default_values() {
+        let store = store();
+        store.set(0, 5020.0);
+        store.set(1, 0.5);
+        store.reset();
+        assert_eq!(store.get(0), 1020.0);
+        assert_eq!(store.get(1), 1.0);
+    }
+
+    #[test]
+    fn randomize_respects_locks_and_ranges() {
+        let store = store();
+        store.set_locked(1, true);
+        store.set_random_range(0, 5020.0, 1020.0);
+        assert_eq!(store.random_range(0), (1020.0, 5020.0));
+        let mut random = Random::new(99);
+        for _ in 0..100 {
+            store.randomize(&mut random);
+            assert!(1020.0 <= store.get(0) && store.get(0) <= 5020.0);
+            assert_eq!(store.get(1), 1.0);
+        }
+        store.randomize(&mut Random::new(5));
+        let first = store.get(0);
+        store.randomize(&mut Random::new(5));
+        assert_eq!(store.get(0), first);
+    }
+
+    #[test]
+    fn mutate_moves_by_at_most_the_amount() {
+        let store = store();
+        store.set_normalized(0, 0.5);
+        let mut random = Random::new(3);
+        for _ in 0..100 {
+            let before = store.get_normalized(0);
+            store.mutate(&mut random, 0.1);
+            assert!((store.get_normalized(0) - before).abs() <= 0.1 + 1e-6);
+        }
+        store.set_random_range(0, 20.0, 20.0);
+        store.mutate(&mut random, 0.1);
+        assert_eq!(store.get(0), 20.0);
+    }
+}
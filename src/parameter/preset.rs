@@ -0,0 +1,588 @@
+//! Presets with meta-data (author, category, tags) and a search index over a directory of
+//! presets, e.g. for a preset browser.
+//!
+//! A [`Preset`] contains the plain values of the parameters of a [`ParameterStore`], by name,
+//! so that presets remain valid when parameters are added or reordered.
+//! Presets are stored in a simple text format:
+//!
+//! ```text
+//! name = Warm pad
+//! author = Jane Doe
+//! category = Pad
+//! tags = warm, soft
+//!
+//! [parameters]
+//! Cutoff = 1020
+//! Volume = 0.8
+//! ```
+//!
+//! Empty lines and lines that start with `#` are ignored.
+//!
+//! A [`PresetIndex`] contains the meta-data of all presets in a directory and its
+//! sub-directories. Scanning a directory performs I/O, so it must not be done on the audio
+//! thread; [`PresetIndex::scan_in_background`] scans in a separate thread.
+//! Use a [`PresetFilter`] to search the index.
+//!
+//! Example
+//! -------
+//! ```
+//! use rsynth::parameter::{ParameterInfo, ParameterStore};
+//! use rsynth::parameter::preset::{Preset, PresetFilter};
+//!
+//! let store = ParameterStore::new(vec![ParameterInfo::new("Cutoff", "Hz", 20.0, 20020.0, 1020.0)]);
+//! let mut preset = Preset::from_store("Warm pad", &store);
+//! preset.category = "Pad".to_string();
+//! preset.tags = vec!["warm".to_string(), "soft".to_string()];
+//! let text = preset.to_text();
+//! let loaded = Preset::from_text(&text).expect("a valid preset");
+//! assert_eq!(loaded, preset);
+//! assert!(PresetFilter::new().with_text("WARM").with_tag("soft").matches(&loaded));
+//! ```
+//!
+//! [`Preset`]: ./struct.Preset.html
+//! [`ParameterStore`]: ../struct.ParameterStore.html
+//! [`PresetIndex`]: ./struct.PresetIndex.html
+//! [`PresetIndex::scan_in_background`]: ./struct.PresetIndex.html#method.scan_in_background
+//! [`PresetFilter`]: ./struct.PresetFilter.html
+use super::ParameterStore;
+use crate::{InvalidState, PluginState};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+/// The extension of preset files.
+pub const EXTENSION: &str = "preset";
+
+const PARAMETERS_SECTION: &str = "[parameters]";
+
+/// The error type that represents the errors you can get when loading a [`Preset`].
+///
+/// [`Preset`]: ./struct.Preset.html
+#[derive(Debug)]
+pub enum PresetError {
+    /// An error occurred when reading or writing the file.
+    Io(io::Error),
+    /// The line with the given (1-based) line number is not of the form `key = value`.
+    InvalidLine(usize),
+    /// The line with the given (1-based) line number has an unknown key.
+    UnknownKey(usize),
+    /// The value of the parameter on the line with the given (1-based) line number
+    /// is not a number.
+    InvalidValue(usize),
+}
+
+/// A preset: the values of the parameters, together with meta-data.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Preset {
+    pub name: String,
+    pub author: String,
+    pub category: String,
+    pub tags: Vec<String>,
+    /// The name and the plain value of every parameter.
+    pub values: Vec<(String, f32)>,
+}
+
+impl Preset {
+    /// Create a preset with the current values of the parameters in the `store`.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn from_store(name: &str, store: &ParameterStore) -> Self {
+        Preset {
+            name: name.to_string(),
+            values: store
+                .infos()
+                .iter()
+                .enumerate()
+                .map(|(index, info)| (info.name.clone(), store.get(index)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the parameters in the `store` to the values of the preset.
+    /// Values of parameters that are not in the `store` are ignored.
+    /// Returns the number of parameters that have been set.
+    pub fn apply(&self, store: &ParameterStore) -> usize {
+        let mut number_of_parameters = 0;
+        for (name, value) in self.values.iter() {
+            if let Some(index) = store.index_of(name) {
+                store.set(index, *value);
+                number_of_parameters += 1;
+            }
+        }
+        number_of_parameters
+    }
+
+    /// Return `true` if the preset has the given tag (ignoring case).
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    /// Convert the preset to the text format that is described in the
+    /// [module level documentation].
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    ///
+    /// [module level documentation]: ./index.html
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "name = {}\nauthor = {}\ncategory = {}\ntags = {}\n\n{}\n",
+            self.name,
+            self.author,
+            self.category,
+            self.tags.join(", "),
+            PARAMETERS_SECTION
+        );
+        for (name, value) in self.values.iter() {
+            text.push_str(&format!("{} = {}\n", name, value));
+        }
+        text
+    }
+
+    /// Parse a preset in the text format that is described in the [module level documentation].
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    ///
+    /// [module level documentation]: ./index.html
+    pub fn from_text(text: &str) -> Result<Self, PresetError> {
+        let mut preset = Preset::default();
+        let mut in_parameters = false;
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line == PARAMETERS_SECTION {
+                in_parameters = true;
+                continue;
+            }
+            if in_parameters {
+                // Parameter names may contain `=`, values do not.
+                let (name, value) = line
+                    .rsplit_once('=')
+                    .ok_or(PresetError::InvalidLine(line_number))?;
+                let value = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| PresetError::InvalidValue(line_number))?;
+                preset.values.push((name.trim().to_string(), value));
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(PresetError::InvalidLine(line_number))?;
+            let value = value.trim().to_string();
+            match key.trim() {
+                "name" => preset.name = value,
+                "author" => preset.author = value,
+                "category" => preset.category = value,
+                "tags" => {
+                    preset.tags = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|tag| !tag.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                _ => return Err(PresetError::UnknownKey(line_number)),
+            }
+        }
+        Ok(preset)
+    }
+
+    /// Load a preset from a file.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory and performs I/O.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PresetError> {
+        let text = fs::read_to_string(path).map_err(PresetError::Io)?;
+        Self::from_text(&text)
+    }
+
+    /// Save the preset to a file.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory and performs I/O.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PresetError> {
+        fs::write(path, self.to_text()).map_err(PresetError::Io)
+    }
+}
+
+/// The meta-data of a preset file in a [`PresetIndex`].
+///
+/// [`PresetIndex`]: ./struct.PresetIndex.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct PresetEntry {
+    /// The path of the preset file; use [`Preset::load`] to load the values.
+    ///
+    /// [`Preset::load`]: ./struct.Preset.html#method.load
+    pub path: PathBuf,
+    pub name: String,
+    pub author: String,
+    pub category: String,
+    pub tags: Vec<String>,
+}
+
+impl PresetEntry {
+    fn new(path: PathBuf, preset: Preset) -> Self {
+        PresetEntry {
+            path,
+            name: preset.name,
+            author: preset.author,
+            category: preset.category,
+            tags: preset.tags,
+        }
+    }
+}
+
+/// Criteria to search a [`PresetIndex`].
+///
+/// All comparisons ignore case. A preset matches when it matches all criteria that are set.
+///
+/// [`PresetIndex`]: ./struct.PresetIndex.html
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PresetFilter {
+    /// Text that must occur in the name, the author, the category or one of the tags.
+    pub text: Option<String>,
+    pub category: Option<String>,
+    pub author: Option<String>,
+    /// Tags that the preset must all have.
+    pub tags: Vec<String>,
+}
+
+impl PresetFilter {
+    /// Create a filter that matches every preset.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.text = Some(text.to_string());
+        self
+    }
+
+    pub fn with_category(mut self, category: &str) -> Self {
+        self.category = Some(category.to_string());
+        self
+    }
+
+    pub fn with_author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Return `true` if the preset matches the filter.
+    pub fn matches(&self, preset: &Preset) -> bool {
+        self.matches_fields(&preset.name, &preset.author, &preset.category, &preset.tags)
+    }
+
+    /// Return `true` if the entry of the index matches the filter.
+    pub fn matches_entry(&self, entry: &PresetEntry) -> bool {
+        self.matches_fields(&entry.name, &entry.author, &entry.category, &entry.tags)
+    }
+
+    fn matches_fields(&self, name: &str, author: &str, category: &str, tags: &[String]) -> bool {
+        let equal = |expected: &Option<String>, actual: &str| match expected {
+            Some(expected) => expected.eq_ignore_ascii_case(actual),
+            None => true,
+        };
+        if !equal(&self.category, category) || !equal(&self.author, author) {
+            return false;
+        }
+        if !self
+            .tags
+            .iter()
+            .all(|tag| tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+        {
+            return false;
+        }
+        match &self.text {
+            Some(text) => {
+                let text = text.to_lowercase();
+                let contains = |field: &str| field.to_lowercase().contains(&text);
+                contains(name)
+                    || contains(author)
+                    || contains(category)
+                    || tags.iter().any(|tag| contains(tag))
+            }
+            None => true,
+        }
+    }
+}
+
+/// The meta-data of all presets in a directory.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+#[derive(Clone, Debug, Default)]
+pub struct PresetIndex {
+    entries: Vec<PresetEntry>,
+}
+
+impl PresetIndex {
+    /// Scan the directory and its sub-directories for files with the [`EXTENSION`] extension.
+    /// Files that cannot be parsed are skipped with a warning.
+    /// Symbolic links are followed, but every directory is scanned at most once.
+    /// The entries are sorted by name.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory and performs I/O.
+    ///
+    /// [`EXTENSION`]: ./constant.EXTENSION.html
+    pub fn scan<P: AsRef<Path>>(directory: P) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        scan_directory(directory.as_ref(), &mut HashSet::new(), &mut entries)?;
+        entries.sort_by_key(|entry| entry.name.to_lowercase());
+        Ok(PresetIndex { entries })
+    }
+
+    /// Scan the directory in a new thread, see [`scan`].
+    ///
+    /// [`scan`]: #method.scan
+    pub fn scan_in_background(directory: PathBuf) -> JoinHandle<io::Result<Self>> {
+        thread::spawn(move || Self::scan(directory))
+    }
+
+    /// Add a preset that has been saved to the given path, e.g. after the user saved a preset.
+    pub fn insert(&mut self, path: PathBuf, preset: &Preset) {
+        self.entries.retain(|entry| entry.path != path);
+        let entry = PresetEntry::new(path, preset.clone());
+        let position = self
+            .entries
+            .iter()
+            .position(|e| e.name.to_lowercase() > entry.name.to_lowercase())
+            .unwrap_or(self.entries.len());
+        self.entries.insert(position, entry);
+    }
+
+    /// All entries, sorted by name.
+    pub fn entries(&self) -> &[PresetEntry] {
+        &self.entries
+    }
+
+    /// The entries that match the filter, sorted by name.
+    pub fn search<'a>(&'a self, filter: &'a PresetFilter) -> impl Iterator<Item = &'a PresetEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| filter.matches_entry(entry))
+    }
+
+    /// All categories that occur in the index, sorted and without duplicates.
+    pub fn categories(&self) -> Vec<&str> {
+        sorted_unique(self.entries.iter().map(|entry| entry.category.as_str()))
+    }
+
+    /// All tags that occur in the index, sorted and without duplicates.
+    pub fn tags(&self) -> Vec<&str> {
+        sorted_unique(
+            self.entries
+                .iter()
+                .flat_map(|entry| entry.tags.iter().map(String::as_str)),
+        )
+    }
+}
+
+fn sorted_unique<'a, I: Iterator<Item = &'a str>>(values: I) -> Vec<&'a str> {
+    let mut values: Vec<&str> = values.filter(|value| !value.is_empty()).collect();
+    values.sort_unstable();
+    values.dedup();
+    values
+}
+
+// `visited` contains the canonical paths of the directories that have already been scanned,
+// so that symbolic links that point to a parent directory do not cause an endless recursion.
+fn scan_directory(
+    directory: &Path,
+    visited: &mut HashSet<PathBuf>,
+    entries: &mut Vec<PresetEntry>,
+) -> io::Result<()> {
+    if !visited.insert(directory.canonicalize()?) {
+        return Ok(());
+    }
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            scan_directory(&path, visited, entries)?;
+        } else if path.extension() == Some(OsStr::new(EXTENSION)) {
+            match Preset::load(&path) {
+                Ok(preset) => entries.push(PresetEntry::new(path, preset)),
+                Err(e) => warn!("Skipping preset {}: {:?}", path.display(), e),
+            }
+        }
+    }
+    Ok(())
+}
+
+impl ParameterStore {
+    /// Serialize the values of the parameters in the preset text format.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn save_state(&self) -> Vec<u8> {
+        Preset::from_store("", self).to_text().into_bytes()
+    }
+
+    /// Set the parameters to the values that have been serialized with [`save_state`].
+    /// Values of parameters that do not exist (anymore) are ignored.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    ///
+    /// [`save_state`]: #method.save_state
+    pub fn load_state(&self, state: &[u8]) -> Result<(), InvalidState> {
+        let text = std::str::from_utf8(state).map_err(|_| InvalidState)?;
+        let preset = Preset::from_text(text).map_err(|_| InvalidState)?;
+        preset.apply(self);
+        Ok(())
+    }
+}
+
+impl PluginState for ParameterStore {
+    fn save_state(&self) -> Vec<u8> {
+        ParameterStore::save_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), InvalidState> {
+        ParameterStore::load_state(self, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameter::ParameterInfo;
+
+    fn preset(name: &str, category: &str, tags: &[&str]) -> Preset {
+        Preset {
+            name: name.to_string(),
+            author: "rsynth".to_string(),
+            category: category.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            values: vec![("Cutoff".to_string(), 440.0), ("Gone".to_string(), 1.0)],
+        }
+    }
+
+    #[test]
+    fn presets_round_trip_and_apply_by_name() {
+        let original = preset("Bass = deep", "Bass", &["dark", "mono"]);
+        let loaded = Preset::from_text(&original.to_text()).expect("a valid preset");
+        assert_eq!(loaded, original);
+        let store = ParameterStore::new(vec![
+            ParameterInfo::new("Volume", "", 0.0, 1.0, 0.5),
+            ParameterInfo::new("Cutoff", "Hz", 20.0, 20020.0, 1020.0),
+        ]);
+        assert_eq!(loaded.apply(&store), 1);
+        assert_eq!(store.get(1), 440.0);
+        assert_eq!(store.get(0), 0.5);
+        match Preset::from_text("[parameters]\nCutoff = high") {
+            Err(PresetError::InvalidValue(2)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match Preset::from_text("# comment\ncolour = red") {
+            Err(PresetError::UnknownKey(2)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn index_scans_directories_and_searches() {
+        let directory =
+            std::env::temp_dir().join(format!("rsynth-preset-index-test-{}", std::process::id()));
+        let bass = directory.join("bass");
+        fs::create_dir_all(&bass).unwrap();
+        preset("Sub", "Bass", &["dark"])
+            .save(bass.join("sub.preset"))
+            .unwrap();
+        preset("Warm pad", "Pad", &["warm", "soft"])
+            .save(directory.join("pad.preset"))
+            .unwrap();
+        fs::write(directory.join("broken.preset"), "nonsense").unwrap();
+        fs::write(directory.join("notes.txt"), "name = Ignored").unwrap();
+
+        let index = PresetIndex::scan_in_background(directory.clone())
+            .join()
+            .unwrap()
+            .unwrap();
+        let names: Vec<&str> = index.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["Sub", "Warm pad"]);
+        assert_eq!(index.categories(), vec!["Bass", "Pad"]);
+        assert_eq!(index.tags(), vec!["dark", "soft", "warm"]);
+
+        let filter = PresetFilter::new().with_text("PAD");
+        let found: Vec<&str> = index.search(&filter).map(|e| e.name.as_str()).collect();
+        assert_eq!(found, vec!["Warm pad"]);
+        let filter = PresetFilter::new().with_category("bass").with_tag("warm");
+        assert_eq!(index.search(&filter).count(), 0);
+        let filter = PresetFilter::new().with_text("dark");
+        assert_eq!(
+            index.search(&filter).next().map(|e| e.path.clone()),
+            Some(bass.join("sub.preset"))
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn index_scans_directories_with_symbolic_link_loops_once() {
+        let directory = std::env::temp_dir().join(format!(
+            "rsynth-preset-index-loop-test-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        preset("Sub", "Bass", &["dark"])
+            .save(directory.join("sub.preset"))
+            .unwrap();
+        std::os::unix::fs::symlink(&directory, directory.join("loop")).unwrap();
+
+        let index = PresetIndex::scan(&directory).unwrap();
+        assert_eq!(index.entries().len(), 1);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn state_of_the_parameter_store() {
+        let store = ParameterStore::new(vec![
+            ParameterInfo::new("Cutoff", "Hz", 20.0, 20020.0, 1020.0),
+            ParameterInfo::new("Volume", "", 0.0, 1.0, 1.0),
+        ]);
+        store.set(0, 440.0);
+        let state = store.save_state();
+
+        // A newer version of the plugin with an extra parameter.
+        let mut newer = ParameterStore::new(vec![
+            ParameterInfo::new("Drive", "", 0.0, 1.0, 0.25),
+            ParameterInfo::new("Cutoff", "Hz", 20.0, 20020.0, 1020.0),
+        ]);
+        assert_eq!(PluginState::load_state(&mut newer, &state), Ok(()));
+        assert_eq!(newer.get(1), 440.0);
+        assert_eq!(newer.get(0), 0.25);
+        assert_eq!(newer.load_state(&[0xFF, 0xFE]), Err(InvalidState));
+    }
+}
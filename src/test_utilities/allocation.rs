@@ -0,0 +1,142 @@
+//! Check that code does not allocate or deallocate memory, e.g. because it is meant to be used
+//! in a real-time context.
+//!
+//! In order to use this, the test binary must use the [`AllocationChecker`] as its global
+//! allocator. Then [`assert_no_allocation`] can be used to check that a closure does not
+//! allocate or deallocate memory.
+//!
+//! Only allocations on the current thread are taken into account, so that tests that run
+//! in parallel do not interfere with each other.
+//!
+//! Example
+//! -------
+//! ```
+//! use rsynth::test_utilities::allocation::{assert_no_allocation, AllocationChecker};
+//! use std::alloc::System;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: AllocationChecker<System> = AllocationChecker::new(System);
+//!
+//! # fn main() {
+//! let mut buffer = Vec::with_capacity(16);
+//! assert_no_allocation(|| {
+//!     // Does not allocate because the capacity is large enough.
+//!     buffer.push(1.0);
+//! });
+//! # }
+//! ```
+//!
+//! [`AllocationChecker`]: ./struct.AllocationChecker.html
+//! [`assert_no_allocation`]: ./fn.assert_no_allocation.html
+use std::alloc::{GlobalAlloc, Layout};
+use std::cell::Cell;
+
+thread_local! {
+    static CHECKING: Cell<bool> = const { Cell::new(false) };
+    static NUMBER_OF_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn record_allocation() {
+    // `try_with` because the thread local may already be destroyed when the thread exits.
+    let checking = CHECKING
+        .try_with(|checking| checking.get())
+        .unwrap_or(false);
+    if checking {
+        let _ = NUMBER_OF_ALLOCATIONS.try_with(|number| number.set(number.get() + 1));
+    }
+}
+
+/// A global allocator that counts allocations, reallocations and deallocations
+/// while [`assert_no_allocation`] is running on the current thread.
+/// The memory management itself is forwarded to the wrapped allocator.
+///
+/// See the [module level documentation] for more information.
+///
+/// [`assert_no_allocation`]: ./fn.assert_no_allocation.html
+/// [module level documentation]: ./index.html
+pub struct AllocationChecker<A> {
+    inner: A,
+}
+
+impl<A> AllocationChecker<A> {
+    /// Create a new `AllocationChecker` that forwards to the given allocator
+    /// (typically `std::alloc::System`).
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+unsafe impl<A> GlobalAlloc for AllocationChecker<A>
+where
+    A: GlobalAlloc,
+{
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        self.inner.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        record_allocation();
+        self.inner.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record_allocation();
+        self.inner.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record_allocation();
+        self.inner.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Call `function` and panic if it allocated, reallocated or deallocated memory on the
+/// current thread.
+///
+/// This only detects allocations if an [`AllocationChecker`] is used as the global allocator,
+/// see the [module level documentation].
+///
+/// [`AllocationChecker`]: ./struct.AllocationChecker.html
+/// [module level documentation]: ./index.html
+pub fn assert_no_allocation<F, T>(function: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let (result, number_of_allocations) = count_allocations(function);
+    assert_eq!(
+        number_of_allocations, 0,
+        "Expected no memory allocations, but memory was (re- or de-)allocated {} times.",
+        number_of_allocations
+    );
+    result
+}
+
+/// Call `function` and return its result and the number of times it allocated, reallocated
+/// or deallocated memory on the current thread.
+///
+/// This only detects allocations if an [`AllocationChecker`] is used as the global allocator,
+/// see the [module level documentation].
+///
+/// [`AllocationChecker`]: ./struct.AllocationChecker.html
+/// [module level documentation]: ./index.html
+pub fn count_allocations<F, T>(function: F) -> (T, usize)
+where
+    F: FnOnce() -> T,
+{
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            CHECKING.with(|checking| checking.set(false));
+        }
+    }
+
+    let was_checking = CHECKING.with(|checking| checking.replace(true));
+    assert!(!was_checking, "Checking allocations cannot be nested.");
+    NUMBER_OF_ALLOCATIONS.with(|number| number.set(0));
+    let result = {
+        let _reset = Reset;
+        function()
+    };
+    (result, NUMBER_OF_ALLOCATIONS.with(|number| number.get()))
+}
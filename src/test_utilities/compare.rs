@@ -0,0 +1,402 @@
+//! Compare the output of two renderers, e.g. to check that an optimized implementation
+//! renders the same as the original one.
+//!
+//! [`compare`] feeds the same audio input and the same events to both renderers, buffer by
+//! buffer, and returns a [`Comparison`] with the maximum difference between the samples,
+//! the RMS error and the location of the first sample where the outputs diverge.
+//!
+//! Example
+//! -------
+//! ```
+//! use rsynth::test_utilities::compare::{compare, CompareSettings};
+//! use rsynth::utilities::trivial_renderers::PassThrough;
+//! use rsynth::event::{RawMidiEvent, Timed};
+//!
+//! let input: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.01).sin()).collect();
+//! let events: Vec<Timed<RawMidiEvent>> = Vec::new();
+//! let comparison = compare(
+//!     &mut PassThrough::new(1),
+//!     &mut PassThrough::new(1),
+//!     &CompareSettings::new(1, input.len()),
+//!     &[&input],
+//!     &events,
+//! );
+//! assert_eq!(comparison.max_difference, 0.0);
+//! assert_eq!(comparison.first_divergence, None);
+//! ```
+//!
+//! [`compare`]: ./fn.compare.html
+//! [`Comparison`]: ./struct.Comparison.html
+use crate::event::{EventHandler, Timed};
+use crate::{AudioHandler, AudioRenderer};
+use asprim::AsPrim;
+use num_traits::Zero;
+use std::fmt::{self, Display, Formatter};
+
+/// The settings for [`compare`].
+///
+/// [`compare`]: ./fn.compare.html
+#[derive(Clone, Debug)]
+pub struct CompareSettings {
+    /// The number of audio outputs of the renderers.
+    pub number_of_outputs: usize,
+    /// The total number of frames that are rendered.
+    pub number_of_frames: usize,
+    /// The number of frames of every buffer. The last buffer may be shorter.
+    pub buffer_size: usize,
+    /// The sample rate that is passed to the renderers with `set_sample_rate`.
+    pub sample_rate: f64,
+    /// Samples that differ by at most this amount are considered equal when looking for
+    /// the first divergence.
+    pub tolerance: f64,
+}
+
+impl CompareSettings {
+    /// Create settings with a buffer size of 64 frames, a sample rate of 44100 Hz
+    /// and a tolerance of `0`.
+    pub fn new(number_of_outputs: usize, number_of_frames: usize) -> Self {
+        CompareSettings {
+            number_of_outputs,
+            number_of_frames,
+            buffer_size: 64,
+            sample_rate: 44100.0,
+            tolerance: 0.0,
+        }
+    }
+}
+
+/// A sample where the outputs of the two renderers differ by more than the tolerance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Divergence {
+    /// The index of the output channel.
+    pub channel: usize,
+    /// The index of the frame, counted from the start of rendering.
+    pub frame: usize,
+    /// The sample that is rendered by the first renderer.
+    pub first: f64,
+    /// The sample that is rendered by the second renderer.
+    pub second: f64,
+}
+
+/// The result of [`compare`].
+///
+/// [`compare`]: ./fn.compare.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    /// The maximum absolute difference between two corresponding samples.
+    pub max_difference: f64,
+    /// The root mean square of the differences between corresponding samples.
+    pub rms_error: f64,
+    /// The first sample (in time, and then by channel) where the outputs differ by more
+    /// than the tolerance, or `None` if the outputs are equal within the tolerance.
+    pub first_divergence: Option<Divergence>,
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "max. difference: {}, RMS error: {}",
+            self.max_difference, self.rms_error
+        )?;
+        match self.first_divergence {
+            Some(divergence) => write!(
+                f,
+                ", first divergence in channel {} at frame {}: {} vs. {}",
+                divergence.channel, divergence.frame, divergence.first, divergence.second
+            ),
+            None => write!(f, ", no divergence"),
+        }
+    }
+}
+
+/// Render `settings.number_of_frames` frames with both renderers and compare their outputs.
+///
+/// Both renderers receive the same `inputs` and the same `events`. The `time_in_frames` of the
+/// `events` is counted from the start of rendering; the events must be sorted by time.
+/// Every event is passed to the renderers before the buffer in which it occurs is rendered,
+/// with `time_in_frames` relative to the start of that buffer.
+///
+/// # Panics
+/// Panics if an input has fewer than `settings.number_of_frames` frames or if
+/// `settings.buffer_size` is `0`.
+pub fn compare<S, E, A, B>(
+    first: &mut A,
+    second: &mut B,
+    settings: &CompareSettings,
+    inputs: &[&[S]],
+    events: &[Timed<E>],
+) -> Comparison
+where
+    S: AsPrim + Zero + Copy,
+    E: Clone,
+    A: AudioRenderer<S> + EventHandler<Timed<E>> + AudioHandler,
+    B: AudioRenderer<S> + EventHandler<Timed<E>> + AudioHandler,
+{
+    assert!(settings.buffer_size > 0);
+    for input in inputs {
+        assert!(input.len() >= settings.number_of_frames);
+    }
+    first.set_sample_rate(settings.sample_rate);
+    second.set_sample_rate(settings.sample_rate);
+
+    let mut first_outputs = vec![vec![S::zero(); settings.buffer_size]; settings.number_of_outputs];
+    let mut second_outputs =
+        vec![vec![S::zero(); settings.buffer_size]; settings.number_of_outputs];
+    let mut accumulator = Accumulator::new(settings.tolerance);
+    let mut remaining_events = events;
+    let mut start = 0;
+    while start < settings.number_of_frames {
+        let end = std::cmp::min(start + settings.buffer_size, settings.number_of_frames);
+        let length = end - start;
+
+        let number_of_events = remaining_events
+            .iter()
+            .take_while(|event| (event.time_in_frames as usize) < end)
+            .count();
+        for event in &remaining_events[..number_of_events] {
+            let timed = Timed {
+                time_in_frames: (event.time_in_frames as usize).saturating_sub(start) as u32,
+                event: event.event.clone(),
+            };
+            first.handle_event(timed.clone());
+            second.handle_event(timed);
+        }
+        remaining_events = &remaining_events[number_of_events..];
+
+        let buffer_inputs: Vec<&[S]> = inputs.iter().map(|input| &input[start..end]).collect();
+        render(first, &buffer_inputs, &mut first_outputs, length);
+        render(second, &buffer_inputs, &mut second_outputs, length);
+
+        for frame in 0..length {
+            for (channel, (first_output, second_output)) in
+                first_outputs.iter().zip(second_outputs.iter()).enumerate()
+            {
+                accumulator.add(
+                    channel,
+                    start + frame,
+                    first_output[frame].as_(),
+                    second_output[frame].as_(),
+                );
+            }
+        }
+        start = end;
+    }
+    accumulator.finish()
+}
+
+/// Compare two multi-channel signals that have already been rendered, e.g. the output of a
+/// renderer and a golden file.
+///
+/// Only the frames and channels that both signals have in common are compared.
+/// Samples that differ by at most `tolerance` are considered equal when looking for the
+/// first divergence.
+///
+/// ```
+/// use rsynth::test_utilities::compare::compare_channels;
+///
+/// let first: Vec<f32> = vec![0.0, 0.5, 1.0];
+/// let second: Vec<f32> = vec![0.0, 0.5, 0.75];
+/// let comparison = compare_channels(&[&first], &[&second], 0.1);
+/// assert_eq!(comparison.max_difference, 0.25);
+/// assert_eq!(comparison.first_divergence.map(|divergence| divergence.frame), Some(2));
+/// ```
+pub fn compare_channels<S>(first: &[&[S]], second: &[&[S]], tolerance: f64) -> Comparison
+where
+    S: AsPrim + Copy,
+{
+    let number_of_frames = first
+        .iter()
+        .chain(second.iter())
+        .map(|channel| channel.len())
+        .min()
+        .unwrap_or(0);
+    let mut accumulator = Accumulator::new(tolerance);
+    for frame in 0..number_of_frames {
+        for (channel, (first_channel, second_channel)) in
+            first.iter().zip(second.iter()).enumerate()
+        {
+            accumulator.add(
+                channel,
+                frame,
+                first_channel[frame].as_(),
+                second_channel[frame].as_(),
+            );
+        }
+    }
+    accumulator.finish()
+}
+
+// Accumulates the differences between corresponding samples into a `Comparison`.
+struct Accumulator {
+    comparison: Comparison,
+    tolerance: f64,
+    sum_of_squares: f64,
+    number_of_samples: usize,
+}
+
+impl Accumulator {
+    fn new(tolerance: f64) -> Self {
+        Accumulator {
+            comparison: Comparison {
+                max_difference: 0.0,
+                rms_error: 0.0,
+                first_divergence: None,
+            },
+            tolerance,
+            sum_of_squares: 0.0,
+            number_of_samples: 0,
+        }
+    }
+
+    fn add(&mut self, channel: usize, frame: usize, first: f64, second: f64) {
+        let difference = (first - second).abs();
+        self.sum_of_squares += difference * difference;
+        self.number_of_samples += 1;
+        if difference > self.comparison.max_difference || difference.is_nan() {
+            self.comparison.max_difference = difference;
+        }
+        let diverges = difference > self.tolerance || difference.is_nan();
+        if diverges && self.comparison.first_divergence.is_none() {
+            self.comparison.first_divergence = Some(Divergence {
+                channel,
+                frame,
+                first,
+                second,
+            });
+        }
+    }
+
+    fn finish(mut self) -> Comparison {
+        if self.number_of_samples > 0 {
+            self.comparison.rms_error =
+                (self.sum_of_squares / self.number_of_samples as f64).sqrt();
+        }
+        self.comparison
+    }
+}
+
+fn render<S, R>(renderer: &mut R, inputs: &[&[S]], outputs: &mut [Vec<S>], length: usize)
+where
+    S: Zero + Copy,
+    R: AudioRenderer<S>,
+{
+    let mut output_slices: Vec<&mut [S]> = outputs
+        .iter_mut()
+        .map(|output| {
+            let output = &mut output[..length];
+            for sample in output.iter_mut() {
+                *sample = S::zero();
+            }
+            output
+        })
+        .collect();
+    renderer.render_buffer(inputs, &mut output_slices);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::trivial_renderers::PassThrough;
+
+    // Multiplies its input by a gain; the gain is set with an event.
+    struct Gain {
+        gain: f32,
+        pending: Option<Timed<f32>>,
+    }
+
+    impl AudioRenderer<f32> for Gain {
+        fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+            for (frame, sample) in outputs[0].iter_mut().enumerate() {
+                if let Some(event) = self.pending {
+                    if event.time_in_frames as usize == frame {
+                        self.gain = event.event;
+                        self.pending = None;
+                    }
+                }
+                *sample = inputs[0][frame] * self.gain;
+            }
+        }
+    }
+
+    impl EventHandler<Timed<f32>> for Gain {
+        fn handle_event(&mut self, event: Timed<f32>) {
+            self.pending = Some(event);
+        }
+    }
+
+    impl AudioHandler for Gain {
+        fn set_sample_rate(&mut self, _sample_rate: f64) {}
+    }
+
+    #[test]
+    fn finds_the_first_divergence() {
+        let input = vec![1.0_f32; 100];
+        let mut settings = CompareSettings::new(1, input.len());
+        settings.buffer_size = 16;
+        settings.tolerance = 0.1;
+        let mut first = Gain {
+            gain: 1.0,
+            pending: None,
+        };
+        let mut second = Gain {
+            gain: 1.0,
+            pending: None,
+        };
+        // The event at frame 40, which is frame 8 of the third buffer, sets the gain to 0.5.
+        let events = vec![Timed::new(40, 0.5)];
+        let comparison = compare(
+            &mut first,
+            &mut PassThrough::new(1),
+            &settings,
+            &[&input],
+            &[],
+        );
+        assert_eq!(comparison.first_divergence, None);
+        let comparison = compare(&mut first, &mut second, &settings, &[&input], &events);
+        // Both renderers received the event, so the outputs are the same.
+        assert_eq!(comparison.max_difference, 0.0);
+        // With a gain of 0.5 and of 2.0, the outputs differ from frame 0 on.
+        second.gain = 2.0;
+        let comparison = compare(&mut first, &mut second, &settings, &[&input], &[]);
+        assert_eq!(comparison.max_difference, 1.5);
+        assert_eq!(
+            comparison.first_divergence,
+            Some(Divergence {
+                channel: 0,
+                frame: 0,
+                first: 0.5,
+                second: 2.0
+            })
+        );
+        assert!((comparison.rms_error - 1.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn events_are_passed_in_the_right_buffer() {
+        let input = vec![1.0_f32; 64];
+        let mut settings = CompareSettings::new(1, input.len());
+        settings.buffer_size = 16;
+        let mut gain = Gain {
+            gain: 1.0,
+            pending: None,
+        };
+        let comparison = compare(
+            &mut gain,
+            &mut PassThrough::new(1),
+            &settings,
+            &[&input],
+            &[Timed::new(40, 0.5)],
+        );
+        assert_eq!(
+            comparison.first_divergence,
+            Some(Divergence {
+                channel: 0,
+                frame: 40,
+                first: 0.5,
+                second: 1.0
+            })
+        );
+        assert_eq!(comparison.max_difference, 0.5);
+    }
+}
@@ -1,12 +1,39 @@
 //! Utilities for testing.
 
+pub mod allocation;
+pub mod compare;
+#[cfg(all(feature = "backend-combined-hound", feature = "backend-combined-rimd"))]
+pub mod render_midi;
+
 use crate::buffer::AudioChunk;
 use crate::event::{ContextualEventHandler, EventHandler};
-use crate::{AudioHandler, AudioHandlerMeta, ContextualAudioRenderer};
+use crate::{AudioHandler, AudioHandlerMeta, AudioRenderer, ContextualAudioRenderer};
 use std::fmt::Debug;
 
 pub struct DummyEventHandler;
 
+/// A renderer that outputs the number of events that it has received so far.
+#[derive(Default)]
+pub struct EventCounter {
+    pub number_of_events: usize,
+}
+
+impl<E> EventHandler<E> for EventCounter {
+    fn handle_event(&mut self, _event: E) {
+        self.number_of_events += 1;
+    }
+}
+
+impl AudioRenderer<f32> for EventCounter {
+    fn render_buffer(&mut self, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+        for output in outputs.iter_mut() {
+            for sample in output.iter_mut() {
+                *sample = self.number_of_events as f32;
+            }
+        }
+    }
+}
+
 impl<E> EventHandler<E> for DummyEventHandler {
     fn handle_event(&mut self, _event: E) {}
 }
@@ -73,6 +100,10 @@ where
     fn set_sample_rate(&mut self, sample_rate: f64) {
         self.meta.set_sample_rate(sample_rate);
     }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.meta.set_max_buffer_size(max_buffer_size);
+    }
 }
 
 impl<S, E, M, C> ContextualAudioRenderer<S, C> for TestPlugin<S, E, M>
@@ -0,0 +1,632 @@
+//! Render a `.mid` file through a plugin and compare the result with golden files.
+//!
+//! [`render_midi`] renders the events of a `.mid` file (and an optional `.wav` file as audio
+//! input) through a plugin with the [combined backend], and compares the rendered audio with
+//! a golden `.wav` file and the midi output of the plugin with an optional golden `.mid` file.
+//! The [`render_midi_test!`] macro turns this into a test of a few lines.
+//!
+//! Without an audio input, the plugin receives silence for the duration of the `.mid` file.
+//! In both cases, `tail_in_seconds` of silence is added, e.g. for the release of the notes.
+//!
+//! Creating and updating the golden files
+//! --------------------------------------
+//! When the environment variable `RSYNTH_BLESS` is set, nothing is compared: the golden files
+//! are (over)written with the rendered audio and midi instead. Listen to the `.wav` file
+//! before committing it.
+//!
+//! Example
+//! -------
+//! In an integration test, e.g. `tests/render.rs`:
+//! ```no_run
+//! # #[macro_use]
+//! # extern crate rsynth;
+//! use rsynth::utilities::trivial_renderers::PassThrough;
+//!
+//! render_midi_test!(
+//!     fn passes_the_input_through,
+//!     PassThrough::new(2),
+//!     midi: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/chord.mid"),
+//!     expected: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/chord.wav"),
+//!     input: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/noise.wav"),
+//!     tolerance: 1e-6,
+//! );
+//! # fn main() {}
+//! ```
+//!
+//! [`render_midi`]: ./fn.render_midi.html
+//! [combined backend]: ../../backend/combined/index.html
+//! [`render_midi_test!`]: ../../macro.render_midi_test.html
+use super::compare::{compare_channels, Comparison};
+use crate::backend::combined::events::{DeltaEventSource, EventSinkContext, MidiWriterSink};
+use crate::backend::combined::hound::HoundAudioReader;
+use crate::backend::combined::memory::{AudioBufferReader, AudioBufferWriter};
+use crate::backend::combined::rimd::{RimdMidiReader, RimdMidiWriter};
+use crate::backend::combined::{run_with_events, AudioReader, MidiWriter, MICROSECONDS_PER_SECOND};
+use crate::buffer::{buffers_as_mut_slice, buffers_as_slice, AudioChunk};
+use crate::event::{DeltaEvent, EventHandler, RawMidiEvent, Timed};
+use crate::{AudioHandler, ContextualAudioRenderer};
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use rimd::{SMFError, SMFWriter, SMF};
+use std::cmp::{max, min};
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// When this environment variable is set, the golden files are written instead of compared.
+pub const BLESS_VARIABLE: &str = "RSYNTH_BLESS";
+
+// The resolution of the golden `.mid` files: 960 ticks per beat at 120 beats per minute,
+// so that a tick is about half a millisecond.
+const TEMPO_IN_MICROSECONDS_PER_BEAT: u32 = 500_000;
+const TICKS_PER_BEAT: u16 = 960;
+
+/// The settings for [`render_midi`].
+///
+/// [`render_midi`]: ./fn.render_midi.html
+#[derive(Clone, Debug)]
+pub struct RenderMidiSettings {
+    /// The `.mid` file with the midi input.
+    pub midi_file: PathBuf,
+    /// The index of the track of the `.mid` file that is used.
+    pub track: usize,
+    /// The `.wav` file with the audio input, or `None` to render silence as audio input.
+    pub input_file: Option<PathBuf>,
+    /// The golden `.wav` file with the expected audio output.
+    pub expected_output_file: PathBuf,
+    /// The golden `.mid` file with the expected midi output, or `None` to ignore
+    /// the midi output.
+    pub expected_midi_file: Option<PathBuf>,
+    /// The number of channels when there is no audio input.
+    /// With an audio input, the plugin gets as many channels as the audio input.
+    pub number_of_channels: usize,
+    /// The sample rate when there is no audio input.
+    /// With an audio input, the sample rate of the audio input is used.
+    pub sample_rate: u32,
+    /// The duration of the silence that is added after the audio input or the midi input.
+    pub tail_in_seconds: f64,
+    /// The number of frames of every buffer.
+    pub buffer_size: usize,
+    /// Samples that differ by at most this amount from the golden file are considered equal.
+    pub tolerance: f64,
+    /// Midi events that are at most this number of microseconds earlier or later than in
+    /// the golden file are considered on time.
+    pub midi_tolerance_in_microseconds: u64,
+}
+
+impl RenderMidiSettings {
+    /// Create settings for two channels at 44100 Hz, without audio input and golden midi file,
+    /// with a tail of one second, a buffer size of 64 frames, a tolerance of `0` and a midi
+    /// tolerance of one millisecond.
+    pub fn new<M, E>(midi_file: M, expected_output_file: E) -> Self
+    where
+        M: Into<PathBuf>,
+        E: Into<PathBuf>,
+    {
+        RenderMidiSettings {
+            midi_file: midi_file.into(),
+            track: 0,
+            input_file: None,
+            expected_output_file: expected_output_file.into(),
+            expected_midi_file: None,
+            number_of_channels: 2,
+            sample_rate: 44100,
+            tail_in_seconds: 1.0,
+            buffer_size: 64,
+            tolerance: 0.0,
+            midi_tolerance_in_microseconds: 1000,
+        }
+    }
+}
+
+/// The error type that represents the errors you can get from [`render_midi`].
+///
+/// [`render_midi`]: ./fn.render_midi.html
+#[derive(Debug)]
+pub enum RenderMidiError {
+    /// A `.mid` file could not be read.
+    Midi(SMFError),
+    /// The track does not exist in the `.mid` file.
+    TrackOutOfBounds { number_of_tracks: usize },
+    /// A `.wav` file could not be read or written.
+    Wav(hound::Error),
+    /// The format of a `.wav` file is not supported.
+    UnsupportedAudioFormat,
+    /// The audio input has no channels.
+    NoAudioChannels,
+    /// A golden `.mid` file could not be written.
+    Io(io::Error),
+}
+
+/// The first midi event that differs from the golden `.mid` file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MidiMismatch {
+    /// The index of the event.
+    pub index: usize,
+    /// The time in microseconds and the event that is rendered, or `None` if fewer events
+    /// are rendered.
+    pub rendered: Option<(u64, RawMidiEvent)>,
+    /// The time in microseconds and the event of the golden file, or `None` if more events
+    /// are rendered.
+    pub expected: Option<(u64, RawMidiEvent)>,
+}
+
+/// The result of [`render_midi`].
+///
+/// [`render_midi`]: ./fn.render_midi.html
+#[derive(Clone, Debug)]
+pub struct RenderMidiOutcome {
+    /// The comparison of the rendered audio with the golden `.wav` file.
+    pub comparison: Comparison,
+    /// The number of rendered channels.
+    pub rendered_channels: usize,
+    /// The number of channels of the golden `.wav` file.
+    pub expected_channels: usize,
+    /// The number of rendered frames.
+    pub rendered_frames: usize,
+    /// The number of frames of the golden `.wav` file.
+    pub expected_frames: usize,
+    /// The midi output of the plugin, with the time in microseconds since the start.
+    pub midi_output: Vec<(u64, RawMidiEvent)>,
+    /// The first midi event that differs from the golden `.mid` file, if any.
+    pub midi_mismatch: Option<MidiMismatch>,
+    /// Whether the golden files have been written instead of compared.
+    pub blessed: bool,
+}
+
+impl RenderMidiOutcome {
+    /// Whether the rendered audio and midi match the golden files.
+    pub fn passed(&self) -> bool {
+        self.comparison.first_divergence.is_none()
+            && self.rendered_channels == self.expected_channels
+            && self.rendered_frames == self.expected_frames
+            && self.midi_mismatch.is_none()
+    }
+}
+
+impl Display for RenderMidiOutcome {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.blessed {
+            return write!(f, "the golden files have been written");
+        }
+        if self.rendered_channels != self.expected_channels {
+            write!(
+                f,
+                "{} channels rendered, {} expected; ",
+                self.rendered_channels, self.expected_channels
+            )?;
+        }
+        if self.rendered_frames != self.expected_frames {
+            write!(
+                f,
+                "{} frames rendered, {} expected; ",
+                self.rendered_frames, self.expected_frames
+            )?;
+        }
+        write!(f, "{}", self.comparison)?;
+        if let Some(mismatch) = self.midi_mismatch {
+            write!(f, "; midi event {} differs: ", mismatch.index)?;
+            match mismatch.rendered {
+                Some((time, event)) => write!(f, "{:?} at {} µs", event, time)?,
+                None => write!(f, "nothing")?,
+            }
+            write!(f, " rendered, ")?;
+            match mismatch.expected {
+                Some((time, event)) => write!(f, "{:?} at {} µs", event, time)?,
+                None => write!(f, "nothing")?,
+            }
+            write!(f, " expected")?;
+        }
+        Ok(())
+    }
+}
+
+/// Render the `.mid` file of the `settings` through the `plugin` and compare the result with
+/// the golden files.
+///
+/// When the environment variable `RSYNTH_BLESS` is set, the golden files are written instead
+/// and the returned outcome always passes.
+///
+/// See the [module level documentation] for more information.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------------
+/// This function allocates memory and reads and writes files.
+///
+/// [module level documentation]: ./index.html
+pub fn render_midi<R>(
+    plugin: &mut R,
+    settings: &RenderMidiSettings,
+) -> Result<RenderMidiOutcome, RenderMidiError>
+where
+    R: for<'a> ContextualAudioRenderer<
+            f32,
+            EventSinkContext<MidiWriterSink<&'a mut Vec<DeltaEvent<RawMidiEvent>>>>,
+        > + EventHandler<Timed<RawMidiEvent>>
+        + AudioHandler,
+{
+    let smf = SMF::from_file(&settings.midi_file).map_err(RenderMidiError::Midi)?;
+    if settings.track >= smf.tracks.len() {
+        return Err(RenderMidiError::TrackOutOfBounds {
+            number_of_tracks: smf.tracks.len(),
+        });
+    }
+    let midi_input: Vec<_> = RimdMidiReader::new(&smf, settings.track).collect();
+    let midi_duration: u64 = midi_input
+        .iter()
+        .map(|event| event.microseconds_since_previous_event)
+        .sum();
+
+    let (mut input, sample_rate) = match &settings.input_file {
+        Some(path) => read_wav(path, settings.buffer_size)?,
+        None => {
+            let frames = frames_for_duration(midi_duration, settings.sample_rate);
+            (
+                AudioChunk::zero(settings.number_of_channels, frames),
+                settings.sample_rate,
+            )
+        }
+    };
+    if input.channels().is_empty() {
+        return Err(RenderMidiError::NoAudioChannels);
+    }
+    let tail = (settings.tail_in_seconds * sample_rate as f64).round() as usize;
+    let silence = vec![vec![0.0; tail]; input.channels().len()];
+    input.append_sliced_chunk(&buffers_as_slice(&silence, tail));
+
+    let (output, midi_output) = render(
+        plugin,
+        &input,
+        sample_rate,
+        settings.buffer_size,
+        midi_input,
+    );
+
+    if env::var_os(BLESS_VARIABLE).is_some() {
+        write_wav(&settings.expected_output_file, &output, sample_rate)?;
+        if let Some(path) = &settings.expected_midi_file {
+            write_mid(path, &midi_output)?;
+        }
+        return Ok(RenderMidiOutcome {
+            comparison: compare_channels(&output.as_slices(), &output.as_slices(), 0.0),
+            rendered_channels: output.channels().len(),
+            expected_channels: output.channels().len(),
+            rendered_frames: number_of_frames(&output),
+            expected_frames: number_of_frames(&output),
+            midi_output: absolute_times(&midi_output),
+            midi_mismatch: None,
+            blessed: true,
+        });
+    }
+
+    let (expected, _) = read_wav(&settings.expected_output_file, settings.buffer_size)?;
+    let midi_output = absolute_times(&midi_output);
+    let midi_mismatch = match &settings.expected_midi_file {
+        Some(path) => {
+            let expected_smf = SMF::from_file(path).map_err(RenderMidiError::Midi)?;
+            let expected_midi: Vec<_> = RimdMidiReader::new(&expected_smf, 0).collect();
+            compare_midi(
+                &midi_output,
+                &absolute_times(&expected_midi),
+                settings.midi_tolerance_in_microseconds,
+            )
+        }
+        None => None,
+    };
+    Ok(RenderMidiOutcome {
+        comparison: compare_channels(
+            &output.as_slices(),
+            &expected.as_slices(),
+            settings.tolerance,
+        ),
+        rendered_channels: output.channels().len(),
+        expected_channels: expected.channels().len(),
+        rendered_frames: number_of_frames(&output),
+        expected_frames: number_of_frames(&expected),
+        midi_output,
+        midi_mismatch,
+        blessed: false,
+    })
+}
+
+/// Define a test that renders a `.mid` file through a plugin and compares the result with
+/// golden files, see the [`render_midi`] module.
+///
+/// The first arguments are the name of the test, an expression that creates the plugin,
+/// the `.mid` file (`midi: path`) and the golden `.wav` file (`expected: path`).
+/// They can be followed by the following optional settings:
+/// * `input: path`: the `.wav` file with the audio input,
+/// * `expected_midi: path`: the golden `.mid` file with the expected midi output,
+/// * any other field of [`RenderMidiSettings`], e.g. `tail_in_seconds: 0.5`.
+///
+/// Relative paths are relative to the working directory of the test, which is the root
+/// of the crate when the test is run with `cargo test`.
+///
+/// [`render_midi`]: ./test_utilities/render_midi/index.html
+/// [`RenderMidiSettings`]: ./test_utilities/render_midi/struct.RenderMidiSettings.html
+#[macro_export]
+macro_rules! render_midi_test {
+    (
+        fn $name:ident,
+        $plugin:expr,
+        midi: $midi_file:expr,
+        expected: $expected_output_file:expr
+        $(, $setting:ident : $value:expr)*
+        $(,)*
+    ) => {
+        #[test]
+        fn $name() {
+            #[allow(unused_mut)]
+            let mut settings = $crate::test_utilities::render_midi::RenderMidiSettings::new(
+                $midi_file,
+                $expected_output_file,
+            );
+            $(
+                $crate::render_midi_test!(@set settings, $setting, $value);
+            )*
+            let mut plugin = $plugin;
+            match $crate::test_utilities::render_midi::render_midi(&mut plugin, &settings) {
+                Ok(outcome) => assert!(
+                    outcome.passed(),
+                    "The rendering of `{}` does not match the golden files: {}",
+                    settings.midi_file.display(),
+                    outcome
+                ),
+                Err(error) => panic!(
+                    "Rendering `{}` failed: {:?}",
+                    settings.midi_file.display(),
+                    error
+                ),
+            }
+        }
+    };
+    (@set $settings:ident, input, $value:expr) => {
+        $settings.input_file = Some(::std::path::PathBuf::from($value));
+    };
+    (@set $settings:ident, expected_midi, $value:expr) => {
+        $settings.expected_midi_file = Some(::std::path::PathBuf::from($value));
+    };
+    (@set $settings:ident, $setting:ident, $value:expr) => {
+        $settings.$setting = $value;
+    };
+}
+
+// Render the `input` and the `midi_input` with the combined backend.
+fn render<R>(
+    plugin: &mut R,
+    input: &AudioChunk<f32>,
+    sample_rate: u32,
+    buffer_size: usize,
+    midi_input: Vec<DeltaEvent<RawMidiEvent>>,
+) -> (AudioChunk<f32>, Vec<DeltaEvent<RawMidiEvent>>)
+where
+    R: for<'a> ContextualAudioRenderer<
+            f32,
+            EventSinkContext<MidiWriterSink<&'a mut Vec<DeltaEvent<RawMidiEvent>>>>,
+        > + EventHandler<Timed<RawMidiEvent>>
+        + AudioHandler,
+{
+    let mut output = AudioChunk::new(input.channels().len());
+    let mut midi_output = Vec::new();
+    let result = run_with_events(
+        plugin,
+        buffer_size,
+        AudioBufferReader::new(input, sample_rate as u64),
+        AudioBufferWriter::new(&mut output),
+        DeltaEventSource::new(midi_input.into_iter()),
+        MidiWriterSink::new(&mut midi_output),
+    );
+    match result {
+        Ok(()) => {}
+        Err(_) => unreachable!("Reading from and writing to memory cannot fail."),
+    }
+    (output, midi_output)
+}
+
+// Find the first event that differs in content or that is not on time.
+fn compare_midi(
+    rendered: &[(u64, RawMidiEvent)],
+    expected: &[(u64, RawMidiEvent)],
+    tolerance_in_microseconds: u64,
+) -> Option<MidiMismatch> {
+    for index in 0..max(rendered.len(), expected.len()) {
+        let rendered_event = rendered.get(index).copied();
+        let expected_event = expected.get(index).copied();
+        let matches = match (rendered_event, expected_event) {
+            (Some((rendered_time, rendered_event)), Some((expected_time, expected_event))) => {
+                rendered_event == expected_event
+                    && max(rendered_time, expected_time) - min(rendered_time, expected_time)
+                        <= tolerance_in_microseconds
+            }
+            _ => false,
+        };
+        if !matches {
+            return Some(MidiMismatch {
+                index,
+                rendered: rendered_event,
+                expected: expected_event,
+            });
+        }
+    }
+    None
+}
+
+fn absolute_times(events: &[DeltaEvent<RawMidiEvent>]) -> Vec<(u64, RawMidiEvent)> {
+    let mut time = 0;
+    events
+        .iter()
+        .map(|event| {
+            time += event.microseconds_since_previous_event;
+            (time, event.event)
+        })
+        .collect()
+}
+
+fn frames_for_duration(duration_in_microseconds: u64, sample_rate: u32) -> usize {
+    let frames =
+        duration_in_microseconds as f64 * sample_rate as f64 / MICROSECONDS_PER_SECOND as f64;
+    frames.ceil() as usize
+}
+
+fn number_of_frames(chunk: &AudioChunk<f32>) -> usize {
+    chunk
+        .channels()
+        .first()
+        .map(|channel| channel.len())
+        .unwrap_or(0)
+}
+
+fn read_wav(path: &Path, buffer_size: usize) -> Result<(AudioChunk<f32>, u32), RenderMidiError> {
+    let mut wav_reader = WavReader::open(path).map_err(RenderMidiError::Wav)?;
+    let mut reader: HoundAudioReader<f32> = HoundAudioReader::new(&mut wav_reader)
+        .map_err(|_| RenderMidiError::UnsupportedAudioFormat)?;
+    let number_of_channels = reader.number_of_channels();
+    let sample_rate = reader.frames_per_second() as u32;
+    let mut chunk = AudioChunk::new(number_of_channels);
+    let mut buffers = vec![vec![0.0; buffer_size]; number_of_channels];
+    loop {
+        let frames = reader
+            .fill_buffer(&mut buffers_as_mut_slice(&mut buffers, buffer_size))
+            .map_err(RenderMidiError::Wav)?;
+        if frames == 0 {
+            break;
+        }
+        chunk.append_sliced_chunk(&buffers_as_slice(&buffers, frames));
+    }
+    Ok((chunk, sample_rate))
+}
+
+fn write_wav(
+    path: &Path,
+    chunk: &AudioChunk<f32>,
+    sample_rate: u32,
+) -> Result<(), RenderMidiError> {
+    let spec = WavSpec {
+        channels: chunk.channels().len() as u16,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut writer = WavWriter::create(path, spec).map_err(RenderMidiError::Wav)?;
+    for frame in 0..number_of_frames(chunk) {
+        for channel in chunk.channels() {
+            writer
+                .write_sample(channel[frame])
+                .map_err(RenderMidiError::Wav)?;
+        }
+    }
+    writer.finalize().map_err(RenderMidiError::Wav)
+}
+
+fn write_mid(path: &Path, events: &[DeltaEvent<RawMidiEvent>]) -> Result<(), RenderMidiError> {
+    let mut writer = RimdMidiWriter::new(TEMPO_IN_MICROSECONDS_PER_BEAT, TICKS_PER_BEAT);
+    for event in events {
+        writer.write_event(*event);
+    }
+    SMFWriter::from_smf(writer.get_smf())
+        .write_to_file(path)
+        .map_err(RenderMidiError::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::trivial_renderers::PassThrough;
+
+    fn note_on(note: u8) -> RawMidiEvent {
+        RawMidiEvent::new(&[0x90, note, 100])
+    }
+
+    #[test]
+    fn render_passes_the_input_through() {
+        let input = AudioChunk::from_channels(vec![(0..200).map(|i| i as f32).collect()]);
+        let mut plugin = PassThrough::new(1);
+        let (output, midi_output) = render(&mut plugin, &input, 44100, 64, Vec::new());
+        assert_eq!(output.channels(), input.channels());
+        assert!(midi_output.is_empty());
+    }
+
+    // Records the time of every event, in frames since the start of rendering.
+    #[derive(Default)]
+    struct EventRecorder {
+        frames_rendered: u64,
+        event_times: Vec<u64>,
+    }
+
+    impl EventHandler<Timed<RawMidiEvent>> for EventRecorder {
+        fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+            self.event_times
+                .push(self.frames_rendered + event.time_in_frames as u64);
+        }
+    }
+
+    impl<C> ContextualAudioRenderer<f32, C> for EventRecorder {
+        fn render_buffer(&mut self, _inputs: &[&[f32]], outputs: &mut [&mut [f32]], _: &mut C) {
+            self.frames_rendered += outputs[0].len() as u64;
+        }
+    }
+
+    impl AudioHandler for EventRecorder {
+        fn set_sample_rate(&mut self, _sample_rate: f64) {}
+    }
+
+    #[test]
+    fn render_passes_the_notes_of_a_chord_at_the_same_frame() {
+        let input = AudioChunk::<f32>::zero(1, 1000);
+        let chord = [(10_000, 60), (0, 64), (0, 67)]
+            .iter()
+            .map(|(delta, note)| DeltaEvent {
+                microseconds_since_previous_event: *delta,
+                event: note_on(*note),
+            })
+            .collect();
+        let mut plugin = EventRecorder::default();
+        render(&mut plugin, &input, 10_000, 64, chord);
+        // 10 ms at 10 kHz is 100 frames.
+        assert_eq!(plugin.event_times, vec![100, 100, 100]);
+    }
+
+    #[test]
+    fn compare_midi_finds_the_first_mismatch() {
+        let expected = vec![(0, note_on(60)), (500_000, note_on(64))];
+        assert_eq!(compare_midi(&expected, &expected, 0), None);
+
+        let late = vec![(0, note_on(60)), (501_000, note_on(64))];
+        assert_eq!(compare_midi(&late, &expected, 1000), None);
+        assert_eq!(
+            compare_midi(&late, &expected, 999).map(|mismatch| mismatch.index),
+            Some(1)
+        );
+
+        let other_note = vec![(0, note_on(61)), (500_000, note_on(64))];
+        assert_eq!(
+            compare_midi(&other_note, &expected, 1000).map(|mismatch| mismatch.index),
+            Some(0)
+        );
+
+        let missing = compare_midi(&expected[..1], &expected, 1000).unwrap();
+        assert_eq!(missing.index, 1);
+        assert_eq!(missing.rendered, None);
+        assert_eq!(missing.expected, Some(expected[1]));
+    }
+
+    #[test]
+    fn absolute_times_accumulate_the_deltas() {
+        let events = vec![
+            DeltaEvent {
+                microseconds_since_previous_event: 10,
+                event: note_on(60),
+            },
+            DeltaEvent {
+                microseconds_since_previous_event: 20,
+                event: note_on(64),
+            },
+        ];
+        assert_eq!(
+            absolute_times(&events),
+            vec![(10, note_on(60)), (30, note_on(64))]
+        );
+        assert_eq!(frames_for_duration(1_000_000, 44100), 44100);
+        assert_eq!(frames_for_duration(1, 44100), 1);
+    }
+}
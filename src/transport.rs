@@ -0,0 +1,472 @@
+//! Musical time: the state of the transport, time signatures and tempo maps.
+//!
+//! Positions in musical time are expressed in beats, where one beat is a quarter note,
+//! as in most hosts. [`MusicalPosition`] converts a position in bars and beats to beats.
+//!
+//! The conversion between beats and seconds is done with the [`TempoMapping`] trait, which is
+//! implemented by [`ConstantTempo`] and [`TempoMap`].
+//!
+//! Tempo-synced DSP (delays, LFOs, arpeggiators, step sequencers, ...) expresses its timing as a
+//! [`MusicalDuration`], such as a dotted eighth note:
+//! ```
+//! use rsynth::transport::{MusicalDuration, TransportState};
+//!
+//! let transport = TransportState::default(); // 120 beats per minute
+//! let delay: MusicalDuration = "1/8D".parse().unwrap();
+//! assert_eq!(delay.in_frames(transport.tempo, 44100.0), 16537.5);
+//! ```
+//!
+//! [`MusicalPosition`]: ./struct.MusicalPosition.html
+//! [`TempoMapping`]: ./trait.TempoMapping.html
+//! [`ConstantTempo`]: ./struct.ConstantTempo.html
+//! [`TempoMap`]: ./struct.TempoMap.html
+//! [`MusicalDuration`]: ./struct.MusicalDuration.html
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// A time signature, e.g. 3/4 or 6/8.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimeSignature {
+    /// The number of beats per bar, e.g. 3 in 3/4.
+    pub numerator: u32,
+    /// The note value of one beat, e.g. 4 in 3/4.
+    pub denominator: u32,
+}
+
+impl TimeSignature {
+    pub fn new(numerator: u32, denominator: u32) -> Self {
+        TimeSignature {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// The length of one beat of the time signature, in quarter notes.
+    pub fn beat_length(&self) -> f64 {
+        4.0 / self.denominator as f64
+    }
+
+    /// The length of one bar, in quarter notes.
+    pub fn bar_length(&self) -> f64 {
+        self.numerator as f64 * self.beat_length()
+    }
+}
+
+impl Default for TimeSignature {
+    /// 4/4
+    fn default() -> Self {
+        TimeSignature::new(4, 4)
+    }
+}
+
+/// A position in bars and beats, as displayed by most sequencers.
+///
+/// Both the bar and the beat start counting at 1, so bar 1, beat 1 is the start of the song.
+/// The beat can have a fractional part: bar 3, beat 2.5 is halfway the second beat of the
+/// third bar. The beat is expressed in the unit of the time signature.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MusicalPosition {
+    pub bar: u32,
+    pub beat: f64,
+}
+
+impl MusicalPosition {
+    pub fn new(bar: u32, beat: f64) -> Self {
+        MusicalPosition { bar, beat }
+    }
+
+    /// The position in quarter notes since the start of the song.
+    /// The time signature is assumed not to change.
+    pub fn in_beats(&self, time_signature: TimeSignature) -> f64 {
+        self.bar.saturating_sub(1) as f64 * time_signature.bar_length()
+            + (self.beat - 1.0).max(0.0) * time_signature.beat_length()
+    }
+}
+
+/// A range that is played repeatedly, in beats.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LoopRange {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl LoopRange {
+    pub fn new(start: f64, end: f64) -> Self {
+        LoopRange { start, end }
+    }
+}
+
+/// The state of the transport at the start of an audio buffer.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TransportState {
+    /// `true` when the transport is playing, `false` when it is stopped.
+    pub is_playing: bool,
+    /// The position in beats (quarter notes) at the start of the buffer.
+    pub position_in_beats: f64,
+    /// The tempo in beats per minute.
+    pub tempo: f64,
+    pub time_signature: TimeSignature,
+    /// The range that is played repeatedly, if looping is enabled.
+    pub loop_range: Option<LoopRange>,
+}
+
+impl Default for TransportState {
+    /// A stopped transport at the start of the song, 120 beats per minute in 4/4, not looping.
+    fn default() -> Self {
+        TransportState {
+            is_playing: false,
+            position_in_beats: 0.0,
+            tempo: 120.0,
+            time_signature: TimeSignature::default(),
+            loop_range: None,
+        }
+    }
+}
+
+/// Implemented by the context that a back-end passes to the plugin when the back-end knows
+/// the state of the transport of the host.
+pub trait TransportContext {
+    /// The state of the transport at the start of the current buffer, or `None` if the host
+    /// did not provide it.
+    fn transport(&self) -> Option<TransportState>;
+}
+
+/// Conversion between beats and seconds.
+pub trait TempoMapping {
+    /// The time in seconds at which the given beat is played.
+    fn seconds_at_beat(&self, beat: f64) -> f64;
+
+    /// The beat that is played at the given time in seconds.
+    fn beat_at_seconds(&self, seconds: f64) -> f64;
+}
+
+/// A tempo that does not change.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ConstantTempo {
+    /// The tempo in beats per minute.
+    pub beats_per_minute: f64,
+}
+
+impl ConstantTempo {
+    pub fn new(beats_per_minute: f64) -> Self {
+        ConstantTempo { beats_per_minute }
+    }
+}
+
+impl TempoMapping for ConstantTempo {
+    fn seconds_at_beat(&self, beat: f64) -> f64 {
+        beat * 60.0 / self.beats_per_minute
+    }
+
+    fn beat_at_seconds(&self, seconds: f64) -> f64 {
+        seconds * self.beats_per_minute / 60.0
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TempoChange {
+    beat: f64,
+    beats_per_minute: f64,
+    // The time at which the tempo changes.
+    seconds: f64,
+}
+
+/// A tempo that changes abruptly at given beats.
+#[derive(Clone, Debug)]
+pub struct TempoMap {
+    // Sorted by beat; the first change is at beat 0.
+    changes: Vec<TempoChange>,
+}
+
+impl TempoMap {
+    /// Create a new tempo map with the given initial tempo in beats per minute.
+    pub fn new(beats_per_minute: f64) -> Self {
+        TempoMap {
+            changes: vec![TempoChange {
+                beat: 0.0,
+                beats_per_minute,
+                seconds: 0.0,
+            }],
+        }
+    }
+
+    /// Change the tempo from the given beat on.
+    /// A tempo change at the same beat as an existing change replaces the existing change.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method may allocate memory.
+    pub fn set_tempo(&mut self, beat: f64, beats_per_minute: f64) {
+        let beat = beat.max(0.0);
+        let index = self.changes.partition_point(|change| change.beat < beat);
+        let change = TempoChange {
+            beat,
+            beats_per_minute,
+            seconds: 0.0,
+        };
+        match self.changes.get_mut(index) {
+            Some(existing) if existing.beat == beat => *existing = change,
+            _ => self.changes.insert(index, change),
+        }
+        for index in 1..self.changes.len() {
+            let previous = self.changes[index - 1];
+            self.changes[index].seconds = previous.seconds
+                + (self.changes[index].beat - previous.beat) * 60.0 / previous.beats_per_minute;
+        }
+    }
+
+    /// The tempo in beats per minute at the given beat.
+    pub fn tempo_at_beat(&self, beat: f64) -> f64 {
+        self.change_at_beat(beat).beats_per_minute
+    }
+
+    fn change_at_beat(&self, beat: f64) -> &TempoChange {
+        let index = self.changes.partition_point(|change| change.beat <= beat);
+        &self.changes[index.saturating_sub(1)]
+    }
+}
+
+impl TempoMapping for TempoMap {
+    fn seconds_at_beat(&self, beat: f64) -> f64 {
+        let change = self.change_at_beat(beat);
+        change.seconds + (beat - change.beat) * 60.0 / change.beats_per_minute
+    }
+
+    fn beat_at_seconds(&self, seconds: f64) -> f64 {
+        let index = self
+            .changes
+            .partition_point(|change| change.seconds <= seconds);
+        let change = &self.changes[index.saturating_sub(1)];
+        change.beat + (seconds - change.seconds) * change.beats_per_minute / 60.0
+    }
+}
+
+/// Modifies the length of a note value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NoteModifier {
+    /// The plain note value.
+    Straight,
+    /// One and a half times the note value.
+    Dotted,
+    /// Two thirds of the note value: three triplets take as long as two straight notes.
+    Triplet,
+}
+
+impl NoteModifier {
+    fn factor(self) -> f64 {
+        match self {
+            NoteModifier::Straight => 1.0,
+            NoteModifier::Dotted => 1.5,
+            NoteModifier::Triplet => 2.0 / 3.0,
+        }
+    }
+}
+
+/// A duration expressed as a note value, e.g. a quarter note, a dotted eighth note or
+/// a sixteenth note triplet.
+///
+/// The note value is a fraction of a whole note: `MusicalDuration::new(1, 4)` is a quarter
+/// note, which is one beat, and `MusicalDuration::new(2, 1)` lasts two whole notes.
+///
+/// A `MusicalDuration` can be parsed from text like `"1/4"`, `"1/8D"` (dotted) or `"1/16T"`
+/// (triplet), and is displayed in the same way, which is convenient for the value names of a
+/// "sync" parameter.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MusicalDuration {
+    pub numerator: u32,
+    pub denominator: u32,
+    pub modifier: NoteModifier,
+}
+
+impl MusicalDuration {
+    /// Create a straight note value of `numerator / denominator` whole notes.
+    pub fn new(numerator: u32, denominator: u32) -> Self {
+        MusicalDuration {
+            numerator,
+            denominator,
+            modifier: NoteModifier::Straight,
+        }
+    }
+
+    /// The dotted version of this note value.
+    pub fn dotted(self) -> Self {
+        MusicalDuration {
+            modifier: NoteModifier::Dotted,
+            ..self
+        }
+    }
+
+    /// The triplet version of this note value.
+    pub fn triplet(self) -> Self {
+        MusicalDuration {
+            modifier: NoteModifier::Triplet,
+            ..self
+        }
+    }
+
+    /// The duration in beats (quarter notes).
+    pub fn in_beats(&self) -> f64 {
+        4.0 * self.numerator as f64 / self.denominator as f64 * self.modifier.factor()
+    }
+
+    /// The duration in seconds at the given tempo in beats per minute.
+    pub fn in_seconds(&self, beats_per_minute: f64) -> f64 {
+        self.in_beats() * 60.0 / beats_per_minute
+    }
+
+    /// The duration in frames at the given tempo in beats per minute and sample rate,
+    /// e.g. for the length of a delay line.
+    pub fn in_frames(&self, beats_per_minute: f64, sample_rate: f64) -> f64 {
+        self.in_seconds(beats_per_minute) * sample_rate
+    }
+
+    /// The duration in seconds when it starts at the given beat and the tempo changes
+    /// according to the `tempo_mapping`.
+    pub fn in_seconds_at_beat<T>(&self, tempo_mapping: &T, beat: f64) -> f64
+    where
+        T: TempoMapping,
+    {
+        tempo_mapping.seconds_at_beat(beat + self.in_beats()) - tempo_mapping.seconds_at_beat(beat)
+    }
+
+    /// The frequency in Hz of something that repeats with this duration at the given tempo,
+    /// e.g. the rate of an LFO.
+    pub fn frequency(&self, beats_per_minute: f64) -> f64 {
+        1.0 / self.in_seconds(beats_per_minute)
+    }
+
+    /// How many times this duration fits between the start of the song and the given beat.
+    ///
+    /// The integer part is e.g. the index of the current step of a step sequencer and the
+    /// fractional part is e.g. the phase of an LFO that is synced to the transport.
+    pub fn position_at_beat(&self, beat: f64) -> f64 {
+        beat / self.in_beats()
+    }
+}
+
+impl Display for MusicalDuration {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)?;
+        match self.modifier {
+            NoteModifier::Straight => Ok(()),
+            NoteModifier::Dotted => write!(f, "D"),
+            NoteModifier::Triplet => write!(f, "T"),
+        }
+    }
+}
+
+/// The error type that is returned when a [`MusicalDuration`] cannot be parsed.
+///
+/// [`MusicalDuration`]: ./struct.MusicalDuration.html
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ParseMusicalDurationError;
+
+impl FromStr for MusicalDuration {
+    type Err = ParseMusicalDurationError;
+
+    /// Parse `numerator/denominator`, optionally followed by `D` or `.` (dotted) or
+    /// `T` (triplet), case insensitive.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let (fraction, modifier) = match text.chars().last() {
+            Some('d') | Some('D') | Some('.') => (&text[..text.len() - 1], NoteModifier::Dotted),
+            Some('t') | Some('T') => (&text[..text.len() - 1], NoteModifier::Triplet),
+            _ => (text, NoteModifier::Straight),
+        };
+        let mut parts = fraction.splitn(2, '/');
+        let numerator = parts.next().unwrap_or("");
+        let denominator = parts.next().ok_or(ParseMusicalDurationError)?;
+        let numerator: u32 = numerator
+            .trim()
+            .parse()
+            .map_err(|_| ParseMusicalDurationError)?;
+        let denominator: u32 = denominator
+            .trim()
+            .parse()
+            .map_err(|_| ParseMusicalDurationError)?;
+        if numerator == 0 || denominator == 0 {
+            return Err(ParseMusicalDurationError);
+        }
+        Ok(MusicalDuration {
+            numerator,
+            denominator,
+            modifier,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn musical_position_in_beats() {
+        let four_four = TimeSignature::default();
+        assert_eq!(MusicalPosition::new(1, 1.0).in_beats(four_four), 0.0);
+        assert_eq!(MusicalPosition::new(3, 2.5).in_beats(four_four), 9.5);
+        let six_eight = TimeSignature::new(6, 8);
+        assert_eq!(MusicalPosition::new(2, 4.0).in_beats(six_eight), 4.5);
+    }
+
+    #[test]
+    fn tempo_map_converts_between_beats_and_seconds() {
+        let mut map = TempoMap::new(120.0);
+        map.set_tempo(4.0, 60.0);
+        // Four beats at 120 BPM take two seconds, every next beat takes one second.
+        assert_eq!(map.seconds_at_beat(2.0), 1.0);
+        assert_eq!(map.seconds_at_beat(6.0), 4.0);
+        assert_eq!(map.beat_at_seconds(4.0), 6.0);
+        assert_eq!(map.beat_at_seconds(1.0), 2.0);
+        assert_eq!(map.tempo_at_beat(3.9), 120.0);
+        assert_eq!(map.tempo_at_beat(4.0), 60.0);
+
+        // Changing an earlier tempo moves the later changes in time.
+        map.set_tempo(0.0, 60.0);
+        assert_eq!(map.seconds_at_beat(6.0), 6.0);
+    }
+
+    #[test]
+    fn musical_duration_conversions() {
+        let quarter = MusicalDuration::new(1, 4);
+        assert_eq!(quarter.in_beats(), 1.0);
+        assert_eq!(quarter.in_seconds(120.0), 0.5);
+        assert_eq!(quarter.in_frames(120.0, 44100.0), 22050.0);
+        assert_eq!(quarter.frequency(120.0), 2.0);
+        assert_eq!(MusicalDuration::new(1, 8).dotted().in_beats(), 0.75);
+        assert!((MusicalDuration::new(1, 8).triplet().in_beats() * 3.0 - 1.0).abs() < 1e-12);
+        assert_eq!(MusicalDuration::new(2, 1).in_beats(), 8.0);
+
+        let sixteenth = MusicalDuration::new(1, 16);
+        assert_eq!(sixteenth.position_at_beat(2.125), 8.5);
+
+        let mut map = TempoMap::new(120.0);
+        map.set_tempo(4.0, 60.0);
+        // Half a bar at 120 BPM and half a bar at 60 BPM.
+        assert_eq!(
+            MusicalDuration::new(1, 1).in_seconds_at_beat(&map, 2.0),
+            3.0
+        );
+    }
+
+    #[test]
+    fn musical_duration_parses_and_displays() {
+        let cases = [
+            ("1/4", MusicalDuration::new(1, 4)),
+            ("1/8D", MusicalDuration::new(1, 8).dotted()),
+            ("1/8.", MusicalDuration::new(1, 8).dotted()),
+            (" 1/16t", MusicalDuration::new(1, 16).triplet()),
+            ("2/1", MusicalDuration::new(2, 1)),
+        ];
+        for (text, expected) in cases.iter() {
+            assert_eq!(text.parse::<MusicalDuration>(), Ok(*expected));
+        }
+        assert_eq!(MusicalDuration::new(1, 8).dotted().to_string(), "1/8D");
+        assert_eq!(MusicalDuration::new(1, 16).triplet().to_string(), "1/16T");
+        for text in ["", "1", "1/", "/4", "0/4", "1/0", "1/4X", "a/b"].iter() {
+            assert_eq!(
+                text.parse::<MusicalDuration>(),
+                Err(ParseMusicalDurationError)
+            );
+        }
+    }
+}
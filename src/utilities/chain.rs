@@ -0,0 +1,338 @@
+//! Connect two renderers in series, e.g. a synthesizer and an effect.
+//!
+//! A [`Chain`] renders the first renderer into intermediate buffers and passes these buffers
+//! as the inputs of the second renderer. Events are passed to both renderers, so that e.g.
+//! the synthesizer and the effect both see the same midi controllers.
+//! The [`Chain`] implements the renderer traits itself, so that chains can be nested and used
+//! with every back-end:
+//!
+//! ```
+//! use rsynth::utilities::chain::Chain;
+//! use rsynth::utilities::trivial_renderers::{PassThrough, Silence};
+//! use rsynth::AudioRenderer;
+//!
+//! // A silent "synthesizer" with two outputs, followed by a stereo "effect".
+//! let mut chain = Chain::<_, _, f32>::new(Silence::new(2), PassThrough::new(2), 2, 64);
+//! let mut left = [1.0; 4];
+//! let mut right = [1.0; 4];
+//! chain.render_buffer(&[], &mut [&mut left[..], &mut right[..]]);
+//! assert_eq!(left, [0.0; 4]);
+//! ```
+//!
+//! [`Chain`]: ./struct.Chain.html
+use crate::buffer::number_of_frames;
+use crate::event::{ContextualEventHandler, EventHandler};
+use crate::{
+    AudioHandler, AudioHandlerMeta, AudioRenderer, ContextualAudioRenderer, Latency, Lifecycle,
+    TailTime,
+};
+use num_traits::Zero;
+use vecstorage::VecStorage;
+
+/// Two renderers in series: the outputs of the first renderer are the inputs of the second.
+///
+/// See the [module level documentation] for more information.
+///
+/// Note about using in a real-time context
+/// =======================================
+/// The intermediate buffers are allocated up front for the maximum buffer size that is given
+/// to [`new`] or to [`set_max_buffer_size`]. When a longer buffer is rendered, the intermediate
+/// buffers are enlarged, which allocates memory.
+///
+/// [module level documentation]: ./index.html
+/// [`new`]: #method.new
+/// [`set_max_buffer_size`]: ../../trait.AudioHandler.html#method.set_max_buffer_size
+pub struct Chain<A, B, S>
+where
+    S: 'static,
+{
+    first: A,
+    second: B,
+    intermediate: Vec<Vec<S>>,
+    first_outputs: VecStorage<&'static [S]>,
+    second_inputs: VecStorage<&'static [S]>,
+}
+
+impl<A, B, S> Chain<A, B, S>
+where
+    S: Zero + Clone + 'static,
+{
+    /// Connect the `number_of_intermediate_channels` outputs of `first` to the inputs of `second`.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(
+        first: A,
+        second: B,
+        number_of_intermediate_channels: usize,
+        max_buffer_size: usize,
+    ) -> Self {
+        Chain {
+            first,
+            second,
+            intermediate: vec![vec![S::zero(); max_buffer_size]; number_of_intermediate_channels],
+            first_outputs: VecStorage::with_capacity(number_of_intermediate_channels),
+            second_inputs: VecStorage::with_capacity(number_of_intermediate_channels),
+        }
+    }
+
+    /// The number of channels between the two renderers.
+    pub fn number_of_intermediate_channels(&self) -> usize {
+        self.intermediate.len()
+    }
+
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    pub fn first_mut(&mut self) -> &mut A {
+        &mut self.first
+    }
+
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+
+    pub fn second_mut(&mut self) -> &mut B {
+        &mut self.second
+    }
+
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+
+    fn reserve(&mut self, number_of_frames: usize) {
+        for buffer in self.intermediate.iter_mut() {
+            if buffer.len() < number_of_frames {
+                buffer.resize(number_of_frames, S::zero());
+            }
+        }
+    }
+
+    fn render_first<F>(&mut self, inputs: &[&[S]], number_of_frames: usize, render: F)
+    where
+        F: FnOnce(&mut A, &[&[S]], &mut [&mut [S]]),
+    {
+        let mut outputs = self.first_outputs.vec_guard();
+        for buffer in self.intermediate.iter_mut() {
+            outputs.push(&mut buffer[..number_of_frames]);
+        }
+        render(&mut self.first, inputs, outputs.as_mut_slice());
+    }
+
+    fn render_second<F>(&mut self, outputs: &mut [&mut [S]], number_of_frames: usize, render: F)
+    where
+        F: FnOnce(&mut B, &[&[S]], &mut [&mut [S]]),
+    {
+        let mut inputs = self.second_inputs.vec_guard();
+        for buffer in self.intermediate.iter() {
+            inputs.push(&buffer[..number_of_frames]);
+        }
+        render(&mut self.second, inputs.as_slice(), outputs);
+    }
+}
+
+impl<A, B, S> AudioRenderer<S> for Chain<A, B, S>
+where
+    A: AudioRenderer<S>,
+    B: AudioRenderer<S>,
+    S: Zero + Clone + 'static,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        let number_of_frames = number_of_frames(inputs, outputs);
+        self.reserve(number_of_frames);
+        self.render_first(inputs, number_of_frames, |first, inputs, outputs| {
+            first.render_buffer(inputs, outputs)
+        });
+        self.render_second(outputs, number_of_frames, |second, inputs, outputs| {
+            second.render_buffer(inputs, outputs)
+        });
+    }
+}
+
+impl<A, B, S, Context> ContextualAudioRenderer<S, Context> for Chain<A, B, S>
+where
+    A: ContextualAudioRenderer<S, Context>,
+    B: ContextualAudioRenderer<S, Context>,
+    S: Zero + Clone + 'static,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], context: &mut Context) {
+        let number_of_frames = number_of_frames(inputs, outputs);
+        self.reserve(number_of_frames);
+        self.render_first(inputs, number_of_frames, |first, inputs, outputs| {
+            first.render_buffer(inputs, outputs, context)
+        });
+        self.render_second(outputs, number_of_frames, |second, inputs, outputs| {
+            second.render_buffer(inputs, outputs, context)
+        });
+    }
+}
+
+impl<A, B, S, E> EventHandler<E> for Chain<A, B, S>
+where
+    A: EventHandler<E>,
+    B: EventHandler<E>,
+    E: Clone,
+{
+    fn handle_event(&mut self, event: E) {
+        self.first.handle_event(event.clone());
+        self.second.handle_event(event);
+    }
+
+    fn handle_events(&mut self, events: &[E])
+    where
+        E: Clone,
+    {
+        self.first.handle_events(events);
+        self.second.handle_events(events);
+    }
+}
+
+impl<A, B, S, E, Context> ContextualEventHandler<E, Context> for Chain<A, B, S>
+where
+    A: ContextualEventHandler<E, Context>,
+    B: ContextualEventHandler<E, Context>,
+    E: Clone,
+{
+    fn handle_event(&mut self, event: E, context: &mut Context) {
+        self.first.handle_event(event.clone(), context);
+        self.second.handle_event(event, context);
+    }
+
+    fn handle_events(&mut self, events: &[E], context: &mut Context)
+    where
+        E: Clone,
+    {
+        self.first.handle_events(events, context);
+        self.second.handle_events(events, context);
+    }
+}
+
+impl<A, B, S> AudioHandler for Chain<A, B, S>
+where
+    A: AudioHandler,
+    B: AudioHandler,
+    S: Zero + Clone + 'static,
+{
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.first.set_sample_rate(sample_rate);
+        self.second.set_sample_rate(sample_rate);
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.reserve(max_buffer_size);
+        self.first.set_max_buffer_size(max_buffer_size);
+        self.second.set_max_buffer_size(max_buffer_size);
+    }
+}
+
+impl<A, B, S> AudioHandlerMeta for Chain<A, B, S>
+where
+    A: AudioHandlerMeta,
+    B: AudioHandlerMeta,
+{
+    fn max_number_of_audio_inputs(&self) -> usize {
+        self.first.max_number_of_audio_inputs()
+    }
+
+    fn max_number_of_audio_outputs(&self) -> usize {
+        self.second.max_number_of_audio_outputs()
+    }
+}
+
+impl<A, B, S> Latency for Chain<A, B, S>
+where
+    A: Latency,
+    B: Latency,
+{
+    fn latency_in_frames(&self) -> usize {
+        self.first.latency_in_frames() + self.second.latency_in_frames()
+    }
+}
+
+impl<A, B, S> TailTime for Chain<A, B, S>
+where
+    A: TailTime,
+    B: TailTime,
+{
+    fn tail_time_in_frames(&self) -> usize {
+        self.first.tail_time_in_frames() + self.second.tail_time_in_frames()
+    }
+}
+
+impl<A, B, S> Lifecycle for Chain<A, B, S>
+where
+    A: Lifecycle,
+    B: Lifecycle,
+{
+    fn on_resume(&mut self) {
+        self.first.on_resume();
+        self.second.on_resume();
+    }
+
+    fn on_suspend(&mut self) {
+        self.second.on_suspend();
+        self.first.on_suspend();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utilities::EventCounter;
+    use crate::utilities::compensation_delay::CompensationDelay;
+    use crate::utilities::trivial_renderers::PassThrough;
+
+    // Adds the number of events that it has received so far to its input.
+    #[derive(Default)]
+    struct EventAdder {
+        number_of_events: usize,
+    }
+
+    impl EventHandler<u8> for EventAdder {
+        fn handle_event(&mut self, _event: u8) {
+            self.number_of_events += 1;
+        }
+    }
+
+    impl AudioRenderer<f32> for EventAdder {
+        fn render_buffer(&mut self, inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+            for (output, input) in outputs[0].iter_mut().zip(inputs[0].iter()) {
+                *output = *input + self.number_of_events as f32;
+            }
+        }
+    }
+
+    #[test]
+    fn renders_in_series_and_passes_events_to_both() {
+        let mut chain = Chain::new(EventCounter::default(), EventAdder::default(), 1, 4);
+        chain.handle_event(0_u8);
+        chain.handle_events(&[1_u8, 2]);
+        let mut output = [0.0; 3];
+        chain.render_buffer(&[], &mut [&mut output[..]]);
+        assert_eq!(output, [6.0; 3]);
+        assert_eq!(chain.first().number_of_events, 3);
+        assert_eq!(chain.second().number_of_events, 3);
+    }
+
+    #[test]
+    fn enlarges_the_intermediate_buffers_for_longer_buffers() {
+        let mut chain = Chain::new(PassThrough::new(1), PassThrough::new(1), 1, 2);
+        let input = [1.0, 2.0, 3.0, 4.0];
+        let mut output = [0.0; 4];
+        AudioRenderer::render_buffer(&mut chain, &[&input[..]], &mut [&mut output[..]]);
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn latency_is_the_sum_of_the_latencies() {
+        let chain = Chain::<_, _, f32>::new(
+            CompensationDelay::new(PassThrough::new(1), 1, 2),
+            CompensationDelay::new(PassThrough::new(1), 1, 3),
+            1,
+            16,
+        );
+        assert_eq!(chain.latency_in_frames(), 5);
+    }
+}
@@ -0,0 +1,401 @@
+//! Renderers that convert between channel layouts.
+//!
+//! * [`MonoToStereo`]: copies one input to two outputs;
+//! * [`StereoToMono`]: mixes two inputs down to one output, with a selectable [`DownMixLaw`];
+//! * [`ChannelRouter`]: copies inputs to outputs in an arbitrary order;
+//! * [`DownMixer`]: converts between [`ChannelLayout`]s, e.g. from 5.1 surround to stereo.
+//!
+//! These renderers ignore all events. Put them in a chain of renderers when the number or
+//! the order of the channels of one renderer does not match the next one.
+//!
+//! ```
+//! use rsynth::utilities::channel_routing::ChannelRouter;
+//! use rsynth::AudioRenderer;
+//!
+//! // Swap left and right and duplicate the left input to a third output.
+//! let mut router = ChannelRouter::new(2, &[Some(1), Some(0), Some(0)]);
+//! let left = [1.0];
+//! let right = [2.0];
+//! let mut outputs = [[0.0]; 3];
+//! let [first, second, third] = &mut outputs;
+//! router.render_buffer(
+//!     &[&left[..], &right[..]],
+//!     &mut [&mut first[..], &mut second[..], &mut third[..]],
+//! );
+//! assert_eq!(outputs, [[2.0], [1.0], [1.0]]);
+//! ```
+//!
+//! [`MonoToStereo`]: ./struct.MonoToStereo.html
+//! [`StereoToMono`]: ./struct.StereoToMono.html
+//! [`DownMixLaw`]: ./enum.DownMixLaw.html
+//! [`ChannelRouter`]: ./struct.ChannelRouter.html
+//! [`DownMixer`]: ./struct.DownMixer.html
+//! [`ChannelLayout`]: ../../channel_layout/enum.ChannelLayout.html
+use super::trivial_renderers::{impl_trivial_traits, meta_data};
+use crate::channel_layout::ChannelLayout;
+use crate::event::{ContextualEventHandler, EventHandler};
+use crate::meta::{Meta, MetaData};
+use crate::{AudioHandler, AudioRenderer, ContextualAudioRenderer, Latency, Lifecycle};
+use asprim::AsPrim;
+use num_traits::{Float, Zero};
+
+fn copy_or_clear<S: Zero + Copy>(input: Option<&&[S]>, output: &mut [S]) {
+    match input {
+        Some(input) => output.copy_from_slice(input),
+        None => {
+            for sample in output.iter_mut() {
+                *sample = S::zero();
+            }
+        }
+    }
+}
+
+/// Copies one audio input to two audio outputs.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct MonoToStereo {
+    meta: MetaData<String, String, String>,
+}
+
+impl MonoToStereo {
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new() -> Self {
+        MonoToStereo {
+            meta: meta_data("mono to stereo", 1, 2),
+        }
+    }
+}
+
+impl Default for MonoToStereo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Zero + Copy> AudioRenderer<S> for MonoToStereo {
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        for output in outputs.iter_mut().take(2) {
+            copy_or_clear(inputs.first(), output);
+        }
+    }
+}
+
+impl_trivial_traits!(MonoToStereo);
+
+/// How two channels are mixed down to one channel.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DownMixLaw {
+    /// `left + right`: keeps the level of signals that are only in one channel, but signals
+    /// that are in both channels become 6 dB louder.
+    Sum,
+    /// `(left + right) / 2`: keeps the level of signals that are in both channels, but signals
+    /// that are only in one channel become 6 dB softer.
+    Average,
+    /// `(left + right) / √2`: a compromise between the two, keeps the power of uncorrelated
+    /// signals.
+    EqualPower,
+    /// Only keep the left channel.
+    Left,
+    /// Only keep the right channel.
+    Right,
+}
+
+impl DownMixLaw {
+    // The gain for the left and for the right channel.
+    fn gains(self) -> (f64, f64) {
+        match self {
+            DownMixLaw::Sum => (1.0, 1.0),
+            DownMixLaw::Average => (0.5, 0.5),
+            DownMixLaw::EqualPower => (
+                std::f64::consts::FRAC_1_SQRT_2,
+                std::f64::consts::FRAC_1_SQRT_2,
+            ),
+            DownMixLaw::Left => (1.0, 0.0),
+            DownMixLaw::Right => (0.0, 1.0),
+        }
+    }
+}
+
+/// Mixes two audio inputs down to one audio output.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct StereoToMono {
+    meta: MetaData<String, String, String>,
+    law: DownMixLaw,
+}
+
+impl StereoToMono {
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(law: DownMixLaw) -> Self {
+        StereoToMono {
+            meta: meta_data("stereo to mono", 2, 1),
+            law,
+        }
+    }
+
+    /// The law that is used to mix the two inputs.
+    pub fn law(&self) -> DownMixLaw {
+        self.law
+    }
+
+    /// Change the law that is used to mix the two inputs.
+    pub fn set_law(&mut self, law: DownMixLaw) {
+        self.law = law;
+    }
+}
+
+impl<S: AsPrim + Float> AudioRenderer<S> for StereoToMono {
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        let output = match outputs.first_mut() {
+            Some(output) => output,
+            None => return,
+        };
+        let (left_gain, right_gain) = self.law.gains();
+        let left_gain: S = left_gain.as_();
+        let right_gain: S = right_gain.as_();
+        for (index, sample) in output.iter_mut().enumerate() {
+            let left = inputs
+                .first()
+                .map(|input| input[index])
+                .unwrap_or(S::zero());
+            let right = inputs.get(1).map(|input| input[index]).unwrap_or(left);
+            *sample = left * left_gain + right * right_gain;
+        }
+    }
+}
+
+impl_trivial_traits!(StereoToMono);
+
+/// Copies audio inputs to audio outputs in an arbitrary order.
+///
+/// Every output is either connected to one input or silent; one input can be connected
+/// to several outputs.
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct ChannelRouter {
+    meta: MetaData<String, String, String>,
+    // For every output, the index of the input.
+    routes: Vec<Option<usize>>,
+}
+
+impl ChannelRouter {
+    /// Create a new router with `number_of_inputs` inputs and one output for every element of
+    /// `routes`, which is the index of the input that is copied to the output, or `None`
+    /// when the output is silent. Indices that are out of range are treated as `None`.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(number_of_inputs: usize, routes: &[Option<usize>]) -> Self {
+        let routes = routes
+            .iter()
+            .map(|route| route.filter(|input| *input < number_of_inputs))
+            .collect::<Vec<_>>();
+        ChannelRouter {
+            meta: meta_data("channel router", number_of_inputs, routes.len()),
+            routes,
+        }
+    }
+
+    /// The index of the input that is copied to the given output.
+    pub fn route(&self, output: usize) -> Option<usize> {
+        self.routes.get(output).copied().flatten()
+    }
+
+    /// Change the input that is copied to the given output.
+    ///
+    /// # Panics
+    /// Panics if `output` is out of range.
+    pub fn set_route(&mut self, output: usize, input: Option<usize>) {
+        let number_of_inputs = self.meta.audio_port_meta.inputs.len();
+        self.routes[output] = input.filter(|input| *input < number_of_inputs);
+    }
+}
+
+impl<S: Zero + Copy> AudioRenderer<S> for ChannelRouter {
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        for (output, route) in outputs.iter_mut().zip(self.routes.iter()) {
+            copy_or_clear(route.and_then(|input| inputs.get(input)), output);
+        }
+    }
+}
+
+impl_trivial_traits!(ChannelRouter);
+
+/// Converts audio from one [`ChannelLayout`] to another, e.g. from 5.1 surround to stereo.
+///
+/// Every output is a weighted sum of the inputs, with the gains of
+/// [`ChannelLayout::down_mix_matrix`]. The gains are not normalized, so the output can be louder
+/// than the input.
+/// See the [module level documentation] for more information.
+///
+/// [`ChannelLayout`]: ../../channel_layout/enum.ChannelLayout.html
+/// [`ChannelLayout::down_mix_matrix`]: ../../channel_layout/enum.ChannelLayout.html#method.down_mix_matrix
+/// [module level documentation]: ./index.html
+pub struct DownMixer {
+    meta: MetaData<String, String, String>,
+    from: ChannelLayout,
+    to: ChannelLayout,
+    // For every output, the gain of every input.
+    gains: Vec<Vec<f64>>,
+}
+
+impl DownMixer {
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(from: ChannelLayout, to: ChannelLayout) -> Self {
+        DownMixer {
+            meta: meta_data(
+                "down mixer",
+                from.number_of_channels(),
+                to.number_of_channels(),
+            ),
+            from,
+            to,
+            gains: from.down_mix_matrix(to),
+        }
+    }
+
+    /// The channel layout of the inputs.
+    pub fn input_layout(&self) -> ChannelLayout {
+        self.from
+    }
+
+    /// The channel layout of the outputs.
+    pub fn output_layout(&self) -> ChannelLayout {
+        self.to
+    }
+
+    /// The gain of the given input in the given output, or `0` if either is out of range.
+    pub fn gain(&self, output: usize, input: usize) -> f64 {
+        self.gains
+            .get(output)
+            .and_then(|gains| gains.get(input))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// Change the gain of the given input in the given output.
+    ///
+    /// # Panics
+    /// Panics if `output` or `input` is out of range.
+    pub fn set_gain(&mut self, output: usize, input: usize, gain: f64) {
+        self.gains[output][input] = gain;
+    }
+}
+
+impl<S: AsPrim + Float> AudioRenderer<S> for DownMixer {
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        for (output, gains) in outputs.iter_mut().zip(self.gains.iter()) {
+            for sample in output.iter_mut() {
+                *sample = S::zero();
+            }
+            for (input, gain) in inputs.iter().zip(gains.iter()) {
+                if *gain == 0.0 {
+                    continue;
+                }
+                let gain: S = gain.as_();
+                for (sample, input_sample) in output.iter_mut().zip(input.iter()) {
+                    *sample = *sample + *input_sample * gain;
+                }
+            }
+        }
+    }
+}
+
+impl_trivial_traits!(DownMixer);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AudioHandlerMeta;
+
+    #[test]
+    fn mono_to_stereo_duplicates_the_input() {
+        let mut renderer = MonoToStereo::new();
+        let input = [1.0_f32, 2.0];
+        let mut left = [0.0; 2];
+        let mut right = [0.0; 2];
+        AudioRenderer::render_buffer(&mut renderer, &[&input], &mut [&mut left, &mut right]);
+        assert_eq!(left, input);
+        assert_eq!(right, input);
+    }
+
+    #[test]
+    fn stereo_to_mono_uses_the_down_mix_law() {
+        let left = [1.0_f64, 0.0];
+        let right = [1.0_f64, 2.0];
+        let mut renderer = StereoToMono::new(DownMixLaw::Average);
+        let mix = |renderer: &mut StereoToMono| {
+            let mut output = [0.0; 2];
+            AudioRenderer::render_buffer(renderer, &[&left, &right], &mut [&mut output]);
+            output
+        };
+        assert_eq!(mix(&mut renderer), [1.0, 1.0]);
+        renderer.set_law(DownMixLaw::Sum);
+        assert_eq!(mix(&mut renderer), [2.0, 2.0]);
+        renderer.set_law(DownMixLaw::Right);
+        assert_eq!(mix(&mut renderer), [1.0, 2.0]);
+        renderer.set_law(DownMixLaw::EqualPower);
+        let output = mix(&mut renderer);
+        assert!((output[0] - 2.0_f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn channel_router_copies_and_clears_outputs() {
+        let mut router = ChannelRouter::new(2, &[Some(1), None, Some(5)]);
+        assert_eq!(router.max_number_of_audio_outputs(), 3);
+        assert_eq!(router.route(2), None);
+        let first = [1.0_f32];
+        let second = [2.0_f32];
+        let mut outputs = [[9.0_f32]; 3];
+        {
+            let [a, b, c] = &mut outputs;
+            AudioRenderer::render_buffer(
+                &mut router,
+                &[&first, &second],
+                &mut [&mut a[..], &mut b[..], &mut c[..]],
+            );
+        }
+        assert_eq!(outputs, [[2.0], [0.0], [0.0]]);
+        router.set_route(1, Some(0));
+        {
+            let [a, b, c] = &mut outputs;
+            AudioRenderer::render_buffer(
+                &mut router,
+                &[&first, &second],
+                &mut [&mut a[..], &mut b[..], &mut c[..]],
+            );
+        }
+        assert_eq!(outputs, [[2.0], [1.0], [0.0]]);
+    }
+
+    #[test]
+    fn down_mixer_mixes_surround_to_stereo() {
+        let mut mixer = DownMixer::new(ChannelLayout::Surround51, ChannelLayout::Stereo);
+        assert_eq!(mixer.max_number_of_audio_inputs(), 6);
+        assert_eq!(mixer.max_number_of_audio_outputs(), 2);
+        let inputs = [[1.0_f64], [2.0], [4.0], [8.0], [16.0], [32.0]];
+        let input_slices: Vec<&[f64]> = inputs.iter().map(|input| &input[..]).collect();
+        let mut left = [9.0];
+        let mut right = [9.0];
+        AudioRenderer::render_buffer(&mut mixer, &input_slices, &mut [&mut left, &mut right]);
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        assert!((left[0] - (1.0 + 4.0 * half + 16.0 * half)).abs() < 1e-12);
+        assert!((right[0] - (2.0 + 4.0 * half + 32.0 * half)).abs() < 1e-12);
+        mixer.set_gain(0, 3, 1.0);
+        assert_eq!(mixer.gain(0, 3), 1.0);
+        AudioRenderer::render_buffer(&mut mixer, &input_slices, &mut [&mut left, &mut right]);
+        assert!((left[0] - (9.0 + 4.0 * half + 16.0 * half)).abs() < 1e-12);
+    }
+}
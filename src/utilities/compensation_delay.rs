@@ -0,0 +1,324 @@
+//! Delay some of the outputs of a renderer, e.g. to align parallel chains with different
+//! latencies.
+//!
+//! When two renderers process the same signal in parallel and one of them has a latency
+//! (e.g. because it uses look-ahead or an FFT), their outputs are no longer aligned.
+//! Wrap the other renderer in a [`CompensationDelay`] to delay its outputs by the difference.
+//!
+//! The [`CompensationDelay`] implements the [`Latency`] trait: its latency is the latency of the
+//! wrapped renderer plus the delay, so that the latency of nested wrappers is computed
+//! automatically. Use [`CompensationDelay::aligned`] to compute the delay from the latency of
+//! the wrapped renderer.
+//!
+//! ```
+//! use rsynth::utilities::compensation_delay::CompensationDelay;
+//! use rsynth::utilities::trivial_renderers::PassThrough;
+//! use rsynth::{AudioRenderer, Latency};
+//!
+//! // Delay the output of a `PassThrough` by 2 frames.
+//! let mut delayed = CompensationDelay::new(PassThrough::new(1), 1, 2);
+//! assert_eq!(delayed.latency_in_frames(), 2);
+//! let input = [1.0, 2.0, 3.0, 4.0];
+//! let mut output = [0.0; 4];
+//! delayed.render_buffer(&[&input[..]], &mut [&mut output[..]]);
+//! assert_eq!(output, [0.0, 0.0, 1.0, 2.0]);
+//! ```
+//!
+//! [`CompensationDelay`]: ./struct.CompensationDelay.html
+//! [`CompensationDelay::aligned`]: ./struct.CompensationDelay.html#method.aligned
+//! [`Latency`]: ../../trait.Latency.html
+use crate::event::{ContextualEventHandler, EventHandler};
+use crate::meta::Meta;
+use crate::{AudioHandler, AudioRenderer, ContextualAudioRenderer, Latency, Lifecycle};
+use asprim::AsPrim;
+
+/// Wraps a renderer and delays some or all of its outputs by a fixed number of frames.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct CompensationDelay<R> {
+    inner: R,
+    delay_in_frames: usize,
+    // One delay line for every output; `None` for the outputs that are not delayed.
+    delay_lines: Vec<Option<Vec<f64>>>,
+    position: usize,
+}
+
+impl<R> CompensationDelay<R> {
+    /// Wrap `inner`, which has `number_of_outputs` audio outputs, and delay all its outputs by
+    /// `delay_in_frames` frames.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(inner: R, number_of_outputs: usize, delay_in_frames: usize) -> Self {
+        CompensationDelay {
+            inner,
+            delay_in_frames,
+            delay_lines: (0..number_of_outputs)
+                .map(|_| Some(vec![0.0; delay_in_frames]))
+                .collect(),
+            position: 0,
+        }
+    }
+
+    /// Wrap `inner` and delay its outputs so that the total latency is `total_latency_in_frames`.
+    /// When the latency of `inner` is larger than `total_latency_in_frames`, the outputs are
+    /// not delayed.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn aligned(inner: R, number_of_outputs: usize, total_latency_in_frames: usize) -> Self
+    where
+        R: Latency,
+    {
+        let delay_in_frames = total_latency_in_frames.saturating_sub(inner.latency_in_frames());
+        Self::new(inner, number_of_outputs, delay_in_frames)
+    }
+
+    /// Only delay the outputs with the given indices; the other outputs are passed unchanged.
+    /// Indices that are out of range are ignored.
+    ///
+    /// Note that [`latency_in_frames`] reports the delay, also when not every output is delayed.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    ///
+    /// [`latency_in_frames`]: #method.latency_in_frames
+    pub fn with_delayed_outputs(mut self, outputs: &[usize]) -> Self {
+        let delay_in_frames = self.delay_in_frames;
+        for (index, delay_line) in self.delay_lines.iter_mut().enumerate() {
+            *delay_line = if outputs.contains(&index) {
+                Some(vec![0.0; delay_in_frames])
+            } else {
+                None
+            };
+        }
+        self
+    }
+
+    /// The number of frames by which the outputs are delayed.
+    pub fn delay_in_frames(&self) -> usize {
+        self.delay_in_frames
+    }
+
+    /// Clear the delay lines.
+    pub fn reset(&mut self) {
+        for delay_line in self.delay_lines.iter_mut().flatten() {
+            for sample in delay_line.iter_mut() {
+                *sample = 0.0;
+            }
+        }
+        self.position = 0;
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn delay_outputs<S: AsPrim + Copy>(&mut self, outputs: &mut [&mut [S]]) {
+        if self.delay_in_frames == 0 {
+            return;
+        }
+        let mut number_of_frames = 0;
+        for (output, delay_line) in outputs.iter_mut().zip(self.delay_lines.iter_mut()) {
+            number_of_frames = output.len();
+            let delay_line = match delay_line {
+                Some(delay_line) => delay_line,
+                None => continue,
+            };
+            let mut position = self.position;
+            for sample in output.iter_mut() {
+                let delayed = delay_line[position];
+                delay_line[position] = sample.as_();
+                *sample = delayed.as_();
+                position += 1;
+                if position == self.delay_in_frames {
+                    position = 0;
+                }
+            }
+        }
+        self.position = (self.position + number_of_frames) % self.delay_in_frames;
+    }
+}
+
+impl<R> Latency for CompensationDelay<R>
+where
+    R: Latency,
+{
+    fn latency_in_frames(&self) -> usize {
+        self.inner.latency_in_frames() + self.delay_in_frames
+    }
+}
+
+impl<R> Lifecycle for CompensationDelay<R>
+where
+    R: Lifecycle,
+{
+    fn on_resume(&mut self) {
+        self.reset();
+        self.inner.on_resume();
+    }
+
+    fn on_suspend(&mut self) {
+        self.inner.on_suspend();
+    }
+}
+
+impl<R, S> AudioRenderer<S> for CompensationDelay<R>
+where
+    R: AudioRenderer<S>,
+    S: AsPrim + Copy,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        self.inner.render_buffer(inputs, outputs);
+        self.delay_outputs(outputs);
+    }
+}
+
+impl<R, S, Context> ContextualAudioRenderer<S, Context> for CompensationDelay<R>
+where
+    R: ContextualAudioRenderer<S, Context>,
+    S: AsPrim + Copy,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], context: &mut Context) {
+        self.inner.render_buffer(inputs, outputs, context);
+        self.delay_outputs(outputs);
+    }
+}
+
+impl<R, E> EventHandler<E> for CompensationDelay<R>
+where
+    R: EventHandler<E>,
+{
+    fn handle_event(&mut self, event: E) {
+        self.inner.handle_event(event);
+    }
+
+    fn handle_events(&mut self, events: &[E])
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events);
+    }
+}
+
+impl<R, E, Context> ContextualEventHandler<E, Context> for CompensationDelay<R>
+where
+    R: ContextualEventHandler<E, Context>,
+{
+    fn handle_event(&mut self, event: E, context: &mut Context) {
+        self.inner.handle_event(event, context);
+    }
+
+    fn handle_events(&mut self, events: &[E], context: &mut Context)
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events, context);
+    }
+}
+
+impl<R> AudioHandler for CompensationDelay<R>
+where
+    R: AudioHandler,
+{
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.inner.set_sample_rate(sample_rate);
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.inner.set_max_buffer_size(max_buffer_size);
+    }
+}
+
+impl<R> Meta for CompensationDelay<R>
+where
+    R: Meta,
+{
+    type MetaData = R::MetaData;
+
+    fn meta(&self) -> &Self::MetaData {
+        self.inner.meta()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::trivial_renderers::PassThrough;
+
+    struct LookAhead;
+
+    impl Latency for LookAhead {
+        fn latency_in_frames(&self) -> usize {
+            3
+        }
+    }
+
+    #[test]
+    fn delays_over_several_buffers() {
+        let mut delayed = CompensationDelay::new(PassThrough::new(1), 1, 3);
+        let input: Vec<f32> = (1..=8).map(|i| i as f32).collect();
+        let mut output = Vec::new();
+        for buffer in input.chunks(2) {
+            let mut buffer_output = [0.0; 2];
+            AudioRenderer::render_buffer(&mut delayed, &[buffer], &mut [&mut buffer_output]);
+            output.extend_from_slice(&buffer_output);
+        }
+        assert_eq!(output, vec![0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        delayed.reset();
+        let mut buffer_output = [0.0; 2];
+        AudioRenderer::render_buffer(&mut delayed, &[&[9.0, 10.0]], &mut [&mut buffer_output]);
+        assert_eq!(buffer_output, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn resuming_clears_the_delay_lines() {
+        let mut delayed = CompensationDelay::new(PassThrough::new(1), 1, 2);
+        let mut buffer_output = [0.0; 2];
+        AudioRenderer::render_buffer(&mut delayed, &[&[1.0, 2.0]], &mut [&mut buffer_output]);
+        delayed.on_suspend();
+        delayed.on_resume();
+        AudioRenderer::render_buffer(&mut delayed, &[&[3.0, 4.0]], &mut [&mut buffer_output]);
+        assert_eq!(buffer_output, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn only_delays_the_selected_outputs() {
+        let mut delayed =
+            CompensationDelay::new(PassThrough::new(2), 2, 1).with_delayed_outputs(&[1]);
+        let left = [1.0_f64, 2.0];
+        let right = [3.0_f64, 4.0];
+        let mut left_output = [0.0; 2];
+        let mut right_output = [0.0; 2];
+        AudioRenderer::render_buffer(
+            &mut delayed,
+            &[&left, &right],
+            &mut [&mut left_output, &mut right_output],
+        );
+        assert_eq!(left_output, [1.0, 2.0]);
+        assert_eq!(right_output, [0.0, 3.0]);
+    }
+
+    #[test]
+    fn aligned_compensates_the_latency_of_the_inner_renderer() {
+        let delayed = CompensationDelay::aligned(LookAhead, 1, 5);
+        assert_eq!(delayed.delay_in_frames(), 2);
+        assert_eq!(delayed.latency_in_frames(), 5);
+        let nested = CompensationDelay::aligned(delayed, 1, 8);
+        assert_eq!(nested.delay_in_frames(), 3);
+        assert_eq!(nested.latency_in_frames(), 8);
+    }
+}
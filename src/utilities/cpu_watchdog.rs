@@ -0,0 +1,493 @@
+//! Detect when rendering nearly exceeds the real-time budget and reduce the CPU load before
+//! the audio drops out.
+//!
+//! The [`CpuWatchdog`] wraps a renderer and measures how long each call to `render_buffer`
+//! takes, relative to the duration of the buffer (the "load"). When the load of a buffer
+//! exceeds the overload threshold, e.g. in a dense passage with many voices, the
+//! [`DegradationPolicy`]s in the [`WatchdogSettings`] are applied by calling the methods of the
+//! [`Degrade`] trait on the wrapped renderer:
+//!
+//! * [`DropQuietestVoices`]: stop some of the quietest voices, for every overloaded buffer,
+//! * [`ReduceUnison`]: reduce the number of unison voices by one step for every overloaded
+//!   buffer and restore them one step at a time when the load has recovered,
+//! * [`RaiseEvent`]: let the renderer decide, with a [`LoadEvent`].
+//!
+//! The load has recovered when it stays below the recovery threshold for a number of
+//! consecutive buffers.
+//!
+//! ```
+//! use rsynth::utilities::cpu_watchdog::{
+//!     CpuWatchdog, Degrade, DegradationPolicy, WatchdogSettings,
+//! };
+//! use rsynth::{AudioHandler, AudioRenderer};
+//!
+//! struct Synth {
+//!     number_of_voices: usize,
+//! }
+//!
+//! impl AudioRenderer<f32> for Synth {
+//!     fn render_buffer(&mut self, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+//!         // Render `self.number_of_voices` voices.
+//!     }
+//! }
+//!
+//! impl AudioHandler for Synth {
+//!     fn set_sample_rate(&mut self, _sample_rate: f64) {}
+//! }
+//!
+//! impl Degrade for Synth {
+//!     fn drop_quietest_voices(&mut self, number_of_voices: usize) {
+//!         self.number_of_voices = self.number_of_voices.saturating_sub(number_of_voices);
+//!     }
+//! }
+//!
+//! let settings = WatchdogSettings {
+//!     policies: vec![DegradationPolicy::DropQuietestVoices(2)],
+//!     ..WatchdogSettings::default()
+//! };
+//! let mut synth = CpuWatchdog::new(Synth { number_of_voices: 16 }, settings);
+//! synth.set_sample_rate(44100.0);
+//! let mut output = [0.0_f32; 64];
+//! synth.render_buffer(&[], &mut [&mut output[..]]);
+//! println!("load: {:.0}%", synth.load() * 100.0);
+//! ```
+//!
+//! Real-time safety
+//! ----------------
+//! Measuring the load and applying the policies does not allocate memory; the methods of the
+//! `Degrade` trait are called from the real-time thread.
+//!
+//! [`CpuWatchdog`]: ./struct.CpuWatchdog.html
+//! [`DegradationPolicy`]: ./enum.DegradationPolicy.html
+//! [`WatchdogSettings`]: ./struct.WatchdogSettings.html
+//! [`Degrade`]: ./trait.Degrade.html
+//! [`DropQuietestVoices`]: ./enum.DegradationPolicy.html#variant.DropQuietestVoices
+//! [`ReduceUnison`]: ./enum.DegradationPolicy.html#variant.ReduceUnison
+//! [`RaiseEvent`]: ./enum.DegradationPolicy.html#variant.RaiseEvent
+//! [`LoadEvent`]: ./enum.LoadEvent.html
+use crate::buffer::number_of_frames;
+use crate::event::{ContextualEventHandler, EventHandler};
+use crate::meta::Meta;
+use crate::{AudioHandler, AudioRenderer, ContextualAudioRenderer, Latency, Lifecycle};
+use std::time::{Duration, Instant};
+
+/// What the [`CpuWatchdog`] does when a buffer is overloaded.
+///
+/// [`CpuWatchdog`]: ./struct.CpuWatchdog.html
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DegradationPolicy {
+    /// Call [`Degrade::drop_quietest_voices`] with the given number of voices.
+    ///
+    /// [`Degrade::drop_quietest_voices`]: ./trait.Degrade.html#method.drop_quietest_voices
+    DropQuietestVoices(usize),
+    /// Call [`Degrade::reduce_unison`] and, when the load has recovered,
+    /// [`Degrade::restore_unison`] once for every reduction.
+    ///
+    /// [`Degrade::reduce_unison`]: ./trait.Degrade.html#method.reduce_unison
+    /// [`Degrade::restore_unison`]: ./trait.Degrade.html#method.restore_unison
+    ReduceUnison,
+    /// Call [`Degrade::handle_load_event`] when a buffer is overloaded and when the load has
+    /// recovered.
+    ///
+    /// [`Degrade::handle_load_event`]: ./trait.Degrade.html#method.handle_load_event
+    RaiseEvent,
+}
+
+/// The settings of the [`CpuWatchdog`].
+///
+/// [`CpuWatchdog`]: ./struct.CpuWatchdog.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchdogSettings {
+    /// A buffer is overloaded when rendering takes more than this fraction of the duration of
+    /// the buffer. The default is 0.8: the host and the back-end also need some time.
+    pub overload_threshold: f64,
+    /// After an overload, the load has recovered when it stays below this fraction for
+    /// `recovery_buffers` consecutive buffers. The default is 0.5.
+    pub recovery_threshold: f64,
+    /// The default is 100 buffers.
+    pub recovery_buffers: usize,
+    /// The policies that are applied, in this order. The default is to raise an event.
+    pub policies: Vec<DegradationPolicy>,
+}
+
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        WatchdogSettings {
+            overload_threshold: 0.8,
+            recovery_threshold: 0.5,
+            recovery_buffers: 100,
+            policies: vec![DegradationPolicy::RaiseEvent],
+        }
+    }
+}
+
+/// The event that is passed to [`Degrade::handle_load_event`].
+///
+/// The load is the time that was needed to render the buffer divided by the duration of the
+/// buffer.
+///
+/// [`Degrade::handle_load_event`]: ./trait.Degrade.html#method.handle_load_event
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LoadEvent {
+    /// The load of the last buffer exceeded the overload threshold.
+    Overload { load: f64 },
+    /// The load has recovered after an overload.
+    Recovered { load: f64 },
+}
+
+/// Reduce the CPU load of a renderer, as requested by the [`CpuWatchdog`].
+///
+/// Implement the methods that correspond to the [`DegradationPolicy`]s that are used;
+/// the default implementations do nothing.
+/// The methods are called from the real-time thread, right after `render_buffer`.
+///
+/// [`CpuWatchdog`]: ./struct.CpuWatchdog.html
+/// [`DegradationPolicy`]: ./enum.DegradationPolicy.html
+pub trait Degrade {
+    /// Stop (e.g. quickly fade out) the given number of voices with the lowest level.
+    fn drop_quietest_voices(&mut self, _number_of_voices: usize) {}
+
+    /// Reduce the number of unison voices by one step, e.g. by halving it.
+    fn reduce_unison(&mut self) {}
+
+    /// Undo one call to `reduce_unison`.
+    fn restore_unison(&mut self) {}
+
+    /// Handle the overload in a custom way.
+    fn handle_load_event(&mut self, _event: LoadEvent) {}
+}
+
+/// Wraps a renderer, measures its load and applies the degradation policies.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct CpuWatchdog<R> {
+    inner: R,
+    settings: WatchdogSettings,
+    sample_rate: f64,
+    load: f64,
+    overloaded: bool,
+    buffers_below_recovery_threshold: usize,
+    number_of_unison_reductions: usize,
+}
+
+impl<R> CpuWatchdog<R> {
+    /// Wrap `inner`.
+    pub fn new(inner: R, settings: WatchdogSettings) -> Self {
+        CpuWatchdog {
+            inner,
+            settings,
+            sample_rate: 44100.0,
+            load: 0.0,
+            overloaded: false,
+            buffers_below_recovery_threshold: 0,
+            number_of_unison_reductions: 0,
+        }
+    }
+
+    /// The load of the last buffer: the time that was needed to render it divided by its
+    /// duration.
+    pub fn load(&self) -> f64 {
+        self.load
+    }
+
+    /// Return `true` after an overload, until the load has recovered.
+    pub fn is_overloaded(&self) -> bool {
+        self.overloaded
+    }
+
+    pub fn settings(&self) -> &WatchdogSettings {
+        &self.settings
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn after_buffer(&mut self, elapsed: Duration, number_of_frames: usize)
+    where
+        R: Degrade,
+    {
+        if number_of_frames == 0 {
+            return;
+        }
+        let budget = number_of_frames as f64 / self.sample_rate;
+        self.load = elapsed.as_secs_f64() / budget;
+        if self.load > self.settings.overload_threshold {
+            self.overloaded = true;
+            self.buffers_below_recovery_threshold = 0;
+            for policy in self.settings.policies.iter() {
+                match policy {
+                    DegradationPolicy::DropQuietestVoices(number_of_voices) => {
+                        self.inner.drop_quietest_voices(*number_of_voices)
+                    }
+                    DegradationPolicy::ReduceUnison => {
+                        self.number_of_unison_reductions += 1;
+                        self.inner.reduce_unison();
+                    }
+                    DegradationPolicy::RaiseEvent => self
+                        .inner
+                        .handle_load_event(LoadEvent::Overload { load: self.load }),
+                }
+            }
+            return;
+        }
+        if !self.overloaded {
+            return;
+        }
+        if self.load < self.settings.recovery_threshold {
+            self.buffers_below_recovery_threshold += 1;
+        } else {
+            self.buffers_below_recovery_threshold = 0;
+        }
+        if self.buffers_below_recovery_threshold < self.settings.recovery_buffers {
+            return;
+        }
+        // Restore the unison voices one step at a time, checking the load after each step.
+        self.buffers_below_recovery_threshold = 0;
+        if self.number_of_unison_reductions > 0 {
+            self.number_of_unison_reductions -= 1;
+            self.inner.restore_unison();
+            if self.number_of_unison_reductions > 0 {
+                return;
+            }
+        }
+        self.overloaded = false;
+        if self
+            .settings
+            .policies
+            .contains(&DegradationPolicy::RaiseEvent)
+        {
+            self.inner
+                .handle_load_event(LoadEvent::Recovered { load: self.load });
+        }
+    }
+}
+
+impl<R> Latency for CpuWatchdog<R>
+where
+    R: Latency,
+{
+    fn latency_in_frames(&self) -> usize {
+        self.inner.latency_in_frames()
+    }
+}
+
+impl<R> Lifecycle for CpuWatchdog<R>
+where
+    R: Lifecycle,
+{
+    fn on_resume(&mut self) {
+        self.inner.on_resume();
+    }
+
+    fn on_suspend(&mut self) {
+        self.inner.on_suspend();
+    }
+}
+
+impl<R, S> AudioRenderer<S> for CpuWatchdog<R>
+where
+    R: AudioRenderer<S> + Degrade,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        let start = Instant::now();
+        self.inner.render_buffer(inputs, outputs);
+        self.after_buffer(start.elapsed(), number_of_frames(inputs, outputs));
+    }
+}
+
+impl<R, S, Context> ContextualAudioRenderer<S, Context> for CpuWatchdog<R>
+where
+    R: ContextualAudioRenderer<S, Context> + Degrade,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], context: &mut Context) {
+        let start = Instant::now();
+        self.inner.render_buffer(inputs, outputs, context);
+        self.after_buffer(start.elapsed(), number_of_frames(inputs, outputs));
+    }
+}
+
+impl<R, E> EventHandler<E> for CpuWatchdog<R>
+where
+    R: EventHandler<E>,
+{
+    fn handle_event(&mut self, event: E) {
+        self.inner.handle_event(event);
+    }
+
+    fn handle_events(&mut self, events: &[E])
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events);
+    }
+}
+
+impl<R, E, Context> ContextualEventHandler<E, Context> for CpuWatchdog<R>
+where
+    R: ContextualEventHandler<E, Context>,
+{
+    fn handle_event(&mut self, event: E, context: &mut Context) {
+        self.inner.handle_event(event, context);
+    }
+
+    fn handle_events(&mut self, events: &[E], context: &mut Context)
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events, context);
+    }
+}
+
+impl<R> AudioHandler for CpuWatchdog<R>
+where
+    R: AudioHandler,
+{
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        self.inner.set_sample_rate(sample_rate);
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.inner.set_max_buffer_size(max_buffer_size);
+    }
+}
+
+impl<R> Meta for CpuWatchdog<R>
+where
+    R: Meta,
+{
+    type MetaData = R::MetaData;
+
+    fn meta(&self) -> &Self::MetaData {
+        self.inner.meta()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        dropped_voices: usize,
+        unison_step: isize,
+        events: Vec<LoadEvent>,
+    }
+
+    impl AudioRenderer<f32> for Recorder {
+        fn render_buffer(&mut self, _: &[&[f32]], _: &mut [&mut [f32]]) {}
+    }
+
+    impl AudioHandler for Recorder {
+        fn set_sample_rate(&mut self, _: f64) {}
+    }
+
+    impl Degrade for Recorder {
+        fn drop_quietest_voices(&mut self, number_of_voices: usize) {
+            self.dropped_voices += number_of_voices;
+        }
+
+        fn reduce_unison(&mut self) {
+            self.unison_step -= 1;
+        }
+
+        fn restore_unison(&mut self) {
+            self.unison_step += 1;
+        }
+
+        fn handle_load_event(&mut self, event: LoadEvent) {
+            self.events.push(event);
+        }
+    }
+
+    fn watchdog(policies: Vec<DegradationPolicy>) -> CpuWatchdog<Recorder> {
+        let mut watchdog = CpuWatchdog::new(
+            Recorder::default(),
+            WatchdogSettings {
+                recovery_buffers: 2,
+                policies,
+                ..WatchdogSettings::default()
+            },
+        );
+        // A buffer of 1000 frames lasts 1 second.
+        watchdog.sample_rate = 1000.0;
+        watchdog
+    }
+
+    fn buffer(watchdog: &mut CpuWatchdog<Recorder>, milliseconds: u64) {
+        watchdog.after_buffer(Duration::from_millis(milliseconds), 1000);
+    }
+
+    #[test]
+    fn overload_raises_an_event_until_the_load_has_recovered() {
+        let mut watchdog = watchdog(vec![
+            DegradationPolicy::DropQuietestVoices(2),
+            DegradationPolicy::RaiseEvent,
+        ]);
+        buffer(&mut watchdog, 700);
+        assert!(!watchdog.is_overloaded());
+        buffer(&mut watchdog, 900);
+        assert!(watchdog.is_overloaded());
+        assert!((watchdog.load() - 0.9).abs() < 1e-9);
+        buffer(&mut watchdog, 850);
+        assert_eq!(watchdog.inner().dropped_voices, 4);
+
+        // Between the thresholds: not recovered.
+        buffer(&mut watchdog, 600);
+        buffer(&mut watchdog, 400);
+        buffer(&mut watchdog, 600);
+        buffer(&mut watchdog, 400);
+        assert!(watchdog.is_overloaded());
+        buffer(&mut watchdog, 300);
+        assert!(!watchdog.is_overloaded());
+        assert_eq!(
+            watchdog.inner().events,
+            vec![
+                LoadEvent::Overload { load: 0.9 },
+                LoadEvent::Overload { load: 0.85 },
+                LoadEvent::Recovered { load: 0.3 }
+            ]
+        );
+    }
+
+    #[test]
+    fn unison_is_restored_one_step_at_a_time() {
+        let mut watchdog = watchdog(vec![DegradationPolicy::ReduceUnison]);
+        buffer(&mut watchdog, 1000);
+        buffer(&mut watchdog, 1000);
+        assert_eq!(watchdog.inner().unison_step, -2);
+        for _ in 0..2 {
+            buffer(&mut watchdog, 100);
+        }
+        assert_eq!(watchdog.inner().unison_step, -1);
+        assert!(watchdog.is_overloaded());
+        for _ in 0..2 {
+            buffer(&mut watchdog, 100);
+        }
+        assert_eq!(watchdog.inner().unison_step, 0);
+        assert!(!watchdog.is_overloaded());
+        assert!(watchdog.inner().events.is_empty());
+    }
+
+    #[test]
+    fn render_buffer_measures_the_load() {
+        let mut watchdog = watchdog(vec![DegradationPolicy::RaiseEvent]);
+        // At this sample rate, any buffer is overloaded.
+        watchdog.set_sample_rate(1.0e15);
+        let mut output = [0.0_f32; 16];
+        AudioRenderer::render_buffer(&mut watchdog, &[], &mut [&mut output[..]]);
+        assert!(watchdog.is_overloaded());
+        assert_eq!(watchdog.inner().events.len(), 1);
+    }
+}
@@ -0,0 +1,417 @@
+//! Detect NaN, infinite, denormal and over-range samples in the output of a renderer.
+//!
+//! When a filter becomes unstable, it often only does so intermittently, and by the time you
+//! hear the result, it is hard to know where the problem started.
+//! Wrap the plugin in a [`DebugRenderer`] to check all output samples after each call to
+//! `render_buffer`. The first problem is logged with [`rt_log!`] and can be retrieved with
+//! [`DebugRenderer::first_problem`], together with the index of the buffer, the channel and the
+//! frame where it occurred.
+//!
+//! The `DebugRenderer` forwards the events, the sample rate and the meta-data to the wrapped
+//! plugin, so it can be used with all back-ends.
+//!
+//! Example
+//! -------
+//! ```
+//! use rsynth::utilities::debug_renderer::{DebugRenderer, SampleProblem};
+//! use rsynth::AudioRenderer;
+//!
+//! struct Unstable;
+//! impl AudioRenderer<f32> for Unstable {
+//!     fn render_buffer(&mut self, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+//!         outputs[0][2] = f32::NAN;
+//!     }
+//! }
+//!
+//! let mut renderer = DebugRenderer::new(Unstable);
+//! let mut output = [0.0; 4];
+//! renderer.render_buffer(&[], &mut [&mut output]);
+//! let problem = renderer.first_problem().expect("a problem should be found");
+//! assert_eq!(problem.problem, SampleProblem::NaN);
+//! assert_eq!((problem.buffer_index, problem.channel, problem.frame), (0, 0, 2));
+//! ```
+//!
+//! [`DebugRenderer`]: ./struct.DebugRenderer.html
+//! [`DebugRenderer::first_problem`]: ./struct.DebugRenderer.html#method.first_problem
+//! [`rt_log!`]: ../../macro.rt_log.html
+use crate::event::{ContextualEventHandler, EventHandler};
+use crate::meta::Meta;
+use crate::utilities::rt_log::Level;
+use crate::{AudioHandler, AudioRenderer, ContextualAudioRenderer, Latency, Lifecycle};
+use num_traits::Float;
+use std::num::FpCategory;
+
+/// The kind of problem that has been found in a sample.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SampleProblem {
+    /// The sample is not a number.
+    NaN,
+    /// The sample is positive or negative infinity.
+    Infinite,
+    /// The sample is a denormal (subnormal) number.
+    Denormal,
+    /// The absolute value of the sample is larger than the maximum amplitude.
+    OverRange,
+}
+
+/// Where and what problem has been found.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SampleDiagnostic {
+    /// The index of the buffer, counting the calls to `render_buffer` from zero.
+    pub buffer_index: u64,
+    /// The index of the output channel.
+    pub channel: usize,
+    /// The index of the frame within the buffer.
+    pub frame: usize,
+    /// The kind of problem.
+    pub problem: SampleProblem,
+}
+
+/// Wraps a renderer and checks its outputs after every call to `render_buffer`.
+///
+/// See the [module level documentation] for more information.
+///
+/// By default, NaN, infinite and over-range samples are detected, with a maximum amplitude
+/// of `1.0`. Detection of denormals is disabled by default.
+///
+/// [module level documentation]: ./index.html
+pub struct DebugRenderer<R> {
+    inner: R,
+    buffer_index: u64,
+    max_amplitude: f64,
+    detect_denormals: bool,
+    panic_on_problem: bool,
+    first_problem: Option<SampleDiagnostic>,
+}
+
+impl<R> DebugRenderer<R> {
+    /// Wrap the given renderer.
+    pub fn new(inner: R) -> Self {
+        DebugRenderer {
+            inner,
+            buffer_index: 0,
+            max_amplitude: 1.0,
+            detect_denormals: false,
+            panic_on_problem: false,
+            first_problem: None,
+        }
+    }
+
+    /// Set the maximum amplitude: samples with a larger absolute value are reported as
+    /// [`SampleProblem::OverRange`].
+    /// Use `f64::INFINITY` to disable this check.
+    ///
+    /// [`SampleProblem::OverRange`]: ./enum.SampleProblem.html#variant.OverRange
+    pub fn with_max_amplitude(mut self, max_amplitude: f64) -> Self {
+        self.max_amplitude = max_amplitude;
+        self
+    }
+
+    /// Enable or disable the detection of denormals.
+    pub fn with_denormal_detection(mut self, detect_denormals: bool) -> Self {
+        self.detect_denormals = detect_denormals;
+        self
+    }
+
+    /// Panic when a problem is found, instead of only logging it.
+    /// This is useful in tests, so that the test fails at the first problem.
+    pub fn with_panic_on_problem(mut self, panic_on_problem: bool) -> Self {
+        self.panic_on_problem = panic_on_problem;
+        self
+    }
+
+    /// The first problem that has been found since the creation or since the last call
+    /// to [`reset`].
+    ///
+    /// [`reset`]: #method.reset
+    pub fn first_problem(&self) -> Option<SampleDiagnostic> {
+        self.first_problem
+    }
+
+    /// Forget the problem that has been found, so that the next problem is reported again.
+    /// The buffer index is not reset.
+    pub fn reset(&mut self) {
+        self.first_problem = None;
+    }
+
+    /// Get a reference to the wrapped renderer.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the wrapped renderer.
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Get the wrapped renderer.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn check_sample<S: Float>(&self, sample: S) -> Option<SampleProblem> {
+        match sample.classify() {
+            FpCategory::Nan => Some(SampleProblem::NaN),
+            FpCategory::Infinite => Some(SampleProblem::Infinite),
+            FpCategory::Subnormal if self.detect_denormals => Some(SampleProblem::Denormal),
+            _ => match sample.abs().to_f64() {
+                Some(amplitude) if amplitude > self.max_amplitude => Some(SampleProblem::OverRange),
+                _ => None,
+            },
+        }
+    }
+
+    fn check_outputs<S: Float>(&mut self, outputs: &[&mut [S]]) {
+        let buffer_index = self.buffer_index;
+        self.buffer_index += 1;
+        if self.first_problem.is_some() {
+            return;
+        }
+        // Report the problem with the lowest frame index, over all channels.
+        let mut first_problem: Option<SampleDiagnostic> = None;
+        for (channel, output) in outputs.iter().enumerate() {
+            let frames_to_check = match first_problem {
+                Some(problem) => problem.frame,
+                None => output.len(),
+            };
+            for (frame, sample) in output[..frames_to_check].iter().enumerate() {
+                if let Some(problem) = self.check_sample(*sample) {
+                    first_problem = Some(SampleDiagnostic {
+                        buffer_index,
+                        channel,
+                        frame,
+                        problem,
+                    });
+                    break;
+                }
+            }
+        }
+        if let Some(diagnostic) = first_problem {
+            self.first_problem = first_problem;
+            if self.panic_on_problem {
+                panic!(
+                    "{:?} sample in buffer {}, channel {}, frame {}",
+                    diagnostic.problem,
+                    diagnostic.buffer_index,
+                    diagnostic.channel,
+                    diagnostic.frame
+                );
+            }
+            crate::rt_log!(
+                Level::Error,
+                "{:?} sample in buffer {}, channel {}, frame {}",
+                diagnostic.problem,
+                diagnostic.buffer_index,
+                diagnostic.channel,
+                diagnostic.frame
+            );
+        }
+    }
+}
+
+impl<R, S> AudioRenderer<S> for DebugRenderer<R>
+where
+    R: AudioRenderer<S>,
+    S: Float,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        self.inner.render_buffer(inputs, outputs);
+        self.check_outputs(outputs);
+    }
+}
+
+impl<R, S, Context> ContextualAudioRenderer<S, Context> for DebugRenderer<R>
+where
+    R: ContextualAudioRenderer<S, Context>,
+    S: Float,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], context: &mut Context) {
+        self.inner.render_buffer(inputs, outputs, context);
+        self.check_outputs(outputs);
+    }
+}
+
+impl<R, E> EventHandler<E> for DebugRenderer<R>
+where
+    R: EventHandler<E>,
+{
+    fn handle_event(&mut self, event: E) {
+        self.inner.handle_event(event);
+    }
+
+    fn handle_events(&mut self, events: &[E])
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events);
+    }
+}
+
+impl<R, E, Context> ContextualEventHandler<E, Context> for DebugRenderer<R>
+where
+    R: ContextualEventHandler<E, Context>,
+{
+    fn handle_event(&mut self, event: E, context: &mut Context) {
+        self.inner.handle_event(event, context);
+    }
+
+    fn handle_events(&mut self, events: &[E], context: &mut Context)
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events, context);
+    }
+}
+
+impl<R> AudioHandler for DebugRenderer<R>
+where
+    R: AudioHandler,
+{
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.inner.set_sample_rate(sample_rate);
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.inner.set_max_buffer_size(max_buffer_size);
+    }
+}
+
+impl<R> Latency for DebugRenderer<R>
+where
+    R: Latency,
+{
+    fn latency_in_frames(&self) -> usize {
+        self.inner.latency_in_frames()
+    }
+}
+
+impl<R> Lifecycle for DebugRenderer<R>
+where
+    R: Lifecycle,
+{
+    fn on_resume(&mut self) {
+        self.inner.on_resume();
+    }
+
+    fn on_suspend(&mut self) {
+        self.inner.on_suspend();
+    }
+}
+
+impl<R> Meta for DebugRenderer<R>
+where
+    R: Meta,
+{
+    type MetaData = R::MetaData;
+
+    fn meta(&self) -> &Self::MetaData {
+        self.inner.meta()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes the given value at the given channel and frame in the given buffer.
+    struct Corrupt {
+        buffer_index: u64,
+        corruptions: Vec<(u64, usize, usize, f32)>,
+    }
+
+    impl AudioRenderer<f32> for Corrupt {
+        fn render_buffer(&mut self, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+            for &(buffer_index, channel, frame, value) in self.corruptions.iter() {
+                if buffer_index == self.buffer_index {
+                    outputs[channel][frame] = value;
+                }
+            }
+            self.buffer_index += 1;
+        }
+    }
+
+    fn render(renderer: &mut DebugRenderer<Corrupt>, number_of_buffers: usize) {
+        for _ in 0..number_of_buffers {
+            let mut left = [0.0; 8];
+            let mut right = [0.0; 8];
+            renderer.render_buffer(&[], &mut [&mut left, &mut right]);
+        }
+    }
+
+    fn corrupt(corruptions: Vec<(u64, usize, usize, f32)>) -> DebugRenderer<Corrupt> {
+        DebugRenderer::new(Corrupt {
+            buffer_index: 0,
+            corruptions,
+        })
+    }
+
+    #[test]
+    fn clean_output_has_no_problem() {
+        let mut renderer = corrupt(vec![(0, 0, 0, 0.5), (1, 1, 7, -1.0)]);
+        render(&mut renderer, 3);
+        assert_eq!(renderer.first_problem(), None);
+    }
+
+    #[test]
+    fn reports_the_first_problem_over_all_channels() {
+        let mut renderer = corrupt(vec![
+            (2, 0, 5, f32::NAN),
+            (2, 1, 3, f32::INFINITY),
+            (3, 0, 0, f32::NAN),
+        ]);
+        render(&mut renderer, 4);
+        assert_eq!(
+            renderer.first_problem(),
+            Some(SampleDiagnostic {
+                buffer_index: 2,
+                channel: 1,
+                frame: 3,
+                problem: SampleProblem::Infinite
+            })
+        );
+    }
+
+    #[test]
+    fn detects_over_range_samples() {
+        let mut renderer = corrupt(vec![(0, 0, 1, 1.5)]);
+        render(&mut renderer, 1);
+        let problem = renderer.first_problem().map(|p| p.problem);
+        assert_eq!(problem, Some(SampleProblem::OverRange));
+
+        let mut renderer = corrupt(vec![(0, 0, 1, 1.5)]).with_max_amplitude(2.0);
+        render(&mut renderer, 1);
+        assert_eq!(renderer.first_problem(), None);
+    }
+
+    #[test]
+    fn detects_denormals_only_when_enabled() {
+        let denormal = f32::MIN_POSITIVE / 2.0;
+        let mut renderer = corrupt(vec![(0, 0, 1, denormal)]);
+        render(&mut renderer, 1);
+        assert_eq!(renderer.first_problem(), None);
+
+        let mut renderer = corrupt(vec![(0, 0, 1, denormal)]).with_denormal_detection(true);
+        render(&mut renderer, 1);
+        let problem = renderer.first_problem().map(|p| p.problem);
+        assert_eq!(problem, Some(SampleProblem::Denormal));
+    }
+
+    #[test]
+    fn reset_reports_the_next_problem() {
+        let mut renderer = corrupt(vec![(0, 0, 0, f32::NAN), (1, 1, 1, f32::NAN)]);
+        render(&mut renderer, 1);
+        renderer.reset();
+        render(&mut renderer, 1);
+        let problem = renderer
+            .first_problem()
+            .map(|p| (p.buffer_index, p.channel));
+        assert_eq!(problem, Some((1, 1)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_problem_when_requested() {
+        let mut renderer = corrupt(vec![(0, 0, 0, f32::NAN)]).with_panic_on_problem(true);
+        render(&mut renderer, 1);
+    }
+}
@@ -0,0 +1,199 @@
+//! Avoid CPU spikes caused by denormal numbers.
+//!
+//! Floating point numbers that are very close to zero ("denormal" or "subnormal" numbers)
+//! are much slower to compute with on most CPUs. They typically occur in the tail of a
+//! filter or a reverb, when the signal fades out, and cause CPU spikes at unexpected moments.
+//!
+//! Most CPUs can be configured to treat denormal numbers as zero ("denormals are zero" or DAZ)
+//! and to round results that would be denormal to zero ("flush to zero" or FTZ).
+//! These settings are per thread. A [`DenormalGuard`] enables them and restores the previous
+//! settings when it is dropped.
+//!
+//! When `rsynth` is compiled with the "flush-denormals" feature, the JACK and VST back-ends
+//! use a `DenormalGuard` in each process callback, so that your audio renderer does not
+//! need to do this.
+//! See [the cargo reference] for more information on setting cargo features.
+//!
+//! Supported platforms
+//! ===================
+//! Currently, x86 and x86_64 (with SSE) and aarch64 are supported.
+//! On aarch64, only "flush to zero" is supported, which also treats denormal inputs as zero.
+//! On other platforms, a `DenormalGuard` does nothing.
+//!
+//! [`DenormalGuard`]: ./struct.DenormalGuard.html
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse"
+))]
+mod platform {
+    use std::arch::asm;
+
+    // See the Intel 64 and IA-32 Architectures Software Developer’s Manual, section "MXCSR
+    // Control and Status Register".
+    const FLUSH_TO_ZERO: u32 = 1 << 15;
+    const DENORMALS_ARE_ZERO: u32 = 1 << 6;
+
+    pub type State = u32;
+
+    fn get() -> u32 {
+        let mut control_status: u32 = 0;
+        unsafe {
+            asm!(
+                "stmxcsr [{}]",
+                in(reg) &mut control_status,
+                options(nostack, preserves_flags)
+            );
+        }
+        control_status
+    }
+
+    fn set(control_status: u32) {
+        unsafe {
+            asm!(
+                "ldmxcsr [{}]",
+                in(reg) &control_status,
+                options(nostack, readonly, preserves_flags)
+            );
+        }
+    }
+
+    pub fn enable() -> State {
+        let state = get();
+        set(state | FLUSH_TO_ZERO | DENORMALS_ARE_ZERO);
+        state
+    }
+
+    pub fn restore(state: State) {
+        set(state);
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod platform {
+    use std::arch::asm;
+
+    // See the Arm Architecture Reference Manual, register "FPCR".
+    const FLUSH_TO_ZERO: u64 = 1 << 24;
+
+    pub type State = u64;
+
+    fn get() -> u64 {
+        let control: u64;
+        unsafe {
+            asm!("mrs {}, fpcr", out(reg) control, options(nomem, nostack, preserves_flags));
+        }
+        control
+    }
+
+    fn set(control: u64) {
+        unsafe {
+            asm!("msr fpcr, {}", in(reg) control, options(nomem, nostack, preserves_flags));
+        }
+    }
+
+    pub fn enable() -> State {
+        let state = get();
+        set(state | FLUSH_TO_ZERO);
+        state
+    }
+
+    pub fn restore(state: State) {
+        set(state);
+    }
+}
+
+#[cfg(not(any(
+    all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        target_feature = "sse"
+    ),
+    target_arch = "aarch64"
+)))]
+mod platform {
+    pub type State = ();
+
+    pub fn enable() -> State {}
+
+    pub fn restore(_state: State) {}
+}
+
+/// Enables "flush to zero" and "denormals are zero" for the current thread, as long as it is
+/// not dropped.
+///
+/// See the [module level documentation] for more information.
+///
+/// Creating and dropping a `DenormalGuard` does not allocate memory and is cheap,
+/// so this can be done in each process callback.
+///
+/// Example
+/// -------
+/// ```
+/// use rsynth::utilities::denormals::DenormalGuard;
+/// # fn render_buffer(outputs: &mut [&mut [f32]]) {}
+/// # let mut outputs: [&mut [f32]; 0] = [];
+/// {
+///     let _guard = DenormalGuard::new();
+///     render_buffer(&mut outputs);
+///     // The previous settings are restored here.
+/// }
+/// ```
+///
+/// [module level documentation]: ./index.html
+pub struct DenormalGuard {
+    previous_state: platform::State,
+    // The settings are per thread, so the guard must be dropped on the thread that created it.
+    _not_send: std::marker::PhantomData<*const ()>,
+}
+
+impl DenormalGuard {
+    /// Enable "flush to zero" and "denormals are zero" for the current thread.
+    pub fn new() -> Self {
+        DenormalGuard {
+            previous_state: platform::enable(),
+            _not_send: std::marker::PhantomData,
+        }
+    }
+}
+
+impl Default for DenormalGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for DenormalGuard {
+    fn drop(&mut self) {
+        platform::restore(self.previous_state);
+    }
+}
+
+#[cfg(all(
+    test,
+    any(
+        all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse"
+        ),
+        target_arch = "aarch64"
+    )
+))]
+mod tests {
+    use super::DenormalGuard;
+    use std::hint::black_box;
+
+    fn halve(value: f32) -> f32 {
+        black_box(black_box(value) / 2.0)
+    }
+
+    #[test]
+    fn denormals_are_flushed_only_while_the_guard_is_alive() {
+        assert_ne!(halve(f32::MIN_POSITIVE), 0.0);
+        {
+            let _guard = DenormalGuard::new();
+            assert_eq!(halve(f32::MIN_POSITIVE), 0.0);
+        }
+        assert_ne!(halve(f32::MIN_POSITIVE), 0.0);
+    }
+}
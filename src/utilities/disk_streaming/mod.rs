@@ -0,0 +1,551 @@
+//! Play samples that are too large to be kept in memory by streaming them from disk.
+//!
+//! The first frames of every sample (the "attack") are preloaded in memory.
+//! When a note starts, the [`StreamingVoice`] plays the preloaded frames and in the meantime,
+//! a background I/O thread reads the rest of the sample and writes it into a lock-free ring
+//! buffer that is owned by the voice.
+//! The preloaded part should be long enough to cover the time the I/O thread needs to
+//! start reading from the disk.
+//!
+//! Usage
+//! -----
+//! Describe every sample with a [`StreamedSample`] and start the streamer with
+//! [`DiskStreamer::start`]. This returns the [`DiskStreamer`], which owns the I/O thread, and one
+//! [`StreamingVoice`] per stream. The voices can be used with the [`SimpleEventDispatcher`].
+//! Samples are read with the [`StreamSource`] trait; with the `backend-combined-hound` feature,
+//! `.wav` files can be read with the `WavFileSource`.
+//!
+//! ```no_run
+//! # #[cfg(feature = "backend-combined-hound")]
+//! # fn main() {
+//! use rsynth::utilities::disk_streaming::{
+//!     DiskStreamer, StreamedSample, StreamingSettings, WavFileSource,
+//! };
+//!
+//! let piano = WavFileSource::open("piano-c4.wav").expect("a readable wav file");
+//! let samples = vec![StreamedSample::new(Box::new(piano), 60)];
+//! let (streamer, voices) = DiskStreamer::start(samples, 16, StreamingSettings::default())
+//!     .expect("the samples can be read");
+//! // Use the voices in the audio thread; keep the streamer alive as long as they play.
+//! # }
+//! # #[cfg(not(feature = "backend-combined-hound"))]
+//! # fn main() {}
+//! ```
+//!
+//! Underruns
+//! ---------
+//! When the I/O thread cannot keep up, the voice runs out of data. It then renders silence
+//! until data is available again and continues where it left off, so the rest of the sample is
+//! delayed. The number of underruns can be queried with [`StreamingVoice::number_of_underruns`]
+//! and [`DiskStreamer::number_of_underruns`].
+//! When reading a sample fails, the voice that plays it stops.
+//!
+//! Real-time safety
+//! ----------------
+//! The voices do not allocate memory, do not lock and do not perform I/O.
+//! Every sample is converted to `f32` and has one or two channels.
+//!
+//! [`StreamingVoice`]: ./struct.StreamingVoice.html
+//! [`StreamingVoice::number_of_underruns`]: ./struct.StreamingVoice.html#method.number_of_underruns
+//! [`StreamedSample`]: ./struct.StreamedSample.html
+//! [`StreamSource`]: ./trait.StreamSource.html
+//! [`DiskStreamer`]: ./struct.DiskStreamer.html
+//! [`DiskStreamer::start`]: ./struct.DiskStreamer.html#method.start
+//! [`DiskStreamer::number_of_underruns`]: ./struct.DiskStreamer.html#method.number_of_underruns
+//! [`SimpleEventDispatcher`]: ../polyphony/simple_event_dispatching/struct.SimpleEventDispatcher.html
+use crate::utilities::ring_buffer::RingBuffer;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+mod voice;
+pub use self::voice::StreamingVoice;
+
+/// A sample that can be read from any position, e.g. a file on disk.
+pub trait StreamSource: Send {
+    /// The number of channels: 1 or 2.
+    fn number_of_channels(&self) -> usize;
+
+    /// The sample rate in frames per second.
+    fn sample_rate(&self) -> f64;
+
+    /// The length of the sample in frames.
+    fn number_of_frames(&self) -> usize;
+
+    /// Read interleaved frames, starting at the given frame, into `buffer`.
+    /// The length of `buffer` is a multiple of the number of channels.
+    /// Return the number of frames that have been read, which may be less than requested.
+    fn read_frames(&mut self, start_frame: usize, buffer: &mut [f32]) -> io::Result<usize>;
+}
+
+/// A sample together with the keys it is played for.
+pub struct StreamedSample {
+    source: Box<dyn StreamSource>,
+    root_key: u8,
+    lowest_key: u8,
+    highest_key: u8,
+}
+
+impl StreamedSample {
+    /// Create a new sample that sounds at its original pitch when `root_key` is played.
+    /// The sample is used for all keys; use [`with_key_range`] to restrict this.
+    ///
+    /// [`with_key_range`]: #method.with_key_range
+    pub fn new(source: Box<dyn StreamSource>, root_key: u8) -> Self {
+        StreamedSample {
+            source,
+            root_key,
+            lowest_key: 0,
+            highest_key: 127,
+        }
+    }
+
+    /// Only use this sample for the keys from `lowest_key` up to and including `highest_key`.
+    pub fn with_key_range(mut self, lowest_key: u8, highest_key: u8) -> Self {
+        self.lowest_key = lowest_key;
+        self.highest_key = highest_key;
+        self
+    }
+}
+
+/// The settings of a [`DiskStreamer`].
+///
+/// [`DiskStreamer`]: ./struct.DiskStreamer.html
+#[derive(Clone, Copy, Debug)]
+pub struct StreamingSettings {
+    /// The number of frames of every sample that are kept in memory.
+    pub preload_frames: usize,
+    /// The capacity of the ring buffer of every voice, in frames.
+    pub ring_buffer_frames: usize,
+    /// The maximum number of frames the I/O thread reads at once.
+    pub read_frames: usize,
+    /// How long the I/O thread sleeps when there is nothing to do.
+    pub poll_interval: Duration,
+    /// The duration of the release of a note.
+    pub release_time: Duration,
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        StreamingSettings {
+            preload_frames: 32768,
+            ring_buffer_frames: 32768,
+            read_frames: 4096,
+            poll_interval: Duration::from_millis(2),
+            release_time: Duration::from_millis(10),
+        }
+    }
+}
+
+/// The errors that can occur when starting a [`DiskStreamer`].
+///
+/// [`DiskStreamer`]: ./struct.DiskStreamer.html
+#[derive(Debug)]
+pub enum DiskStreamingError {
+    /// The sample with the given index has a number of channels that is not supported.
+    UnsupportedNumberOfChannels(usize),
+    /// The attack of the sample with the given index could not be read.
+    ReadError(usize, io::Error),
+    /// The I/O thread could not be started.
+    ThreadSpawnError(io::Error),
+}
+
+// What the voices need to know about a sample.
+struct SampleInfo {
+    number_of_channels: usize,
+    sample_rate: f64,
+    number_of_frames: usize,
+    root_key: u8,
+    lowest_key: u8,
+    highest_key: u8,
+    // The interleaved preloaded frames.
+    preload: Vec<f32>,
+}
+
+impl SampleInfo {
+    fn preloaded_frames(&self) -> usize {
+        self.preload.len() / self.number_of_channels
+    }
+}
+
+const NO_SAMPLE: usize = usize::MAX;
+
+// The state that is shared between one voice and the I/O thread.
+//
+// The voice requests a sample by storing the index of the sample and the first frame to stream
+// and then increasing `generation`. The I/O thread clears the ring buffer and stores the
+// generation in `filled_generation` before it writes the frames of the new request.
+// The voice only reads from the ring buffer when `filled_generation` equals the generation of
+// its last request, so it never reads frames that belong to an earlier request.
+struct Stream {
+    ring_buffer: RingBuffer,
+    sample_index: AtomicUsize,
+    start_frame: AtomicUsize,
+    generation: AtomicUsize,
+    filled_generation: AtomicUsize,
+    failed_generation: AtomicUsize,
+    number_of_underruns: AtomicUsize,
+}
+
+impl Stream {
+    fn new(ring_buffer_capacity: usize) -> Self {
+        Stream {
+            ring_buffer: RingBuffer::new(ring_buffer_capacity),
+            sample_index: AtomicUsize::new(NO_SAMPLE),
+            start_frame: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+            filled_generation: AtomicUsize::new(0),
+            failed_generation: AtomicUsize::new(0),
+            number_of_underruns: AtomicUsize::new(0),
+        }
+    }
+
+    // Called by the voice. Returns the generation of the request.
+    fn request(&self, sample_index: usize, start_frame: usize) -> usize {
+        self.sample_index.store(sample_index, Ordering::Relaxed);
+        self.start_frame.store(start_frame, Ordering::Relaxed);
+        let generation = self.generation.load(Ordering::Relaxed).wrapping_add(1);
+        self.generation.store(generation, Ordering::Release);
+        generation
+    }
+}
+
+// The state of the I/O thread for one stream.
+struct Feeder {
+    generation: usize,
+    sample_index: usize,
+    next_frame: usize,
+}
+
+fn feed(
+    stream: &Stream,
+    feeder: &mut Feeder,
+    sources: &mut [Box<dyn StreamSource>],
+    buffer: &mut [f32],
+) -> bool {
+    let generation = stream.generation.load(Ordering::Acquire);
+    if generation != feeder.generation {
+        feeder.generation = generation;
+        feeder.sample_index = stream.sample_index.load(Ordering::Relaxed);
+        feeder.next_frame = stream.start_frame.load(Ordering::Relaxed);
+        stream.ring_buffer.clear();
+        stream
+            .filled_generation
+            .store(generation, Ordering::Release);
+    }
+    let source = match sources.get_mut(feeder.sample_index) {
+        Some(source) => source,
+        None => return false,
+    };
+    let number_of_channels = source.number_of_channels();
+    let remaining_frames = source.number_of_frames().saturating_sub(feeder.next_frame);
+    let number_of_frames = (stream.ring_buffer.free() / number_of_channels)
+        .min(buffer.len() / number_of_channels)
+        .min(remaining_frames);
+    if number_of_frames == 0 {
+        return false;
+    }
+    let buffer = &mut buffer[..number_of_frames * number_of_channels];
+    match source.read_frames(feeder.next_frame, buffer) {
+        Ok(frames_read) if frames_read > 0 => {
+            let frames_read = frames_read.min(number_of_frames);
+            stream
+                .ring_buffer
+                .push(&buffer[..frames_read * number_of_channels]);
+            feeder.next_frame += frames_read;
+            true
+        }
+        Ok(_) | Err(_) => {
+            stream
+                .failed_generation
+                .store(generation, Ordering::Release);
+            feeder.sample_index = NO_SAMPLE;
+            false
+        }
+    }
+}
+
+/// Owns the background I/O thread that streams the samples to the [`StreamingVoice`]s.
+///
+/// Dropping the `DiskStreamer` stops the I/O thread; the voices then only play the preloaded
+/// frames.
+///
+/// [`StreamingVoice`]: ./struct.StreamingVoice.html
+pub struct DiskStreamer {
+    streams: Vec<Arc<Stream>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DiskStreamer {
+    /// Preload the attack of every sample, start the I/O thread and create `number_of_voices`
+    /// voices.
+    pub fn start(
+        samples: Vec<StreamedSample>,
+        number_of_voices: usize,
+        settings: StreamingSettings,
+    ) -> Result<(DiskStreamer, Vec<StreamingVoice>), DiskStreamingError> {
+        let mut sources = Vec::with_capacity(samples.len());
+        let mut infos = Vec::with_capacity(samples.len());
+        for (index, sample) in samples.into_iter().enumerate() {
+            let mut source = sample.source;
+            let number_of_channels = source.number_of_channels();
+            if number_of_channels != 1 && number_of_channels != 2 {
+                return Err(DiskStreamingError::UnsupportedNumberOfChannels(index));
+            }
+            let number_of_frames = source.number_of_frames();
+            let mut preload =
+                vec![0.0; settings.preload_frames.min(number_of_frames) * number_of_channels];
+            let mut frames_read = 0;
+            while frames_read * number_of_channels < preload.len() {
+                let n = source
+                    .read_frames(
+                        frames_read,
+                        &mut preload[frames_read * number_of_channels..],
+                    )
+                    .map_err(|e| DiskStreamingError::ReadError(index, e))?;
+                if n == 0 {
+                    break;
+                }
+                frames_read += n;
+            }
+            preload.truncate(frames_read * number_of_channels);
+            infos.push(SampleInfo {
+                number_of_channels,
+                sample_rate: source.sample_rate(),
+                number_of_frames,
+                root_key: sample.root_key,
+                lowest_key: sample.lowest_key,
+                highest_key: sample.highest_key,
+                preload,
+            });
+            sources.push(source);
+        }
+        let infos = Arc::new(infos);
+
+        // Two channels per frame.
+        let ring_buffer_capacity = settings.ring_buffer_frames * 2;
+        let streams: Vec<_> = (0..number_of_voices)
+            .map(|_| Arc::new(Stream::new(ring_buffer_capacity)))
+            .collect();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread_streams = streams.clone();
+        let thread_stop = stop.clone();
+        let read_frames = settings.read_frames.max(1);
+        let poll_interval = settings.poll_interval;
+        let thread = thread::Builder::new()
+            .name("disk_streaming".to_string())
+            .spawn(move || {
+                let mut sources = sources;
+                let mut buffer = vec![0.0; read_frames * 2];
+                let mut feeders: Vec<_> = thread_streams
+                    .iter()
+                    .map(|_| Feeder {
+                        generation: 0,
+                        sample_index: NO_SAMPLE,
+                        next_frame: 0,
+                    })
+                    .collect();
+                while !thread_stop.load(Ordering::Acquire) {
+                    let mut busy = false;
+                    for (stream, feeder) in thread_streams.iter().zip(feeders.iter_mut()) {
+                        busy |= feed(stream, feeder, &mut sources, &mut buffer);
+                    }
+                    if !busy {
+                        thread::sleep(poll_interval);
+                    }
+                }
+            })
+            .map_err(DiskStreamingError::ThreadSpawnError)?;
+
+        let voices = streams
+            .iter()
+            .map(|stream| StreamingVoice::new(infos.clone(), stream.clone(), settings.release_time))
+            .collect();
+        let streamer = DiskStreamer {
+            streams,
+            stop,
+            thread: Some(thread),
+        };
+        Ok((streamer, voices))
+    }
+
+    /// The total number of underruns of all voices.
+    pub fn number_of_underruns(&self) -> usize {
+        self.streams
+            .iter()
+            .map(|stream| stream.number_of_underruns.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+impl Drop for DiskStreamer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(feature = "backend-combined-hound")]
+mod wav {
+    use super::StreamSource;
+    use hound::{SampleFormat, WavReader};
+    use std::fs::File;
+    use std::io::{self, BufReader};
+    use std::path::Path;
+
+    /// A [`StreamSource`] that reads a `.wav` file.
+    ///
+    /// [`StreamSource`]: ./trait.StreamSource.html
+    pub struct WavFileSource {
+        reader: WavReader<BufReader<File>>,
+        // The frame at which the reader is positioned.
+        position: usize,
+    }
+
+    impl WavFileSource {
+        /// Open the file with the given path.
+        pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, hound::Error> {
+            Ok(WavFileSource {
+                reader: WavReader::open(path)?,
+                position: 0,
+            })
+        }
+    }
+
+    fn to_io_error(error: hound::Error) -> io::Error {
+        match error {
+            hound::Error::IoError(error) => error,
+            error => io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", error)),
+        }
+    }
+
+    impl StreamSource for WavFileSource {
+        fn number_of_channels(&self) -> usize {
+            self.reader.spec().channels as usize
+        }
+
+        fn sample_rate(&self) -> f64 {
+            self.reader.spec().sample_rate as f64
+        }
+
+        fn number_of_frames(&self) -> usize {
+            self.reader.duration() as usize
+        }
+
+        fn read_frames(&mut self, start_frame: usize, buffer: &mut [f32]) -> io::Result<usize> {
+            if start_frame != self.position {
+                self.reader.seek(start_frame as u32)?;
+                self.position = start_frame;
+            }
+            let spec = self.reader.spec();
+            let number_of_channels = spec.channels as usize;
+            let mut samples_read = 0;
+            match spec.sample_format {
+                SampleFormat::Float => {
+                    for (destination, sample) in buffer.iter_mut().zip(self.reader.samples::<f32>())
+                    {
+                        *destination = sample.map_err(to_io_error)?;
+                        samples_read += 1;
+                    }
+                }
+                SampleFormat::Int => {
+                    let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                    for (destination, sample) in buffer.iter_mut().zip(self.reader.samples::<i32>())
+                    {
+                        *destination = sample.map_err(to_io_error)? as f32 * scale;
+                        samples_read += 1;
+                    }
+                }
+            }
+            let frames_read = samples_read / number_of_channels;
+            self.position += frames_read;
+            Ok(frames_read)
+        }
+    }
+}
+#[cfg(feature = "backend-combined-hound")]
+pub use self::wav::WavFileSource;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // A mono sample in memory whose value at every frame is the index of the frame.
+    pub struct RampSource {
+        pub number_of_frames: usize,
+        pub fail: bool,
+    }
+
+    impl StreamSource for RampSource {
+        fn number_of_channels(&self) -> usize {
+            1
+        }
+
+        fn sample_rate(&self) -> f64 {
+            100.0
+        }
+
+        fn number_of_frames(&self) -> usize {
+            self.number_of_frames
+        }
+
+        fn read_frames(&mut self, start_frame: usize, buffer: &mut [f32]) -> io::Result<usize> {
+            if self.fail && start_frame > 0 {
+                return Err(io::Error::other("test"));
+            }
+            let end = (start_frame + buffer.len()).min(self.number_of_frames);
+            for (frame, sample) in (start_frame..end).zip(buffer.iter_mut()) {
+                *sample = frame as f32;
+            }
+            Ok(end.saturating_sub(start_frame))
+        }
+    }
+
+    #[test]
+    fn preloads_the_attack() {
+        let samples = vec![StreamedSample::new(
+            Box::new(RampSource {
+                number_of_frames: 10,
+                fail: false,
+            }),
+            60,
+        )];
+        let settings = StreamingSettings {
+            preload_frames: 4,
+            ..StreamingSettings::default()
+        };
+        let (_streamer, voices) = DiskStreamer::start(samples, 2, settings).expect("no error");
+        assert_eq!(voices.len(), 2);
+        assert_eq!(voices[0].samples[0].preload, vec![0.0, 1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn rejects_unsupported_number_of_channels() {
+        struct SurroundSource;
+        impl StreamSource for SurroundSource {
+            fn number_of_channels(&self) -> usize {
+                6
+            }
+            fn sample_rate(&self) -> f64 {
+                44100.0
+            }
+            fn number_of_frames(&self) -> usize {
+                0
+            }
+            fn read_frames(&mut self, _: usize, _: &mut [f32]) -> io::Result<usize> {
+                Ok(0)
+            }
+        }
+        let samples = vec![StreamedSample::new(Box::new(SurroundSource), 60)];
+        match DiskStreamer::start(samples, 1, StreamingSettings::default()) {
+            Err(DiskStreamingError::UnsupportedNumberOfChannels(0)) => {}
+            _ => panic!("Expected an error."),
+        }
+    }
+}
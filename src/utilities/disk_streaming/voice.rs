@@ -0,0 +1,377 @@
+use super::{SampleInfo, Stream, NO_SAMPLE};
+use crate::event::{EventHandler, RawMidiEvent, Timed};
+use crate::utilities::polyphony::{
+    simple_event_dispatching::SimpleVoiceState, ToneIdentifier, Voice,
+};
+use crate::{AudioHandler, AudioRenderer};
+use asprim::AsPrim;
+use midi_consts::channel_event::control_change::{ALL_NOTES_OFF, ALL_SOUND_OFF};
+use midi_consts::channel_event::*;
+use num_traits::Float;
+use std::f64::consts::FRAC_1_SQRT_2;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+enum Fetched {
+    Frame([f32; 2]),
+    Underrun,
+    Failed,
+}
+
+// The sample that is being played.
+struct Playback {
+    sample_index: usize,
+    generation: usize,
+    // The index of the next frame to fetch. `frames` contains the two frames before it.
+    next_frame: usize,
+    frames: [[f32; 2]; 2],
+    // The position between `frames[0]` and `frames[1]`.
+    fraction: f64,
+    increment: f64,
+    gain: f64,
+    release_gain: f64,
+    is_released: bool,
+    is_in_underrun: bool,
+}
+
+/// A voice that plays samples that are streamed from disk by a [`DiskStreamer`].
+///
+/// The voice handles note on and note off, "all notes off" and "all sound off".
+/// The MIDI channel is ignored.
+/// It implements `Voice<SimpleVoiceState<ToneIdentifier>>`, so that it can be used with the
+/// [`SimpleEventDispatcher`] and the [`RawMidiEventToneIdentifierDispatchClassifier`].
+/// The voice ignores the timing of the events; use an [`EventQueue`] for sample-accurate timing.
+///
+/// When rendering, the voice adds its output to the existing content of the outputs:
+/// to the first two outputs in stereo, or to a single output in mono.
+///
+/// Real-time safety
+/// ----------------
+/// Handling events and rendering do not allocate memory, do not lock and do not perform I/O.
+///
+/// [`DiskStreamer`]: ./struct.DiskStreamer.html
+/// [`SimpleEventDispatcher`]: ../polyphony/simple_event_dispatching/struct.SimpleEventDispatcher.html
+/// [`RawMidiEventToneIdentifierDispatchClassifier`]: ../polyphony/struct.RawMidiEventToneIdentifierDispatchClassifier.html
+/// [`EventQueue`]: ../../event/event_queue/struct.EventQueue.html
+pub struct StreamingVoice {
+    pub(super) samples: Arc<Vec<SampleInfo>>,
+    stream: Arc<Stream>,
+    sample_rate: f64,
+    release_time: f64,
+    state: SimpleVoiceState<ToneIdentifier>,
+    playback: Option<Playback>,
+}
+
+impl StreamingVoice {
+    pub(super) fn new(
+        samples: Arc<Vec<SampleInfo>>,
+        stream: Arc<Stream>,
+        release_time: Duration,
+    ) -> Self {
+        StreamingVoice {
+            samples,
+            stream,
+            sample_rate: 44100.0,
+            release_time: release_time.as_secs_f64(),
+            state: SimpleVoiceState::Idle,
+            playback: None,
+        }
+    }
+
+    /// Start playing the given key with the first sample whose key range contains the key.
+    pub fn note_on(&mut self, key: u8, velocity: u8) {
+        let samples = &self.samples;
+        let sample_index = match samples
+            .iter()
+            .position(|sample| sample.lowest_key <= key && key <= sample.highest_key)
+        {
+            Some(index) => index,
+            None => {
+                self.stop();
+                return;
+            }
+        };
+        let sample = &samples[sample_index];
+        let preloaded_frames = sample.preloaded_frames();
+        let generation = if sample.number_of_frames > preloaded_frames {
+            self.stream.request(sample_index, preloaded_frames)
+        } else {
+            self.stream.request(NO_SAMPLE, 0)
+        };
+        let semitones = key as f64 - sample.root_key as f64;
+        self.playback = Some(Playback {
+            sample_index,
+            generation,
+            next_frame: 0,
+            frames: [[0.0; 2]; 2],
+            // Fetch two frames before rendering the first one.
+            fraction: 2.0,
+            increment: 2.0_f64.powf(semitones / 12.0) * sample.sample_rate / self.sample_rate,
+            // An approximation of the default "velocity to attenuation" curve of MIDI.
+            gain: (velocity as f64 / 127.0).powi(2),
+            release_gain: 1.0,
+            is_released: false,
+            is_in_underrun: false,
+        });
+        self.state = SimpleVoiceState::Active(ToneIdentifier(key));
+    }
+
+    /// Release the key that is being played: the sample fades out during the release time.
+    pub fn note_off(&mut self) {
+        if let SimpleVoiceState::Active(identifier) = self.state {
+            if let Some(playback) = self.playback.as_mut() {
+                playback.is_released = true;
+            }
+            self.state = SimpleVoiceState::Releasing(identifier);
+        }
+    }
+
+    /// Stop playing immediately, without release.
+    pub fn stop(&mut self) {
+        self.playback = None;
+        if self.state != SimpleVoiceState::Idle {
+            self.stream.request(NO_SAMPLE, 0);
+        }
+        self.state = SimpleVoiceState::Idle;
+    }
+
+    /// The number of times this voice ran out of streamed data.
+    pub fn number_of_underruns(&self) -> usize {
+        self.stream.number_of_underruns.load(Ordering::Relaxed)
+    }
+
+    fn fetch(&self, playback: &Playback) -> Fetched {
+        let sample = &self.samples[playback.sample_index];
+        let frame = playback.next_frame;
+        let number_of_channels = sample.number_of_channels;
+        let mut result = [0.0; 2];
+        if frame < sample.preloaded_frames() {
+            let start = frame * number_of_channels;
+            result[..number_of_channels]
+                .copy_from_slice(&sample.preload[start..start + number_of_channels]);
+        } else if frame < sample.number_of_frames {
+            let stream = &self.stream;
+            if stream.failed_generation.load(Ordering::Acquire) == playback.generation {
+                return Fetched::Failed;
+            }
+            if stream.filled_generation.load(Ordering::Acquire) != playback.generation
+                || stream.ring_buffer.available() < number_of_channels
+            {
+                return Fetched::Underrun;
+            }
+            stream.ring_buffer.pop(&mut result[..number_of_channels]);
+        }
+        if number_of_channels == 1 {
+            result[1] = result[0];
+        }
+        Fetched::Frame(result)
+    }
+
+    // Return `None` when the sample has finished playing.
+    fn next_frame(&mut self) -> Option<[f64; 2]> {
+        let release_step = 1.0 / (self.release_time * self.sample_rate).max(1.0);
+        let mut playback = self.playback.take()?;
+        while playback.fraction >= 1.0 {
+            match self.fetch(&playback) {
+                Fetched::Frame(frame) => {
+                    playback.frames = [playback.frames[1], frame];
+                    playback.next_frame += 1;
+                    playback.fraction -= 1.0;
+                    playback.is_in_underrun = false;
+                }
+                Fetched::Underrun => {
+                    if !playback.is_in_underrun {
+                        playback.is_in_underrun = true;
+                        self.stream
+                            .number_of_underruns
+                            .fetch_add(1, Ordering::Relaxed);
+                    }
+                    self.playback = Some(playback);
+                    return Some([0.0; 2]);
+                }
+                Fetched::Failed => return None,
+            }
+        }
+        let sample = &self.samples[playback.sample_index];
+        if playback.next_frame - 2 >= sample.number_of_frames {
+            return None;
+        }
+        if playback.is_released {
+            playback.release_gain -= release_step;
+            if playback.release_gain <= 0.0 {
+                return None;
+            }
+        }
+        let gain = playback.gain * playback.release_gain;
+        let [first, second] = playback.frames;
+        let fraction = playback.fraction;
+        let mut result = [0.0; 2];
+        for (channel, value) in result.iter_mut().enumerate() {
+            let current = first[channel] as f64;
+            *value = (current + (second[channel] as f64 - current) * fraction) * gain;
+        }
+        playback.fraction += playback.increment;
+        self.playback = Some(playback);
+        Some(result)
+    }
+}
+
+impl Voice<SimpleVoiceState<ToneIdentifier>> for StreamingVoice {
+    fn state(&self) -> SimpleVoiceState<ToneIdentifier> {
+        self.state
+    }
+}
+
+impl EventHandler<Timed<RawMidiEvent>> for StreamingVoice {
+    fn handle_event(&mut self, timed: Timed<RawMidiEvent>) {
+        let data = timed.event.data();
+        match data[0] & EVENT_TYPE_MASK {
+            NOTE_ON if data[2] > 0 => self.note_on(data[1], data[2]),
+            NOTE_ON | NOTE_OFF => self.note_off(),
+            CONTROL_CHANGE => match data[1] {
+                ALL_NOTES_OFF => self.note_off(),
+                ALL_SOUND_OFF => self.stop(),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+impl AudioHandler for StreamingVoice {
+    /// Set the sample rate. This only affects notes that are started afterwards.
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+    }
+}
+
+impl<S> AudioRenderer<S> for StreamingVoice
+where
+    S: AsPrim + Float,
+{
+    fn render_buffer(&mut self, _inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        if self.state == SimpleVoiceState::Idle {
+            return;
+        }
+        let number_of_frames = outputs.first().map(|output| output.len()).unwrap_or(0);
+        for frame in 0..number_of_frames {
+            let [left_value, right_value] = match self.next_frame() {
+                Some(values) => values,
+                None => {
+                    self.stop();
+                    return;
+                }
+            };
+            match outputs {
+                [] => {}
+                [mono] => {
+                    mono[frame] = mono[frame] + ((left_value + right_value) * FRAC_1_SQRT_2).as_();
+                }
+                [left, right, ..] => {
+                    left[frame] = left[frame] + left_value.as_();
+                    right[frame] = right[frame] + right_value.as_();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::RampSource;
+    use super::super::{DiskStreamer, StreamedSample, StreamingSettings};
+    use super::*;
+    use std::thread;
+    use std::time::Instant;
+
+    fn start(number_of_frames: usize, fail: bool) -> (DiskStreamer, StreamingVoice) {
+        let samples = vec![StreamedSample::new(
+            Box::new(RampSource {
+                number_of_frames,
+                fail,
+            }),
+            60,
+        )];
+        let settings = StreamingSettings {
+            preload_frames: 4,
+            ring_buffer_frames: 256,
+            read_frames: 16,
+            poll_interval: Duration::from_millis(1),
+            release_time: Duration::from_millis(10),
+        };
+        let (streamer, mut voices) = DiskStreamer::start(samples, 1, settings).expect("no error");
+        let mut voice = voices.pop().expect("one voice");
+        voice.set_sample_rate(100.0);
+        (streamer, voice)
+    }
+
+    fn wait_until<F: Fn() -> bool>(condition: F) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < Duration::from_secs(10), "Timeout.");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn render(voice: &mut StreamingVoice, number_of_frames: usize) -> Vec<f32> {
+        let mut left = vec![0.0; number_of_frames];
+        let mut right = vec![0.0; number_of_frames];
+        voice.render_buffer(&[], &mut [&mut left, &mut right]);
+        assert_eq!(left, right);
+        left
+    }
+
+    fn note(status: u8, key: u8, velocity: u8) -> Timed<RawMidiEvent> {
+        Timed::new(0, RawMidiEvent::new(&[status, key, velocity]))
+    }
+
+    #[test]
+    fn plays_the_preloaded_and_the_streamed_frames() {
+        let (_streamer, mut voice) = start(100, false);
+        voice.handle_event(note(NOTE_ON, 60, 127));
+        let stream = voice.stream.clone();
+        wait_until(|| stream.ring_buffer.available() >= 96);
+        let output = render(&mut voice, 120);
+        let expected: Vec<_> = (0..100).map(|frame| frame as f32).collect();
+        assert_eq!(&output[..100], &expected[..]);
+        assert!(output[100..].iter().all(|s| *s == 0.0));
+        assert_eq!(voice.number_of_underruns(), 0);
+        assert!(voice.state() == SimpleVoiceState::Idle);
+    }
+
+    #[test]
+    fn underrun_renders_silence_and_continues_later() {
+        let (streamer, mut voice) = start(100, false);
+        // Stop the I/O thread, so that only the preloaded frames are available.
+        drop(streamer);
+        voice.handle_event(note(NOTE_ON, 60, 127));
+        let output = render(&mut voice, 8);
+        assert_eq!(&output[..3], &[0.0, 1.0, 2.0]);
+        assert!(output[3..].iter().all(|s| *s == 0.0));
+        assert_eq!(voice.number_of_underruns(), 1);
+        assert!(voice.state() == SimpleVoiceState::Active(ToneIdentifier(60)));
+    }
+
+    #[test]
+    fn read_error_stops_the_voice() {
+        let (_streamer, mut voice) = start(100, true);
+        voice.handle_event(note(NOTE_ON, 60, 127));
+        let stream = voice.stream.clone();
+        wait_until(|| stream.failed_generation.load(Ordering::Acquire) != 0);
+        render(&mut voice, 8);
+        assert!(voice.state() == SimpleVoiceState::Idle);
+    }
+
+    #[test]
+    fn note_off_fades_out() {
+        let (_streamer, mut voice) = start(4, false);
+        voice.handle_event(note(NOTE_ON, 60, 127));
+        voice.handle_event(note(NOTE_OFF, 60, 0));
+        assert!(voice.state() == SimpleVoiceState::Releasing(ToneIdentifier(60)));
+        // The release time is 10 ms, which is one frame at 100 Hz.
+        let output = render(&mut voice, 2);
+        assert_eq!(output, vec![0.0, 0.0]);
+        assert!(voice.state() == SimpleVoiceState::Idle);
+    }
+}
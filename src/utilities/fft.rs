@@ -0,0 +1,67 @@
+// A radix-2 fast Fourier transform, used to band-limit the frames of a wavetable, by the
+// spectrum analyzer and by the short-time Fourier transform of the `spectral` module.
+
+// Transform in place. The length of `real` and `imaginary` must be the same power of two.
+// The inverse transform is not scaled.
+pub(crate) fn fft(real: &mut [f64], imaginary: &mut [f64], inverse: bool) {
+    let length = real.len();
+    debug_assert_eq!(length, imaginary.len());
+    debug_assert!(length.is_power_of_two());
+    // Bit-reversal permutation.
+    let mut j = 0;
+    for i in 1..length {
+        let mut bit = length >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            real.swap(i, j);
+            imaginary.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut size = 2;
+    while size <= length {
+        let angle = sign * 2.0 * std::f64::consts::PI / size as f64;
+        let (step_imaginary, step_real) = angle.sin_cos();
+        for start in (0..length).step_by(size) {
+            let mut twiddle_real = 1.0;
+            let mut twiddle_imaginary = 0.0;
+            for k in 0..size / 2 {
+                let even = start + k;
+                let odd = even + size / 2;
+                let odd_real = real[odd] * twiddle_real - imaginary[odd] * twiddle_imaginary;
+                let odd_imaginary = real[odd] * twiddle_imaginary + imaginary[odd] * twiddle_real;
+                real[odd] = real[even] - odd_real;
+                imaginary[odd] = imaginary[even] - odd_imaginary;
+                real[even] += odd_real;
+                imaginary[even] += odd_imaginary;
+                let next_real = twiddle_real * step_real - twiddle_imaginary * step_imaginary;
+                twiddle_imaginary = twiddle_real * step_imaginary + twiddle_imaginary * step_real;
+                twiddle_real = next_real;
+            }
+        }
+        size <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forward_and_inverse_transform_round_trip() {
+        let input = [1.0, -2.0, 0.5, 3.0, 0.0, 0.25, -1.0, 2.0];
+        let mut real = input.to_vec();
+        let mut imaginary = vec![0.0; input.len()];
+        fft(&mut real, &mut imaginary, false);
+        // The first bin is the sum of the input.
+        assert!((real[0] - input.iter().sum::<f64>()).abs() < 1e-12);
+        fft(&mut real, &mut imaginary, true);
+        for (expected, actual) in input.iter().zip(real.iter()) {
+            assert!((expected - actual / input.len() as f64).abs() < 1e-12);
+        }
+    }
+}
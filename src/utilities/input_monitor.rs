@@ -0,0 +1,521 @@
+//! Mix the audio input into the output, e.g. to audition an effect plugin in a standalone
+//! application.
+//!
+//! An effect plugin that is run standalone only processes the live input; to compare the
+//! processed signal with the dry signal, or to hear the input of an instrument while playing
+//! along, the input needs to be sent to the outputs as well. Wrap the plugin in an
+//! [`InputMonitor`] to add the input, multiplied by a gain, to the outputs of the plugin.
+//! Input `i` is added to output `i`; a mono input is added to all outputs.
+//! Changes of the gain and switching monitoring on or off are ramped over one buffer,
+//! so that they do not click.
+//!
+//! The `InputMonitor` is moved to the audio thread, so it is switched on and off and its
+//! gain is changed with a [`MonitorControl`], which can be cloned and used from any thread.
+//!
+//! The round-trip latency that the player hears depends on the latency of the audio device:
+//! the back-end sets it with [`MonitorControl::set_device_latency`] (the [`cpal`] back-end
+//! does this automatically) and the application displays the [`MonitorLatency`], e.g. in its
+//! user interface.
+//!
+//! ```
+//! use rsynth::utilities::input_monitor::InputMonitor;
+//! use rsynth::utilities::trivial_renderers::Silence;
+//! use rsynth::{AudioHandler, AudioRenderer};
+//!
+//! let mut monitor = InputMonitor::new(Silence::new(1));
+//! let control = monitor.control();
+//! monitor.set_sample_rate(48000.0);
+//! control.set_enabled(true);
+//! control.set_device_latency(128, 128);
+//! assert_eq!(control.latency().dry_in_frames, 256);
+//!
+//! let input = [1.0; 4];
+//! let mut output = [0.0; 4];
+//! // The first buffer ramps from silence to the full gain.
+//! monitor.render_buffer(&[&input[..]], &mut [&mut output[..]]);
+//! monitor.render_buffer(&[&input[..]], &mut [&mut output[..]]);
+//! assert_eq!(output, [1.0; 4]);
+//! ```
+//!
+//! [`InputMonitor`]: ./struct.InputMonitor.html
+//! [`MonitorControl`]: ./struct.MonitorControl.html
+//! [`MonitorControl::set_device_latency`]: ./struct.MonitorControl.html#method.set_device_latency
+//! [`MonitorLatency`]: ./struct.MonitorLatency.html
+//! [`cpal`]: ../../backend/cpal/index.html
+use crate::event::{ContextualEventHandler, EventHandler};
+use crate::meta::Meta;
+use crate::{AudioHandler, AudioRenderer, ContextualAudioRenderer, Latency, Lifecycle};
+use asprim::AsPrim;
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The latency that the player hears when monitoring.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MonitorLatency {
+    /// The latency of the monitored input: the input latency plus the output latency of
+    /// the audio device.
+    pub dry_in_frames: usize,
+    /// The latency of the processed signal: the latency of the dry signal plus the latency
+    /// of the plugin.
+    pub processed_in_frames: usize,
+    /// The sample rate that is used to convert frames to milliseconds.
+    pub sample_rate: f64,
+}
+
+impl MonitorLatency {
+    /// The latency of the monitored input in milliseconds.
+    pub fn dry_in_milliseconds(&self) -> f64 {
+        self.dry_in_frames as f64 * 1000.0 / self.sample_rate
+    }
+
+    /// The latency of the processed signal in milliseconds.
+    pub fn processed_in_milliseconds(&self) -> f64 {
+        self.processed_in_frames as f64 * 1000.0 / self.sample_rate
+    }
+}
+
+impl Display for MonitorLatency {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "input: {:.1} ms, processed: {:.1} ms",
+            self.dry_in_milliseconds(),
+            self.processed_in_milliseconds()
+        )
+    }
+}
+
+// The settings and the latencies, shared by the `InputMonitor` and its `MonitorControl`s.
+struct Shared {
+    enabled: AtomicBool,
+    // The bits of the `f64` gain.
+    gain: AtomicU64,
+    input_latency_in_frames: AtomicUsize,
+    output_latency_in_frames: AtomicUsize,
+    plugin_latency_in_frames: AtomicUsize,
+    // The bits of the `f64` sample rate.
+    sample_rate: AtomicU64,
+}
+
+/// Switches the monitoring of an [`InputMonitor`] on and off, changes its gain and reads
+/// its latency, e.g. from the user interface, while the `InputMonitor` is used in the audio
+/// thread. Created with [`InputMonitor::control`].
+///
+/// [`InputMonitor`]: ./struct.InputMonitor.html
+/// [`InputMonitor::control`]: ./struct.InputMonitor.html#method.control
+#[derive(Clone)]
+pub struct MonitorControl {
+    shared: Arc<Shared>,
+}
+
+impl MonitorControl {
+    fn new() -> Self {
+        MonitorControl {
+            shared: Arc::new(Shared {
+                enabled: AtomicBool::new(false),
+                gain: AtomicU64::new(1.0_f64.to_bits()),
+                input_latency_in_frames: AtomicUsize::new(0),
+                output_latency_in_frames: AtomicUsize::new(0),
+                plugin_latency_in_frames: AtomicUsize::new(0),
+                sample_rate: AtomicU64::new(44100.0_f64.to_bits()),
+            }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.shared.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Switch monitoring on or off.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.shared.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// The linear gain of the monitored input.
+    pub fn gain(&self) -> f64 {
+        f64::from_bits(self.shared.gain.load(Ordering::Relaxed))
+    }
+
+    /// Set the linear gain of the monitored input.
+    pub fn set_gain(&self, gain: f64) {
+        self.shared.gain.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Set the input and output latency of the audio device, as reported by the back-end.
+    pub fn set_device_latency(&self, input_in_frames: usize, output_in_frames: usize) {
+        self.shared
+            .input_latency_in_frames
+            .store(input_in_frames, Ordering::Relaxed);
+        self.shared
+            .output_latency_in_frames
+            .store(output_in_frames, Ordering::Relaxed);
+    }
+
+    /// The round-trip latency of the monitored input and of the processed signal.
+    /// The latency of the plugin is only included when the `InputMonitor` has been created
+    /// with [`InputMonitor::with_latency`].
+    ///
+    /// [`InputMonitor::with_latency`]: ./struct.InputMonitor.html#method.with_latency
+    pub fn latency(&self) -> MonitorLatency {
+        let dry_in_frames = self.shared.input_latency_in_frames.load(Ordering::Relaxed)
+            + self.shared.output_latency_in_frames.load(Ordering::Relaxed);
+        MonitorLatency {
+            dry_in_frames,
+            processed_in_frames: dry_in_frames
+                + self.shared.plugin_latency_in_frames.load(Ordering::Relaxed),
+            sample_rate: f64::from_bits(self.shared.sample_rate.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+fn no_latency<R>(_: &R) -> usize {
+    0
+}
+
+fn latency_in_frames<R: Latency>(renderer: &R) -> usize {
+    renderer.latency_in_frames()
+}
+
+/// Wraps a plugin and adds its audio input to its outputs.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct InputMonitor<R> {
+    inner: R,
+    control: MonitorControl,
+    // The gain at the end of the previous buffer.
+    current_gain: f64,
+    // Gets the latency of the plugin, which is passed to the `MonitorControl`.
+    plugin_latency: fn(&R) -> usize,
+}
+
+impl<R> InputMonitor<R> {
+    /// Wrap `inner`. Monitoring is disabled and the gain is 1.
+    pub fn new(inner: R) -> Self {
+        InputMonitor {
+            inner,
+            control: MonitorControl::new(),
+            current_gain: 0.0,
+            plugin_latency: no_latency::<R>,
+        }
+    }
+
+    /// Wrap `inner` and include its latency in the latency of the processed signal that is
+    /// reported by the [`MonitorControl`]. Monitoring is disabled and the gain is 1.
+    ///
+    /// [`MonitorControl`]: ./struct.MonitorControl.html
+    pub fn with_latency(inner: R) -> Self
+    where
+        R: Latency,
+    {
+        let monitor = InputMonitor {
+            plugin_latency: latency_in_frames::<R>,
+            ..InputMonitor::new(inner)
+        };
+        monitor.update_plugin_latency();
+        monitor
+    }
+
+    /// A handle to switch monitoring on and off, to change the gain and to read the latency
+    /// from another thread.
+    pub fn control(&self) -> MonitorControl {
+        self.control.clone()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.control.is_enabled()
+    }
+
+    /// Switch monitoring on or off.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.control.set_enabled(enabled);
+    }
+
+    /// The linear gain of the monitored input.
+    pub fn gain(&self) -> f64 {
+        self.control.gain()
+    }
+
+    /// Set the linear gain of the monitored input.
+    pub fn set_gain(&mut self, gain: f64) {
+        self.control.set_gain(gain);
+    }
+
+    /// Set the input and output latency of the audio device, as reported by the back-end.
+    pub fn set_device_latency(&mut self, input_in_frames: usize, output_in_frames: usize) {
+        self.control
+            .set_device_latency(input_in_frames, output_in_frames);
+    }
+
+    /// The round-trip latency of the monitored input and of the processed signal.
+    pub fn latency(&self) -> MonitorLatency
+    where
+        R: Latency,
+    {
+        let latency = self.control.latency();
+        MonitorLatency {
+            processed_in_frames: latency.dry_in_frames + self.inner.latency_in_frames(),
+            ..latency
+        }
+    }
+
+    fn update_plugin_latency(&self) {
+        self.control
+            .shared
+            .plugin_latency_in_frames
+            .store((self.plugin_latency)(&self.inner), Ordering::Relaxed);
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn mix_input<S: AsPrim + Copy>(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        self.update_plugin_latency();
+        let start_gain = self.current_gain;
+        let end_gain = if self.control.is_enabled() {
+            self.control.gain()
+        } else {
+            0.0
+        };
+        self.current_gain = end_gain;
+        if (start_gain == 0.0 && end_gain == 0.0) || inputs.is_empty() {
+            return;
+        }
+        for (index, output) in outputs.iter_mut().enumerate() {
+            let input = if inputs.len() == 1 {
+                inputs[0]
+            } else {
+                match inputs.get(index) {
+                    Some(input) => *input,
+                    None => continue,
+                }
+            };
+            let number_of_frames = output.len();
+            for (frame, (sample, input_sample)) in output.iter_mut().zip(input.iter()).enumerate() {
+                let gain = start_gain
+                    + (end_gain - start_gain) * (frame + 1) as f64 / number_of_frames as f64;
+                let mixed: f64 = sample.as_::<f64>() + gain * input_sample.as_::<f64>();
+                *sample = mixed.as_();
+            }
+        }
+    }
+}
+
+impl<R> Latency for InputMonitor<R>
+where
+    R: Latency,
+{
+    fn latency_in_frames(&self) -> usize {
+        self.inner.latency_in_frames()
+    }
+}
+
+impl<R> Lifecycle for InputMonitor<R>
+where
+    R: Lifecycle,
+{
+    fn on_resume(&mut self) {
+        self.inner.on_resume();
+    }
+
+    fn on_suspend(&mut self) {
+        self.inner.on_suspend();
+    }
+}
+
+impl<R, S> AudioRenderer<S> for InputMonitor<R>
+where
+    R: AudioRenderer<S>,
+    S: AsPrim + Copy,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        self.inner.render_buffer(inputs, outputs);
+        self.mix_input(inputs, outputs);
+    }
+}
+
+impl<R, S, Context> ContextualAudioRenderer<S, Context> for InputMonitor<R>
+where
+    R: ContextualAudioRenderer<S, Context>,
+    S: AsPrim + Copy,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], context: &mut Context) {
+        self.inner.render_buffer(inputs, outputs, context);
+        self.mix_input(inputs, outputs);
+    }
+}
+
+impl<R, E> EventHandler<E> for InputMonitor<R>
+where
+    R: EventHandler<E>,
+{
+    fn handle_event(&mut self, event: E) {
+        self.inner.handle_event(event);
+    }
+
+    fn handle_events(&mut self, events: &[E])
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events);
+    }
+}
+
+impl<R, E, Context> ContextualEventHandler<E, Context> for InputMonitor<R>
+where
+    R: ContextualEventHandler<E, Context>,
+{
+    fn handle_event(&mut self, event: E, context: &mut Context) {
+        self.inner.handle_event(event, context);
+    }
+
+    fn handle_events(&mut self, events: &[E], context: &mut Context)
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events, context);
+    }
+}
+
+impl<R> AudioHandler for InputMonitor<R>
+where
+    R: AudioHandler,
+{
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.control
+            .shared
+            .sample_rate
+            .store(sample_rate.to_bits(), Ordering::Relaxed);
+        self.inner.set_sample_rate(sample_rate);
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.inner.set_max_buffer_size(max_buffer_size);
+    }
+}
+
+impl<R> Meta for InputMonitor<R>
+where
+    R: Meta,
+{
+    type MetaData = R::MetaData;
+
+    fn meta(&self) -> &Self::MetaData {
+        self.inner.meta()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::trivial_renderers::{PassThrough, Silence};
+
+    struct LookAhead;
+
+    impl Latency for LookAhead {
+        fn latency_in_frames(&self) -> usize {
+            64
+        }
+    }
+
+    impl AudioHandler for LookAhead {
+        fn set_sample_rate(&mut self, _sample_rate: f64) {}
+    }
+
+    #[test]
+    fn gain_is_ramped_and_added_to_the_output() {
+        let mut monitor = InputMonitor::new(PassThrough::new(2));
+        let input = [1.0_f32; 4];
+        let mut left = [0.0_f32; 4];
+        let mut right = [0.0_f32; 4];
+        // Disabled: only the output of the plugin.
+        AudioRenderer::render_buffer(
+            &mut monitor,
+            &[&input, &input],
+            &mut [&mut left, &mut right],
+        );
+        assert_eq!(left, [1.0; 4]);
+
+        monitor.set_enabled(true);
+        monitor.set_gain(0.5);
+        AudioRenderer::render_buffer(
+            &mut monitor,
+            &[&input, &input],
+            &mut [&mut left, &mut right],
+        );
+        assert_eq!(left, [1.125, 1.25, 1.375, 1.5]);
+        AudioRenderer::render_buffer(
+            &mut monitor,
+            &[&input, &input],
+            &mut [&mut left, &mut right],
+        );
+        assert_eq!(right, [1.5; 4]);
+
+        monitor.set_enabled(false);
+        AudioRenderer::render_buffer(
+            &mut monitor,
+            &[&input, &input],
+            &mut [&mut left, &mut right],
+        );
+        assert_eq!(left, [1.375, 1.25, 1.125, 1.0]);
+    }
+
+    #[test]
+    fn mono_input_is_added_to_all_outputs() {
+        let mut monitor = InputMonitor::new(Silence::new(2));
+        monitor.set_enabled(true);
+        let input = [0.5_f32; 2];
+        let mut left = [0.0_f32; 2];
+        let mut right = [0.0_f32; 2];
+        for _ in 0..2 {
+            AudioRenderer::render_buffer(&mut monitor, &[&input], &mut [&mut left, &mut right]);
+        }
+        assert_eq!(left, [0.5; 2]);
+        assert_eq!(right, [0.5; 2]);
+    }
+
+    #[test]
+    fn latency_includes_the_device_and_the_plugin() {
+        let mut monitor = InputMonitor::new(LookAhead);
+        monitor.set_sample_rate(48000.0);
+        monitor.set_device_latency(96, 144);
+        let latency = monitor.latency();
+        assert_eq!(latency.dry_in_frames, 240);
+        assert_eq!(latency.processed_in_frames, 304);
+        assert_eq!(latency.dry_in_milliseconds(), 5.0);
+        assert_eq!(latency.to_string(), "input: 5.0 ms, processed: 6.3 ms");
+        assert_eq!(monitor.latency_in_frames(), 64);
+    }
+
+    #[test]
+    fn the_control_changes_the_monitor_and_reports_the_latency() {
+        let mut monitor = InputMonitor::with_latency(PassThrough::new(1));
+        let control = monitor.control();
+        control.set_enabled(true);
+        control.set_gain(0.5);
+        control.set_device_latency(32, 64);
+        monitor.set_sample_rate(48000.0);
+        let input = [1.0_f32; 2];
+        let mut output = [0.0_f32; 2];
+        for _ in 0..2 {
+            AudioRenderer::render_buffer(&mut monitor, &[&input], &mut [&mut output]);
+        }
+        assert_eq!(output, [1.5; 2]);
+        assert!(monitor.is_enabled());
+        let latency = control.latency();
+        assert_eq!(latency.dry_in_frames, 96);
+        assert_eq!(latency.processed_in_frames, 96);
+        assert_eq!(latency.dry_in_milliseconds(), 2.0);
+    }
+}
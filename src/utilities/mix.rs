@@ -0,0 +1,364 @@
+//! Run several renderers in parallel and mix their outputs, e.g. to layer two synthesizers.
+//!
+//! Every layer of a [`Mix`] gets the same inputs and the same events. The outputs of the
+//! layers are multiplied by the gain of the layer and summed into the outputs of the [`Mix`].
+//! The [`Mix`] implements the renderer traits itself, so that it can be used with every
+//! back-end and combined with a [`Chain`]:
+//!
+//! ```
+//! use rsynth::utilities::mix::Mix;
+//! use rsynth::utilities::trivial_renderers::PassThrough;
+//! use rsynth::AudioRenderer;
+//!
+//! // Mix the input at full volume with the input at half volume.
+//! let mut mix = Mix::<_, f32>::new(1, 64)
+//!     .with_layer(PassThrough::new(1), 1.0)
+//!     .with_layer(PassThrough::new(1), 0.5);
+//! let input = [1.0, 2.0];
+//! let mut output = [0.0; 2];
+//! mix.render_buffer(&[&input[..]], &mut [&mut output[..]]);
+//! assert_eq!(output, [1.5, 3.0]);
+//! ```
+//!
+//! All layers have the same type. To layer renderers of different types, use an enum or
+//! a boxed trait object.
+//!
+//! [`Mix`]: ./struct.Mix.html
+//! [`Chain`]: ../chain/struct.Chain.html
+use crate::buffer::number_of_frames;
+use crate::event::{ContextualEventHandler, EventHandler};
+use crate::{
+    AudioHandler, AudioHandlerMeta, AudioRenderer, ContextualAudioRenderer, Latency, Lifecycle,
+    TailTime,
+};
+use asprim::AsPrim;
+use num_traits::Float;
+use vecstorage::VecStorage;
+
+struct Layer<R> {
+    renderer: R,
+    gain: f64,
+}
+
+/// Renderers in parallel, with the outputs mixed together.
+///
+/// See the [module level documentation] for more information.
+///
+/// Note about using in a real-time context
+/// =======================================
+/// The buffers in which the layers are rendered are allocated up front for the maximum buffer
+/// size that is given to [`new`] or to [`set_max_buffer_size`]. When a longer buffer is
+/// rendered, these buffers are enlarged, which allocates memory.
+///
+/// [module level documentation]: ./index.html
+/// [`new`]: #method.new
+/// [`set_max_buffer_size`]: ../../trait.AudioHandler.html#method.set_max_buffer_size
+pub struct Mix<R, S>
+where
+    S: 'static,
+{
+    layers: Vec<Layer<R>>,
+    layer_buffers: Vec<Vec<S>>,
+    layer_outputs: VecStorage<&'static [S]>,
+}
+
+impl<R, S> Mix<R, S>
+where
+    S: Float + 'static,
+{
+    /// Create a new `Mix` without layers, with `number_of_outputs` audio outputs.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(number_of_outputs: usize, max_buffer_size: usize) -> Self {
+        Mix {
+            layers: Vec::new(),
+            layer_buffers: vec![vec![S::zero(); max_buffer_size]; number_of_outputs],
+            layer_outputs: VecStorage::with_capacity(number_of_outputs),
+        }
+    }
+
+    /// Add a layer with the given gain.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method may allocate memory.
+    pub fn with_layer(mut self, renderer: R, gain: f64) -> Self {
+        self.layers.push(Layer { renderer, gain });
+        self
+    }
+
+    /// The number of layers.
+    pub fn number_of_layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// The gain of the layer with the given index.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of range.
+    pub fn gain(&self, index: usize) -> f64 {
+        self.layers[index].gain
+    }
+
+    /// Change the gain of the layer with the given index.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of range.
+    pub fn set_gain(&mut self, index: usize, gain: f64) {
+        self.layers[index].gain = gain;
+    }
+
+    /// The renderer of the layer with the given index.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of range.
+    pub fn layer(&self, index: usize) -> &R {
+        &self.layers[index].renderer
+    }
+
+    /// The renderer of the layer with the given index.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of range.
+    pub fn layer_mut(&mut self, index: usize) -> &mut R {
+        &mut self.layers[index].renderer
+    }
+
+    /// Return the renderers of the layers.
+    pub fn into_layers(self) -> Vec<R> {
+        self.layers
+            .into_iter()
+            .map(|layer| layer.renderer)
+            .collect()
+    }
+
+    fn reserve(&mut self, number_of_frames: usize) {
+        for buffer in self.layer_buffers.iter_mut() {
+            if buffer.len() < number_of_frames {
+                buffer.resize(number_of_frames, S::zero());
+            }
+        }
+    }
+
+    fn render_layers<F>(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], mut render: F)
+    where
+        S: AsPrim,
+        F: FnMut(&mut R, &[&[S]], &mut [&mut [S]]),
+    {
+        let number_of_frames = number_of_frames(inputs, outputs);
+        self.reserve(number_of_frames);
+        for output in outputs.iter_mut() {
+            for sample in output.iter_mut() {
+                *sample = S::zero();
+            }
+        }
+        for layer in self.layers.iter_mut() {
+            {
+                let mut layer_outputs = self.layer_outputs.vec_guard();
+                for buffer in self.layer_buffers.iter_mut() {
+                    layer_outputs.push(&mut buffer[..number_of_frames]);
+                }
+                render(&mut layer.renderer, inputs, layer_outputs.as_mut_slice());
+            }
+            if layer.gain == 0.0 {
+                continue;
+            }
+            let gain: S = layer.gain.as_();
+            for (output, buffer) in outputs.iter_mut().zip(self.layer_buffers.iter()) {
+                for (sample, layer_sample) in output.iter_mut().zip(buffer.iter()) {
+                    *sample = *sample + *layer_sample * gain;
+                }
+            }
+        }
+    }
+}
+
+impl<R, S> AudioRenderer<S> for Mix<R, S>
+where
+    R: AudioRenderer<S>,
+    S: AsPrim + Float + 'static,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        self.render_layers(inputs, outputs, |renderer, inputs, outputs| {
+            renderer.render_buffer(inputs, outputs)
+        });
+    }
+}
+
+impl<R, S, Context> ContextualAudioRenderer<S, Context> for Mix<R, S>
+where
+    R: ContextualAudioRenderer<S, Context>,
+    S: AsPrim + Float + 'static,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], context: &mut Context) {
+        self.render_layers(inputs, outputs, |renderer, inputs, outputs| {
+            renderer.render_buffer(inputs, outputs, context)
+        });
+    }
+}
+
+impl<R, S, E> EventHandler<E> for Mix<R, S>
+where
+    R: EventHandler<E>,
+    E: Clone,
+{
+    fn handle_event(&mut self, event: E) {
+        for layer in self.layers.iter_mut() {
+            layer.renderer.handle_event(event.clone());
+        }
+    }
+
+    fn handle_events(&mut self, events: &[E])
+    where
+        E: Clone,
+    {
+        for layer in self.layers.iter_mut() {
+            layer.renderer.handle_events(events);
+        }
+    }
+}
+
+impl<R, S, E, Context> ContextualEventHandler<E, Context> for Mix<R, S>
+where
+    R: ContextualEventHandler<E, Context>,
+    E: Clone,
+{
+    fn handle_event(&mut self, event: E, context: &mut Context) {
+        for layer in self.layers.iter_mut() {
+            layer.renderer.handle_event(event.clone(), context);
+        }
+    }
+
+    fn handle_events(&mut self, events: &[E], context: &mut Context)
+    where
+        E: Clone,
+    {
+        for layer in self.layers.iter_mut() {
+            layer.renderer.handle_events(events, context);
+        }
+    }
+}
+
+impl<R, S> AudioHandler for Mix<R, S>
+where
+    R: AudioHandler,
+    S: Float + 'static,
+{
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        for layer in self.layers.iter_mut() {
+            layer.renderer.set_sample_rate(sample_rate);
+        }
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.reserve(max_buffer_size);
+        for layer in self.layers.iter_mut() {
+            layer.renderer.set_max_buffer_size(max_buffer_size);
+        }
+    }
+}
+
+impl<R, S> AudioHandlerMeta for Mix<R, S>
+where
+    R: AudioHandlerMeta,
+{
+    fn max_number_of_audio_inputs(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|layer| layer.renderer.max_number_of_audio_inputs())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn max_number_of_audio_outputs(&self) -> usize {
+        self.layer_buffers.len()
+    }
+}
+
+/// The latency of the layer with the largest latency.
+///
+/// The outputs of layers with different latencies are not aligned; wrap the layers in a
+/// [`CompensationDelay`] to align them.
+///
+/// [`CompensationDelay`]: ../compensation_delay/struct.CompensationDelay.html
+impl<R, S> Latency for Mix<R, S>
+where
+    R: Latency,
+{
+    fn latency_in_frames(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|layer| layer.renderer.latency_in_frames())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl<R, S> TailTime for Mix<R, S>
+where
+    R: TailTime,
+{
+    fn tail_time_in_frames(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|layer| layer.renderer.tail_time_in_frames())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl<R, S> Lifecycle for Mix<R, S>
+where
+    R: Lifecycle,
+{
+    fn on_resume(&mut self) {
+        for layer in self.layers.iter_mut() {
+            layer.renderer.on_resume();
+        }
+    }
+
+    fn on_suspend(&mut self) {
+        for layer in self.layers.iter_mut() {
+            layer.renderer.on_suspend();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utilities::EventCounter;
+    use crate::utilities::trivial_renderers::PassThrough;
+
+    #[test]
+    fn sums_the_layers_with_their_gains_and_passes_events_to_all_layers() {
+        let mut mix = Mix::new(2, 4)
+            .with_layer(EventCounter::default(), 1.0)
+            .with_layer(EventCounter::default(), 0.25);
+        mix.handle_events(&[0_u8, 1, 2, 3]);
+        let mut left = [1.0; 3];
+        let mut right = [1.0; 3];
+        mix.render_buffer(&[], &mut [&mut left[..], &mut right[..]]);
+        assert_eq!(left, [5.0; 3]);
+        assert_eq!(right, [5.0; 3]);
+
+        mix.set_gain(1, 0.0);
+        mix.render_buffer(&[], &mut [&mut left[..], &mut right[..]]);
+        assert_eq!(left, [4.0; 3]);
+        assert_eq!(mix.layer(1).number_of_events, 4);
+    }
+
+    #[test]
+    fn enlarges_the_buffers_for_longer_buffers() {
+        let mut mix = Mix::new(1, 1)
+            .with_layer(PassThrough::new(1), 1.0)
+            .with_layer(PassThrough::new(1), 1.0);
+        let input = [1.0_f64, 2.0, 3.0];
+        let mut output = [0.0; 3];
+        AudioRenderer::render_buffer(&mut mix, &[&input[..]], &mut [&mut output[..]]);
+        assert_eq!(output, [2.0, 4.0, 6.0]);
+        assert_eq!(mix.max_number_of_audio_inputs(), 1);
+    }
+}
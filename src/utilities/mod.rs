@@ -1 +1,57 @@
+#[cfg(feature = "std")]
+pub mod chain;
+#[cfg(feature = "std")]
+pub mod channel_routing;
+#[cfg(feature = "std")]
+pub mod compensation_delay;
+#[cfg(feature = "std")]
+pub mod cpu_watchdog;
+#[cfg(feature = "std")]
+pub mod debug_renderer;
+#[cfg(feature = "std")]
+pub mod denormals;
+#[cfg(feature = "std")]
+pub mod disk_streaming;
+#[cfg(feature = "std")]
+pub(crate) mod fft;
+#[cfg(feature = "std")]
+pub mod input_monitor;
+#[cfg(feature = "std")]
+pub mod mix;
+#[cfg(feature = "std")]
+pub mod pitch_detector;
 pub mod polyphony;
+#[cfg(feature = "std")]
+pub mod random;
+#[cfg(feature = "recorder")]
+pub mod recorder;
+#[cfg(feature = "std")]
+pub mod resampling;
+#[cfg(feature = "std")]
+pub(crate) mod ring_buffer;
+#[cfg(feature = "std")]
+pub mod rt_log;
+#[cfg(feature = "std")]
+pub mod scale_quantizer;
+#[cfg(feature = "sf2")]
+pub mod sf2;
+#[cfg(all(feature = "std", feature = "dasp"))]
+pub mod signal;
+#[cfg(feature = "std")]
+pub mod soa;
+#[cfg(feature = "std")]
+pub mod spectral;
+#[cfg(feature = "std")]
+pub mod spectrum_analyzer;
+#[cfg(feature = "thread-pool")]
+pub mod thread_pool;
+#[cfg(feature = "std")]
+pub mod timing_filter;
+#[cfg(feature = "trash-can")]
+pub mod trash_can;
+#[cfg(feature = "std")]
+pub mod trivial_renderers;
+#[cfg(feature = "std")]
+pub mod wavetable;
+#[cfg(feature = "worker")]
+pub mod worker;
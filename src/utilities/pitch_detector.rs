@@ -0,0 +1,289 @@
+//! Detect the pitch of a monophonic signal, e.g. for a tuner or to convert audio to midi.
+//!
+//! The [`PitchDetector`] uses the YIN algorithm ("YIN, a fundamental frequency estimator for
+//! speech and music", A. de Cheveigné and H. Kawahara, 2002). Pass the input buffers to
+//! [`PitchDetector::process`]; a new estimate is computed every [`hop_size`] samples.
+//! Every estimate has a confidence between 0 and 1; when no pitch is found (e.g. for silence or
+//! noise), the estimate is `None`.
+//!
+//! ```
+//! use rsynth::utilities::pitch_detector::PitchDetector;
+//!
+//! let sample_rate = 44100.0;
+//! // Detect pitches between 50 Hz and 2 kHz.
+//! let mut detector = PitchDetector::new(sample_rate, 50.0, 2000.0);
+//! let input: Vec<f32> = (0..4096)
+//!     .map(|i| (2.0 * std::f32::consts::PI * 440.0 * i as f32 / sample_rate as f32).sin())
+//!     .collect();
+//! let pitch = detector.process(&input).expect("a pitch is found");
+//! assert!((pitch.frequency - 440.0).abs() < 1.0);
+//! assert_eq!(pitch.nearest_midi_note(), 69);
+//! ```
+//!
+//! Real-time safety
+//! ----------------
+//! The methods of the [`PitchDetector`] do not allocate memory, except for [`PitchDetector::new`].
+//! Computing an estimate takes time proportional to the square of the period of the lowest
+//! frequency, so choose the lowest frequency and the hop size with care.
+//!
+//! [`PitchDetector`]: ./struct.PitchDetector.html
+//! [`PitchDetector::new`]: ./struct.PitchDetector.html#method.new
+//! [`PitchDetector::process`]: ./struct.PitchDetector.html#method.process
+//! [`hop_size`]: ./struct.PitchDetector.html#method.hop_size
+use asprim::AsPrim;
+
+// Below this mean square level, the signal is considered silent.
+const SILENCE: f64 = 1e-8;
+
+/// An estimate of the pitch.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Pitch {
+    /// The fundamental frequency in Hz.
+    pub frequency: f64,
+    /// How periodic the signal is, from 0 (not periodic) to 1 (perfectly periodic).
+    pub confidence: f64,
+}
+
+impl Pitch {
+    /// The pitch as a (fractional) midi note number, where 69 is A4 at 440 Hz.
+    pub fn midi_note(&self) -> f64 {
+        69.0 + 12.0 * (self.frequency / 440.0).log2()
+    }
+
+    /// The midi note that is closest to the pitch.
+    pub fn nearest_midi_note(&self) -> u8 {
+        self.midi_note().round().clamp(0.0, 127.0) as u8
+    }
+
+    /// The deviation from the nearest midi note in cents, between -50 and 50.
+    pub fn cents(&self) -> f64 {
+        let note = self.midi_note();
+        (note - note.round()) * 100.0
+    }
+}
+
+/// A monophonic pitch detector. See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct PitchDetector {
+    sample_rate: f64,
+    // The shortest and the longest period that are detected, in samples.
+    minimum_lag: usize,
+    maximum_lag: usize,
+    // The number of samples that are compared for every lag.
+    window_size: usize,
+    hop_size: usize,
+    threshold: f64,
+    // The most recent `window_size + maximum_lag` samples.
+    history: Vec<f32>,
+    samples_since_estimate: usize,
+    // The cumulative mean normalized difference for every lag.
+    difference: Vec<f64>,
+    latest: Option<Pitch>,
+}
+
+impl PitchDetector {
+    /// Create a new pitch detector that detects frequencies between `minimum_frequency` and
+    /// `maximum_frequency` (in Hz).
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(sample_rate: f64, minimum_frequency: f64, maximum_frequency: f64) -> Self {
+        let maximum_lag = (sample_rate / minimum_frequency).ceil().max(2.0) as usize;
+        let minimum_lag =
+            ((sample_rate / maximum_frequency).floor() as usize).clamp(1, maximum_lag);
+        let window_size = maximum_lag;
+        PitchDetector {
+            sample_rate,
+            minimum_lag,
+            maximum_lag,
+            window_size,
+            hop_size: window_size / 2,
+            threshold: 0.15,
+            history: vec![0.0; window_size + maximum_lag + 1],
+            samples_since_estimate: 0,
+            difference: vec![0.0; maximum_lag + 2],
+            latest: None,
+        }
+    }
+
+    /// The number of samples between two estimates.
+    pub fn hop_size(&self) -> usize {
+        self.hop_size
+    }
+
+    /// Set the number of samples between two estimates. The default is half the period of the
+    /// lowest frequency.
+    pub fn set_hop_size(&mut self, hop_size: usize) {
+        self.hop_size = hop_size.max(1);
+    }
+
+    /// Set the threshold of the YIN algorithm, typically between 0.1 and 0.2 (the default is
+    /// 0.15). Lower values give fewer, but more reliable estimates.
+    pub fn set_threshold(&mut self, threshold: f64) {
+        self.threshold = threshold;
+    }
+
+    /// The most recent estimate.
+    pub fn latest(&self) -> Option<Pitch> {
+        self.latest
+    }
+
+    /// Forget the input that has been processed so far.
+    pub fn reset(&mut self) {
+        for sample in self.history.iter_mut() {
+            *sample = 0.0;
+        }
+        self.samples_since_estimate = 0;
+        self.latest = None;
+    }
+
+    /// Process a buffer of input samples and return the most recent estimate.
+    pub fn process<S: AsPrim + Copy>(&mut self, input: &[S]) -> Option<Pitch> {
+        let length = self.history.len();
+        let mut start = 0;
+        while start < input.len() {
+            let remaining = self.hop_size - self.samples_since_estimate.min(self.hop_size - 1);
+            let end = (start + remaining).min(input.len());
+            let count = end - start;
+            if count >= length {
+                for (sample, value) in self.history.iter_mut().zip(&input[end - length..end]) {
+                    *sample = value.as_();
+                }
+            } else {
+                self.history.copy_within(count.., 0);
+                for (sample, value) in self.history[length - count..]
+                    .iter_mut()
+                    .zip(&input[start..end])
+                {
+                    *sample = value.as_();
+                }
+            }
+            self.samples_since_estimate += count;
+            if self.samples_since_estimate >= self.hop_size {
+                self.samples_since_estimate = 0;
+                self.latest = self.estimate();
+            }
+            start = end;
+        }
+        self.latest
+    }
+
+    fn estimate(&mut self) -> Option<Pitch> {
+        let window = &self.history[..self.window_size];
+        let energy = window
+            .iter()
+            .map(|x| (*x as f64) * (*x as f64))
+            .sum::<f64>();
+        if energy / (self.window_size as f64) < SILENCE {
+            return None;
+        }
+        // The cumulative mean normalized difference function.
+        self.difference[0] = 1.0;
+        let mut running_sum = 0.0;
+        for lag in 1..=self.maximum_lag + 1 {
+            let lagged = &self.history[lag..lag + self.window_size];
+            let difference = window
+                .iter()
+                .zip(lagged.iter())
+                .map(|(a, b)| {
+                    let delta = (*a - *b) as f64;
+                    delta * delta
+                })
+                .sum::<f64>();
+            running_sum += difference;
+            self.difference[lag] = if running_sum > 0.0 {
+                difference * lag as f64 / running_sum
+            } else {
+                1.0
+            };
+        }
+        // The first dip below the threshold, followed down to its minimum.
+        let mut lag = self.minimum_lag.max(2);
+        while lag <= self.maximum_lag {
+            if self.difference[lag] < self.threshold {
+                while lag < self.maximum_lag && self.difference[lag + 1] < self.difference[lag] {
+                    lag += 1;
+                }
+                break;
+            }
+            lag += 1;
+        }
+        if lag > self.maximum_lag {
+            return None;
+        }
+        // Parabolic interpolation around the minimum.
+        let (previous, current, next) = (
+            self.difference[lag - 1],
+            self.difference[lag],
+            self.difference[lag + 1],
+        );
+        let denominator = previous - 2.0 * current + next;
+        let offset = if denominator.abs() > f64::EPSILON {
+            (0.5 * (previous - next) / denominator).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        Some(Pitch {
+            frequency: self.sample_rate / (lag as f64 + offset),
+            confidence: (1.0 - current).clamp(0.0, 1.0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: f32, length: usize) -> Vec<f32> {
+        (0..length)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate).sin())
+            .collect()
+    }
+
+    #[test]
+    fn detects_the_pitch_of_a_sine_wave() {
+        let mut detector = PitchDetector::new(48000.0, 60.0, 1500.0);
+        let input = sine(110.0, 48000.0, 8192);
+        // Feed the input in small buffers, as in a real-time context.
+        for buffer in input.chunks(64) {
+            detector.process(buffer);
+        }
+        let pitch = detector.latest().expect("a pitch is found");
+        assert!((pitch.frequency - 110.0).abs() < 0.5);
+        assert!(pitch.confidence > 0.9);
+        assert_eq!(pitch.nearest_midi_note(), 45);
+        assert!(pitch.cents().abs() < 10.0);
+    }
+
+    #[test]
+    fn detects_the_fundamental_of_a_sawtooth() {
+        let mut detector = PitchDetector::new(44100.0, 50.0, 2000.0);
+        let period = 44100.0 / 220.0;
+        let input: Vec<f32> = (0..8192)
+            .map(|i| {
+                let phase = (i as f64 / period).fract();
+                (2.0 * phase - 1.0) as f32
+            })
+            .collect();
+        let pitch = detector.process(&input).expect("a pitch is found");
+        assert!((pitch.frequency - 220.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn silence_has_no_pitch() {
+        let mut detector = PitchDetector::new(44100.0, 50.0, 2000.0);
+        assert_eq!(detector.process(&[0.0_f32; 4096]), None);
+    }
+
+    #[test]
+    fn pitch_to_midi_note() {
+        let pitch = Pitch {
+            frequency: 452.89,
+            confidence: 1.0,
+        };
+        assert_eq!(pitch.nearest_midi_note(), 69);
+        assert!((pitch.cents() - 50.0).abs() < 0.1);
+    }
+}
@@ -8,6 +8,15 @@
 //! 3. Then, the event can be dispatched.
 //!    The `EventDispatcher` trait and the `ContextualEventDispatcher` trait define
 //!    methods for doing this.
+//!
+//! Real-time safety
+//! ================
+//! Classifying, assigning and dispatching events does not allocate memory:
+//! the voices are passed as a slice and all bookkeeping is done in place.
+//! This is checked by the tests in `tests/polyphony.rs` with the
+//! [`allocation`] test utilities.
+//!
+//! [`allocation`]: ../../test_utilities/allocation/index.html
 use crate::event::{ContextualEventHandler, EventHandler, RawMidiEvent};
 use midi_consts::channel_event::*;
 
@@ -154,7 +163,7 @@ pub mod simple_event_dispatching {
         Voice, VoiceAssigner,
     };
     use crate::event::{ContextualEventHandler, EventHandler};
-    use std::marker::PhantomData;
+    use core::marker::PhantomData;
 
     #[derive(Clone, Copy, PartialEq, Eq)]
     pub enum SimpleVoiceState<VoiceIdentifier>
@@ -166,6 +175,28 @@ pub mod simple_event_dispatching {
         Active(VoiceIdentifier),
     }
 
+    // Deriving `Default` would require `VoiceIdentifier: Default`.
+    #[allow(clippy::derivable_impls)]
+    impl<VoiceIdentifier> Default for SimpleVoiceState<VoiceIdentifier>
+    where
+        VoiceIdentifier: Copy + Eq,
+    {
+        fn default() -> Self {
+            SimpleVoiceState::Idle
+        }
+    }
+
+    // This allows to use the voice states as the voices, e.g. when the voice data is stored
+    // as a "structure of arrays", see the `soa` module.
+    impl<VoiceIdentifier> Voice<SimpleVoiceState<VoiceIdentifier>> for SimpleVoiceState<VoiceIdentifier>
+    where
+        VoiceIdentifier: Copy + Eq,
+    {
+        fn state(&self) -> SimpleVoiceState<VoiceIdentifier> {
+            *self
+        }
+    }
+
     pub struct SimpleEventDispatcher<Classifier, V> {
         classifier: Classifier,
         _voice_phantom: PhantomData<V>,
@@ -0,0 +1,91 @@
+//! A small, seedable pseudo-random number generator.
+//!
+//! [`Random`] is an xorshift64* generator: it is fast, does not allocate memory and can be
+//! used in a real-time context. It is not suitable for cryptography.
+//! With the same seed, it always generates the same sequence of numbers, so that e.g. a random
+//! patch or a fuzz test can be reproduced.
+//!
+//! ```
+//! use rsynth::utilities::random::Random;
+//!
+//! let mut first = Random::new(42);
+//! let mut second = Random::new(42);
+//! let value = first.next_unipolar();
+//! assert!(0.0 <= value && value < 1.0);
+//! assert_eq!(value, second.next_unipolar());
+//! ```
+//!
+//! [`Random`]: ./struct.Random.html
+
+/// A seedable pseudo-random number generator.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+#[derive(Clone, Debug)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    /// Create a new generator with the given seed.
+    pub fn new(seed: u64) -> Self {
+        let mut random = Random { state: 0 };
+        random.set_seed(seed);
+        random
+    }
+
+    /// Restart the sequence with the given seed.
+    pub fn set_seed(&mut self, seed: u64) {
+        // The state of an xorshift generator must not be zero.
+        self.state = seed.max(1);
+    }
+
+    /// A number that is uniformly distributed over all values of `u64`.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A number in the range `0.0..1.0`.
+    pub fn next_unipolar(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// A number in the range `-1.0..1.0`.
+    pub fn next_bipolar(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 52) as f64 - 1.0
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Random::new(0x9E37_79B9_7F4A_7C15)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Random;
+
+    #[test]
+    fn numbers_are_in_range_and_reproducible() {
+        let mut random = Random::new(0);
+        let mut sum = 0.0;
+        for _ in 0..1000 {
+            let unipolar = random.next_unipolar();
+            assert!((0.0..1.0).contains(&unipolar));
+            let bipolar = random.next_bipolar();
+            assert!((-1.0..1.0).contains(&bipolar));
+            sum += bipolar;
+        }
+        assert!((sum / 1000.0).abs() < 0.1);
+        random.set_seed(7);
+        let first: Vec<u64> = (0..3).map(|_| random.next_u64()).collect();
+        random.set_seed(7);
+        let second: Vec<u64> = (0..3).map(|_| random.next_u64()).collect();
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,330 @@
+//! Record audio from the audio thread to a `.wav` file.
+//!
+//! The [`Recorder`] is used in the audio thread: it copies the samples that are passed to
+//! [`Recorder::record`] (e.g. the outputs of the plugin, at the end of `render_buffer`, or the
+//! inputs) into a lock-free ring buffer. A background thread writes them to a `.wav` file with
+//! 32-bit float samples. The recording is started and stopped with the [`RecorderControl`].
+//!
+//! This module is only available when compiled with the `recorder` feature.
+//!
+//! ```no_run
+//! use rsynth::utilities::recorder::recorder;
+//!
+//! let (mut recorder, mut control) = recorder(2, 44100);
+//! control.start("bounce.wav", 44100).expect("the file can be created");
+//! // In the audio thread:
+//! # let left = [0.0_f32; 64];
+//! # let right = [0.0_f32; 64];
+//! recorder.record(&[&left[..], &right[..]]);
+//! // Later:
+//! let number_of_frames = control.stop().expect("the file can be written");
+//! ```
+//!
+//! Dropouts
+//! --------
+//! When the background thread cannot keep up, the ring buffer becomes full and frames are
+//! dropped. The number of dropped frames is returned by
+//! [`RecorderControl::number_of_dropped_frames`].
+//!
+//! Real-time safety
+//! ----------------
+//! [`Recorder::record`] does not allocate memory, does not lock and does not perform I/O.
+//!
+//! [`Recorder`]: ./struct.Recorder.html
+//! [`Recorder::record`]: ./struct.Recorder.html#method.record
+//! [`RecorderControl`]: ./struct.RecorderControl.html
+//! [`RecorderControl::number_of_dropped_frames`]: ./struct.RecorderControl.html#method.number_of_dropped_frames
+use crate::utilities::ring_buffer::RingBuffer;
+use asprim::AsPrim;
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// The number of frames that `record` interleaves at once.
+const CHUNK_FRAMES: usize = 256;
+// How long the background thread sleeps when the ring buffer is empty.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+struct Shared {
+    ring_buffer: RingBuffer,
+    number_of_channels: usize,
+    is_recording: AtomicBool,
+    number_of_dropped_frames: AtomicUsize,
+}
+
+/// Create a [`Recorder`] for the audio thread and a [`RecorderControl`] to start and stop
+/// recording.
+///
+/// `capacity` is the number of frames that the ring buffer can hold; it should cover
+/// at least a few hundred milliseconds.
+///
+/// [`Recorder`]: ./struct.Recorder.html
+/// [`RecorderControl`]: ./struct.RecorderControl.html
+pub fn recorder(number_of_channels: usize, capacity: usize) -> (Recorder, RecorderControl) {
+    let number_of_channels = number_of_channels.max(1);
+    let shared = Arc::new(Shared {
+        ring_buffer: RingBuffer::new(capacity * number_of_channels),
+        number_of_channels,
+        is_recording: AtomicBool::new(false),
+        number_of_dropped_frames: AtomicUsize::new(0),
+    });
+    let recorder = Recorder {
+        shared: shared.clone(),
+        chunk: vec![0.0; CHUNK_FRAMES * number_of_channels],
+    };
+    let control = RecorderControl {
+        shared,
+        stop: Arc::new(AtomicBool::new(false)),
+        thread: None,
+    };
+    (recorder, control)
+}
+
+/// The part of the recorder that is used in the audio thread. Created with [`recorder`].
+///
+/// [`recorder`]: ./fn.recorder.html
+pub struct Recorder {
+    shared: Arc<Shared>,
+    // Interleaved frames.
+    chunk: Vec<f32>,
+}
+
+impl Recorder {
+    /// Record one buffer, with one slice per channel. This does nothing when not recording.
+    ///
+    /// Missing channels are recorded as silence and additional channels are ignored.
+    pub fn record<S: AsPrim>(&mut self, channels: &[&[S]]) {
+        let shared = &self.shared;
+        if !shared.is_recording.load(Ordering::Acquire) {
+            return;
+        }
+        let number_of_channels = shared.number_of_channels;
+        let number_of_frames = channels.first().map(|channel| channel.len()).unwrap_or(0);
+        let mut start = 0;
+        while start < number_of_frames {
+            let end = (start + CHUNK_FRAMES).min(number_of_frames);
+            let free_frames = shared.ring_buffer.free() / number_of_channels;
+            let frames_to_write = (end - start).min(free_frames);
+            for (frame, interleaved) in self
+                .chunk
+                .chunks_mut(number_of_channels)
+                .take(frames_to_write)
+                .enumerate()
+            {
+                for (channel, sample) in interleaved.iter_mut().enumerate() {
+                    *sample = channels
+                        .get(channel)
+                        .and_then(|channel| channel.get(start + frame))
+                        .map(|sample| sample.as_())
+                        .unwrap_or(0.0);
+                }
+            }
+            shared
+                .ring_buffer
+                .push(&self.chunk[..frames_to_write * number_of_channels]);
+            let dropped_frames = end - start - frames_to_write;
+            if dropped_frames > 0 {
+                shared
+                    .number_of_dropped_frames
+                    .fetch_add(dropped_frames, Ordering::Relaxed);
+            }
+            start = end;
+        }
+    }
+}
+
+/// The error type that represents the errors you can get when starting or stopping a recording.
+#[derive(Debug)]
+pub enum RecorderError {
+    /// A recording is already in progress.
+    AlreadyRecording,
+    /// There is no recording in progress.
+    NotRecording,
+    /// The `.wav` file could not be created or written.
+    WavError(hound::Error),
+    /// The background thread could not be started.
+    ThreadSpawnError(io::Error),
+    /// The background thread panicked.
+    ThreadPanicked,
+}
+
+/// Starts and stops recording. Created with [`recorder`].
+///
+/// Dropping the `RecorderControl` stops the recording.
+///
+/// [`recorder`]: ./fn.recorder.html
+pub struct RecorderControl {
+    shared: Arc<Shared>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<usize, hound::Error>>>,
+}
+
+impl RecorderControl {
+    /// Create the file and start recording.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method performs I/O and starts a thread.
+    pub fn start<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        sample_rate: u32,
+    ) -> Result<(), RecorderError> {
+        if self.thread.is_some() {
+            return Err(RecorderError::AlreadyRecording);
+        }
+        let shared = self.shared.clone();
+        let spec = WavSpec {
+            channels: shared.number_of_channels as u16,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(path, spec).map_err(RecorderError::WavError)?;
+        // Skip what may have been recorded while the previous recording was being stopped.
+        shared.ring_buffer.discard();
+        shared.number_of_dropped_frames.store(0, Ordering::Relaxed);
+        let stop = Arc::new(AtomicBool::new(false));
+        self.stop = stop.clone();
+        let thread = thread::Builder::new()
+            .name("recorder".to_string())
+            .spawn(move || {
+                let number_of_channels = shared.number_of_channels;
+                let mut buffer = vec![0.0; CHUNK_FRAMES * number_of_channels];
+                let mut number_of_frames = 0;
+                loop {
+                    // Read `stop` before checking the ring buffer, so that the frames that have
+                    // been recorded before stopping are written.
+                    let is_stopped = stop.load(Ordering::Acquire);
+                    let available = shared.ring_buffer.available() / number_of_channels;
+                    let frames_to_read = available.min(CHUNK_FRAMES);
+                    if frames_to_read > 0 {
+                        let samples = &mut buffer[..frames_to_read * number_of_channels];
+                        shared.ring_buffer.pop(samples);
+                        for sample in samples.iter() {
+                            writer.write_sample(*sample)?;
+                        }
+                        number_of_frames += frames_to_read;
+                    } else if is_stopped {
+                        break;
+                    } else {
+                        thread::sleep(POLL_INTERVAL);
+                    }
+                }
+                writer.finalize()?;
+                Ok(number_of_frames)
+            })
+            .map_err(RecorderError::ThreadSpawnError)?;
+        self.thread = Some(thread);
+        self.shared.is_recording.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Stop recording and finish writing the file.
+    /// Return the number of frames that have been written.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method waits for the background thread to finish.
+    pub fn stop(&mut self) -> Result<usize, RecorderError> {
+        let thread = self.thread.take().ok_or(RecorderError::NotRecording)?;
+        self.shared.is_recording.store(false, Ordering::Release);
+        self.stop.store(true, Ordering::Release);
+        match thread.join() {
+            Ok(result) => result.map_err(RecorderError::WavError),
+            Err(_) => Err(RecorderError::ThreadPanicked),
+        }
+    }
+
+    /// Return `true` when a recording is in progress.
+    pub fn is_recording(&self) -> bool {
+        self.thread.is_some()
+    }
+
+    /// The number of frames that have been dropped during the current (or the last) recording
+    /// because the ring buffer was full.
+    pub fn number_of_dropped_frames(&self) -> usize {
+        self.shared.number_of_dropped_frames.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for RecorderControl {
+    fn drop(&mut self) {
+        if self.is_recording() {
+            let _ = self.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::WavReader;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temporary_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "rsynth-recorder-{}-{}.wav",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn records_interleaved_frames() {
+        let path = temporary_file("interleaved");
+        let (mut recorder, mut control) = recorder(2, 1024);
+        let left: Vec<f32> = (0..300).map(|i| i as f32).collect();
+        let right: Vec<f32> = (0..300).map(|i| -(i as f32)).collect();
+        // Not recording yet.
+        recorder.record(&[&left[..], &right[..]]);
+        control.start(&path, 48000).expect("no error");
+        assert!(control.is_recording());
+        recorder.record(&[&left[..], &right[..]]);
+        assert_eq!(control.stop().expect("no error"), 300);
+        assert!(!control.is_recording());
+        assert_eq!(control.number_of_dropped_frames(), 0);
+
+        let mut reader = WavReader::open(&path).expect("a valid wav file");
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 48000);
+        let samples: Vec<f32> = reader.samples().map(|s| s.expect("no error")).collect();
+        assert_eq!(samples.len(), 600);
+        assert_eq!(&samples[..6], &[0.0, 0.0, 1.0, -1.0, 2.0, -2.0]);
+        assert_eq!(samples[599], -299.0);
+        fs::remove_file(&path).expect("no error");
+    }
+
+    #[test]
+    fn counts_dropped_frames() {
+        let path = temporary_file("dropped");
+        let (mut recorder, mut control) = recorder(1, 8);
+        control.start(&path, 44100).expect("no error");
+        recorder.record(&[&[0.5_f32; 64][..]]);
+        assert_eq!(control.number_of_dropped_frames(), 56);
+        assert_eq!(control.stop().expect("no error"), 8);
+        fs::remove_file(&path).expect("no error");
+    }
+
+    #[test]
+    fn start_twice_is_an_error() {
+        let path = temporary_file("twice");
+        let (_recorder, mut control) = recorder(1, 8);
+        control.start(&path, 44100).expect("no error");
+        match control.start(&path, 44100) {
+            Err(RecorderError::AlreadyRecording) => {}
+            _ => panic!("Expected an error."),
+        }
+        control.stop().expect("no error");
+        match control.stop() {
+            Err(RecorderError::NotRecording) => {}
+            _ => panic!("Expected an error."),
+        }
+        fs::remove_file(&path).expect("no error");
+    }
+}
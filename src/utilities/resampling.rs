@@ -0,0 +1,687 @@
+//! Run a renderer at a different sample rate than the back-end.
+//!
+//! Some renderers only work at one sample rate, e.g. because they play samples that have been
+//! recorded at that sample rate. A [`SampleRateAdapter`] runs such a renderer at its own sample
+//! rate (the "inner" sample rate) inside a back-end that runs at another sample rate (the
+//! "outer" sample rate, as passed to `set_sample_rate`).
+//! The audio inputs are converted from the outer to the inner sample rate, the audio outputs
+//! from the inner to the outer sample rate, and the `time_in_frames` of the events is converted
+//! to frames at the inner sample rate.
+//!
+//! ```
+//! use rsynth::event::RawMidiEvent;
+//! use rsynth::utilities::resampling::SampleRateAdapter;
+//! use rsynth::utilities::trivial_renderers::PassThrough;
+//! use rsynth::{AudioHandler, AudioRenderer, Latency};
+//!
+//! // A mono renderer that runs at 44100 Hz, ...
+//! let mut adapter =
+//!     SampleRateAdapter::<_, f32, RawMidiEvent>::new(PassThrough::new(1), 44100.0, 1, 1, 256);
+//! // ... inside a back-end that runs at 48000 Hz.
+//! adapter.set_sample_rate(48000.0);
+//! let input = [0.5; 256];
+//! let mut output = [0.0; 256];
+//! for _ in 0..4 {
+//!     adapter.render_buffer(&[&input[..]], &mut [&mut output[..]]);
+//! }
+//! // The output is delayed by `latency_in_frames()` frames.
+//! assert!(adapter.latency_in_frames() < 256);
+//! assert!((output[255] - 0.5).abs() < 0.01);
+//! ```
+//!
+//! The conversion uses a windowed sinc filter, which adds a latency of a few dozen frames.
+//!
+//! Events that the renderer writes to the context while rendering are passed to the context
+//! unchanged, so their `time_in_frames` is at the inner sample rate.
+//!
+//! [`SampleRateAdapter`]: ./struct.SampleRateAdapter.html
+use crate::buffer::number_of_frames;
+use crate::event::event_queue::{AlwaysInsertNewAfterOld, EventQueue};
+use crate::event::{EventHandler, Timed};
+use crate::{
+    AudioHandler, AudioHandlerMeta, AudioRenderer, ContextualAudioRenderer, Latency, Lifecycle,
+};
+use asprim::AsPrim;
+use num_traits::Zero;
+use std::f64::consts::PI;
+use vecstorage::VecStorage;
+
+// The number of zero crossings of the sinc function on each side of the filter kernel.
+const ZERO_CROSSINGS: usize = 16;
+// The number of fractional positions for which the filter kernel is tabulated.
+const PHASES: usize = 256;
+// The maximum number of events that can be queued for the renderer.
+const EVENT_QUEUE_CAPACITY: usize = 1024;
+
+fn windowed_sinc(distance: f64, cutoff: f64, half_width: usize) -> f64 {
+    let x = distance / half_width as f64;
+    if x.abs() >= 1.0 {
+        return 0.0;
+    }
+    let sinc = if distance == 0.0 {
+        1.0
+    } else {
+        (PI * cutoff * distance).sin() / (PI * cutoff * distance)
+    };
+    // Blackman window.
+    let window = 0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos();
+    cutoff * sinc * window
+}
+
+// Converts a stream of frames from one sample rate to another.
+struct Converter {
+    // The number of input frames per output frame.
+    step: f64,
+    // The number of input frames on each side of an output frame that are used to compute it.
+    half_width: usize,
+    // The filter kernel for `PHASES + 1` fractional positions, `2 * half_width` taps each.
+    kernel: Vec<f64>,
+    // The input frames that are still needed, one buffer per channel.
+    channels: Vec<Vec<f64>>,
+    // The number of input frames, also when there are no channels.
+    length: usize,
+    // The position of the next output frame, in input frames since the start of `channels`.
+    position: f64,
+}
+
+impl Converter {
+    // The output is delayed by `delay` input frames.
+    fn new(
+        number_of_channels: usize,
+        from_sample_rate: f64,
+        to_sample_rate: f64,
+        delay: usize,
+        max_buffer_size: usize,
+    ) -> Self {
+        let step = from_sample_rate / to_sample_rate;
+        // Remove the frequencies that cannot be represented at the lower sample rate.
+        let cutoff = (1.0 / step).min(1.0);
+        let half_width = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+        let taps = 2 * half_width;
+        let mut kernel = Vec::with_capacity((PHASES + 1) * taps);
+        for phase in 0..=PHASES {
+            let fraction = phase as f64 / PHASES as f64;
+            for tap in 0..taps {
+                let distance = fraction + (half_width - 1) as f64 - tap as f64;
+                kernel.push(windowed_sinc(distance, cutoff, half_width));
+            }
+        }
+        // The frames before the start of the stream are silent.
+        let length = half_width + delay;
+        let capacity = length + taps + max_buffer_size + step.ceil() as usize;
+        let channels = (0..number_of_channels)
+            .map(|_| {
+                let mut channel = Vec::with_capacity(capacity);
+                channel.resize(length, 0.0);
+                channel
+            })
+            .collect();
+        Converter {
+            step,
+            half_width,
+            kernel,
+            channels,
+            length,
+            position: half_width as f64,
+        }
+    }
+
+    fn reserve(&mut self, max_buffer_size: usize) {
+        let capacity = 4 * self.half_width + max_buffer_size + self.step.ceil() as usize;
+        for channel in self.channels.iter_mut() {
+            channel.reserve(capacity.saturating_sub(channel.len()));
+        }
+    }
+
+    fn push<S>(&mut self, inputs: &[&[S]], number_of_frames: usize)
+    where
+        S: AsPrim,
+    {
+        // Forget the frames that are no longer needed.
+        let first_needed = (self.position.floor() as usize + 1)
+            .saturating_sub(self.half_width)
+            .min(self.length);
+        if first_needed > 0 {
+            for channel in self.channels.iter_mut() {
+                channel.copy_within(first_needed.., 0);
+                channel.truncate(self.length - first_needed);
+            }
+            self.length -= first_needed;
+            self.position -= first_needed as f64;
+        }
+
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            match inputs.get(index) {
+                Some(input) => {
+                    channel.extend(input[..number_of_frames].iter().map(|s| s.as_::<f64>()))
+                }
+                None => channel.resize(channel.len() + number_of_frames, 0.0),
+            }
+        }
+        self.length += number_of_frames;
+    }
+
+    // The number of output frames that can be computed from the input frames so far.
+    fn available(&self) -> usize {
+        let limit = self.length as f64 - self.half_width as f64 - self.position;
+        if limit <= 0.0 {
+            return 0;
+        }
+        let mut number_of_frames = (limit / self.step).ceil() as usize;
+        // Guard against rounding errors.
+        while number_of_frames > 0
+            && (self.position + (number_of_frames - 1) as f64 * self.step).floor() as usize
+                + self.half_width
+                >= self.length
+        {
+            number_of_frames -= 1;
+        }
+        number_of_frames
+    }
+
+    fn pull<S>(&mut self, outputs: &mut [&mut [S]], number_of_frames: usize)
+    where
+        S: AsPrim,
+    {
+        debug_assert!(number_of_frames <= self.available());
+        let taps = 2 * self.half_width;
+        for frame in 0..number_of_frames {
+            let index = self.position.floor();
+            let fraction = (self.position - index) * PHASES as f64;
+            let phase = (fraction.floor() as usize).min(PHASES - 1);
+            let weight = fraction - phase as f64;
+            let kernel = &self.kernel[phase * taps..(phase + 1) * taps];
+            let next_kernel = &self.kernel[(phase + 1) * taps..(phase + 2) * taps];
+            let first = index as usize + 1 - self.half_width;
+            for (channel, output) in self.channels.iter().zip(outputs.iter_mut()) {
+                let mut sum = 0.0;
+                for ((sample, a), b) in channel[first..first + taps]
+                    .iter()
+                    .zip(kernel.iter())
+                    .zip(next_kernel.iter())
+                {
+                    sum += sample * (a + (b - a) * weight);
+                }
+                output[frame] = sum.as_();
+            }
+            self.position += self.step;
+        }
+    }
+}
+
+/// Runs a renderer at a fixed sample rate, independent of the sample rate of the back-end.
+///
+/// The type parameter `S` is the sample type and `E` is the type of the events,
+/// e.g. [`RawMidiEvent`].
+/// See the [module level documentation] for more information.
+///
+/// Note about using in a real-time context
+/// =======================================
+/// The buffers are allocated up front for the maximum buffer size that is given to [`new`]
+/// or to [`set_max_buffer_size`]. When a longer buffer is rendered, the buffers are enlarged,
+/// which allocates memory. Changing the sample rate also allocates memory.
+///
+/// [`RawMidiEvent`]: ../../event/struct.RawMidiEvent.html
+/// [module level documentation]: ./index.html
+/// [`new`]: #method.new
+/// [`set_max_buffer_size`]: ../../trait.AudioHandler.html#method.set_max_buffer_size
+pub struct SampleRateAdapter<R, S, E>
+where
+    S: 'static,
+{
+    inner: R,
+    inner_sample_rate: f64,
+    outer_sample_rate: f64,
+    number_of_inputs: usize,
+    number_of_outputs: usize,
+    max_buffer_size: usize,
+    inner_max_buffer_size: usize,
+    input_converter: Converter,
+    output_converter: Converter,
+    // The number of (silent) inner frames by which the output is delayed, so that every
+    // buffer of the back-end can be filled completely.
+    output_delay: usize,
+    inner_inputs: Vec<Vec<S>>,
+    inner_outputs: Vec<Vec<S>>,
+    inner_input_slices: VecStorage<&'static [S]>,
+    inner_output_slices: VecStorage<&'static [S]>,
+    // Times are relative to the start of the next buffer of the renderer.
+    events: EventQueue<E>,
+    outer_frames: u64,
+    inner_frames: u64,
+}
+
+impl<R, S, E> SampleRateAdapter<R, S, E>
+where
+    S: Zero + Clone + 'static,
+{
+    /// Run `inner`, which has `number_of_inputs` audio inputs and `number_of_outputs` audio
+    /// outputs, at `inner_sample_rate` frames per second.
+    ///
+    /// Until `set_sample_rate` is called, the back-end is assumed to run at the same sample
+    /// rate as `inner`.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    ///
+    /// # Panics
+    /// Panics if `inner_sample_rate` is not positive.
+    pub fn new(
+        inner: R,
+        inner_sample_rate: f64,
+        number_of_inputs: usize,
+        number_of_outputs: usize,
+        max_buffer_size: usize,
+    ) -> Self
+    where
+        R: AudioHandler,
+    {
+        assert!(inner_sample_rate > 0.0);
+        let (input_converter, output_converter, output_delay) = converters(
+            inner_sample_rate,
+            inner_sample_rate,
+            number_of_inputs,
+            number_of_outputs,
+            max_buffer_size,
+        );
+        let mut adapter = SampleRateAdapter {
+            inner,
+            inner_sample_rate,
+            outer_sample_rate: inner_sample_rate,
+            number_of_inputs,
+            number_of_outputs,
+            max_buffer_size,
+            inner_max_buffer_size: 0,
+            input_converter,
+            output_converter,
+            output_delay,
+            inner_inputs: vec![Vec::new(); number_of_inputs],
+            inner_outputs: vec![Vec::new(); number_of_outputs],
+            inner_input_slices: VecStorage::with_capacity(number_of_inputs),
+            inner_output_slices: VecStorage::with_capacity(number_of_outputs),
+            events: EventQueue::new(EVENT_QUEUE_CAPACITY),
+            outer_frames: 0,
+            inner_frames: 0,
+        };
+        adapter.inner.set_sample_rate(inner_sample_rate);
+        adapter.allocate();
+        adapter
+    }
+
+    /// The sample rate at which the wrapped renderer runs.
+    pub fn inner_sample_rate(&self) -> f64 {
+        self.inner_sample_rate
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // The number of inner frames per outer frame.
+    fn ratio(&self) -> f64 {
+        self.inner_sample_rate / self.outer_sample_rate
+    }
+
+    // Forget the audio and the events and start again.
+    fn restart(&mut self) {
+        let (input_converter, output_converter, output_delay) = converters(
+            self.outer_sample_rate,
+            self.inner_sample_rate,
+            self.number_of_inputs,
+            self.number_of_outputs,
+            self.max_buffer_size,
+        );
+        self.input_converter = input_converter;
+        self.output_converter = output_converter;
+        self.output_delay = output_delay;
+        self.events.clear();
+        self.outer_frames = 0;
+        self.inner_frames = 0;
+    }
+
+    // Make room for buffers of `max_buffer_size` frames.
+    fn reserve(&mut self, max_buffer_size: usize)
+    where
+        R: AudioHandler,
+    {
+        if max_buffer_size > self.max_buffer_size {
+            self.max_buffer_size = max_buffer_size;
+            self.allocate();
+        }
+    }
+
+    fn allocate(&mut self)
+    where
+        R: AudioHandler,
+    {
+        self.inner_max_buffer_size =
+            (self.max_buffer_size as f64 * self.ratio()).ceil() as usize + 2;
+        for buffer in self
+            .inner_inputs
+            .iter_mut()
+            .chain(self.inner_outputs.iter_mut())
+        {
+            buffer.resize(self.inner_max_buffer_size, S::zero());
+        }
+        self.input_converter.reserve(self.max_buffer_size);
+        self.output_converter.reserve(self.inner_max_buffer_size);
+        self.inner.set_max_buffer_size(self.inner_max_buffer_size);
+    }
+
+    fn render_inner<F>(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], render: F)
+    where
+        R: AudioHandler + EventHandler<Timed<E>>,
+        S: AsPrim,
+        E: Clone,
+        F: FnOnce(&mut R, &[&[S]], &mut [&mut [S]]),
+    {
+        let number_of_frames = number_of_frames(inputs, outputs);
+        self.reserve(number_of_frames);
+
+        // Convert the inputs to the inner sample rate.
+        self.input_converter.push(inputs, number_of_frames);
+        let inner_number_of_frames = self.input_converter.available();
+        if inner_number_of_frames > self.inner_max_buffer_size {
+            self.max_buffer_size = (inner_number_of_frames as f64 / self.ratio()).ceil() as usize;
+            self.allocate();
+        }
+        {
+            let mut inner_inputs = self.inner_input_slices.vec_guard();
+            for buffer in self.inner_inputs.iter_mut() {
+                inner_inputs.push(&mut buffer[..inner_number_of_frames]);
+            }
+            self.input_converter
+                .pull(inner_inputs.as_mut_slice(), inner_number_of_frames);
+        }
+
+        for event in self.events.iter() {
+            if event.time_in_frames as usize >= inner_number_of_frames {
+                break;
+            }
+            self.inner.handle_event(event.clone());
+        }
+        self.events.advance(inner_number_of_frames as u32);
+
+        {
+            let mut inner_inputs = self.inner_input_slices.vec_guard();
+            for buffer in self.inner_inputs.iter() {
+                inner_inputs.push(&buffer[..inner_number_of_frames]);
+            }
+            let mut inner_outputs = self.inner_output_slices.vec_guard();
+            for buffer in self.inner_outputs.iter_mut() {
+                inner_outputs.push(&mut buffer[..inner_number_of_frames]);
+            }
+            render(
+                &mut self.inner,
+                inner_inputs.as_slice(),
+                inner_outputs.as_mut_slice(),
+            );
+        }
+
+        // Convert the outputs back to the outer sample rate.
+        {
+            let mut inner_outputs = self.inner_output_slices.vec_guard();
+            for buffer in self.inner_outputs.iter() {
+                inner_outputs.push(&buffer[..inner_number_of_frames]);
+            }
+            self.output_converter
+                .push(inner_outputs.as_slice(), inner_number_of_frames);
+        }
+        let available = std::cmp::min(self.output_converter.available(), number_of_frames);
+        self.output_converter.pull(outputs, available);
+        for (index, output) in outputs.iter_mut().enumerate() {
+            let start = if index < self.number_of_outputs {
+                available
+            } else {
+                0
+            };
+            for sample in output[start..].iter_mut() {
+                *sample = S::zero();
+            }
+        }
+
+        self.outer_frames += number_of_frames as u64;
+        self.inner_frames += inner_number_of_frames as u64;
+    }
+}
+
+// Create the converter for the inputs, the converter for the outputs and the delay of the
+// output.
+fn converters(
+    outer_sample_rate: f64,
+    inner_sample_rate: f64,
+    number_of_inputs: usize,
+    number_of_outputs: usize,
+    max_buffer_size: usize,
+) -> (Converter, Converter, usize) {
+    let ratio = inner_sample_rate / outer_sample_rate;
+    let inner_max_buffer_size = (max_buffer_size as f64 * ratio).ceil() as usize + 2;
+    let input_converter = Converter::new(
+        number_of_inputs,
+        outer_sample_rate,
+        inner_sample_rate,
+        0,
+        max_buffer_size,
+    );
+    // The renderer lags `input_converter.half_width` outer frames behind the back-end and the
+    // output converter needs `half_width` inner frames ahead of each output frame.
+    let half_width = Converter::new(0, inner_sample_rate, outer_sample_rate, 0, 0).half_width;
+    let output_delay = half_width + (input_converter.half_width as f64 * ratio).ceil() as usize + 2;
+    let output_converter = Converter::new(
+        number_of_outputs,
+        inner_sample_rate,
+        outer_sample_rate,
+        output_delay,
+        inner_max_buffer_size,
+    );
+    (input_converter, output_converter, output_delay)
+}
+
+impl<R, S, E> AudioRenderer<S> for SampleRateAdapter<R, S, E>
+where
+    R: AudioRenderer<S> + AudioHandler + EventHandler<Timed<E>>,
+    S: AsPrim + Zero + Clone + 'static,
+    E: Clone,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        self.render_inner(inputs, outputs, |inner, inputs, outputs| {
+            inner.render_buffer(inputs, outputs)
+        });
+    }
+}
+
+impl<R, S, E, Context> ContextualAudioRenderer<S, Context> for SampleRateAdapter<R, S, E>
+where
+    R: ContextualAudioRenderer<S, Context> + AudioHandler + EventHandler<Timed<E>>,
+    S: AsPrim + Zero + Clone + 'static,
+    E: Clone,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], context: &mut Context) {
+        self.render_inner(inputs, outputs, |inner, inputs, outputs| {
+            inner.render_buffer(inputs, outputs, context)
+        });
+    }
+}
+
+/// The event is passed to the wrapped renderer when the buffer that contains the event at the
+/// inner sample rate is rendered.
+impl<R, S, E> EventHandler<Timed<E>> for SampleRateAdapter<R, S, E>
+where
+    S: 'static,
+{
+    fn handle_event(&mut self, event: Timed<E>) {
+        let ratio = self.inner_sample_rate / self.outer_sample_rate;
+        let time_in_inner_frames =
+            ((self.outer_frames + event.time_in_frames as u64) as f64 * ratio).round() as u64;
+        let time_in_frames = std::cmp::min(
+            time_in_inner_frames.saturating_sub(self.inner_frames),
+            u32::MAX as u64,
+        ) as u32;
+        self.events.queue_event(
+            Timed::new(time_in_frames, event.event),
+            AlwaysInsertNewAfterOld,
+        );
+    }
+}
+
+impl<R, S, E> AudioHandler for SampleRateAdapter<R, S, E>
+where
+    R: AudioHandler,
+    S: Zero + Clone + 'static,
+{
+    /// Set the sample rate of the back-end. The sample rate of the wrapped renderer does not
+    /// change.
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        assert!(sample_rate > 0.0);
+        if sample_rate != self.outer_sample_rate {
+            self.outer_sample_rate = sample_rate;
+            self.restart();
+            self.allocate();
+        }
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.reserve(max_buffer_size);
+    }
+}
+
+impl<R, S, E> AudioHandlerMeta for SampleRateAdapter<R, S, E>
+where
+    S: 'static,
+{
+    fn max_number_of_audio_inputs(&self) -> usize {
+        self.number_of_inputs
+    }
+
+    fn max_number_of_audio_outputs(&self) -> usize {
+        self.number_of_outputs
+    }
+}
+
+/// The latency of the wrapped renderer and of the conversion, in frames at the sample rate
+/// of the back-end.
+impl<R, S, E> Latency for SampleRateAdapter<R, S, E>
+where
+    R: Latency,
+    S: 'static,
+{
+    fn latency_in_frames(&self) -> usize {
+        let ratio = self.inner_sample_rate / self.outer_sample_rate;
+        ((self.output_delay + self.inner.latency_in_frames()) as f64 / ratio).round() as usize
+    }
+}
+
+impl<R, S, E> Lifecycle for SampleRateAdapter<R, S, E>
+where
+    R: Lifecycle,
+    S: Zero + Clone + 'static,
+{
+    fn on_resume(&mut self) {
+        self.restart();
+        self.inner.on_resume();
+    }
+
+    fn on_suspend(&mut self) {
+        self.inner.on_suspend();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::trivial_renderers::PassThrough;
+
+    fn render(adapter: &mut SampleRateAdapter<PassThrough, f64, ()>, input: &[f64]) -> Vec<f64> {
+        let mut output = Vec::new();
+        for buffer in input.chunks(64) {
+            let mut buffer_output = vec![0.0; buffer.len()];
+            AudioRenderer::render_buffer(adapter, &[buffer], &mut [&mut buffer_output[..]]);
+            output.extend_from_slice(&buffer_output);
+        }
+        output
+    }
+
+    #[test]
+    fn passes_the_audio_unchanged_at_the_same_sample_rate() {
+        let mut adapter = SampleRateAdapter::new(PassThrough::new(1), 44100.0, 1, 1, 64);
+        let input: Vec<f64> = (0..512).map(|i| ((i * 7) % 13) as f64 - 6.0).collect();
+        let output = render(&mut adapter, &input);
+        let latency = adapter.latency_in_frames();
+        for (expected, actual) in input.iter().zip(output[latency..].iter()) {
+            assert!((expected - actual).abs() < 1e-6);
+        }
+        assert!(output[..latency].iter().all(|sample| sample.abs() < 1e-6));
+    }
+
+    #[test]
+    fn converts_a_sine_to_another_sample_rate_and_back() {
+        for &outer_sample_rate in &[32000.0, 48000.0, 96000.0] {
+            let mut adapter = SampleRateAdapter::new(PassThrough::new(1), 44100.0, 1, 1, 64);
+            adapter.set_sample_rate(outer_sample_rate);
+            let frequency = 1000.0;
+            let input: Vec<f64> = (0..4096)
+                .map(|i| (2.0 * PI * frequency * i as f64 / outer_sample_rate).sin())
+                .collect();
+            let output = render(&mut adapter, &input);
+            // The exact delay is not a whole number of frames, so compare with the delayed sine.
+            let delay = adapter.output_delay as f64 * outer_sample_rate / 44100.0;
+            for (index, actual) in output.iter().enumerate().skip(1024) {
+                let expected =
+                    (2.0 * PI * frequency * (index as f64 - delay) / outer_sample_rate).sin();
+                assert!(
+                    (expected - actual).abs() < 0.01,
+                    "{} Hz, frame {}: expected {}, got {}",
+                    outer_sample_rate,
+                    index,
+                    expected,
+                    actual
+                );
+            }
+        }
+    }
+
+    // Records the time of the events, in frames since it started rendering.
+    #[derive(Default)]
+    struct EventRecorder {
+        frames: u64,
+        times: Vec<u64>,
+    }
+
+    impl AudioHandler for EventRecorder {
+        fn set_sample_rate(&mut self, _sample_rate: f64) {}
+    }
+
+    impl EventHandler<Timed<()>> for EventRecorder {
+        fn handle_event(&mut self, event: Timed<()>) {
+            self.times.push(self.frames + event.time_in_frames as u64);
+        }
+    }
+
+    impl AudioRenderer<f32> for EventRecorder {
+        fn render_buffer(&mut self, _inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+            self.frames += outputs[0].len() as u64;
+        }
+    }
+
+    #[test]
+    fn converts_the_time_of_the_events() {
+        let mut adapter = SampleRateAdapter::new(EventRecorder::default(), 96000.0, 0, 1, 64);
+        adapter.set_sample_rate(48000.0);
+        let mut output = [0.0_f32; 64];
+        for _ in 0..4 {
+            adapter.handle_event(Timed::new(10, ()));
+            AudioRenderer::render_buffer(&mut adapter, &[], &mut [&mut output[..]]);
+        }
+        // The events are at frames 10, 74, 138 and 202 at 48000 Hz.
+        assert_eq!(adapter.inner().times, vec![20, 148, 276, 404]);
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+// A single-producer single-consumer ring buffer. Samples are stored as the bits of an `f32`,
+// so that no unsafe code is needed.
+pub(crate) struct RingBuffer {
+    samples: Box<[AtomicU32]>,
+    // The read and write positions only increase (and wrap around); the capacity is a power of
+    // two, so that wrapping around does not cause a discontinuity.
+    read_position: AtomicUsize,
+    write_position: AtomicUsize,
+}
+
+impl RingBuffer {
+    pub(crate) fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        RingBuffer {
+            samples: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+            read_position: AtomicUsize::new(0),
+            write_position: AtomicUsize::new(0),
+        }
+    }
+
+    fn index(&self, position: usize) -> usize {
+        position & (self.samples.len() - 1)
+    }
+
+    // Only called by the consumer.
+    pub(crate) fn available(&self) -> usize {
+        let write_position = self.write_position.load(Ordering::Acquire);
+        write_position.wrapping_sub(self.read_position.load(Ordering::Relaxed))
+    }
+
+    // Only called by the consumer, after checking that enough samples are available.
+    pub(crate) fn pop(&self, destination: &mut [f32]) {
+        let read_position = self.read_position.load(Ordering::Relaxed);
+        for (offset, sample) in destination.iter_mut().enumerate() {
+            let index = self.index(read_position.wrapping_add(offset));
+            *sample = f32::from_bits(self.samples[index].load(Ordering::Relaxed));
+        }
+        self.read_position.store(
+            read_position.wrapping_add(destination.len()),
+            Ordering::Release,
+        );
+    }
+
+    // Only called by the producer.
+    pub(crate) fn free(&self) -> usize {
+        let read_position = self.read_position.load(Ordering::Acquire);
+        let write_position = self.write_position.load(Ordering::Relaxed);
+        self.samples.len() - write_position.wrapping_sub(read_position)
+    }
+
+    // Only called by the producer, after checking that there is enough room.
+    pub(crate) fn push(&self, source: &[f32]) {
+        let write_position = self.write_position.load(Ordering::Relaxed);
+        for (offset, sample) in source.iter().enumerate() {
+            let index = self.index(write_position.wrapping_add(offset));
+            self.samples[index].store(sample.to_bits(), Ordering::Relaxed);
+        }
+        self.write_position
+            .store(write_position.wrapping_add(source.len()), Ordering::Release);
+    }
+
+    // Only called by the producer, while the consumer is not reading.
+    pub(crate) fn clear(&self) {
+        let read_position = self.read_position.load(Ordering::Acquire);
+        self.write_position.store(read_position, Ordering::Release);
+    }
+
+    // Only called by the consumer: skip all samples that are available.
+    #[cfg_attr(not(feature = "recorder"), allow(dead_code))]
+    pub(crate) fn discard(&self) {
+        let write_position = self.write_position.load(Ordering::Acquire);
+        self.read_position.store(write_position, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_wraps_around() {
+        let ring_buffer = RingBuffer::new(4);
+        let mut destination = [0.0; 3];
+        for round in 0..5 {
+            let base = round as f32 * 10.0;
+            assert_eq!(ring_buffer.free(), 4);
+            ring_buffer.push(&[base, base + 1.0, base + 2.0]);
+            assert_eq!(ring_buffer.available(), 3);
+            ring_buffer.pop(&mut destination);
+            assert_eq!(destination, [base, base + 1.0, base + 2.0]);
+        }
+    }
+}
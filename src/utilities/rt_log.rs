@@ -0,0 +1,404 @@
+//! Logging from the real-time thread.
+//!
+//! The macros of the `log` crate call the logger directly. Most loggers allocate memory,
+//! take locks or write to a file, so they should not be used in the audio thread.
+//! The [`rt_log!`] macro formats the message into a fixed-size record and pushes it onto a
+//! preallocated lock-free queue. A background thread drains the queue and passes the messages
+//! to the `log` crate.
+//!
+//! Usage
+//! -----
+//! Call [`init`] once, outside the real-time thread, e.g. when the plugin is created.
+//! Messages that are logged before [`init`] has been called are silently dropped.
+//! When the queue is full, messages are dropped as well; the number of dropped messages is
+//! reported by the background thread.
+//!
+//! ```
+//! # #[macro_use]
+//! # extern crate rsynth;
+//! use rsynth::utilities::rt_log::{self, Level};
+//! use std::time::Duration;
+//!
+//! # fn main() {
+//! rt_log::init(1024, Duration::from_millis(50)).expect("Only initialized once.");
+//! // In the audio thread:
+//! let number_of_frames = 64;
+//! rt_log!(Level::Debug, "Rendering {} frames.", number_of_frames);
+//! # }
+//! ```
+//!
+//! Real-time safety
+//! ----------------
+//! Logging with [`rt_log!`] does not allocate and does not lock, as long as the arguments can be
+//! formatted without allocating memory (this is the case for numbers and string slices).
+//! Messages longer than [`MAX_MESSAGE_LENGTH`] bytes are truncated.
+//!
+//! [`rt_log!`]: ../../macro.rt_log.html
+//! [`init`]: ./fn.init.html
+//! [`MAX_MESSAGE_LENGTH`]: ./constant.MAX_MESSAGE_LENGTH.html
+use std::cell::UnsafeCell;
+use std::fmt::{self, Write};
+use std::io;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+pub use log::Level;
+
+/// The maximum length of a message in bytes. Longer messages are truncated.
+pub const MAX_MESSAGE_LENGTH: usize = 256;
+
+/// A log message, formatted without allocating memory.
+#[derive(Clone, Copy)]
+pub struct RtLogRecord {
+    level: Level,
+    target: &'static str,
+    length: usize,
+    message: [u8; MAX_MESSAGE_LENGTH],
+}
+
+impl RtLogRecord {
+    /// Format a new record. The message is truncated to [`MAX_MESSAGE_LENGTH`] bytes.
+    ///
+    /// [`MAX_MESSAGE_LENGTH`]: ./constant.MAX_MESSAGE_LENGTH.html
+    pub fn new(level: Level, target: &'static str, args: fmt::Arguments) -> Self {
+        let mut record = RtLogRecord {
+            level,
+            target,
+            length: 0,
+            message: [0; MAX_MESSAGE_LENGTH],
+        };
+        // An error only means that the message has been truncated.
+        let _ = record.write_fmt(args);
+        record
+    }
+
+    /// The level of the message.
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// The target of the message, by default the module path of the place where it was logged.
+    pub fn target(&self) -> &'static str {
+        self.target
+    }
+
+    /// The (possibly truncated) message.
+    pub fn message(&self) -> &str {
+        // `write_str` only cuts messages at character boundaries.
+        std::str::from_utf8(&self.message[..self.length]).unwrap_or_default()
+    }
+}
+
+impl Write for RtLogRecord {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut length = s.len().min(MAX_MESSAGE_LENGTH - self.length);
+        while !s.is_char_boundary(length) {
+            length -= 1;
+        }
+        self.message[self.length..self.length + length].copy_from_slice(&s.as_bytes()[..length]);
+        self.length += length;
+        if length < s.len() {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+struct Slot {
+    sequence: AtomicUsize,
+    record: UnsafeCell<RtLogRecord>,
+}
+
+/// A bounded lock-free queue of log records that supports several producers.
+///
+/// This is used behind the scenes by [`rt_log!`], but it can also be used on its own,
+/// e.g. to drain the records in a thread that you already have.
+///
+/// [`rt_log!`]: ../../macro.rt_log.html
+pub struct RtLogQueue {
+    slots: Box<[Slot]>,
+    mask: usize,
+    enqueue_position: AtomicUsize,
+    dequeue_position: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+// Each slot is only accessed by the thread that has claimed it with the sequence number.
+unsafe impl Sync for RtLogQueue {}
+
+impl RtLogQueue {
+    /// Create a new queue that can contain at least `capacity` records.
+    /// The capacity is rounded up to a power of two.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory and cannot be used in a real-time context.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        let slots = (0..capacity)
+            .map(|index| Slot {
+                sequence: AtomicUsize::new(index),
+                record: UnsafeCell::new(RtLogRecord::new(Level::Trace, "", format_args!(""))),
+            })
+            .collect();
+        RtLogQueue {
+            slots,
+            mask: capacity - 1,
+            enqueue_position: AtomicUsize::new(0),
+            dequeue_position: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// The maximum number of records in the queue.
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Push a record onto the queue.
+    /// Return `false` if the queue is full, in which case the record is dropped.
+    pub fn push(&self, record: RtLogRecord) -> bool {
+        let mut position = self.enqueue_position.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let difference = sequence.wrapping_sub(position) as isize;
+            if difference == 0 {
+                match self.enqueue_position.compare_exchange_weak(
+                    position,
+                    position.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe {
+                            *slot.record.get() = record;
+                        }
+                        slot.sequence
+                            .store(position.wrapping_add(1), Ordering::Release);
+                        return true;
+                    }
+                    Err(current) => position = current,
+                }
+            } else if difference < 0 {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return false;
+            } else {
+                position = self.enqueue_position.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Pop the oldest record from the queue, if any.
+    pub fn pop(&self) -> Option<RtLogRecord> {
+        let mut position = self.dequeue_position.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let difference = sequence.wrapping_sub(position.wrapping_add(1)) as isize;
+            if difference == 0 {
+                match self.dequeue_position.compare_exchange_weak(
+                    position,
+                    position.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let record = unsafe { *slot.record.get() };
+                        slot.sequence
+                            .store(position.wrapping_add(self.mask + 1), Ordering::Release);
+                        return Some(record);
+                    }
+                    Err(current) => position = current,
+                }
+            } else if difference < 0 {
+                return None;
+            } else {
+                position = self.dequeue_position.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Return the number of records that have been dropped because the queue was full
+    /// and reset this number to zero.
+    pub fn take_number_of_dropped_records(&self) -> usize {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+
+    /// Pass all records in the queue to the `log` crate.
+    /// Return the number of records that have been passed.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// The logger may allocate memory or take locks.
+    pub fn drain_into_log(&self) -> usize {
+        let mut number_of_records = 0;
+        while let Some(record) = self.pop() {
+            log!(target: record.target(), record.level(), "{}", record.message());
+            number_of_records += 1;
+        }
+        let dropped = self.take_number_of_dropped_records();
+        if dropped > 0 {
+            warn!(
+                "{} real-time log messages have been dropped because the queue was full.",
+                dropped
+            );
+        }
+        number_of_records
+    }
+}
+
+static QUEUE: AtomicPtr<RtLogQueue> = AtomicPtr::new(ptr::null_mut());
+
+/// The error type that represents the errors you can get from the [`init`] function.
+///
+/// [`init`]: ./fn.init.html
+#[derive(Debug)]
+pub enum RtLogInitError {
+    /// [`init`] has already been called.
+    ///
+    /// [`init`]: ./fn.init.html
+    AlreadyInitialized,
+    /// The background thread could not be started.
+    ThreadSpawnError(io::Error),
+}
+
+/// Allocate the queue for [`rt_log!`] and start the background thread that drains it every
+/// `poll_interval`.
+///
+/// This function can only be called once; the queue and the background thread live until the
+/// end of the program.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------
+/// This function allocates memory and starts a thread.
+///
+/// [`rt_log!`]: ../../macro.rt_log.html
+pub fn init(capacity: usize, poll_interval: Duration) -> Result<(), RtLogInitError> {
+    let queue = Box::into_raw(Box::new(RtLogQueue::new(capacity)));
+    if QUEUE
+        .compare_exchange(ptr::null_mut(), queue, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        drop(unsafe { Box::from_raw(queue) });
+        return Err(RtLogInitError::AlreadyInitialized);
+    }
+    // The queue is never deallocated, because `rt_log!` may be called at any time.
+    let queue: &'static RtLogQueue = unsafe { &*queue };
+    thread::Builder::new()
+        .name("rt_log".to_string())
+        .spawn(move || loop {
+            queue.drain_into_log();
+            thread::sleep(poll_interval);
+        })
+        .map_err(RtLogInitError::ThreadSpawnError)?;
+    Ok(())
+}
+
+#[doc(hidden)]
+pub fn __log(level: Level, target: &'static str, args: fmt::Arguments) {
+    if level > log::max_level() {
+        return;
+    }
+    if let Some(queue) = unsafe { QUEUE.load(Ordering::Acquire).as_ref() } {
+        queue.push(RtLogRecord::new(level, target, args));
+    }
+}
+
+/// Log a message from the real-time thread.
+///
+/// The syntax is the same as for the `log!` macro of the `log` crate:
+/// `rt_log!(level, "format string", arguments...)`, optionally preceded by `target: "target",`.
+/// See the [`rt_log`] module for more information.
+///
+/// [`rt_log`]: ./utilities/rt_log/index.html
+#[macro_export]
+macro_rules! rt_log {
+    (target: $target:expr, $level:expr, $($arg:tt)+) => {
+        $crate::utilities::rt_log::__log($level, $target, format_args!($($arg)+))
+    };
+    ($level:expr, $($arg:tt)+) => {
+        $crate::rt_log!(target: module_path!(), $level, $($arg)+)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn record(message: &str) -> RtLogRecord {
+        RtLogRecord::new(Level::Info, "test", format_args!("{}", message))
+    }
+
+    #[test]
+    fn records_are_popped_in_the_order_they_are_pushed() {
+        let queue = RtLogQueue::new(4);
+        assert!(queue.push(record("first")));
+        assert!(queue.push(record("second")));
+        assert_eq!(
+            queue.pop().map(|r| r.message().to_string()),
+            Some("first".to_string())
+        );
+        assert_eq!(
+            queue.pop().map(|r| r.message().to_string()),
+            Some("second".to_string())
+        );
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn records_are_dropped_when_the_queue_is_full() {
+        let queue = RtLogQueue::new(2);
+        assert!(queue.push(record("a")));
+        assert!(queue.push(record("b")));
+        assert!(!queue.push(record("c")));
+        assert_eq!(queue.take_number_of_dropped_records(), 1);
+        assert_eq!(queue.take_number_of_dropped_records(), 0);
+        assert!(queue.pop().is_some());
+        assert!(queue.push(record("d")));
+    }
+
+    #[test]
+    fn long_messages_are_truncated_at_a_character_boundary() {
+        let message = "é".repeat(MAX_MESSAGE_LENGTH);
+        let record = record(&message);
+        assert_eq!(record.message().len(), MAX_MESSAGE_LENGTH);
+        let record = RtLogRecord::new(Level::Info, "test", format_args!("a{}", message));
+        assert_eq!(record.message().len(), MAX_MESSAGE_LENGTH - 1);
+        assert!(record.message().starts_with("aé"));
+    }
+
+    #[test]
+    fn several_threads_can_push_at_the_same_time() {
+        const NUMBER_OF_THREADS: usize = 4;
+        const RECORDS_PER_THREAD: usize = 100;
+        let queue = Arc::new(RtLogQueue::new(NUMBER_OF_THREADS * RECORDS_PER_THREAD));
+        let threads: Vec<_> = (0..NUMBER_OF_THREADS)
+            .map(|thread_index| {
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || {
+                    for index in 0..RECORDS_PER_THREAD {
+                        let record = RtLogRecord::new(
+                            Level::Info,
+                            "test",
+                            format_args!("{} {}", thread_index, index),
+                        );
+                        assert!(queue.push(record));
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().expect("Thread should not panic.");
+        }
+        let mut number_of_records = 0;
+        while queue.pop().is_some() {
+            number_of_records += 1;
+        }
+        assert_eq!(number_of_records, NUMBER_OF_THREADS * RECORDS_PER_THREAD);
+    }
+}
@@ -0,0 +1,355 @@
+//! Map incoming notes to the notes of a scale or a chord.
+//!
+//! Wrap the plugin in a [`ScaleQuantizer`] to replace every incoming note by a note of the
+//! configured [`Scale`] in the configured key, before the note reaches the plugin.
+//! The quantizer remembers which note it has sent for every key that is pressed, so that the
+//! note off (and the polyphonic key pressure) are sent for the same note, even when the scale
+//! or the key has changed in the meantime.
+//!
+//! All other events, the audio, the sample rate and the meta-data are forwarded to the wrapped
+//! plugin unchanged.
+//!
+//! Example
+//! -------
+//! ```
+//! use rsynth::event::{EventHandler, RawMidiEvent, Timed};
+//! use rsynth::utilities::scale_quantizer::{QuantizeStrategy, Scale, ScaleQuantizer};
+//!
+//! struct Recorder(Vec<u8>);
+//! impl EventHandler<Timed<RawMidiEvent>> for Recorder {
+//!     fn handle_event(&mut self, timed: Timed<RawMidiEvent>) {
+//!         self.0.push(timed.event.data()[1]);
+//!     }
+//! }
+//!
+//! // D minor: D = 2.
+//! let mut quantizer = ScaleQuantizer::new(Recorder(Vec::new()), Scale::MINOR, 2);
+//! quantizer.set_strategy(QuantizeStrategy::Up);
+//! // C# (61) is not in D minor; the next note up is D (62).
+//! quantizer.handle_event(Timed::new(0, RawMidiEvent::new(&[0x90, 61, 100])));
+//! assert_eq!(quantizer.inner().0, vec![62]);
+//! ```
+//!
+//! [`ScaleQuantizer`]: ./struct.ScaleQuantizer.html
+//! [`Scale`]: ./struct.Scale.html
+use crate::event::{ContextualEventHandler, EventHandler, RawMidiEvent, Timed};
+use crate::meta::Meta;
+use crate::{AudioHandler, AudioRenderer, ContextualAudioRenderer};
+use midi_consts::channel_event::{
+    EVENT_TYPE_MASK, MIDI_CHANNEL_MASK, NOTE_OFF, NOTE_ON, POLYPHONIC_KEY_PRESSURE,
+};
+
+/// A set of pitch classes, relative to the key.
+///
+/// Bit `n` is set when the note `n` semitones above the key is part of the scale.
+/// Scales and chords are represented in the same way: a chord is a scale with fewer notes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Scale(u16);
+
+impl Scale {
+    pub const CHROMATIC: Scale = Scale(0b1111_1111_1111);
+    pub const MAJOR: Scale = Scale::from_intervals(&[0, 2, 4, 5, 7, 9, 11]);
+    pub const MINOR: Scale = Scale::from_intervals(&[0, 2, 3, 5, 7, 8, 10]);
+    pub const HARMONIC_MINOR: Scale = Scale::from_intervals(&[0, 2, 3, 5, 7, 8, 11]);
+    pub const MELODIC_MINOR: Scale = Scale::from_intervals(&[0, 2, 3, 5, 7, 9, 11]);
+    pub const DORIAN: Scale = Scale::from_intervals(&[0, 2, 3, 5, 7, 9, 10]);
+    pub const MIXOLYDIAN: Scale = Scale::from_intervals(&[0, 2, 4, 5, 7, 9, 10]);
+    pub const MAJOR_PENTATONIC: Scale = Scale::from_intervals(&[0, 2, 4, 7, 9]);
+    pub const MINOR_PENTATONIC: Scale = Scale::from_intervals(&[0, 3, 5, 7, 10]);
+    pub const BLUES: Scale = Scale::from_intervals(&[0, 3, 5, 6, 7, 10]);
+    pub const MAJOR_TRIAD: Scale = Scale::from_intervals(&[0, 4, 7]);
+    pub const MINOR_TRIAD: Scale = Scale::from_intervals(&[0, 3, 7]);
+    pub const DOMINANT_SEVENTH: Scale = Scale::from_intervals(&[0, 4, 7, 10]);
+    pub const MAJOR_SEVENTH: Scale = Scale::from_intervals(&[0, 4, 7, 11]);
+    pub const MINOR_SEVENTH: Scale = Scale::from_intervals(&[0, 3, 7, 10]);
+
+    /// Create a scale from the intervals in semitones above the key.
+    /// Intervals of an octave or more are reduced to less than an octave.
+    pub const fn from_intervals(intervals: &[u8]) -> Self {
+        let mut mask = 0;
+        let mut index = 0;
+        while index < intervals.len() {
+            mask |= 1 << (intervals[index] % 12);
+            index += 1;
+        }
+        Scale(mask)
+    }
+
+    /// Return `true` when the pitch class, `0..12` semitones above the key, is part of the scale.
+    pub fn contains(&self, interval: u8) -> bool {
+        self.0 & (1 << (interval % 12)) != 0
+    }
+
+    /// Return `true` when the scale contains no notes.
+    pub fn is_empty(&self) -> bool {
+        self.0 & 0b1111_1111_1111 == 0
+    }
+}
+
+/// What to do with a note that is not part of the scale.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum QuantizeStrategy {
+    /// Use the nearest note of the scale; when two notes are equally near, use the lower one.
+    Nearest,
+    /// Use the nearest note of the scale; when two notes are equally near, use the higher one.
+    NearestUp,
+    /// Use the nearest note of the scale below the note.
+    Down,
+    /// Use the nearest note of the scale above the note.
+    Up,
+}
+
+// No note has been sent for this key.
+const NO_NOTE: u8 = 0xFF;
+
+/// Wraps a plugin and maps incoming notes to the notes of a scale.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct ScaleQuantizer<P> {
+    inner: P,
+    scale: Scale,
+    key: u8,
+    strategy: QuantizeStrategy,
+    // For every channel and every incoming key, the note that has been sent.
+    sent_notes: [[u8; 128]; 16],
+}
+
+impl<P> ScaleQuantizer<P> {
+    /// Wrap the given plugin. `key` is the pitch class of the key (0 = C, 1 = C#, ..., 11 = B).
+    /// The default strategy is [`QuantizeStrategy::Nearest`].
+    ///
+    /// [`QuantizeStrategy::Nearest`]: ./enum.QuantizeStrategy.html#variant.Nearest
+    pub fn new(inner: P, scale: Scale, key: u8) -> Self {
+        ScaleQuantizer {
+            inner,
+            scale,
+            key: key % 12,
+            strategy: QuantizeStrategy::Nearest,
+            sent_notes: [[NO_NOTE; 128]; 16],
+        }
+    }
+
+    /// Set the scale that is used for the next notes.
+    pub fn set_scale(&mut self, scale: Scale) {
+        self.scale = scale;
+    }
+
+    /// Set the key that is used for the next notes (0 = C, 1 = C#, ..., 11 = B).
+    pub fn set_key(&mut self, key: u8) {
+        self.key = key % 12;
+    }
+
+    /// Set the strategy for notes that are not part of the scale.
+    pub fn set_strategy(&mut self, strategy: QuantizeStrategy) {
+        self.strategy = strategy;
+    }
+
+    /// Map a note to a note of the scale.
+    /// The note is returned unchanged when the scale is empty or when no note of the scale
+    /// can be found within the MIDI range.
+    pub fn quantize(&self, note: u8) -> u8 {
+        if self.scale.is_empty() {
+            return note;
+        }
+        let is_in_scale = |note: i16| {
+            (0..128).contains(&note) && self.scale.contains((note - self.key as i16 + 12) as u8)
+        };
+        let note = note as i16;
+        for distance in 0..12 {
+            let down = note - distance;
+            let up = note + distance;
+            let candidate = match self.strategy {
+                QuantizeStrategy::Nearest if is_in_scale(down) => Some(down),
+                QuantizeStrategy::Nearest if is_in_scale(up) => Some(up),
+                QuantizeStrategy::NearestUp if is_in_scale(up) => Some(up),
+                QuantizeStrategy::NearestUp if is_in_scale(down) => Some(down),
+                QuantizeStrategy::Down if is_in_scale(down) => Some(down),
+                QuantizeStrategy::Up if is_in_scale(up) => Some(up),
+                _ => None,
+            };
+            if let Some(candidate) = candidate {
+                return candidate as u8;
+            }
+        }
+        note as u8
+    }
+
+    /// Get a reference to the wrapped plugin.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the wrapped plugin.
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.inner
+    }
+
+    /// Return the wrapped plugin.
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+
+    fn map_event(&mut self, timed: Timed<RawMidiEvent>) -> Timed<RawMidiEvent> {
+        let data = timed.event.data();
+        let (status, key, value) = (data[0], data[1] & 0x7F, data[2]);
+        let channel = (status & MIDI_CHANNEL_MASK) as usize;
+        let sent_note = self.sent_notes[channel][key as usize];
+        let note = match status & EVENT_TYPE_MASK {
+            NOTE_ON if value > 0 => {
+                let note = self.quantize(key);
+                self.sent_notes[channel][key as usize] = note;
+                note
+            }
+            NOTE_ON | NOTE_OFF => {
+                self.sent_notes[channel][key as usize] = NO_NOTE;
+                if sent_note == NO_NOTE {
+                    self.quantize(key)
+                } else {
+                    sent_note
+                }
+            }
+            POLYPHONIC_KEY_PRESSURE if sent_note != NO_NOTE => sent_note,
+            _ => return timed,
+        };
+        Timed::new(
+            timed.time_in_frames,
+            RawMidiEvent::new(&[status, note, value]),
+        )
+    }
+}
+
+impl<P> EventHandler<Timed<RawMidiEvent>> for ScaleQuantizer<P>
+where
+    P: EventHandler<Timed<RawMidiEvent>>,
+{
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+        let event = self.map_event(event);
+        self.inner.handle_event(event);
+    }
+}
+
+impl<P, Context> ContextualEventHandler<Timed<RawMidiEvent>, Context> for ScaleQuantizer<P>
+where
+    P: ContextualEventHandler<Timed<RawMidiEvent>, Context>,
+{
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>, context: &mut Context) {
+        let event = self.map_event(event);
+        self.inner.handle_event(event, context);
+    }
+}
+
+impl<P, S> AudioRenderer<S> for ScaleQuantizer<P>
+where
+    P: AudioRenderer<S>,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        self.inner.render_buffer(inputs, outputs);
+    }
+}
+
+impl<P, S, Context> ContextualAudioRenderer<S, Context> for ScaleQuantizer<P>
+where
+    P: ContextualAudioRenderer<S, Context>,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], context: &mut Context) {
+        self.inner.render_buffer(inputs, outputs, context);
+    }
+}
+
+impl<P> AudioHandler for ScaleQuantizer<P>
+where
+    P: AudioHandler,
+{
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.inner.set_sample_rate(sample_rate);
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.inner.set_max_buffer_size(max_buffer_size);
+    }
+}
+
+impl<P> Meta for ScaleQuantizer<P>
+where
+    P: Meta,
+{
+    type MetaData = P::MetaData;
+
+    fn meta(&self) -> &Self::MetaData {
+        self.inner.meta()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Collector(Vec<[u8; 3]>);
+
+    impl EventHandler<Timed<RawMidiEvent>> for Collector {
+        fn handle_event(&mut self, timed: Timed<RawMidiEvent>) {
+            let data = timed.event.data();
+            self.0.push([data[0], data[1], data[2]]);
+        }
+    }
+
+    fn event(status: u8, key: u8, value: u8) -> Timed<RawMidiEvent> {
+        Timed::new(0, RawMidiEvent::new(&[status, key, value]))
+    }
+
+    #[test]
+    fn strategies_choose_different_neighbours() {
+        // C major; F# (66) is between F (65) and G (67).
+        let mut quantizer = ScaleQuantizer::new((), Scale::MAJOR, 0);
+        assert_eq!(quantizer.quantize(60), 60);
+        assert_eq!(quantizer.quantize(66), 65);
+        quantizer.set_strategy(QuantizeStrategy::NearestUp);
+        assert_eq!(quantizer.quantize(66), 67);
+        quantizer.set_strategy(QuantizeStrategy::Down);
+        assert_eq!(quantizer.quantize(63), 62);
+        quantizer.set_strategy(QuantizeStrategy::Up);
+        assert_eq!(quantizer.quantize(63), 64);
+    }
+
+    #[test]
+    fn chord_in_another_key() {
+        // A minor triad: A, C, E.
+        let quantizer = ScaleQuantizer::new((), Scale::MINOR_TRIAD, 9);
+        assert_eq!(quantizer.quantize(59), 60);
+        assert_eq!(quantizer.quantize(62), 60);
+        assert_eq!(quantizer.quantize(66), 64);
+        assert_eq!(quantizer.quantize(68), 69);
+    }
+
+    #[test]
+    fn stays_within_the_midi_range() {
+        let mut quantizer = ScaleQuantizer::new((), Scale::from_intervals(&[0]), 0);
+        quantizer.set_strategy(QuantizeStrategy::Up);
+        // The next C would be 132.
+        assert_eq!(quantizer.quantize(127), 127);
+        quantizer.set_strategy(QuantizeStrategy::Nearest);
+        assert_eq!(quantizer.quantize(127), 120);
+    }
+
+    #[test]
+    fn note_off_is_sent_for_the_note_that_was_sent() {
+        let mut quantizer = ScaleQuantizer::new(Collector(Vec::new()), Scale::MAJOR, 0);
+        quantizer.handle_event(event(NOTE_ON | 3, 66, 100));
+        // Changing the key while the note is held does not affect the note off.
+        quantizer.set_key(1);
+        quantizer.handle_event(event(POLYPHONIC_KEY_PRESSURE | 3, 66, 50));
+        quantizer.handle_event(event(NOTE_OFF | 3, 66, 0));
+        quantizer.handle_event(event(0xB3, 66, 1));
+        assert_eq!(
+            quantizer.inner().0,
+            vec![
+                [NOTE_ON | 3, 65, 100],
+                [POLYPHONIC_KEY_PRESSURE | 3, 65, 50],
+                [NOTE_OFF | 3, 65, 0],
+                [0xB3, 66, 1],
+            ]
+        );
+    }
+}
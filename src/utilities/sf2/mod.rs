@@ -0,0 +1,761 @@
+//! Play SoundFont 2 (SF2) files.
+//!
+//! Support is only enabled if you compile with the "sf2" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! A [`SoundFont`] is loaded from a file (outside the real-time thread). Each of its
+//! [`Preset`]s (the "instruments" a user can choose with a program change) consists of a number
+//! of [`Region`]s: a sample that is played for a range of keys and velocities, with its loop
+//! points, tuning, volume envelope and filter.
+//! The generators of the preset and instrument levels of the file are already combined,
+//! as described in the SF2 specification, so a region contains all that is needed to play it.
+//!
+//! The [`Sf2Voice`] plays the regions of a preset and can be used with the
+//! [`polyphony`] utilities. Sharing the `SoundFont` between voices (in an `Arc`) is cheap.
+//!
+//! Supported features
+//! ------------------
+//! * key and velocity ranges, with up to [`MAX_LAYERS`] regions played at the same time
+//! * sample offsets, loop points and the loop modes
+//! * root key, coarse and fine tuning, scale tuning and the pitch correction of the sample
+//! * the volume envelope (delay, attack, hold, decay, sustain and release)
+//! * initial attenuation and pan
+//! * the initial low-pass filter cutoff and resonance
+//!
+//! Modulators, the modulation envelope, the LFOs, exclusive classes and the effect sends are not
+//! supported. The default "velocity to attenuation" modulator is approximated.
+//!
+//! Example
+//! -------
+//! ```no_run
+//! use rsynth::utilities::polyphony::{
+//!     simple_event_dispatching::SimpleEventDispatcher, EventDispatcher,
+//!     RawMidiEventToneIdentifierDispatchClassifier,
+//! };
+//! use rsynth::utilities::sf2::{Sf2Voice, SoundFont};
+//! use rsynth::event::{RawMidiEvent, Timed};
+//! use rsynth::AudioRenderer;
+//! use std::sync::Arc;
+//!
+//! let sound_font = Arc::new(SoundFont::from_file("GeneralUser.sf2").expect("Cannot load."));
+//! let mut voices: Vec<_> = (0..32).map(|_| Sf2Voice::new(Arc::clone(&sound_font))).collect();
+//! let mut dispatcher: SimpleEventDispatcher<RawMidiEventToneIdentifierDispatchClassifier, _> =
+//!     SimpleEventDispatcher::default();
+//!
+//! // In the audio thread:
+//! let note_on = Timed::new(0, RawMidiEvent::new(&[0x90, 60, 100]));
+//! dispatcher.dispatch_event(note_on, &mut voices);
+//! let mut left = [0.0_f32; 64];
+//! let mut right = [0.0_f32; 64];
+//! for voice in voices.iter_mut() {
+//!     voice.render_buffer(&[], &mut [&mut left, &mut right]);
+//! }
+//! ```
+//!
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`SoundFont`]: ./struct.SoundFont.html
+//! [`Preset`]: ./struct.Preset.html
+//! [`Region`]: ./struct.Region.html
+//! [`Sf2Voice`]: ./struct.Sf2Voice.html
+//! [`MAX_LAYERS`]: ./constant.MAX_LAYERS.html
+//! [`polyphony`]: ../polyphony/index.html
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+mod parser;
+mod voice;
+
+use self::parser::{Chunk, Generator, SampleHeader, ROM_SAMPLE};
+pub use self::voice::{Sf2Voice, MAX_LAYERS};
+
+/// The error type that represents the errors you can get when loading a [`SoundFont`].
+///
+/// [`SoundFont`]: ./struct.SoundFont.html
+#[derive(Debug)]
+pub enum Sf2Error {
+    /// An error occurred when reading the file.
+    Io(io::Error),
+    /// The data is not a SoundFont 2 file.
+    NotASoundFont,
+    /// A required chunk is missing.
+    MissingChunk(&'static str),
+    /// A chunk has an invalid size or refers to data that does not exist.
+    InvalidChunk(&'static str),
+}
+
+/// How a sample is looped.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LoopMode {
+    /// The sample is played once, without looping.
+    NoLoop,
+    /// The loop is repeated, also during the release.
+    Continuous,
+    /// The loop is repeated until the key is released, then the rest of the sample is played.
+    UntilRelease,
+}
+
+/// The parameters of the volume envelope. Times are in seconds.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EnvelopeParameters {
+    pub delay: f64,
+    pub attack: f64,
+    pub hold: f64,
+    /// The time needed to decay from full level to an attenuation of 96 dB.
+    pub decay: f64,
+    /// The attenuation during the sustain phase, in centibels (0 is full level).
+    pub sustain_attenuation: f64,
+    /// The time needed to go from full level to an attenuation of 96 dB.
+    pub release: f64,
+}
+
+/// A sample with the range of keys and velocities for which it is played and how it is played.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Region {
+    pub key_low: u8,
+    pub key_high: u8,
+    pub velocity_low: u8,
+    pub velocity_high: u8,
+    /// The index of the first frame of the sample in [`SoundFont::sample_data`].
+    ///
+    /// [`SoundFont::sample_data`]: ./struct.SoundFont.html#method.sample_data
+    pub sample_start: usize,
+    /// The index after the last frame of the sample.
+    pub sample_end: usize,
+    /// The index of the first frame of the loop.
+    pub loop_start: usize,
+    /// The index after the last frame of the loop.
+    pub loop_end: usize,
+    pub loop_mode: LoopMode,
+    /// The sample rate at which the sample has been recorded.
+    pub sample_rate: f64,
+    /// The key at which the sample is played at its original pitch.
+    pub root_key: u8,
+    /// The tuning, in cents, including the pitch correction of the sample.
+    pub tuning: f64,
+    /// How much the pitch changes per key, in cents.
+    pub scale_tuning: f64,
+    /// The attenuation in centibels.
+    pub attenuation: f64,
+    /// The pan, from `-0.5` (left) to `0.5` (right).
+    pub pan: f64,
+    pub volume_envelope: EnvelopeParameters,
+    /// The cutoff frequency of the low-pass filter in Hz, `None` if the filter is not used.
+    pub filter_cutoff: Option<f64>,
+    /// The resonance of the low-pass filter in dB.
+    pub filter_resonance: f64,
+}
+
+impl Region {
+    /// Return `true` if this region is played for the given key and velocity.
+    pub fn contains(&self, key: u8, velocity: u8) -> bool {
+        self.key_low <= key
+            && key <= self.key_high
+            && self.velocity_low <= velocity
+            && velocity <= self.velocity_high
+    }
+}
+
+/// A preset, which can be selected with a bank select and a program change.
+pub struct Preset {
+    name: String,
+    bank: u16,
+    program: u16,
+    regions: Vec<Region>,
+}
+
+impl Preset {
+    /// The name of the preset.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The bank of the preset. Percussion presets are typically in bank 128.
+    pub fn bank(&self) -> u16 {
+        self.bank
+    }
+
+    /// The program number of the preset.
+    pub fn program(&self) -> u16 {
+        self.program
+    }
+
+    /// All regions of the preset.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// The regions that are played for the given key and velocity.
+    pub fn regions_for(&self, key: u8, velocity: u8) -> impl Iterator<Item = &Region> {
+        self.regions
+            .iter()
+            .filter(move |region| region.contains(key, velocity))
+    }
+}
+
+/// The presets and the sample data of a SoundFont 2 file.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct SoundFont {
+    presets: Vec<Preset>,
+    sample_data: Vec<f32>,
+}
+
+impl SoundFont {
+    /// Load a SoundFont 2 file.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory and reads a file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Sf2Error> {
+        Self::from_reader(File::open(path).map_err(Sf2Error::Io)?)
+    }
+
+    /// Load a SoundFont 2 file from a reader.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, Sf2Error> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).map_err(Sf2Error::Io)?;
+        Self::from_bytes(&data)
+    }
+
+    /// Load a SoundFont 2 file that is already in memory.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Sf2Error> {
+        let riff = parser::read_chunks(data)?
+            .into_iter()
+            .next()
+            .ok_or(Sf2Error::NotASoundFont)?;
+        if &riff.id != b"RIFF" || riff.list_type() != Some(&b"sfbk"[..]) {
+            return Err(Sf2Error::NotASoundFont);
+        }
+        let lists = riff.sub_chunks()?;
+        let sdta = find_list(&lists, "sdta")?;
+        let pdta = find_list(&lists, "pdta")?;
+
+        let sample_data = match sdta.iter().find(|chunk| &chunk.id == b"smpl") {
+            Some(chunk) => chunk
+                .data
+                .chunks_exact(2)
+                .map(|s| i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let preset_headers = parser::read_preset_headers(find_chunk(&pdta, "phdr")?)?;
+        let preset_bags = parser::read_bags(find_chunk(&pdta, "pbag")?, "pbag")?;
+        let preset_generators = parser::read_generators(find_chunk(&pdta, "pgen")?, "pgen")?;
+        let instrument_headers = parser::read_instrument_headers(find_chunk(&pdta, "inst")?)?;
+        let instrument_bags = parser::read_bags(find_chunk(&pdta, "ibag")?, "ibag")?;
+        let instrument_generators = parser::read_generators(find_chunk(&pdta, "igen")?, "igen")?;
+        let sample_headers = parser::read_sample_headers(find_chunk(&pdta, "shdr")?)?;
+
+        // Each instrument is a list of zones (one for each region) with its generators.
+        let mut instruments = Vec::new();
+        for pair in instrument_headers.windows(2) {
+            instruments.push(zones(
+                &instrument_bags,
+                &instrument_generators,
+                pair[0].bag_index,
+                pair[1].bag_index,
+                "ibag",
+                generator::SAMPLE_ID,
+            )?);
+        }
+
+        let mut presets = Vec::new();
+        // The last header is a terminator.
+        for pair in preset_headers.windows(2) {
+            let header = &pair[0];
+            let preset_zones = zones(
+                &preset_bags,
+                &preset_generators,
+                header.bag_index,
+                pair[1].bag_index,
+                "pbag",
+                generator::INSTRUMENT,
+            )?;
+            let mut regions = Vec::new();
+            for preset_zone in preset_zones.iter() {
+                let instrument = match preset_zone
+                    .amount(generator::INSTRUMENT)
+                    .and_then(|index| instruments.get(index as u16 as usize))
+                {
+                    Some(instrument) => instrument,
+                    None => continue,
+                };
+                for instrument_zone in instrument.iter() {
+                    if let Some(region) = region(
+                        preset_zone,
+                        instrument_zone,
+                        &sample_headers,
+                        sample_data.len(),
+                    ) {
+                        regions.push(region);
+                    }
+                }
+            }
+            presets.push(Preset {
+                name: header.name.clone(),
+                bank: header.bank,
+                program: header.program,
+                regions,
+            });
+        }
+
+        Ok(SoundFont {
+            presets,
+            sample_data,
+        })
+    }
+
+    /// All presets in the order in which they are defined in the file.
+    pub fn presets(&self) -> &[Preset] {
+        &self.presets
+    }
+
+    /// The index of the preset with the given bank and program, if any.
+    pub fn preset_index(&self, bank: u16, program: u16) -> Option<usize> {
+        self.presets
+            .iter()
+            .position(|preset| preset.bank == bank && preset.program == program)
+    }
+
+    /// All samples of the file, converted to `f32` between `-1.0` and `1.0`.
+    pub fn sample_data(&self) -> &[f32] {
+        &self.sample_data
+    }
+}
+
+fn find_list<'a>(lists: &[Chunk<'a>], list_type: &'static str) -> Result<Vec<Chunk<'a>>, Sf2Error> {
+    match lists
+        .iter()
+        .find(|chunk| &chunk.id == b"LIST" && chunk.list_type() == Some(list_type.as_bytes()))
+    {
+        Some(list) => list.sub_chunks(),
+        None => Err(Sf2Error::MissingChunk(list_type)),
+    }
+}
+
+fn find_chunk<'a>(chunks: &[Chunk<'a>], id: &'static str) -> Result<&'a [u8], Sf2Error> {
+    chunks
+        .iter()
+        .find(|chunk| &chunk.id[..] == id.as_bytes())
+        .map(|chunk| chunk.data)
+        .ok_or(Sf2Error::MissingChunk(id))
+}
+
+mod generator {
+    pub const START_ADDRS_OFFSET: usize = 0;
+    pub const END_ADDRS_OFFSET: usize = 1;
+    pub const STARTLOOP_ADDRS_OFFSET: usize = 2;
+    pub const ENDLOOP_ADDRS_OFFSET: usize = 3;
+    pub const START_ADDRS_COARSE_OFFSET: usize = 4;
+    pub const INITIAL_FILTER_FC: usize = 8;
+    pub const INITIAL_FILTER_Q: usize = 9;
+    pub const END_ADDRS_COARSE_OFFSET: usize = 12;
+    pub const PAN: usize = 17;
+    pub const DELAY_VOL_ENV: usize = 33;
+    pub const ATTACK_VOL_ENV: usize = 34;
+    pub const HOLD_VOL_ENV: usize = 35;
+    pub const DECAY_VOL_ENV: usize = 36;
+    pub const SUSTAIN_VOL_ENV: usize = 37;
+    pub const RELEASE_VOL_ENV: usize = 38;
+    pub const INSTRUMENT: usize = 41;
+    pub const KEY_RANGE: usize = 43;
+    pub const VEL_RANGE: usize = 44;
+    pub const STARTLOOP_ADDRS_COARSE_OFFSET: usize = 45;
+    pub const INITIAL_ATTENUATION: usize = 48;
+    pub const ENDLOOP_ADDRS_COARSE_OFFSET: usize = 50;
+    pub const COARSE_TUNE: usize = 51;
+    pub const FINE_TUNE: usize = 52;
+    pub const SAMPLE_ID: usize = 53;
+    pub const SAMPLE_MODES: usize = 54;
+    pub const SCALE_TUNING: usize = 56;
+    pub const OVERRIDING_ROOT_KEY: usize = 58;
+    pub const NUMBER_OF_GENERATORS: usize = 61;
+
+    // The default values as defined in the SF2 specification.
+    pub fn default_value(operator: usize) -> i32 {
+        match operator {
+            INITIAL_FILTER_FC => 13500,
+            DELAY_VOL_ENV | ATTACK_VOL_ENV | HOLD_VOL_ENV | DECAY_VOL_ENV | RELEASE_VOL_ENV => {
+                -12000
+            }
+            SCALE_TUNING => 100,
+            OVERRIDING_ROOT_KEY => -1,
+            _ => 0,
+        }
+    }
+}
+
+// The generators of one zone.
+#[derive(Clone, Copy)]
+struct Generators([Option<[u8; 2]>; generator::NUMBER_OF_GENERATORS]);
+
+impl Generators {
+    fn new(generators: &[Generator]) -> Self {
+        let mut result = Generators([None; generator::NUMBER_OF_GENERATORS]);
+        for generator in generators {
+            if let Some(amount) = result.0.get_mut(generator.operator as usize) {
+                *amount = Some(generator.amount);
+            }
+        }
+        result
+    }
+
+    // Generators of `local` override the generators of `self`.
+    fn overlay(&self, local: &Generators) -> Generators {
+        let mut result = *self;
+        for (amount, local_amount) in result.0.iter_mut().zip(local.0.iter()) {
+            if local_amount.is_some() {
+                *amount = *local_amount;
+            }
+        }
+        result
+    }
+
+    fn amount(&self, operator: usize) -> Option<i16> {
+        self.0[operator].map(i16::from_le_bytes)
+    }
+
+    fn value_or_default(&self, operator: usize) -> i32 {
+        self.amount(operator)
+            .map(i32::from)
+            .unwrap_or_else(|| generator::default_value(operator))
+    }
+
+    fn range(&self, operator: usize) -> (u8, u8) {
+        self.0[operator]
+            .map(|[low, high]| (low, high))
+            .unwrap_or((0, 127))
+    }
+}
+
+// Read the zones from `first_bag` up to `end_bag`. A first zone without the
+// `terminal_generator` is a global zone: its generators are the defaults for the other zones.
+fn zones(
+    bags: &[usize],
+    generators: &[Generator],
+    first_bag: usize,
+    end_bag: usize,
+    name: &'static str,
+    terminal_generator: usize,
+) -> Result<Vec<Generators>, Sf2Error> {
+    if first_bag > end_bag || end_bag >= bags.len() {
+        return Err(Sf2Error::InvalidChunk(name));
+    }
+    let mut global = Generators::new(&[]);
+    let mut result = Vec::new();
+    for bag in first_bag..end_bag {
+        let zone_generators = generators
+            .get(bags[bag]..bags[bag + 1])
+            .ok_or(Sf2Error::InvalidChunk(name))?;
+        let zone = Generators::new(zone_generators);
+        if zone.amount(terminal_generator).is_some() {
+            result.push(global.overlay(&zone));
+        } else if bag == first_bag {
+            global = zone;
+        }
+    }
+    Ok(result)
+}
+
+fn timecents_to_seconds(timecents: i32) -> f64 {
+    2.0_f64.powf(timecents.clamp(-12000, 8000) as f64 / 1200.0)
+}
+
+fn intersect(a: (u8, u8), b: (u8, u8)) -> Option<(u8, u8)> {
+    let low = a.0.max(b.0);
+    let high = a.1.min(b.1);
+    if low <= high {
+        Some((low, high))
+    } else {
+        None
+    }
+}
+
+// Combine a preset zone and an instrument zone into a region.
+// Return `None` when the region can never be played.
+fn region(
+    preset: &Generators,
+    instrument: &Generators,
+    sample_headers: &[SampleHeader],
+    sample_data_length: usize,
+) -> Option<Region> {
+    use self::generator::*;
+    let (key_low, key_high) = intersect(preset.range(KEY_RANGE), instrument.range(KEY_RANGE))?;
+    let (velocity_low, velocity_high) =
+        intersect(preset.range(VEL_RANGE), instrument.range(VEL_RANGE))?;
+    let sample = sample_headers.get(instrument.amount(SAMPLE_ID)? as u16 as usize)?;
+    if sample.sample_type & ROM_SAMPLE != 0 || sample.sample_rate == 0 {
+        return None;
+    }
+
+    // Generators at the preset level are added to the generators at the instrument level.
+    let value = |operator| {
+        instrument.value_or_default(operator) + preset.amount(operator).map(i32::from).unwrap_or(0)
+    };
+    // Some generators can only be used at the instrument level.
+    let instrument_value = |operator| instrument.value_or_default(operator);
+    let address = |base: u32, fine, coarse| {
+        let address =
+            base as i64 + instrument_value(fine) as i64 + 32768 * instrument_value(coarse) as i64;
+        address.max(0).min(sample_data_length as i64) as usize
+    };
+
+    let sample_start = address(sample.start, START_ADDRS_OFFSET, START_ADDRS_COARSE_OFFSET);
+    let sample_end = address(sample.end, END_ADDRS_OFFSET, END_ADDRS_COARSE_OFFSET);
+    if sample_end <= sample_start + 1 {
+        return None;
+    }
+    let loop_start = address(
+        sample.loop_start,
+        STARTLOOP_ADDRS_OFFSET,
+        STARTLOOP_ADDRS_COARSE_OFFSET,
+    );
+    let loop_end = address(
+        sample.loop_end,
+        ENDLOOP_ADDRS_OFFSET,
+        ENDLOOP_ADDRS_COARSE_OFFSET,
+    );
+    let loop_is_valid =
+        sample_start <= loop_start && loop_start < loop_end && loop_end <= sample_end;
+    let loop_mode = match instrument_value(SAMPLE_MODES) & 3 {
+        1 if loop_is_valid => LoopMode::Continuous,
+        3 if loop_is_valid => LoopMode::UntilRelease,
+        _ => LoopMode::NoLoop,
+    };
+
+    let root_key = match instrument_value(OVERRIDING_ROOT_KEY) {
+        key @ 0..=127 => key as u8,
+        _ if sample.original_pitch <= 127 => sample.original_pitch,
+        _ => 60,
+    };
+
+    let filter_cutoff_cents = value(INITIAL_FILTER_FC).clamp(1500, 13500);
+    let filter_resonance = value(INITIAL_FILTER_Q).clamp(0, 960);
+    let filter_cutoff = if filter_cutoff_cents >= 13500 && filter_resonance == 0 {
+        None
+    } else {
+        Some(8.176 * 2.0_f64.powf(filter_cutoff_cents as f64 / 1200.0))
+    };
+
+    Some(Region {
+        key_low,
+        key_high,
+        velocity_low,
+        velocity_high,
+        sample_start,
+        sample_end,
+        loop_start,
+        loop_end,
+        loop_mode,
+        sample_rate: sample.sample_rate as f64,
+        root_key,
+        tuning: (value(COARSE_TUNE) * 100 + value(FINE_TUNE) + sample.pitch_correction as i32)
+            as f64,
+        scale_tuning: value(SCALE_TUNING) as f64,
+        attenuation: value(INITIAL_ATTENUATION).clamp(0, 1440) as f64,
+        pan: value(PAN).clamp(-500, 500) as f64 / 1000.0,
+        volume_envelope: EnvelopeParameters {
+            delay: timecents_to_seconds(value(DELAY_VOL_ENV)),
+            attack: timecents_to_seconds(value(ATTACK_VOL_ENV)),
+            hold: timecents_to_seconds(value(HOLD_VOL_ENV)),
+            decay: timecents_to_seconds(value(DECAY_VOL_ENV)),
+            sustain_attenuation: value(SUSTAIN_VOL_ENV).clamp(0, 1440) as f64,
+            release: timecents_to_seconds(value(RELEASE_VOL_ENV)),
+        },
+        filter_cutoff,
+        filter_resonance: filter_resonance as f64 / 10.0,
+    })
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut result = id.to_vec();
+        result.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        result.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            result.push(0);
+        }
+        result
+    }
+
+    fn list(list_type: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = list_type.to_vec();
+        for c in chunks {
+            data.extend_from_slice(c);
+        }
+        chunk(b"LIST", &data)
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut result = name.as_bytes().to_vec();
+        result.resize(20, 0);
+        result
+    }
+
+    fn generators(generators: &[(u16, [u8; 2])]) -> Vec<u8> {
+        let mut result = Vec::new();
+        for (operator, amount) in generators.iter().chain(&[(0, [0, 0])]) {
+            result.extend_from_slice(&operator.to_le_bytes());
+            result.extend_from_slice(amount);
+        }
+        result
+    }
+
+    fn bags(generator_indices: &[u16]) -> Vec<u8> {
+        let mut result = Vec::new();
+        for index in generator_indices {
+            result.extend_from_slice(&index.to_le_bytes());
+            result.extend_from_slice(&0_u16.to_le_bytes());
+        }
+        result
+    }
+
+    // A small sound font with one preset ("Test", bank 0, program 0) and one instrument with two
+    // regions: keys 0-59 play a sample that loops continuously, keys 60-127 a sample that is
+    // played once. Both samples are recorded at 100 Hz, with root key 60.
+    // The preset zone adds 12 dB (120 cB) of attenuation.
+    pub(in super::super) fn test_sound_font_bytes() -> Vec<u8> {
+        let mut samples = Vec::new();
+        // Sample 0: 0..8, loop 2..6.
+        // Sample 1: 8..16.
+        for value in [0, 8192, 16384, 8192, 0, -8192, -16384, -8192]
+            .iter()
+            .cycle()
+            .take(16)
+        {
+            samples.extend_from_slice(&(*value as i16).to_le_bytes());
+        }
+        let sdta = list(b"sdta", &[chunk(b"smpl", &samples)]);
+
+        let mut phdr = Vec::new();
+        for (preset_name, bag) in [("Test", 0_u16), ("EOP", 1)].iter() {
+            phdr.extend_from_slice(&name(preset_name));
+            phdr.extend_from_slice(&0_u16.to_le_bytes()); // program
+            phdr.extend_from_slice(&0_u16.to_le_bytes()); // bank
+            phdr.extend_from_slice(&bag.to_le_bytes());
+            phdr.extend_from_slice(&[0; 12]);
+        }
+        let pbag = bags(&[0, 2]);
+        let pgen = generators(&[
+            (generator::INITIAL_ATTENUATION as u16, 120_i16.to_le_bytes()),
+            (generator::INSTRUMENT as u16, [0, 0]),
+        ]);
+
+        let mut inst = Vec::new();
+        for (instrument_name, bag) in [("Instrument", 0_u16), ("EOI", 3)].iter() {
+            inst.extend_from_slice(&name(instrument_name));
+            inst.extend_from_slice(&bag.to_le_bytes());
+        }
+        // A global zone that sets the root key, followed by the two regions.
+        let ibag = bags(&[0, 1, 4, 6]);
+        let igen = generators(&[
+            (generator::OVERRIDING_ROOT_KEY as u16, 60_i16.to_le_bytes()),
+            (generator::KEY_RANGE as u16, [0, 59]),
+            (generator::SAMPLE_MODES as u16, 1_i16.to_le_bytes()),
+            (generator::SAMPLE_ID as u16, [0, 0]),
+            (generator::KEY_RANGE as u16, [60, 127]),
+            (generator::SAMPLE_ID as u16, [1, 0]),
+        ]);
+
+        let mut shdr = Vec::new();
+        for (sample_name, start, end, loop_start, loop_end) in [
+            ("Looped", 0_u32, 8_u32, 2_u32, 6_u32),
+            ("OneShot", 8, 16, 8, 16),
+            ("EOS", 0, 0, 0, 0),
+        ]
+        .iter()
+        {
+            shdr.extend_from_slice(&name(sample_name));
+            for value in [*start, *end, *loop_start, *loop_end, 100].iter() {
+                shdr.extend_from_slice(&value.to_le_bytes());
+            }
+            shdr.extend_from_slice(&[69, 0, 0, 0, 1, 0]);
+        }
+
+        let pdta = list(
+            b"pdta",
+            &[
+                chunk(b"phdr", &phdr),
+                chunk(b"pbag", &pbag),
+                chunk(b"pmod", &[0; 10]),
+                chunk(b"pgen", &pgen),
+                chunk(b"inst", &inst),
+                chunk(b"ibag", &ibag),
+                chunk(b"imod", &[0; 10]),
+                chunk(b"igen", &igen),
+                chunk(b"shdr", &shdr),
+            ],
+        );
+        let info = list(b"INFO", &[chunk(b"ifil", &[2, 0, 1, 0])]);
+        let mut sfbk = b"sfbk".to_vec();
+        sfbk.extend_from_slice(&info);
+        sfbk.extend_from_slice(&sdta);
+        sfbk.extend_from_slice(&pdta);
+        chunk(b"RIFF", &sfbk)
+    }
+
+    #[test]
+    fn loads_presets_and_regions() {
+        let sound_font = SoundFont::from_bytes(&test_sound_font_bytes()).expect("valid sound font");
+        assert_eq!(sound_font.sample_data().len(), 16);
+        assert_eq!(sound_font.sample_data()[1], 0.25);
+        assert_eq!(sound_font.presets().len(), 1);
+        assert_eq!(sound_font.preset_index(0, 0), Some(0));
+        assert_eq!(sound_font.preset_index(0, 1), None);
+
+        let preset = &sound_font.presets()[0];
+        assert_eq!(preset.name(), "Test");
+        let regions = preset.regions();
+        assert_eq!(regions.len(), 2);
+
+        assert_eq!((regions[0].key_low, regions[0].key_high), (0, 59));
+        assert_eq!(regions[0].loop_mode, LoopMode::Continuous);
+        assert_eq!((regions[0].loop_start, regions[0].loop_end), (2, 6));
+        assert_eq!((regions[1].key_low, regions[1].key_high), (60, 127));
+        assert_eq!(regions[1].loop_mode, LoopMode::NoLoop);
+        assert_eq!((regions[1].sample_start, regions[1].sample_end), (8, 16));
+
+        for region in regions {
+            // From the global instrument zone, not from the original pitch of the sample.
+            assert_eq!(region.root_key, 60);
+            // From the preset zone.
+            assert_eq!(region.attenuation, 120.0);
+            assert_eq!(region.sample_rate, 100.0);
+            assert_eq!(region.filter_cutoff, None);
+            // The default of -12000 timecents is about 1 ms.
+            assert!((region.volume_envelope.attack - 0.001).abs() < 1e-4);
+        }
+
+        assert_eq!(preset.regions_for(59, 100).count(), 1);
+        assert_eq!(preset.regions_for(60, 100).next(), Some(&regions[1]));
+    }
+
+    #[test]
+    fn rejects_data_that_is_not_a_sound_font() {
+        match SoundFont::from_bytes(b"RIFF\x04\x00\x00\x00WAVE") {
+            Err(Sf2Error::NotASoundFont) => {}
+            _ => panic!("Expected NotASoundFont."),
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_sound_font() {
+        let bytes = test_sound_font_bytes();
+        assert!(SoundFont::from_bytes(&bytes[..bytes.len() - 10]).is_err());
+    }
+}
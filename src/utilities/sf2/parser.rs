@@ -0,0 +1,142 @@
+// Parsing of the RIFF structure and the records of a SoundFont 2 file.
+// The records are converted into presets and regions in `mod.rs`.
+use super::Sf2Error;
+
+pub(super) struct Chunk<'a> {
+    pub id: [u8; 4],
+    pub data: &'a [u8],
+}
+
+impl<'a> Chunk<'a> {
+    // The type of a `RIFF` or a `LIST` chunk.
+    pub fn list_type(&self) -> Option<&'a [u8]> {
+        self.data.get(0..4)
+    }
+
+    pub fn sub_chunks(&self) -> Result<Vec<Chunk<'a>>, Sf2Error> {
+        read_chunks(&self.data[4..])
+    }
+}
+
+pub(super) fn read_chunks(mut data: &[u8]) -> Result<Vec<Chunk<'_>>, Sf2Error> {
+    let mut chunks = Vec::new();
+    while data.len() >= 8 {
+        let id = [data[0], data[1], data[2], data[3]];
+        let size = read_u32(&data[4..8]) as usize;
+        let end = 8_usize
+            .checked_add(size)
+            .ok_or(Sf2Error::InvalidChunk("chunk size"))?;
+        let chunk_data = data
+            .get(8..end)
+            .ok_or(Sf2Error::InvalidChunk("chunk size"))?;
+        chunks.push(Chunk {
+            id,
+            data: chunk_data,
+        });
+        // Chunks are padded to an even number of bytes.
+        data = data.get(end + (size & 1)..).unwrap_or(&[]);
+    }
+    Ok(chunks)
+}
+
+pub(super) fn read_u16(data: &[u8]) -> u16 {
+    u16::from_le_bytes([data[0], data[1]])
+}
+
+pub(super) fn read_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+}
+
+fn read_name(data: &[u8]) -> String {
+    let length = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..length])
+        .trim_end()
+        .to_string()
+}
+
+fn read_records<T, F>(
+    data: &[u8],
+    size: usize,
+    name: &'static str,
+    f: F,
+) -> Result<Vec<T>, Sf2Error>
+where
+    F: Fn(&[u8]) -> T,
+{
+    let records = data.chunks_exact(size);
+    if !records.remainder().is_empty() {
+        return Err(Sf2Error::InvalidChunk(name));
+    }
+    Ok(records.map(f).collect())
+}
+
+pub(super) struct PresetHeader {
+    pub name: String,
+    pub program: u16,
+    pub bank: u16,
+    pub bag_index: usize,
+}
+
+pub(super) fn read_preset_headers(data: &[u8]) -> Result<Vec<PresetHeader>, Sf2Error> {
+    read_records(data, 38, "phdr", |record| PresetHeader {
+        name: read_name(&record[0..20]),
+        program: read_u16(&record[20..22]),
+        bank: read_u16(&record[22..24]),
+        bag_index: read_u16(&record[24..26]) as usize,
+    })
+}
+
+pub(super) struct InstrumentHeader {
+    pub bag_index: usize,
+}
+
+pub(super) fn read_instrument_headers(data: &[u8]) -> Result<Vec<InstrumentHeader>, Sf2Error> {
+    read_records(data, 22, "inst", |record| InstrumentHeader {
+        bag_index: read_u16(&record[20..22]) as usize,
+    })
+}
+
+// A bag defines a zone by pointing to its first generator.
+pub(super) fn read_bags(data: &[u8], name: &'static str) -> Result<Vec<usize>, Sf2Error> {
+    read_records(data, 4, name, |record| read_u16(&record[0..2]) as usize)
+}
+
+#[derive(Clone, Copy)]
+pub(super) struct Generator {
+    pub operator: u16,
+    pub amount: [u8; 2],
+}
+
+pub(super) fn read_generators(data: &[u8], name: &'static str) -> Result<Vec<Generator>, Sf2Error> {
+    read_records(data, 4, name, |record| Generator {
+        operator: read_u16(&record[0..2]),
+        amount: [record[2], record[3]],
+    })
+}
+
+pub(super) struct SampleHeader {
+    pub start: u32,
+    pub end: u32,
+    pub loop_start: u32,
+    pub loop_end: u32,
+    pub sample_rate: u32,
+    pub original_pitch: u8,
+    pub pitch_correction: i8,
+    pub sample_type: u16,
+}
+
+// Samples with this bit in the sample type are stored in ROM, which is not supported.
+pub(super) const ROM_SAMPLE: u16 = 0x8000;
+
+pub(super) fn read_sample_headers(data: &[u8]) -> Result<Vec<SampleHeader>, Sf2Error> {
+    read_records(data, 46, "shdr", |record| SampleHeader {
+        start: read_u32(&record[20..24]),
+        end: read_u32(&record[24..28]),
+        loop_start: read_u32(&record[28..32]),
+        loop_end: read_u32(&record[32..36]),
+        sample_rate: read_u32(&record[36..40]),
+        original_pitch: record[40],
+        pitch_correction: record[41] as i8,
+        sample_type: read_u16(&record[44..46]),
+    })
+}
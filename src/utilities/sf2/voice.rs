@@ -0,0 +1,543 @@
+use super::{EnvelopeParameters, LoopMode, Region, SoundFont};
+use crate::event::{EventHandler, RawMidiEvent, Timed};
+use crate::utilities::polyphony::{
+    simple_event_dispatching::SimpleVoiceState, ToneIdentifier, Voice,
+};
+use crate::{AudioHandler, AudioRenderer};
+use asprim::AsPrim;
+use midi_consts::channel_event::control_change::{ALL_NOTES_OFF, ALL_SOUND_OFF, BANK_SELECT_MSB};
+use midi_consts::channel_event::*;
+use num_traits::Float;
+use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2, PI};
+use std::sync::Arc;
+
+/// The maximum number of regions that one voice plays at the same time.
+/// When more regions match a key and velocity, only the first ones are played.
+pub const MAX_LAYERS: usize = 4;
+
+// The attenuation in centibels at which a voice is considered silent.
+const SILENCE: f64 = 960.0;
+
+fn centibels_to_gain(centibels: f64) -> f64 {
+    10.0_f64.powf(-centibels / 200.0)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Stage {
+    Delay,
+    Attack,
+    Hold,
+    Decay,
+    Sustain,
+    Release,
+    Finished,
+}
+
+// The volume envelope as described in the SF2 specification: the attack is linear in amplitude,
+// the decay and the release are linear in decibels.
+#[derive(Clone, Copy)]
+struct VolumeEnvelope {
+    parameters: EnvelopeParameters,
+    frame_duration: f64,
+    stage: Stage,
+    time_in_stage: f64,
+    // The attenuation during the decay, sustain and release, in centibels.
+    attenuation: f64,
+    gain: f64,
+}
+
+impl VolumeEnvelope {
+    fn new(parameters: EnvelopeParameters, sample_rate: f64) -> Self {
+        VolumeEnvelope {
+            parameters,
+            frame_duration: 1.0 / sample_rate,
+            stage: Stage::Delay,
+            time_in_stage: 0.0,
+            attenuation: 0.0,
+            gain: 0.0,
+        }
+    }
+
+    fn release(&mut self) {
+        match self.stage {
+            Stage::Release | Stage::Finished => {}
+            Stage::Delay => self.stage = Stage::Finished,
+            _ => {
+                if self.gain <= 0.0 {
+                    self.stage = Stage::Finished;
+                } else {
+                    self.attenuation = -200.0 * self.gain.log10();
+                    self.stage = Stage::Release;
+                }
+            }
+        }
+    }
+
+    fn is_released(&self) -> bool {
+        matches!(self.stage, Stage::Release | Stage::Finished)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.stage == Stage::Finished
+    }
+
+    fn enter(&mut self, stage: Stage) {
+        self.stage = stage;
+        self.time_in_stage = 0.0;
+    }
+
+    fn next_gain(&mut self) -> f64 {
+        let parameters = self.parameters;
+        match self.stage {
+            Stage::Delay => {
+                self.gain = 0.0;
+                if self.time_in_stage >= parameters.delay {
+                    self.enter(Stage::Attack);
+                }
+            }
+            Stage::Attack => {
+                self.gain = (self.time_in_stage / parameters.attack).min(1.0);
+                if self.time_in_stage >= parameters.attack {
+                    self.enter(Stage::Hold);
+                }
+            }
+            Stage::Hold => {
+                self.gain = 1.0;
+                if self.time_in_stage >= parameters.hold {
+                    self.enter(Stage::Decay);
+                }
+            }
+            Stage::Decay => {
+                self.attenuation += SILENCE * self.frame_duration / parameters.decay;
+                if self.attenuation >= parameters.sustain_attenuation {
+                    self.attenuation = parameters.sustain_attenuation;
+                    self.enter(Stage::Sustain);
+                }
+                self.gain = centibels_to_gain(self.attenuation);
+            }
+            Stage::Sustain => {
+                if self.attenuation >= SILENCE {
+                    self.enter(Stage::Finished);
+                }
+            }
+            Stage::Release => {
+                self.attenuation += SILENCE * self.frame_duration / parameters.release;
+                if self.attenuation >= SILENCE {
+                    self.enter(Stage::Finished);
+                }
+                self.gain = centibels_to_gain(self.attenuation);
+            }
+            Stage::Finished => {
+                self.gain = 0.0;
+            }
+        }
+        self.time_in_stage += self.frame_duration;
+        self.gain
+    }
+}
+
+// A resonant low-pass filter (a biquad filter as in the "Audio EQ Cookbook").
+#[derive(Clone, Copy)]
+struct LowPass {
+    enabled: bool,
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+impl LowPass {
+    fn new(cutoff: Option<f64>, resonance: f64, sample_rate: f64) -> Self {
+        let mut filter = LowPass {
+            enabled: false,
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        };
+        if let Some(cutoff) = cutoff {
+            let omega = 2.0 * PI * cutoff.min(0.45 * sample_rate) / sample_rate;
+            let q = 10.0_f64.powf(resonance / 20.0) * FRAC_1_SQRT_2;
+            let alpha = omega.sin() / (2.0 * q);
+            let cos = omega.cos();
+            let a0 = 1.0 + alpha;
+            filter.enabled = true;
+            filter.b0 = (1.0 - cos) / 2.0 / a0;
+            filter.b1 = (1.0 - cos) / a0;
+            filter.b2 = filter.b0;
+            filter.a1 = -2.0 * cos / a0;
+            filter.a2 = (1.0 - alpha) / a0;
+        }
+        filter
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        if !self.enabled {
+            return x;
+        }
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1
+            - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+// One region that is being played.
+#[derive(Clone, Copy)]
+struct Layer {
+    region: Region,
+    position: f64,
+    increment: f64,
+    gain_left: f64,
+    gain_right: f64,
+    envelope: VolumeEnvelope,
+    filter: LowPass,
+}
+
+impl Layer {
+    fn new(region: &Region, key: u8, velocity: u8, sample_rate: f64) -> Self {
+        let cents = (key as f64 - region.root_key as f64) * region.scale_tuning + region.tuning;
+        let increment = 2.0_f64.powf(cents / 1200.0) * region.sample_rate / sample_rate;
+        // An approximation of the default "velocity to attenuation" modulator.
+        let velocity_gain = (velocity as f64 / 127.0).powi(2);
+        let gain = centibels_to_gain(region.attenuation) * velocity_gain;
+        let angle = (region.pan + 0.5) * FRAC_PI_2;
+        Layer {
+            region: *region,
+            position: region.sample_start as f64,
+            increment,
+            gain_left: gain * angle.cos(),
+            gain_right: gain * angle.sin(),
+            envelope: VolumeEnvelope::new(region.volume_envelope, sample_rate),
+            filter: LowPass::new(region.filter_cutoff, region.filter_resonance, sample_rate),
+        }
+    }
+
+    // Return `None` when the layer has finished playing.
+    fn next_sample(&mut self, sample_data: &[f32]) -> Option<f64> {
+        let region = &self.region;
+        let is_looping = match region.loop_mode {
+            LoopMode::NoLoop => false,
+            LoopMode::Continuous => true,
+            LoopMode::UntilRelease => !self.envelope.is_released(),
+        };
+        // `position` is past the end of the sample when the layer has finished playing.
+        let index = (self.position as usize).min(region.sample_end - 1);
+        let next_index = if is_looping && index + 1 >= region.loop_end {
+            region.loop_start
+        } else {
+            index + 1
+        };
+        if next_index >= region.sample_end {
+            return None;
+        }
+        let fraction = self.position - index as f64;
+        let current = sample_data[index] as f64;
+        let value = current + (sample_data[next_index] as f64 - current) * fraction;
+
+        let gain = self.envelope.next_gain();
+        if self.envelope.is_finished() {
+            return None;
+        }
+        self.position += self.increment;
+        if is_looping && self.position >= region.loop_end as f64 {
+            // The increment can be larger than the loop when a short loop is played at a high
+            // pitch.
+            let loop_start = region.loop_start as f64;
+            let loop_length = (region.loop_end - region.loop_start) as f64;
+            self.position = loop_start + (self.position - loop_start) % loop_length;
+        }
+        Some(self.filter.process(value) * gain)
+    }
+}
+
+/// A voice that plays the regions of a preset of a [`SoundFont`].
+///
+/// The voice handles the following MIDI events:
+/// * note on and note off,
+/// * program change and bank select (MSB), which select the preset for the next note,
+/// * "all notes off" and "all sound off".
+///
+/// The MIDI channel is ignored.
+/// It implements `Voice<SimpleVoiceState<ToneIdentifier>>`, so that it can be used with the
+/// [`SimpleEventDispatcher`] and the [`RawMidiEventToneIdentifierDispatchClassifier`].
+/// The voice ignores the timing of the events; use an [`EventQueue`] for sample-accurate timing.
+///
+/// When rendering, the voice adds its output to the existing content of the outputs:
+/// to the first two outputs in stereo, or to a single output in mono.
+///
+/// Real-time safety
+/// ----------------
+/// Handling events and rendering do not allocate memory.
+///
+/// [`SoundFont`]: ./struct.SoundFont.html
+/// [`SimpleEventDispatcher`]: ../polyphony/simple_event_dispatching/struct.SimpleEventDispatcher.html
+/// [`RawMidiEventToneIdentifierDispatchClassifier`]: ../polyphony/struct.RawMidiEventToneIdentifierDispatchClassifier.html
+/// [`EventQueue`]: ../../event/event_queue/struct.EventQueue.html
+pub struct Sf2Voice {
+    sound_font: Arc<SoundFont>,
+    sample_rate: f64,
+    bank: u16,
+    preset_index: Option<usize>,
+    state: SimpleVoiceState<ToneIdentifier>,
+    layers: [Option<Layer>; MAX_LAYERS],
+}
+
+impl Sf2Voice {
+    /// Create a new voice that plays the first preset of bank 0, program 0,
+    /// or the first preset if there is no such preset.
+    pub fn new(sound_font: Arc<SoundFont>) -> Self {
+        let mut voice = Sf2Voice {
+            sound_font,
+            sample_rate: 44100.0,
+            bank: 0,
+            preset_index: None,
+            state: SimpleVoiceState::Idle,
+            layers: [None; MAX_LAYERS],
+        };
+        voice.select_preset(0, 0);
+        voice
+    }
+
+    /// Select the preset that is used for the next note.
+    ///
+    /// If there is no preset with this bank and program, the preset with this program in bank 0
+    /// is used and if that does not exist either, the first preset is used.
+    pub fn select_preset(&mut self, bank: u16, program: u16) {
+        let sound_font = &self.sound_font;
+        self.bank = bank;
+        self.preset_index = sound_font
+            .preset_index(bank, program)
+            .or_else(|| sound_font.preset_index(0, program))
+            .or_else(|| {
+                if sound_font.presets().is_empty() {
+                    None
+                } else {
+                    Some(0)
+                }
+            });
+    }
+
+    /// The index of the preset that is used for the next note.
+    pub fn preset_index(&self) -> Option<usize> {
+        self.preset_index
+    }
+
+    /// Start playing the given key.
+    pub fn note_on(&mut self, key: u8, velocity: u8) {
+        self.layers = [None; MAX_LAYERS];
+        self.state = SimpleVoiceState::Idle;
+        let sound_font = &self.sound_font;
+        let preset = match self
+            .preset_index
+            .and_then(|index| sound_font.presets().get(index))
+        {
+            Some(preset) => preset,
+            None => return,
+        };
+        let mut number_of_layers = 0;
+        for (layer, region) in self
+            .layers
+            .iter_mut()
+            .zip(preset.regions_for(key, velocity))
+        {
+            *layer = Some(Layer::new(region, key, velocity, self.sample_rate));
+            number_of_layers += 1;
+        }
+        if number_of_layers > 0 {
+            self.state = SimpleVoiceState::Active(ToneIdentifier(key));
+        }
+    }
+
+    /// Release the key that is being played: the release phase of the envelope starts.
+    pub fn note_off(&mut self) {
+        if let SimpleVoiceState::Active(identifier) = self.state {
+            for layer in self.layers.iter_mut().flatten() {
+                layer.envelope.release();
+            }
+            self.state = SimpleVoiceState::Releasing(identifier);
+        }
+    }
+
+    /// Stop playing immediately, without release.
+    pub fn stop(&mut self) {
+        self.layers = [None; MAX_LAYERS];
+        self.state = SimpleVoiceState::Idle;
+    }
+}
+
+impl Voice<SimpleVoiceState<ToneIdentifier>> for Sf2Voice {
+    fn state(&self) -> SimpleVoiceState<ToneIdentifier> {
+        self.state
+    }
+}
+
+impl EventHandler<Timed<RawMidiEvent>> for Sf2Voice {
+    fn handle_event(&mut self, timed: Timed<RawMidiEvent>) {
+        let data = timed.event.data();
+        match data[0] & EVENT_TYPE_MASK {
+            NOTE_ON if data[2] > 0 => self.note_on(data[1], data[2]),
+            NOTE_ON | NOTE_OFF => self.note_off(),
+            PROGRAM_CHANGE => self.select_preset(self.bank, data[1] as u16),
+            CONTROL_CHANGE => match data[1] {
+                BANK_SELECT_MSB => self.bank = data[2] as u16,
+                ALL_NOTES_OFF => self.note_off(),
+                ALL_SOUND_OFF => self.stop(),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+impl AudioHandler for Sf2Voice {
+    /// Set the sample rate. This only affects notes that are started afterwards.
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+    }
+}
+
+impl<S> AudioRenderer<S> for Sf2Voice
+where
+    S: AsPrim + Float,
+{
+    fn render_buffer(&mut self, _inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        if self.state == SimpleVoiceState::Idle {
+            return;
+        }
+        let sample_data = self.sound_font.sample_data();
+        let number_of_frames = outputs.first().map(|output| output.len()).unwrap_or(0);
+        for slot in self.layers.iter_mut() {
+            let layer = match slot {
+                Some(layer) => layer,
+                None => continue,
+            };
+            for frame in 0..number_of_frames {
+                let value = match layer.next_sample(sample_data) {
+                    Some(value) => value,
+                    None => {
+                        *slot = None;
+                        break;
+                    }
+                };
+                match outputs {
+                    [] => {}
+                    [mono] => {
+                        let gain = (layer.gain_left + layer.gain_right) * FRAC_1_SQRT_2;
+                        mono[frame] = mono[frame] + (value * gain).as_();
+                    }
+                    [left, right, ..] => {
+                        left[frame] = left[frame] + (value * layer.gain_left).as_();
+                        right[frame] = right[frame] + (value * layer.gain_right).as_();
+                    }
+                }
+            }
+        }
+        if self.layers.iter().all(Option::is_none) {
+            self.state = SimpleVoiceState::Idle;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::test_sound_font_bytes;
+    use super::*;
+
+    fn voice() -> Sf2Voice {
+        let sound_font = SoundFont::from_bytes(&test_sound_font_bytes()).expect("valid sound font");
+        let mut voice = Sf2Voice::new(Arc::new(sound_font));
+        voice.set_sample_rate(100.0);
+        voice
+    }
+
+    fn render(voice: &mut Sf2Voice, number_of_frames: usize) -> (Vec<f32>, Vec<f32>) {
+        let mut left = vec![0.0; number_of_frames];
+        let mut right = vec![0.0; number_of_frames];
+        voice.render_buffer(&[], &mut [&mut left, &mut right]);
+        (left, right)
+    }
+
+    fn note(status: u8, key: u8, velocity: u8) -> Timed<RawMidiEvent> {
+        Timed::new(0, RawMidiEvent::new(&[status, key, velocity]))
+    }
+
+    #[test]
+    fn one_shot_region_stops_at_the_end_of_the_sample() {
+        let mut voice = voice();
+        voice.handle_event(note(NOTE_ON, 60, 127));
+        assert!(voice.state() == SimpleVoiceState::Active(ToneIdentifier(60)));
+        let (left, right) = render(&mut voice, 16);
+        // The sample has 8 frames and is played at the original pitch.
+        assert!(left[1..7].iter().any(|s| *s != 0.0));
+        assert!(left[7..].iter().all(|s| *s == 0.0));
+        // Centered pan.
+        assert_eq!(left, right);
+        assert!(voice.state() == SimpleVoiceState::Idle);
+    }
+
+    #[test]
+    fn looped_region_plays_until_released() {
+        let mut voice = voice();
+        voice.handle_event(note(NOTE_ON, 59, 127));
+        let (left, _) = render(&mut voice, 200);
+        assert!(left[150..].iter().any(|s| *s != 0.0));
+        assert!(voice.state() == SimpleVoiceState::Active(ToneIdentifier(59)));
+
+        voice.handle_event(note(NOTE_OFF, 59, 0));
+        assert!(voice.state() == SimpleVoiceState::Releasing(ToneIdentifier(59)));
+        // The release time is 1 ms, which is less than one frame at 100 Hz.
+        render(&mut voice, 4);
+        assert!(voice.state() == SimpleVoiceState::Idle);
+    }
+
+    #[test]
+    fn short_loop_can_be_played_at_a_high_pitch() {
+        let sound_font = SoundFont::from_bytes(&test_sound_font_bytes()).expect("valid sound font");
+        let mut region = sound_font.presets()[0].regions()[0];
+        assert_eq!((region.loop_start, region.loop_end), (2, 6));
+        // Key 59 is played almost five octaves higher: the increment is larger than the loop.
+        region.root_key = 0;
+        let mut layer = Layer::new(&region, 59, 127, 100.0);
+        assert!(layer.increment > 4.0);
+        for _ in 0..100 {
+            assert!(layer.next_sample(sound_font.sample_data()).is_some());
+            assert!(layer.position >= 2.0 && layer.position < 6.0);
+        }
+    }
+
+    #[test]
+    fn attenuation_and_velocity_reduce_the_level() {
+        let mut voice = voice();
+        voice.handle_event(note(NOTE_ON, 60, 127));
+        let (loud, _) = render(&mut voice, 4);
+        voice.handle_event(note(NOTE_ON, 60, 64));
+        let (soft, _) = render(&mut voice, 4);
+        let peak = |samples: &[f32]| samples.iter().fold(0.0_f32, |m, s| m.max(s.abs()));
+        // 12 dB attenuation from the preset, -3 dB for the center pan.
+        let expected_peak = 0.5 * centibels_to_gain(120.0) * FRAC_1_SQRT_2;
+        assert!((peak(&loud) as f64 - expected_peak).abs() < 1e-6);
+        assert!(peak(&soft) < peak(&loud) / 2.0);
+    }
+
+    #[test]
+    fn program_change_to_unknown_program_falls_back_to_first_preset() {
+        let mut voice = voice();
+        voice.handle_event(Timed::new(0, RawMidiEvent::new(&[PROGRAM_CHANGE, 5, 0])));
+        assert_eq!(voice.preset_index(), Some(0));
+    }
+}
@@ -0,0 +1,135 @@
+//! Use a [`dasp`] `Signal` as an [`AudioRenderer`].
+//!
+//! Support is only enabled if you compile with the "dasp" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! This is useful for test tones and for reusing the `dasp` ecosystem inside a plugin.
+//!
+//! Channel mapping
+//! ===============
+//! Channel `i` of the frames of the signal is written to output `i`.
+//! As an exception, a signal with mono frames is written to all outputs.
+//! Outputs that do not correspond to a channel are filled with silence
+//! and channels that do not correspond to an output are ignored.
+//!
+//! End of the signal
+//! =================
+//! When the signal is exhausted, the outputs are filled with silence.
+//! You can check this with [`SignalRenderer::is_exhausted`].
+//!
+//! [`dasp`]: https://crates.io/crates/dasp
+//! [`AudioRenderer`]: ../../trait.AudioRenderer.html
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`SignalRenderer::is_exhausted`]: ./struct.SignalRenderer.html#method.is_exhausted
+use crate::AudioRenderer;
+use dasp::frame::Frame;
+use dasp::sample::Sample;
+use dasp::signal::{self, FromIterator, Signal};
+
+/// Renders a `dasp` `Signal`. The inputs are ignored.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct SignalRenderer<Sig> {
+    signal: Sig,
+}
+
+impl<Sig> SignalRenderer<Sig>
+where
+    Sig: Signal,
+{
+    /// Create a new `SignalRenderer` that renders the given signal.
+    pub fn new(signal: Sig) -> Self {
+        Self { signal }
+    }
+
+    /// Return `true` if the signal is exhausted, in which case only silence is rendered.
+    pub fn is_exhausted(&self) -> bool {
+        self.signal.is_exhausted()
+    }
+
+    /// Get a reference to the underlying signal.
+    pub fn signal(&self) -> &Sig {
+        &self.signal
+    }
+
+    /// Get the underlying signal.
+    pub fn into_signal(self) -> Sig {
+        self.signal
+    }
+}
+
+impl<I> SignalRenderer<FromIterator<I>>
+where
+    I: Iterator,
+    I::Item: Frame,
+{
+    /// Create a new `SignalRenderer` that renders the given frames.
+    /// The signal is exhausted when the iterator is exhausted.
+    pub fn from_frames<F>(frames: F) -> Self
+    where
+        F: IntoIterator<IntoIter = I, Item = I::Item>,
+    {
+        Self::new(signal::from_iter(frames))
+    }
+}
+
+impl<Sig, S> AudioRenderer<S> for SignalRenderer<Sig>
+where
+    Sig: Signal,
+    Sig::Frame: Frame<Sample = S>,
+    S: Sample,
+{
+    fn render_buffer(&mut self, _inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        let number_of_frames = outputs.first().map(|output| output.len()).unwrap_or(0);
+        let is_mono = <Sig::Frame as Frame>::CHANNELS == 1;
+        for index in 0..number_of_frames {
+            let frame = if self.signal.is_exhausted() {
+                <Sig::Frame as Frame>::EQUILIBRIUM
+            } else {
+                self.signal.next()
+            };
+            for (channel_index, output) in outputs.iter_mut().enumerate() {
+                let channel_index = if is_mono { 0 } else { channel_index };
+                output[index] = frame
+                    .channel(channel_index)
+                    .copied()
+                    .unwrap_or(S::EQUILIBRIUM);
+            }
+        }
+    }
+}
+
+#[test]
+fn mono_signal_is_written_to_all_outputs() {
+    let mut renderer = SignalRenderer::from_frames(vec![[1.0_f32], [2.0], [3.0]]);
+    let mut left = [0.0_f32; 3];
+    let mut right = [0.0_f32; 3];
+    renderer.render_buffer(&[], &mut [&mut left, &mut right]);
+    assert_eq!(left, [1.0, 2.0, 3.0]);
+    assert_eq!(right, [1.0, 2.0, 3.0]);
+}
+
+#[test]
+fn channels_are_mapped_to_outputs() {
+    let mut renderer = SignalRenderer::from_frames(vec![[1.0_f32, -1.0], [2.0, -2.0]]);
+    let mut first = [0.5_f32; 2];
+    let mut second = [0.5_f32; 2];
+    let mut third = [0.5_f32; 2];
+    renderer.render_buffer(&[], &mut [&mut first, &mut second, &mut third]);
+    assert_eq!(first, [1.0, 2.0]);
+    assert_eq!(second, [-1.0, -2.0]);
+    assert_eq!(third, [0.0, 0.0]);
+}
+
+#[test]
+fn silence_is_rendered_when_the_signal_is_exhausted() {
+    let mut renderer = SignalRenderer::from_frames(vec![[1.0_f32], [2.0]]);
+    let mut output = [0.5_f32; 3];
+    renderer.render_buffer(&[], &mut [&mut output]);
+    assert_eq!(output, [1.0, 2.0, 0.0]);
+    assert!(renderer.is_exhausted());
+    renderer.render_buffer(&[], &mut [&mut output]);
+    assert_eq!(output, [0.0, 0.0, 0.0]);
+}
@@ -0,0 +1,170 @@
+//! Store per-voice data as a "structure of arrays".
+//!
+//! The straightforward way to write a polyphonic synthesizer is to define a struct for one voice
+//! and to keep a `Vec` of these ("array of structures"). When rendering, the per-sample loop then
+//! jumps from one voice to the next, touching all the fields of each voice, also the fields that
+//! are not needed for rendering.
+//! When the data is stored as a "structure of arrays", each field is stored in its own array,
+//! with one element per voice. The hot per-sample loop can then iterate over contiguous memory
+//! across the voices, which is more cache friendly and easier to vectorize for the compiler.
+//!
+//! The [`structure_of_arrays!`] macro defines such a struct.
+//!
+//! Dispatching events
+//! ------------------
+//! The types in the [`polyphony`] module expect a slice of voices. If you store the voice state
+//! (e.g. a [`SimpleVoiceState`]) as one of the fields, you can pass this field to
+//! [`VoiceAssigner::assign_event`] and use the returned index for the other fields.
+//! See the `soa_synth` example for more details.
+//!
+//! [`structure_of_arrays!`]: ../../macro.structure_of_arrays.html
+//! [`polyphony`]: ../polyphony/index.html
+//! [`SimpleVoiceState`]: ../polyphony/simple_event_dispatching/enum.SimpleVoiceState.html
+//! [`VoiceAssigner::assign_event`]: ../polyphony/trait.VoiceAssigner.html#method.assign_event
+
+#[macro_export]
+/// Define a struct that stores per-voice data as a "structure of arrays".
+///
+/// Each field `name: Type` of the definition becomes a field `name: Box<[Type]>` of the struct,
+/// with one element per voice. Because the fields are boxed slices, the number of voices
+/// cannot change after construction, so all fields always have the same length.
+///
+/// The following methods are generated:
+/// * `new(number_of_voices: usize) -> Self`: create the struct with `Default::default()` for
+///   all elements (so all field types need to implement `Default`),
+/// * `len(&self) -> usize`: the number of voices,
+/// * `is_empty(&self) -> bool`,
+/// * `swap(&mut self, a: usize, b: usize)`: swap the data of two voices.
+///
+/// Note: `new` allocates memory, so it cannot be used in a real-time context.
+///
+/// ## Example
+/// ```
+/// # #[macro_use]
+/// # extern crate rsynth;
+/// structure_of_arrays! {
+///     /// The voices of a sine synthesizer.
+///     pub struct SineVoices {
+///         pub phase: f32,
+///         pub phase_increment: f32,
+///         pub amplitude: f32,
+///     }
+/// }
+///
+/// # fn main() {
+/// let mut voices = SineVoices::new(8);
+/// assert_eq!(voices.len(), 8);
+/// voices.amplitude[2] = 0.5;
+/// voices.phase_increment[2] = 0.01;
+///
+/// let mut output = [0.0; 64];
+/// for sample in output.iter_mut() {
+///     for ((phase, increment), amplitude) in voices
+///         .phase
+///         .iter_mut()
+///         .zip(voices.phase_increment.iter())
+///         .zip(voices.amplitude.iter())
+///     {
+///         *sample += (*phase * 2.0 * std::f32::consts::PI).sin() * amplitude;
+///         *phase = (*phase + increment).fract();
+///     }
+/// }
+/// # }
+/// ```
+macro_rules! structure_of_arrays {
+    (
+        $(#[$struct_meta:meta])*
+        $struct_vis:vis struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident : $field_type:ty
+            ),+
+            $(,)?
+        }
+    ) => {
+        $(#[$struct_meta])*
+        $struct_vis struct $name {
+            $(
+                $(#[$field_meta])*
+                $field_vis $field: Box<[$field_type]>,
+            )+
+        }
+
+        impl $name {
+            /// Create a new structure of arrays with `number_of_voices` elements in each array,
+            /// initialized with the default value.
+            #[allow(dead_code)]
+            pub fn new(number_of_voices: usize) -> Self {
+                Self {
+                    $(
+                        $field: (0..number_of_voices)
+                            .map(|_| <$field_type as ::std::default::Default>::default())
+                            .collect(),
+                    )+
+                }
+            }
+
+            /// The number of voices.
+            #[allow(dead_code)]
+            pub fn len(&self) -> usize {
+                $crate::structure_of_arrays!(@first_len self; $($field),+)
+            }
+
+            /// Return `true` if there are no voices.
+            #[allow(dead_code)]
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            /// Swap the data of the voices with index `a` and `b`.
+            ///
+            /// # Panics
+            /// Panics if `a` or `b` are out of bounds.
+            #[allow(dead_code)]
+            pub fn swap(&mut self, a: usize, b: usize) {
+                $(
+                    self.$field.swap(a, b);
+                )+
+            }
+        }
+    };
+    (@first_len $self:ident; $first:ident $(, $rest:ident)*) => {
+        $self.$first.len()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    structure_of_arrays! {
+        struct TestVoices {
+            position: usize,
+            /// Fields can be documented.
+            amplitude: f32,
+        }
+    }
+
+    #[test]
+    fn new_initializes_all_fields_with_default() {
+        let voices = TestVoices::new(3);
+        assert_eq!(voices.len(), 3);
+        assert!(!voices.is_empty());
+        assert_eq!(&*voices.position, &[0, 0, 0]);
+        assert_eq!(&*voices.amplitude, &[0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn swap_swaps_all_fields() {
+        let mut voices = TestVoices::new(2);
+        voices.position[0] = 1;
+        voices.amplitude[0] = 0.5;
+        voices.swap(0, 1);
+        assert_eq!(&*voices.position, &[0, 1]);
+        assert_eq!(&*voices.amplitude, &[0.0, 0.5]);
+    }
+
+    #[test]
+    fn empty_structure_of_arrays() {
+        let voices = TestVoices::new(0);
+        assert!(voices.is_empty());
+    }
+}
@@ -0,0 +1,561 @@
+//! Process audio in the frequency domain, e.g. for a spectral freeze, robotization or a
+//! spectral gate.
+//!
+//! Implement the [`SpectralRenderer`] trait to process the [`Spectrum`] of each channel and
+//! wrap the renderer in an [`Stft`] (short-time Fourier transform). The `Stft` implements the
+//! `AudioRenderer` and `ContextualAudioRenderer` traits, so that it can be used with every
+//! back-end, and forwards the events, the sample rate and the meta-data to the wrapped renderer.
+//!
+//! The `Stft` collects the input in overlapping frames of `frame_size` samples; a new frame
+//! starts every `frame_size / overlap` samples (the "hop"). Each frame is multiplied with a Hann
+//! window and transformed to the frequency domain; after the renderer has processed the
+//! spectra, they are transformed back, multiplied with the Hann window again and added to the
+//! output ("overlap-add"). When the spectra are not changed, the output is the input, delayed
+//! by `frame_size` frames. The `Stft` implements the [`Latency`] trait to report this delay.
+//!
+//! The magnitudes are linear and scaled so that a sine wave with amplitude 1 at the center
+//! frequency of a bin gives a magnitude of 1 in that bin, as in the [`spectrum_analyzer`].
+//!
+//! Example
+//! -------
+//! Robotize the voice by setting the phase of every bin to zero:
+//! ```
+//! use rsynth::utilities::spectral::{SpectralRenderer, Spectrum, Stft};
+//! use rsynth::{AudioRenderer, Latency};
+//!
+//! struct Robotize;
+//!
+//! impl SpectralRenderer for Robotize {
+//!     fn render_spectra(&mut self, spectra: &mut [Spectrum]) {
+//!         for spectrum in spectra.iter_mut() {
+//!             for bin in 0..spectrum.number_of_bins() {
+//!                 let magnitude = spectrum.magnitude(bin);
+//!                 spectrum.set_polar(bin, magnitude, 0.0);
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! let mut robot = Stft::new(Robotize, 1, 1024, 4);
+//! assert_eq!(robot.latency_in_frames(), 1024);
+//! let input = [0.0_f32; 256];
+//! let mut output = [0.0_f32; 256];
+//! robot.render_buffer(&[&input[..]], &mut [&mut output[..]]);
+//! ```
+//!
+//! Real-time safety
+//! ----------------
+//! Rendering does not allocate memory. The renderer is called at most once per hop, so the
+//! CPU load is higher in the buffers in which a hop ends.
+//!
+//! [`SpectralRenderer`]: ./trait.SpectralRenderer.html
+//! [`Spectrum`]: ./struct.Spectrum.html
+//! [`Stft`]: ./struct.Stft.html
+//! [`Latency`]: ../../trait.Latency.html
+//! [`spectrum_analyzer`]: ../spectrum_analyzer/index.html
+use crate::event::{ContextualEventHandler, EventHandler};
+use crate::meta::Meta;
+use crate::utilities::fft::fft;
+use crate::{AudioHandler, AudioRenderer, ContextualAudioRenderer, Latency};
+use asprim::AsPrim;
+
+/// Defines how the spectra of the audio are processed, see the [module level documentation].
+///
+/// [module level documentation]: ./index.html
+pub trait SpectralRenderer {
+    /// Process the spectra of one hop, one spectrum for every channel.
+    ///
+    /// This is called from the real-time thread, see the notes about real-time safety in the
+    /// [module level documentation].
+    ///
+    /// [module level documentation]: ./index.html
+    fn render_spectra(&mut self, spectra: &mut [Spectrum]);
+}
+
+/// The spectrum of one frame of one channel.
+///
+/// Only the bins from 0 (DC) to `frame_size / 2` (the Nyquist frequency) can be accessed;
+/// the bins for the negative frequencies are derived from these.
+pub struct Spectrum {
+    real: Vec<f64>,
+    imaginary: Vec<f64>,
+    sample_rate: f64,
+}
+
+impl Spectrum {
+    fn new(frame_size: usize) -> Self {
+        Spectrum {
+            real: vec![0.0; frame_size],
+            imaginary: vec![0.0; frame_size],
+            sample_rate: 44100.0,
+        }
+    }
+
+    /// The number of bins: `frame_size / 2 + 1`.
+    pub fn number_of_bins(&self) -> usize {
+        self.real.len() / 2 + 1
+    }
+
+    /// The real parts of the bins.
+    pub fn real(&self) -> &[f64] {
+        &self.real[..self.number_of_bins()]
+    }
+
+    pub fn real_mut(&mut self) -> &mut [f64] {
+        let number_of_bins = self.number_of_bins();
+        &mut self.real[..number_of_bins]
+    }
+
+    /// The imaginary parts of the bins.
+    pub fn imaginary(&self) -> &[f64] {
+        &self.imaginary[..self.number_of_bins()]
+    }
+
+    pub fn imaginary_mut(&mut self) -> &mut [f64] {
+        let number_of_bins = self.number_of_bins();
+        &mut self.imaginary[..number_of_bins]
+    }
+
+    /// The magnitude of the bin with the given index.
+    pub fn magnitude(&self, bin: usize) -> f64 {
+        self.real[bin].hypot(self.imaginary[bin])
+    }
+
+    /// The phase of the bin with the given index, in radians.
+    pub fn phase(&self, bin: usize) -> f64 {
+        self.imaginary[bin].atan2(self.real[bin])
+    }
+
+    /// Set the magnitude and the phase (in radians) of the bin with the given index.
+    pub fn set_polar(&mut self, bin: usize, magnitude: f64, phase: f64) {
+        let (sin, cos) = phase.sin_cos();
+        self.real[bin] = magnitude * cos;
+        self.imaginary[bin] = magnitude * sin;
+    }
+
+    /// The center frequency of the bin with the given index, in Hz.
+    pub fn bin_frequency(&self, bin: usize) -> f64 {
+        bin as f64 * self.sample_rate / self.real.len() as f64
+    }
+
+    // Derive the negative frequencies from the positive frequencies, so that the inverse
+    // transform is real.
+    fn make_symmetric(&mut self) {
+        let frame_size = self.real.len();
+        self.imaginary[0] = 0.0;
+        self.imaginary[frame_size / 2] = 0.0;
+        for bin in 1..frame_size / 2 {
+            self.real[frame_size - bin] = self.real[bin];
+            self.imaginary[frame_size - bin] = -self.imaginary[bin];
+        }
+    }
+}
+
+// The input and the output of one channel.
+struct ChannelState {
+    // The last `frame_size` input samples; the last hop is being filled.
+    input: Vec<f64>,
+    // The sum of the frames that have been transformed back and that are not yet output.
+    overlap_add: Vec<f64>,
+    // The samples of the current hop that are being output.
+    output: Vec<f64>,
+}
+
+/// Wraps a [`SpectralRenderer`] and renders audio with a short-time Fourier transform.
+///
+/// See the [module level documentation] for more information.
+///
+/// [`SpectralRenderer`]: ./trait.SpectralRenderer.html
+/// [module level documentation]: ./index.html
+pub struct Stft<R> {
+    inner: R,
+    frame_size: usize,
+    hop_size: usize,
+    window: Vec<f64>,
+    // The scale that is applied to the transformed frame so that a sine wave with
+    // amplitude 1 gives a magnitude of 1.
+    analysis_scale: f64,
+    // The scale that is applied after the inverse transform, for every position in the hop,
+    // so that the overlapping windows add up to 1.
+    synthesis_scale: Vec<f64>,
+    channels: Vec<ChannelState>,
+    spectra: Vec<Spectrum>,
+    // The position in the current hop.
+    position: usize,
+}
+
+impl<R> Stft<R> {
+    /// Wrap `inner` to process `number_of_channels` channels in frames of `frame_size` samples,
+    /// with `overlap` frames overlapping every sample.
+    ///
+    /// # Panics
+    /// Panics if `frame_size` is not a power of two or if `overlap` is not a power of two in
+    /// the range `2..=frame_size`.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory.
+    pub fn new(inner: R, number_of_channels: usize, frame_size: usize, overlap: usize) -> Self {
+        assert!(frame_size.is_power_of_two());
+        assert!(overlap.is_power_of_two() && overlap >= 2 && overlap <= frame_size);
+        let hop_size = frame_size / overlap;
+        let window: Vec<f64> = (0..frame_size)
+            .map(|i| {
+                let phase = 2.0 * std::f64::consts::PI * i as f64 / frame_size as f64;
+                0.5 - 0.5 * phase.cos()
+            })
+            .collect();
+        let analysis_scale = 2.0 / window.iter().sum::<f64>();
+        let synthesis_scale = (0..hop_size)
+            .map(|position| {
+                let sum_of_squares: f64 = (position..frame_size)
+                    .step_by(hop_size)
+                    .map(|index| window[index] * window[index])
+                    .sum();
+                1.0 / (sum_of_squares * analysis_scale * frame_size as f64)
+            })
+            .collect();
+        Stft {
+            inner,
+            frame_size,
+            hop_size,
+            window,
+            analysis_scale,
+            synthesis_scale,
+            channels: (0..number_of_channels)
+                .map(|_| ChannelState {
+                    input: vec![0.0; frame_size],
+                    overlap_add: vec![0.0; frame_size],
+                    output: vec![0.0; hop_size],
+                })
+                .collect(),
+            spectra: (0..number_of_channels)
+                .map(|_| Spectrum::new(frame_size))
+                .collect(),
+            position: 0,
+        }
+    }
+
+    /// The number of samples in one frame.
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// The number of samples between the start of two frames.
+    pub fn hop_size(&self) -> usize {
+        self.hop_size
+    }
+
+    /// Forget the audio that has been rendered, e.g. when the transport jumps.
+    pub fn reset(&mut self) {
+        for channel in self.channels.iter_mut() {
+            channel.input.iter_mut().for_each(|sample| *sample = 0.0);
+            channel
+                .overlap_add
+                .iter_mut()
+                .for_each(|sample| *sample = 0.0);
+            channel.output.iter_mut().for_each(|sample| *sample = 0.0);
+        }
+        self.position = 0;
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn render<S>(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]])
+    where
+        R: SpectralRenderer,
+        S: AsPrim + Copy,
+    {
+        let number_of_frames = outputs
+            .first()
+            .map(|output| output.len())
+            .or_else(|| inputs.first().map(|input| input.len()))
+            .unwrap_or(0);
+        for output in outputs.iter_mut().skip(self.channels.len()) {
+            for sample in output.iter_mut() {
+                *sample = 0.0.as_();
+            }
+        }
+        let mut start = 0;
+        while start < number_of_frames {
+            let length = (self.hop_size - self.position).min(number_of_frames - start);
+            let offset = self.frame_size - self.hop_size + self.position;
+            for (index, channel) in self.channels.iter_mut().enumerate() {
+                let destination = &mut channel.input[offset..offset + length];
+                match inputs.get(index) {
+                    Some(input) => {
+                        for (sample, input) in destination.iter_mut().zip(&input[start..]) {
+                            *sample = input.as_();
+                        }
+                    }
+                    None => destination.iter_mut().for_each(|sample| *sample = 0.0),
+                }
+                if let Some(output) = outputs.get_mut(index) {
+                    let source = &channel.output[self.position..self.position + length];
+                    for (sample, output) in source.iter().zip(output[start..].iter_mut()) {
+                        *output = sample.as_();
+                    }
+                }
+            }
+            self.position += length;
+            start += length;
+            if self.position == self.hop_size {
+                self.position = 0;
+                self.process_hop();
+            }
+        }
+    }
+
+    fn process_hop(&mut self)
+    where
+        R: SpectralRenderer,
+    {
+        for (channel, spectrum) in self.channels.iter().zip(self.spectra.iter_mut()) {
+            for (index, (real, imaginary)) in spectrum
+                .real
+                .iter_mut()
+                .zip(spectrum.imaginary.iter_mut())
+                .enumerate()
+            {
+                *real = channel.input[index] * self.window[index];
+                *imaginary = 0.0;
+            }
+            fft(&mut spectrum.real, &mut spectrum.imaginary, false);
+            for (real, imaginary) in spectrum.real.iter_mut().zip(spectrum.imaginary.iter_mut()) {
+                *real *= self.analysis_scale;
+                *imaginary *= self.analysis_scale;
+            }
+        }
+
+        self.inner.render_spectra(&mut self.spectra);
+
+        let hop_size = self.hop_size;
+        for (channel, spectrum) in self.channels.iter_mut().zip(self.spectra.iter_mut()) {
+            spectrum.make_symmetric();
+            fft(&mut spectrum.real, &mut spectrum.imaginary, true);
+            for (index, sample) in channel.overlap_add.iter_mut().enumerate() {
+                *sample += spectrum.real[index]
+                    * self.window[index]
+                    * self.synthesis_scale[index % hop_size];
+            }
+            channel
+                .output
+                .copy_from_slice(&channel.overlap_add[..hop_size]);
+            channel.overlap_add.copy_within(hop_size.., 0);
+            let frame_size = self.frame_size;
+            channel.overlap_add[frame_size - hop_size..]
+                .iter_mut()
+                .for_each(|sample| *sample = 0.0);
+            channel.input.copy_within(hop_size.., 0);
+        }
+    }
+}
+
+impl<R> Latency for Stft<R> {
+    fn latency_in_frames(&self) -> usize {
+        self.frame_size
+    }
+}
+
+impl<R, S> AudioRenderer<S> for Stft<R>
+where
+    R: SpectralRenderer,
+    S: AsPrim + Copy,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        self.render(inputs, outputs);
+    }
+}
+
+/// The context is not used: the [`SpectralRenderer`] does not have access to it.
+///
+/// [`SpectralRenderer`]: ./trait.SpectralRenderer.html
+impl<R, S, Context> ContextualAudioRenderer<S, Context> for Stft<R>
+where
+    R: SpectralRenderer,
+    S: AsPrim + Copy,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], _: &mut Context) {
+        self.render(inputs, outputs);
+    }
+}
+
+/// Events are forwarded to the wrapped renderer; they take effect at the next hop.
+impl<R, E> EventHandler<E> for Stft<R>
+where
+    R: EventHandler<E>,
+{
+    fn handle_event(&mut self, event: E) {
+        self.inner.handle_event(event);
+    }
+
+    fn handle_events(&mut self, events: &[E])
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events);
+    }
+}
+
+impl<R, E, Context> ContextualEventHandler<E, Context> for Stft<R>
+where
+    R: ContextualEventHandler<E, Context>,
+{
+    fn handle_event(&mut self, event: E, context: &mut Context) {
+        self.inner.handle_event(event, context);
+    }
+
+    fn handle_events(&mut self, events: &[E], context: &mut Context)
+    where
+        E: Clone,
+    {
+        self.inner.handle_events(events, context);
+    }
+}
+
+impl<R> AudioHandler for Stft<R>
+where
+    R: AudioHandler,
+{
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        for spectrum in self.spectra.iter_mut() {
+            spectrum.sample_rate = sample_rate;
+        }
+        self.inner.set_sample_rate(sample_rate);
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.inner.set_max_buffer_size(max_buffer_size);
+    }
+}
+
+impl<R> Meta for Stft<R>
+where
+    R: Meta,
+{
+    type MetaData = R::MetaData;
+
+    fn meta(&self) -> &Self::MetaData {
+        self.inner.meta()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Unchanged;
+
+    impl SpectralRenderer for Unchanged {
+        fn render_spectra(&mut self, _: &mut [Spectrum]) {}
+    }
+
+    // Record the magnitude of one bin of the first channel.
+    struct BinMeter {
+        bin: usize,
+        magnitudes: Vec<f64>,
+    }
+
+    impl SpectralRenderer for BinMeter {
+        fn render_spectra(&mut self, spectra: &mut [Spectrum]) {
+            self.magnitudes.push(spectra[0].magnitude(self.bin));
+            for spectrum in spectra.iter_mut() {
+                spectrum.real_mut().iter_mut().for_each(|bin| *bin = 0.0);
+                spectrum
+                    .imaginary_mut()
+                    .iter_mut()
+                    .for_each(|bin| *bin = 0.0);
+            }
+        }
+    }
+
+    fn input_signal(index: usize) -> f32 {
+        ((index * 7919) % 200) as f32 / 100.0 - 1.0
+    }
+
+    #[test]
+    fn unchanged_spectra_reproduce_the_delayed_input() {
+        for overlap in [2, 4, 8].iter() {
+            let mut stft = Stft::new(Unchanged, 2, 64, *overlap);
+            let input: Vec<f32> = (0..1000).map(input_signal).collect();
+            let mut left = vec![0.0_f32; input.len()];
+            let mut right = vec![0.0_f32; input.len()];
+            let mut start = 0;
+            // Buffer sizes that are not aligned with the hops.
+            for length in [1, 37, 100, 5, 300, 557].iter() {
+                let end = start + length;
+                AudioRenderer::render_buffer(
+                    &mut stft,
+                    &[&input[start..end]],
+                    &mut [&mut left[start..end], &mut right[start..end]],
+                );
+                start = end;
+            }
+            let latency = stft.latency_in_frames();
+            assert_eq!(latency, 64);
+            assert!(left[..latency].iter().all(|sample| sample.abs() < 1e-9));
+            for (output, input) in left[latency..].iter().zip(input.iter()) {
+                assert!((output - input).abs() < 1e-5, "{} != {}", output, input);
+            }
+            // The second channel has no input.
+            assert!(right.iter().all(|sample| sample.abs() < 1e-9));
+        }
+    }
+
+    #[test]
+    fn sine_at_the_center_of_a_bin_has_its_amplitude_as_magnitude() {
+        let frame_size = 256;
+        let mut stft = Stft::new(
+            BinMeter {
+                bin: 8,
+                magnitudes: Vec::new(),
+            },
+            1,
+            frame_size,
+            4,
+        );
+        let input: Vec<f64> = (0..2048)
+            .map(|index| {
+                let phase = 2.0 * std::f64::consts::PI * 8.0 * index as f64 / frame_size as f64;
+                0.5 * phase.sin()
+            })
+            .collect();
+        let mut output = vec![1.0; input.len()];
+        AudioRenderer::render_buffer(&mut stft, &[&input[..]], &mut [&mut output[..]]);
+        let magnitudes = &stft.inner().magnitudes;
+        assert_eq!(magnitudes.len(), 2048 / 64);
+        // The first frames also contain the silence before the input.
+        for magnitude in magnitudes[4..].iter() {
+            assert!((magnitude - 0.5).abs() < 1e-9, "{}", magnitude);
+        }
+        // All bins are cleared, so the output is silent.
+        assert!(output.iter().all(|sample| sample.abs() < 1e-12));
+    }
+
+    #[test]
+    fn bin_frequency_depends_on_the_sample_rate() {
+        struct Nothing;
+        impl SpectralRenderer for Nothing {
+            fn render_spectra(&mut self, _: &mut [Spectrum]) {}
+        }
+        impl AudioHandler for Nothing {
+            fn set_sample_rate(&mut self, _: f64) {}
+        }
+        let mut stft = Stft::new(Nothing, 1, 1024, 2);
+        stft.set_sample_rate(48000.0);
+        let spectrum = &stft.spectra[0];
+        assert_eq!(spectrum.number_of_bins(), 513);
+        assert_eq!(spectrum.bin_frequency(512), 24000.0);
+        assert_eq!(stft.hop_size(), 512);
+    }
+}
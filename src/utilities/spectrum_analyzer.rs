@@ -0,0 +1,294 @@
+//! Compute the spectrum of an audio stream in a background thread, e.g. to display it in a GUI.
+//!
+//! The [`SpectrumTap`] is used in the audio thread: it mixes the channels that are passed to
+//! [`SpectrumTap::tap`] down to mono and copies the samples into a lock-free ring buffer.
+//! The [`SpectrumTap`] also implements the `AudioRenderer` trait: it then copies its inputs to
+//! its outputs, so that it can be inserted in a chain of renderers.
+//!
+//! A background thread computes the magnitudes of overlapping, Hann-windowed FFT frames and
+//! publishes them on an [`AnalysisBus`], where they can be read with
+//! [`AnalysisSnapshot::spectrum`].
+//! The magnitudes are linear and scaled so that a sine wave with amplitude 1 at the center
+//! frequency of a bin gives a magnitude of 1 in that bin.
+//! [`SpectrumAnalyzer::bin_frequency`] gives the center frequency of each bin.
+//!
+//! ```
+//! use rsynth::utilities::spectrum_analyzer::{SpectrumAnalyzer, SpectrumSettings};
+//!
+//! let (mut tap, analyzer) = SpectrumAnalyzer::start(SpectrumSettings::default())
+//!     .expect("the thread can be started");
+//! let bus = analyzer.bus();
+//!
+//! // In the audio thread, e.g. at the end of `render_buffer`:
+//! # let left = [0.0_f32; 64];
+//! # let right = [0.0_f32; 64];
+//! tap.tap(&[&left[..], &right[..]]);
+//!
+//! // In the GUI thread:
+//! let loudest_bin = bus.read(|snapshot| {
+//!     snapshot
+//!         .spectrum()
+//!         .iter()
+//!         .enumerate()
+//!         .fold((0, 0.0), |(b, m), (bin, magnitude)| {
+//!             if *magnitude > m { (bin, *magnitude) } else { (b, m) }
+//!         })
+//!         .0
+//! });
+//! let frequency = analyzer.bin_frequency(loudest_bin, 44100.0);
+//! ```
+//!
+//! Real-time safety
+//! ----------------
+//! [`SpectrumTap::tap`] does not allocate memory, does not lock and does not perform I/O.
+//! When the background thread cannot keep up, samples are dropped.
+//!
+//! [`SpectrumTap`]: ./struct.SpectrumTap.html
+//! [`SpectrumTap::tap`]: ./struct.SpectrumTap.html#method.tap
+//! [`SpectrumAnalyzer::bin_frequency`]: ./struct.SpectrumAnalyzer.html#method.bin_frequency
+//! [`AnalysisBus`]: ../../analysis/struct.AnalysisBus.html
+//! [`AnalysisSnapshot::spectrum`]: ../../analysis/struct.AnalysisSnapshot.html#method.spectrum
+use crate::analysis::{analysis_bus, AnalysisBus};
+use crate::utilities::fft::fft;
+use crate::utilities::ring_buffer::RingBuffer;
+use crate::{AudioRenderer, ContextualAudioRenderer};
+use asprim::AsPrim;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// The number of samples that `tap` mixes down at once.
+const CHUNK_SIZE: usize = 256;
+
+/// The settings of the [`SpectrumAnalyzer`].
+///
+/// [`SpectrumAnalyzer`]: ./struct.SpectrumAnalyzer.html
+#[derive(Clone, Debug)]
+pub struct SpectrumSettings {
+    /// The number of samples of one FFT frame. This is rounded up to a power of two.
+    /// The spectrum has half as many bins.
+    pub fft_size: usize,
+    /// The number of FFT frames that overlap each sample, e.g. 4 to compute a new spectrum
+    /// every quarter of an FFT frame.
+    pub overlap: usize,
+    /// The number of samples that the ring buffer can hold.
+    pub ring_buffer_size: usize,
+    /// How long the background thread sleeps when not enough samples are available.
+    pub poll_interval: Duration,
+}
+
+impl Default for SpectrumSettings {
+    fn default() -> Self {
+        SpectrumSettings {
+            fft_size: 2048,
+            overlap: 4,
+            ring_buffer_size: 16384,
+            poll_interval: Duration::from_millis(5),
+        }
+    }
+}
+
+/// Copies samples from the audio thread to the [`SpectrumAnalyzer`].
+/// See the [module level documentation] for more information.
+///
+/// [`SpectrumAnalyzer`]: ./struct.SpectrumAnalyzer.html
+/// [module level documentation]: ./index.html
+pub struct SpectrumTap {
+    ring_buffer: Arc<RingBuffer>,
+    chunk: Vec<f32>,
+}
+
+impl SpectrumTap {
+    /// Mix the given channels down to mono and send the samples to the analyzer.
+    pub fn tap<S: AsPrim + Copy>(&mut self, channels: &[&[S]]) {
+        let number_of_frames = channels.first().map(|channel| channel.len()).unwrap_or(0);
+        if channels.is_empty() {
+            return;
+        }
+        let gain = 1.0 / channels.len() as f32;
+        let mut start = 0;
+        while start < number_of_frames {
+            let end = (start + CHUNK_SIZE).min(number_of_frames);
+            let length = (end - start).min(self.ring_buffer.free());
+            if length == 0 {
+                return;
+            }
+            let chunk = &mut self.chunk[..length];
+            for sample in chunk.iter_mut() {
+                *sample = 0.0;
+            }
+            for channel in channels {
+                for (sample, input) in chunk.iter_mut().zip(channel[start..].iter()) {
+                    *sample += input.as_::<f32>() * gain;
+                }
+            }
+            self.ring_buffer.push(chunk);
+            start = end;
+        }
+    }
+}
+
+impl<S: AsPrim + Copy> AudioRenderer<S> for SpectrumTap {
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        self.tap(inputs);
+        for (output, input) in outputs.iter_mut().zip(inputs.iter()) {
+            output.copy_from_slice(input);
+        }
+    }
+}
+
+impl<S: AsPrim + Copy, Context> ContextualAudioRenderer<S, Context> for SpectrumTap {
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], _context: &mut Context) {
+        AudioRenderer::render_buffer(self, inputs, outputs);
+    }
+}
+
+/// The background thread that computes the spectrum.
+/// See the [module level documentation] for more information.
+///
+/// Dropping the `SpectrumAnalyzer` stops the thread.
+///
+/// [module level documentation]: ./index.html
+pub struct SpectrumAnalyzer {
+    bus: Arc<AnalysisBus>,
+    fft_size: usize,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SpectrumAnalyzer {
+    /// Start the background thread.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory and starts a thread.
+    pub fn start(settings: SpectrumSettings) -> io::Result<(SpectrumTap, SpectrumAnalyzer)> {
+        let fft_size = settings.fft_size.max(4).next_power_of_two();
+        let hop_size = (fft_size / settings.overlap.clamp(1, fft_size)).max(1);
+        let number_of_bins = fft_size / 2;
+        let ring_buffer = Arc::new(RingBuffer::new(settings.ring_buffer_size.max(fft_size)));
+        let (mut publisher, bus) = analysis_bus(0, number_of_bins);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread_ring_buffer = ring_buffer.clone();
+        let poll_interval = settings.poll_interval;
+        let thread = thread::Builder::new()
+            .name("spectrum analyzer".to_string())
+            .spawn(move || {
+                let window: Vec<f64> = (0..fft_size)
+                    .map(|i| {
+                        let phase = 2.0 * std::f64::consts::PI * i as f64 / fft_size as f64;
+                        0.5 - 0.5 * phase.cos()
+                    })
+                    .collect();
+                // Scale the magnitudes so that a sine wave with amplitude 1 gives 1.
+                let scale = 2.0 / window.iter().sum::<f64>();
+                let mut history = vec![0.0_f32; fft_size];
+                let mut real = vec![0.0; fft_size];
+                let mut imaginary = vec![0.0; fft_size];
+                let mut magnitudes = vec![0.0_f32; number_of_bins];
+                while !thread_stop.load(Ordering::Acquire) {
+                    if thread_ring_buffer.available() < hop_size {
+                        thread::sleep(poll_interval);
+                        continue;
+                    }
+                    history.copy_within(hop_size.., 0);
+                    thread_ring_buffer.pop(&mut history[fft_size - hop_size..]);
+                    for (index, sample) in history.iter().enumerate() {
+                        real[index] = *sample as f64 * window[index];
+                        imaginary[index] = 0.0;
+                    }
+                    fft(&mut real, &mut imaginary, false);
+                    for (bin, magnitude) in magnitudes.iter_mut().enumerate() {
+                        *magnitude = ((real[bin] * real[bin] + imaginary[bin] * imaginary[bin])
+                            .sqrt()
+                            * scale) as f32;
+                    }
+                    publisher.set_spectrum(&magnitudes);
+                    publisher.publish();
+                }
+            })?;
+        let tap = SpectrumTap {
+            ring_buffer,
+            chunk: vec![0.0; CHUNK_SIZE],
+        };
+        let analyzer = SpectrumAnalyzer {
+            bus,
+            fft_size,
+            stop,
+            thread: Some(thread),
+        };
+        Ok((tap, analyzer))
+    }
+
+    /// The bus on which the spectrum is published.
+    pub fn bus(&self) -> Arc<AnalysisBus> {
+        self.bus.clone()
+    }
+
+    /// The number of samples of one FFT frame.
+    pub fn fft_size(&self) -> usize {
+        self.fft_size
+    }
+
+    /// The center frequency of the given bin, in Hz.
+    pub fn bin_frequency(&self, bin: usize, sample_rate: f64) -> f64 {
+        bin as f64 * sample_rate / self.fft_size as f64
+    }
+}
+
+impl Drop for SpectrumAnalyzer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn sine_wave_shows_up_in_its_bin() {
+        let settings = SpectrumSettings {
+            fft_size: 256,
+            overlap: 2,
+            ring_buffer_size: 4096,
+            poll_interval: Duration::from_millis(1),
+        };
+        let (mut tap, analyzer) = SpectrumAnalyzer::start(settings).expect("no error");
+        assert_eq!(analyzer.bin_frequency(32, 256.0), 32.0);
+        // A sine wave at the center frequency of bin 32, in both channels.
+        let sine: Vec<f32> = (0..1024)
+            .map(|i| (2.0 * std::f32::consts::PI * 32.0 * i as f32 / 256.0).sin())
+            .collect();
+        tap.tap(&[&sine[..], &sine[..]]);
+        let bus = analyzer.bus();
+        let start = Instant::now();
+        // Wait until the frames with only the sine wave have been analyzed.
+        while bus.read(|snapshot| snapshot.sequence_number()) < 6 {
+            assert!(start.elapsed() < Duration::from_secs(5), "Timed out.");
+            thread::sleep(Duration::from_millis(1));
+        }
+        let spectrum = bus.latest().spectrum().to_vec();
+        assert_eq!(spectrum.len(), 128);
+        assert!((spectrum[32] - 1.0).abs() < 0.01);
+        assert!(spectrum[20] < 0.01);
+        assert!(spectrum[40] < 0.01);
+    }
+
+    #[test]
+    fn tap_passes_the_audio_through() {
+        let (mut tap, _analyzer) =
+            SpectrumAnalyzer::start(SpectrumSettings::default()).expect("no error");
+        let input = [0.5_f32, -0.5];
+        let mut output = [0.0; 2];
+        AudioRenderer::render_buffer(&mut tap, &[&input], &mut [&mut output]);
+        assert_eq!(output, input);
+    }
+}
@@ -0,0 +1,368 @@
+//! A thread pool for rendering independent parts (e.g. voices) in parallel.
+//!
+//! Support is only enabled if you compile with the "thread-pool" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! The [`RealtimeThreadPool`] is designed to be used from the audio thread:
+//!
+//! * it has a fixed number of worker threads, that are started when the pool is created,
+//! * the worker threads can get real-time priority (currently only on Unix-like systems),
+//! * dispatching work does not allocate memory,
+//! * idle worker threads first busy-wait for a short time (so that they can react quickly
+//!   to the next dispatch) and then park (so that they don't burn CPU when the audio
+//!   thread is idle).
+//!
+//! The work is a slice of independent items (e.g. the voices of a synthesizer, each with its
+//! own output buffer). Each item is processed exactly once by one of the threads; the threads
+//! (including the calling thread) take the next unprocessed item as soon as they are done with
+//! the previous one, so that the work is balanced, even if some items take longer than others.
+//!
+//! Example
+//! -------
+//! ```
+//! use rsynth::utilities::thread_pool::RealtimeThreadPool;
+//!
+//! struct Voice {
+//!     frequency: f32,
+//!     output: Vec<f32>,
+//! }
+//!
+//! let mut voices: Vec<_> = (1..=8)
+//!     .map(|i| Voice { frequency: 110.0 * i as f32, output: vec![0.0; 64] })
+//!     .collect();
+//!
+//! // Note: creating the thread pool cannot be done in a real-time context.
+//! let mut pool = RealtimeThreadPool::new(3, None).expect("Failed to start the thread pool");
+//!
+//! // This can be done in a real-time context.
+//! pool.for_each_mut(&mut voices, |voice| {
+//!     for (index, sample) in voice.output.iter_mut().enumerate() {
+//!         *sample = (voice.frequency * index as f32 / 44100.0).sin();
+//!     }
+//! });
+//! // The voices can now be mixed.
+//! ```
+//!
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`RealtimeThreadPool`]: ./struct.RealtimeThreadPool.html
+use std::cell::UnsafeCell;
+use std::hint;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+// The number of times an idle thread checks for new work before it parks.
+const SPIN_ITERATIONS: usize = 1 << 14;
+
+// A type-erased description of the work.
+#[derive(Clone, Copy)]
+struct Job {
+    function: *const (),
+    items: *mut (),
+    number_of_items: usize,
+    call: unsafe fn(*const (), *mut (), usize),
+}
+
+impl Job {
+    fn empty() -> Self {
+        unsafe fn nothing(_: *const (), _: *mut (), _: usize) {}
+        Job {
+            function: std::ptr::null(),
+            items: std::ptr::null_mut(),
+            number_of_items: 0,
+            call: nothing,
+        }
+    }
+}
+
+// Process `items[index]` with `function`.
+//
+// Safety: `function` must point to an `F`, `items` must point to a slice of `T`s with more
+// than `index` elements and no other thread may access `items[index]` at the same time.
+unsafe fn call<T, F>(function: *const (), items: *mut (), index: usize)
+where
+    F: Fn(&mut T),
+{
+    let function = &*(function as *const F);
+    let item = &mut *(items as *mut T).add(index);
+    function(item);
+}
+
+struct Shared {
+    // Only written by the dispatching thread while `open` is `false` and `active` is `0`.
+    job: UnsafeCell<Job>,
+    // Incremented for each dispatch, so that the workers know there is new work.
+    generation: AtomicUsize,
+    // `true` while the workers are allowed to read `job`.
+    open: AtomicBool,
+    // The number of workers that may be reading `job`.
+    active: AtomicUsize,
+    // The index of the next item that is not yet claimed by a thread.
+    next_index: AtomicUsize,
+    // The number of items that are not yet processed.
+    remaining: AtomicUsize,
+    panicked: AtomicBool,
+    stop: AtomicBool,
+}
+
+// Access to `job` is synchronized with `open` and `active`.
+unsafe impl Sync for Shared {}
+unsafe impl Send for Shared {}
+
+impl Shared {
+    // Claim and process items until there are no more items left.
+    //
+    // Safety: `job` must be the job that is currently dispatched.
+    unsafe fn work(&self, job: Job) {
+        loop {
+            let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+            if index >= job.number_of_items {
+                return;
+            }
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                (job.call)(job.function, job.items, index)
+            }));
+            if result.is_err() {
+                self.panicked.store(true, Ordering::Relaxed);
+            }
+            self.remaining.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+/// A thread pool with a fixed number of worker threads that can be used in a real-time context.
+///
+/// See the [module level documentation] for more information.
+///
+/// The worker threads are stopped when the `RealtimeThreadPool` is dropped.
+///
+/// [module level documentation]: ./index.html
+pub struct RealtimeThreadPool {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl RealtimeThreadPool {
+    /// Start a thread pool with the given number of worker threads.
+    ///
+    /// The thread that dispatches the work also processes items, so in order to use `n` cores,
+    /// use `n - 1` worker threads.
+    ///
+    /// If `realtime_priority` is `Some(priority)`, the worker threads try to get real-time
+    /// priority with the given priority (e.g. the priority of the audio thread).
+    /// If this fails (e.g. because the user is not allowed to use real-time priority),
+    /// a warning is logged and the threads use the normal priority.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory and spawns threads.
+    pub fn new(number_of_workers: usize, realtime_priority: Option<i32>) -> io::Result<Self> {
+        let shared = Arc::new(Shared {
+            job: UnsafeCell::new(Job::empty()),
+            generation: AtomicUsize::new(0),
+            open: AtomicBool::new(false),
+            active: AtomicUsize::new(0),
+            next_index: AtomicUsize::new(0),
+            remaining: AtomicUsize::new(0),
+            panicked: AtomicBool::new(false),
+            stop: AtomicBool::new(false),
+        });
+        let mut pool = Self {
+            shared,
+            workers: Vec::with_capacity(number_of_workers),
+        };
+        for index in 0..number_of_workers {
+            let shared = pool.shared.clone();
+            let worker = thread::Builder::new()
+                .name(format!("rsynth worker {}", index))
+                .spawn(move || {
+                    if let Some(priority) = realtime_priority {
+                        set_realtime_priority(priority);
+                    }
+                    run_worker(&shared);
+                })?;
+            // If spawning fails, the workers that were already started are stopped
+            // when `pool` is dropped.
+            pool.workers.push(worker);
+        }
+        Ok(pool)
+    }
+
+    /// The number of worker threads.
+    pub fn number_of_workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Call `function` for each item in `items`, in parallel, and wait until all items have been
+    /// processed.
+    ///
+    /// The calling thread also processes items.
+    /// The order in which the items are processed is not specified.
+    ///
+    /// This method does not allocate memory.
+    ///
+    /// Panics
+    /// ------
+    /// Panics if `function` panicked for one of the items. The other items are still processed.
+    pub fn for_each_mut<T, F>(&mut self, items: &mut [T], function: F)
+    where
+        T: Send,
+        F: Fn(&mut T) + Sync,
+    {
+        if items.is_empty() {
+            return;
+        }
+        let shared = &*self.shared;
+        let job = Job {
+            function: &function as *const F as *const (),
+            items: items.as_mut_ptr() as *mut (),
+            number_of_items: items.len(),
+            call: call::<T, F>,
+        };
+        // Safety: `open` is `false` and `active` is `0`, so no worker is reading `job`.
+        unsafe {
+            *shared.job.get() = job;
+        }
+        shared.panicked.store(false, Ordering::Relaxed);
+        shared.next_index.store(0, Ordering::Relaxed);
+        shared.remaining.store(items.len(), Ordering::Relaxed);
+        shared.open.store(true, Ordering::SeqCst);
+        shared.generation.fetch_add(1, Ordering::SeqCst);
+        for worker in self.workers.iter() {
+            worker.thread().unpark();
+        }
+
+        // Safety: `job` is the job that is currently dispatched.
+        unsafe {
+            shared.work(job);
+        }
+
+        while shared.remaining.load(Ordering::Acquire) > 0 {
+            hint::spin_loop();
+        }
+        // Make sure that no worker is still reading `job` before `items` and `function` go
+        // out of scope.
+        shared.open.store(false, Ordering::SeqCst);
+        while shared.active.load(Ordering::SeqCst) > 0 {
+            hint::spin_loop();
+        }
+
+        if shared.panicked.load(Ordering::Relaxed) {
+            panic!("A thread of the thread pool panicked.");
+        }
+    }
+}
+
+impl Drop for RealtimeThreadPool {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::SeqCst);
+        for worker in self.workers.drain(..) {
+            worker.thread().unpark();
+            if worker.join().is_err() {
+                error!("A worker thread of the thread pool panicked.");
+            }
+        }
+    }
+}
+
+fn run_worker(shared: &Shared) {
+    let mut last_generation = shared.generation.load(Ordering::SeqCst);
+    loop {
+        let mut spins = 0;
+        loop {
+            if shared.stop.load(Ordering::SeqCst) {
+                return;
+            }
+            let generation = shared.generation.load(Ordering::SeqCst);
+            if generation != last_generation {
+                last_generation = generation;
+                break;
+            }
+            if spins < SPIN_ITERATIONS {
+                spins += 1;
+                hint::spin_loop();
+            } else {
+                thread::park();
+            }
+        }
+
+        // Announce that we may read the job before checking that this is allowed, so that the
+        // dispatching thread waits for us.
+        shared.active.fetch_add(1, Ordering::SeqCst);
+        if shared.open.load(Ordering::SeqCst) {
+            // Safety: `open` is `true` and we're counted in `active`, so the job is not
+            // modified and the items and the function are still alive.
+            unsafe {
+                let job = *shared.job.get();
+                shared.work(job);
+            }
+        }
+        shared.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(unix)]
+fn set_realtime_priority(priority: i32) {
+    let parameters = libc::sched_param {
+        sched_priority: priority,
+    };
+    let result =
+        unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &parameters) };
+    if result != 0 {
+        warn!(
+            "Failed to set real-time priority {} for a worker thread (error code {}).",
+            priority, result
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn set_realtime_priority(priority: i32) {
+    warn!(
+        "Setting real-time priority {} for worker threads is not supported on this platform.",
+        priority
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RealtimeThreadPool;
+
+    #[test]
+    fn every_item_is_processed_exactly_once() {
+        let mut pool = RealtimeThreadPool::new(3, None).expect("Failed to start the thread pool");
+        let mut items = vec![0_usize; 100];
+        for round in 0..200 {
+            pool.for_each_mut(&mut items, |item| *item += 1);
+            assert!(items.iter().all(|item| *item == round + 1));
+        }
+    }
+
+    #[test]
+    fn works_without_worker_threads() {
+        let mut pool = RealtimeThreadPool::new(0, None).expect("Failed to start the thread pool");
+        let mut items: Vec<_> = (0..10).collect();
+        pool.for_each_mut(&mut items, |item| *item *= 2);
+        assert_eq!(items, (0..10).map(|item| item * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn panics_are_propagated_and_the_pool_can_be_used_afterwards() {
+        let mut pool = RealtimeThreadPool::new(2, None).expect("Failed to start the thread pool");
+        let mut items = vec![0; 10];
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            pool.for_each_mut(&mut items, |item| {
+                *item += 1;
+                if *item == 1 {
+                    panic!("Expected panic.");
+                }
+            })
+        }));
+        assert!(result.is_err());
+        let mut items = vec![1; 10];
+        pool.for_each_mut(&mut items, |item| *item += 1);
+        assert_eq!(items, vec![2; 10]);
+    }
+}
@@ -0,0 +1,431 @@
+//! Quantize or humanize the timing and the velocity of incoming notes.
+//!
+//! Wrap the plugin in a [`TimingFilter`] to move note events before they reach the plugin:
+//! * quantizing moves every note on towards the nearest position on a grid in beats,
+//!   using the state of the transport;
+//! * humanizing moves every note on by a bounded random offset and varies its velocity.
+//!
+//! The note off is moved by the same amount as the corresponding note on, so that the length
+//! of the note does not change.
+//! Events can be moved forward in time beyond the end of the buffer; they are then passed to the
+//! plugin in one of the next buffers. Events cannot be moved before the start of the buffer.
+//!
+//! Because the transport is only known when rendering, the events are collected by the
+//! `handle_event` method and passed to the plugin at the start of `render_buffer`.
+//! When using the `AudioRenderer` trait, call [`set_transport`] before every buffer;
+//! when using the `ContextualAudioRenderer` trait, the transport is obtained from the context
+//! with the [`TransportContext`] trait.
+//!
+//! Real-time safety
+//! ----------------
+//! The filter does not allocate memory after its creation. When more events are received than
+//! the capacity allows, the events that do not fit are passed to the plugin without moving them.
+//!
+//! [`TimingFilter`]: ./struct.TimingFilter.html
+//! [`set_transport`]: ./struct.TimingFilter.html#method.set_transport
+//! [`TransportContext`]: ../../transport/trait.TransportContext.html
+use crate::buffer::number_of_frames;
+use crate::event::{ContextualEventHandler, EventHandler, RawMidiEvent, Timed};
+use crate::meta::Meta;
+use crate::transport::{TransportContext, TransportState};
+use crate::utilities::random::Random;
+use crate::utilities::rt_log::Level;
+use crate::{AudioHandler, AudioRenderer, ContextualAudioRenderer};
+use midi_consts::channel_event::{EVENT_TYPE_MASK, MIDI_CHANNEL_MASK, NOTE_OFF, NOTE_ON};
+
+/// Wraps a plugin and quantizes or humanizes the timing of incoming notes.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct TimingFilter<P> {
+    inner: P,
+    sample_rate: f64,
+    transport: TransportState,
+    // The grid in beats and the strength, from 0 (no quantization) to 1.
+    quantize: Option<(f64, f64)>,
+    max_time_offset: f64,
+    max_velocity_offset: u8,
+    random: Random,
+    // The events received since the last buffer, with their time in this buffer.
+    // Events that have been moved to `pending` are replaced by `None`.
+    incoming: Vec<Option<Timed<RawMidiEvent>>>,
+    // The events to pass to the plugin, sorted by time relative to the start of the buffer.
+    pending: Vec<(u64, RawMidiEvent)>,
+    // For every channel and key, how many frames the last note on has been moved.
+    shifts: [[i64; 128]; 16],
+}
+
+impl<P> TimingFilter<P> {
+    /// Wrap the given plugin. `capacity` is the maximum number of events that can be delayed.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(inner: P, capacity: usize) -> Self {
+        TimingFilter {
+            inner,
+            sample_rate: 44100.0,
+            transport: TransportState::default(),
+            quantize: None,
+            max_time_offset: 0.0,
+            max_velocity_offset: 0,
+            random: Random::default(),
+            incoming: Vec::with_capacity(capacity),
+            pending: Vec::with_capacity(capacity),
+            shifts: [[0; 128]; 16],
+        }
+    }
+
+    /// Quantize note ons to a grid of `grid` beats (quarter notes), e.g. `0.25` for
+    /// sixteenth notes. `strength` runs from 0 (the notes are not moved) to 1 (the notes
+    /// are moved onto the grid).
+    /// Notes are only quantized while the transport is playing.
+    pub fn with_quantize(mut self, grid: f64, strength: f64) -> Self {
+        self.quantize = if grid > 0.0 {
+            Some((grid, strength.clamp(0.0, 1.0)))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Move every note on by a random offset of at most `max_time_offset` seconds
+    /// (earlier or later) and change its velocity by at most `max_velocity_offset`.
+    pub fn with_humanize(mut self, max_time_offset: f64, max_velocity_offset: u8) -> Self {
+        self.max_time_offset = max_time_offset.abs();
+        self.max_velocity_offset = max_velocity_offset;
+        self
+    }
+
+    /// Set the seed of the random number generator that is used for humanizing.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.random.set_seed(seed);
+        self
+    }
+
+    /// Set the state of the transport at the start of the next buffer.
+    /// This is only needed when using the `AudioRenderer` trait.
+    pub fn set_transport(&mut self, transport: TransportState) {
+        self.transport = transport;
+    }
+
+    /// Get a reference to the wrapped plugin.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the wrapped plugin.
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.inner
+    }
+
+    /// Return the wrapped plugin.
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+
+    // The number of frames to move a note on that is received at the given frame.
+    fn shift_for_note_on(&mut self, time_in_frames: u32) -> i64 {
+        let mut shift = 0.0;
+        let transport = &self.transport;
+        if let Some((grid, strength)) = self.quantize {
+            if transport.is_playing && transport.tempo > 0.0 {
+                let frames_per_beat = self.sample_rate * 60.0 / transport.tempo;
+                let beat = transport.position_in_beats + time_in_frames as f64 / frames_per_beat;
+                let nearest = (beat / grid).round() * grid;
+                shift += (nearest - beat) * frames_per_beat * strength;
+            }
+        }
+        if self.max_time_offset > 0.0 {
+            shift += self.random.next_bipolar() * self.max_time_offset * self.sample_rate;
+        }
+        shift.round() as i64
+    }
+
+    fn vary_velocity(&mut self, velocity: u8) -> u8 {
+        if self.max_velocity_offset == 0 {
+            return velocity;
+        }
+        let offset = self.random.next_bipolar() * (self.max_velocity_offset as f64 + 0.5);
+        (velocity as f64 + offset).round().clamp(1.0, 127.0) as u8
+    }
+
+    // Move the incoming events into `pending`.
+    // The events that do not fit in `pending` are left in `incoming`.
+    fn schedule_incoming(&mut self) {
+        for index in 0..self.incoming.len() {
+            let Timed {
+                time_in_frames,
+                mut event,
+            } = match self.incoming[index] {
+                Some(event) => event,
+                None => continue,
+            };
+            let data = event.data();
+            let (status, key, velocity) = (data[0], (data[1] & 0x7F) as usize, data[2]);
+            let channel = (status & MIDI_CHANNEL_MASK) as usize;
+            let shift = match status & EVENT_TYPE_MASK {
+                NOTE_ON if velocity > 0 => {
+                    let shift = self.shift_for_note_on(time_in_frames);
+                    self.shifts[channel][key] = shift;
+                    let velocity = self.vary_velocity(velocity);
+                    event = RawMidiEvent::new(&[status, key as u8, velocity]);
+                    shift
+                }
+                NOTE_ON | NOTE_OFF => self.shifts[channel][key],
+                _ => 0,
+            };
+            let time = (time_in_frames as i64 + shift).max(0) as u64;
+            if self.pending.len() == self.pending.capacity() {
+                // No room to delay the event: pass it on as it is.
+                self.incoming[index] = Some(Timed::new(time_in_frames, event));
+                continue;
+            }
+            let position = self.pending.partition_point(|(t, _)| *t <= time);
+            self.pending.insert(position, (time, event));
+            self.incoming[index] = None;
+        }
+    }
+
+    // Pass the events that fall within the buffer to `handler`
+    // and make the time of the other events relative to the start of the next buffer.
+    fn dispatch<F: FnMut(&mut P, Timed<RawMidiEvent>)>(
+        &mut self,
+        number_of_frames: usize,
+        mut handler: F,
+    ) {
+        self.schedule_incoming();
+        let number_of_frames = number_of_frames as u64;
+        let ready = self
+            .pending
+            .partition_point(|(time, _)| *time < number_of_frames);
+        // The events that did not fit in `pending` are passed in chronological order with the
+        // pending events, after the pending events at the same time, which are older.
+        let mut not_delayed = self.incoming.drain(..).flatten().peekable();
+        for (time, event) in self.pending.drain(..ready) {
+            while let Some(earlier) =
+                not_delayed.next_if(|earlier| (earlier.time_in_frames as u64) < time)
+            {
+                handler(&mut self.inner, earlier);
+            }
+            handler(&mut self.inner, Timed::new(time as u32, event));
+        }
+        for event in not_delayed {
+            handler(&mut self.inner, event);
+        }
+        for (time, _) in self.pending.iter_mut() {
+            *time -= number_of_frames;
+        }
+    }
+}
+
+impl<P> EventHandler<Timed<RawMidiEvent>> for TimingFilter<P> {
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>) {
+        if self.incoming.len() < self.incoming.capacity() {
+            self.incoming.push(Some(event));
+        } else {
+            crate::rt_log!(Level::Warn, "Timing filter is full, dropping an event.");
+        }
+    }
+}
+
+impl<P, Context> ContextualEventHandler<Timed<RawMidiEvent>, Context> for TimingFilter<P> {
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>, _context: &mut Context) {
+        EventHandler::handle_event(self, event);
+    }
+}
+
+impl<P, S> AudioRenderer<S> for TimingFilter<P>
+where
+    P: AudioRenderer<S> + EventHandler<Timed<RawMidiEvent>>,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        let number_of_frames = number_of_frames(inputs, outputs);
+        self.dispatch(number_of_frames, |inner, event| inner.handle_event(event));
+        self.inner.render_buffer(inputs, outputs);
+    }
+}
+
+impl<P, S, Context> ContextualAudioRenderer<S, Context> for TimingFilter<P>
+where
+    P: ContextualAudioRenderer<S, Context> + ContextualEventHandler<Timed<RawMidiEvent>, Context>,
+    Context: TransportContext,
+{
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]], context: &mut Context) {
+        if let Some(transport) = context.transport() {
+            self.transport = transport;
+        }
+        let number_of_frames = number_of_frames(inputs, outputs);
+        self.dispatch(number_of_frames, |inner, event| {
+            inner.handle_event(event, context)
+        });
+        self.inner.render_buffer(inputs, outputs, context);
+    }
+}
+
+impl<P> AudioHandler for TimingFilter<P>
+where
+    P: AudioHandler,
+{
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        self.inner.set_sample_rate(sample_rate);
+    }
+
+    fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.inner.set_max_buffer_size(max_buffer_size);
+    }
+}
+
+impl<P> Meta for TimingFilter<P>
+where
+    P: Meta,
+{
+    type MetaData = P::MetaData;
+
+    fn meta(&self) -> &Self::MetaData {
+        self.inner.meta()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Collector {
+        events: Vec<(u32, [u8; 3])>,
+        buffers: usize,
+    }
+
+    impl EventHandler<Timed<RawMidiEvent>> for Collector {
+        fn handle_event(&mut self, timed: Timed<RawMidiEvent>) {
+            let data = timed.event.data();
+            self.events
+                .push((timed.time_in_frames, [data[0], data[1], data[2]]));
+        }
+    }
+
+    impl AudioRenderer<f32> for Collector {
+        fn render_buffer(&mut self, _inputs: &[&[f32]], _outputs: &mut [&mut [f32]]) {
+            self.buffers += 1;
+        }
+    }
+
+    impl AudioHandler for Collector {
+        fn set_sample_rate(&mut self, _sample_rate: f64) {}
+    }
+
+    fn event(time: u32, status: u8, key: u8, velocity: u8) -> Timed<RawMidiEvent> {
+        Timed::new(time, RawMidiEvent::new(&[status, key, velocity]))
+    }
+
+    fn render(filter: &mut TimingFilter<Collector>, number_of_frames: usize) {
+        let mut output = vec![0.0; number_of_frames];
+        AudioRenderer::render_buffer(filter, &[], &mut [&mut output]);
+    }
+
+    // One beat per second at a sample rate of 100 Hz: 100 frames per beat.
+    fn filter() -> TimingFilter<Collector> {
+        let mut filter = TimingFilter::new(Collector::default(), 16);
+        filter.set_sample_rate(100.0);
+        filter.set_transport(TransportState {
+            is_playing: true,
+            tempo: 60.0,
+            ..TransportState::default()
+        });
+        filter
+    }
+
+    #[test]
+    fn quantizes_note_on_and_moves_note_off_by_the_same_amount() {
+        // Sixteenth notes: 25 frames.
+        let mut filter = filter().with_quantize(0.25, 1.0);
+        EventHandler::handle_event(&mut filter, event(10, NOTE_ON, 60, 100));
+        EventHandler::handle_event(&mut filter, event(15, NOTE_ON, 62, 100));
+        EventHandler::handle_event(&mut filter, event(30, 0xB0, 1, 2));
+        EventHandler::handle_event(&mut filter, event(40, NOTE_OFF, 62, 0));
+        render(&mut filter, 100);
+        assert_eq!(
+            filter.inner().events,
+            vec![
+                (0, [NOTE_ON, 60, 100]),
+                (25, [NOTE_ON, 62, 100]),
+                (30, [0xB0, 1, 2]),
+                (50, [NOTE_OFF, 62, 0]),
+            ]
+        );
+        assert_eq!(filter.inner().buffers, 1);
+    }
+
+    #[test]
+    fn events_are_passed_to_a_renderer_without_audio_outputs() {
+        let mut filter = filter().with_quantize(0.25, 1.0);
+        EventHandler::handle_event(&mut filter, event(15, NOTE_ON, 60, 100));
+        let input = vec![0.0; 20];
+        AudioRenderer::render_buffer(&mut filter, &[&input], &mut []);
+        assert!(filter.inner().events.is_empty());
+        AudioRenderer::render_buffer(&mut filter, &[&input], &mut []);
+        assert_eq!(filter.inner().events, vec![(5, [NOTE_ON, 60, 100])]);
+    }
+
+    #[test]
+    fn delayed_events_are_passed_in_the_next_buffer() {
+        let mut filter = filter().with_quantize(0.25, 1.0);
+        EventHandler::handle_event(&mut filter, event(15, NOTE_ON, 60, 100));
+        render(&mut filter, 20);
+        assert!(filter.inner().events.is_empty());
+        render(&mut filter, 20);
+        assert_eq!(filter.inner().events, vec![(5, [NOTE_ON, 60, 100])]);
+    }
+
+    #[test]
+    fn events_that_do_not_fit_are_passed_after_older_pending_events() {
+        let mut filter = TimingFilter::new(Collector::default(), 1).with_quantize(0.25, 1.0);
+        filter.set_sample_rate(100.0);
+        filter.set_transport(TransportState {
+            is_playing: true,
+            tempo: 60.0,
+            ..TransportState::default()
+        });
+        EventHandler::handle_event(&mut filter, event(15, NOTE_ON, 60, 100));
+        render(&mut filter, 20);
+        // The note on is pending, so there is no room to delay this event.
+        EventHandler::handle_event(&mut filter, event(10, 0xB0, 1, 2));
+        render(&mut filter, 20);
+        assert_eq!(
+            filter.inner().events,
+            vec![(5, [NOTE_ON, 60, 100]), (10, [0xB0, 1, 2])]
+        );
+    }
+
+    #[test]
+    fn does_not_quantize_when_the_transport_is_stopped() {
+        let mut filter = filter().with_quantize(0.25, 1.0);
+        filter.set_transport(TransportState::default());
+        EventHandler::handle_event(&mut filter, event(10, NOTE_ON, 60, 100));
+        render(&mut filter, 100);
+        assert_eq!(filter.inner().events, vec![(10, [NOTE_ON, 60, 100])]);
+    }
+
+    #[test]
+    fn humanize_stays_within_bounds() {
+        // At most 5 frames and 10 velocity steps.
+        let mut filter = filter().with_humanize(0.05, 10).with_seed(42);
+        let mut moved = false;
+        for _ in 0..100 {
+            EventHandler::handle_event(&mut filter, event(50, NOTE_ON, 60, 64));
+            EventHandler::handle_event(&mut filter, event(60, NOTE_OFF, 60, 0));
+            render(&mut filter, 100);
+            let events = std::mem::take(&mut filter.inner_mut().events);
+            let (on_time, on) = events[0];
+            let (off_time, _) = events[1];
+            assert!((45..=55).contains(&on_time));
+            assert!((54..=74).contains(&on[2]));
+            assert_eq!(off_time - on_time, 10);
+            moved |= on_time != 50;
+        }
+        assert!(moved);
+    }
+}
@@ -0,0 +1,187 @@
+//! Drop values that are no longer used by the audio thread in a background thread.
+//!
+//! Dropping a value that owns memory (e.g. a `Vec` or a `Box`) deallocates that memory,
+//! which is not real-time safe. When the audio thread replaces such a value (an old wavetable,
+//! an old voice configuration, ...), it throws the old value in the [`TrashCan`] instead.
+//! The values in the trash can are dropped by a background thread, so that no `Drop`
+//! implementation runs in `render_buffer`.
+//!
+//! This module is only available when compiled with the `trash-can` feature.
+//!
+//! ```
+//! use rsynth::utilities::trash_can::trash_can;
+//!
+//! let (mut trash_can, collector) = trash_can(16).expect("the thread can be started");
+//! let mut table = vec![0.0_f32; 2048];
+//!
+//! // In the audio thread, with a new table that has been prepared by another thread:
+//! # let new_table = vec![1.0_f32; 2048];
+//! if let Err(new_table) = trash_can.replace(&mut table, new_table) {
+//!     // The trash can is full: keep the new table and try again in the next buffer.
+//! }
+//! ```
+//!
+//! Real-time safety
+//! ----------------
+//! The methods of the [`TrashCan`] do not allocate memory, do not lock and do not drop values.
+//! Dropping the [`TrashCan`] itself may drop the values that have not yet been collected.
+//!
+//! See also [`wavetable_swap`] and the [`worker`] module for the same pattern in a more
+//! specific setting.
+//!
+//! [`TrashCan`]: ./struct.TrashCan.html
+//! [`wavetable_swap`]: ../wavetable/fn.wavetable_swap.html
+//! [`worker`]: ../worker/index.html
+use ringbuf::{Producer, RingBuffer};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// How long the collector sleeps when the trash can is empty.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Create a [`TrashCan`] that can hold `capacity` values and start the [`TrashCollector`]
+/// thread that drops them.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------
+/// This function allocates memory and starts a thread.
+///
+/// [`TrashCan`]: ./struct.TrashCan.html
+/// [`TrashCollector`]: ./struct.TrashCollector.html
+pub fn trash_can<T>(capacity: usize) -> io::Result<(TrashCan<T>, TrashCollector)>
+where
+    T: Send + 'static,
+{
+    let (producer, mut consumer) = RingBuffer::<T>::new(capacity.max(1)).split();
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+    let thread = thread::Builder::new()
+        .name("trash collector".to_string())
+        .spawn(move || {
+            loop {
+                // Read `stop` before emptying the trash can, so that the values that have been
+                // thrown away before stopping are dropped here.
+                let is_stopped = thread_stop.load(Ordering::Acquire);
+                while let Some(value) = consumer.pop() {
+                    drop(value);
+                }
+                if is_stopped {
+                    break;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        })?;
+    let collector = TrashCollector {
+        stop,
+        thread: Some(thread),
+    };
+    Ok((TrashCan { producer }, collector))
+}
+
+/// Collects the values that are thrown away by the audio thread. Created with [`trash_can`].
+///
+/// [`trash_can`]: ./fn.trash_can.html
+pub struct TrashCan<T> {
+    producer: Producer<T>,
+}
+
+impl<T> TrashCan<T> {
+    /// Throw away a value, so that it is dropped by the collector thread.
+    /// Return the value as an error when the trash can is full.
+    pub fn throw_away(&mut self, value: T) -> Result<(), T> {
+        self.producer.push(value)
+    }
+
+    /// Replace `current` by `new` and throw away the old value.
+    /// When the trash can is full, `current` is not changed and `new` is returned as an error.
+    pub fn replace(&mut self, current: &mut T, new: T) -> Result<(), T> {
+        if self.producer.is_full() {
+            return Err(new);
+        }
+        let old = std::mem::replace(current, new);
+        if self.producer.push(old).is_err() {
+            unreachable!("The trash can has been checked not to be full.");
+        }
+        Ok(())
+    }
+
+    /// Return `true` when no more values can be thrown away until the collector has
+    /// emptied the trash can.
+    pub fn is_full(&self) -> bool {
+        self.producer.is_full()
+    }
+}
+
+/// The thread that drops the values in the trash can. Created with [`trash_can`].
+///
+/// Dropping the `TrashCollector` drops the values that are in the trash can and stops the
+/// thread. Values that are thrown away after that are dropped when the [`TrashCan`] is dropped.
+///
+/// [`trash_can`]: ./fn.trash_can.html
+/// [`TrashCan`]: ./struct.TrashCan.html
+pub struct TrashCollector {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for TrashCollector {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::thread::ThreadId;
+
+    struct DropRecorder {
+        dropped_in: Arc<Mutex<Vec<ThreadId>>>,
+    }
+
+    impl Drop for DropRecorder {
+        fn drop(&mut self) {
+            self.dropped_in
+                .lock()
+                .expect("not poisoned")
+                .push(thread::current().id());
+        }
+    }
+
+    #[test]
+    fn values_are_dropped_by_the_collector() {
+        let dropped_in = Arc::new(Mutex::new(Vec::new()));
+        let value = || DropRecorder {
+            dropped_in: dropped_in.clone(),
+        };
+        let (mut trash_can, collector) = trash_can(4).expect("no error");
+        let mut current = value();
+        assert!(trash_can.replace(&mut current, value()).is_ok());
+        assert!(trash_can.throw_away(value()).is_ok());
+        // Dropping the collector drops the remaining values.
+        drop(collector);
+        let dropped_in = dropped_in.lock().expect("not poisoned");
+        assert_eq!(dropped_in.len(), 2);
+        assert!(dropped_in.iter().all(|id| *id != thread::current().id()));
+    }
+
+    #[test]
+    fn full_trash_can_returns_the_value() {
+        let (mut trash_can, collector) = trash_can(1).expect("no error");
+        // Stop the collector, so that the trash can is not emptied.
+        drop(collector);
+        assert_eq!(trash_can.throw_away(1), Ok(()));
+        assert!(trash_can.is_full());
+        assert_eq!(trash_can.throw_away(2), Err(2));
+        let mut current = 3;
+        assert_eq!(trash_can.replace(&mut current, 4), Err(4));
+        assert_eq!(current, 3);
+    }
+}
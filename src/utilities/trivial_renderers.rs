@@ -0,0 +1,247 @@
+//! Renderers that do (almost) nothing.
+//!
+//! * [`Silence`]: writes silence to its outputs and has no inputs;
+//! * [`PassThrough`]: copies its inputs to its outputs;
+//! * [`NullSink`]: ignores its inputs and has no outputs.
+//!
+//! These renderers ignore all events. They are useful as placeholders in a chain of renderers,
+//! for measuring the overhead of a back-end and as minimal working examples in tests.
+//!
+//! ```
+//! use rsynth::utilities::trivial_renderers::PassThrough;
+//! use rsynth::{AudioHandlerMeta, AudioRenderer};
+//!
+//! let mut pass_through = PassThrough::new(1);
+//! assert_eq!(pass_through.max_number_of_audio_inputs(), 1);
+//! let input = [1.0, 2.0, 3.0];
+//! let mut output = [0.0; 3];
+//! pass_through.render_buffer(&[&input[..]], &mut [&mut output[..]]);
+//! assert_eq!(output, input);
+//! ```
+//!
+//! [`Silence`]: ./struct.Silence.html
+//! [`PassThrough`]: ./struct.PassThrough.html
+//! [`NullSink`]: ./struct.NullSink.html
+use crate::event::{ContextualEventHandler, EventHandler};
+use crate::meta::{InOut, Meta, MetaData};
+use crate::{AudioHandler, AudioRenderer, ContextualAudioRenderer, Latency, Lifecycle};
+use num_traits::Zero;
+
+pub(super) fn meta_data(
+    name: &str,
+    number_of_inputs: usize,
+    number_of_outputs: usize,
+) -> MetaData<String, String, String> {
+    MetaData {
+        general_meta: name.to_string(),
+        audio_port_meta: InOut {
+            inputs: (1..=number_of_inputs)
+                .map(|index| format!("audio in {}", index))
+                .collect(),
+            outputs: (1..=number_of_outputs)
+                .map(|index| format!("audio out {}", index))
+                .collect(),
+        },
+        midi_port_meta: InOut {
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        },
+    }
+}
+
+// Implement the traits that are the same for every renderer in this module and in the
+// `channel_routing` module.
+macro_rules! impl_trivial_traits {
+    ($renderer:ident) => {
+        impl<S, Context> ContextualAudioRenderer<S, Context> for $renderer
+        where
+            $renderer: AudioRenderer<S>,
+        {
+            fn render_buffer(
+                &mut self,
+                inputs: &[&[S]],
+                outputs: &mut [&mut [S]],
+                _context: &mut Context,
+            ) {
+                AudioRenderer::render_buffer(self, inputs, outputs);
+            }
+        }
+
+        impl<E> EventHandler<E> for $renderer {
+            fn handle_event(&mut self, _event: E) {}
+        }
+
+        impl<E, Context> ContextualEventHandler<E, Context> for $renderer {
+            fn handle_event(&mut self, _event: E, _context: &mut Context) {}
+        }
+
+        impl AudioHandler for $renderer {
+            fn set_sample_rate(&mut self, _sample_rate: f64) {}
+        }
+
+        impl Latency for $renderer {
+            fn latency_in_frames(&self) -> usize {
+                0
+            }
+        }
+
+        impl Lifecycle for $renderer {}
+
+        impl Meta for $renderer {
+            type MetaData = MetaData<String, String, String>;
+
+            fn meta(&self) -> &Self::MetaData {
+                &self.meta
+            }
+        }
+    };
+}
+
+pub(super) use impl_trivial_traits;
+
+/// Writes silence to its outputs. Has no audio inputs.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct Silence {
+    meta: MetaData<String, String, String>,
+}
+
+impl Silence {
+    /// Create a new `Silence` with the given number of audio outputs.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(number_of_outputs: usize) -> Self {
+        Silence {
+            meta: meta_data("silence", 0, number_of_outputs),
+        }
+    }
+}
+
+impl<S: Zero + Copy> AudioRenderer<S> for Silence {
+    fn render_buffer(&mut self, _inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        for output in outputs.iter_mut() {
+            for sample in output.iter_mut() {
+                *sample = S::zero();
+            }
+        }
+    }
+}
+
+impl_trivial_traits!(Silence);
+
+/// Copies its audio inputs to its audio outputs.
+///
+/// Outputs without a corresponding input are filled with silence.
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct PassThrough {
+    meta: MetaData<String, String, String>,
+}
+
+impl PassThrough {
+    /// Create a new `PassThrough` with the given number of audio inputs and outputs.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(number_of_channels: usize) -> Self {
+        PassThrough {
+            meta: meta_data("pass through", number_of_channels, number_of_channels),
+        }
+    }
+}
+
+impl<S: Zero + Copy> AudioRenderer<S> for PassThrough {
+    fn render_buffer(&mut self, inputs: &[&[S]], outputs: &mut [&mut [S]]) {
+        for (index, output) in outputs.iter_mut().enumerate() {
+            match inputs.get(index) {
+                Some(input) => output.copy_from_slice(input),
+                None => {
+                    for sample in output.iter_mut() {
+                        *sample = S::zero();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl_trivial_traits!(PassThrough);
+
+/// Ignores its audio inputs. Has no audio outputs.
+///
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct NullSink {
+    meta: MetaData<String, String, String>,
+}
+
+impl NullSink {
+    /// Create a new `NullSink` with the given number of audio inputs.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(number_of_inputs: usize) -> Self {
+        NullSink {
+            meta: meta_data("null sink", number_of_inputs, 0),
+        }
+    }
+}
+
+impl<S> AudioRenderer<S> for NullSink {
+    fn render_buffer(&mut self, _inputs: &[&[S]], _outputs: &mut [&mut [S]]) {}
+}
+
+impl_trivial_traits!(NullSink);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AudioHandlerMeta, CommonAudioPortMeta, CommonPluginMeta};
+
+    #[test]
+    fn silence_clears_the_outputs() {
+        let mut silence = Silence::new(2);
+        assert_eq!(silence.max_number_of_audio_inputs(), 0);
+        assert_eq!(silence.max_number_of_audio_outputs(), 2);
+        assert_eq!(silence.audio_output_name(1), "audio out 2");
+        let mut left = [1.0_f32; 4];
+        let mut right = [2.0_f32; 4];
+        AudioRenderer::render_buffer(&mut silence, &[], &mut [&mut left, &mut right]);
+        assert_eq!(left, [0.0; 4]);
+        assert_eq!(right, [0.0; 4]);
+    }
+
+    #[test]
+    fn pass_through_copies_the_inputs() {
+        let mut pass_through = PassThrough::new(1);
+        assert_eq!(pass_through.name(), "pass through");
+        let input = [1.0_f64, 2.0, 3.0];
+        let mut first = [0.0; 3];
+        let mut second = [5.0; 3];
+        ContextualAudioRenderer::render_buffer(
+            &mut pass_through,
+            &[&input],
+            &mut [&mut first, &mut second],
+            &mut (),
+        );
+        assert_eq!(first, input);
+        assert_eq!(second, [0.0; 3]);
+    }
+
+    #[test]
+    fn null_sink_has_no_outputs() {
+        let mut sink = NullSink::new(2);
+        assert_eq!(sink.max_number_of_audio_inputs(), 2);
+        assert_eq!(sink.max_number_of_audio_outputs(), 0);
+        EventHandler::handle_event(&mut sink, 42);
+        AudioRenderer::<f32>::render_buffer(&mut sink, &[&[1.0], &[2.0]], &mut []);
+    }
+}
@@ -0,0 +1,245 @@
+// Loading of Serum-style `.wav` files and Surge `.wt` files.
+use super::{Wavetable, WavetableError};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// The frame length of a `.wav` file without `clm ` chunk.
+const DEFAULT_FRAME_LENGTH: usize = 2048;
+
+// Flags in the header of a `.wt` file.
+const WT_INT16: u16 = 0x04;
+const WT_INT16_FULL_RANGE: u16 = 0x08;
+
+fn read_u16(data: &[u8]) -> u16 {
+    u16::from_le_bytes([data[0], data[1]])
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+}
+
+impl Wavetable {
+    /// Load a `.wav` or a `.wt` file. The format is determined by the content of the file.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory and performs I/O.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, WavetableError> {
+        let mut data = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(WavetableError::Io)?;
+        Self::from_bytes(&data)
+    }
+
+    /// Load a `.wav` or a `.wt` file that is already in memory.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, WavetableError> {
+        match data.get(0..4) {
+            Some(b"RIFF") => from_wav(data),
+            Some(b"vawt") => from_wt(data),
+            _ => Err(WavetableError::UnknownFormat),
+        }
+    }
+}
+
+fn from_wav(data: &[u8]) -> Result<Wavetable, WavetableError> {
+    if data.get(8..12) != Some(&b"WAVE"[..]) {
+        return Err(WavetableError::UnknownFormat);
+    }
+    let mut format = None;
+    let mut sample_data = None;
+    let mut frame_length = DEFAULT_FRAME_LENGTH;
+    let mut rest = &data[12..];
+    while rest.len() >= 8 {
+        let size = read_u32(&rest[4..8]) as usize;
+        let chunk = rest
+            .get(8..8 + size)
+            .ok_or(WavetableError::InvalidFile("chunk size"))?;
+        match &rest[0..4] {
+            b"fmt " => format = Some(chunk),
+            b"data" => sample_data = Some(chunk),
+            b"clm " => frame_length = parse_clm(chunk)?,
+            _ => {}
+        }
+        // Chunks are padded to an even number of bytes.
+        rest = rest.get(8 + size + (size & 1)..).unwrap_or(&[]);
+    }
+    let format = format.ok_or(WavetableError::InvalidFile("fmt "))?;
+    let sample_data = sample_data.ok_or(WavetableError::InvalidFile("data"))?;
+    if format.len() < 16 {
+        return Err(WavetableError::InvalidFile("fmt "));
+    }
+    let mut format_tag = read_u16(&format[0..2]);
+    let number_of_channels = read_u16(&format[2..4]) as usize;
+    let bits_per_sample = read_u16(&format[14..16]) as usize;
+    // WAVE_FORMAT_EXTENSIBLE: the format is in the first two bytes of the sub-format.
+    if format_tag == 0xFFFE && format.len() >= 26 {
+        format_tag = read_u16(&format[24..26]);
+    }
+    if number_of_channels == 0 {
+        return Err(WavetableError::InvalidFile("fmt "));
+    }
+    let bytes_per_sample = bits_per_sample / 8;
+    let decode: fn(&[u8]) -> f32 = match (format_tag, bits_per_sample) {
+        (1, 8) => |b| (b[0] as f32 - 128.0) / 128.0,
+        (1, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+        (1, 24) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0,
+        (1, 32) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+        (3, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        (3, 64) => |b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32,
+        _ => return Err(WavetableError::UnsupportedSampleFormat),
+    };
+    // Only use the first channel.
+    let samples: Vec<f32> = sample_data
+        .chunks_exact(bytes_per_sample * number_of_channels)
+        .map(decode)
+        .collect();
+    Wavetable::new(&samples, frame_length)
+}
+
+// The `clm ` chunk contains text like "<!>2048 10000000 wavetable (www.xferrecords.com)".
+fn parse_clm(chunk: &[u8]) -> Result<usize, WavetableError> {
+    let text = chunk
+        .strip_prefix(b"<!>")
+        .ok_or(WavetableError::InvalidFile("clm "))?;
+    let digits = text.iter().take_while(|b| b.is_ascii_digit()).count();
+    std::str::from_utf8(&text[..digits])
+        .ok()
+        .and_then(|digits| digits.parse().ok())
+        .ok_or(WavetableError::InvalidFile("clm "))
+}
+
+fn from_wt(data: &[u8]) -> Result<Wavetable, WavetableError> {
+    if data.len() < 12 {
+        return Err(WavetableError::InvalidFile("header"));
+    }
+    let frame_length = read_u32(&data[4..8]) as usize;
+    let number_of_frames = read_u16(&data[8..10]) as usize;
+    let flags = read_u16(&data[10..12]);
+    let number_of_samples = frame_length * number_of_frames;
+    let samples: Vec<f32> = if flags & WT_INT16 != 0 {
+        let scale = if flags & WT_INT16_FULL_RANGE != 0 {
+            1.0 / 32768.0
+        } else {
+            1.0 / 16384.0
+        };
+        data[12..]
+            .chunks_exact(2)
+            .take(number_of_samples)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 * scale)
+            .collect()
+    } else {
+        data[12..]
+            .chunks_exact(4)
+            .take(number_of_samples)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    };
+    if samples.len() < number_of_samples {
+        return Err(WavetableError::InvalidFile("data"));
+    }
+    Wavetable::new(&samples, frame_length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut result = id.to_vec();
+        result.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        result.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            result.push(0);
+        }
+        result
+    }
+
+    // A stereo 16-bit wav file with two frames of 8 samples: the first frame is 0.5,
+    // the second frame is -0.5. The right channel is silent.
+    fn serum_wav(clm: &[u8]) -> Vec<u8> {
+        let mut format = Vec::new();
+        format.extend_from_slice(&1_u16.to_le_bytes());
+        format.extend_from_slice(&2_u16.to_le_bytes());
+        format.extend_from_slice(&44100_u32.to_le_bytes());
+        format.extend_from_slice(&(44100_u32 * 4).to_le_bytes());
+        format.extend_from_slice(&4_u16.to_le_bytes());
+        format.extend_from_slice(&16_u16.to_le_bytes());
+        let mut samples = Vec::new();
+        for value in [16384_i16; 8].iter().chain([-16384_i16; 8].iter()) {
+            samples.extend_from_slice(&value.to_le_bytes());
+            samples.extend_from_slice(&0_i16.to_le_bytes());
+        }
+        let mut wave = b"WAVE".to_vec();
+        wave.extend(chunk(b"fmt ", &format));
+        wave.extend(chunk(b"clm ", clm));
+        wave.extend(chunk(b"data", &samples));
+        chunk(b"RIFF", &wave)
+    }
+
+    #[test]
+    fn loads_serum_wav() {
+        let table =
+            Wavetable::from_bytes(&serum_wav(b"<!>8 10000000 wavetable")).expect("valid wavetable");
+        assert_eq!(table.frame_length(), 8);
+        assert_eq!(table.number_of_frames(), 2);
+        assert!((table.sample(0, 0.0, 0.5) - 0.5).abs() < 1e-6);
+        assert!((table.sample(0, 1.0, 0.5) + 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rejects_invalid_clm_chunk() {
+        match Wavetable::from_bytes(&serum_wav(b"2048")) {
+            Err(WavetableError::InvalidFile("clm ")) => {}
+            _ => panic!("Expected an error."),
+        }
+    }
+
+    fn wt(flags: u16, samples: &[u8]) -> Vec<u8> {
+        let mut data = b"vawt".to_vec();
+        data.extend_from_slice(&4_u32.to_le_bytes());
+        data.extend_from_slice(&2_u16.to_le_bytes());
+        data.extend_from_slice(&flags.to_le_bytes());
+        data.extend_from_slice(samples);
+        data
+    }
+
+    #[test]
+    fn loads_float_wt() {
+        let mut samples = Vec::new();
+        for value in [0.25_f32; 4].iter().chain([0.75_f32; 4].iter()) {
+            samples.extend_from_slice(&value.to_le_bytes());
+        }
+        let table = Wavetable::from_bytes(&wt(0, &samples)).expect("valid wavetable");
+        assert_eq!(table.frame_length(), 4);
+        assert_eq!(table.number_of_frames(), 2);
+        assert!((table.sample(0, 0.0, 0.0) - 0.25).abs() < 1e-6);
+        assert!((table.sample(0, 1.0, 0.0) - 0.75).abs() < 1e-6);
+    }
+
+    #[test]
+    fn loads_int16_wt() {
+        let mut samples = Vec::new();
+        for _ in 0..8 {
+            samples.extend_from_slice(&8192_i16.to_le_bytes());
+        }
+        let table = Wavetable::from_bytes(&wt(WT_INT16, &samples)).expect("valid wavetable");
+        assert!((table.sample(0, 0.0, 0.0) - 0.5).abs() < 1e-6);
+        let table = Wavetable::from_bytes(&wt(WT_INT16 | WT_INT16_FULL_RANGE, &samples))
+            .expect("valid wavetable");
+        assert!((table.sample(0, 0.0, 0.0) - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rejects_truncated_wt() {
+        match Wavetable::from_bytes(&wt(0, &[0; 12])) {
+            Err(WavetableError::InvalidFile("data")) => {}
+            _ => panic!("Expected an error."),
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! Wavetables and a wavetable oscillator.
+//!
+//! A [`Wavetable`] consists of a number of frames (single-cycle waveforms) of the same length.
+//! To avoid aliasing, every frame is stored several times, each time with fewer harmonics
+//! (a "mipmap"); the [`WavetableOscillator`] selects the level that fits the frequency it plays.
+//!
+//! Loading wavetable files
+//! -----------------------
+//! [`Wavetable::from_file`] and [`Wavetable::from_bytes`] load the following formats:
+//! * Serum-style `.wav` files: the frame length is read from the `clm ` chunk and defaults to
+//!   2048 frames when there is no such chunk. Only the first channel is used.
+//! * `.wt` files as used by Surge.
+//!
+//! Loading a wavetable allocates memory and takes time, so it must be done outside the
+//! audio thread. Use [`wavetable_swap`] to hand the table over to the audio thread.
+//!
+//! ```no_run
+//! use rsynth::utilities::wavetable::{wavetable_swap, Wavetable, WavetableOscillator};
+//! use std::thread;
+//!
+//! let initial = Wavetable::from_file("saw.wav").expect("a valid wavetable");
+//! let (mut sender, mut receiver) = wavetable_swap(initial);
+//! thread::spawn(move || {
+//!     let table = Wavetable::from_file("formant.wt").expect("a valid wavetable");
+//!     sender.send(table);
+//! });
+//!
+//! // In the audio thread:
+//! let mut oscillator = WavetableOscillator::new();
+//! oscillator.set_frequency(440.0);
+//! receiver.update();
+//! let sample = oscillator.next_sample(receiver.table(), 44100.0);
+//! ```
+//!
+//! [`Wavetable`]: ./struct.Wavetable.html
+//! [`Wavetable::from_file`]: ./struct.Wavetable.html#method.from_file
+//! [`Wavetable::from_bytes`]: ./struct.Wavetable.html#method.from_bytes
+//! [`WavetableOscillator`]: ./struct.WavetableOscillator.html
+//! [`wavetable_swap`]: ./fn.wavetable_swap.html
+use crate::utilities::fft;
+use std::io;
+
+mod file;
+mod swap;
+pub use self::swap::{wavetable_swap, WavetableReceiver, WavetableSender};
+
+/// The error type that represents the errors you can get when loading a [`Wavetable`].
+///
+/// [`Wavetable`]: ./struct.Wavetable.html
+#[derive(Debug)]
+pub enum WavetableError {
+    /// An error occurred when reading the file.
+    Io(io::Error),
+    /// The data is neither a `.wav` file nor a `.wt` file.
+    UnknownFormat,
+    /// The file is malformed; the parameter describes the part that is invalid.
+    InvalidFile(&'static str),
+    /// The `.wav` file uses a sample format that is not supported.
+    UnsupportedSampleFormat,
+    /// The frame length is not a power of two of at least 4 samples,
+    /// or the number of samples is not a multiple of the frame length.
+    InvalidFrameLength(usize),
+}
+
+/// A band-limited wavetable.
+pub struct Wavetable {
+    frame_length: usize,
+    number_of_frames: usize,
+    // For every level, all frames after each other. Every frame is followed by a copy of its
+    // first sample to simplify the interpolation.
+    levels: Vec<Vec<f32>>,
+}
+
+impl Wavetable {
+    /// Create a wavetable from frames of `frame_length` samples that are stored after each other.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method allocates memory.
+    pub fn new(samples: &[f32], frame_length: usize) -> Result<Self, WavetableError> {
+        if frame_length < 4
+            || !frame_length.is_power_of_two()
+            || samples.is_empty()
+            || !samples.chunks_exact(frame_length).remainder().is_empty()
+        {
+            return Err(WavetableError::InvalidFrameLength(frame_length));
+        }
+        let number_of_frames = samples.len() / frame_length;
+        // Level `k` contains the harmonics up to `(frame_length / 2) >> k`.
+        let number_of_levels = frame_length.trailing_zeros() as usize;
+        let stride = frame_length + 1;
+        let mut levels = vec![vec![0.0; number_of_frames * stride]; number_of_levels];
+        let mut spectrum_real = vec![0.0; frame_length];
+        let mut spectrum_imaginary = vec![0.0; frame_length];
+        let mut real = vec![0.0; frame_length];
+        let mut imaginary = vec![0.0; frame_length];
+        for (frame_index, frame) in samples.chunks(frame_length).enumerate() {
+            for (destination, sample) in spectrum_real.iter_mut().zip(frame) {
+                *destination = *sample as f64;
+            }
+            spectrum_imaginary.iter_mut().for_each(|x| *x = 0.0);
+            fft::fft(&mut spectrum_real, &mut spectrum_imaginary, false);
+            for (level_index, level) in levels.iter_mut().enumerate() {
+                let highest_harmonic = (frame_length / 2) >> level_index;
+                real.copy_from_slice(&spectrum_real);
+                imaginary.copy_from_slice(&spectrum_imaginary);
+                for bin in highest_harmonic + 1..frame_length - highest_harmonic {
+                    real[bin] = 0.0;
+                    imaginary[bin] = 0.0;
+                }
+                fft::fft(&mut real, &mut imaginary, true);
+                let destination = &mut level[frame_index * stride..(frame_index + 1) * stride];
+                for (destination, value) in destination.iter_mut().zip(real.iter()) {
+                    *destination = (*value / frame_length as f64) as f32;
+                }
+                destination[frame_length] = destination[0];
+            }
+        }
+        Ok(Wavetable {
+            frame_length,
+            number_of_frames,
+            levels,
+        })
+    }
+
+    /// The number of samples of one frame.
+    pub fn frame_length(&self) -> usize {
+        self.frame_length
+    }
+
+    /// The number of frames.
+    pub fn number_of_frames(&self) -> usize {
+        self.number_of_frames
+    }
+
+    /// The number of mipmap levels. Level 0 contains all harmonics and every next level
+    /// contains half as many harmonics as the previous one. The last level only contains the
+    /// fundamental.
+    pub fn number_of_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// The mipmap level that contains as many harmonics as possible without aliasing
+    /// when the table is played at the given frequency.
+    pub fn level_for_frequency(&self, frequency: f64, sample_rate: f64) -> usize {
+        let allowed_harmonics = sample_rate / (2.0 * frequency.abs());
+        let mut level = 0;
+        while level + 1 < self.levels.len()
+            && ((self.frame_length / 2) >> level) as f64 > allowed_harmonics
+        {
+            level += 1;
+        }
+        level
+    }
+
+    /// The value at the given mipmap level, position and phase.
+    ///
+    /// `position` runs from 0 (the first frame) to 1 (the last frame) and `phase` runs from 0
+    /// to 1 within a frame; both are interpolated linearly.
+    pub fn sample(&self, level: usize, position: f64, phase: f64) -> f32 {
+        let level = &self.levels[level.min(self.levels.len() - 1)];
+        let stride = self.frame_length + 1;
+        let position = position.clamp(0.0, 1.0) * (self.number_of_frames - 1) as f64;
+        let frame = (position as usize).min(self.number_of_frames - 1);
+        let next_frame = (frame + 1).min(self.number_of_frames - 1);
+        let frame_fraction = (position - frame as f64) as f32;
+
+        let phase = (phase - phase.floor()) * self.frame_length as f64;
+        let index = (phase as usize).min(self.frame_length - 1);
+        let fraction = (phase - index as f64) as f32;
+        let read = |frame: usize| {
+            let current = level[frame * stride + index];
+            let next = level[frame * stride + index + 1];
+            current + (next - current) * fraction
+        };
+        let first = read(frame);
+        first + (read(next_frame) - first) * frame_fraction
+    }
+}
+
+/// An oscillator that plays a [`Wavetable`].
+///
+/// The oscillator does not own the wavetable, so that one table can be shared between the
+/// voices of a plugin.
+///
+/// [`Wavetable`]: ./struct.Wavetable.html
+pub struct WavetableOscillator {
+    phase: f64,
+    frequency: f64,
+    position: f64,
+}
+
+impl WavetableOscillator {
+    /// Create a new oscillator with frequency 0 that plays the first frame.
+    pub fn new() -> Self {
+        WavetableOscillator {
+            phase: 0.0,
+            frequency: 0.0,
+            position: 0.0,
+        }
+    }
+
+    /// Set the frequency in Hz.
+    pub fn set_frequency(&mut self, frequency: f64) {
+        self.frequency = frequency;
+    }
+
+    /// Set the position in the wavetable: from 0 (the first frame) to 1 (the last frame).
+    pub fn set_position(&mut self, position: f64) {
+        self.position = position.clamp(0.0, 1.0);
+    }
+
+    /// Restart the waveform.
+    pub fn reset_phase(&mut self) {
+        self.phase = 0.0;
+    }
+
+    /// Compute the next sample.
+    pub fn next_sample(&mut self, table: &Wavetable, sample_rate: f64) -> f32 {
+        let level = table.level_for_frequency(self.frequency, sample_rate);
+        let value = table.sample(level, self.position, self.phase);
+        self.phase += self.frequency / sample_rate;
+        self.phase -= self.phase.floor();
+        value
+    }
+}
+
+impl Default for WavetableOscillator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const FRAME_LENGTH: usize = 64;
+
+    // A frame with the first and the 16th harmonic.
+    fn two_harmonics() -> Vec<f32> {
+        (0..FRAME_LENGTH)
+            .map(|i| {
+                let phase = 2.0 * PI * i as f64 / FRAME_LENGTH as f64;
+                (phase.sin() + (16.0 * phase).sin()) as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn level_zero_contains_all_harmonics() {
+        let samples = two_harmonics();
+        let table = Wavetable::new(&samples, FRAME_LENGTH).expect("valid wavetable");
+        assert_eq!(table.number_of_levels(), 6);
+        for (i, expected) in samples.iter().enumerate() {
+            let actual = table.sample(0, 0.0, i as f64 / FRAME_LENGTH as f64);
+            assert!((actual - expected).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn higher_levels_remove_high_harmonics() {
+        let table = Wavetable::new(&two_harmonics(), FRAME_LENGTH).expect("valid wavetable");
+        // Level 2 contains the harmonics up to 8.
+        for i in 0..FRAME_LENGTH {
+            let phase = i as f64 / FRAME_LENGTH as f64;
+            let expected = (2.0 * PI * phase).sin() as f32;
+            assert!((table.sample(2, 0.0, phase) - expected).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn level_for_frequency_avoids_aliasing() {
+        let table = Wavetable::new(&two_harmonics(), FRAME_LENGTH).expect("valid wavetable");
+        // At 100 Hz and a sample rate of 6400 Hz, 32 harmonics fit.
+        assert_eq!(table.level_for_frequency(100.0, 6400.0), 0);
+        assert_eq!(table.level_for_frequency(200.0, 6400.0), 1);
+        assert_eq!(table.level_for_frequency(250.0, 6400.0), 2);
+        assert_eq!(table.level_for_frequency(10000.0, 6400.0), 5);
+    }
+
+    #[test]
+    fn position_interpolates_between_frames() {
+        let mut samples = vec![0.0; FRAME_LENGTH];
+        samples.extend(vec![1.0; FRAME_LENGTH]);
+        let table = Wavetable::new(&samples, FRAME_LENGTH).expect("valid wavetable");
+        let mut oscillator = WavetableOscillator::new();
+        oscillator.set_frequency(1.0);
+        oscillator.set_position(0.25);
+        assert!((oscillator.next_sample(&table, 44100.0) - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rejects_invalid_frame_length() {
+        match Wavetable::new(&[0.0; 48], 48) {
+            Err(WavetableError::InvalidFrameLength(48)) => {}
+            _ => panic!("Expected an error."),
+        }
+        match Wavetable::new(&[0.0; 48], 32) {
+            Err(WavetableError::InvalidFrameLength(32)) => {}
+            _ => panic!("Expected an error."),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use super::Wavetable;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Arc;
+
+struct Slots {
+    // A table that has been sent, but that the receiver has not yet picked up.
+    pending: AtomicPtr<Wavetable>,
+    // A table that the receiver no longer uses and that the sender must deallocate.
+    garbage: AtomicPtr<Wavetable>,
+}
+
+fn drop_table(table: *mut Wavetable) {
+    if !table.is_null() {
+        drop(unsafe { Box::from_raw(table) });
+    }
+}
+
+impl Drop for Slots {
+    fn drop(&mut self) {
+        drop_table(*self.pending.get_mut());
+        drop_table(*self.garbage.get_mut());
+    }
+}
+
+/// Create a channel to replace the [`Wavetable`] that is used in the audio thread.
+///
+/// The [`WavetableSender`] is used by the thread that loads the tables, the
+/// [`WavetableReceiver`] is used in the audio thread.
+///
+/// [`Wavetable`]: ./struct.Wavetable.html
+/// [`WavetableSender`]: ./struct.WavetableSender.html
+/// [`WavetableReceiver`]: ./struct.WavetableReceiver.html
+pub fn wavetable_swap(initial: Wavetable) -> (WavetableSender, WavetableReceiver) {
+    let slots = Arc::new(Slots {
+        pending: AtomicPtr::new(ptr::null_mut()),
+        garbage: AtomicPtr::new(ptr::null_mut()),
+    });
+    let sender = WavetableSender {
+        slots: slots.clone(),
+    };
+    let receiver = WavetableReceiver {
+        slots,
+        current: Box::new(initial),
+    };
+    (sender, receiver)
+}
+
+/// Sends wavetables to the audio thread. Created with [`wavetable_swap`].
+///
+/// The sender also deallocates the tables that the audio thread no longer uses.
+///
+/// [`wavetable_swap`]: ./fn.wavetable_swap.html
+pub struct WavetableSender {
+    slots: Arc<Slots>,
+}
+
+impl WavetableSender {
+    /// Send a new wavetable. If the previous table that has been sent has not yet been picked
+    /// up by the receiver, it is replaced.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method deallocates memory.
+    pub fn send(&mut self, table: Wavetable) {
+        let table = Box::into_raw(Box::new(table));
+        drop_table(self.slots.pending.swap(table, Ordering::AcqRel));
+        self.collect_garbage();
+    }
+
+    /// Deallocate the table that has been replaced by the receiver, if any.
+    /// The receiver only replaces a table when the previous one has been deallocated, so call
+    /// this method regularly when sending tables in quick succession.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------
+    /// This method deallocates memory.
+    pub fn collect_garbage(&mut self) {
+        drop_table(self.slots.garbage.swap(ptr::null_mut(), Ordering::AcqRel));
+    }
+}
+
+/// Gives access to the wavetable in the audio thread. Created with [`wavetable_swap`].
+///
+/// Real-time safety
+/// ----------------
+/// The methods of the receiver do not allocate and deallocate memory and do not lock.
+/// Dropping the receiver deallocates the table.
+///
+/// [`wavetable_swap`]: ./fn.wavetable_swap.html
+pub struct WavetableReceiver {
+    slots: Arc<Slots>,
+    current: Box<Wavetable>,
+}
+
+impl WavetableReceiver {
+    /// Switch to the table that has been sent most recently, if any.
+    /// Return `true` when the table has been replaced.
+    ///
+    /// Call this method at the start of a buffer, so that the table does not change while
+    /// rendering the buffer.
+    pub fn update(&mut self) -> bool {
+        if !self.slots.garbage.load(Ordering::Acquire).is_null() {
+            // The sender has not yet deallocated the previous table; try again later.
+            return false;
+        }
+        let pending = self.slots.pending.swap(ptr::null_mut(), Ordering::AcqRel);
+        if pending.is_null() {
+            return false;
+        }
+        let previous = std::mem::replace(&mut self.current, unsafe { Box::from_raw(pending) });
+        self.slots
+            .garbage
+            .store(Box::into_raw(previous), Ordering::Release);
+        true
+    }
+
+    /// The table that is currently used.
+    pub fn table(&self) -> &Wavetable {
+        &self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant(value: f32) -> Wavetable {
+        Wavetable::new(&[value; 4], 4).expect("valid wavetable")
+    }
+
+    #[test]
+    fn receiver_switches_to_the_most_recent_table() {
+        let (mut sender, mut receiver) = wavetable_swap(constant(0.0));
+        assert!(!receiver.update());
+        sender.send(constant(1.0));
+        sender.send(constant(2.0));
+        assert!(receiver.update());
+        assert_eq!(receiver.table().sample(0, 0.0, 0.0), 2.0);
+        assert!(!receiver.update());
+    }
+
+    #[test]
+    fn receiver_waits_until_the_garbage_is_collected() {
+        let (mut sender, mut receiver) = wavetable_swap(constant(0.0));
+        sender.send(constant(1.0));
+        assert!(receiver.update());
+        // Send a table without collecting the garbage of the previous swap.
+        sender
+            .slots
+            .pending
+            .store(Box::into_raw(Box::new(constant(2.0))), Ordering::Release);
+        assert!(!receiver.update());
+        sender.collect_garbage();
+        assert!(receiver.update());
+        assert_eq!(receiver.table().sample(0, 0.0, 0.0), 2.0);
+    }
+}
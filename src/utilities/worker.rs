@@ -0,0 +1,257 @@
+//! Offload work that is not real-time safe from the audio thread to a background thread.
+//!
+//! Loading a sample, recomputing a wavetable or parsing a preset allocate memory and may
+//! perform I/O, so they cannot be done in `render_buffer`. With [`spawn_worker`], the audio
+//! thread posts jobs to a background thread via a lock-free queue. The background thread runs
+//! the jobs and sends the results back via another lock-free queue.
+//!
+//! When the audio thread replaces a value by a result, the old value must not be dropped in
+//! the audio thread, because dropping it deallocates memory. [`WorkerQueue::retire`] sends the
+//! old value back to the background thread, where it is dropped. [`WorkerQueue::receive_into`]
+//! does both at once for the common case where every result replaces the current value.
+//!
+//! This module is only available when compiled with the `worker` feature.
+//!
+//! ```
+//! use rsynth::utilities::worker::spawn_worker;
+//!
+//! // The job is the length of the table, the result is the table.
+//! let (mut queue, worker) = spawn_worker(16, |length: usize| vec![0.0_f32; length])
+//!     .expect("the thread can be started");
+//! let mut table = vec![0.0_f32; 256];
+//!
+//! // In the audio thread:
+//! queue.post(2048).expect("the queue is not full");
+//! // In one of the next buffers:
+//! # while !queue.receive_into(&mut table) {}
+//! if queue.receive_into(&mut table) {
+//!     // `table` has been replaced; the old table is dropped by the worker.
+//! }
+//! # assert_eq!(table.len(), 2048);
+//! ```
+//!
+//! Real-time safety
+//! ----------------
+//! The methods of the [`WorkerQueue`] do not allocate memory, do not lock and do not drop
+//! jobs or results.
+//!
+//! [`spawn_worker`]: ./fn.spawn_worker.html
+//! [`WorkerQueue`]: ./struct.WorkerQueue.html
+//! [`WorkerQueue::retire`]: ./struct.WorkerQueue.html#method.retire
+//! [`WorkerQueue::receive_into`]: ./struct.WorkerQueue.html#method.receive_into
+use ringbuf::{Consumer, Producer, RingBuffer};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// How long the background thread sleeps when there is nothing to do.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Start a background thread that runs `run` for every job that is posted to the
+/// [`WorkerQueue`]. `capacity` is the number of jobs, results and retired values
+/// that each of the queues can hold.
+///
+/// Dropping the [`Worker`] stops the thread.
+///
+/// Note: cannot be used in a real-time context
+/// -------------------------------------
+/// This function allocates memory and starts a thread.
+///
+/// [`WorkerQueue`]: ./struct.WorkerQueue.html
+/// [`Worker`]: ./struct.Worker.html
+pub fn spawn_worker<J, R, F>(capacity: usize, mut run: F) -> io::Result<(WorkerQueue<J, R>, Worker)>
+where
+    J: Send + 'static,
+    R: Send + 'static,
+    F: FnMut(J) -> R + Send + 'static,
+{
+    let capacity = capacity.max(1);
+    let (job_producer, mut job_consumer) = RingBuffer::<J>::new(capacity).split();
+    let (mut result_producer, result_consumer) = RingBuffer::<R>::new(capacity).split();
+    let (garbage_producer, mut garbage_consumer) = RingBuffer::<R>::new(capacity).split();
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+    let thread = thread::Builder::new()
+        .name("worker".to_string())
+        .spawn(move || {
+            // A result that could not be sent because the result queue was full.
+            let mut unsent = None;
+            while !thread_stop.load(Ordering::Acquire) {
+                let mut is_idle = true;
+                while let Some(garbage) = garbage_consumer.pop() {
+                    drop(garbage);
+                    is_idle = false;
+                }
+                if unsent.is_none() {
+                    unsent = job_consumer.pop().map(&mut run);
+                }
+                if let Some(result) = unsent.take() {
+                    is_idle = false;
+                    if let Err(result) = result_producer.push(result) {
+                        unsent = Some(result);
+                        is_idle = true;
+                    }
+                }
+                if is_idle {
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+        })?;
+    let queue = WorkerQueue {
+        jobs: job_producer,
+        results: result_consumer,
+        garbage: garbage_producer,
+    };
+    let worker = Worker {
+        stop,
+        thread: Some(thread),
+    };
+    Ok((queue, worker))
+}
+
+/// Posts jobs to the background thread and receives the results; used in the audio thread.
+/// Created with [`spawn_worker`].
+///
+/// [`spawn_worker`]: ./fn.spawn_worker.html
+pub struct WorkerQueue<J, R> {
+    jobs: Producer<J>,
+    results: Consumer<R>,
+    garbage: Producer<R>,
+}
+
+impl<J, R> WorkerQueue<J, R> {
+    /// Post a job. Return the job as an error when the queue is full.
+    pub fn post(&mut self, job: J) -> Result<(), J> {
+        self.jobs.push(job)
+    }
+
+    /// Receive the result of a job that has been completed, if any.
+    /// Results are received in the order in which the jobs have been posted.
+    pub fn receive(&mut self) -> Option<R> {
+        self.results.pop()
+    }
+
+    /// Send a value to the background thread, so that it is dropped there.
+    /// Return the value as an error when the queue is full; try again in the next buffer.
+    pub fn retire(&mut self, value: R) -> Result<(), R> {
+        self.garbage.push(value)
+    }
+
+    /// Replace `current` by the result of a job that has been completed, if any, and retire
+    /// the old value. Return `true` when `current` has been replaced.
+    ///
+    /// When the old value cannot be retired, `current` is not replaced and the result is
+    /// received in one of the next calls.
+    pub fn receive_into(&mut self, current: &mut R) -> bool {
+        if self.garbage.is_full() {
+            return false;
+        }
+        match self.results.pop() {
+            Some(result) => {
+                let old = std::mem::replace(current, result);
+                if self.garbage.push(old).is_err() {
+                    unreachable!("The garbage queue has been checked not to be full.");
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// The background thread that runs the jobs. Created with [`spawn_worker`].
+///
+/// Dropping the `Worker` stops the thread; jobs that have not been started are dropped.
+///
+/// [`spawn_worker`]: ./fn.spawn_worker.html
+pub struct Worker {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::thread::ThreadId;
+    use std::time::Instant;
+
+    fn wait_for<T, F: FnMut() -> Option<T>>(mut f: F) -> T {
+        let start = Instant::now();
+        loop {
+            if let Some(value) = f() {
+                return value;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "Timed out.");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn results_are_received_in_order() {
+        let (mut queue, _worker) = spawn_worker(4, |x: u32| x * 2).expect("no error");
+        queue.post(1).expect("not full");
+        queue.post(2).expect("not full");
+        assert_eq!(wait_for(|| queue.receive()), 2);
+        assert_eq!(wait_for(|| queue.receive()), 4);
+    }
+
+    #[test]
+    fn full_queue_returns_the_job() {
+        // The worker is blocked until `proceed` is set, so the jobs are not consumed.
+        let proceed = Arc::new(AtomicBool::new(false));
+        let worker_proceed = proceed.clone();
+        let (mut queue, _worker) = spawn_worker(1, move |x: u32| {
+            while !worker_proceed.load(Ordering::Acquire) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            x
+        })
+        .expect("no error");
+        queue.post(1).expect("not full");
+        // Wait until the worker has taken the first job.
+        wait_for(|| queue.post(2).ok());
+        assert_eq!(queue.post(3), Err(3));
+        proceed.store(true, Ordering::Release);
+    }
+
+    struct DropRecorder {
+        dropped_in: Arc<Mutex<Option<ThreadId>>>,
+    }
+
+    impl Drop for DropRecorder {
+        fn drop(&mut self) {
+            *self.dropped_in.lock().expect("not poisoned") = Some(thread::current().id());
+        }
+    }
+
+    #[test]
+    fn old_values_are_dropped_by_the_worker() {
+        let new_drops = Arc::new(Mutex::new(None));
+        let worker_new_drops = new_drops.clone();
+        let (mut queue, _worker) = spawn_worker(4, move |_: ()| DropRecorder {
+            dropped_in: worker_new_drops.clone(),
+        })
+        .expect("no error");
+        let old_drops = Arc::new(Mutex::new(None));
+        let mut current = DropRecorder {
+            dropped_in: old_drops.clone(),
+        };
+        queue.post(()).expect("not full");
+        wait_for(|| Some(()).filter(|_| queue.receive_into(&mut current)));
+        let dropped_in = wait_for(|| *old_drops.lock().expect("not poisoned"));
+        assert_ne!(dropped_in, thread::current().id());
+        assert!(new_drops.lock().expect("not poisoned").is_none());
+    }
+}
@@ -0,0 +1,469 @@
+//! Remote control over a WebSocket, e.g. from a web browser.
+//!
+//! Support is only enabled if you compile with the "websocket" feature, see
+//! [the cargo reference] for more information on setting cargo features.
+//!
+//! This can be used to control a headless application (e.g. on a Raspberry Pi) from
+//! a web page.
+//! The [`WebSocketServer`] exposes a [`ParameterStore`] and [`Meters`].
+//! It runs on separate threads, so the audio thread is never blocked by it.
+//!
+//! Protocol
+//! ========
+//! All messages are JSON objects with a `"type"` field.
+//!
+//! The client can send the following messages:
+//! * `{"type": "get_parameters"}`: the server responds with a `parameters` message.
+//! * `{"type": "set_parameter", "index": 0, "value": 440.0}`: set the (plain) value of
+//!   a parameter.
+//! * `{"type": "begin_gesture", "index": 0}` and `{"type": "end_gesture"}`: all changes of
+//!   the parameter between these messages (e.g. while dragging a slider) are undone at once.
+//! * `{"type": "undo"}` and `{"type": "redo"}`: undo or redo the last change that has been
+//!   made via the server, see the [`history`] module.
+//!
+//! The server sends the following messages:
+//! * `{"type": "parameters", "parameters": [{"name": "Frequency", "label": "Hz", "min": 20.0, "max": 20000.0, "value": 440.0}, ...]}`:
+//!   sent when the client connects and as a response to `get_parameters`.
+//! * `{"type": "parameter", "index": 0, "value": 440.0}`: sent when the value of a parameter
+//!   has been changed (by this client or by someone else).
+//! * `{"type": "meters", "meters": [{"name": "Peak", "value": 0.5}, ...]}`: sent periodically.
+//! * `{"type": "error", "message": "..."}`: sent when a message of the client could not be
+//!   handled.
+//!
+//! [the cargo reference]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//! [`WebSocketServer`]: ./struct.WebSocketServer.html
+//! [`ParameterStore`]: ../parameter/struct.ParameterStore.html
+//! [`Meters`]: ../meter/struct.Meters.html
+//! [`history`]: ../parameter/history/index.html
+use crate::meter::Meters;
+use crate::parameter::history::{UndoHistory, DEFAULT_CAPACITY};
+use crate::parameter::ParameterStore;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tungstenite::handshake::HandshakeError;
+use tungstenite::{Message, WebSocket};
+
+// How long the server threads block before they check if they need to stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// How often the meters are sent to the clients.
+const METER_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    GetParameters,
+    SetParameter { index: usize, value: f32 },
+    BeginGesture { index: usize },
+    EndGesture,
+    Undo,
+    Redo,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct ParameterDescription<'a> {
+    name: &'a str,
+    label: &'a str,
+    min: f32,
+    max: f32,
+    value: f32,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct MeterValue<'a> {
+    name: &'a str,
+    value: f32,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Response<'a> {
+    Parameters {
+        parameters: Vec<ParameterDescription<'a>>,
+    },
+    Parameter {
+        index: usize,
+        value: f32,
+    },
+    Meters {
+        meters: Vec<MeterValue<'a>>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+fn describe_parameters(parameters: &ParameterStore) -> Response<'_> {
+    Response::Parameters {
+        parameters: parameters
+            .infos()
+            .iter()
+            .enumerate()
+            .map(|(index, info)| ParameterDescription {
+                name: &info.name,
+                label: &info.label,
+                min: info.min,
+                max: info.max,
+                value: parameters.get(index),
+            })
+            .collect(),
+    }
+}
+
+fn describe_meters(meters: &Meters) -> Response<'_> {
+    Response::Meters {
+        meters: meters
+            .names()
+            .iter()
+            .enumerate()
+            .map(|(index, name)| MeterValue {
+                name,
+                value: meters.get(index),
+            })
+            .collect(),
+    }
+}
+
+/// Handle a message of the client. Return the response, if any.
+fn handle_request<'a>(
+    text: &str,
+    parameters: &'a ParameterStore,
+    history: &Mutex<UndoHistory>,
+) -> Option<Response<'a>> {
+    let request = match serde_json::from_str(text) {
+        Ok(Request::GetParameters) => return Some(describe_parameters(parameters)),
+        Ok(request) => request,
+        Err(e) => {
+            return Some(Response::Error {
+                message: format!("Invalid message: {}", e),
+            })
+        }
+    };
+    if let Request::SetParameter { index, .. } | Request::BeginGesture { index } = request {
+        if index >= parameters.len() {
+            return Some(Response::Error {
+                message: format!("Parameter index {} is out of bounds.", index),
+            });
+        }
+    }
+    // The history remains consistent when a thread panics while it is locked.
+    let mut history = history
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    match request {
+        Request::GetParameters => {}
+        Request::SetParameter { index, value } => history.set(parameters, index, value),
+        Request::BeginGesture { index } => history.begin_gesture(index),
+        Request::EndGesture => history.end_gesture(),
+        Request::Undo => {
+            history.undo(parameters);
+        }
+        Request::Redo => {
+            history.redo(parameters);
+        }
+    }
+    None
+}
+
+/// Serves the parameters and meters over a WebSocket.
+///
+/// The server is stopped when the `WebSocketServer` is dropped.
+/// See the [module level documentation] for more information.
+///
+/// [module level documentation]: ./index.html
+pub struct WebSocketServer {
+    local_address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WebSocketServer {
+    /// Start listening for WebSocket connections on the given TCP address.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory and spawns a thread.
+    pub fn start<A>(
+        address: A,
+        parameters: Arc<ParameterStore>,
+        meters: Arc<Meters>,
+    ) -> io::Result<Self>
+    where
+        A: ToSocketAddrs,
+    {
+        let history = Arc::new(Mutex::new(UndoHistory::new(DEFAULT_CAPACITY)));
+        Self::start_with_history(address, parameters, meters, history)
+    }
+
+    /// Start listening for WebSocket connections on the given TCP address and record the
+    /// changes of the parameters in the given undo history, e.g. to share the history with
+    /// the editor.
+    ///
+    /// Note: cannot be used in a real-time context
+    /// -------------------------------------------
+    /// This method allocates memory and spawns a thread.
+    pub fn start_with_history<A>(
+        address: A,
+        parameters: Arc<ParameterStore>,
+        meters: Arc<Meters>,
+        history: Arc<Mutex<UndoHistory>>,
+    ) -> io::Result<Self>
+    where
+        A: ToSocketAddrs,
+    {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let local_address = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        info!("Listening for WebSocket connections on {}", local_address);
+        let thread = thread::Builder::new()
+            .name("rsynth websocket server".to_string())
+            .spawn(move || listen(listener, parameters, meters, history, thread_stop))?;
+        Ok(Self {
+            local_address,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_address(&self) -> SocketAddr {
+        self.local_address
+    }
+}
+
+impl Drop for WebSocketServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("The WebSocket server thread panicked.");
+            }
+        }
+    }
+}
+
+fn listen(
+    listener: TcpListener,
+    parameters: Arc<ParameterStore>,
+    meters: Arc<Meters>,
+    history: Arc<Mutex<UndoHistory>>,
+    stop: Arc<AtomicBool>,
+) {
+    let mut connections: Vec<JoinHandle<()>> = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, address)) => {
+                info!("Accepted WebSocket connection from {}", address);
+                // Forget the connections that have been closed, so that `connections` does not
+                // keep growing while the server is running.
+                connections.retain(|connection| !connection.is_finished());
+                let parameters = parameters.clone();
+                let meters = meters.clone();
+                let history = history.clone();
+                let stop = stop.clone();
+                match thread::Builder::new()
+                    .name("rsynth websocket connection".to_string())
+                    .spawn(move || serve(stream, &parameters, &meters, &history, &stop))
+                {
+                    Ok(connection) => connections.push(connection),
+                    Err(e) => error!("Failed to spawn WebSocket connection thread: {:?}", e),
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(e) => {
+                error!("Failed to accept WebSocket connection: {:?}", e);
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+    for connection in connections {
+        if connection.join().is_err() {
+            error!("A WebSocket connection thread panicked.");
+        }
+    }
+}
+
+// `tungstenite::Error` is large, but we cannot change that.
+#[allow(clippy::result_large_err)]
+fn send(socket: &mut WebSocket<TcpStream>, response: &Response) -> Result<(), tungstenite::Error> {
+    match serde_json::to_string(response) {
+        Ok(text) => socket.send(Message::Text(text)),
+        Err(e) => {
+            error!("Failed to serialize WebSocket message: {:?}", e);
+            Ok(())
+        }
+    }
+}
+
+fn is_timeout(error: &tungstenite::Error) -> bool {
+    match error {
+        tungstenite::Error::Io(e) => {
+            e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut
+        }
+        _ => false,
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn serve(
+    stream: TcpStream,
+    parameters: &ParameterStore,
+    meters: &Meters,
+    history: &Mutex<UndoHistory>,
+    stop: &AtomicBool,
+) {
+    if let Err(e) = stream
+        .set_nonblocking(false)
+        .and_then(|_| stream.set_read_timeout(Some(POLL_INTERVAL)))
+    {
+        error!("Failed to configure WebSocket connection: {:?}", e);
+        return;
+    }
+    let mut handshake = tungstenite::accept(stream);
+    let mut socket = loop {
+        match handshake {
+            Ok(socket) => break socket,
+            Err(HandshakeError::Interrupted(mid_handshake)) => {
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                handshake = mid_handshake.handshake();
+            }
+            Err(HandshakeError::Failure(e)) => {
+                warn!("WebSocket handshake failed: {:?}", e);
+                return;
+            }
+        }
+    };
+
+    let mut values: Vec<f32> = (0..parameters.len()).map(|i| parameters.get(i)).collect();
+    let mut result = send(&mut socket, &describe_parameters(parameters));
+    let mut last_meter_update = Instant::now();
+    while result.is_ok() && !stop.load(Ordering::Relaxed) {
+        result = match socket.read() {
+            Ok(Message::Text(text)) => match handle_request(&text, parameters, history) {
+                Some(response) => send(&mut socket, &response),
+                None => Ok(()),
+            },
+            Ok(_) => Ok(()),
+            Err(ref e) if is_timeout(e) => Ok(()),
+            Err(e) => Err(e),
+        };
+        for (index, value) in values.iter_mut().enumerate() {
+            let new_value = parameters.get(index);
+            if result.is_ok() && new_value != *value {
+                *value = new_value;
+                result = send(
+                    &mut socket,
+                    &Response::Parameter {
+                        index,
+                        value: new_value,
+                    },
+                );
+            }
+        }
+        if result.is_ok() && !meters.is_empty() && last_meter_update.elapsed() >= METER_INTERVAL {
+            last_meter_update = Instant::now();
+            result = send(&mut socket, &describe_meters(meters));
+        }
+    }
+    match result {
+        Ok(()) => {
+            if let Err(e) = socket.close(None).and_then(|_| socket.flush()) {
+                trace!("Failed to close WebSocket connection: {:?}", e);
+            }
+        }
+        Err(tungstenite::Error::ConnectionClosed) | Err(tungstenite::Error::AlreadyClosed) => {
+            info!("WebSocket connection closed");
+        }
+        Err(e) => warn!("WebSocket connection failed: {:?}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{handle_request, Response};
+    use crate::parameter::history::UndoHistory;
+    use crate::parameter::{ParameterInfo, ParameterStore};
+    use std::sync::Mutex;
+
+    fn parameters() -> ParameterStore {
+        ParameterStore::new(vec![ParameterInfo::new(
+            "Frequency",
+            "Hz",
+            0.0,
+            1000.0,
+            500.0,
+        )])
+    }
+
+    #[test]
+    fn set_parameter_changes_the_value() {
+        let parameters = parameters();
+        let response = handle_request(
+            r#"{"type": "set_parameter", "index": 0, "value": 250.0}"#,
+            &parameters,
+            &Mutex::new(UndoHistory::new(10)),
+        );
+        assert_eq!(response, None);
+        assert_eq!(parameters.get(0), 250.0);
+    }
+
+    #[test]
+    fn set_parameter_with_invalid_index_returns_an_error() {
+        let parameters = parameters();
+        match handle_request(
+            r#"{"type": "set_parameter", "index": 1, "value": 880.0}"#,
+            &parameters,
+            &Mutex::new(UndoHistory::new(10)),
+        ) {
+            Some(Response::Error { .. }) => {}
+            response => panic!("Expected an error, got {:?}", response),
+        }
+    }
+
+    #[test]
+    fn get_parameters_returns_the_parameters() {
+        let parameters = parameters();
+        let response = handle_request(
+            r#"{"type": "get_parameters"}"#,
+            &parameters,
+            &Mutex::new(UndoHistory::new(10)),
+        )
+        .expect("Expected a response.");
+        assert_eq!(
+            serde_json::to_string(&response).expect("Serialization should succeed."),
+            r#"{"type":"parameters","parameters":[{"name":"Frequency","label":"Hz","min":0.0,"max":1000.0,"value":500.0}]}"#
+        );
+    }
+
+    #[test]
+    fn undo_reverts_a_gesture() {
+        let parameters = parameters();
+        let history = Mutex::new(UndoHistory::new(10));
+        for request in &[
+            r#"{"type": "begin_gesture", "index": 0}"#,
+            r#"{"type": "set_parameter", "index": 0, "value": 250.0}"#,
+            r#"{"type": "set_parameter", "index": 0, "value": 300.0}"#,
+            r#"{"type": "end_gesture"}"#,
+            r#"{"type": "undo"}"#,
+        ] {
+            assert_eq!(handle_request(request, &parameters, &history), None);
+        }
+        assert_eq!(parameters.get(0), 500.0);
+        assert_eq!(
+            handle_request(r#"{"type": "redo"}"#, &parameters, &history),
+            None
+        );
+        assert_eq!(parameters.get(0), 300.0);
+    }
+}
@@ -1,3 +1,115 @@
+extern crate midi_consts;
 extern crate rsynth;
 
-fn main() {}
+use midi_consts::channel_event::{CONTROL_CHANGE, EVENT_TYPE_MASK, NOTE_OFF, NOTE_ON};
+use rsynth::event::{ContextualEventHandler, EventHandler, RawMidiEvent, Timed};
+use rsynth::test_utilities::allocation::{assert_no_allocation, AllocationChecker};
+use rsynth::utilities::polyphony::simple_event_dispatching::{
+    SimpleEventDispatcher, SimpleVoiceState,
+};
+use rsynth::utilities::polyphony::{
+    ContextualEventDispatcher, EventDispatcher, RawMidiEventToneIdentifierDispatchClassifier,
+    ToneIdentifier, Voice,
+};
+use std::alloc::System;
+
+#[global_allocator]
+static ALLOCATOR: AllocationChecker<System> = AllocationChecker::new(System);
+
+const NUMBER_OF_VOICES: usize = 4;
+
+struct TestVoice {
+    state: SimpleVoiceState<ToneIdentifier>,
+    number_of_events: usize,
+}
+
+impl TestVoice {
+    fn new() -> Self {
+        Self {
+            state: SimpleVoiceState::Idle,
+            number_of_events: 0,
+        }
+    }
+}
+
+impl Voice<SimpleVoiceState<ToneIdentifier>> for TestVoice {
+    fn state(&self) -> SimpleVoiceState<ToneIdentifier> {
+        self.state
+    }
+}
+
+impl EventHandler<Timed<RawMidiEvent>> for TestVoice {
+    fn handle_event(&mut self, timed: Timed<RawMidiEvent>) {
+        let data = timed.event.data();
+        match data[0] & EVENT_TYPE_MASK {
+            NOTE_ON => self.state = SimpleVoiceState::Active(ToneIdentifier(data[1])),
+            NOTE_OFF => self.state = SimpleVoiceState::Releasing(ToneIdentifier(data[1])),
+            _ => {}
+        }
+        self.number_of_events += 1;
+    }
+}
+
+impl<C> ContextualEventHandler<Timed<RawMidiEvent>, C> for TestVoice {
+    fn handle_event(&mut self, event: Timed<RawMidiEvent>, _context: &mut C) {
+        EventHandler::handle_event(self, event);
+    }
+}
+
+fn event(status: u8, data1: u8, data2: u8) -> Timed<RawMidiEvent> {
+    Timed::new(0, RawMidiEvent::new(&[status, data1, data2]))
+}
+
+// Note ons (more than there are voices, so that voices are stolen), a broadcast
+// control change, note offs and note offs for notes that are not playing.
+fn events() -> Vec<Timed<RawMidiEvent>> {
+    let mut events = Vec::new();
+    for note in 60..70 {
+        events.push(event(NOTE_ON, note, 100));
+    }
+    events.push(event(CONTROL_CHANGE, 1, 64));
+    for note in 60..80 {
+        events.push(event(NOTE_OFF, note, 0));
+        events.push(event(NOTE_ON, note, 0));
+    }
+    events
+}
+
+#[test]
+fn event_dispatching_does_not_allocate() {
+    let events = events();
+    let mut voices: Vec<_> = (0..NUMBER_OF_VOICES).map(|_| TestVoice::new()).collect();
+    let mut dispatcher =
+        SimpleEventDispatcher::<RawMidiEventToneIdentifierDispatchClassifier, TestVoice>::default();
+
+    assert_no_allocation(|| {
+        for event in events.iter() {
+            dispatcher.dispatch_event(*event, &mut voices);
+        }
+    });
+
+    assert!(voices.iter().all(|voice| voice.number_of_events > 0));
+}
+
+#[test]
+fn contextual_event_dispatching_does_not_allocate() {
+    let events = events();
+    let mut voices: Vec<_> = (0..NUMBER_OF_VOICES).map(|_| TestVoice::new()).collect();
+    let mut dispatcher =
+        SimpleEventDispatcher::<RawMidiEventToneIdentifierDispatchClassifier, TestVoice>::default();
+    let mut context = ();
+
+    assert_no_allocation(|| {
+        for event in events.iter() {
+            dispatcher.dispatch_contextual_event(*event, &mut voices, &mut context);
+        }
+    });
+
+    assert!(voices.iter().all(|voice| voice.number_of_events > 0));
+}
+
+#[test]
+#[should_panic(expected = "Expected no memory allocations")]
+fn allocations_are_detected() {
+    assert_no_allocation(|| vec![1]);
+}